@@ -0,0 +1,254 @@
+use std::{path::Path, time::Duration};
+
+use clap::{ArgMatches, parser::ValueSource};
+use nirion_lib::{
+    config::ProjectSource, docker::DockerCommand, lock_store::LockStore,
+    projects::Projects,
+};
+use nirion_tui_lib::{color::Colorize, table::format_table};
+
+/// How long detecting the docker and compose versions may take each.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// One line of the banner: a value and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Setting {
+    name: &'static str,
+    value: String,
+    source: String,
+}
+
+impl Setting {
+    fn new(
+        name: &'static str,
+        value: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            value: value.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// What the banner is built from, as `main` resolved it.
+pub(crate) struct EffectiveConfig<'a> {
+    /// Matches of the command line the file options were parsed from.
+    pub file_matches: &'a ArgMatches,
+    pub matches: &'a ArgMatches,
+    pub project_source: Option<&'a ProjectSource>,
+    /// The project file read, after building it for a nix target.
+    pub project_file: Option<&'a Path>,
+    /// The configured lock store, and whether the command reads it.
+    pub lock_store: Option<&'a LockStore>,
+    pub reads_lock: bool,
+    pub auth_file: Option<&'a Path>,
+    pub projects: &'a Projects,
+    pub docker_command: &'a DockerCommand,
+}
+
+impl EffectiveConfig<'_> {
+    /// Prints the banner on stderr, so that stdout stays clean for the
+    /// command's output.
+    pub(crate) async fn print(&self) {
+        let mut settings = vec![
+            self.project_file_setting(),
+            self.lock_setting(),
+            self.auth_setting(),
+            self.projects_setting(),
+        ];
+        settings.push(
+            version_setting(
+                "docker",
+                self.docker_command,
+                &["version", "--format", "{{.Server.Version}}"],
+            )
+            .await,
+        );
+        settings.push(
+            version_setting(
+                "compose",
+                self.docker_command,
+                &["compose", "version", "--short"],
+            )
+            .await,
+        );
+        eprint!("{}", format_settings(&settings));
+    }
+
+    fn project_file_setting(&self) -> Setting {
+        let file = self
+            .project_file
+            .map(|file| file.display().to_string());
+        match self.project_source {
+            Some(ProjectSource::File(path)) => Setting::new(
+                "project file",
+                path.display().to_string(),
+                source(
+                    self.file_matches,
+                    "project_file",
+                    "--project-file",
+                    "NIRION_PROJECT_FILE",
+                ),
+            ),
+            Some(ProjectSource::Nix(target)) => Setting::new(
+                "project file",
+                file.unwrap_or_else(|| "-".to_string()),
+                format!("nix eval of {target}"),
+            ),
+            Some(ProjectSource::Stdin) => {
+                Setting::new("project file", "-", "stdin")
+            }
+            None => Setting::new("project file", "none", "not needed"),
+        }
+    }
+
+    fn lock_setting(&self) -> Setting {
+        let mut setting = match self.lock_store {
+            Some(LockStore::File(path)) => Setting::new(
+                "lock file",
+                path.display().to_string(),
+                source(
+                    self.file_matches,
+                    "lock_file",
+                    "--lock-file",
+                    "NIRION_LOCK_FILE",
+                ),
+            ),
+            Some(LockStore::Dir(path)) => Setting::new(
+                "lock dir",
+                path.display().to_string(),
+                source(
+                    self.file_matches,
+                    "lock_dir",
+                    "--lock-dir",
+                    "NIRION_LOCK_DIR",
+                ),
+            ),
+            None => return Setting::new("lock file", "none", "not set"),
+        };
+        if !self.reads_lock {
+            setting
+                .source
+                .push_str(", not read by this command");
+        }
+        setting
+    }
+
+    fn auth_setting(&self) -> Setting {
+        match self.auth_file {
+            Some(path) => Setting::new(
+                "auth file",
+                path.display().to_string(),
+                source(
+                    self.matches,
+                    "auth_file",
+                    "--auth-file",
+                    "NIRION_AUTH_FILE",
+                ),
+            ),
+            None => Setting::new("auth file", "none", "not set"),
+        }
+    }
+
+    fn projects_setting(&self) -> Setting {
+        let (projects, services) = self.projects.iter().fold(
+            (0, 0),
+            |(projects, services), (_, project)| {
+                (projects + 1, services + project.services.len())
+            },
+        );
+        Setting::new(
+            "loaded",
+            format!("{projects} projects, {services} services"),
+            "project file",
+        )
+    }
+}
+
+/// `--flag`, or the variable if clap took the value from there.
+fn source(
+    matches: &ArgMatches,
+    id: &str,
+    flag: &str,
+    env: &str,
+) -> String {
+    match matches.value_source(id) {
+        Some(ValueSource::EnvVariable) => env.to_string(),
+        _ => flag.to_string(),
+    }
+}
+
+/// The version `docker <args>` prints, or why it couldn't be detected.
+async fn version_setting(
+    name: &'static str,
+    docker_command: &DockerCommand,
+    args: &[&str],
+) -> Setting {
+    let command = args
+        .iter()
+        .take_while(|arg| !arg.starts_with('-'))
+        .fold("docker".to_string(), |command, arg| command + " " + arg);
+    let output = docker_command
+        .command()
+        .args(args)
+        .kill_on_drop(true)
+        .output();
+    let value = match tokio::time::timeout(VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .to_string()
+        }
+        Ok(Ok(output)) => format!("unavailable ({})", output.status),
+        Ok(Err(error)) => format!("unavailable ({error})"),
+        Err(_) => "unavailable (timed out)".to_string(),
+    };
+    Setting::new(name, value, command)
+}
+
+/// `nirion 1.2.3` and a row per setting, with its source in grey.
+fn format_settings(settings: &[Setting]) -> String {
+    let rows = settings
+        .iter()
+        .map(|setting| {
+            format!(
+                "  {}\t{}\t{}",
+                setting.name,
+                setting.value,
+                format!("({})", setting.source).grey()
+            )
+        })
+        .collect();
+    format!(
+        "{} {}\n{}",
+        "nirion".bold(),
+        env!("CARGO_PKG_VERSION"),
+        format_table(rows)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+
+    #[test]
+    fn format_settings_aligns_values_and_sources() {
+        let settings = [
+            Setting::new("lock file", "/etc/nirion.lock", "NIRION_LOCK_FILE"),
+            Setting::new("docker", "27.3.1", "docker version"),
+        ];
+
+        assert_eq!(
+            strip_ansi_codes(&format_settings(&settings)),
+            format!(
+                "nirion {}\n  \
+                 lock file  /etc/nirion.lock  (NIRION_LOCK_FILE)\n  \
+                 docker     27.3.1            (docker version)\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+}
@@ -1,31 +1,202 @@
 use paste::paste;
 
-use clap::{Args, Subcommand};
+use clap::{ArgMatches, Args, Subcommand, ValueEnum, parser::ValueSource};
+use clap_complete::ArgValueCompleter;
 use nirion_lib::context::NirionContext;
-use std::num::NonZeroUsize;
+use nirion_lib::projects::{
+    Projects, ServiceSelector, parse_selector, selected_project_names,
+};
+use nirion_tui_lib::color::Colorize;
+use std::{num::NonZeroUsize, ops::Deref};
 use tokio::time::Duration;
 
+use crate::completion::{project_flag_completer, service_flag_completer};
 use crate::lifecycle::LifecycleOptions;
+use crate::output::OutputOptions;
 use crate::progress_render::ProgressPresentation;
+use crate::{ClapSelector, TargetSelector, loaded_projects};
 use nirion_lib::wait::WaitTarget;
 
+/// Commands that `--strict-targets` keeps from falling back to `*`.
+pub const STRICT_TARGET_COMMANDS: &[&str] = &["down", "stop", "restart"];
+
+/// Default of every `--refresh` flag.
+pub const DEFAULT_REFRESH: &str = "250ms";
+
+/// Polling docker faster than this only adds load on the daemon.
+const MIN_REFRESH: Duration = Duration::from_millis(50);
+
+/// Value parser for `--refresh` flags: a duration such as `500ms` or `2s`
+/// of at least [`MIN_REFRESH`]. Bare integers are still read as seconds,
+/// with a warning, until they are rejected in a later release.
+pub fn parse_refresh(value: &str) -> Result<Duration, String> {
+    let (refresh, bare_seconds) = parse_refresh_value(value)?;
+    if bare_seconds {
+        eprintln!(
+            "{} reading --refresh {value} as seconds; write `{value}s`, \
+             as bare numbers will stop being accepted",
+            "warning:".yellow()
+        );
+    }
+    Ok(refresh)
+}
+
+fn parse_refresh_value(value: &str) -> Result<(Duration, bool), String> {
+    let (refresh, bare_seconds) = match value.trim().parse::<u64>() {
+        Ok(seconds) => (Duration::from_secs(seconds), true),
+        Err(_) => (
+            humantime::parse_duration(value).map_err(|e| e.to_string())?,
+            false,
+        ),
+    };
+
+    if refresh < MIN_REFRESH {
+        return Err(format!(
+            "refresh interval must be at least {}",
+            humantime::format_duration(MIN_REFRESH)
+        ));
+    }
+    Ok((refresh, bare_seconds))
+}
+
+/// The positional target shared by the lifecycle commands.
+#[derive(Args, Debug, Clone)]
+pub struct TargetArg {
+    /// Target selector: *, project, or project.service
+    #[arg(
+        default_value = "*",
+        value_parser = TargetSelector::clap_parse,
+        add = TargetSelector::clap_completer()
+    )]
+    pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+}
+
+impl TargetArg {
+    fn parts_mut(&mut self) -> (&mut TargetSelector, &SelectorFlags) {
+        (&mut self.target, &self.selector)
+    }
+}
+
+impl Deref for TargetArg {
+    type Target = TargetSelector;
+
+    fn deref(&self) -> &TargetSelector {
+        &self.target
+    }
+}
+
+/// Value parser for `--project`: the name of a project in the project
+/// file.
+fn parse_project_flag(value: &str) -> Result<String, String> {
+    if loaded_projects()?.contains_key(value) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("Project '{value}' not found"))
+    }
+}
+
+/// `--project` and `--service`, spelling the positional selector as
+/// separate flags for scripts and for shells whose filename completion
+/// trips over the dot. Commands flatten these next to their positional
+/// `target`, and [`Commands::apply_selector_flags`] folds them into it.
+#[derive(Args, Debug, Clone, Default)]
+pub struct SelectorFlags {
+    /// Select this project instead of passing a positional selector
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "target",
+        value_parser = parse_project_flag,
+        add = ArgValueCompleter::new(project_flag_completer)
+    )]
+    pub project: Option<String>,
+
+    /// Narrow --project to this service
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "project",
+        add = ArgValueCompleter::new(service_flag_completer)
+    )]
+    pub service: Option<String>,
+}
+
+impl SelectorFlags {
+    /// The selector the flags spell, if `--project` was given.
+    pub fn selector(
+        &self,
+        projects: &Projects,
+    ) -> Result<Option<TargetSelector>, String> {
+        let Some(project) = &self.project else {
+            return Ok(None);
+        };
+        let selector = match &self.service {
+            Some(service) => format!("{project}.{service}"),
+            None => project.clone(),
+        };
+        parse_selector(&selector, projects)
+            .map(Some)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Replaces `target` with the selector the flags spell, if any.
+    pub fn apply(
+        &self,
+        target: &mut TargetSelector,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        if let Some(selector) = self.selector(projects)? {
+            *target = selector;
+        }
+        Ok(())
+    }
+
+    /// Like [`SelectorFlags::apply`], for commands that need a single
+    /// service and so both flags.
+    pub fn apply_service(
+        &self,
+        target: &mut Option<ServiceSelector>,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        match self.selector(projects)? {
+            Some(TargetSelector::Service(service)) => *target = Some(service),
+            Some(_) => {
+                return Err(
+                    "--service is required along with --project".to_string()
+                );
+            }
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// Flags of every command that runs compose across projects with a
+/// progress view.
 #[derive(Args, Debug, Clone)]
 pub struct LifecycleArgs {
     /// Use plain Docker Compose output instead of the progress UI
     #[arg(long)]
     pub plain: bool,
 
-    /// Refresh interval in seconds for status updates when monitoring
-    #[arg(short = 'r', long, default_value = "250ms", value_parser = humantime::parse_duration)]
+    /// Refresh interval for status updates when monitoring
+    #[arg(short = 'r', long, default_value = DEFAULT_REFRESH, value_parser = parse_refresh)]
     pub refresh: Duration,
 
-    /// Suppress non-essential output
-    #[arg(short, long)]
-    pub quiet: bool,
-
     /// Maximum number of projects to run concurrently
     #[arg(short = 'j', long)]
     pub jobs: Option<NonZeroUsize>,
+
+    #[command(flatten)]
+    pub profile: ProfileArgs,
+
+    /// Show the affected services, which of them are healthy and which
+    /// have pending lock changes, and ask before going ahead
+    #[arg(long)]
+    pub preview: bool,
 }
 
 impl LifecycleArgs {
@@ -42,13 +213,7 @@ impl LifecycleArgs {
     }
 
     pub fn presentation(&self) -> ProgressPresentation {
-        if self.quiet {
-            ProgressPresentation::Hidden
-        } else if self.plain {
-            ProgressPresentation::Plain
-        } else {
-            ProgressPresentation::Progress
-        }
+        OutputOptions::get().presentation(self.plain)
     }
 
     pub fn jobs(&self) -> usize {
@@ -62,6 +227,63 @@ impl LifecycleArgs {
     }
 }
 
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailOn {
+    /// Services from the project file that got no container
+    Missing,
+}
+
+/// Flags of the commands that bring containers up.
+#[derive(Args, Debug, Clone)]
+pub struct StartupArgs {
+    /// Skip health checks when determining if containers are ready
+    #[arg(short, long)]
+    pub skip_healthcheck: bool,
+
+    /// Exit with an error if any of these problems remain after starting
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub fail_on: Vec<FailOn>,
+
+    /// Lines of output to show for containers that failed or are
+    /// unhealthy after starting; 0 disables it
+    #[arg(long, value_name = "LINES", default_value_t = 30)]
+    pub failure_logs: usize,
+}
+
+impl StartupArgs {
+    pub fn wait_target(&self) -> WaitTarget {
+        if self.skip_healthcheck {
+            WaitTarget::NoWait
+        } else {
+            WaitTarget::Healthy
+        }
+    }
+
+    pub fn fails_on(
+        &self,
+        problem: FailOn,
+    ) -> bool {
+        self.fail_on.contains(&problem)
+    }
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ProfileArgs {
+    /// Enable a compose profile on top of the project file's; repeatable
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profiles: Vec<String>,
+}
+
+impl ProfileArgs {
+    /// The context every compose invocation of the command should use.
+    pub fn apply(
+        &self,
+        context: &NirionContext,
+    ) -> NirionContext {
+        context.with_profiles(&self.profiles)
+    }
+}
+
 macro_rules! define_commands {
     (
         [ $( $modname:ident ),* $(,)? ]
@@ -104,19 +326,394 @@ define_commands!([
     reload,
     start,
     stop,
+    pause,
+    unpause,
     list,
     pull,
     update,
     lock,
     exec,
+    exec_all,
     logs,
     cat,
+    env,
     ps,
     top,
     volumes,
     restart,
     compose_exec,
     monitor,
+    api,
     inspect,
-    health
+    health,
+    history,
+    registries,
+    lint,
+    adopt,
+    auth,
+    maintenance,
+    bundle,
+    completions,
+    man
 ]);
+
+/// Whether the subcommand `name` needs the project file. Decided from the
+/// name alone, as the full command line only parses once the projects are
+/// loaded. `adopt` writes one rather than reading it.
+pub fn needs_project_file(name: Option<&str>) -> bool {
+    !matches!(name, Some("completions" | "man" | "adopt" | "auth"))
+}
+
+impl Commands {
+    /// Whether the command reads or writes locked images; the lock file is
+    /// only loaded, and only required, for those.
+    pub fn needs_lock_file(&self) -> bool {
+        match self {
+            Commands::Lock { .. }
+            | Commands::Update { .. }
+            | Commands::Api { .. } => true,
+            Commands::Cat { args } => args.pinned,
+            Commands::Monitor { args } => args.check_updates.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether the command compares containers with the lock file when
+    /// one is configured, without requiring one: `up` to recreate stale
+    /// services, and the impact preview.
+    pub fn reads_lock_file(&self) -> bool {
+        match self {
+            Commands::Up { args } if !args.no_auto_recreate => true,
+            Commands::Down { args } => {
+                args.lifecycle.preview || *args.target == TargetSelector::All
+            }
+            Commands::Restart { args } => {
+                args.lifecycle.preview || *args.target == TargetSelector::All
+            }
+            Commands::Bundle { .. } => true,
+            _ => self
+                .lifecycle_args()
+                .is_some_and(|lifecycle| lifecycle.preview),
+        }
+    }
+
+    /// Whether the command still runs when the lock file can't be loaded,
+    /// as `bundle` is meant to report exactly that.
+    pub fn tolerates_broken_lock_file(&self) -> bool {
+        matches!(self, Commands::Bundle { .. })
+    }
+
+    /// Whether the command says so and stops when the project file
+    /// defines no projects, rather than drawing an empty status or
+    /// probing the daemon for nothing. Output meant for scripts stays
+    /// as it is.
+    pub fn stops_without_projects(&self) -> bool {
+        match self {
+            Commands::Ps { args } => !args.json && args.format.is_none(),
+            Commands::Update { args } => !args.json,
+            Commands::List { .. }
+            | Commands::Up { .. }
+            | Commands::Monitor { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn lifecycle_args(&self) -> Option<&LifecycleArgs> {
+        match self {
+            Commands::Up { args } => Some(&args.lifecycle),
+            Commands::Down { args } => Some(&args.lifecycle),
+            Commands::Reload { args } => Some(&args.lifecycle),
+            Commands::Start { args } => Some(&args.lifecycle),
+            Commands::Stop { args } => Some(&args.lifecycle),
+            Commands::Pause { args } => Some(&args.lifecycle),
+            Commands::Unpause { args } => Some(&args.lifecycle),
+            Commands::Restart { args } => Some(&args.lifecycle),
+            _ => None,
+        }
+    }
+
+    /// Whether the command talks to registries, which is when credentials
+    /// are also looked up in the keyring.
+    pub fn needs_registry_auth(&self) -> bool {
+        match self {
+            Commands::Lock { .. }
+            | Commands::Update { .. }
+            | Commands::Api { .. }
+            | Commands::Registries { .. } => true,
+            Commands::Auth { args } => {
+                matches!(args.command, auth::AuthCommand::Check { .. })
+            }
+            Commands::Monitor { args } => args.check_updates.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether the command talks to the Docker daemon, which is probed
+    /// once before it runs. `monitor` isn't listed: it waits for the
+    /// daemon instead of failing. Neither is `api`, which answers with an
+    /// error while the daemon is down, nor `bundle`, which records it.
+    pub fn needs_daemon(&self) -> bool {
+        !matches!(
+            self,
+            Commands::List { .. }
+                | Commands::Update { .. }
+                | Commands::Lock { .. }
+                | Commands::Cat { .. }
+                | Commands::Env { .. }
+                | Commands::Monitor { .. }
+                | Commands::Api { .. }
+                | Commands::History { .. }
+                | Commands::Registries { .. }
+                | Commands::Lint { .. }
+                | Commands::Auth { .. }
+                | Commands::Maintenance { .. }
+                | Commands::Bundle { .. }
+                | Commands::Completions { .. }
+                | Commands::Man { .. }
+        )
+    }
+
+    /// Folds `--project` and `--service` into the command's target. Runs
+    /// once the projects are loaded, as the selector they spell can only
+    /// be checked against them then.
+    pub fn apply_selector_flags(
+        &mut self,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        let (target, selector) = match self {
+            Commands::Exec { args } => {
+                return args
+                    .selector
+                    .apply_service(&mut args.target, projects);
+            }
+            Commands::Env { args } => {
+                return args
+                    .selector
+                    .apply_service(&mut args.target, projects);
+            }
+            Commands::Inspect { args } => {
+                return args.apply_selector_flags(projects);
+            }
+            Commands::Health { args } => {
+                return args.apply_selector_flags(projects);
+            }
+            command => match command.target_parts() {
+                Some(parts) => parts,
+                None => return Ok(()),
+            },
+        };
+        selector.apply(target, projects)
+    }
+
+    /// Replaces the `*` target a command falls back to when run without
+    /// one by the `--default-target` configured for it. Under
+    /// `--strict-targets`, [`STRICT_TARGET_COMMANDS`] without either fail
+    /// instead.
+    pub fn apply_default_target(
+        &mut self,
+        matches: &ArgMatches,
+        defaults: &[(String, String)],
+        strict: bool,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        let Some((name, matches)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let Some((target, _)) = self.target_parts() else {
+            return Ok(());
+        };
+        if matches.value_source("target") != Some(ValueSource::DefaultValue)
+            || matches
+                .value_source("project")
+                .is_some()
+        {
+            return Ok(());
+        }
+
+        if let Some((_, selector)) = defaults
+            .iter()
+            .rev()
+            .find(|(command, _)| command == name)
+        {
+            *target = parse_selector(selector, projects).map_err(|error| {
+                format!("--default-target {name}={selector}: {error}")
+            })?;
+            return Ok(());
+        }
+
+        if strict && STRICT_TARGET_COMMANDS.contains(&name) {
+            let everything =
+                selected_project_names(&TargetSelector::All, projects);
+            return Err(format!(
+                "specify a target, or pass '*' explicitly to affect \
+                 everything ('*' is {})",
+                if everything.is_empty() {
+                    "no projects".to_string()
+                } else {
+                    everything.join(", ")
+                }
+            ));
+        }
+        Ok(())
+    }
+
+    /// The positional selector and `--project`/`--service` of commands
+    /// that take any target.
+    fn target_parts(
+        &mut self
+    ) -> Option<(&mut TargetSelector, &SelectorFlags)> {
+        let parts = match self {
+            Commands::Up { args } => args.target.parts_mut(),
+            Commands::Down { args } => args.target.parts_mut(),
+            Commands::Reload { args } => args.target.parts_mut(),
+            Commands::Start { args } => args.target.parts_mut(),
+            Commands::Stop { args } => args.target.parts_mut(),
+            Commands::Pause { args } => args.target.parts_mut(),
+            Commands::Unpause { args } => args.target.parts_mut(),
+            Commands::Restart { args } => args.target.parts_mut(),
+            Commands::List { args } => (&mut args.target, &args.selector),
+            Commands::Pull { args } => (&mut args.target, &args.selector),
+            Commands::Update { args } => (&mut args.target, &args.selector),
+            Commands::Lock { args } => (&mut args.target, &args.selector),
+            Commands::ExecAll { args } => (&mut args.target, &args.selector),
+            Commands::Logs { args } => (&mut args.target, &args.selector),
+            Commands::Cat { args } => (&mut args.target, &args.selector),
+            Commands::Ps { args } => (&mut args.target, &args.selector),
+            Commands::Top { args } => (&mut args.target, &args.selector),
+            Commands::Volumes { args } => (&mut args.target, &args.selector),
+            Commands::ComposeExec { args } => {
+                (&mut args.target, &args.selector)
+            }
+            Commands::Monitor { args } => (&mut args.target, &args.selector),
+            Commands::History { args } => (&mut args.target, &args.selector),
+            Commands::Lint { args } => (&mut args.target, &args.selector),
+            Commands::Exec { .. }
+            | Commands::Env { .. }
+            | Commands::Inspect { .. }
+            | Commands::Health { .. }
+            | Commands::Api { .. }
+            | Commands::Registries { .. }
+            | Commands::Adopt { .. }
+            | Commands::Auth { .. }
+            | Commands::Maintenance { .. }
+            | Commands::Bundle { .. }
+            | Commands::Completions { .. }
+            | Commands::Man { .. } => return None,
+        };
+        Some(parts)
+    }
+
+    /// The name and target of lifecycle commands, which are recorded for
+    /// `nirion history` once they finish.
+    pub fn history_target(&self) -> Option<(&'static str, &TargetSelector)> {
+        let entry: (&'static str, &TargetSelector) = match self {
+            Commands::Up { args } => ("up", &args.target),
+            Commands::Down { args } => ("down", &args.target),
+            Commands::Reload { args } => ("reload", &args.target),
+            Commands::Start { args } => ("start", &args.target),
+            Commands::Stop { args } => ("stop", &args.target),
+            Commands::Pause { args } => ("pause", &args.target),
+            Commands::Unpause { args } => ("unpause", &args.target),
+            Commands::Restart { args } => ("restart", &args.target),
+            _ => return None,
+        };
+        Some(entry)
+    }
+
+    /// The name and target of commands that refuse to touch projects in
+    /// maintenance.
+    pub fn maintenance_target(
+        &self
+    ) -> Option<(&'static str, &TargetSelector)> {
+        let entry: (&'static str, &TargetSelector) = match self {
+            Commands::Up { args } => ("up", &args.target),
+            Commands::Down { args } => ("down", &args.target),
+            Commands::Reload { args } => ("reload", &args.target),
+            Commands::Restart { args } => ("restart", &args.target),
+            Commands::Update { args } => ("update", &args.target),
+            _ => return None,
+        };
+        Some(entry)
+    }
+
+    /// The target of commands that lock images or start containers,
+    /// whose compose files should run the images the project file names.
+    pub fn image_check_target(&self) -> Option<&TargetSelector> {
+        match self {
+            Commands::Up { args } => Some(&args.target),
+            Commands::Lock { args } if args.command.is_none() => {
+                Some(&args.target)
+            }
+            Commands::Update { args } => Some(&args.target),
+            _ => None,
+        }
+    }
+
+    /// Selected projects whose compose files the command hands to docker
+    /// compose or reads itself. `monitor` isn't listed: it keeps running
+    /// and shows unreadable projects as error rows instead.
+    pub fn compose_projects(
+        &self,
+        projects: &Projects,
+    ) -> Vec<String> {
+        let target: &TargetSelector = match self {
+            Commands::Up { args } => &args.target,
+            Commands::Down { args } => &args.target,
+            Commands::Reload { args } => &args.target,
+            Commands::Start { args } => &args.target,
+            Commands::Stop { args } => &args.target,
+            Commands::Pause { args } => &args.target,
+            Commands::Unpause { args } => &args.target,
+            Commands::Pull { args } => &args.target,
+            Commands::ExecAll { args } => &args.target,
+            Commands::Logs { args } => &args.target,
+            Commands::Cat { args } => &args.target,
+            Commands::Ps { args } => &args.target,
+            Commands::Top { args } => &args.target,
+            Commands::Volumes { args } => &args.target,
+            Commands::Restart { args } => &args.target,
+            Commands::ComposeExec { args } => &args.target,
+            Commands::Lint { args } => &args.target,
+            Commands::Exec { args } => {
+                return vec![args.target().project.clone()];
+            }
+            Commands::Env { args } => {
+                return vec![args.target().project.clone()];
+            }
+            _ => return vec![],
+        };
+        selected_project_names(target, projects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_accepts_durations_and_bare_seconds() {
+        assert_eq!(
+            parse_refresh_value("500ms"),
+            Ok((Duration::from_millis(500), false))
+        );
+        assert_eq!(
+            parse_refresh_value("2"),
+            Ok((Duration::from_secs(2), true))
+        );
+        assert_eq!(
+            parse_refresh_value(DEFAULT_REFRESH),
+            Ok((Duration::from_millis(250), false))
+        );
+    }
+
+    #[test]
+    fn refresh_rejects_intervals_below_minimum() {
+        assert!(
+            parse_refresh_value("10ms")
+                .unwrap_err()
+                .contains("at least 50ms")
+        );
+        assert!(parse_refresh_value("0").is_err());
+        assert!(parse_refresh_value("soon").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use nirion_lib::{
+    adopt::{adopted_project_file, discover_compose_projects},
+    context::NirionContext,
+};
+use nirion_tui_lib::color::Colorize;
+use std::{fs, path::PathBuf};
+
+use crate::prompt::Confirm;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Write a project file for what is running on this host
+  nirion adopt --output projects.json
+  # Compare it with the project file in use
+  nirion --yes adopt | diff - \"$NIRION_PROJECT_FILE\"";
+
+/// Write a project file for the compose projects running on this host
+///
+/// Projects are found through the labels compose puts on containers, with
+/// the image, restart policy and healthcheck of each service taken from
+/// `docker inspect`.
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct AdoptArgs {
+    /// Compose projects to adopt; without any, asks about each one found
+    #[arg(value_name = "PROJECT")]
+    pub projects: Vec<String>,
+
+    /// Write the project file here instead of printing it
+    #[arg(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+}
+
+pub async fn handle_adopt(
+    args: &AdoptArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let discovered = discover_compose_projects(&context.docker_command)
+        .await
+        .context("failed to discover compose projects")?;
+    for name in &args.projects {
+        if !discovered
+            .iter()
+            .any(|project| project.name == *name)
+        {
+            anyhow::bail!("no containers of a compose project '{name}' found");
+        }
+    }
+
+    let mut adopted = Vec::new();
+    for found in &discovered {
+        if !args.projects.is_empty() && !args.projects.contains(&found.name) {
+            continue;
+        }
+        let Some(project) = found.to_project() else {
+            eprintln!(
+                "{} skipping '{}': its containers don't record the compose \
+                 file they were created from",
+                "warning:".yellow(),
+                found.name
+            );
+            continue;
+        };
+
+        if args.projects.is_empty() {
+            let question = format!(
+                "Adopt '{}' ({} services, {})?",
+                found.name,
+                project.services.len(),
+                project.docker_compose.display()
+            );
+            if !Confirm::new(question, true).ask()? {
+                continue;
+            }
+        }
+        if found.compose_files.len() > 1 {
+            eprintln!(
+                "{} '{}' was created from {} compose files; only {} is \
+                 kept, merge the others into it",
+                "warning:".yellow(),
+                found.name,
+                found.compose_files.len(),
+                project.docker_compose.display()
+            );
+        }
+        adopted.push(project);
+    }
+
+    let json = adopted_project_file(&adopted)?;
+    let Some(path) = &args.output else {
+        println!("{json}");
+        return Ok(());
+    };
+    if path.exists()
+        && !Confirm::new(format!("Overwrite {}?", path.display()), false)
+            .destructive()
+            .ask()?
+    {
+        anyhow::bail!("{} left as it was", path.display());
+    }
+    fs::write(path, format!("{json}\n"))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!(
+        "Adopted {} project(s) into {}",
+        adopted.len(),
+        path.display()
+    );
+    Ok(())
+}
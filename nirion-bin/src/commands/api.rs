@@ -0,0 +1,477 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use nirion_lib::{
+    context::NirionContext,
+    docker::ProjectStatus,
+    drift::image_drift,
+    projects::{
+        get_local_build_images, selected_project_names, ServiceImage,
+        TargetSelector,
+    },
+};
+use nirion_tui_lib::color::Colorize;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    task::JoinSet,
+};
+
+use crate::{
+    commands::{bundle::redacted_projects, ps::query_statuses},
+    foreground::shutdown_signal,
+};
+
+/// Requests whose head doesn't fit are answered with 431.
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// Clients that don't send a full request by then are disconnected.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long requests still in flight get to finish after Ctrl-C.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+const EXAMPLES: &str = "\
+Examples:
+  # Serve on the default address, 127.0.0.1:7878
+  nirion api
+  # Listen on every interface and require a token
+  NIRION_API_TOKEN=secret nirion api --listen 0.0.0.0:7878
+  # Query it
+  curl -H 'Authorization: Bearer secret' localhost:7878/status/media";
+
+/// Serve read-only JSON about the projects over HTTP, for dashboards
+///
+/// Endpoints: /projects, /status, /status/<project>, /lock and /diff,
+/// which compares the lock file with the digests containers run.
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct ApiArgs {
+    /// Address to listen on
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7878")]
+    pub listen: SocketAddr,
+
+    /// Require `Authorization: Bearer <TOKEN>` on every request
+    #[arg(long, env = "NIRION_API_TOKEN", hide_env_values = true)]
+    pub token: Option<String>,
+
+    /// How long a project's status is reused before docker is asked again
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "2s",
+        value_parser = humantime::parse_duration
+    )]
+    pub cache_ttl: Duration,
+}
+
+pub async fn handle_api(
+    args: &ApiArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let listener = TcpListener::bind(args.listen)
+        .await
+        .with_context(|| format!("failed to listen on {}", args.listen))?;
+    if args.token.is_none() && !args.listen.ip().is_loopback() {
+        eprintln!(
+            "{} serving {} without --token; anyone who can reach it can \
+             read the projects",
+            "warning:".yellow(),
+            args.listen
+        );
+    }
+    println!("Listening on http://{}", listener.local_addr()?);
+
+    let api = Arc::new(Api {
+        context: context.clone(),
+        token: args.token.clone(),
+        cache_ttl: args.cache_ttl,
+        statuses: Mutex::new(BTreeMap::new()),
+    });
+    let mut connections = JoinSet::new();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            result = &mut shutdown => {
+                result?;
+                break;
+            }
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        eprintln!(
+                            "{} failed to accept a connection: {e}",
+                            "warning:".yellow()
+                        );
+                        continue;
+                    }
+                };
+                let api = api.clone();
+                connections.spawn(async move {
+                    // The client going away mid-response is its business.
+                    let _ = api.serve(stream).await;
+                });
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+        }
+    }
+
+    drop(listener);
+    let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        connections.abort_all();
+    }
+    Ok(())
+}
+
+struct Api {
+    context: NirionContext,
+    token: Option<String>,
+    cache_ttl: Duration,
+    statuses: Mutex<BTreeMap<String, (Instant, ProjectStatus)>>,
+}
+
+impl Api {
+    async fn serve(
+        &self,
+        mut stream: TcpStream,
+    ) -> std::io::Result<()> {
+        let read = tokio::time::timeout(READ_TIMEOUT, read_head(&mut stream));
+        let (response, head_only) = match read.await {
+            Err(_) => return Ok(()),
+            Ok(Err(response)) => (response, false),
+            Ok(Ok(head)) => match parse_request(&head) {
+                Ok(request) => {
+                    (self.respond(&request).await, request.method == "HEAD")
+                }
+                Err(response) => (response, false),
+            },
+        };
+
+        stream
+            .write_all(&response.to_bytes(head_only))
+            .await?;
+        stream.shutdown().await
+    }
+
+    async fn respond(
+        &self,
+        request: &Request,
+    ) -> Response {
+        if !matches!(request.method.as_str(), "GET" | "HEAD") {
+            return Response::error(405, "only GET and HEAD are supported")
+                .header("Allow", "GET, HEAD");
+        }
+        if !self.authorized(request) {
+            return Response::error(401, "missing or wrong bearer token")
+                .header("WWW-Authenticate", "Bearer");
+        }
+
+        self.route(&request.path)
+            .await
+            .unwrap_or_else(|error| Response::error(500, &format!("{error:#}")))
+    }
+
+    fn authorized(
+        &self,
+        request: &Request,
+    ) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| same_token(given.trim(), token))
+    }
+
+    async fn route(
+        &self,
+        path: &str,
+    ) -> Result<Response> {
+        let projects = &self.context.projects;
+        let segments = path
+            .trim_matches('/')
+            .split('/')
+            .collect::<Vec<_>>();
+
+        match segments.as_slice() {
+            ["projects"] => Response::json(&redacted_projects(projects)?),
+            ["status"] => {
+                let names =
+                    selected_project_names(&TargetSelector::All, projects);
+                Response::json(&self.statuses(&names).await?)
+            }
+            ["status", project] if projects.contains_key(project) => {
+                let mut statuses = self
+                    .statuses(&[project.to_string()])
+                    .await?;
+                Response::json(&statuses.remove(*project))
+            }
+            ["status", project] => Ok(Response::error(
+                404,
+                &format!("Project '{project}' not found"),
+            )),
+            ["lock"] => Response::json(&self.context.lock_store()?.load()?),
+            ["diff"] => {
+                let locked = self.context.lock_store()?.load()?;
+                let names =
+                    selected_project_names(&TargetSelector::All, projects);
+                // Stale entries of services since marked as local builds
+                // say nothing about what they should run.
+                let builds =
+                    get_local_build_images(&TargetSelector::All, projects)?
+                        .iter()
+                        .map(ServiceImage::service_ref)
+                        .collect::<BTreeSet<_>>();
+                let drift = image_drift(&locked, &self.statuses(&names).await?)
+                    .into_iter()
+                    .filter(|drift| !builds.contains(&drift.service))
+                    .collect::<Vec<_>>();
+                Response::json(&drift)
+            }
+            _ => Ok(Response::error(404, &format!("no endpoint at {path}"))),
+        }
+    }
+
+    /// The status of `names`, querying docker for the projects whose
+    /// cached status is older than the TTL, several at once with a single
+    /// `docker ps`. The cache stays locked meanwhile, so a burst of
+    /// requests causes one query rather than one per request.
+    async fn statuses(
+        &self,
+        names: &[String],
+    ) -> Result<BTreeMap<String, ProjectStatus>> {
+        let mut cache = self.statuses.lock().await;
+        let stale = names
+            .iter()
+            .filter(|name| {
+                cache
+                    .get(*name)
+                    .is_none_or(|(at, _)| at.elapsed() >= self.cache_ttl)
+            })
+            .collect::<Vec<_>>();
+
+        let stale = stale
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let queried = query_statuses(&self.context, &stale)
+            .await
+            .context("failed to query the project status")?;
+        for (name, status) in queried {
+            cache.insert(name, (Instant::now(), status));
+        }
+
+        Ok(names
+            .iter()
+            .filter_map(|name| {
+                let (_, status) = cache.get(name)?;
+                Some((name.clone(), status.clone()))
+            })
+            .collect())
+    }
+}
+
+/// Compares without returning early at the first differing byte, so the
+/// response time doesn't tell how much of a guess was right.
+fn same_token(
+    given: &str,
+    token: &str,
+) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    method: String,
+    /// Without the query string, which no endpoint reads.
+    path: String,
+    authorization: Option<String>,
+}
+
+/// Reads up to the blank line ending the request head. Requests have no
+/// body worth reading, as only GET and HEAD are served.
+async fn read_head(stream: &mut TcpStream) -> Result<String, Response> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        if let Some(end) = head
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            head.truncate(end);
+            return String::from_utf8(head)
+                .map_err(|_| Response::error(400, "request head isn't UTF-8"));
+        }
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(Response::error(431, "request head too large"));
+        }
+
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => {
+                return Err(Response::error(400, "incomplete request"));
+            }
+            Ok(read) => head.extend_from_slice(&buffer[..read]),
+        }
+    }
+}
+
+fn parse_request(head: &str) -> Result<Request, Response> {
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let [method, target, version] = request_line
+        .split(' ')
+        .collect::<Vec<_>>()[..]
+    else {
+        return Err(Response::error(400, "malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Response::error(505, "only HTTP/1.x is supported"));
+    }
+
+    let authorization = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+        .map(|(_, value)| value.trim().to_string());
+
+    Ok(Request {
+        method: method.to_string(),
+        path: target
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        authorization,
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Response {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(value: &impl Serialize) -> Result<Self> {
+        Ok(Self {
+            status: 200,
+            headers: Vec::new(),
+            body: serde_json::to_string_pretty(value)?,
+        })
+    }
+
+    fn error(
+        status: u16,
+        message: &str,
+    ) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+
+    fn header(
+        mut self,
+        name: &'static str,
+        value: &str,
+    ) -> Self {
+        self.headers
+            .push((name, value.to_string()));
+        self
+    }
+
+    fn to_bytes(
+        &self,
+        head_only: bool,
+    ) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            431 => "Request Header Fields Too Large",
+            505 => "HTTP Version Not Supported",
+            _ => "Internal Server Error",
+        };
+        let mut response = format!(
+            "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str("\r\n");
+        if !head_only {
+            response.push_str(&self.body);
+        }
+        response.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_heads_are_parsed_without_the_query() {
+        let request = parse_request(
+            "GET /status/app?pretty=1 HTTP/1.1\r\nHost: localhost\r\n\
+             authorization: Bearer secret ",
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            Request {
+                method: "GET".into(),
+                path: "/status/app".into(),
+                authorization: Some("Bearer secret".into()),
+            }
+        );
+
+        assert_eq!(
+            parse_request("GET /")
+                .unwrap_err()
+                .status,
+            400
+        );
+        assert_eq!(
+            parse_request("GET / HTTP/2.0")
+                .unwrap_err()
+                .status,
+            505
+        );
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(same_token("secret", "secret"));
+        assert!(!same_token("secreT", "secret"));
+        assert!(!same_token("secret2", "secret"));
+        assert!(!same_token("", "secret"));
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use futures::future::join_all;
+use nirion_lib::{context::NirionContext, keyring::Keyring};
+use nirion_oci_lib::{
+    auth::RegistryAuth,
+    check::{Access, CredentialCheck},
+    client::{normalize_scope, AuthConfig},
+    oci::resolve_registry,
+    oci_client::Reference,
+};
+use nirion_tui_lib::{color::Colorize, table::print_table};
+
+use crate::{output::OutputOptions, prompt::read_line};
+
+const EXAMPLES: &str = "\
+Examples:
+  # Store credentials for a registry in the keyring (Linux only)
+  nirion auth set ghcr.io --username me
+  # Read a token from stdin instead of asking for it
+  echo \"$TOKEN\" | nirion auth set registry.example.com --token
+  # Check that the credentials for an image work
+  nirion auth check ghcr.io/me/private-app:latest";
+
+/// Manage and test registry credentials
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct AuthArgs {
+    #[command(subcommand)]
+    pub command: AuthCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuthCommand {
+    /// Store credentials for a registry in the keyring, asked for on the
+    /// terminal or read line by line from stdin
+    ///
+    /// The keyring is the freedesktop Secret Service, reached through
+    /// libsecret's `secret-tool`, so this only works on Linux. Elsewhere,
+    /// put the credentials in an auth file.
+    Set {
+        /// Registry, or registry/repository for credentials that only
+        /// apply to that repository
+        registry: String,
+
+        /// User to log in as; asked for if not given
+        #[arg(short, long)]
+        username: Option<String>,
+
+        /// Store a bearer token instead of a username and password
+        #[arg(long, conflicts_with = "username")]
+        token: bool,
+    },
+
+    /// List the registries with credentials in the keyring
+    List,
+
+    /// Remove the credentials stored for a registry
+    Remove {
+        /// Registry, or registry/repository, as given to `auth set`
+        registry: String,
+    },
+
+    /// Try the configured credentials against their registries, with and
+    /// without logging in, failing if any are rejected
+    Check {
+        /// Only check this registry, or pull access to this image with
+        /// the credentials that apply to it
+        target: Option<String>,
+
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// What `auth check` was asked to check.
+#[derive(Debug, PartialEq)]
+enum CheckTarget {
+    Registry(String),
+    Image(Reference),
+}
+
+pub async fn handle_auth(
+    args: &AuthArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let keyring = Keyring::default();
+    let quiet = OutputOptions::get().quiet;
+
+    match &args.command {
+        AuthCommand::Set {
+            registry,
+            username,
+            token,
+        } => {
+            let auth = if *token {
+                RegistryAuth::bearer(non_empty(read_line("Token", true)?)?)
+            } else {
+                let username = match username {
+                    Some(username) => username.clone(),
+                    None => non_empty(read_line("Username", false)?)?,
+                };
+                RegistryAuth::basic(
+                    username,
+                    non_empty(read_line("Password", true)?)?,
+                )
+            };
+            keyring.store(registry, &auth).await?;
+            if !quiet {
+                println!(
+                    "Stored credentials for {} in the keyring",
+                    normalize_scope(registry)
+                );
+            }
+        }
+        AuthCommand::List => {
+            let entries = stored_entries(&keyring).await?;
+            let mut rows = vec![format!(
+                "{}\t{}\t{}",
+                "registry".blue(),
+                "type".blue(),
+                "user".blue()
+            )];
+            for (scope, auth) in &entries {
+                let (kind, user) = match auth {
+                    RegistryAuth::Basic { username, .. } => {
+                        ("basic", username.as_str())
+                    }
+                    RegistryAuth::Bearer { .. } => ("token", ""),
+                    RegistryAuth::Anonymous => ("anonymous", ""),
+                };
+                rows.push(format!("{}\t{kind}\t{user}", scope.as_str().cyan()));
+            }
+            print_table(rows);
+        }
+        AuthCommand::Remove { registry } => {
+            let scope = normalize_scope(registry);
+            if !stored_entries(&keyring)
+                .await?
+                .contains_key(&scope)
+            {
+                anyhow::bail!("no credentials for {scope} in the keyring");
+            }
+            keyring.remove(&scope).await?;
+            if !quiet {
+                println!(
+                    "Removed the credentials for {scope} from the keyring"
+                );
+            }
+        }
+        AuthCommand::Check { target, json } => {
+            let target = target
+                .as_deref()
+                .map(parse_check_target)
+                .transpose()?;
+            return check_credentials(context, target, *json).await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_credentials(
+    context: &NirionContext,
+    target: Option<CheckTarget>,
+    json: bool,
+) -> Result<()> {
+    let checks = checked_scopes(context.oci_client.auth_config(), target);
+    if checks.is_empty() {
+        eprintln!("No registry credentials configured");
+        return Ok(());
+    }
+
+    let checker = context.oci_client.credential_checker();
+    let checks = join_all(
+        checks
+            .iter()
+            .map(|(scope, auth, image)| {
+                checker.check(scope, auth, image.as_ref())
+            }),
+    )
+    .await;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        print_checks(&checks);
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.failed())
+        .count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{failed} of {} credential{} rejected",
+            checks.len(),
+            if checks.len() == 1 { " was" } else { "s were" }
+        );
+    }
+
+    Ok(())
+}
+
+/// A registry is told apart from an image the way docker tells a
+/// registry host from a repository name: by a dot, a port or `localhost`.
+fn parse_check_target(target: &str) -> Result<CheckTarget> {
+    let target = target
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = target
+        .split(':')
+        .next()
+        .unwrap_or_default();
+    let is_registry = !target.contains(['/', '@'])
+        && (host.contains('.') || host == "localhost");
+    if is_registry {
+        return Ok(CheckTarget::Registry(normalize_scope(target)));
+    }
+
+    Reference::try_from(target)
+        .map(CheckTarget::Image)
+        .with_context(|| {
+            format!("'{target}' is neither a registry nor an image")
+        })
+}
+
+/// The scopes to check, with their credentials and the image to pull.
+fn checked_scopes(
+    auth: &AuthConfig,
+    target: Option<CheckTarget>,
+) -> Vec<(String, RegistryAuth, Option<Reference>)> {
+    match target {
+        None => {
+            let mut scopes = auth
+                .sources
+                .iter()
+                .map(|(scope, auth)| (scope.clone(), auth.clone(), None))
+                .collect::<Vec<_>>();
+            scopes.sort_by(|a, b| a.0.cmp(&b.0));
+            scopes
+        }
+        Some(CheckTarget::Registry(registry)) => {
+            let mut scopes = auth
+                .sources
+                .iter()
+                .filter(|(scope, _)| {
+                    scope.split('/').next() == Some(registry.as_str())
+                })
+                .map(|(scope, auth)| (scope.clone(), auth.clone(), None))
+                .collect::<Vec<_>>();
+            scopes.sort_by(|a, b| a.0.cmp(&b.0));
+            if scopes.is_empty() {
+                scopes.push((registry, RegistryAuth::anonymous(), None));
+            }
+            scopes
+        }
+        Some(CheckTarget::Image(image)) => {
+            let scope = auth
+                .scope_for(&image)
+                .unwrap_or_else(|| {
+                    resolve_registry(image.registry().to_string())
+                });
+            vec![(scope, auth.auth_for(&image), Some(image))]
+        }
+    }
+}
+
+fn print_checks(checks: &[CredentialCheck]) {
+    let mut rows = vec![format!(
+        "{}\t{}\t{}\t{}\t{}",
+        "registry".blue(),
+        "anonymous".blue(),
+        "authenticated".blue(),
+        "user".blue(),
+        "quota".blue()
+    )];
+
+    for check in checks {
+        let scope = match &check.repository {
+            Some(repository) if !check.scope.ends_with(repository) => {
+                format!("{} ({repository})", check.scope)
+            }
+            _ => check.scope.clone(),
+        };
+        let anonymous = match check.anonymous {
+            Access::Granted => "yes".green().to_string(),
+            Access::Denied(_) => "no".grey().to_string(),
+        };
+        let authenticated = match &check.authenticated {
+            None => "-".grey().to_string(),
+            Some(Access::Granted) => "ok".green().to_string(),
+            Some(Access::Denied(_)) => "failed".red().to_string(),
+        };
+        let quota = check
+            .rate_limit
+            .map(|limit| match (limit.remaining, limit.limit) {
+                (Some(remaining), Some(limit)) => {
+                    format!("{remaining}/{limit}")
+                }
+                (Some(remaining), None) => remaining.to_string(),
+                (None, Some(limit)) => format!("?/{limit}"),
+                (None, None) => String::new(),
+            })
+            .unwrap_or_default();
+        rows.push(format!(
+            "{}\t{anonymous}\t{authenticated}\t{}\t{quota}",
+            scope.as_str().cyan(),
+            check
+                .username
+                .as_deref()
+                .unwrap_or_default()
+        ));
+    }
+
+    print_table(rows);
+
+    for check in checks {
+        if let Some(Access::Denied(error)) = &check.authenticated {
+            eprintln!("{} {}: {error}", "rejected:".red(), check.scope);
+        }
+    }
+}
+
+async fn stored_entries(
+    keyring: &Keyring
+) -> Result<std::collections::BTreeMap<String, RegistryAuth>> {
+    keyring
+        .entries()
+        .await?
+        .ok_or_else(|| keyring.unavailable())
+}
+
+fn non_empty(value: String) -> Result<String> {
+    if value.is_empty() {
+        anyhow::bail!("no credentials given");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_targets_are_registries_when_they_look_like_hosts() {
+        let registry = |target: &str| CheckTarget::Registry(target.to_string());
+        let image = |target: &str| {
+            CheckTarget::Image(Reference::try_from(target).unwrap())
+        };
+
+        assert_eq!(parse_check_target("ghcr.io").unwrap(), registry("ghcr.io"));
+        assert_eq!(
+            parse_check_target("https://docker.io").unwrap(),
+            registry("index.docker.io")
+        );
+        assert_eq!(
+            parse_check_target("localhost:5000").unwrap(),
+            registry("localhost:5000")
+        );
+        assert_eq!(
+            parse_check_target("nginx:1.27").unwrap(),
+            image("nginx:1.27")
+        );
+        assert_eq!(
+            parse_check_target("ghcr.io/me/app").unwrap(),
+            image("ghcr.io/me/app")
+        );
+    }
+
+    #[test]
+    fn checked_scopes_pick_the_credentials_of_the_target() {
+        let mut auth = AuthConfig::default();
+        auth.add_auth("ghcr.io".to_string(), RegistryAuth::bearer("a"));
+        auth.add_auth("ghcr.io/me".to_string(), RegistryAuth::bearer("b"));
+        auth.add_auth("quay.io".to_string(), RegistryAuth::bearer("c"));
+        let scopes = |target| {
+            checked_scopes(&auth, target)
+                .into_iter()
+                .map(|(scope, _, image)| (scope, image.is_some()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            scopes(None),
+            [
+                ("ghcr.io".to_string(), false),
+                ("ghcr.io/me".to_string(), false),
+                ("quay.io".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            scopes(Some(CheckTarget::Registry("ghcr.io".to_string()))),
+            [
+                ("ghcr.io".to_string(), false),
+                ("ghcr.io/me".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            scopes(Some(CheckTarget::Registry("lscr.io".to_string()))),
+            [("lscr.io".to_string(), false)]
+        );
+        assert_eq!(
+            scopes(Some(CheckTarget::Image(
+                Reference::try_from("ghcr.io/me/app:1").unwrap()
+            ))),
+            [("ghcr.io/me".to_string(), true)]
+        );
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use flate2::{write::GzEncoder, Compression};
+use nirion_lib::{
+    compose_file::{image_mismatches, resolved_compose, service_mismatch},
+    context::NirionContext,
+    daemon::{probe_daemon, DAEMON_PROBE_TIMEOUT},
+    docker::{inspect_unhealthy_containers, ServiceState},
+    env::redact_secrets,
+    lint::lint_projects,
+    lock_store::LockStore,
+    logs::tail_container_logs,
+    projects::{selected_project_names, Projects, TargetSelector},
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{commands::ps::query_statuses, output::OutputOptions};
+
+/// The directory everything in the archive is put under.
+const BUNDLE_DIR: &str = "nirion-bundle";
+
+/// Files are cut off after this many bytes.
+const MAX_FILE_BYTES: usize = 1024 * 1024;
+
+/// Log tails keep at most this many bytes of their latest lines.
+const MAX_LOG_BYTES: usize = 64 * 1024;
+
+/// How long `docker version` and `docker compose version` may take.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+const EXAMPLES: &str = "\
+Examples:
+  # Collect everything into nirion-bundle.tar.gz
+  nirion bundle
+  # With longer log tails, somewhere else
+  nirion bundle --output /tmp/support.tar.gz --log-lines 1000";
+
+/// Collect the deployment state into one archive to share when asking
+/// for help
+///
+/// The archive holds the project file and compose configs with secrets
+/// redacted, the lock file, the status of every project, log tails of
+/// failed and unhealthy containers, tool versions and the results of
+/// nirion's checks. Parts that can't be collected are listed with their
+/// error in its manifest.json.
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct BundleArgs {
+    /// Where to write the archive
+    #[arg(short, long, default_value = "nirion-bundle.tar.gz")]
+    pub output: PathBuf,
+
+    /// Lines of logs to include per failed or unhealthy container
+    #[arg(long, value_name = "LINES", default_value_t = 200)]
+    pub log_lines: usize,
+}
+
+pub async fn handle_bundle(
+    args: &BundleArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let names = selected_project_names(&TargetSelector::All, &context.projects);
+    let mut bundle = Bundle::default();
+
+    bundle.add(
+        "project file",
+        "projects.json",
+        redacted_projects(&context.projects)
+            .and_then(|projects| Ok(serde_json::to_vec_pretty(&projects)?)),
+    );
+    add_lock_files(&mut bundle, context.lock_store.as_ref());
+    for name in &names {
+        bundle.add(
+            &format!("compose config of {name}"),
+            &format!("compose/{name}.yml"),
+            compose_config(context, name).await,
+        );
+    }
+
+    let daemon = probe_daemon(&context.docker_command, DAEMON_PROBE_TIMEOUT)
+        .await
+        .map_err(|error| format!("{error:#}"));
+    if let Err(error) = &daemon {
+        bundle.fail("docker daemon", anyhow::anyhow!("{error}"));
+        bundle.fail(
+            "status",
+            anyhow::anyhow!("skipped, as the Docker daemon can't be reached"),
+        );
+    } else {
+        add_status(&mut bundle, context, &names, args.log_lines).await;
+    }
+
+    bundle.add(
+        "docker version",
+        "versions/docker.txt",
+        command_output(context, &["version"]).await,
+    );
+    bundle.add(
+        "docker compose version",
+        "versions/compose.txt",
+        command_output(context, &["compose", "version"]).await,
+    );
+    bundle.add(
+        "checks",
+        "checks.json",
+        checks(context, &names, daemon.err()),
+    );
+
+    bundle.write(&args.output)?;
+    if !OutputOptions::get().quiet {
+        let failed = bundle.failures();
+        println!("Wrote {}", args.output.display());
+        if failed > 0 {
+            eprintln!(
+                "{failed} part(s) couldn't be collected; their errors are in \
+                 manifest.json"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// What `manifest.json` lists for each part of the bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct Part {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// Cut off at [`MAX_FILE_BYTES`], or [`MAX_LOG_BYTES`] for log tails.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    nirion: &'static str,
+    created: String,
+    parts: &'a [Part],
+}
+
+#[derive(Debug, Default)]
+struct Bundle {
+    files: Vec<(String, Vec<u8>)>,
+    parts: Vec<Part>,
+}
+
+impl Bundle {
+    /// Adds `file`, or records why it couldn't be collected.
+    fn add(
+        &mut self,
+        name: &str,
+        file: &str,
+        contents: Result<Vec<u8>>,
+    ) {
+        match contents {
+            Ok(mut contents) => {
+                let truncated = contents.len() > MAX_FILE_BYTES;
+                contents.truncate(MAX_FILE_BYTES);
+                self.add_file(name, file, contents, truncated);
+            }
+            Err(error) => self.fail(name, error),
+        }
+    }
+
+    fn add_file(
+        &mut self,
+        name: &str,
+        file: &str,
+        contents: Vec<u8>,
+        truncated: bool,
+    ) {
+        self.files
+            .push((file.to_string(), contents));
+        self.parts.push(Part {
+            name: name.to_string(),
+            file: Some(file.to_string()),
+            truncated,
+            error: None,
+        });
+    }
+
+    fn fail(
+        &mut self,
+        name: &str,
+        error: anyhow::Error,
+    ) {
+        self.parts.push(Part {
+            name: name.to_string(),
+            file: None,
+            truncated: false,
+            error: Some(format!("{error:#}")),
+        });
+    }
+
+    fn failures(&self) -> usize {
+        self.parts
+            .iter()
+            .filter(|part| part.error.is_some())
+            .count()
+    }
+
+    /// Writes the manifest and the files as a gzipped tarball, replacing
+    /// `path` only once it is complete.
+    fn write(
+        &self,
+        path: &Path,
+    ) -> Result<()> {
+        let manifest = Manifest {
+            nirion: env!("CARGO_PKG_VERSION"),
+            created: humantime::format_rfc3339_seconds(SystemTime::now())
+                .to_string(),
+            parts: &self.parts,
+        };
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+        let mut tmp = OsString::from(path.as_os_str());
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let file = fs::File::create(&tmp)
+            .with_context(|| format!("failed to create {}", tmp.display()))?;
+
+        let mut archive =
+            tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let files = std::iter::once(("manifest.json", manifest.as_slice()))
+            .chain(
+                self.files
+                    .iter()
+                    .map(|(file, contents)| {
+                        (file.as_str(), contents.as_slice())
+                    }),
+            );
+        for (file, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            archive
+                .append_data(
+                    &mut header,
+                    Path::new(BUNDLE_DIR).join(file),
+                    contents,
+                )
+                .with_context(|| {
+                    format!("failed to write {}", tmp.display())
+                })?;
+        }
+        archive
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .and_then(|mut file| file.flush())
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+
+        fs::rename(&tmp, path)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// The projects as loaded, with secret-looking values redacted.
+pub(crate) fn redacted_projects(
+    projects: &Projects
+) -> Result<serde_yaml_ng::Value> {
+    let mut projects = serde_yaml_ng::to_value(projects)?;
+    redact_secrets(&mut projects);
+    Ok(projects)
+}
+
+/// Copies the lock file, or every file of the lock directory, as is.
+fn add_lock_files(
+    bundle: &mut Bundle,
+    lock_store: Option<&LockStore>,
+) {
+    let Some(lock_store) = lock_store else {
+        bundle.fail(
+            "lock file",
+            anyhow::anyhow!("no lock file or directory is configured"),
+        );
+        return;
+    };
+
+    let files = match lock_store {
+        LockStore::File(path) => Ok(vec![path.clone()]),
+        LockStore::Dir(dir) => lock_dir_files(dir),
+    };
+    match files {
+        Ok(files) => {
+            for path in files {
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                bundle.add(
+                    &format!("lock file {name}"),
+                    &format!("lock/{name}"),
+                    fs::read(&path).with_context(|| {
+                        format!("failed to read {}", path.display())
+                    }),
+                );
+            }
+        }
+        Err(error) => bundle.fail("lock file", error),
+    }
+
+    // The raw files are kept either way; this records why nirion itself
+    // can't make sense of them.
+    if let Err(error) = lock_store.load() {
+        bundle.fail("parse lock file", error);
+    }
+}
+
+fn lock_dir_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("failed to read {}", dir.display()))?;
+    files.retain(|path| path.is_file());
+    files.sort();
+    Ok(files)
+}
+
+/// `docker compose config` of a project, with secret-looking values,
+/// such as those env files fill in, redacted.
+async fn compose_config(
+    context: &NirionContext,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let mut compose =
+        resolved_compose(&context.docker_command, &context.projects[name])
+            .await?;
+    redact_secrets(&mut compose);
+    Ok(serde_yaml_ng::to_string(&compose)?.into_bytes())
+}
+
+/// The status of every project, plus the log tails of its failed and
+/// unhealthy containers.
+async fn add_status(
+    bundle: &mut Bundle,
+    context: &NirionContext,
+    names: &[String],
+    log_lines: usize,
+) {
+    let mut statuses = match query_statuses(context, names).await {
+        Ok(statuses) => statuses,
+        Err(error) => {
+            bundle.fail("status", error);
+            return;
+        }
+    };
+    for (name, status) in statuses.iter_mut() {
+        if let Err(error) =
+            inspect_unhealthy_containers(&context.docker_command, status).await
+        {
+            bundle.fail(&format!("health checks of {name}"), error);
+        }
+    }
+    bundle.add(
+        "status",
+        "status.json",
+        serde_json::to_vec_pretty(&statuses).map_err(Into::into),
+    );
+
+    for (name, status) in &statuses {
+        for container in status.containers() {
+            if !matches!(
+                container.state,
+                ServiceState::Failed | ServiceState::Unhealthy
+            ) {
+                continue;
+            }
+            let part = format!("logs of {}", container.container_name);
+            let file = format!("logs/{name}/{}.log", container.container_name);
+            match tail_container_logs(context, &container.id, log_lines).await {
+                Ok(lines) => {
+                    let (contents, truncated) =
+                        latest_lines(&lines, MAX_LOG_BYTES);
+                    bundle.add_file(&part, &file, contents, truncated);
+                }
+                Err(error) => bundle.fail(&part, error),
+            }
+        }
+    }
+}
+
+/// The latest of `lines` that fit into `max_bytes`, newline-terminated,
+/// and whether any had to be left out.
+fn latest_lines(
+    lines: &[String],
+    max_bytes: usize,
+) -> (Vec<u8>, bool) {
+    let mut size = 0;
+    let kept = lines
+        .iter()
+        .rev()
+        .take_while(|line| {
+            size += line.len() + 1;
+            size <= max_bytes
+        })
+        .count();
+
+    let mut contents = Vec::with_capacity(size.min(max_bytes));
+    for line in &lines[lines.len() - kept..] {
+        contents.extend_from_slice(line.as_bytes());
+        contents.push(b'\n');
+    }
+    (contents, kept < lines.len())
+}
+
+/// What a docker command prints, stdout followed by stderr.
+async fn command_output(
+    context: &NirionContext,
+    args: &[&str],
+) -> Result<Vec<u8>> {
+    let command = format!("docker {}", args.join(" "));
+    let output = context
+        .docker_command
+        .command()
+        .args(args)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(VERSION_TIMEOUT, output)
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "{command} didn't finish within {}",
+                humantime::format_duration(VERSION_TIMEOUT)
+            )
+        })?
+        .with_context(|| format!("failed to execute {command}"))?;
+
+    let mut contents = output.stdout;
+    contents.extend_from_slice(&output.stderr);
+    if !output.status.success() {
+        anyhow::bail!(
+            "{command} failed with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&contents).trim()
+        );
+    }
+    Ok(contents)
+}
+
+/// The checks nirion runs before commands, collected in one report:
+/// whether the daemon answers, lint findings, and services and images
+/// that differ between the project file and the compose files.
+fn checks(
+    context: &NirionContext,
+    names: &[String],
+    daemon_error: Option<String>,
+) -> Result<Vec<u8>> {
+    let lint = match lint_projects(&context.projects, &TargetSelector::All) {
+        Ok(findings) => serde_json::to_value(findings)?,
+        Err(error) => json!({ "error": format!("{error:#}") }),
+    };
+
+    let mut compose = serde_json::Map::new();
+    for name in names {
+        let project = &context.projects[name];
+        // Unreadable compose files already show up as a failed compose
+        // config.
+        let mut report = serde_json::Map::new();
+        if let Ok(Some(mismatch)) = service_mismatch(project) {
+            report.insert(
+                "services".to_string(),
+                json!({
+                    "only_in_project": mismatch.only_in_project,
+                    "only_in_compose": mismatch.only_in_compose,
+                }),
+            );
+        }
+        let images = image_mismatches(project)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mismatch| {
+                json!({
+                    "service": mismatch.service,
+                    "project_image": mismatch.project_image,
+                    "compose_image": mismatch.compose_image,
+                })
+            })
+            .collect::<Vec<_>>();
+        if !images.is_empty() {
+            report.insert("images".to_string(), Value::Array(images));
+        }
+        if !report.is_empty() {
+            compose.insert(name.clone(), Value::Object(report));
+        }
+    }
+
+    let report = json!({
+        "daemon": match daemon_error {
+            Some(error) => json!({ "error": error }),
+            None => json!("reachable"),
+        },
+        "lint": lint,
+        "compose_mismatches": compose,
+    });
+    Ok(serde_json::to_vec_pretty(&report)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latest_lines_keep_the_end_within_the_limit() {
+        let lines = ["first", "second", "third"].map(String::from);
+
+        assert_eq!(
+            latest_lines(&lines, 1024),
+            (b"first\nsecond\nthird\n".to_vec(), false)
+        );
+        assert_eq!(
+            latest_lines(&lines, 13),
+            (b"second\nthird\n".to_vec(), true)
+        );
+        assert_eq!(latest_lines(&lines, 3), (Vec::new(), true));
+    }
+
+    #[test]
+    fn failed_parts_are_recorded_instead_of_added() {
+        let mut bundle = Bundle::default();
+        bundle.add("status", "status.json", Ok(b"{}".to_vec()));
+        bundle.add(
+            "docker version",
+            "versions/docker.txt",
+            Err(anyhow::anyhow!("docker not found")),
+        );
+        bundle.add(
+            "compose config of big",
+            "compose/big.yml",
+            Ok(vec![b'x'; MAX_FILE_BYTES + 1]),
+        );
+
+        assert_eq!(
+            bundle
+                .files
+                .iter()
+                .map(|(file, contents)| (file.as_str(), contents.len()))
+                .collect::<Vec<_>>(),
+            [("status.json", 2), ("compose/big.yml", MAX_FILE_BYTES)]
+        );
+        assert_eq!(
+            serde_json::to_value(&bundle.parts).unwrap(),
+            json!([
+                { "name": "status", "file": "status.json" },
+                { "name": "docker version", "error": "docker not found" },
+                {
+                    "name": "compose config of big",
+                    "file": "compose/big.yml",
+                    "truncated": true
+                },
+            ])
+        );
+        assert_eq!(bundle.failures(), 1);
+    }
+}
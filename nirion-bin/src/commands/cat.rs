@@ -1,15 +1,26 @@
 use anyhow::Result;
 use clap::Args;
 use nirion_lib::{
-    compose_file::{compose_to_string, full_compose, service_compose},
+    compose_file::{
+        compose_to_string, extract_service, full_compose, pin_compose,
+        resolved_compose,
+    },
     context::NirionContext,
-    projects::TargetSelector,
+    projects::{Project, TargetSelector},
 };
 
-use crate::ClapSelector;
+use crate::{commands::SelectorFlags, ClapSelector};
+
+const EXAMPLES: &str = "\
+Examples:
+  # The compose file of a project as written
+  nirion cat media
+  # As compose resolves it, with the images pinned to the lock file
+  nirion cat media --resolve --pinned";
 
 /// Print the docker compose file
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct CatArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -18,6 +29,17 @@ pub struct CatArgs {
         add = TargetSelector::clap_completer()
     )]
     pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Rewrite service images to the locked repo@digest references
+    #[arg(long)]
+    pub pinned: bool,
+
+    /// Print the output of `docker compose config` instead of the raw file
+    #[arg(long)]
+    pub resolve: bool,
 }
 
 pub async fn handle_cat(
@@ -28,18 +50,21 @@ pub async fn handle_cat(
         TargetSelector::All => {
             for (project_name, project) in context.projects.iter() {
                 println!("Project {}:", project_name);
-                print_compose(&full_compose(project)?)?;
+                print_compose(
+                    &load(args, context, project_name, project).await?,
+                )?;
             }
         }
         TargetSelector::Project(proj) => {
             let project = &context.projects[&proj.name];
-            print_compose(&full_compose(project)?)?;
+            print_compose(&load(args, context, &proj.name, project).await?)?;
         }
         TargetSelector::Service(img) => {
             let project = &context.projects[&img.project];
-            print_compose(&service_compose(
+            let compose = load(args, context, &img.project, project).await?;
+            print_compose(&extract_service(
+                &compose,
                 &img.project,
-                project,
                 &img.service,
             )?)?;
         }
@@ -48,6 +73,31 @@ pub async fn handle_cat(
     Ok(())
 }
 
+async fn load(
+    args: &CatArgs,
+    context: &NirionContext,
+    project_name: &str,
+    project: &Project,
+) -> Result<serde_yaml_ng::Value> {
+    let mut compose = if args.resolve {
+        resolved_compose(&context.docker_command, project).await?
+    } else {
+        full_compose(project)?
+    };
+
+    if args.pinned {
+        pin_compose(
+            &mut compose,
+            project_name,
+            project,
+            &context.locked_images,
+            &context.oci_client,
+        );
+    }
+
+    Ok(compose)
+}
+
 fn print_compose(compose: &serde_yaml_ng::Value) -> Result<()> {
     let pretty = compose_to_string(compose)?;
     println!("{}", pretty);
@@ -0,0 +1,39 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::{Args, CommandFactory};
+use clap_complete::{generate, Shell};
+use nirion_lib::context::NirionContext;
+
+use crate::Cli;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Install the bash script
+  nirion completions bash > ~/.local/share/bash-completion/completions/nirion";
+
+/// Print a static completion script for a shell
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct CompletionsArgs {
+    /// Shell to generate the script for
+    pub shell: Shell,
+}
+
+/// Static scripts complete subcommands and flags only; selectors need the
+/// dynamic completion of `COMPLETE=<shell>`.
+fn print_completions(shell: Shell) -> Result<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    let mut script = vec![];
+    generate(shell, &mut command, name, &mut script);
+    std::io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+pub async fn handle_completions(
+    args: &CompletionsArgs,
+    _context: &NirionContext,
+) -> Result<()> {
+    print_completions(args.shell)
+}
@@ -1,11 +1,24 @@
 use clap::Args;
-use nirion_lib::projects::TargetSelector;
+use nirion_lib::{compose::compose_args, projects::TargetSelector};
 
-use crate::{docker::compose_target_cmd, ClapSelector};
+use crate::{
+    commands::SelectorFlags,
+    docker::compose_target_cmd,
+    foreground::{run_foreground, ChildExit},
+    ClapSelector,
+};
 use nirion_lib::context::NirionContext;
 
+const EXAMPLES: &str = "\
+Examples:
+  # Any docker compose command, run for one project
+  nirion compose-exec media -- config --services
+  # Follow the compose logs of one service
+  nirion compose-exec media.jellyfin -- logs -f";
+
 /// Run a docker compose command for a project or service
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct ComposeExecArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -15,6 +28,9 @@ pub struct ComposeExecArgs {
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
     /// Command to execute in container
     cmd: Vec<String>,
 }
@@ -23,11 +39,28 @@ pub async fn handle_compose_exec(
     args: &ComposeExecArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
-    let cmd_slices: Vec<&str> = args
-        .cmd
-        .iter()
-        .map(|s| s.as_str())
-        .collect();
+    // A single project gets the terminal, so interactive commands such as
+    // `exec` or `logs -f` behave as they would under docker compose.
+    let (project, service) = match &args.target {
+        TargetSelector::All => {
+            let cmd_slices: Vec<&str> = args
+                .cmd
+                .iter()
+                .map(|s| s.as_str())
+                .collect();
+            return compose_target_cmd(context, &args.target, &cmd_slices)
+                .await;
+        }
+        TargetSelector::Project(selector) => (&selector.name, None),
+        TargetSelector::Service(selector) => {
+            (&selector.project, Some(selector.service.as_str()))
+        }
+    };
+
+    let mut command = context.docker_command.command();
+    command
+        .arg("compose")
+        .args(compose_args(&context.projects[project], service, &args.cmd));
 
-    compose_target_cmd(context, &args.target, &cmd_slices).await
+    ChildExit::check(run_foreground(&mut command).await?)
 }
@@ -1,38 +1,77 @@
 use anyhow::Result;
 use clap::Args;
-use nirion_lib::projects::TargetSelector;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::ClapSelector;
+use crate::commands::{LifecycleArgs, TargetArg};
+use crate::impact::print_impact;
+use crate::lifecycle::{run_project_hooks, run_shutdown_command};
+use crate::prompt::{assumes_yes, Confirm};
 use nirion_lib::context::NirionContext;
-use nirion_lib::wait::WaitTarget;
+use nirion_lib::hooks::HookPhase;
+use nirion_lib::projects::TargetSelector;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Take down one project
+  nirion down media
+  # Take down everything without being asked
+  nirion down '*' --yes";
 
 /// Stop and remove service containers, networks
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct DownArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
+
+    /// Ask before taking down `*` when it selects more than this many
+    /// projects
+    #[arg(
+        long,
+        env = "NIRION_DOWN_CONFIRM_ABOVE",
+        value_name = "COUNT",
+        default_value_t = 1
+    )]
+    pub confirm_above: usize,
+
+    /// Skip the projects' preDown and postDown hooks
+    #[arg(long)]
+    pub no_hooks: bool,
 }
 
 pub async fn handle_down(
     args: &DownArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    run_lifecycle_command(
-        context,
-        &args.target,
-        &["down"],
-        args.lifecycle
-            .options(WaitTarget::NoWait),
-    )
-    .await
+    let context = &args.lifecycle.profile.apply(context);
+    let count = context.projects.iter().count();
+    let confirm_all =
+        *args.target == TargetSelector::All && count > args.confirm_above;
+    if args.lifecycle.preview || (confirm_all && !assumes_yes()) {
+        print_impact(context, "down", &args.target).await?;
+    }
+    let question = if confirm_all {
+        format!("Take down all {count} projects?")
+    } else {
+        format!("Take down {}?", *args.target)
+    };
+    if (confirm_all || args.lifecycle.preview)
+        && !Confirm::new(question, false)
+            .destructive()
+            .ask()?
+    {
+        anyhow::bail!("down aborted; nothing was changed");
+    }
+
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PreDown).await?;
+    }
+    run_shutdown_command(context, &args.target, &["down"], &args.lifecycle)
+        .await?;
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PostDown).await?;
+    }
+    Ok(())
 }
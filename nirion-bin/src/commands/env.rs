@@ -0,0 +1,114 @@
+use anyhow::Result;
+use clap::Args;
+use nirion_lib::{
+    context::NirionContext,
+    env::{
+        compare_env, container_env, is_secret_key, service_env, EnvComparison,
+        EnvDrift, REDACTED,
+    },
+};
+use nirion_tui_lib::{
+    color::{Colorize, GREY},
+    table::print_table,
+};
+
+use crate::{commands::SelectorFlags, ClapSelector, ServiceSelector};
+
+const EXAMPLES: &str = "\
+Examples:
+  # Compare a service's env file with its running container
+  nirion env media.jellyfin
+  # Include the secret values, as JSON
+  nirion env media.jellyfin --show-secrets --json";
+
+/// Show a service's env file variables and compare them with the container
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct EnvArgs {
+    /// Service selector: project.service
+    #[arg(
+        required_unless_present = "project",
+        value_parser = ServiceSelector::clap_parse,
+        add = ServiceSelector::clap_completer()
+    )]
+    pub target: Option<ServiceSelector>,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Print secret values instead of redacting them
+    #[arg(long)]
+    pub show_secrets: bool,
+
+    /// Print the comparison as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl EnvArgs {
+    /// The selected service, given positionally or with `--project` and
+    /// `--service`.
+    pub fn target(&self) -> &ServiceSelector {
+        self.target
+            .as_ref()
+            .expect("required by clap; --project and --service are folded in before the command runs")
+    }
+}
+
+pub async fn handle_env(
+    args: &EnvArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let project = &context.projects[&args.target().project];
+    let vars = service_env(&project.docker_compose, args.target())?;
+    let container = container_env(context, args.target()).await?;
+
+    let mut comparisons = compare_env(vars, container.as_ref());
+    if !args.show_secrets {
+        comparisons
+            .iter_mut()
+            .filter(|c| is_secret_key(&c.var.key))
+            .for_each(redact);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&comparisons)?);
+        return Ok(());
+    }
+
+    let mut rows = vec![format!(
+        "[{}]\t{}\t{}\t{}",
+        format!("{}.{}", args.target().project, args.target().service).cyan(),
+        "value".blue(),
+        "source".blue(),
+        "container".blue()
+    )];
+    rows.extend(comparisons.iter().map(format_row));
+    print_table(rows);
+
+    Ok(())
+}
+
+fn redact(comparison: &mut EnvComparison) {
+    comparison.var.value = REDACTED.to_string();
+    if let EnvDrift::Differs { container_value } = &mut comparison.drift {
+        *container_value = REDACTED.to_string();
+    }
+}
+
+fn format_row(comparison: &EnvComparison) -> String {
+    let drift = match &comparison.drift {
+        EnvDrift::Match => "ok".green().to_string(),
+        EnvDrift::Differs { container_value } => {
+            format!("{} ({})", "differs".yellow(), container_value)
+        }
+        EnvDrift::Missing => "missing".red().to_string(),
+        EnvDrift::NotRunning => "not running".fg(GREY).to_string(),
+        EnvDrift::Unresolved => "unresolved".fg(GREY).to_string(),
+    };
+
+    format!(
+        " - {}\t{}\t{}\t{}",
+        comparison.var.key, comparison.var.value, comparison.var.source, drift
+    )
+}
@@ -1,21 +1,51 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    num::NonZeroUsize,
+};
+
 use clap::{Args, ValueHint};
+use clap_complete::ArgValueCompleter;
+use futures::StreamExt;
 use nirion_lib::{
     context::NirionContext,
-    exec::{exec, ExecRequest},
+    docker::ReplicaRange,
+    exec::{exec_all_stream, exec_command, select_replicas, ExecRequest},
+    exec_history::{
+        exec_history_file, read_exec_history, record_exec, ExecHistoryEntry,
+    },
+    state::state_dir,
+};
+use nirion_tui_lib::color::Colorize;
+
+use crate::{
+    commands::exec_all::print_output,
+    commands::SelectorFlags,
+    completion::running_service_completer,
+    foreground::{run_foreground, ChildExit},
+    ClapSelector, ServiceSelector,
 };
 
-use crate::{ClapSelector, ServiceSelector};
+const EXAMPLES: &str = "\
+Examples:
+  # Open a shell in a service container
+  nirion exec media.jellyfin sh
+  # Run a command as another user in every replica
+  nirion exec web.app --all-replicas -u www-data -- php artisan cache:clear";
 
 /// Execute a command in a running service container
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct ExecArgs {
     /// Service selector: project.service
     #[arg(
-        default_value = "*",
+        required_unless_present = "project",
         value_parser = ServiceSelector::clap_parse,
-        add = ServiceSelector::clap_completer()
+        add = ArgValueCompleter::new(running_service_completer)
     )]
-    target: ServiceSelector,
+    pub target: Option<ServiceSelector>,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
 
     /// Detached mode: run in background
     #[arg(short = 'd', long)]
@@ -33,9 +63,18 @@ pub struct ExecArgs {
     #[arg(short = 'w', long, value_hint = ValueHint::DirPath)]
     workdir: Option<String>,
 
-    /// Container index if service has multiple replicas
-    #[arg(long)]
-    index: Option<u32>,
+    /// Container index if service has multiple replicas, or a range such
+    /// as `1-2` to run the command in each of them
+    #[arg(long, value_name = "INDEX")]
+    index: Option<ReplicaRange>,
+
+    /// Run the command in every replica of the service
+    #[arg(long, conflicts_with_all = ["index", "history"])]
+    all_replicas: bool,
+
+    /// Maximum number of replicas to run the command in concurrently
+    #[arg(short = 'p', long, default_value = "1")]
+    parallel: NonZeroUsize,
 
     /// Environment variables (can be repeated)
     #[arg(short = 'e', long)]
@@ -45,27 +84,180 @@ pub struct ExecArgs {
     #[arg(long)]
     privileged: bool,
 
+    /// Append this invocation to the exec history in the state directory
+    #[arg(long, env = "NIRION_EXEC_RECORD")]
+    record: bool,
+
+    /// List recent recorded commands for the service and pick one to re-run
+    #[arg(long, conflicts_with = "cmd")]
+    history: bool,
+
     /// Command to execute in container
     cmd: Vec<String>,
 }
 
+const HISTORY_LIMIT: usize = 20;
+
+impl ExecArgs {
+    /// The selected service, given positionally or with `--project` and
+    /// `--service`.
+    pub fn target(&self) -> &ServiceSelector {
+        self.target
+            .as_ref()
+            .expect("required by clap; --project and --service are folded in before the command runs")
+    }
+}
+
 pub async fn handle_exec(
     args: &ExecArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
-    exec(
-        context,
-        &ExecRequest {
-            target: args.target.clone(),
-            detach: args.detach,
-            no_tty: args.no_tty,
-            user: args.user.clone(),
-            workdir: args.workdir.clone(),
-            index: args.index,
-            env: args.env.clone(),
-            privileged: args.privileged,
-            cmd: args.cmd.clone(),
-        },
-    )
-    .await
+    if args.history {
+        return handle_history(args, context).await;
+    }
+
+    run(args, context, &args.cmd).await
+}
+
+async fn run(
+    args: &ExecArgs,
+    context: &NirionContext,
+    cmd: &[String],
+) -> anyhow::Result<()> {
+    if args.record && !cmd.is_empty() {
+        record_exec(
+            &exec_history_file(&state_dir()?),
+            &ExecHistoryEntry::now(args.target(), cmd),
+        )?;
+    }
+
+    let request = ExecRequest {
+        target: args.target().clone(),
+        detach: args.detach,
+        no_tty: args.no_tty,
+        user: args.user.clone(),
+        workdir: args.workdir.clone(),
+        index: None,
+        env: args.env.clone(),
+        privileged: args.privileged,
+        cmd: cmd.to_vec(),
+    };
+    let index = args
+        .index
+        .and_then(|range| range.single());
+    if args.all_replicas || (args.index.is_some() && index.is_none()) {
+        return run_replicas(args, context, request).await;
+    }
+
+    let mut command =
+        exec_command(context, &ExecRequest { index, ..request }).await?;
+
+    ChildExit::check(run_foreground(&mut command).await?)
+}
+
+/// Runs `request` in each selected replica with captured output, labeled
+/// per replica. Fails with the highest exit code among the replicas the
+/// command failed in.
+async fn run_replicas(
+    args: &ExecArgs,
+    context: &NirionContext,
+    request: ExecRequest,
+) -> anyhow::Result<()> {
+    let indices = select_replicas(context, args.target(), args.index).await?;
+    let total = indices.len();
+    let requests = indices
+        .into_iter()
+        .map(|index| ExecRequest {
+            index: Some(index),
+            ..request.clone()
+        })
+        .collect();
+
+    let mut stream =
+        exec_all_stream(context.clone(), requests, args.parallel.get(), false);
+    let mut exit_codes = Vec::new();
+    while let Some(output) = stream.next().await {
+        print_output(&output);
+        if !output.exit.success {
+            exit_codes.push(output.exit.code.unwrap_or(1));
+        }
+    }
+
+    println!();
+    println!(
+        "{}/{} replica(s) succeeded",
+        total - exit_codes.len(),
+        total
+    );
+
+    match exit_codes.into_iter().max() {
+        Some(code) => Err(ChildExit { code }.into()),
+        None => Ok(()),
+    }
+}
+
+async fn handle_history(
+    args: &ExecArgs,
+    context: &NirionContext,
+) -> anyhow::Result<()> {
+    let history = read_exec_history(
+        &exec_history_file(&state_dir()?),
+        args.target(),
+        HISTORY_LIMIT,
+    )?;
+
+    if history.is_empty() {
+        println!(
+            "No recorded commands for {}.{}",
+            args.target().project,
+            args.target().service
+        );
+        return Ok(());
+    }
+
+    for (i, entry) in history.iter().enumerate() {
+        println!(
+            "{:>3}  {}  {}  {}",
+            i + 1,
+            entry.timestamp.as_str().dim(),
+            entry.user.as_str().cyan(),
+            entry.command.join(" ")
+        );
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+
+    let Some(choice) = prompt_choice(history.len())? else {
+        return Ok(());
+    };
+
+    run(args, context, &history[choice].command).await
+}
+
+fn prompt_choice(count: usize) -> anyhow::Result<Option<usize>> {
+    loop {
+        print!("Re-run [1-{count}, Enter to cancel]: ");
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if std::io::stdin()
+            .lock()
+            .read_line(&mut line)?
+            == 0
+        {
+            return Ok(None);
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        match line.parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => return Ok(Some(n - 1)),
+            _ => println!("{}", "Invalid selection".red()),
+        }
+    }
 }
@@ -0,0 +1,164 @@
+use std::num::NonZeroUsize;
+
+use clap::{Args, ValueHint};
+use futures::StreamExt;
+use nirion_lib::{
+    context::NirionContext,
+    exec::{exec_all_stream, running_services, ExecOutput, ExecRequest},
+};
+use nirion_tui_lib::color::Colorize;
+
+use crate::{commands::SelectorFlags, ClapSelector, TargetSelector};
+
+const EXAMPLES: &str = "\
+Examples:
+  # Check the disk usage of every container of a project
+  nirion exec-all media -- df -h
+  # Run in four containers at a time and keep going past failures
+  nirion exec-all '*' -p 4 --continue-on-error -- cat /etc/os-release";
+
+/// Execute a command in every running service container of a target
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct ExecAllArgs {
+    /// Target selector: *, project, or project.service
+    #[arg(
+        value_parser = TargetSelector::clap_parse,
+        add = TargetSelector::clap_completer()
+    )]
+    pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Maximum number of services to run the command in concurrently
+    #[arg(short = 'p', long, default_value = "1")]
+    parallel: NonZeroUsize,
+
+    /// Keep going after a service fails instead of stopping at the first
+    /// failure
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Run as this user
+    #[arg(short = 'u', long)]
+    user: Option<String>,
+
+    /// Set working directory inside container
+    #[arg(short = 'w', long, value_hint = ValueHint::DirPath)]
+    workdir: Option<String>,
+
+    /// Environment variables (can be repeated)
+    #[arg(short = 'e', long)]
+    env: Vec<String>,
+
+    /// Privileged mode
+    #[arg(long)]
+    privileged: bool,
+
+    /// Command to execute in each container
+    #[arg(last = true, required = true)]
+    cmd: Vec<String>,
+}
+
+pub async fn handle_exec_all(
+    args: &ExecAllArgs,
+    context: &NirionContext,
+) -> anyhow::Result<()> {
+    let services = running_services(context, &args.target).await?;
+    if services.is_empty() {
+        println!("No running services matched the target");
+        return Ok(());
+    }
+
+    let total = services.len();
+    let requests = services
+        .into_iter()
+        .map(|target| ExecRequest {
+            target,
+            detach: false,
+            no_tty: true,
+            user: args.user.clone(),
+            workdir: args.workdir.clone(),
+            index: None,
+            env: args.env.clone(),
+            privileged: args.privileged,
+            cmd: args.cmd.clone(),
+        })
+        .collect();
+
+    let mut stream = exec_all_stream(
+        context.clone(),
+        requests,
+        args.parallel.get(),
+        !args.continue_on_error,
+    );
+
+    let mut completed = 0;
+    let mut failures = Vec::new();
+
+    while let Some(output) = stream.next().await {
+        completed += 1;
+        print_output(&output);
+
+        if !output.exit.success {
+            failures.push(format!(
+                "{}.{}",
+                output.target.project, output.target.service
+            ));
+        }
+    }
+
+    println!();
+    println!(
+        "{}/{} service(s) succeeded",
+        completed - failures.len(),
+        total
+    );
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    if completed < total {
+        anyhow::bail!(
+            "stopped after failure in {}; {} service(s) not run",
+            failures.join(", "),
+            total - completed
+        );
+    }
+
+    anyhow::bail!(
+        "command failed in {} service(s): {}",
+        failures.len(),
+        failures.join(", ")
+    )
+}
+
+/// The output of one container under a header with its outcome; the
+/// replica number is part of the name when the request named one.
+pub(crate) fn print_output(output: &ExecOutput) {
+    let mut name =
+        format!("{}.{}", output.target.project, output.target.service);
+    if let Some(index) = output.index {
+        name.push_str(&format!("[{index}]"));
+    }
+    let header = if output.exit.success {
+        format!("{} {}", "✓".green(), name.green())
+    } else {
+        let code = output
+            .exit
+            .code
+            .map(|code| format!(" (exit {code})"))
+            .unwrap_or_default();
+        format!("{} {}{}", "✗".red(), name.red(), code)
+    };
+
+    println!("{header}");
+    for line in output.stdout.lines() {
+        println!("  {line}");
+    }
+    for line in output.stderr.lines() {
+        eprintln!("  {}", line.red());
+    }
+}
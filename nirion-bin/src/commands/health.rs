@@ -4,14 +4,26 @@ use futures::StreamExt;
 use nirion_lib::{
     context::NirionContext,
     health::{health_logs_stream, HealthLogStreamOptions},
-    projects::TargetSelector,
+    projects::{Projects, TargetSelector},
 };
 use std::time::Duration;
 
-use crate::{health_render::HealthRenderer, ClapSelector};
+use crate::{
+    commands::{parse_refresh, SelectorFlags, DEFAULT_REFRESH},
+    health_render::HealthRenderer,
+    ClapSelector,
+};
+
+const EXAMPLES: &str = "\
+Examples:
+  # The healthcheck output of every service
+  nirion health logs
+  # Follow the healthchecks of one project
+  nirion health logs media -f";
 
 /// Inspect service healthchecks
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct HealthArgs {
     #[command(subcommand)]
     command: HealthCommand,
@@ -33,15 +45,31 @@ struct HealthLogsArgs {
     )]
     target: TargetSelector,
 
+    #[command(flatten)]
+    selector: SelectorFlags,
+
     /// Follow healthcheck log output
     #[arg(short = 'f', long)]
     follow: bool,
 
     /// Refresh interval for discovering healthcheck changes when following
-    #[arg(short = 'r', long, default_value = "250ms", value_parser = humantime::parse_duration)]
+    #[arg(short = 'r', long, default_value = DEFAULT_REFRESH, value_parser = parse_refresh)]
     refresh: Duration,
 }
 
+impl HealthArgs {
+    pub fn apply_selector_flags(
+        &mut self,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        match &mut self.command {
+            HealthCommand::Logs(args) => args
+                .selector
+                .apply(&mut args.target, projects),
+        }
+    }
+}
+
 pub async fn handle_health(
     args: &HealthArgs,
     context: &NirionContext,
@@ -0,0 +1,119 @@
+use anyhow::Result;
+use clap::Args;
+use nirion_lib::{
+    context::NirionContext,
+    history::{
+        history_file, read_history, HistoryEntry, HistoryFilter,
+        HistoryOutcome, HistoryTime,
+    },
+    state::state_dir,
+};
+use nirion_tui_lib::color::Colorize;
+
+use crate::{
+    commands::SelectorFlags, output::OutputOptions, ClapSelector,
+    TargetSelector,
+};
+
+const EXAMPLES: &str = "\
+Examples:
+  # The last ten commands that touched a project
+  nirion history media -n 10
+  # Everything deployed since a date
+  nirion history --since 2026-01-01";
+
+/// Show when lifecycle commands ran and which image digests were deployed
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct HistoryArgs {
+    /// Target selector: *, project, or project.service
+    #[arg(
+        default_value = "*",
+        value_parser = TargetSelector::clap_parse,
+        add = TargetSelector::clap_completer()
+    )]
+    pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Only entries from this time on: an RFC 3339 timestamp or a
+    /// YYYY-MM-DD date
+    #[arg(long)]
+    pub since: Option<HistoryTime>,
+
+    /// Only entries up to this time: an RFC 3339 timestamp or a
+    /// YYYY-MM-DD date
+    #[arg(long)]
+    pub until: Option<HistoryTime>,
+
+    /// Show only the most recent entries
+    #[arg(short = 'n', long, value_name = "COUNT")]
+    pub limit: Option<usize>,
+
+    /// Print the entries as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+pub async fn handle_history(
+    args: &HistoryArgs,
+    _context: &NirionContext,
+) -> Result<()> {
+    let filter = HistoryFilter {
+        target: args.target.clone(),
+        since: args.since,
+        until: args.until,
+    };
+    let mut entries = read_history(&history_file(&state_dir()?), &filter)?;
+    if let Some(limit) = args.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    for entry in &entries {
+        println!("{}", format_entry(entry));
+    }
+    Ok(())
+}
+
+/// A header line with the command and its outcome, then a line per
+/// affected service with the digest it was deployed with.
+fn format_entry(entry: &HistoryEntry) -> String {
+    let outcome = match (&entry.outcome, &entry.error) {
+        (HistoryOutcome::Success, _) => "ok".green().to_string(),
+        (HistoryOutcome::Failure, Some(error)) => format!("failed: {error}")
+            .red()
+            .to_string(),
+        (HistoryOutcome::Failure, None) => "failed".red().to_string(),
+    };
+    let mut lines = vec![format!(
+        "{} {} {} {} {outcome}",
+        entry.timestamp.as_str().grey(),
+        entry.command.as_str().cyan(),
+        entry.target,
+        format!("({})", entry.user).grey(),
+    )];
+
+    let width = entry
+        .services
+        .keys()
+        .map(|service| service.chars().count())
+        .max()
+        .unwrap_or_default();
+    let digests = OutputOptions::get();
+    for (service, digest) in &entry.services {
+        lines.push(format!(
+            "  {service:<width$}  {}",
+            digest
+                .as_deref()
+                .map_or("-", |digest| digests.digest(digest))
+        ));
+    }
+
+    lines.join("\n")
+}
@@ -3,16 +3,27 @@ use clap::{Args, Subcommand};
 use nirion_lib::{
     context::NirionContext,
     inspect::{
-        inspect_container, inspect_image, inspect_project_containers,
-        inspect_project_images,
+        extract_path, inspect_container, inspect_image,
+        inspect_project_containers, inspect_project_images, InspectResults,
     },
-    projects::{ProjectSelector, TargetSelector},
+    projects::{ProjectSelector, Projects, ServiceSelector, TargetSelector},
 };
+use serde_json::{Map, Value};
 
-use crate::ClapSelector;
+use crate::{commands::SelectorFlags, ClapSelector};
+
+const EXAMPLES: &str = "\
+Examples:
+  # The containers of one service, as JSON
+  nirion inspect container media.jellyfin
+  # The image of every service, one line each
+  nirion inspect image --path Id
+  # The healthcheck history of one service
+  nirion inspect container media.jellyfin --health";
 
 /// Inspect images and services
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct InspectArgs {
     #[command(subcommand)]
     command: InspectCommand,
@@ -21,7 +32,7 @@ pub struct InspectArgs {
 #[derive(Subcommand, Debug, Clone)]
 enum InspectCommand {
     /// Inspect service containers
-    Container(InspectTargetArgs),
+    Container(InspectContainerArgs),
 
     /// Inspect service images
     Image(InspectTargetArgs),
@@ -37,13 +48,52 @@ struct InspectTargetArgs {
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    selector: SelectorFlags,
+
     /// The inspect format
     #[arg(short, long, default_value = "json")]
     format: String,
 
-    /// Print json without pretty printing
+    /// Print json without pretty printing; several services are printed
+    /// as one object keyed by `project.service`
     #[arg(short, long)]
     raw: bool,
+
+    /// Print only this field of each output, as `project.service: value`.
+    /// Either a JSON pointer (`/Config/Image`) or a dotted path
+    /// (`Config.Image`, `Mounts.0.Source`)
+    #[arg(long)]
+    path: Option<String>,
+}
+
+/// Docker format printing the healthcheck history of a container.
+const HEALTH_FORMAT: &str = "{{json .State.Health.Log}}";
+
+#[derive(Args, Debug, Clone)]
+struct InspectContainerArgs {
+    #[command(flatten)]
+    target: InspectTargetArgs,
+
+    /// Print only the healthcheck history, a shortcut for
+    /// `--format '{{json .State.Health.Log}}'`
+    #[arg(long, conflicts_with = "format")]
+    health: bool,
+}
+
+impl InspectArgs {
+    pub fn apply_selector_flags(
+        &mut self,
+        projects: &Projects,
+    ) -> Result<(), String> {
+        let target = match &mut self.command {
+            InspectCommand::Container(args) => &mut args.target,
+            InspectCommand::Image(args) => args,
+        };
+        target
+            .selector
+            .apply(&mut target.target, projects)
+    }
 }
 
 pub async fn handle_inspect(
@@ -52,7 +102,11 @@ pub async fn handle_inspect(
 ) -> Result<()> {
     match &args.command {
         InspectCommand::Container(args) => {
-            inspect_containers(args, context).await?
+            let mut target = args.target.clone();
+            if args.health {
+                target.format = HEALTH_FORMAT.to_string();
+            }
+            inspect_containers(&target, context).await?
         }
         InspectCommand::Image(args) => inspect_images(args, context).await?,
     }
@@ -64,80 +118,142 @@ async fn inspect_containers(
     args: &InspectTargetArgs,
     context: &NirionContext,
 ) -> Result<()> {
+    let raw = args.raw || args.path.is_some();
     match &args.target {
-        TargetSelector::All => {
-            for (project_name, _) in context.projects.iter() {
-                let project_selector = ProjectSelector {
-                    name: project_name.to_string(),
-                };
-                for output in inspect_project_containers(
-                    context,
-                    &project_selector,
-                    &args.format,
-                    args.raw,
-                )
-                .await?
-                {
-                    println!("{output}");
-                }
-            }
+        TargetSelector::Service(service) => {
+            let output =
+                inspect_container(context, service, &args.format, raw).await?;
+            print_service_output(args, service, &output)
         }
-        TargetSelector::Project(proj) => {
-            for output in inspect_project_containers(
-                context,
-                proj,
-                &args.format,
-                args.raw,
-            )
-            .await?
-            {
-                println!("{output}");
+        target => {
+            let mut results = InspectResults::default();
+            for project in target_projects(context, target) {
+                results.extend(
+                    inspect_project_containers(
+                        context,
+                        &project,
+                        &args.format,
+                        raw,
+                    )
+                    .await,
+                );
             }
-        }
-        TargetSelector::Service(img) => {
-            let output =
-                inspect_container(context, img, &args.format, args.raw).await?;
-            println!("{output}");
+            print_results(args, &mut results)?;
+            results.check()
         }
     }
-    Ok(())
 }
 
 async fn inspect_images(
     args: &InspectTargetArgs,
     context: &NirionContext,
 ) -> Result<()> {
+    let raw = args.raw || args.path.is_some();
     match &args.target {
-        TargetSelector::All => {
-            for (project_name, _) in context.projects.iter() {
-                let project_selector = ProjectSelector {
-                    name: project_name.to_string(),
-                };
-                for output in inspect_project_images(
-                    context,
-                    &project_selector,
-                    &args.format,
-                    args.raw,
-                )
-                .await?
-                {
-                    println!("{output}");
-                }
+        TargetSelector::Service(service) => {
+            let output =
+                inspect_image(context, service, &args.format, raw).await?;
+            print_service_output(args, service, &output)
+        }
+        target => {
+            let mut results = InspectResults::default();
+            for project in target_projects(context, target) {
+                results.extend(
+                    inspect_project_images(
+                        context,
+                        &project,
+                        &args.format,
+                        raw,
+                    )
+                    .await,
+                );
             }
+            print_results(args, &mut results)?;
+            results.check()
         }
-        TargetSelector::Project(proj) => {
-            for output in
-                inspect_project_images(context, proj, &args.format, args.raw)
-                    .await?
-            {
-                println!("{output}");
+    }
+}
+
+fn target_projects(
+    context: &NirionContext,
+    target: &TargetSelector,
+) -> Vec<ProjectSelector> {
+    match target {
+        TargetSelector::All => context
+            .projects
+            .iter()
+            .map(|(name, _)| ProjectSelector {
+                name: name.to_string(),
+            })
+            .collect(),
+        TargetSelector::Project(project) => vec![project.clone()],
+        TargetSelector::Service(service) => vec![ProjectSelector {
+            name: service.project.clone(),
+        }],
+    }
+}
+
+fn print_service_output(
+    args: &InspectTargetArgs,
+    service: &ServiceSelector,
+    output: &str,
+) -> Result<()> {
+    match &args.path {
+        Some(path) => {
+            let value = extract_path(output, path)?;
+            println!(
+                "{}.{}: {}",
+                service.project,
+                service.service,
+                format_value(&value)
+            );
+        }
+        None => println!("{output}"),
+    }
+    Ok(())
+}
+
+/// With `--path`, one `project.service: value` line per service; with
+/// `--raw`, a single JSON object keyed by `project.service` so the
+/// output can be piped to jq as a whole. Services the path can't be
+/// found in are added to the failures.
+fn print_results(
+    args: &InspectTargetArgs,
+    results: &mut InspectResults,
+) -> Result<()> {
+    if let Some(path) = &args.path {
+        for (label, output) in &results.outputs {
+            match extract_path(output, path) {
+                Ok(value) => println!("{label}: {}", format_value(&value)),
+                Err(e) => results
+                    .failures
+                    .push(format!("{label}: {e}")),
             }
         }
-        TargetSelector::Service(img) => {
-            let output =
-                inspect_image(context, img, &args.format, args.raw).await?;
+    } else if args.raw {
+        let document = results
+            .outputs
+            .iter()
+            .map(|(label, output)| {
+                let value = serde_json::from_str(output).unwrap_or_else(|_| {
+                    Value::String(output.trim_end().to_string())
+                });
+                (label.clone(), value)
+            })
+            .collect::<Map<_, _>>();
+        println!("{}", serde_json::to_string(&document)?);
+    } else {
+        for (_, output) in &results.outputs {
             println!("{output}");
         }
     }
     Ok(())
 }
+
+/// Strings are printed without quotes, everything else as compact JSON.
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use nirion_lib::{
+    context::NirionContext,
+    lint::{lint_projects, Finding, Severity},
+};
+use nirion_tui_lib::color::Colorize;
+
+use crate::{
+    commands::SelectorFlags, output::OutputOptions, ClapSelector,
+    TargetSelector,
+};
+
+const EXAMPLES: &str = "\
+Examples:
+  # Check every compose file
+  nirion lint
+  # Also fail on warnings, with the findings as JSON
+  nirion lint media --deny warnings --json";
+
+/// Check the compose files for patterns nirion can't handle well
+///
+/// Findings are suppressed per service by listing their code or name in
+/// the service's `lintIgnore` in the project file.
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct LintArgs {
+    /// Target selector: *, project, or project.service
+    #[arg(
+        default_value = "*",
+        value_parser = TargetSelector::clap_parse,
+        add = TargetSelector::clap_completer()
+    )]
+    pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Also fail on findings of this severity; errors always fail
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    pub deny: Option<Deny>,
+
+    /// Print the findings as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deny {
+    Warnings,
+}
+
+pub async fn handle_lint(
+    args: &LintArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let findings = lint_projects(&context.projects, &args.target)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            println!("{}", format_finding(finding));
+        }
+    }
+
+    let count = |severity| {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    };
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    let summary = format!("{errors} error(s), {warnings} warning(s)");
+    if errors > 0 || (args.deny == Some(Deny::Warnings) && warnings > 0) {
+        anyhow::bail!("lint failed with {summary}");
+    }
+    if !args.json && !OutputOptions::get().quiet {
+        eprintln!("{}", summary.grey().for_stderr());
+    }
+    Ok(())
+}
+
+fn format_finding(finding: &Finding) -> String {
+    let label = format!("{}[{}]", finding.severity, finding.code.code());
+    let label = match finding.severity {
+        Severity::Error => label.red().bold().to_string(),
+        Severity::Warning => label.yellow().bold().to_string(),
+    };
+    format!(
+        "{label} {}.{}: {}\n  {} {}",
+        finding.project,
+        finding.service,
+        finding.message,
+        "-->".blue(),
+        finding.location
+    )
+}
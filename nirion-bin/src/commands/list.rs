@@ -1,10 +1,18 @@
 use clap::Args;
 use nirion_lib::context::NirionContext;
 
-use crate::{ClapSelector, TargetSelector};
+use crate::{commands::SelectorFlags, ClapSelector, TargetSelector};
+
+const EXAMPLES: &str = "\
+Examples:
+  # Every project and its services
+  nirion list
+  # The services of one project
+  nirion list media";
 
 /// List projects or services
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct ListArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -13,6 +21,9 @@ pub struct ListArgs {
         add = TargetSelector::clap_completer()
     )]
     pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
 }
 
 pub async fn handle_list(
@@ -37,8 +48,8 @@ pub async fn handle_list(
 
         TargetSelector::Service(img) => {
             println!(
-                "Selector '{}' refers to a specific service. Printing that one only:",
-                format!("{}.{}", img.project, img.service)
+                "Selector '{}.{}' refers to a specific service. Printing that one only:",
+                img.project, img.service
             );
             println!("- {}", img.service);
         }
@@ -1,21 +1,52 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use clap::Args;
-use futures::StreamExt;
+use anyhow::Context;
+use clap::{Args, Subcommand};
 use nirion_lib::{
     context::NirionContext,
-    events::LockUpdateEvent,
-    lock::{DiffEntry, LockedImages},
-    lock_update::image_update_stream,
-    projects::{get_images, TargetSelector},
+    downgrade::SuspectedDowngrade,
+    events::{DigestSource, LockUpdateEvent},
+    git::{projects_at_ref, retain_changed_images},
+    lock::{
+        group_diffs, lock_schema_version, DiffEntry, DiffGroup, LockedImages,
+        VersionedImage, LOCK_SCHEMA_VERSION,
+    },
+    lock_store::LockStore,
+    lock_update::image_lock_stream,
+    projects::{
+        get_images, get_local_build_images, retain_images_matching,
+        ImagePattern, TargetSelector,
+    },
+    resolve_failure::FailureReport,
+};
+use nirion_tui_lib::{color::Colorize, table::format_table};
+
+use crate::{
+    commands::SelectorFlags,
+    output::OutputOptions,
+    update_progress::{print_lock_update_events, ProgressMode},
+    ClapSelector, PROJECT_SOURCE,
 };
-use nirion_tui_lib::color::Colorize;
 
-use crate::ClapSelector;
+const EXAMPLES: &str = "\
+Examples:
+  # Lock the services that have no entry yet
+  nirion lock
+  # Also re-lock the existing entries of one project
+  nirion lock --force media";
 
 /// Create missing lock file entries
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct LockArgs {
+    #[command(subcommand)]
+    pub command: Option<LockCommand>,
+
     /// Target selector: *, project, or project.service
     #[arg(
         default_value = "*",
@@ -24,27 +55,297 @@ pub struct LockArgs {
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Also re-lock the existing entries of this target at the digest
+    /// their image reference points to now
+    #[arg(
+        long,
+        value_name = "TARGET",
+        value_parser = TargetSelector::clap_parse,
+        add = TargetSelector::clap_completer()
+    )]
+    pub force: Option<TargetSelector>,
+
+    /// Re-lock only the services whose image reference in the project
+    /// file changed since this git ref, e.g. HEAD
+    #[arg(long, value_name = "GIT_REF")]
+    pub changed_since: Option<String>,
+
+    /// Only services whose image repository contains this text or
+    /// matches this glob, whatever the tag; repeatable
+    #[arg(long, value_name = "PATTERN")]
+    pub image: Vec<ImagePattern>,
+
+    /// Print the services --image selects without resolving anything
+    #[arg(long, requires = "image")]
+    pub list_matches: bool,
+
+    /// Take the digest of images already pulled on this host from docker
+    /// instead of the registry, which may lag behind what their tag
+    /// points to now
+    #[arg(long)]
+    pub prefer_local: bool,
+
     /// Number of concurrent digest fetches
     #[arg(short = 'j', long = "jobs", default_value_t = 10)]
     pub jobs: usize,
+
+    /// How to show images being checked
+    #[arg(long, value_enum, default_value = "auto")]
+    pub progress: ProgressMode,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum LockCommand {
+    /// Rewrite the lock file in the current schema version
+    Migrate,
+
+    /// Split the lock file into one lock file per project
+    Split {
+        /// Directory to write the per-project lock files to
+        dir: PathBuf,
+    },
 }
 
 pub async fn handle_lock(
     args: &LockArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
-    let mut images = get_images(&args.target, &context.projects);
-    retain_images_missing_lock_entries(&mut images, &context.locked_images);
+    match &args.command {
+        Some(LockCommand::Migrate) => return migrate_lock(context),
+        Some(LockCommand::Split { dir }) => return split_lock(context, dir),
+        None => {}
+    }
 
-    let mut events = image_update_stream(context, images, args.jobs);
+    let announce = !OutputOptions::get().quiet;
+    let mut images = get_images(&args.target, &context.projects)?;
+    match &args.changed_since {
+        Some(git_ref) => {
+            retain_changed_since(&mut images, git_ref, announce).await?
+        }
+        None => retain_images_missing_lock_entries(
+            &mut images,
+            &context.locked_images,
+        ),
+    }
+    if let Some(force) = &args.force {
+        images.extend(get_images(force, &context.projects)?);
+    }
+    if !filter_by_image(&mut images, &args.image, args.list_matches, announce) {
+        return Ok(());
+    }
+    skip_local_builds(&args.target, context, &args.image, announce)?;
 
-    while let Some(event) = events.next().await {
-        println!("{}", format_lock_update_event(event?));
+    let total = images.len();
+    let events =
+        image_lock_stream(context, images, args.jobs, args.prefer_local);
+
+    let mut local = Vec::new();
+    print_lock_update_events(
+        events,
+        total,
+        args.jobs,
+        args.progress,
+        OutputOptions::get(),
+        |event| {
+            if let LockUpdateEvent::ImageResolved {
+                service,
+                source: DigestSource::LocalImage,
+            } = event
+            {
+                local.push(service.clone());
+            }
+        },
+    )
+    .await?;
+
+    if announce && !local.is_empty() {
+        local.sort();
+        println!(
+            "{}",
+            format!("Digests of {} taken from local images", local.join(", "))
+                .grey()
+        );
     }
+    Ok(())
+}
+
+fn migrate_lock(context: &NirionContext) -> anyhow::Result<()> {
+    let path = match context.lock_store()? {
+        LockStore::File(path) => path,
+        LockStore::Dir(_) => {
+            let written = context
+                .lock_store()?
+                .write(&LockedImages::default(), &context.locked_images)?;
+            for path in written {
+                println!("Rewrote {}", path.display());
+            }
+            return Ok(());
+        }
+    };
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let value = serde_json::from_str(&data)
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let schema = lock_schema_version(&value)?;
+    if schema == LOCK_SCHEMA_VERSION {
+        println!(
+            "{} already uses lock schema v{LOCK_SCHEMA_VERSION}",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let mut locked_images = LockedImages::from_value(value)?;
+    let images = get_images(&TargetSelector::All, &context.projects)?;
+    let filled = locked_images.fill_missing_images(&images);
+
+    println!(
+        "Migrating {} from lock schema v{schema} to v{LOCK_SCHEMA_VERSION} \
+         ({} entries)",
+        path.display(),
+        locked_images.len()
+    );
+    for service in &filled {
+        println!("  ~ {}: image set to {}", service.cyan(), images[service]);
+    }
+    for (service, locked) in locked_images.iter() {
+        if locked.image.is_empty() {
+            println!(
+                "  ! {}: not in the project file, image left empty",
+                service.yellow()
+            );
+        }
+    }
+
+    fs::write(path, locked_images.to_pretty_string()?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    println!("Lock file migrated successfully");
 
     Ok(())
 }
 
+fn split_lock(
+    context: &NirionContext,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    if let LockStore::Dir(current) = context.lock_store()? {
+        anyhow::bail!(
+            "already using per-project lock files in {}",
+            current.display()
+        );
+    }
+
+    let written = LockStore::Dir(dir.to_path_buf())
+        .write(&LockedImages::default(), &context.locked_images)?;
+    for path in &written {
+        println!("Wrote {}", path.display());
+    }
+    println!(
+        "Split {} entries into {} project lock files; pass --lock-dir {} to \
+         use them",
+        context.locked_images.len(),
+        written.len(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
+/// Narrows `images` to the `--image` patterns and, with `announce`, says
+/// which services they matched. False if the command should stop there,
+/// as with `--list-matches`.
+pub(crate) fn filter_by_image(
+    images: &mut BTreeMap<String, String>,
+    patterns: &[ImagePattern],
+    list_matches: bool,
+    announce: bool,
+) -> bool {
+    if patterns.is_empty() {
+        return true;
+    }
+    retain_images_matching(images, patterns);
+
+    if list_matches {
+        for service in images.keys() {
+            println!("{service}");
+        }
+        return false;
+    }
+    if images.is_empty() {
+        let patterns = patterns
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        eprintln!(
+            "{} no service image matches {patterns}",
+            "warning:".yellow()
+        );
+    } else if announce {
+        println!("Matched {} service(s) by image:", images.len());
+        for (service, image) in images.iter() {
+            println!("  {} {}", service.cyan(), image.as_str().grey());
+        }
+    }
+    true
+}
+
+/// The services of `target` marked as local builds whose image matches
+/// `patterns`, which lock and update leave alone. With `announce`, each
+/// is listed as skipped.
+pub(crate) fn skip_local_builds(
+    target: &TargetSelector,
+    context: &NirionContext,
+    patterns: &[ImagePattern],
+    announce: bool,
+) -> anyhow::Result<Vec<String>> {
+    let mut builds = get_local_build_images(target, &context.projects)?
+        .into_iter()
+        .map(|image| (image.service_ref(), image.image))
+        .collect::<BTreeMap<_, _>>();
+    retain_images_matching(&mut builds, patterns);
+    if announce {
+        for (service, image) in &builds {
+            let event = LockUpdateEvent::ImageSkipped {
+                service: service.clone(),
+                image: image.clone(),
+            };
+            println!("{}", format_lock_update_event(event));
+        }
+    }
+    Ok(builds.into_keys().collect())
+}
+
+/// Narrows `images` to the services whose image reference differs from
+/// the one in the project file at `git_ref`, and lists them.
+pub(crate) async fn retain_changed_since(
+    images: &mut BTreeMap<String, String>,
+    git_ref: &str,
+    announce: bool,
+) -> anyhow::Result<()> {
+    let source = PROJECT_SOURCE
+        .get()
+        .context("the project file is not loaded")?;
+    let old = projects_at_ref(source, git_ref).await?;
+    retain_changed_images(images, &get_images(&TargetSelector::All, &old)?);
+
+    if announce {
+        println!(
+            "{} service image(s) changed since {git_ref}{}",
+            images.len(),
+            if images.is_empty() { "" } else { ":" }
+        );
+        for (service, image) in images.iter() {
+            println!("  {} {}", service.cyan(), image.as_str().grey());
+        }
+    }
+    Ok(())
+}
+
 fn retain_images_missing_lock_entries(
     images: &mut BTreeMap<String, String>,
     locked_images: &LockedImages,
@@ -63,15 +364,41 @@ pub fn format_lock_update_event(event: LockUpdateEvent) -> String {
         LockUpdateEvent::ImageStarted { service, image } => {
             format!("Checking {service}: {image}")
         }
-        LockUpdateEvent::ImageResolved { service } => {
-            format!("Resolved {service}")
+        LockUpdateEvent::ImageResolved {
+            service,
+            source: DigestSource::Registry,
+        } => format!("Resolved {service}"),
+        LockUpdateEvent::ImageResolved {
+            service,
+            source: DigestSource::LocalImage,
+        } => format!("Resolved {service} from the local image"),
+        LockUpdateEvent::ImageSkipped { service, image } => {
+            format!("{service}: {image} skipped (local build)")
         }
+        LockUpdateEvent::TagSearchCapped {
+            service,
+            image,
+            searched,
+        } => format!(
+            "{} {service}: stopped looking for the version of {image} after \
+             its newest {searched} tags; it may be missing or not the best \
+             match",
+            "warning:".yellow()
+        ),
         LockUpdateEvent::UpToDate => {
             "All images are already up-to-date".to_string()
         }
+        LockUpdateEvent::DowngradesSuspected { downgrades, held } => {
+            format!("\n{}", format_downgrades(&downgrades, held).trim_end())
+        }
         LockUpdateEvent::ChangesDetected { diffs } => {
             format!("\nChanges:\n{}", format_diff(&diffs).trim_end())
         }
+        LockUpdateEvent::ResolutionFailed { report } => {
+            format_failure_report(&report)
+                .trim_end()
+                .to_string()
+        }
         LockUpdateEvent::WritingLockFile => {
             "\nUpdating lock file...".to_string()
         }
@@ -81,55 +408,364 @@ pub fn format_lock_update_event(event: LockUpdateEvent) -> String {
     }
 }
 
+/// What `--quiet` keeps of `event`: the changes and the failures,
+/// without headers or status messages.
+pub fn format_quiet_lock_update_event(
+    event: LockUpdateEvent
+) -> Option<String> {
+    match event {
+        LockUpdateEvent::ChangesDetected { diffs } => Some(
+            format_diff(&diffs)
+                .trim_end()
+                .to_string(),
+        ),
+        LockUpdateEvent::DowngradesSuspected { downgrades, held } => Some(
+            format_downgrades(&downgrades, held)
+                .trim_end()
+                .to_string(),
+        ),
+        LockUpdateEvent::ResolutionFailed { .. } => {
+            Some(format_lock_update_event(event))
+        }
+        _ => None,
+    }
+}
+
+/// The changes as one aligned table in Added, Updated and Removed
+/// sections, sorted by project and service, followed by
+/// [`format_diff_summary`]. Services sharing the same change share a row,
+/// with their names listed below it.
 fn format_diff(diffs: &[DiffEntry]) -> String {
-    let mut output = String::new();
+    let mut sorted = diffs.to_vec();
+    sorted.sort_by(|a, b| {
+        service_sort_key(a.service()).cmp(&service_sort_key(b.service()))
+    });
+    let groups = group_diffs(&sorted);
+    let digests = OutputOptions::get();
 
-    for entry in diffs {
-        match entry {
-            DiffEntry::Added { service, new } => {
-                output.push_str(&format!("  + {}:\n", service.green()));
-                if let Some(version) = &new.version {
-                    output
-                        .push_str(&format!("      new version: {}\n", version));
-                }
-                output.push_str(&format!("      new digest: {}\n", new.digest));
+    let mut rows = vec![];
+    for section in ["Added", "Updated", "Removed"] {
+        let mut section_groups = groups
+            .iter()
+            .filter(|group| change_kind(group) == Some(section))
+            .peekable();
+        if section_groups.peek().is_none() {
+            continue;
+        }
+        let header = format!("{section}:");
+        rows.push(format!(
+            "  {}",
+            match section {
+                "Added" => header.green(),
+                "Updated" => header.cyan(),
+                _ => header.yellow(),
             }
-            DiffEntry::Updated { service, old, new } => {
-                output.push_str(&format!("  ~ {}:\n", service.cyan()));
-                if let Some(version) = &new.version {
-                    let old_version = old
-                        .version
-                        .as_ref()
-                        .map(|s| s.as_str())
-                        .unwrap_or("none");
-
-                    output.push_str(&format!(
-                        "      new version: {} -> {}",
-                        old_version, version
-                    ));
-                    output.push('\n');
+        ));
+
+        for group in section_groups {
+            let subject = match group.services.as_slice() {
+                [service] => service.clone(),
+                services => {
+                    format!("{} ({} services)", group.image(), services.len())
                 }
-                output.push_str(&format!("      old digest: {}\n", old.digest));
-                output.push_str(&format!("      new digest: {}\n", new.digest));
+            };
+            let (version, digest) = match (&group.old, &group.new) {
+                (Some(old), Some(new)) => (
+                    format_change(
+                        old.version.as_deref(),
+                        new.version.as_deref(),
+                    ),
+                    format_change(
+                        Some(digests.digest(&old.digest)),
+                        Some(digests.digest(&new.digest)),
+                    ),
+                ),
+                (Some(image), None) | (None, Some(image)) => (
+                    image
+                        .version
+                        .clone()
+                        .unwrap_or_else(|| "-".to_string()),
+                    digests
+                        .digest(&image.digest)
+                        .to_string(),
+                ),
+                (None, None) => continue,
+            };
+
+            let mut row = format!("    {subject}\t{version}\t{digest}");
+            if let Some(size) = group
+                .new
+                .as_ref()
+                .and_then(|new| new.size)
+            {
+                row.push_str(&format!("\t{}", format_size(size)));
             }
-            DiffEntry::Removed { service, old } => {
-                output.push_str(&format!("  - {}:\n", service.yellow()));
-                if let Some(version) = &old.version {
-                    output
-                        .push_str(&format!("      old version: {}\n", version));
+            rows.push(row);
+            if group.services.len() > 1 {
+                for service in &group.services {
+                    rows.push(format!("      {}", service.as_str().grey()));
                 }
-                output.push_str(&format!("      old digest: {}\n", old.digest));
             }
         }
     }
 
+    let mut output = format_table(rows);
+    output.push_str(&format!("\n  {}\n", format_diff_summary(diffs)));
+
+    // A shared image is pulled once, however many services use it.
+    let total = groups
+        .iter()
+        .filter_map(|group| group.new.as_ref()?.size)
+        .reduce(|total, size| total + size);
+    if let Some(total) = total {
+        output.push_str(&format!(
+            "  total download size: {}\n",
+            format_size(total)
+        ));
+    }
+
+    output
+}
+
+/// The updates that look like downgrades, in red with the reason, and
+/// whether they were held back or written anyway.
+fn format_downgrades(
+    downgrades: &[SuspectedDowngrade],
+    held: bool,
+) -> String {
+    let header = if held {
+        "Suspected downgrades, not written (pass --allow-downgrade to \
+         accept):"
+    } else {
+        "Suspected downgrades, written because of --allow-downgrade:"
+    };
+    let digests = OutputOptions::get();
+
+    let mut rows = vec![];
+    for downgrade in downgrades {
+        rows.push(format!(
+            "    {}\t{}\t{}\t{}",
+            downgrade.service,
+            format_change(
+                downgrade.old.version.as_deref(),
+                downgrade.new.version.as_deref(),
+            ),
+            format_change(
+                Some(digests.digest(&downgrade.old.digest)),
+                Some(digests.digest(&downgrade.new.digest)),
+            ),
+            downgrade.reason.describe().red()
+        ));
+    }
+    format!("  {}\n{}", header.red(), format_table(rows))
+}
+
+/// `3 added, 7 updated, 1 removed`, leaving out kinds of change that
+/// didn't happen.
+pub fn format_diff_summary(diffs: &[DiffEntry]) -> String {
+    let count = |kind: fn(&DiffEntry) -> bool| {
+        diffs
+            .iter()
+            .filter(|entry| kind(entry))
+            .count()
+    };
+    let counts = [
+        (
+            count(|entry| matches!(entry, DiffEntry::Added { .. })),
+            "added",
+        ),
+        (
+            count(|entry| matches!(entry, DiffEntry::Updated { .. })),
+            "updated",
+        ),
+        (
+            count(|entry| matches!(entry, DiffEntry::Removed { .. })),
+            "removed",
+        ),
+    ];
+
+    let parts = counts
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, kind)| format!("{count} {kind}"))
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        "no changes".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+fn change_kind(group: &DiffGroup) -> Option<&'static str> {
+    match (&group.old, &group.new) {
+        (None, Some(_)) => Some("Added"),
+        (Some(_), Some(_)) => Some("Updated"),
+        (Some(_), None) => Some("Removed"),
+        (None, None) => None,
+    }
+}
+
+/// `project.service` split in two, so that services sort by project
+/// first, whatever characters follow a shorter project's name.
+fn service_sort_key(service: &str) -> (&str, &str) {
+    service
+        .split_once('.')
+        .unwrap_or((service, ""))
+}
+
+/// `old → new` with the old value greyed out, or just the value if it
+/// didn't change. Missing values read `none`, or `-` if both are.
+fn format_change(
+    old: Option<&str>,
+    new: Option<&str>,
+) -> String {
+    match (old, new) {
+        (None, None) => "-".to_string(),
+        (old, new) if old == new => new.unwrap_or_default().to_string(),
+        (old, new) => format!(
+            "{} {} {}",
+            old.unwrap_or("none").grey(),
+            "→".grey(),
+            new.unwrap_or("none")
+        ),
+    }
+}
+
+/// Failures grouped by cause, each group ending with a suggested fix.
+/// Registries where every image failed the same way get a single line.
+fn format_failure_report(report: &FailureReport) -> String {
+    let mut output = format!(
+        "\nFailed to resolve {} of {} images:\n",
+        report.failed, report.total
+    );
+
+    for group in &report.groups {
+        let count = group.image_count(report);
+        output.push_str(&format!(
+            "\n  {} ({count} image{}):\n",
+            group.cause.label().red(),
+            if count == 1 { "" } else { "s" }
+        ));
+        for registry in &group.registries {
+            output.push_str(&format!(
+                "    {}: all {} images\n",
+                registry,
+                report.registry_images(registry)
+            ));
+        }
+        for failure in &group.failures {
+            let registry = failure
+                .registry
+                .as_deref()
+                .map(|registry| format!(" ({registry})"))
+                .unwrap_or_default();
+            output.push_str(&format!(
+                "    {}: {}{registry}\n      {}\n",
+                failure.service, failure.image, failure.error
+            ));
+        }
+        output.push_str(&format!("    → {}\n", group.hint));
+    }
+
     output
 }
 
+/// A markdown summary of `diffs` for a commit body or PR description:
+/// a subject line, then one bullet per service.
+pub fn format_markdown_summary(diffs: &[DiffEntry]) -> String {
+    let mut output =
+        format!("Update locked images: {}\n\n", format_diff_summary(diffs));
+
+    for entry in diffs {
+        let line = match entry {
+            DiffEntry::Added { service, new } => format!(
+                "- `{service}`: add {} at {}",
+                new.image,
+                version_and_digest(new)
+            ),
+            DiffEntry::Updated { service, old, new } => format!(
+                "- `{service}`: {} → {}",
+                version_and_digest(old),
+                version_and_digest(new)
+            ),
+            DiffEntry::Removed { service, old } => format!(
+                "- `{service}`: remove {} at {}",
+                old.image,
+                version_and_digest(old)
+            ),
+        };
+        output.push_str(&line);
+
+        let new = match entry {
+            DiffEntry::Added { new, .. } | DiffEntry::Updated { new, .. } => {
+                Some(new)
+            }
+            DiffEntry::Removed { .. } => None,
+        };
+        if let Some(url) = new.and_then(changelog_url) {
+            output.push_str(&format!(" ([changelog]({url}))"));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+fn version_and_digest(image: &VersionedImage) -> String {
+    let digest = OutputOptions::get().digest(&image.digest);
+    match &image.version {
+        Some(version) => format!("{version} (`{digest}`)"),
+        None => format!("`{digest}`"),
+    }
+}
+
+/// The releases of the GitHub repository an image names as its source.
+/// Images without the label, or built elsewhere, get no link.
+fn changelog_url(image: &VersionedImage) -> Option<String> {
+    let source = image.source.as_deref()?;
+    let path = source
+        .strip_prefix("https://github.com/")?
+        .trim_end_matches('/');
+    let mut parts = path.split('/');
+    let (owner, repo) = (parts.next()?, parts.next()?);
+    let repo = repo.trim_end_matches(".git");
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+
+    Some(format!("https://github.com/{owner}/{repo}/releases"))
+}
+
+/// Decimal units, like docker reports image sizes.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next;
+    }
+
+    if size < 10.0 {
+        format!("{size:.1} {unit}")
+    } else {
+        format!("{size:.0} {unit}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use nirion_lib::lock::VersionedImage;
+    use nirion_lib::resolve_failure::ImageFailure;
+    use nirion_oci_lib::oci_client::errors::OciDistributionError;
     use nirion_tui_lib::ansi::strip_ansi_codes;
 
     fn image(
@@ -141,6 +777,8 @@ mod tests {
             image: image.to_string(),
             version: version.map(str::to_string),
             digest: digest.to_string(),
+            size: None,
+            source: None,
         }
     }
 
@@ -164,20 +802,78 @@ mod tests {
 
         let output = strip_ansi_codes(&format_diff(&diffs)).into_owned();
 
-        let added = output.find("+ app.web").unwrap();
-        let added_version = output.find("1.27").unwrap();
-        let updated = output.find("~ app.worker").unwrap();
-        let old_version = output.find("1.0").unwrap();
-        let new_version = output.find("2.0").unwrap();
-        let removed = output.find("- app.db").unwrap();
-        let removed_version = output.find("16").unwrap();
+        assert_eq!(
+            output,
+            "  Added:\n\
+             \x20   app.web     1.27       added\n\
+             \x20 Updated:\n\
+             \x20   app.worker  1.0 → 2.0  old → new\n\
+             \x20 Removed:\n\
+             \x20   app.db      16         removed\n\
+             \n  1 added, 1 updated, 1 removed\n"
+        );
+    }
+
+    #[test]
+    fn format_diff_sorts_by_project_then_service() {
+        let diffs = ["app.worker", "app-x.web", "app.api"]
+            .map(|service| DiffEntry::Added {
+                service: service.to_string(),
+                new: image("nginx:1.27", None, service),
+            })
+            .to_vec();
+
+        let output = strip_ansi_codes(&format_diff(&diffs)).into_owned();
+        let services = output
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|word| word.contains('.'))
+            .collect::<Vec<_>>();
+        assert_eq!(services, ["app.api", "app.worker", "app-x.web"]);
+    }
+
+    #[test]
+    fn format_downgrades_flags_each_service_with_its_reason() {
+        use nirion_lib::downgrade::DowngradeReason;
+
+        let downgrades = vec![SuspectedDowngrade {
+            service: "app.web".to_string(),
+            old: image("nginx:1", Some("1.27.1"), "sha256:new"),
+            new: image("nginx:1", Some("1.27.0"), "sha256:old"),
+            reason: DowngradeReason::OlderVersion,
+        }];
 
-        assert!(added < added_version);
-        assert!(added_version < updated);
-        assert!(updated < old_version);
-        assert!(old_version < new_version);
-        assert!(new_version < removed);
-        assert!(removed < removed_version);
+        assert_eq!(
+            strip_ansi_codes(&format_downgrades(&downgrades, true)),
+            "  Suspected downgrades, not written (pass --allow-downgrade to \
+             accept):\n    \
+             app.web  1.27.1 → 1.27.0  new → old  older than the locked \
+             version\n"
+        );
+        assert!(strip_ansi_codes(&format_downgrades(&downgrades, false))
+            .starts_with("  Suspected downgrades, written because of"));
+    }
+
+    #[test]
+    fn format_diff_summary_counts_each_kind_of_change() {
+        let added = |service: &str| DiffEntry::Added {
+            service: service.to_string(),
+            new: image("nginx:1.27", None, "sha256:new"),
+        };
+        let removed = |service: &str| DiffEntry::Removed {
+            service: service.to_string(),
+            old: image("nginx:1.27", None, "sha256:old"),
+        };
+
+        assert_eq!(
+            format_diff_summary(&[
+                added("a.web"),
+                added("b.web"),
+                removed("c.db")
+            ]),
+            "2 added, 1 removed"
+        );
+        assert_eq!(format_diff_summary(&[]), "no changes");
     }
 
     #[test]
@@ -206,10 +902,30 @@ mod tests {
         let resolved =
             format_lock_update_event(LockUpdateEvent::ImageResolved {
                 service: "app.web".to_string(),
+                source: DigestSource::Registry,
             });
         assert!(resolved
             .to_lowercase()
             .contains("resolved"));
+        let resolved =
+            format_lock_update_event(LockUpdateEvent::ImageResolved {
+                service: "app.web".to_string(),
+                source: DigestSource::LocalImage,
+            });
+        assert!(resolved.contains("local image"));
+
+        let capped =
+            format_lock_update_event(LockUpdateEvent::TagSearchCapped {
+                service: "app.web".to_string(),
+                image: "nginx:latest".to_string(),
+                searched: 2000,
+            });
+        assert_eq!(
+            strip_ansi_codes(&capped),
+            "warning: app.web: stopped looking for the version of \
+             nginx:latest after its newest 2000 tags; it may be missing or \
+             not the best match"
+        );
 
         let changes =
             format_lock_update_event(LockUpdateEvent::ChangesDetected {
@@ -222,7 +938,7 @@ mod tests {
         assert!(changes.contains("changes"));
         assert!(changes.contains("app"));
         assert!(changes.contains("web"));
-        assert!(changes.contains("sha256:added"));
+        assert!(changes.contains("app.web  -  added"));
 
         let writing =
             format_lock_update_event(LockUpdateEvent::WritingLockFile);
@@ -238,6 +954,30 @@ mod tests {
         assert!(written.contains("success"));
     }
 
+    #[test]
+    fn quiet_lock_update_events_keep_only_results() {
+        assert_eq!(
+            format_quiet_lock_update_event(LockUpdateEvent::UpToDate),
+            None
+        );
+        assert_eq!(
+            format_quiet_lock_update_event(LockUpdateEvent::LockFileWritten),
+            None
+        );
+
+        let changes =
+            format_quiet_lock_update_event(LockUpdateEvent::ChangesDetected {
+                diffs: vec![DiffEntry::Added {
+                    service: "app.web".to_string(),
+                    new: image("nginx:1.27", None, "sha256:added"),
+                }],
+            })
+            .unwrap();
+        let changes = strip_ansi_codes(&changes);
+        assert!(changes.starts_with("  Added:\n    app.web"));
+        assert!(!changes.contains("Changes"));
+    }
+
     #[test]
     fn lock_filter_keeps_entries_with_changed_image_references() {
         let mut images = BTreeMap::from([
@@ -264,4 +1004,156 @@ mod tests {
             )])
         );
     }
+
+    #[test]
+    fn format_diff_reports_download_sizes_and_total() {
+        let mut web = image("nginx:1.27", Some("1.27"), "sha256:new");
+        web.size = Some(312_000_000);
+        let mut db = image("postgres:17", Some("17"), "sha256:db");
+        db.size = Some(1_800_000_000);
+        let diffs = vec![
+            DiffEntry::Updated {
+                service: "app.web".to_string(),
+                old: image("nginx:1.26", Some("1.26"), "sha256:old"),
+                new: web,
+            },
+            DiffEntry::Added {
+                service: "app.db".to_string(),
+                new: db,
+            },
+        ];
+
+        let output = strip_ansi_codes(&format_diff(&diffs)).into_owned();
+
+        assert!(
+            output.contains("    app.db   17           db         1.8 GB\n")
+        );
+        assert!(
+            output.contains("    app.web  1.26 → 1.27  old → new  312 MB\n")
+        );
+        assert!(output.ends_with("total download size: 2.1 GB\n"));
+    }
+
+    #[test]
+    fn format_diff_prints_a_shared_image_change_once() {
+        let mut new = image("postgres:16-alpine", Some("16.4"), "sha256:new");
+        new.size = Some(100_000_000);
+        let old = image("postgres:16-alpine", Some("16.3"), "sha256:old");
+        let mut diffs: Vec<_> = ["a.db", "b.db", "c.db"]
+            .into_iter()
+            .map(|service| DiffEntry::Updated {
+                service: service.to_string(),
+                old: old.clone(),
+                new: new.clone(),
+            })
+            .collect();
+        diffs.push(DiffEntry::Updated {
+            service: "d.db".to_string(),
+            old: image("postgres:16-alpine", Some("16.2"), "sha256:older"),
+            new: image("postgres:16-alpine", Some("16.4"), "sha256:new"),
+        });
+        diffs.push(DiffEntry::Added {
+            service: "a.web".to_string(),
+            new: image("nginx:1.27", None, "sha256:web"),
+        });
+
+        let output = strip_ansi_codes(&format_diff(&diffs)).into_owned();
+
+        assert_eq!(
+            output,
+            "  Added:\n\
+             \x20   a.web                            -            web\n\
+             \x20 Updated:\n\
+             \x20   postgres:16-alpine (3 services)  16.3 → 16.4  old → new    100 MB\n\
+             \x20     a.db\n\
+             \x20     b.db\n\
+             \x20     c.db\n\
+             \x20   d.db                             16.2 → 16.4  older → new\n\
+             \n  1 added, 4 updated\n\
+             \x20 total download size: 100 MB\n"
+        );
+        assert!(output.ends_with("total download size: 100 MB\n"));
+    }
+
+    #[test]
+    fn format_size_uses_decimal_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1_500), "1.5 kB");
+        assert_eq!(format_size(312_400_000), "312 MB");
+    }
+
+    #[test]
+    fn failure_report_collapses_registries_and_suggests_fixes() {
+        let images = BTreeMap::from([
+            ("a.api".to_string(), "ghcr.io/acme/api:1".to_string()),
+            ("a.worker".to_string(), "ghcr.io/acme/worker:1".to_string()),
+            ("b.web".to_string(), "Not Valid".to_string()),
+        ]);
+        let failures = images
+            .iter()
+            .map(|(service, image)| {
+                ImageFailure::classify(
+                    service.clone(),
+                    image.clone(),
+                    &anyhow::Error::new(
+                        OciDistributionError::UnauthorizedError {
+                            url: "https://ghcr.io".to_string(),
+                        },
+                    ),
+                )
+            })
+            .collect();
+        let report = FailureReport::new(&images, failures);
+
+        let output = format_failure_report(&report);
+        let output = strip_ansi_codes(&output);
+
+        assert!(output.contains("Failed to resolve 3 of 3 images"));
+        assert!(output.contains("auth required (2 images)"));
+        assert!(output.contains("ghcr.io: all 2 images"));
+        assert!(!output.contains("a.api"));
+        assert!(output.contains("configure auth for ghcr.io"));
+        assert!(output.contains("invalid reference (1 image)"));
+        assert!(output.contains("b.web: Not Valid\n"));
+    }
+
+    #[test]
+    fn markdown_summary_lists_each_change() {
+        let diffs = vec![
+            DiffEntry::Updated {
+                service: "app.web".to_string(),
+                old: image(
+                    "ghcr.io/acme/web:latest",
+                    Some("1.0"),
+                    "sha256:0123456789abcdef",
+                ),
+                new: VersionedImage {
+                    source: Some("https://github.com/acme/web.git".to_string()),
+                    ..image(
+                        "ghcr.io/acme/web:latest",
+                        Some("v1.1"),
+                        "sha256:fedcba9876543210",
+                    )
+                },
+            },
+            DiffEntry::Added {
+                service: "app.db".to_string(),
+                new: image("postgres:17", None, "sha256:aaaaaaaaaaaaaaaa"),
+            },
+            DiffEntry::Updated {
+                service: "app.api".to_string(),
+                old: image("ghcr.io/acme/api:1", Some("1.0"), "sha256:1111"),
+                new: image("ghcr.io/acme/api:1", Some("1.1"), "sha256:2222"),
+            },
+        ];
+
+        assert_eq!(
+            format_markdown_summary(&diffs),
+            "Update locked images: 1 added, 2 updated\n\n\
+             - `app.web`: 1.0 (`0123456789ab`) → v1.1 (`fedcba987654`) \
+             ([changelog](https://github.com/acme/web/releases))\n\
+             - `app.db`: add postgres:17 at `aaaaaaaaaaaa`\n\
+             - `app.api`: 1.0 (`1111`) → 1.1 (`2222`)\n"
+        );
+    }
 }
@@ -1,13 +1,27 @@
+use anyhow::Context;
 use clap::{Args, ValueEnum};
+use clap_complete::ArgValueCompleter;
 use futures::StreamExt;
 use nirion_lib::{
     context::NirionContext,
-    logs::{logs_stream, LogStreamOptions},
+    docker::ReplicaRange,
+    logs::{logs_stream, merge_log_lines, LogStreamOptions, LogTime},
     projects::TargetSelector,
 };
-use std::time::Duration;
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
 
-use crate::{log_render::LogRenderer, ClapSelector};
+use crate::{
+    commands::{parse_refresh, ProfileArgs, SelectorFlags, DEFAULT_REFRESH},
+    completion::running_target_completer,
+    foreground::shutdown_signal,
+    log_render::LogRenderer,
+    ClapSelector,
+};
 
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LogLabelFormat {
@@ -24,23 +38,37 @@ pub enum LogEventsMode {
     Never,
 }
 
+const EXAMPLES: &str = "\
+Examples:
+  # Follow the logs of a project
+  nirion logs media -f
+  # The last 100 lines of one service, with timestamps
+  nirion logs media.jellyfin -n 100 -t
+  # Everything since the start of the day, or of the last 90 minutes
+  nirion logs media --since today
+  nirion logs media --since 1h30m";
+
 /// View output from service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct LogsArgs {
     /// Target selector: *, project, or project.service
     #[arg(
         default_value = "*",
         value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
+        add = ArgValueCompleter::new(running_target_completer)
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
     /// Follow log output
     #[arg(short = 'f', long)]
     pub follow: bool,
 
     /// Refresh interval for discovering container changes when following
-    #[arg(short = 'r', long, default_value = "250ms", value_parser = humantime::parse_duration)]
+    #[arg(short = 'r', long, default_value = DEFAULT_REFRESH, value_parser = parse_refresh)]
     pub refresh: Duration,
 
     /// Don't print prefix in logs
@@ -51,13 +79,14 @@ pub struct LogsArgs {
     #[arg(long, value_enum, default_value = "auto")]
     pub events: LogEventsMode,
 
-    /// Show logs since timestamp
-    #[arg(long)]
-    pub since: Option<String>,
+    /// Show logs since this time: a duration ago such as `1h30m`, an RFC
+    /// 3339 timestamp, `today` or `yesterday`
+    #[arg(long, value_name = "TIME")]
+    pub since: Option<LogTime>,
 
-    /// Show logs before timestamp
-    #[arg(long, conflicts_with = "follow")]
-    pub until: Option<String>,
+    /// Show logs before this time, given like --since
+    #[arg(long, value_name = "TIME", conflicts_with = "follow")]
+    pub until: Option<LogTime>,
 
     /// Number of lines to show from the end
     #[arg(short = 'n', long)]
@@ -66,23 +95,55 @@ pub struct LogsArgs {
     /// Show timestamps
     #[arg(short = 't', long)]
     pub timestamps: bool,
+
+    /// Only these replicas of each service: a replica number or a range
+    /// such as `1-2`
+    #[arg(long, value_name = "INDEX")]
+    pub index: Option<ReplicaRange>,
+
+    /// Collect the logs of every selected service and print them as one
+    /// stream, sorted by time
+    #[arg(long, conflicts_with = "follow")]
+    pub merge: bool,
+
+    /// Write the merged stream to this file instead of stdout
+    #[arg(long, requires = "merge", value_name = "PATH")]
+    pub output_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub profile: ProfileArgs,
 }
 
 pub async fn handle_logs(
     args: &LogsArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
+    let context = &args.profile.apply(context);
+    if let (Some(since), Some(until)) = (args.since, args.until) {
+        anyhow::ensure!(
+            since.0 < until.0,
+            "--since {} is not before --until {}",
+            since.docker_arg(),
+            until.docker_arg()
+        );
+    }
     let options = LogStreamOptions {
         follow: args.follow,
         refresh_interval: args.refresh,
-        since: args.since.clone(),
-        until: args.until.clone(),
+        since: args.since,
+        until: args.until,
         tail: args.tail.clone(),
-        timestamps: args.timestamps,
+        // Merging sorts by the timestamps docker prefixes lines with.
+        timestamps: args.timestamps || args.merge,
+        replicas: args.index,
     };
+    if args.merge {
+        return print_merged(args, context, options).await;
+    }
+
     let mut renderer = LogRenderer::new(args.label, args.events, args.follow);
     let mut stream = logs_stream(context.clone(), args.target.clone(), options);
-    let shutdown = tokio::signal::ctrl_c();
+    let shutdown = shutdown_signal();
     tokio::pin!(shutdown);
 
     loop {
@@ -99,3 +160,40 @@ pub async fn handle_logs(
 
     Ok(())
 }
+
+async fn print_merged(
+    args: &LogsArgs,
+    context: &NirionContext,
+    options: LogStreamOptions,
+) -> anyhow::Result<()> {
+    let mut stream = logs_stream(context.clone(), args.target.clone(), options);
+    let mut events = Vec::new();
+    while let Some(event) = stream.next().await {
+        events.push(event?);
+    }
+
+    let merged = merge_log_lines(events);
+    let renderer = LogRenderer::new(args.label, LogEventsMode::Never, false);
+    match &args.output_file {
+        Some(path) => {
+            let file = File::create(path).with_context(|| {
+                format!("failed to create {}", path.display())
+            })?;
+            let mut file = BufWriter::new(file);
+            renderer.write_merged(
+                &mut file,
+                &merged,
+                args.timestamps,
+                false,
+            )?;
+            file.flush()?;
+        }
+        None => renderer.write_merged(
+            &mut stdout().lock(),
+            &merged,
+            args.timestamps,
+            true,
+        )?,
+    }
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use nirion_lib::{
+    context::NirionContext,
+    maintenance::{
+        clear_maintenance, maintenance_file, read_maintenance, set_maintenance,
+        MaintenanceMarker,
+    },
+    projects::{selected_project_names, TargetSelector},
+    state::state_dir,
+};
+use nirion_tui_lib::{color::Colorize, table::print_table};
+
+use crate::output::OutputOptions;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Keep lifecycle commands away from a project for two hours
+  nirion maintenance on media --reason \"db migration\" --for 2h
+  # Take it out again
+  nirion maintenance off media";
+
+/// Keep lifecycle commands away from projects, e.g. during a migration
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct MaintenanceArgs {
+    #[command(subcommand)]
+    pub command: MaintenanceCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum MaintenanceCommand {
+    /// Put a project into maintenance: up, down, reload, restart and
+    /// update refuse to touch it, and skip it when run on `*`
+    On {
+        /// The project to put into maintenance
+        project: String,
+
+        /// Why, shown wherever the project is refused or listed
+        #[arg(short, long)]
+        reason: Option<String>,
+
+        /// Take the project out of maintenance again after DURATION,
+        /// e.g. `2h`
+        #[arg(
+            long = "for",
+            value_name = "DURATION",
+            value_parser = humantime::parse_duration
+        )]
+        duration: Option<Duration>,
+    },
+
+    /// Take a project out of maintenance
+    Off {
+        /// The project to take out of maintenance
+        project: String,
+    },
+
+    /// List the projects in maintenance
+    List {
+        /// Print the markers as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+pub async fn handle_maintenance(
+    args: &MaintenanceArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let path = maintenance_file(&state_dir()?);
+    let quiet = OutputOptions::get().quiet;
+
+    match &args.command {
+        MaintenanceCommand::On {
+            project,
+            reason,
+            duration,
+        } => {
+            if !context.projects.contains_key(project) {
+                anyhow::bail!("Project '{project}' not found");
+            }
+            let marker = MaintenanceMarker::now(reason.clone(), *duration)?;
+            let until = marker.until;
+            set_maintenance(&path, project, marker)?;
+            if !quiet {
+                match until {
+                    Some(until) => println!(
+                        "{} is in maintenance until {until}",
+                        project.as_str().cyan()
+                    ),
+                    None => println!(
+                        "{} is in maintenance",
+                        project.as_str().cyan()
+                    ),
+                }
+            }
+        }
+        MaintenanceCommand::Off { project } => {
+            // Unknown projects are accepted so that a marker outlives
+            // the project being renamed or removed.
+            if clear_maintenance(&path, project)?.is_none() {
+                anyhow::bail!("{project} is not in maintenance");
+            }
+            if !quiet {
+                println!("{} is out of maintenance", project.as_str().cyan());
+            }
+        }
+        MaintenanceCommand::List { json } => {
+            let markers = read_maintenance(&path)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&markers)?);
+                return Ok(());
+            }
+
+            let mut rows = vec![format!(
+                "{}\t{}\t{}\t{}\t{}",
+                "project".blue(),
+                "since".blue(),
+                "until".blue(),
+                "user".blue(),
+                "reason".blue()
+            )];
+            for (project, marker) in &markers {
+                rows.push(format!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    project.as_str().cyan(),
+                    marker.since,
+                    marker
+                        .until
+                        .map_or("-".to_string(), |until| until.to_string()),
+                    marker.user,
+                    marker.reason.as_deref().unwrap_or("-")
+                ));
+            }
+            print_table(rows);
+        }
+    }
+    Ok(())
+}
+
+/// The active maintenance markers for display, e.g. in `ps`. A marker
+/// that can't be read only costs the wrench next to its project, so
+/// errors are treated as there being none.
+pub(crate) fn maintenance_markers() -> BTreeMap<String, MaintenanceMarker> {
+    state_dir()
+        .and_then(|dir| read_maintenance(&maintenance_file(&dir)))
+        .unwrap_or_default()
+}
+
+/// `🔧 maintenance: <reason>`, shown next to projects in maintenance.
+pub(crate) fn maintenance_label(marker: &MaintenanceMarker) -> String {
+    match &marker.reason {
+        Some(reason) => format!("🔧 maintenance: {reason}"),
+        None => "🔧 maintenance".to_string(),
+    }
+}
+
+/// Checks `command` against the projects in maintenance. Naming such a
+/// project, or one of its services, is an error; when run on `*` they are
+/// skipped with a warning and left out of the returned context.
+pub(crate) fn exclude_projects_in_maintenance(
+    command: &str,
+    target: &TargetSelector,
+    mut context: NirionContext,
+) -> Result<NirionContext> {
+    // Without a state directory no marker could have been set either.
+    let Ok(dir) = state_dir() else {
+        return Ok(context);
+    };
+    let markers = read_maintenance(&maintenance_file(&dir))?;
+
+    for project in selected_project_names(target, &context.projects) {
+        let Some(marker) = markers.get(&project) else {
+            continue;
+        };
+        let description = describe_marker(marker);
+        if *target != TargetSelector::All {
+            anyhow::bail!(
+                "{project} is in maintenance ({description}); run \
+                 `nirion maintenance off {project}` before `{command}`"
+            );
+        }
+        eprintln!(
+            "{} skipping {}, which is in maintenance ({description})",
+            "warning:".yellow(),
+            project.as_str().cyan()
+        );
+        context.projects.remove(&project);
+    }
+    Ok(context)
+}
+
+/// `db migration, set by alice at ... until ...`
+fn describe_marker(marker: &MaintenanceMarker) -> String {
+    let mut description = match &marker.reason {
+        Some(reason) => format!("{reason}, set by {}", marker.user),
+        None => format!("set by {}", marker.user),
+    };
+    description.push_str(&format!(" at {}", marker.since));
+    if let Some(until) = marker.until {
+        description.push_str(&format!(" until {until}"));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marker(reason: Option<&str>) -> MaintenanceMarker {
+        MaintenanceMarker {
+            reason: reason.map(str::to_string),
+            user: "alice".to_string(),
+            since: "2026-03-01T12:00:00Z".parse().unwrap(),
+            until: None,
+        }
+    }
+
+    #[test]
+    fn describe_marker_names_reason_user_and_times() {
+        assert_eq!(
+            describe_marker(&marker(Some("db migration"))),
+            "db migration, set by alice at 2026-03-01 12:00:00 UTC"
+        );
+        let mut marker = marker(None);
+        marker.until = Some("2026-03-01T14:00:00Z".parse().unwrap());
+        assert_eq!(
+            describe_marker(&marker),
+            "set by alice at 2026-03-01 12:00:00 UTC until 2026-03-01 \
+             14:00:00 UTC"
+        );
+    }
+}
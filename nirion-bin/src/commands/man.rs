@@ -0,0 +1,404 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Args, Command, CommandFactory, ValueHint};
+use clap_mangen::{
+    roff::{bold, italic, roman, Roff},
+    Man,
+};
+use nirion_lib::{
+    context::NirionContext, exec_history::EXEC_HISTORY_FILE,
+    history::HISTORY_FILE, maintenance::MAINTENANCE_FILE,
+};
+
+use crate::commands::monitor::MONITOR_STATE_FILE;
+use crate::Cli;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Read the overview, with the selector syntax and environment
+  nirion man | man -l -
+  # Install the pages of every command
+  nirion man --output-dir $out/share/man/man1";
+
+/// Print man pages, or write them all to a directory
+#[derive(Args, Debug, Clone)]
+#[command(hide = true, after_help = EXAMPLES)]
+pub struct ManArgs {
+    /// The command to print the page of, e.g. `update` or `auth check`;
+    /// the nirion(1) overview without one
+    pub command: Vec<String>,
+
+    /// Write the pages of nirion and every command to this directory,
+    /// named like `nirion-update.1`, instead of printing one
+    #[arg(
+        long,
+        value_name = "DIR",
+        value_hint = ValueHint::DirPath,
+        conflicts_with = "command"
+    )]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Variables nirion reads that aren't the default of a flag, which the
+/// flags' own variables are listed along with.
+const ENVIRONMENT: &[(&str, &str)] = &[
+    (
+        "NIRION_STATE_DIR",
+        "Directory of the state files listed under FILES. Defaults to \
+         $XDG_STATE_HOME/nirion, then ~/.local/state/nirion.",
+    ),
+    (
+        "HTTPS_PROXY, HTTP_PROXY, NO_PROXY",
+        "Proxies for registry requests; the lowercase variants are read \
+         too.",
+    ),
+    (
+        "DOCKER_HOST",
+        "Passed on to docker like the rest of the environment; named in \
+         the error when the daemon can't be reached.",
+    ),
+    (
+        "COMPLETE",
+        "A shell name makes nirion print its dynamic completion script, \
+         e.g. COMPLETE=bash nirion.",
+    ),
+];
+
+/// The command tree the pages are rendered from. Building it fills in
+/// the names of subcommands, e.g. `nirion-update`.
+fn man_command() -> Command {
+    let mut command = Cli::command().disable_help_subcommand(true);
+    command.build();
+    command
+}
+
+/// `command` and every visible command below it, depth first.
+fn visible_commands(command: &Command) -> Vec<&Command> {
+    let mut commands = vec![command];
+    for subcommand in command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+    {
+        commands.extend(visible_commands(subcommand));
+    }
+    commands
+}
+
+/// How `command` is run, e.g. `nirion auth check`.
+fn invocation(command: &Command) -> &str {
+    command
+        .get_bin_name()
+        .unwrap_or(command.get_name())
+}
+
+/// Follows `path`, e.g. `["auth", "check"]`, down from `command`.
+fn find_command<'a>(
+    command: &'a Command,
+    path: &[String],
+) -> Result<&'a Command> {
+    path.iter()
+        .try_fold(command, |command, name| {
+            command
+                .find_subcommand(name)
+                .with_context(|| {
+                    format!("no command `{} {name}`", invocation(command))
+                })
+        })
+}
+
+/// The page of `command`: clap_mangen's, with the examples of its
+/// `--help` as an EXAMPLES section rather than a bare EXTRA one. The
+/// overview of the root command also explains selectors, the environment
+/// and the files nirion uses.
+fn render_page(
+    root: &Command,
+    command: &Command,
+) -> Result<Vec<u8>> {
+    let mut page = Vec::new();
+    Man::new(command.clone().after_help(None::<&str>))
+        .source(format!("nirion {}", env!("CARGO_PKG_VERSION")))
+        .manual("Nirion Manual")
+        .render(&mut page)?;
+
+    let mut roff = Roff::new();
+    if std::ptr::eq(root, command) {
+        selectors_section(&mut roff);
+        environment_section(&mut roff, root);
+        files_section(&mut roff);
+    }
+    examples_section(&mut roff, command);
+    // Every rendering starts with the same preamble, which the page
+    // already has.
+    let preamble = Roff::new().render();
+    let sections = roff.render();
+    page.extend_from_slice(
+        sections
+            .strip_prefix(&preamble)
+            .unwrap_or(&sections)
+            .as_bytes(),
+    );
+
+    Ok(page)
+}
+
+fn selectors_section(roff: &mut Roff) {
+    roff.control("SH", ["SELECTORS"]);
+    roff.text([roman(
+        "Most commands take a target selector as their first argument:",
+    )]);
+    for (selector, meaning) in [
+        (
+            "*",
+            "every project; the default, quoted to keep it from the shell",
+        ),
+        ("PROJECT", "every service of one project"),
+        ("PROJECT.SERVICE", "a single service"),
+    ] {
+        roff.control("TP", []);
+        roff.text([bold(selector)]);
+        roff.text([roman(meaning)]);
+    }
+    roff.control("PP", []);
+    roff.text([
+        bold("--project"),
+        roman(" NAME and "),
+        bold("--service"),
+        roman(
+            " NAME spell the same selector as flags. A command run without \
+             one targets what ",
+        ),
+        bold("--default-target"),
+        roman(" COMMAND=SELECTOR names for it; under "),
+        bold("--strict-targets"),
+        roman(" down, stop and restart refuse to run without one."),
+    ]);
+    roff.control("PP", []);
+    roff.text([
+        roman("lock and update narrow the selection further with "),
+        bold("--image"),
+        roman(
+            " PATTERN: a glob with * and ? that must match the whole image \
+             repository, or otherwise a part of it. Tags are ignored, so ",
+        ),
+        italic("'ghcr.io/linuxserver/*'"),
+        roman(" selects every image from that organization."),
+    ]);
+}
+
+/// The variables of every flag in the tree, then [`ENVIRONMENT`].
+fn environment_section(
+    roff: &mut Roff,
+    root: &Command,
+) {
+    let mut variables = BTreeMap::new();
+    for command in visible_commands(root) {
+        for arg in command.get_arguments() {
+            let (Some(env), Some(long)) = (arg.get_env(), arg.get_long())
+            else {
+                continue;
+            };
+            let mut text = format!("Default of --{long}");
+            if !std::ptr::eq(command, root) {
+                text.push_str(&format!(" of {}", invocation(command)));
+            }
+            if let Some(help) = arg.get_help() {
+                text.push_str(&format!(": {help}"));
+            }
+            // Global flags show up again on every subcommand; the root,
+            // visited first, keeps them.
+            variables
+                .entry(env.to_string_lossy().into_owned())
+                .or_insert(text);
+        }
+    }
+
+    roff.control("SH", ["ENVIRONMENT"]);
+    let others = ENVIRONMENT
+        .iter()
+        .map(|(name, text)| (name.to_string(), text.to_string()));
+    for (name, text) in variables.into_iter().chain(others) {
+        roff.control("TP", []);
+        roff.text([bold(name)]);
+        roff.text([roman(text)]);
+    }
+}
+
+fn files_section(roff: &mut Roff) {
+    let state = |file: &str| format!("$NIRION_STATE_DIR/{file}");
+    let files = [
+        (
+            "project file".to_string(),
+            "The projects with their compose files and services, as JSON. \
+             The NixOS module writes it, nirion adopt writes one for \
+             compose projects already running, and --nix-eval builds it \
+             from a flake."
+                .to_string(),
+        ),
+        (
+            "lock file".to_string(),
+            "The digest and version each service's image is pinned to, as \
+             JSON; written by lock and update. With --lock-dir, one such \
+             file per project."
+                .to_string(),
+        ),
+        (
+            "auth file".to_string(),
+            "Registry credentials for lock, update and registries; see \
+             nirion auth."
+                .to_string(),
+        ),
+        (
+            state(HISTORY_FILE),
+            "Lifecycle commands that ran and the digests they deployed, \
+             shown by nirion history."
+                .to_string(),
+        ),
+        (
+            state(EXEC_HISTORY_FILE),
+            "Commands recorded with nirion exec --record.".to_string(),
+        ),
+        (
+            state(MAINTENANCE_FILE),
+            "Projects put in maintenance with nirion maintenance.".to_string(),
+        ),
+        (
+            state(MONITOR_STATE_FILE),
+            "Settings of nirion monitor restored on its next run.".to_string(),
+        ),
+    ];
+
+    roff.control("SH", ["FILES"]);
+    for (file, text) in files {
+        roff.control("TP", []);
+        roff.text([italic(file)]);
+        roff.text([roman(text)]);
+    }
+}
+
+/// The `Examples:` of the command's `--help`, kept line by line.
+fn examples_section(
+    roff: &mut Roff,
+    command: &Command,
+) {
+    let Some(help) = command.get_after_help() else {
+        return;
+    };
+    let help = help.to_string();
+    let Some(examples) = help.strip_prefix("Examples:\n") else {
+        return;
+    };
+
+    roff.control("SH", ["EXAMPLES"]);
+    roff.control("nf", []);
+    for line in examples.lines() {
+        roff.text([roman(line)]);
+    }
+    roff.control("fi", []);
+}
+
+/// Writes the page of every visible command to `dir`, which is created
+/// if needed.
+fn write_pages(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let root = man_command();
+    for command in visible_commands(&root) {
+        let name = command
+            .get_display_name()
+            .unwrap_or(command.get_name());
+        let path = dir.join(format!("{name}.1"));
+        std::fs::write(&path, render_page(&root, command)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+pub async fn handle_man(
+    args: &ManArgs,
+    _context: &NirionContext,
+) -> Result<()> {
+    if let Some(dir) = &args.output_dir {
+        return write_pages(dir);
+    }
+
+    let root = man_command();
+    let command = find_command(&root, &args.command)?;
+    std::io::stdout().write_all(&render_page(&root, command)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(path: &[&str]) -> String {
+        let root = man_command();
+        let path = path
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let command = find_command(&root, &path).unwrap();
+        String::from_utf8(render_page(&root, command).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn overview_explains_selectors_environment_and_files() {
+        let page = page(&[]);
+
+        assert!(page.contains("\n.TH nirion 1 "), "{page}");
+        for section in ["SELECTORS", "ENVIRONMENT", "FILES", "EXAMPLES"] {
+            assert!(page.contains(&format!(".SH {section}")), "{section}");
+        }
+        assert!(page.contains("NIRION_LOCK_FILE"));
+        assert!(page.contains("NIRION_API_TOKEN"));
+        assert!(page.contains("history.jsonl"));
+        assert!(!page.contains("nirion\\-man(1)"));
+    }
+
+    #[test]
+    fn command_pages_carry_their_examples() {
+        let page = page(&["update"]);
+
+        assert!(page.contains("\n.TH nirion-update 1 "), "{page}");
+        assert!(page.contains(".SH EXAMPLES\n.nf\n"));
+        assert!(page.contains("nirion update \\-\\-jobs 4"));
+        assert!(!page.contains(".SH SELECTORS"));
+    }
+
+    #[test]
+    fn every_command_has_examples() {
+        let root = man_command();
+        let missing = root
+            .get_subcommands()
+            .filter(|command| {
+                !command
+                    .get_after_help()
+                    .is_some_and(|help| {
+                        help.to_string()
+                            .starts_with("Examples:")
+                    })
+            })
+            .map(|command| command.get_name())
+            .collect::<Vec<_>>();
+
+        assert!(missing.is_empty(), "{missing:?}");
+    }
+
+    #[test]
+    fn unknown_commands_are_named_in_full() {
+        let root = man_command();
+
+        assert_eq!(
+            find_command(&root, &["auth".to_string(), "nope".to_string()])
+                .unwrap_err()
+                .to_string(),
+            "no command `nirion auth nope`"
+        );
+    }
+}
@@ -1,15 +1,52 @@
+use anyhow::Context;
 use clap::Args;
 use futures::stream;
 use nirion_lib::{
-    context::NirionContext, docker::status_stream, wait::WaitTarget,
+    compose_file::{check_compose_files, unreadable_compose_files},
+    context::NirionContext,
+    daemon::DaemonWatch,
+    monitor::DockerMonitor,
+    projects::{get_images, selected_project_names},
+    state::state_dir,
+    wait::WaitTarget,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use std::time::Duration;
 
+use crate::commands::maintenance::maintenance_markers;
+use crate::commands::{parse_refresh, DEFAULT_REFRESH};
+use crate::output::OutputOptions;
+use crate::monitor_view::{KeyInput, MonitorSort, MonitorView};
 use crate::progress::run_progress;
 use crate::progress_render::StatusProgressRenderer;
-use crate::{ClapSelector, TargetSelector};
+use crate::stats_render::StatsView;
+use crate::updates_render::UpdatesView;
+use crate::{commands::SelectorFlags, ClapSelector, TargetSelector};
+
+pub(crate) const MONITOR_STATE_FILE: &str = "monitor.json";
+
+/// How often the daemon is probed, so a banner shows while it is away.
+const DAEMON_PROBE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// `docker stats` is much slower than `docker compose ps`, so it is
+/// sampled less often than the status is refreshed.
+const STATS_INTERVAL: Duration = Duration::from_secs(3);
+
+const EXAMPLES: &str = "\
+Examples:
+  # Watch every project, with CPU and memory usage
+  nirion monitor --stats
+  # Watch one project and look for image updates every six hours
+  nirion monitor media --check-updates 6h";
 
+/// Watch the status of projects live
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct MonitorArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -19,24 +56,199 @@ pub struct MonitorArgs {
     )]
     pub target: TargetSelector,
 
-    /// Refresh interval in seconds for status updates when monitoring
-    #[arg(short = 'r', long, default_value = "250ms", value_parser = humantime::parse_duration)]
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Refresh interval for status updates when monitoring
+    #[arg(short = 'r', long, default_value = DEFAULT_REFRESH, value_parser = parse_refresh)]
     pub refresh: Duration,
+
+    /// Hide projects where everything is healthy, like pressing `p`;
+    /// remembered for later runs until set to false
+    #[arg(
+        short = 'p',
+        long,
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub only_problems: Option<bool>,
+
+    /// Sample CPU and memory usage with `docker stats` and show recent
+    /// trends per service
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Look for newer images every INTERVAL, e.g. `6h`, and show which
+    /// services have updates; the lock file is never written
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = humantime::parse_duration
+    )]
+    pub check_updates: Option<Duration>,
+}
+
+/// Monitor settings restored on the next run.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct MonitorState {
+    only_problems: bool,
+    filter: String,
+    sort: MonitorSort,
+    /// Projects listed with their services.
+    expanded: BTreeSet<String>,
+}
+
+impl MonitorState {
+    fn view(self) -> MonitorView {
+        MonitorView::new(
+            self.only_problems,
+            self.filter,
+            self.sort,
+            self.expanded,
+        )
+    }
+
+    fn from_view(view: &MonitorView) -> Self {
+        Self {
+            only_problems: view.only_problems,
+            filter: view.filter.clone(),
+            sort: view.sort,
+            expanded: view.expanded.clone(),
+        }
+    }
+
+    /// A missing or unreadable state file falls back to the defaults.
+    fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(
+        &self,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create {}", parent.display())
+            })?;
+        }
+
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .and_then(|_| fs::rename(&tmp, path))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+fn monitor_state_file() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join(MONITOR_STATE_FILE))
 }
 
 pub async fn handle_monitor(
     args: &MonitorArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
-    run_progress(
+    if !OutputOptions::get().animated() {
+        anyhow::bail!(
+            "monitor only draws a live status view, which --quiet and \
+             --no-progress turn off; use `nirion ps` for a one-off status"
+        );
+    }
+
+    let state_file = monitor_state_file()?;
+    let mut state = MonitorState::load(&state_file);
+    if let Some(only_problems) = args.only_problems {
+        state.only_problems = only_problems;
+    }
+
+    // Projects whose compose file is gone would fail every poll; they
+    // get an error row while the rest are monitored as usual.
+    let selected = selected_project_names(&args.target, &context.projects);
+    let unreadable = unreadable_compose_files(&context.projects, &selected);
+    if unreadable.len() == selected.len() {
+        check_compose_files(&context.projects, &selected)?;
+    }
+    let readable = selected
+        .into_iter()
+        .filter(|name| !unreadable.contains_key(name))
+        .collect::<Vec<_>>();
+    let updates = match args.check_updates {
+        Some(interval) => Some(UpdatesView::spawn(
+            context,
+            get_images(&args.target, &context.projects)?,
+            interval,
+        )),
+        None => None,
+    };
+
+    let mut renderer = StatusProgressRenderer::status_only()
+        .view(state.view())
+        .keys(KeyInput::spawn())
+        .project_errors(unreadable)
+            .maintenance(maintenance_markers())
+            .stats(args.stats.then(|| {
+                StatsView::spawn(context.docker_command.clone(), STATS_INTERVAL)
+            }))
+            .updates(updates)
+            .daemon_watch(DaemonWatch::spawn(
+                context.docker_command.clone(),
+                DAEMON_PROBE_INTERVAL,
+            ));
+
+    // Ctrl-C ends the monitor through run_progress's cancel path, so the
+    // view is saved however it stops.
+    let result = run_progress(
         context,
         &args.target,
         stream::empty(),
-        status_stream(context, args.target.clone(), args.refresh),
-        StatusProgressRenderer::without_spinner(),
+        DockerMonitor::builder(args.refresh)
+            .detailed(true)
+            .spawn_projects(context, readable)
+            .into_events(),
+        &mut renderer,
         WaitTarget::Forever,
+        args.refresh,
     )
-    .await?;
+    .await;
+    MonitorState::from_view(renderer.current_view()).save(&state_file)?;
+
+    result.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitor_state_round_trips_through_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("nested")
+            .join(MONITOR_STATE_FILE);
+        let state = MonitorState {
+            only_problems: true,
+            filter: "med".into(),
+            sort: MonitorSort::Problems,
+            expanded: BTreeSet::from(["media".to_string()]),
+        };
+
+        state.save(&path).unwrap();
+
+        assert_eq!(MonitorState::load(&path), state);
+    }
+
+    #[test]
+    fn monitor_state_defaults_when_missing_or_corrupt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MONITOR_STATE_FILE);
+
+        assert_eq!(MonitorState::load(&path), MonitorState::default());
 
-    Ok(())
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(MonitorState::load(&path), MonitorState::default());
+    }
 }
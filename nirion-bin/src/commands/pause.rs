@@ -0,0 +1,48 @@
+use anyhow::Result;
+use clap::Args;
+
+use crate::commands::{LifecycleArgs, TargetArg};
+use crate::docker::compose_target_cmd;
+use crate::impact::confirm_preview;
+use crate::lifecycle::run_lifecycle_command;
+use crate::TargetSelector;
+use nirion_lib::context::NirionContext;
+use nirion_lib::wait::WaitTarget;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Freeze one service while taking a backup of its data
+  nirion pause media.jellyfin";
+
+/// Pause service containers, freezing their processes
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct PauseArgs {
+    #[command(flatten)]
+    pub target: TargetArg,
+
+    #[command(flatten)]
+    pub lifecycle: LifecycleArgs,
+}
+
+pub async fn handle_pause(
+    args: &PauseArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "pause", &args.target, &args.lifecycle).await?;
+    // A single service has no progress to aggregate, so compose's own
+    // output is shown as is.
+    if let TargetSelector::Service(_) = *args.target {
+        return compose_target_cmd(context, &args.target, &["pause"]).await;
+    }
+
+    run_lifecycle_command(
+        context,
+        &args.target,
+        &["pause"],
+        args.lifecycle
+            .options(WaitTarget::NoWait),
+    )
+    .await
+}
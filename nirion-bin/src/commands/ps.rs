@@ -2,16 +2,43 @@ use anyhow::Result;
 use clap::Args;
 use nirion_lib::{
     context::NirionContext,
-    docker::{query_project_status, Port, ServiceStatus},
+    docker::{
+        inspect_all_container_details, inspect_all_unhealthy_containers,
+        query_project_status, query_project_statuses, Port, ProjectStatus,
+        ServiceState, ServiceStatus,
+    },
+    projects::{selected_project_names, Project},
+    state::state_dir,
+    status_cache::{status_cache_file, write_status_cache},
 };
 use nirion_tui_lib::color::Colorize;
-use nirion_tui_lib::table::print_table;
-use std::collections::HashSet;
+use nirion_tui_lib::table::format_table;
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    commands::{
+        maintenance::{maintenance_label, maintenance_markers},
+        ProfileArgs, SelectorFlags,
+    },
+    format_template::{Field, FormatTemplate},
+    output::OutputOptions,
+    status_display::format_uptime,
+    ClapSelector, TargetSelector,
+};
 
-use crate::{ClapSelector, TargetSelector};
+const EXAMPLES: &str = "\
+Examples:
+  # Also show start times, restart counts and OOM kills
+  nirion ps --wide
+  # One line per container for scripts
+  nirion ps media --format '{{.Name}}\\t{{.Status}}'";
 
 /// List running service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct PsArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -20,81 +47,405 @@ pub struct PsArgs {
         add = TargetSelector::clap_completer()
     )]
     pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Also show start time, restart count and OOM kills
+    #[arg(short, long)]
+    pub wide: bool,
+
+    /// Print the container status as JSON, including runtime details
+    #[arg(long)]
+    pub json: bool,
+
+    /// Print each container through a template instead of the table,
+    /// e.g. `{{.Name}}\t{{.Status}}`. Supports field access and
+    /// `{{json .}}`; the fields are Name, Service, Project, State,
+    /// Status, Health, Image, Ports and RunningFor
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = FormatTemplate::parse,
+        conflicts_with_all = ["json", "wide"]
+    )]
+    pub format: Option<FormatTemplate>,
+
+    /// Only show containers created less than DURATION ago, e.g. `10m`
+    /// for the ones recreated or restarted by a recent deploy
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub uptime_threshold: Option<Duration>,
+
+    #[command(flatten)]
+    pub profile: ProfileArgs,
 }
 
 pub async fn handle_ps(
     args: &PsArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    let mut rows = vec![];
+    let context = &args.profile.apply(context);
+    let mut statuses =
+        selected_statuses(context, &args.target, args.wide || args.json)
+            .await?;
+    if let Some(threshold) = args.uptime_threshold {
+        let now = SystemTime::now();
+        statuses.retain_mut(|(_, status)| {
+            retain_created_within(status, threshold, now)
+        });
+    }
 
-    match &args.target {
-        TargetSelector::All => {
-            for (project_name, _) in context.projects.iter() {
-                rows.extend(print_project_status(context, project_name).await?);
+    if let Some(template) = &args.format {
+        for (project_name, status) in &statuses {
+            for svc in status
+                .containers()
+                .chain(&status.foreign)
+            {
+                println!(
+                    "{}",
+                    template.render(|field| {
+                        template_field(project_name, svc, field)
+                    })
+                );
             }
         }
+        return Ok(());
+    }
+
+    if args.json {
+        let services = statuses
+            .iter()
+            .map(|(project, status)| (project.as_str(), &status.services))
+            .collect::<BTreeMap<_, _>>();
+        println!("{}", serde_json::to_string_pretty(&services)?);
+        return Ok(());
+    }
 
-        TargetSelector::Project(sel) => {
-            if context.projects.contains_key(&sel.name) {
-                rows.extend(print_project_status(context, &sel.name).await?);
+    // Quiet output is just the container rows, one per line.
+    let decorated = !OutputOptions::get().quiet;
+    let mut rows = vec![];
+    // Failing healthchecks and foreign containers get a note on its own
+    // line below the container, and maintenance markers below the
+    // project header, outside the table so it doesn't widen its columns.
+    let mut notes = BTreeMap::new();
+    let maintenance = maintenance_markers();
+    for (project_name, status) in &statuses {
+        let project = &context.projects[project_name];
+
+        if decorated {
+            if let Some(marker) = maintenance.get(project_name) {
+                notes.insert(
+                    rows.len(),
+                    format!(" {}", maintenance_label(marker).yellow()),
+                );
+            }
+            rows.push(print_header(project_name, args.wide));
+            if project.services.is_empty() && status.services.is_empty() {
+                let note = format!("  {}", "no services defined".grey());
+                notes
+                    .entry(rows.len() - 1)
+                    .and_modify(|notes: &mut String| {
+                        notes.push('\n');
+                        notes.push_str(&note);
+                    })
+                    .or_insert(note);
             }
         }
-
-        TargetSelector::Service(sel) => {
-            if context
-                .projects
-                .contains_key(&sel.project)
-            {
-                let status =
-                    query_project_status(context, &sel.project).await?;
-
-                if let Some(svc) = status.services.get(&sel.service) {
-                    rows.push(print_header(&sel.project));
-                    rows.push(print_row(svc)?);
+        for replicas in status.services.values() {
+            for (i, svc) in replicas.iter().enumerate() {
+                if let Some(line) =
+                    print_health_check(svc).filter(|_| decorated)
+                {
+                    notes.insert(rows.len() + i, line);
+                }
+            }
+            let replicas = print_replicas(replicas, project, args.wide)?;
+            rows.extend(replicas.into_iter().map(|row| {
+                match row.strip_prefix(" - ") {
+                    Some(bare) if !decorated => bare.to_string(),
+                    _ => row,
                 }
+            }));
+        }
+        if decorated {
+            for svc in &status.foreign {
+                notes.insert(
+                    rows.len(),
+                    format!(
+                        "     {} {}",
+                        "↳".grey(),
+                        "not managed by this project file".yellow()
+                    ),
+                );
+                rows.push(print_row(svc, 1, false)?);
             }
         }
+        if decorated && !matches!(args.target, TargetSelector::Service(_)) {
+            rows.push(String::new());
+        }
     }
 
-    print_table(rows);
+    for (i, row) in format_table(rows).lines().enumerate() {
+        println!("{row}");
+        if let Some(line) = notes.get(&i) {
+            println!("{line}");
+        }
+    }
     Ok(())
 }
 
-async fn print_project_status(
+/// Queries the selected projects, narrowing a service selector down to
+/// that service's containers and skipping it if it has none.
+async fn selected_statuses(
     context: &NirionContext,
-    project_name: &str,
-) -> anyhow::Result<Vec<String>> {
-    let mut rows = vec![];
+    target: &TargetSelector,
+    detailed: bool,
+) -> anyhow::Result<Vec<(String, ProjectStatus)>> {
+    let names = selected_project_names(target, &context.projects)
+        .into_iter()
+        .filter(|name| context.projects.contains_key(name))
+        .collect::<Vec<_>>();
+    let mut queried = query_statuses(context, &names).await?;
+    if detailed {
+        inspect_all_container_details(
+            &context.docker_command,
+            queried.values_mut(),
+        )
+        .await?;
+    } else {
+        inspect_all_unhealthy_containers(
+            &context.docker_command,
+            queried.values_mut(),
+        )
+        .await?;
+    }
+
+    let mut statuses = vec![];
+    for project_name in names {
+        let Some(mut status) = queried.remove(&project_name) else {
+            continue;
+        };
+        // Shell completion reads this snapshot instead of waiting on
+        // docker; failing to write it must not fail `ps`.
+        if let Ok(state_dir) = state_dir() {
+            let _ = write_status_cache(
+                &status_cache_file(&state_dir),
+                &project_name,
+                &status,
+            );
+        }
+
+        if let TargetSelector::Service(sel) = target {
+            status
+                .services
+                .retain(|name, _| *name == sel.service);
+            status
+                .foreign
+                .retain(|container| container.service == sel.service);
+            if status.services.is_empty() && status.foreign.is_empty() {
+                continue;
+            }
+        }
 
-    rows.push(print_header(project_name));
+        statuses.push((project_name, status));
+    }
 
-    let status = query_project_status(context, project_name).await?;
+    Ok(statuses)
+}
 
-    for svc in status.services.values() {
-        rows.push(print_row(svc)?);
+/// Drops the containers created `threshold` or longer before `now`, and
+/// those docker gave no creation time for. Returns whether any are left.
+fn retain_created_within(
+    status: &mut ProjectStatus,
+    threshold: Duration,
+    now: SystemTime,
+) -> bool {
+    let recent = |svc: &ServiceStatus| {
+        svc.uptime(now)
+            .is_some_and(|uptime| uptime < threshold)
+    };
+    for replicas in status.services.values_mut() {
+        replicas.retain(recent);
     }
+    status
+        .services
+        .retain(|_, replicas| !replicas.is_empty());
+    status.foreign.retain(recent);
 
-    rows.push(String::new());
+    !status.services.is_empty() || !status.foreign.is_empty()
+}
 
-    Ok(rows)
+/// `docker compose ps` for a single project; several are listed with
+/// one `docker ps` instead of a compose call each.
+pub(crate) async fn query_statuses(
+    context: &NirionContext,
+    names: &[String],
+) -> anyhow::Result<BTreeMap<String, ProjectStatus>> {
+    match names {
+        [] => Ok(BTreeMap::new()),
+        [name] => Ok(BTreeMap::from([(
+            name.clone(),
+            query_project_status(context, name).await?,
+        )])),
+        names => {
+            query_project_statuses(
+                &context.docker_command,
+                names
+                    .iter()
+                    .map(|name| (name.as_str(), &context.projects[name])),
+            )
+            .await
+        }
+    }
 }
 
-fn print_header(project_name: &str) -> String {
-    format!(
+fn print_header(
+    project_name: &str,
+    wide: bool,
+) -> String {
+    let mut header = format!(
         "[{}]\t{}\t{}\t{}",
         project_name.cyan(),
         "created".blue(),
         "status".blue(),
         "ports".blue()
-    )
+    );
+    if wide {
+        header.push_str(&format!(
+            "\t{}\t{}",
+            "started".blue(),
+            "restarts".blue()
+        ));
+    }
+    header
+}
+
+fn print_replicas(
+    replicas: &[ServiceStatus],
+    project: &Project,
+    wide: bool,
+) -> anyhow::Result<Vec<String>> {
+    replicas
+        .iter()
+        .map(|svc| {
+            let row =
+                print_row(svc, replicas.len(), svc.is_completed(project))?;
+            Ok(if wide {
+                format!("{row}\t{}", print_details(svc))
+            } else {
+                row
+            })
+        })
+        .collect()
+}
+
+/// The latest healthcheck probe of `svc`, if it failed.
+fn print_health_check(svc: &ServiceStatus) -> Option<String> {
+    let check = svc
+        .last_health_check
+        .as_ref()
+        .filter(|check| check.exit_code != 0)?;
+    let output = check
+        .output
+        .lines()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some(format!(
+        "     {} {}",
+        "↳".grey(),
+        format!("healthcheck exit {}: {output}", check.exit_code).red()
+    ))
 }
 
-fn print_row(svc: &ServiceStatus) -> anyhow::Result<String> {
+fn print_details(svc: &ServiceStatus) -> String {
+    let Some(details) = &svc.details else {
+        return "\t".to_string();
+    };
+
+    let started = details
+        .started_at
+        .as_deref()
+        .map(format_started_at)
+        .unwrap_or_default();
+    let restarts = match details.restart_count {
+        0 => "0".to_string(),
+        n => n.to_string().yellow().to_string(),
+    };
+    let oom = if details.oom_killed {
+        format!(" {}", "OOM killed".red())
+    } else {
+        String::new()
+    };
+
+    format!("{started}\t{restarts}{oom}")
+}
+
+/// Trims docker's RFC 3339 timestamp (with nanoseconds) to the second.
+/// Containers that never started report the zero time, shown as blank.
+fn format_started_at(started_at: &str) -> String {
+    if started_at.starts_with("0001-") {
+        return String::new();
+    }
+
+    started_at
+        .get(..19)
+        .unwrap_or(started_at)
+        .replace('T', " ")
+}
+
+/// The value of `field` for `--format`, uncolored.
+fn template_field(
+    project_name: &str,
+    svc: &ServiceStatus,
+    field: Field,
+) -> String {
+    match field {
+        Field::Name => svc.container_name.clone(),
+        Field::Service => svc.service.clone(),
+        Field::Project => project_name.to_string(),
+        Field::State => svc.state.as_str().to_string(),
+        Field::Status => svc.status.clone().unwrap_or_default(),
+        Field::Health => svc.health.clone().unwrap_or_default(),
+        Field::Image => svc.image.clone(),
+        Field::Ports => format_ports(&svc.ports),
+        Field::RunningFor => running_for(svc),
+    }
+}
+
+/// How long ago the container was created. Docker's own wording varies
+/// in length and language, so it's only the fallback for containers
+/// without a creation time.
+fn running_for(svc: &ServiceStatus) -> String {
+    match svc.uptime(SystemTime::now()) {
+        Some(uptime) => format_uptime(uptime),
+        None => svc
+            .running_for
+            .clone()
+            .unwrap_or_default(),
+    }
+}
+
+fn format_ports(ports: &[Port]) -> String {
+    let port_strs = collapsed_ports(ports)
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut port_strs = port_strs
+        .into_iter()
+        .collect::<Vec<_>>();
+    port_strs.sort_unstable();
+    port_strs.join(", ")
+}
+
+fn print_row(
+    svc: &ServiceStatus,
+    replicas: usize,
+    completed: bool,
+) -> anyhow::Result<String> {
     let unhealthy_token = "PS_REPLACE_TOKEN1";
     let healthy_token = "PS_REPLACE_TOKEN2";
 
-    let running_for = svc.running_for.as_deref().unwrap_or("");
+    let running_for = running_for(svc);
     let status = svc
         .status
         .as_deref()
@@ -102,20 +453,35 @@ fn print_row(svc: &ServiceStatus) -> anyhow::Result<String> {
         .replace("unhealthy", unhealthy_token)
         .replace("healthy", healthy_token)
         .replace(healthy_token, &"healthy".green().to_string())
-        .replace(unhealthy_token, &"unhealthy".red().to_string());
+        .replace(unhealthy_token, &"unhealthy".red().to_string())
+        .replace("(Paused)", &"(Paused)".blue().to_string());
+    let status = match &svc.state {
+        ServiceState::Unknown(raw) => {
+            format!("{status} {}", format!("[{raw}]").grey())
+        }
+        _ => status,
+    };
+    let status = if completed {
+        format!("{} {}", "✓ completed".cyan(), status.grey())
+    } else {
+        status
+    };
 
-    let port_strs = collapsed_ports(&svc.ports)
-        .into_iter()
-        .collect::<HashSet<_>>();
-    let mut port_strs = port_strs
-        .into_iter()
-        .collect::<Vec<_>>();
-    port_strs.sort_unstable();
-    let port_str = port_strs.join(", ");
+    let port_str = format_ports(&svc.ports);
+
+    let name = if replicas > 1 {
+        format!(
+            "{} {}",
+            svc.container_name,
+            format!("[{}/{}]", svc.index, replicas).grey()
+        )
+    } else {
+        svc.container_name.clone()
+    };
 
     Ok(format!(
         " - {}\t{}\t{}\t{}",
-        svc.container_name, running_for, status, port_str
+        name, running_for, status, port_str
     ))
 }
 
@@ -188,7 +554,7 @@ fn format_port_range(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use nirion_lib::docker::{ExternalPort, ServiceState};
+    use nirion_lib::docker::{ContainerDetails, ExternalPort};
     use nirion_tui_lib::ansi::strip_ansi_codes;
 
     fn port(
@@ -225,14 +591,18 @@ mod tests {
             id: "id".to_string(),
             service: "web".to_string(),
             container_name: "web-1".to_string(),
+            index: 1,
             image: "image".to_string(),
             state: ServiceState::Running,
             health: None,
             exit_code: None,
             running_for: Some("2 minutes".to_string()),
+            created_at: None,
             status: status.map(str::to_string),
             ports,
             networks: Vec::new(),
+            details: None,
+            last_health_check: None,
         }
     }
 
@@ -312,10 +682,14 @@ mod tests {
 
     #[test]
     fn print_row_deduplicates_rendered_ports() {
-        let row = print_row(&service_status(
-            Some("running"),
-            vec![port(80, "tcp"), port(80, "tcp")],
-        ))
+        let row = print_row(
+            &service_status(
+                Some("running"),
+                vec![port(80, "tcp"), port(80, "tcp")],
+            ),
+            1,
+            false,
+        )
         .unwrap();
 
         assert_eq!(
@@ -326,10 +700,14 @@ mod tests {
 
     #[test]
     fn print_row_colors_healthy_and_unhealthy_independently() {
-        let row = print_row(&service_status(
-            Some("running (healthy), running (unhealthy)"),
-            vec![],
-        ))
+        let row = print_row(
+            &service_status(
+                Some("running (healthy), running (unhealthy)"),
+                vec![],
+            ),
+            1,
+            false,
+        )
         .unwrap();
 
         assert_eq!(
@@ -339,4 +717,64 @@ mod tests {
         assert!(row.contains("healthy"));
         assert!(row.contains("unhealthy"));
     }
+
+    #[test]
+    fn print_replicas_renders_one_row_per_replica_with_index() {
+        let first = service_status(Some("running"), vec![]);
+        let second = ServiceStatus {
+            container_name: "web-2".to_string(),
+            index: 2,
+            ..first.clone()
+        };
+
+        let project = serde_json::from_str(
+            r#"{"name": "myapp", "dockerCompose": "compose.yml", "services": {}}"#,
+        )
+        .unwrap();
+
+        let rows = print_replicas(&[first, second], &project, false).unwrap();
+
+        assert_eq!(
+            rows.iter()
+                .map(|row| strip_ansi_codes(row))
+                .collect::<Vec<_>>(),
+            vec![
+                " - web-1 [1/2]\t2 minutes\trunning\t",
+                " - web-2 [2/2]\t2 minutes\trunning\t",
+            ]
+        );
+    }
+
+    #[test]
+    fn print_row_marks_completed_one_shots() {
+        let row = print_row(
+            &service_status(Some("Exited (0) 5 minutes ago"), vec![]),
+            1,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            strip_ansi_codes(&row),
+            " - web-1\t2 minutes\t✓ completed Exited (0) 5 minutes ago\t"
+        );
+    }
+
+    #[test]
+    fn print_details_shows_start_time_restarts_and_oom_kills() {
+        let svc = ServiceStatus {
+            details: Some(ContainerDetails {
+                started_at: Some("2024-05-01T10:00:00.123456789Z".into()),
+                restart_count: 3,
+                oom_killed: true,
+            }),
+            ..service_status(Some("running"), vec![])
+        };
+
+        assert_eq!(
+            strip_ansi_codes(&print_details(&svc)),
+            "2024-05-01 10:00:00\t3 OOM killed"
+        );
+        assert_eq!(format_started_at("0001-01-01T00:00:00Z"), "");
+    }
 }
@@ -2,11 +2,19 @@ use anyhow::Result;
 use clap::Args;
 
 use crate::docker::compose_target_cmd;
-use crate::{ClapSelector, TargetSelector};
+use crate::{commands::SelectorFlags, ClapSelector, TargetSelector};
 use nirion_lib::context::NirionContext;
 
+const EXAMPLES: &str = "\
+Examples:
+  # Pull the images of one project
+  nirion pull media
+  # Only the image of one service
+  nirion pull media.jellyfin";
+
 /// Pull service images
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct PullArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -15,6 +23,9 @@ pub struct PullArgs {
         add = TargetSelector::clap_completer()
     )]
     pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
 }
 
 pub async fn handle_pull(
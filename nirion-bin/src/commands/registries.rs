@@ -0,0 +1,164 @@
+use anyhow::Result;
+use clap::Args;
+use nirion_lib::{
+    context::NirionContext,
+    registries::{registry_report, RegistryUsage},
+};
+use nirion_tui_lib::{color::Colorize, table::print_table};
+
+const EXAMPLE_SERVICES: usize = 3;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Every registry the images come from
+  nirion registries
+  # Fail in CI if a private registry has no credentials
+  nirion registries --missing-auth";
+
+/// List the registries images are pulled from and their credentials
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct RegistriesArgs {
+    /// Print the registries as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Only list private registries without credentials, failing if
+    /// there are any
+    #[arg(long)]
+    pub missing_auth: bool,
+}
+
+pub async fn handle_registries(
+    args: &RegistriesArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let mut report =
+        registry_report(&context.projects, context.oci_client.auth_config())?;
+    if args.missing_auth {
+        report
+            .registries
+            .retain(RegistryUsage::missing_auth);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_registries(&report.registries);
+        for (service, image) in &report.invalid {
+            eprintln!(
+                "{} {service}: invalid image reference '{image}'",
+                "warning:".yellow()
+            );
+        }
+    }
+
+    if args.missing_auth && !report.registries.is_empty() {
+        anyhow::bail!(
+            "{} private registr{} without credentials",
+            report.registries.len(),
+            if report.registries.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        );
+    }
+
+    Ok(())
+}
+
+fn print_registries(registries: &[RegistryUsage]) {
+    let mut rows = vec![format!(
+        "{}\t{}\t{}\t{}",
+        "registry".blue(),
+        "images".blue(),
+        "auth".blue(),
+        "services".blue()
+    )];
+
+    for usage in registries {
+        rows.push(format!(
+            "{}\t{}\t{}\t{}",
+            usage.registry.as_str().cyan(),
+            usage.image_count(),
+            auth_label(usage),
+            example_services(usage)
+        ));
+    }
+
+    print_table(rows);
+}
+
+fn auth_label(usage: &RegistryUsage) -> String {
+    let total = usage.services.len();
+    match usage.authenticated.len() {
+        0 if usage.is_docker_hub() => "anonymous".grey().to_string(),
+        0 => "missing".red().to_string(),
+        count if count == total => "configured".green().to_string(),
+        count => format!("partial ({count}/{total})")
+            .yellow()
+            .to_string(),
+    }
+}
+
+fn example_services(usage: &RegistryUsage) -> String {
+    let mut examples = usage
+        .services
+        .keys()
+        .take(EXAMPLE_SERVICES)
+        .cloned()
+        .collect::<Vec<_>>();
+    let remaining = usage
+        .services
+        .len()
+        .saturating_sub(EXAMPLE_SERVICES);
+    if remaining > 0 {
+        examples.push(format!("+{remaining} more"));
+    }
+    examples.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+    use std::collections::BTreeMap;
+
+    fn usage(
+        registry: &str,
+        services: usize,
+        authenticated: usize,
+    ) -> RegistryUsage {
+        let services = (0..services)
+            .map(|i| (format!("app.svc{i}"), format!("{registry}/img:{i}")))
+            .collect::<BTreeMap<_, _>>();
+        RegistryUsage {
+            registry: registry.to_string(),
+            authenticated: services
+                .keys()
+                .take(authenticated)
+                .cloned()
+                .collect(),
+            services,
+        }
+    }
+
+    #[test]
+    fn auth_label_distinguishes_partial_and_missing_credentials() {
+        let label = |usage| strip_ansi_codes(&auth_label(&usage)).to_string();
+
+        assert_eq!(label(usage("index.docker.io", 2, 0)), "anonymous");
+        assert_eq!(label(usage("ghcr.io", 2, 0)), "missing");
+        assert_eq!(label(usage("ghcr.io", 2, 1)), "partial (1/2)");
+        assert_eq!(label(usage("ghcr.io", 2, 2)), "configured");
+    }
+
+    #[test]
+    fn example_services_are_truncated() {
+        assert_eq!(
+            example_services(&usage("ghcr.io", 5, 0)),
+            "app.svc0, app.svc1, app.svc2, +2 more"
+        );
+    }
+}
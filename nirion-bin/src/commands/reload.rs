@@ -1,35 +1,49 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::{ClapSelector, TargetSelector};
+use crate::commands::{LifecycleArgs, StartupArgs, TargetArg};
+use crate::impact::confirm_preview;
+use crate::lifecycle::{
+    run_lifecycle_command, run_project_hooks, run_startup_command,
+};
 use nirion_lib::context::NirionContext;
+use nirion_lib::hooks::HookPhase;
 use nirion_lib::wait::WaitTarget;
 
+const EXAMPLES: &str = "\
+Examples:
+  # Recreate one project after changing its compose file
+  nirion reload media
+  # Recreate one service without waiting for its healthcheck
+  nirion reload media.jellyfin --skip-healthcheck";
+
 /// Stop and recreate service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct ReloadArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
 
-    /// Skip health checks when determining if containers are ready
-    #[arg(short, long)]
-    pub skip_healthcheck: bool,
+    #[command(flatten)]
+    pub startup: StartupArgs,
+
+    /// Skip the projects' preDown, postDown, preUp and postUp hooks
+    #[arg(long)]
+    pub no_hooks: bool,
 }
 
 pub async fn handle_reload(
     args: &ReloadArgs,
     context: &NirionContext,
 ) -> Result<()> {
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "reload", &args.target, &args.lifecycle).await?;
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PreDown).await?;
+    }
     run_lifecycle_command(
         context,
         &args.target,
@@ -38,16 +52,20 @@ pub async fn handle_reload(
             .options(WaitTarget::NoWait),
     )
     .await?;
-    run_lifecycle_command(
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PostDown).await?;
+        run_project_hooks(context, &args.target, HookPhase::PreUp).await?;
+    }
+    run_startup_command(
         context,
         &args.target,
         &["up", "-d"],
-        args.lifecycle
-            .options(if args.skip_healthcheck {
-                WaitTarget::NoWait
-            } else {
-                WaitTarget::Healthchecks
-            }),
+        &args.lifecycle,
+        &args.startup,
     )
-    .await
+    .await?;
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PostUp).await?;
+    }
+    Ok(())
 }
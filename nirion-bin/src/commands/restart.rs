@@ -1,45 +1,51 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::{ClapSelector, TargetSelector};
+use crate::commands::{LifecycleArgs, StartupArgs, TargetArg};
+use crate::impact::{confirm_impact, confirm_preview};
+use crate::lifecycle::run_startup_command;
+use crate::prompt::assumes_yes;
 use nirion_lib::context::NirionContext;
-use nirion_lib::wait::WaitTarget;
+use nirion_lib::projects::TargetSelector;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Restart one service and wait until it is healthy again
+  nirion restart media.jellyfin
+  # Restart two projects at a time
+  nirion restart '*' --jobs 2";
 
 /// Restart service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct RestartArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
 
-    /// Skip health checks when determining if containers are ready
-    #[arg(short, long)]
-    pub skip_healthcheck: bool,
+    #[command(flatten)]
+    pub startup: StartupArgs,
 }
 
 pub async fn handle_restart(
     args: &RestartArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    run_lifecycle_command(
+    let context = &args.lifecycle.profile.apply(context);
+    if args.lifecycle.preview {
+        confirm_preview(context, "restart", &args.target, &args.lifecycle)
+            .await?;
+    } else if *args.target == TargetSelector::All && !assumes_yes() {
+        confirm_impact(context, "restart", &args.target).await?;
+    }
+    run_startup_command(
         context,
         &args.target,
         &["restart"],
-        args.lifecycle
-            .options(if args.skip_healthcheck {
-                WaitTarget::NoWait
-            } else {
-                WaitTarget::Healthchecks
-            }),
+        &args.lifecycle,
+        &args.startup,
     )
     .await
 }
@@ -1,45 +1,44 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::{ClapSelector, TargetSelector};
+use crate::commands::{LifecycleArgs, StartupArgs, TargetArg};
+use crate::impact::confirm_preview;
+use crate::lifecycle::run_startup_command;
 use nirion_lib::context::NirionContext;
-use nirion_lib::wait::WaitTarget;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Start the stopped containers of one project
+  nirion start media
+  # Start everything and fail if a service got no container
+  nirion start --fail-on missing";
 
 /// Start service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct StartArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
 
-    /// Skip health checks when determining if containers are ready
-    #[arg(short, long)]
-    pub skip_healthcheck: bool,
+    #[command(flatten)]
+    pub startup: StartupArgs,
 }
 
 pub async fn handle_start(
     args: &StartArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    run_lifecycle_command(
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "start", &args.target, &args.lifecycle).await?;
+    run_startup_command(
         context,
         &args.target,
         &["start"],
-        args.lifecycle
-            .options(if args.skip_healthcheck {
-                WaitTarget::NoWait
-            } else {
-                WaitTarget::Healthchecks
-            }),
+        &args.lifecycle,
+        &args.startup,
     )
     .await
 }
@@ -1,22 +1,24 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::{ClapSelector, TargetSelector};
+use crate::commands::{LifecycleArgs, TargetArg};
+use crate::impact::confirm_preview;
+use crate::lifecycle::run_shutdown_command;
 use nirion_lib::context::NirionContext;
-use nirion_lib::wait::WaitTarget;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Stop one service, keeping its container
+  nirion stop media.jellyfin
+  # See what would be stopped and ask first
+  nirion stop '*' --preview";
 
 /// Stop service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct StopArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
@@ -26,12 +28,8 @@ pub async fn handle_stop(
     args: &StopArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    run_lifecycle_command(
-        context,
-        &args.target,
-        &["stop"],
-        args.lifecycle
-            .options(WaitTarget::NoWait),
-    )
-    .await
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "stop", &args.target, &args.lifecycle).await?;
+    run_shutdown_command(context, &args.target, &["stop"], &args.lifecycle)
+        .await
 }
@@ -1,10 +1,19 @@
 use clap::Args;
 
-use crate::{docker::compose_target_cmd, ClapSelector, TargetSelector};
+use crate::{
+    commands::SelectorFlags, docker::compose_running_target_cmd, ClapSelector,
+    TargetSelector,
+};
 use nirion_lib::context::NirionContext;
 
+const EXAMPLES: &str = "\
+Examples:
+  # The processes of one service
+  nirion top media.jellyfin";
+
 /// Display the running processes of a service container
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct TopArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -13,6 +22,9 @@ pub struct TopArgs {
         add = TargetSelector::clap_completer()
     )]
     pub target: TargetSelector,
+
+    #[command(flatten)]
+    pub selector: SelectorFlags,
 }
 
 pub async fn handle_top(
@@ -22,5 +34,5 @@ pub async fn handle_top(
     // docker compose top has no flags: just ["top"]
     let cmd: Vec<&str> = vec!["top"];
 
-    compose_target_cmd(context, &args.target, &cmd).await
+    compose_running_target_cmd(context, &args.target, &cmd).await
 }
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use clap::Args;
+
+use crate::commands::{LifecycleArgs, TargetArg};
+use crate::docker::compose_target_cmd;
+use crate::impact::confirm_preview;
+use crate::lifecycle::run_lifecycle_command;
+use crate::TargetSelector;
+use nirion_lib::context::NirionContext;
+use nirion_lib::wait::WaitTarget;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Let it run again
+  nirion unpause media.jellyfin";
+
+/// Unpause paused service containers
+#[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
+pub struct UnpauseArgs {
+    #[command(flatten)]
+    pub target: TargetArg,
+
+    #[command(flatten)]
+    pub lifecycle: LifecycleArgs,
+}
+
+pub async fn handle_unpause(
+    args: &UnpauseArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "unpause", &args.target, &args.lifecycle).await?;
+    if let TargetSelector::Service(_) = *args.target {
+        return compose_target_cmd(context, &args.target, &["unpause"]).await;
+    }
+
+    run_lifecycle_command(
+        context,
+        &args.target,
+        &["unpause"],
+        args.lifecycle
+            .options(WaitTarget::NoWait),
+    )
+    .await
+}
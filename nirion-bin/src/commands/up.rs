@@ -1,45 +1,357 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::commands::LifecycleArgs;
-use crate::lifecycle::run_lifecycle_command;
-use crate::{ClapSelector, TargetSelector};
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::commands::{LifecycleArgs, StartupArgs, TargetArg};
+use crate::impact::confirm_preview;
+use crate::lifecycle::{
+    run_lifecycle_command, run_project_hooks, run_pull_phase,
+    run_startup_command, write_textfile_metrics,
+};
+use crate::output::OutputOptions;
+use crate::TargetSelector;
+use nirion_lib::compose_file::full_compose;
 use nirion_lib::context::NirionContext;
+use nirion_lib::docker::{
+    container_image_ids, inspect_local_image, query_project_status,
+    ProjectStatus,
+};
+use nirion_lib::drift::LockDeployment;
+use nirion_lib::hooks::HookPhase;
+use nirion_lib::projects::{selected_project_names, ServiceSelector};
+use nirion_lib::textfile::UpMetrics;
 use nirion_lib::wait::WaitTarget;
+use nirion_tui_lib::color::Colorize;
+use serde_yaml_ng::Value;
+
+const EXAMPLES: &str = "\
+Examples:
+  # Start every project and wait for their healthchecks
+  nirion up
+  # Pull first, then start one project without waiting
+  nirion up media --pull-first --skip-healthcheck
+  # Show what would change and ask before going ahead
+  nirion up media.jellyfin --preview";
 
 /// Create and start service containers
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct UpArgs {
-    /// Target selector: *, project, or project.service
-    #[arg(
-        default_value = "*",
-        value_parser = TargetSelector::clap_parse,
-        add = TargetSelector::clap_completer()
-    )]
-    pub target: TargetSelector,
+    #[command(flatten)]
+    pub target: TargetArg,
 
     #[command(flatten)]
     pub lifecycle: LifecycleArgs,
 
-    /// Skip health checks when determining if containers are ready
-    #[arg(short, long)]
-    pub skip_healthcheck: bool,
+    #[command(flatten)]
+    pub startup: StartupArgs,
+
+    /// Pull all images first and only start containers once every pull
+    /// succeeded
+    #[arg(long)]
+    pub pull_first: bool,
+
+    /// Leave containers running an older image than their tag alone,
+    /// even once the tag points at the locked digest, instead of
+    /// recreating them
+    #[arg(long)]
+    pub no_auto_recreate: bool,
+
+    /// Skip the projects' preUp and postUp hooks
+    #[arg(long)]
+    pub no_hooks: bool,
+
+    /// Write node_exporter textfile metrics to DIR/nirion.prom when done
+    ///
+    /// The gauges, next to the ones `update` writes to the same file:
+    ///
+    ///   nirion_up_failed_services{project,service}
+    ///                             1 if the service has no container, or
+    ///                             one that failed, is unhealthy or keeps
+    ///                             restarting, else 0
+    ///   nirion_last_up_success    1 if up succeeded, else 0
+    ///   nirion_last_up_timestamp  when it finished, in unix seconds
+    #[arg(
+        long,
+        env = "NIRION_TEXTFILE_DIR",
+        value_name = "DIR",
+        verbatim_doc_comment
+    )]
+    pub textfile_dir: Option<PathBuf>,
 }
 
 pub async fn handle_up(
     args: &UpArgs,
     context: &NirionContext,
 ) -> Result<()> {
-    run_lifecycle_command(
+    let context = &args.lifecycle.profile.apply(context);
+    confirm_preview(context, "up", &args.target, &args.lifecycle).await?;
+    let result = run_up(args, context).await;
+    if let Some(dir) = &args.textfile_dir {
+        let metrics = up_metrics(context, &args.target).await;
+        write_textfile_metrics(
+            dir,
+            &metrics.gauges(result.is_ok(), SystemTime::now()),
+        );
+    }
+    result
+}
+
+async fn run_up(
+    args: &UpArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PreUp).await?;
+    }
+
+    if args.pull_first {
+        run_pull_phase(
+            context,
+            &args.target,
+            args.lifecycle
+                .options(WaitTarget::NoWait),
+        )
+        .await
+        .map_err(|error| {
+            error.context("pulling images failed, no containers were started")
+        })?;
+    }
+
+    if !args.no_auto_recreate {
+        recreate_stale_services(args, context).await?;
+    }
+
+    run_startup_command(
         context,
         &args.target,
         &["up", "-d"],
-        args.lifecycle
-            .options(if args.skip_healthcheck {
-                WaitTarget::NoWait
-            } else {
-                WaitTarget::Healthchecks
-            }),
+        &args.lifecycle,
+        &args.startup,
     )
-    .await
+    .await?;
+
+    if !args.no_hooks {
+        run_project_hooks(context, &args.target, HookPhase::PostUp).await?;
+    }
+    Ok(())
+}
+
+/// A locked service `up -d` alone wouldn't bring onto its lock entry.
+enum StaleService {
+    /// The compose file pins another digest than the lock, so no
+    /// recreate gets past it.
+    ComposeFile {
+        service: String,
+        pinned: String,
+        locked: String,
+    },
+    /// The compose file names a tag that points at the locked digest on
+    /// this host by now, while containers still run an older image.
+    Containers {
+        service: String,
+        image: String,
+        locked: String,
+    },
+}
+
+/// Recreates the selected services whose containers run an older image
+/// than their tag, which already points at the lock entry. `up -d` alone
+/// only recreates them when the compose file changed too. Compose files
+/// pinning another digest than the lock are reported instead, as
+/// recreating from them would only start the old digest again.
+async fn recreate_stale_services(
+    args: &UpArgs,
+    context: &NirionContext,
+) -> Result<()> {
+    let output = OutputOptions::get();
+    for stale in stale_services(context, &args.target).await? {
+        let (service, image, locked) = match stale {
+            StaleService::ComposeFile {
+                service,
+                pinned,
+                locked,
+            } => {
+                eprintln!(
+                    "{} {service}: the lock pins {}, but its compose file \
+                     still pins {}; rebuild it, e.g. with `nixos-rebuild \
+                     switch`, to deploy the locked image",
+                    "warning:".yellow(),
+                    output.digest(&locked),
+                    output.digest(&pinned)
+                );
+                continue;
+            }
+            StaleService::Containers {
+                service,
+                image,
+                locked,
+            } => (service, image, locked),
+        };
+        let Some((project, service_name)) = service.split_once('.') else {
+            continue;
+        };
+        if !output.quiet {
+            eprintln!(
+                "{} {service}: its containers run an older image than \
+                 {image}, which is at the locked {} now",
+                "Recreating".cyan(),
+                output.digest(&locked)
+            );
+        }
+
+        let target = TargetSelector::Service(ServiceSelector {
+            project: project.to_string(),
+            service: service_name.to_string(),
+        });
+        run_lifecycle_command(
+            context,
+            &target,
+            &["up", "-d", "--force-recreate", "--no-deps"],
+            args.lifecycle
+                .options(WaitTarget::NoWait),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// The selected services with a lock entry that `up -d` alone won't
+/// deploy. Local builds and lock entries for another image than the
+/// service's are left out, as they say nothing about what it should run,
+/// and so are compose files that can't be read, which `up` reports
+/// itself.
+async fn stale_services(
+    context: &NirionContext,
+    target: &TargetSelector,
+) -> Result<Vec<StaleService>> {
+    if context.locked_images.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut stale = Vec::new();
+    for project_name in selected_project_names(target, &context.projects) {
+        let project = &context.projects[&project_name];
+        let Ok(compose) = full_compose(project) else {
+            continue;
+        };
+        let mut status = None;
+        for (service_name, service) in &project.services {
+            if !target.selects_service(&project_name, service_name) {
+                continue;
+            }
+            let key = format!("{project_name}.{service_name}");
+            let Some(locked) =
+                context
+                    .locked_images
+                    .get(&key)
+                    .filter(|locked| {
+                        !service.build
+                            && service.image.as_deref() == Some(&locked.image)
+                    })
+            else {
+                continue;
+            };
+            let Some(image) = compose
+                .get("services")
+                .and_then(|services| services.get(service_name.as_str()))
+                .and_then(|definition| definition.get("image"))
+                .and_then(Value::as_str)
+                .filter(|image| !image.contains('$'))
+            else {
+                continue;
+            };
+
+            match LockDeployment::of(image, &locked.digest) {
+                LockDeployment::Pinned => {}
+                LockDeployment::StaleComposeFile { pinned } => {
+                    stale.push(StaleService::ComposeFile {
+                        service: key,
+                        pinned,
+                        locked: locked.digest.clone(),
+                    });
+                }
+                LockDeployment::Tag => {
+                    if status.is_none() {
+                        status = Some(
+                            query_project_status(context, &project_name)
+                                .await?,
+                        );
+                    }
+                    let ids = status
+                        .as_ref()
+                        .and_then(|status| status.services.get(service_name))
+                        .map(|replicas| {
+                            replicas
+                                .iter()
+                                .map(|replica| replica.id.clone())
+                                .collect::<Vec<_>>()
+                        })
+                        .unwrap_or_default();
+                    if behind_tag(context, &ids, image, &locked.digest).await? {
+                        stale.push(StaleService::Containers {
+                            service: key,
+                            image: image.to_string(),
+                            locked: locked.digest.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Whether `image` points at `digest` on this host while a container in
+/// `ids` was created from another image, so that recreating it moves it
+/// onto `digest`.
+async fn behind_tag(
+    context: &NirionContext,
+    ids: &[String],
+    image: &str,
+    digest: &str,
+) -> Result<bool> {
+    if ids.is_empty() {
+        return Ok(false);
+    }
+    let Some(local) = inspect_local_image(&context.docker_command, image).await
+    else {
+        return Ok(false);
+    };
+    let at_digest = local
+        .repo_digests
+        .iter()
+        .any(|repo_digest| {
+            repo_digest
+                .split_once('@')
+                .is_some_and(|(_, pulled)| pulled == digest)
+        });
+    if !at_digest {
+        return Ok(false);
+    }
+
+    Ok(container_image_ids(&context.docker_command, ids)
+        .await?
+        .iter()
+        .any(|running| *running != local.id))
+}
+
+/// Where each selected service ended up once `up` is done. Projects
+/// whose status can't be queried count as having no containers.
+async fn up_metrics(
+    context: &NirionContext,
+    target: &TargetSelector,
+) -> UpMetrics {
+    let mut metrics = UpMetrics::default();
+    for project_name in selected_project_names(target, &context.projects) {
+        let Some(project) = context.projects.get(&project_name) else {
+            continue;
+        };
+        let status = query_project_status(context, &project_name)
+            .await
+            .unwrap_or_else(|_| ProjectStatus::from_containers([]));
+        metrics.observe(&project_name, project, &status, target);
+    }
+    metrics
 }
@@ -1,15 +1,51 @@
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use anyhow::Context;
 use clap::Args;
-use futures::StreamExt;
+use futures::{stream::BoxStream, StreamExt};
 use nirion_lib::{
     context::NirionContext,
+    downgrade::{accept_downgrades, SuspectedDowngrade},
+    events::LockUpdateEvent,
+    lock::{group_diffs, DiffEntry, DiffGroup, VersionedImage},
     lock_update::image_update_stream,
-    projects::{get_images, TargetSelector},
+    projects::{get_images, ImagePattern, TargetSelector},
+    resolve_failure::FailureReport,
+    textfile::UpdateMetrics,
+};
+use nirion_tui_lib::color::Colorize;
+use serde::Serialize;
+
+use crate::{
+    commands::lock::{
+        filter_by_image, format_lock_update_event, format_markdown_summary,
+        retain_changed_since, skip_local_builds,
+    },
+    commands::SelectorFlags,
+    lifecycle::write_textfile_metrics,
+    output::OutputOptions,
+    prompt::{assumes_yes, Confirm},
+    update_progress::{print_lock_update_events, ProgressMode},
+    ClapSelector,
 };
 
-use crate::{commands::lock::format_lock_update_event, ClapSelector};
+const EXAMPLES: &str = "\
+Examples:
+  # Update every lock entry, resolving four images at a time
+  nirion update --jobs 4
+  # Only the images of one registry organization
+  nirion update --image 'ghcr.io/linuxserver/*'
+  # Only services whose image changed in the project file since main
+  nirion update --changed-since main";
 
 /// Update lock file entries
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct UpdateArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -19,21 +55,383 @@ pub struct UpdateArgs {
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
+    /// Only services whose image repository contains this text or
+    /// matches this glob, whatever the tag; repeatable
+    #[arg(long, value_name = "PATTERN")]
+    pub image: Vec<ImagePattern>,
+
+    /// Print the services --image selects without resolving anything
+    #[arg(long, requires = "image")]
+    pub list_matches: bool,
+
+    /// Only services whose image reference in the project file changed
+    /// since this git ref, e.g. HEAD
+    #[arg(long, value_name = "GIT_REF")]
+    pub changed_since: Option<String>,
+
+    /// Write the images that resolved even if others failed; the
+    /// failed ones keep their current lock entries
+    #[arg(long)]
+    pub partial: bool,
+
+    /// Write updates that look like downgrades: to a version older than
+    /// the locked one, or to a digest a service was deployed with
+    /// before. Otherwise they keep their lock entries, after asking
+    /// about each one on a terminal
+    #[arg(long)]
+    pub allow_downgrade: bool,
+
     /// Number of concurrent digest fetches
     #[arg(short = 'j', long = "jobs", default_value_t = 10)]
     pub jobs: usize,
+
+    /// How to show images being checked
+    #[arg(long, value_enum, default_value = "auto")]
+    pub progress: ProgressMode,
+
+    /// Write a markdown summary of the changes, usable as a commit
+    /// message, to this file
+    ///
+    /// The file is removed when nothing changed, so a stale summary from
+    /// an earlier run is never committed.
+    #[arg(long, visible_alias = "commit-message", value_name = "PATH")]
+    pub summary_file: Option<PathBuf>,
+
+    /// Print the changes and any failures as JSON
+    #[arg(long, conflicts_with_all = ["summary_file", "progress"])]
+    pub json: bool,
+
+    /// Write node_exporter textfile metrics to DIR/nirion.prom when done
+    ///
+    /// The gauges, next to the ones `up` writes to the same file:
+    ///
+    ///   nirion_lock_outdated_total    lock entries the update found behind
+    ///   nirion_update_failed_images   images that failed to resolve
+    ///   nirion_last_update_success    1 if the update succeeded, else 0
+    ///   nirion_last_update_timestamp  when it finished, in unix seconds
+    #[arg(
+        long,
+        env = "NIRION_TEXTFILE_DIR",
+        value_name = "DIR",
+        verbatim_doc_comment
+    )]
+    pub textfile_dir: Option<PathBuf>,
+}
+
+#[derive(Serialize, Default)]
+struct UpdateReport {
+    changes: Vec<DiffEntry>,
+    groups: Vec<DiffGroup>,
+    /// Services left out as local builds.
+    skipped: Vec<String>,
+    failures: Option<FailureReport>,
+    /// Suspected downgrades that kept their lock entries.
+    held_downgrades: Vec<SuspectedDowngrade>,
 }
 
 pub async fn handle_update(
     args: &UpdateArgs,
     context: &NirionContext,
 ) -> anyhow::Result<()> {
-    let images = get_images(&args.target, &context.projects);
-    let mut events = image_update_stream(context, images, args.jobs);
+    let mut images = get_images(&args.target, &context.projects)?;
+    let announce = !args.json && !OutputOptions::get().quiet;
+    if let Some(git_ref) = &args.changed_since {
+        retain_changed_since(&mut images, git_ref, announce).await?;
+    }
+    if !filter_by_image(&mut images, &args.image, args.list_matches, announce) {
+        return Ok(());
+    }
+    let skipped =
+        skip_local_builds(&args.target, context, &args.image, announce)?;
+    let total = images.len();
+    let single =
+        matches!(args.target, TargetSelector::Service(_)) && total == 1;
+    let events = image_update_stream(
+        context,
+        images,
+        args.jobs,
+        args.partial,
+        args.allow_downgrade,
+    );
+
+    let mut metrics = UpdateMetrics::default();
+    let mut held = Vec::new();
+    let result = if args.json {
+        print_update_json(events, skipped, |event| metrics.observe(event)).await
+    } else if single {
+        print_single_update(args, events, &mut metrics, &mut held).await
+    } else {
+        print_update(args, events, total, &mut metrics, &mut held).await
+    };
+    // Asked about even after a partial failure, which still wrote the
+    // images that resolved.
+    let result = confirm_downgrades(context, &held).and(result);
+    if let Some(dir) = &args.textfile_dir {
+        write_textfile_metrics(
+            dir,
+            &metrics.gauges(result.is_ok(), SystemTime::now()),
+        );
+    }
+    result
+}
+
+async fn print_update(
+    args: &UpdateArgs,
+    events: BoxStream<'static, anyhow::Result<LockUpdateEvent>>,
+    total: usize,
+    metrics: &mut UpdateMetrics,
+    held: &mut Vec<SuspectedDowngrade>,
+) -> anyhow::Result<()> {
+    let output = OutputOptions::get();
+    let mut summary = None;
+    print_lock_update_events(
+        events,
+        total,
+        args.jobs,
+        args.progress,
+        output,
+        |event| {
+            metrics.observe(event);
+            match event {
+                LockUpdateEvent::ChangesDetected { diffs } => {
+                    summary = Some(format_markdown_summary(diffs));
+                }
+                LockUpdateEvent::DowngradesSuspected {
+                    downgrades,
+                    held: true,
+                } => held.clone_from(downgrades),
+                _ => {}
+            }
+        },
+    )
+    .await?;
+
+    write_summary(args, summary, output)
+}
+
+/// Updates one service without any progress display: a single line
+/// with the outcome, or the failure.
+async fn print_single_update(
+    args: &UpdateArgs,
+    mut events: BoxStream<'static, anyhow::Result<LockUpdateEvent>>,
+    metrics: &mut UpdateMetrics,
+    held: &mut Vec<SuspectedDowngrade>,
+) -> anyhow::Result<()> {
+    let output = OutputOptions::get();
+    let mut summary = None;
+    let mut checked = None;
 
     while let Some(event) = events.next().await {
-        println!("{}", format_lock_update_event(event?));
+        let event = event?;
+        metrics.observe(&event);
+        match event {
+            LockUpdateEvent::ImageStarted { service, image } => {
+                checked = Some(format!("{service}: {image}"));
+            }
+            LockUpdateEvent::UpToDate if !output.quiet => {
+                if let Some(checked) = &checked {
+                    println!("{checked} is up to date");
+                }
+            }
+            LockUpdateEvent::ChangesDetected { diffs } => {
+                summary = Some(format_markdown_summary(&diffs));
+                for diff in &diffs {
+                    println!("{}", format_single_change(diff, output));
+                }
+            }
+            LockUpdateEvent::DowngradesSuspected {
+                downgrades,
+                held: is_held,
+            } => {
+                println!(
+                    "{}",
+                    format_lock_update_event(
+                        LockUpdateEvent::DowngradesSuspected {
+                            downgrades: downgrades.clone(),
+                            held: is_held,
+                        }
+                    )
+                    .trim_start()
+                );
+                if is_held {
+                    *held = downgrades;
+                }
+            }
+            event @ LockUpdateEvent::ResolutionFailed { .. } => {
+                println!("{}", format_lock_update_event(event));
+            }
+            event @ LockUpdateEvent::ImageSkipped { .. } if !output.quiet => {
+                println!("{}", format_lock_update_event(event));
+            }
+            event @ LockUpdateEvent::TagSearchCapped { .. }
+                if !output.quiet =>
+            {
+                eprintln!("{}", format_lock_update_event(event));
+            }
+            _ => {}
+        }
+    }
+
+    write_summary(args, summary, output)
+}
+
+fn format_single_change(
+    diff: &DiffEntry,
+    output: OutputOptions,
+) -> String {
+    let version = |image: &VersionedImage| {
+        image
+            .version
+            .clone()
+            .unwrap_or_else(|| "none".to_string())
+    };
+    match diff {
+        DiffEntry::Added { service, new } => format!(
+            "{}: locked at {} ({})",
+            service.as_str().green(),
+            version(new),
+            output.digest(&new.digest)
+        ),
+        DiffEntry::Updated { service, old, new } => format!(
+            "{}: {} -> {} ({} -> {})",
+            service.as_str().cyan(),
+            version(old),
+            version(new),
+            output.digest(&old.digest),
+            output.digest(&new.digest)
+        ),
+        DiffEntry::Removed { service, .. } => {
+            format!("{}: removed", service.as_str().yellow())
+        }
     }
+}
 
+/// Asks about each suspected downgrade that was held back and writes the
+/// ones accepted. Without a terminal, or with `--yes`, they stay held
+/// back: only `--allow-downgrade` writes them unasked.
+fn confirm_downgrades(
+    context: &NirionContext,
+    held: &[SuspectedDowngrade],
+) -> anyhow::Result<()> {
+    if held.is_empty() || assumes_yes() || !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+
+    let output = OutputOptions::get();
+    let mut accepted = Vec::new();
+    for downgrade in held {
+        let target = downgrade
+            .new
+            .version
+            .clone()
+            .unwrap_or_else(|| {
+                output
+                    .digest(&downgrade.new.digest)
+                    .to_string()
+            });
+        let question = format!(
+            "Lock {} at {target} anyway ({})?",
+            downgrade.service,
+            downgrade.reason.describe()
+        );
+        if Confirm::new(question, false)
+            .destructive()
+            .ask()?
+        {
+            accepted.push(downgrade.clone());
+        }
+    }
+    if accepted.is_empty() {
+        return Ok(());
+    }
+
+    accept_downgrades(context.lock_store()?, &accepted)?;
+    if !output.quiet {
+        println!(
+            "Lock file updated with {} accepted downgrade(s)",
+            accepted.len()
+        );
+    }
     Ok(())
 }
+
+/// Writes `summary` to `--summary-file`, or removes the file if there
+/// were no changes.
+fn write_summary(
+    args: &UpdateArgs,
+    summary: Option<String>,
+    output: OutputOptions,
+) -> anyhow::Result<()> {
+    let Some(path) = &args.summary_file else {
+        return Ok(());
+    };
+
+    match summary {
+        Some(summary) => {
+            fs::write(path, summary).with_context(|| {
+                format!("failed to write {}", path.display())
+            })?;
+            if !output.quiet {
+                println!("Summary written to {}", path.display());
+            }
+        }
+        None => match fs::remove_file(path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                return Err(error).with_context(|| {
+                    format!("failed to remove {}", path.display())
+                });
+            }
+            _ => {}
+        },
+    }
+
+    Ok(())
+}
+
+async fn print_update_json(
+    mut events: BoxStream<'static, anyhow::Result<LockUpdateEvent>>,
+    skipped: Vec<String>,
+    mut on_event: impl FnMut(&LockUpdateEvent),
+) -> anyhow::Result<()> {
+    let mut report = UpdateReport {
+        skipped,
+        ..Default::default()
+    };
+    let mut error = None;
+
+    while let Some(event) = events.next().await {
+        if let Ok(event) = &event {
+            on_event(event);
+        }
+        match event {
+            Ok(LockUpdateEvent::ChangesDetected { diffs }) => {
+                report.groups = group_diffs(&diffs);
+                report.changes = diffs;
+            }
+            Ok(LockUpdateEvent::ImageSkipped { service, .. }) => {
+                report.skipped.push(service);
+            }
+            Ok(LockUpdateEvent::DowngradesSuspected {
+                downgrades,
+                held: true,
+            }) => {
+                report.held_downgrades = downgrades;
+            }
+            Ok(LockUpdateEvent::ResolutionFailed { report: failures }) => {
+                report.failures = Some(failures);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+
+    report.skipped.sort();
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    error.map_or(Ok(()), Err)
+}
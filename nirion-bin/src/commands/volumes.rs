@@ -1,11 +1,22 @@
 use anyhow::Result;
 use clap::Args;
 
-use crate::{docker::compose_target_cmd, ClapSelector, TargetSelector};
+use crate::{
+    commands::SelectorFlags, docker::compose_target_cmd, output::OutputOptions,
+    ClapSelector, TargetSelector,
+};
 use nirion_lib::context::NirionContext;
 
+const EXAMPLES: &str = "\
+Examples:
+  # The volumes of every project
+  nirion volumes
+  # The volumes of one project as JSON
+  nirion volumes media --format json";
+
 /// List volumes
 #[derive(Args, Debug, Clone)]
+#[command(after_help = EXAMPLES)]
 pub struct VolumesArgs {
     /// Target selector: *, project, or project.service
     #[arg(
@@ -15,13 +26,12 @@ pub struct VolumesArgs {
     )]
     pub target: TargetSelector,
 
+    #[command(flatten)]
+    pub selector: SelectorFlags,
+
     /// Output format (table, json, Go template)
     #[arg(long, default_value = "table")]
     pub format: String,
-
-    /// Only display volume names
-    #[arg(short = 'q', long)]
-    pub quiet: bool,
 }
 
 pub async fn handle_volumes(
@@ -31,7 +41,8 @@ pub async fn handle_volumes(
     let mut cmd: Vec<String> =
         vec!["volumes".into(), "--format".into(), args.format.clone()];
 
-    if args.quiet {
+    // The global --quiet narrows the listing to volume names.
+    if OutputOptions::get().quiet {
         cmd.push("--quiet".into());
     }
 
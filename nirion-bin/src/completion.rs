@@ -0,0 +1,419 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    time::Duration,
+};
+
+use clap::Parser;
+use clap_complete::CompletionCandidate;
+use futures::future::join_all;
+use nirion_lib::{
+    docker::{
+        DockerCommand, ProjectStatus, ServiceState,
+        query_project_status_for_command,
+    },
+    projects::{Project, Projects},
+    state::state_dir,
+    status_cache::{read_status_cache, status_cache_file, write_status_cache},
+};
+
+use crate::CoreCli;
+
+/// Completion waits at most this long for `docker compose ps` before
+/// offering every service from the project file instead.
+const LIVE_STATUS_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Snapshots older than this are re-queried; completion needs a fresher
+/// view than the cache keeps around for other readers.
+const COMPLETION_CACHE_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Completes `project.service` with only the services that have a
+/// running container, annotated with their state.
+pub fn running_service_completer(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return vec![];
+    };
+    let (projects, statuses) = projects_and_statuses();
+
+    running_service_candidates(&projects, &statuses, current)
+}
+
+/// Like [`crate::target_selector_completer`], but the `project.service`
+/// candidates are limited to running services.
+pub fn running_target_completer(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return vec![];
+    };
+    let (projects, statuses) = projects_and_statuses();
+
+    let mut completions = vec![];
+    if !current.contains('.') {
+        if "*".starts_with(current) {
+            completions.push(CompletionCandidate::new("*"));
+        }
+        completions.extend(
+            projects
+                .iter()
+                .map(|(project, _)| project)
+                .filter(|project| project.starts_with(current))
+                .map(CompletionCandidate::new),
+        );
+    }
+    completions
+        .extend(running_service_candidates(&projects, &statuses, current));
+
+    completions
+}
+
+/// Completes `--project` with the names of the projects.
+pub fn project_flag_completer(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return vec![];
+    };
+
+    file_projects()
+        .iter()
+        .map(|(project, _)| project)
+        .filter(|project| project.starts_with(current))
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Completes `--service` with the services of the project given with
+/// `--project` on the same command line, or of every project without it.
+pub fn service_flag_completer(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return vec![];
+    };
+    let args = std::env::args().collect::<Vec<_>>();
+
+    service_flag_candidates(
+        &file_projects(),
+        flag_value(&args, "--project"),
+        current,
+    )
+}
+
+fn service_flag_candidates(
+    projects: &Projects,
+    project: Option<&str>,
+    current: &str,
+) -> Vec<CompletionCandidate> {
+    let services = projects
+        .iter()
+        .filter(|(name, _)| project.is_none_or(|project| *name == project))
+        .flat_map(|(_, project)| project.services.keys())
+        .filter(|service| service.starts_with(current))
+        .collect::<BTreeSet<_>>();
+
+    services
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// The value of `flag` in `args`, given as `--flag value` or
+/// `--flag=value`.
+fn flag_value<'a>(
+    args: &'a [String],
+    flag: &str,
+) -> Option<&'a str> {
+    args.iter()
+        .enumerate()
+        .find_map(|(index, arg)| {
+            if arg == flag {
+                return args.get(index + 1).map(String::as_str);
+            }
+            arg.strip_prefix(flag)?
+                .strip_prefix('=')
+        })
+}
+
+fn file_projects() -> Projects {
+    let core_cli = CoreCli::parse();
+
+    tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current()
+            .block_on(core_cli.files.completion_projects())
+    })
+}
+
+fn projects_and_statuses() -> (Projects, BTreeMap<String, ProjectStatus>) {
+    let core_cli = CoreCli::parse();
+
+    tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async {
+            let projects = core_cli
+                .files
+                .completion_projects()
+                .await;
+            let statuses = project_statuses(&projects).await;
+            (projects, statuses)
+        })
+    })
+}
+
+/// Recent snapshots from the state directory, topped up with live
+/// queries for projects without one.
+async fn project_statuses(
+    projects: &Projects
+) -> BTreeMap<String, ProjectStatus> {
+    let cache_file = state_dir()
+        .ok()
+        .map(|dir| status_cache_file(&dir));
+    let mut statuses = cache_file
+        .as_deref()
+        .map(|path| read_status_cache(path, COMPLETION_CACHE_MAX_AGE))
+        .unwrap_or_default();
+    statuses.retain(|project, _| projects.contains_key(project));
+
+    let missing = projects
+        .iter()
+        .filter(|(name, _)| !statuses.contains_key(*name))
+        .map(|(name, project)| (name.to_string(), project))
+        .collect::<Vec<_>>();
+    let live =
+        live_statuses(&DockerCommand::default(), missing, LIVE_STATUS_TIMEOUT)
+            .await;
+    for (name, status) in live {
+        if let Some(path) = &cache_file {
+            let _ = write_status_cache(path, &name, &status);
+        }
+        statuses.insert(name, status);
+    }
+
+    statuses
+}
+
+/// Queries the projects concurrently. Projects whose query fails or
+/// doesn't finish within `timeout` are left out, without holding back
+/// the others.
+async fn live_statuses(
+    docker_command: &DockerCommand,
+    projects: Vec<(String, &Project)>,
+    timeout: Duration,
+) -> Vec<(String, ProjectStatus)> {
+    let queries = projects
+        .into_iter()
+        .map(|(name, project)| async move {
+            let status = tokio::time::timeout(
+                timeout,
+                query_project_status_for_command(docker_command, project),
+            )
+            .await;
+            match status {
+                Ok(Ok(status)) => Some((name, status)),
+                _ => None,
+            }
+        });
+
+    join_all(queries)
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Projects without a status offer all their services unannotated.
+fn running_service_candidates(
+    projects: &Projects,
+    statuses: &BTreeMap<String, ProjectStatus>,
+    current: &str,
+) -> Vec<CompletionCandidate> {
+    let (project_prefix, service_prefix) = current
+        .split_once('.')
+        .map_or((current, None), |(project, service)| {
+            (project, Some(service))
+        });
+
+    let mut completions = vec![];
+    for (project_name, project) in projects.iter() {
+        let selected = match service_prefix {
+            Some(_) => project_name == project_prefix,
+            None => project_name.starts_with(project_prefix),
+        };
+        if !selected {
+            continue;
+        }
+
+        let status = statuses.get(project_name);
+        for service_name in project.services.keys() {
+            if !service_name.starts_with(service_prefix.unwrap_or_default()) {
+                continue;
+            }
+
+            let candidate = CompletionCandidate::new(format!(
+                "{project_name}.{service_name}"
+            ));
+            match status {
+                None => completions.push(candidate),
+                Some(status) => {
+                    if let Some(state) = running_state(status, service_name) {
+                        completions.push(candidate.help(Some(state.into())));
+                    }
+                }
+            }
+        }
+    }
+
+    completions
+}
+
+/// State of the first replica of `service` with a running container.
+fn running_state(
+    status: &ProjectStatus,
+    service: &str,
+) -> Option<&'static str> {
+    status
+        .services
+        .get(service)?
+        .iter()
+        .find_map(|container| match container.state {
+            ServiceState::Healthy => Some("healthy"),
+            ServiceState::Unhealthy => Some("unhealthy"),
+            ServiceState::Starting => Some("starting"),
+            ServiceState::Running => Some("running"),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projects() -> Projects {
+        serde_json::from_str(
+            r#"
+{
+  "media": {
+    "name": "media",
+    "dockerCompose": "media.yml",
+    "services": {
+      "jellyfin": {"image": "jellyfin", "healthcheck": true, "restart": null},
+      "sonarr": {"image": "sonarr", "healthcheck": false, "restart": null},
+      "backup": {"image": "restic", "healthcheck": false, "restart": null}
+    }
+  },
+  "web": {
+    "name": "web",
+    "dockerCompose": "web.yml",
+    "services": {
+      "nginx": {"image": "nginx", "healthcheck": false, "restart": null}
+    }
+  }
+}
+"#,
+        )
+        .unwrap()
+    }
+
+    fn media_status() -> ProjectStatus {
+        ProjectStatus::from_json(
+            r#"{"ID":"1","Name":"media-jellyfin-1","Service":"jellyfin","Image":"jellyfin","State":"running","Health":"healthy"}
+{"ID":"2","Name":"media-sonarr-1","Service":"sonarr","Image":"sonarr","State":"running","Health":""}
+{"ID":"3","Name":"media-backup-1","Service":"backup","Image":"restic","State":"exited","ExitCode":1}"#,
+        )
+        .unwrap()
+    }
+
+    fn rendered(candidates: Vec<CompletionCandidate>) -> Vec<String> {
+        candidates
+            .into_iter()
+            .map(|candidate| {
+                let value = candidate
+                    .get_value()
+                    .to_string_lossy()
+                    .to_string();
+                match candidate.get_help() {
+                    Some(help) => format!("{value} ({help})"),
+                    None => value,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn only_running_services_are_offered_with_their_state() {
+        let statuses = BTreeMap::from([("media".to_string(), media_status())]);
+
+        assert_eq!(
+            rendered(running_service_candidates(&projects(), &statuses, "")),
+            [
+                "media.jellyfin (healthy)",
+                "media.sonarr (running)",
+                "web.nginx"
+            ]
+        );
+        assert_eq!(
+            rendered(running_service_candidates(
+                &projects(),
+                &statuses,
+                "media.s"
+            )),
+            ["media.sonarr (running)"]
+        );
+    }
+
+    #[test]
+    fn projects_without_status_fall_back_to_the_project_file() {
+        assert_eq!(
+            rendered(running_service_candidates(
+                &projects(),
+                &BTreeMap::new(),
+                "media."
+            )),
+            ["media.backup", "media.jellyfin", "media.sonarr"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_slow_project_does_not_drop_the_other_live_statuses() {
+        let dir = tempfile::tempdir().unwrap();
+        let docker = dir.path().join("docker");
+        std::fs::write(
+            &docker,
+            r#"case "$*" in
+*"--project-name web "*) sleep 5 ;;
+esac
+printf '%s\n' '{"ID":"1","Name":"media-sonarr-1","Service":"sonarr","Image":"sonarr","State":"running","Health":""}'
+"#,
+        )
+        .unwrap();
+        let projects = projects();
+
+        let statuses = live_statuses(
+            &DockerCommand::with_args("/bin/sh", [&docker]),
+            projects
+                .iter()
+                .map(|(name, project)| (name.to_string(), project))
+                .collect(),
+            Duration::from_millis(500),
+        )
+        .await;
+
+        let names = statuses
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["media"]);
+    }
+
+    #[test]
+    fn service_flag_completes_the_services_of_the_project_flag() {
+        assert_eq!(
+            rendered(service_flag_candidates(&projects(), Some("media"), "")),
+            ["backup", "jellyfin", "sonarr"]
+        );
+        assert_eq!(
+            rendered(service_flag_candidates(&projects(), None, "n")),
+            ["nginx"]
+        );
+
+        let args = ["nirion", "logs", "--project=web", "--service", ""]
+            .map(String::from);
+        assert_eq!(flag_value(&args, "--project"), Some("web"));
+        let args = ["nirion", "logs", "--project", "media"].map(String::from);
+        assert_eq!(flag_value(&args, "--project"), Some("media"));
+        assert_eq!(flag_value(&args, "--service"), None);
+    }
+}
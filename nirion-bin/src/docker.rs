@@ -3,10 +3,13 @@ use nirion_lib::{
     compose::{ComposeConcurrency, compose_stream},
     context::NirionContext,
     events::{ComposeEvent, ProcessEvent},
-    projects::TargetSelector,
+    exec::running_services,
+    projects::{ProjectSelector, TargetSelector, selected_project_names},
 };
 use nirion_tui_lib::color::Colorize;
 
+use crate::output::ComposeWarningFilter;
+
 pub async fn compose_target_cmd(
     context: &NirionContext,
     target: &TargetSelector,
@@ -21,22 +24,86 @@ pub async fn compose_target_cmd(
         ComposeConcurrency::sequential(),
     );
 
+    let mut first = true;
     while let Some(event) = stream.next().await {
-        render_compose_event(event?);
+        render_compose_event(event?, &mut first);
+    }
+
+    Ok(())
+}
+
+/// Like [`compose_target_cmd`], for commands such as `top` that only work
+/// on running containers. Every project gets a header, and projects
+/// without a running container for the target are noted instead of
+/// handed to compose, which would only fail on them.
+pub async fn compose_running_target_cmd(
+    context: &NirionContext,
+    target: &TargetSelector,
+    args: &[&str],
+) -> anyhow::Result<()> {
+    let running = running_services(context, target).await?;
+
+    let mut failed = vec![];
+    for (i, project) in selected_project_names(target, &context.projects)
+        .into_iter()
+        .enumerate()
+    {
+        print_project_header(&project, i == 0);
+        if !running
+            .iter()
+            .any(|service| service.project == project)
+        {
+            println!("{}", "(no containers)".dim());
+            continue;
+        }
+
+        let project_target = match target {
+            TargetSelector::Service(_) => target.clone(),
+            _ => TargetSelector::Project(ProjectSelector {
+                name: project.clone(),
+            }),
+        };
+        if let Err(error) =
+            compose_target_cmd(context, &project_target, args).await
+        {
+            eprintln!("{error:#}");
+            failed.push(project);
+        }
     }
 
+    if !failed.is_empty() {
+        anyhow::bail!(
+            "docker compose failed for {} project(s): {}",
+            failed.len(),
+            failed.join(", ")
+        );
+    }
     Ok(())
 }
 
-fn render_compose_event(event: ComposeEvent) {
+/// Separates the output of consecutive projects with a blank line.
+fn print_project_header(
+    project: &str,
+    first: bool,
+) {
+    if !first {
+        println!();
+    }
+    println!("[{}]", project.cyan().bold());
+}
+
+fn render_compose_event(
+    event: ComposeEvent,
+    first: &mut bool,
+) {
     match event {
         ComposeEvent::ProjectStarted { project } => {
-            println!("[{}]", project.cyan());
+            print_project_header(&project, *first);
+            *first = false;
         }
         ComposeEvent::Process { event, .. } => render_process_event(event),
         ComposeEvent::ProjectFailed { project, error } => {
             eprintln!("Project '{}' failed: {}", project, error);
-            println!();
         }
     }
 }
@@ -45,8 +112,8 @@ fn render_process_event(event: ProcessEvent) {
     match event {
         ProcessEvent::StdoutLine(line) => println!("{}", line),
         ProcessEvent::StderrLine(line) => {
-            if !line.contains("the attribute `version` is obsolete") {
-                println!("{}", line);
+            if !ComposeWarningFilter::get().hides(&line) {
+                eprintln!("{}", line);
             }
         }
         ProcessEvent::Exited(_) => {}
@@ -0,0 +1,164 @@
+use std::{fmt::Display, process::ExitStatus};
+
+use anyhow::Context;
+use nirion_tui_lib::terminal::TerminalModeGuard;
+use tokio::process::Command;
+
+/// A foreground child exited unsuccessfully; nirion exits with the same
+/// code instead of printing an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildExit {
+    pub code: i32,
+}
+
+impl Display for ChildExit {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "command exited with status {}", self.code)
+    }
+}
+
+impl std::error::Error for ChildExit {}
+
+impl ChildExit {
+    /// `Err` for an unsuccessful `status`. Children killed by a signal
+    /// report 128 plus the signal number, like a shell does.
+    pub fn check(status: ExitStatus) -> anyhow::Result<()> {
+        if status.success() {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        let code = status
+            .code()
+            .or(signal.map(|signal| 128 + signal))
+            .unwrap_or(1);
+        Err(ChildExit { code }.into())
+    }
+}
+
+/// Runs `command` attached to nirion's terminal and waits for it to exit.
+///
+/// Nirion outlives the child: Ctrl-C and terminal resizes already reach
+/// the child through the foreground process group, so nirion only stops
+/// reacting to them, while SIGTERM and SIGHUP sent to nirion alone are
+/// passed on. The terminal mode from before the child started is put back
+/// afterwards, even if the child left it in raw mode.
+pub async fn run_foreground(
+    command: &mut Command
+) -> anyhow::Result<ExitStatus> {
+    let _terminal = TerminalModeGuard::save();
+    // Caught from before the child starts, so that a signal in between
+    // can't kill nirion and leave the child running on a raw terminal.
+    #[cfg(unix)]
+    let (mut interrupt, mut terminate, mut hangup) = {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        (
+            signal(SignalKind::interrupt())?,
+            signal(SignalKind::terminate())?,
+            signal(SignalKind::hangup())?,
+        )
+    };
+    let mut child = command.spawn().with_context(|| {
+        format!(
+            "failed to execute {}",
+            command
+                .as_std()
+                .get_program()
+                .to_string_lossy()
+        )
+    })?;
+
+    #[cfg(unix)]
+    {
+        let forward = |child: &tokio::process::Child, signal: i32| {
+            if let Some(pid) = child.id() {
+                // SAFETY: kill has no memory effects; a pid that already
+                // exited only makes it fail.
+                unsafe {
+                    libc::kill(pid as libc::pid_t, signal);
+                }
+            }
+        };
+
+        loop {
+            tokio::select! {
+                status = child.wait() => return Ok(status?),
+                _ = interrupt.recv() => {}
+                _ = terminate.recv() => forward(&child, libc::SIGTERM),
+                _ = hangup.recv() => forward(&child, libc::SIGHUP),
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    Ok(child.wait().await?)
+}
+
+/// Resolves on the first Ctrl-C, SIGTERM or SIGHUP, for commands that
+/// should wind down their children instead of dying with them running.
+pub async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+            _ = hangup.recv() => {}
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    Ok(tokio::signal::ctrl_c().await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn child_exit_codes_are_passed_on() {
+        let status = run_foreground(Command::new("sh").args(["-c", "exit 7"]))
+            .await
+            .unwrap();
+        let error = ChildExit::check(status).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&ChildExit { code: 7 }));
+
+        let status =
+            run_foreground(Command::new("sh").args(["-c", "kill -TERM $$"]))
+                .await
+                .unwrap();
+        let error = ChildExit::check(status).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&ChildExit { code: 143 }));
+
+        let status = run_foreground(&mut Command::new("true"))
+            .await
+            .unwrap();
+        assert!(ChildExit::check(status).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn terminate_right_after_the_start_is_passed_on() {
+        // The child sends SIGTERM to nirion as its first act; nirion has
+        // to catch it and stop the child rather than die itself.
+        let status = run_foreground(
+            Command::new("sh").args(["-c", "kill -TERM $PPID; sleep 5"]),
+        )
+        .await
+        .unwrap();
+        let error = ChildExit::check(status).unwrap_err();
+        assert_eq!(error.downcast_ref(), Some(&ChildExit { code: 143 }));
+    }
+}
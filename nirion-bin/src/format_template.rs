@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+/// A field a template can access, named as in `docker ps --format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Service,
+    Project,
+    State,
+    Status,
+    Health,
+    Image,
+    Ports,
+    RunningFor,
+}
+
+impl Field {
+    pub const ALL: [Field; 9] = [
+        Field::Name,
+        Field::Service,
+        Field::Project,
+        Field::State,
+        Field::Status,
+        Field::Health,
+        Field::Image,
+        Field::Ports,
+        Field::RunningFor,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Field::Name => "Name",
+            Field::Service => "Service",
+            Field::Project => "Project",
+            Field::State => "State",
+            Field::Status => "Status",
+            Field::Health => "Health",
+            Field::Image => "Image",
+            Field::Ports => "Ports",
+            Field::RunningFor => "RunningFor",
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+    /// `{{json .}}`: every field as one JSON object.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatTemplate {
+    segments: Vec<Segment>,
+}
+
+impl FormatTemplate {
+    /// Parses `template`, e.g. `{{.Name}}\t{{.Status}}`. `\t` and `\n`
+    /// are read as a tab and a newline, as shells pass them on verbatim.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let template = template
+            .replace(r"\t", "\t")
+            .replace(r"\n", "\n");
+        let mut segments = Vec::new();
+        let mut rest = template.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(format!(
+                    "unclosed action in template: {}",
+                    &rest[start..]
+                ));
+            };
+            let action = &rest[start + 2..start + end];
+            segments.push(parse_action(action)?);
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// Fills in the template with the value `field` gives for each field.
+    pub fn render(
+        &self,
+        field: impl Fn(Field) -> String,
+    ) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Field(name) => field(*name),
+                Segment::Json => {
+                    let object = Field::ALL
+                        .iter()
+                        .map(|name| (name.to_string(), field(*name).into()))
+                        .collect::<serde_json::Map<_, _>>();
+                    serde_json::Value::Object(object).to_string()
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_action(action: &str) -> Result<Segment, String> {
+    let trimmed = action.trim();
+    let json = trimmed
+        .strip_prefix("json")
+        .is_some_and(|argument| {
+            argument.starts_with(char::is_whitespace) && argument.trim() == "."
+        });
+    if json {
+        return Ok(Segment::Json);
+    }
+
+    let Some(name) = trimmed
+        .strip_prefix('.')
+        .filter(|name| {
+            name.chars()
+                .all(|c| c.is_ascii_alphanumeric())
+        })
+    else {
+        return Err(format!(
+            "unsupported template action {{{{{action}}}}}; only field \
+             access like {{{{.Name}}}} and {{{{json .}}}} are supported"
+        ));
+    };
+
+    Field::ALL
+        .into_iter()
+        .find(|field| field.as_str() == name)
+        .map(Segment::Field)
+        .ok_or_else(|| {
+            let fields = Field::ALL
+                .into_iter()
+                .map(Field::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("unknown field .{name}; valid fields are {fields}")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(field: Field) -> String {
+        match field {
+            Field::Name => "myapp-web-1".to_string(),
+            Field::Status => "Up 2 minutes".to_string(),
+            Field::Health => String::new(),
+            field => field.as_str().to_lowercase(),
+        }
+    }
+
+    #[test]
+    fn fields_are_substituted() {
+        let template =
+            FormatTemplate::parse(r"{{.Name}}\t{{ .Status }} ({{.State}})")
+                .unwrap();
+        assert_eq!(template.render(value), "myapp-web-1\tUp 2 minutes (state)");
+    }
+
+    #[test]
+    fn json_renders_every_field() {
+        let template = FormatTemplate::parse("{{json .}}").unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&template.render(value)).unwrap();
+
+        assert_eq!(json["Name"], "myapp-web-1");
+        assert_eq!(json["Health"], "");
+        assert_eq!(json.as_object().unwrap().len(), Field::ALL.len());
+    }
+
+    #[test]
+    fn unknown_fields_and_actions_are_rejected() {
+        assert_eq!(
+            FormatTemplate::parse("{{.ID}}").unwrap_err(),
+            "unknown field .ID; valid fields are Name, Service, Project, \
+             State, Status, Health, Image, Ports, RunningFor"
+        );
+        assert!(
+            FormatTemplate::parse("{{if .Health}}x{{end}}")
+                .unwrap_err()
+                .starts_with("unsupported template action {{if .Health}}")
+        );
+        assert!(
+            FormatTemplate::parse("{{.Name")
+                .unwrap_err()
+                .starts_with("unclosed action")
+        );
+    }
+}
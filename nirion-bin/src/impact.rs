@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use nirion_lib::{
+    context::NirionContext,
+    docker::{ProjectStatus, ServiceState},
+    drift::{ImageDrift, image_drift},
+    projects::{ServiceImage, get_local_build_images, selected_project_names},
+};
+use nirion_tui_lib::{color::Colorize, table::format_table};
+
+use crate::TargetSelector;
+use crate::commands::LifecycleArgs;
+use crate::commands::ps::query_statuses;
+use crate::prompt::Confirm;
+
+/// The selected services of one project.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ProjectImpact {
+    services: usize,
+    /// Services with a healthy or running container.
+    healthy: Vec<String>,
+    /// Services whose containers run another digest than the lock pins,
+    /// which the command may move to the locked one.
+    lock_changes: Vec<String>,
+}
+
+/// Queries the selected projects all at once and compares them with the
+/// lock file, for [`format_impact`].
+async fn impact(
+    context: &NirionContext,
+    target: &TargetSelector,
+) -> anyhow::Result<BTreeMap<String, ProjectImpact>> {
+    let names = selected_project_names(target, &context.projects);
+    let statuses = query_statuses(context, &names).await?;
+    let builds = get_local_build_images(target, &context.projects)?
+        .iter()
+        .map(ServiceImage::service_ref)
+        .collect::<BTreeSet<_>>();
+    let drifted = image_drift(&context.locked_images, &statuses)
+        .into_iter()
+        .filter(|drift| matches!(drift.drift, ImageDrift::Differs { .. }))
+        .filter(|drift| !builds.contains(&drift.service))
+        .map(|drift| drift.service)
+        .collect::<BTreeSet<_>>();
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            let impact =
+                project_impact(context, target, &name, &statuses, &drifted);
+            (name, impact)
+        })
+        .collect())
+}
+
+fn project_impact(
+    context: &NirionContext,
+    target: &TargetSelector,
+    name: &str,
+    statuses: &BTreeMap<String, ProjectStatus>,
+    drifted: &BTreeSet<String>,
+) -> ProjectImpact {
+    let project = &context.projects[name];
+    let mut impact = ProjectImpact::default();
+
+    for (service_name, service) in &project.services {
+        if !target.selects_service(name, service_name)
+            || !project.service_enabled(service)
+        {
+            continue;
+        }
+        impact.services += 1;
+
+        let healthy = statuses
+            .get(name)
+            .into_iter()
+            .flat_map(|status| status.replicas(service_name))
+            .any(|replica| {
+                matches!(
+                    replica.state,
+                    ServiceState::Healthy | ServiceState::Running
+                )
+            });
+        if healthy {
+            impact
+                .healthy
+                .push(service_name.clone());
+        }
+        if drifted.contains(&format!("{name}.{service_name}")) {
+            impact
+                .lock_changes
+                .push(service_name.clone());
+        }
+    }
+    impact
+}
+
+/// `<command> <target> affects 2 projects, 5 services:` followed by a
+/// row per project with its healthy services and pending lock changes.
+fn format_impact(
+    command: &str,
+    target: &TargetSelector,
+    impact: &BTreeMap<String, ProjectImpact>,
+) -> String {
+    let services = impact
+        .values()
+        .map(|project| project.services)
+        .sum::<usize>();
+    let mut output = format!(
+        "{} {target} affects {}, {}:\n",
+        command.bold(),
+        plural(impact.len(), "project"),
+        plural(services, "service"),
+    );
+
+    let rows = impact
+        .iter()
+        .map(|(name, project)| {
+            let healthy = match project.healthy.as_slice() {
+                [] => "none healthy".grey().to_string(),
+                healthy => format!(
+                    "{} healthy ({})",
+                    healthy.len(),
+                    healthy.join(", ")
+                )
+                .green()
+                .to_string(),
+            };
+            let mut row = format!(
+                "  {}\t{}\t{healthy}",
+                name.as_str().cyan(),
+                plural(project.services, "service")
+            );
+            if !project.lock_changes.is_empty() {
+                let changes = format!(
+                    "{} ({})",
+                    plural(project.lock_changes.len(), "lock change"),
+                    project.lock_changes.join(", ")
+                );
+                row.push_str(&format!("\t{}", changes.yellow()));
+            }
+            row
+        })
+        .collect();
+    output.push_str(&format_table(rows));
+    output
+}
+
+fn plural(
+    count: usize,
+    noun: &str,
+) -> String {
+    if count == 1 {
+        format!("{count} {noun}")
+    } else {
+        format!("{count} {noun}s")
+    }
+}
+
+/// Prints what `command` is about to affect on stderr.
+pub(crate) async fn print_impact(
+    context: &NirionContext,
+    command: &str,
+    target: &TargetSelector,
+) -> anyhow::Result<()> {
+    let impact = impact(context, target).await?;
+    eprint!("{}", format_impact(command, target, &impact));
+    Ok(())
+}
+
+/// With `--preview`, prints what `command` is about to affect and asks
+/// before going ahead.
+pub(crate) async fn confirm_preview(
+    context: &NirionContext,
+    command: &str,
+    target: &TargetSelector,
+    lifecycle: &LifecycleArgs,
+) -> anyhow::Result<()> {
+    if !lifecycle.preview {
+        return Ok(());
+    }
+    confirm_impact(context, command, target).await
+}
+
+/// Prints what `command` is about to affect and asks before going ahead.
+pub(crate) async fn confirm_impact(
+    context: &NirionContext,
+    command: &str,
+    target: &TargetSelector,
+) -> anyhow::Result<()> {
+    print_impact(context, command, target).await?;
+    if !Confirm::new(format!("Run {command} {target}?"), false).ask()? {
+        anyhow::bail!("{command} aborted; nothing was changed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+
+    #[test]
+    fn format_impact_lists_healthy_services_and_lock_changes() {
+        let impact = BTreeMap::from([
+            (
+                "media".to_string(),
+                ProjectImpact {
+                    services: 3,
+                    healthy: vec!["jellyfin".into(), "sonarr".into()],
+                    lock_changes: vec!["jellyfin".into()],
+                },
+            ),
+            (
+                "web".to_string(),
+                ProjectImpact {
+                    services: 1,
+                    healthy: vec![],
+                    lock_changes: vec![],
+                },
+            ),
+        ]);
+
+        assert_eq!(
+            strip_ansi_codes(&format_impact(
+                "down",
+                &TargetSelector::All,
+                &impact
+            )),
+            "down * affects 2 projects, 4 services:\n  \
+             media  3 services  2 healthy (jellyfin, sonarr)  1 lock change (jellyfin)\n  \
+             web    1 service   none healthy\n"
+        );
+    }
+}
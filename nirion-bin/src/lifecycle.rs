@@ -2,15 +2,33 @@ use futures::{StreamExt, stream};
 use nirion_lib::{
     compose::{ComposeConcurrency, compose_stream},
     context::NirionContext,
-    docker::status_stream,
-    wait::{WaitTarget, wait_finished},
+    docker::{
+        ProjectStatus, ServiceState, ServiceStatus,
+        inspect_unhealthy_containers, query_project_status,
+    },
+    events::ProcessEvent,
+    history::{HistoryEntry, history_file, record_history},
+    hooks::{HookPhase, run_hook},
+    logs::tail_container_logs,
+    monitor::DockerMonitor,
+    projects::{Projects, selected_project_names},
+    state::state_dir,
+    textfile::{Gauge, write_textfile},
+    wait::{WaitTarget, project_stragglers, wait_finished},
 };
+use nirion_tui_lib::color::Colorize;
 use std::collections::BTreeMap;
+use std::path::Path;
 use tokio::time::Duration;
 
 use crate::TargetSelector;
+use crate::commands::{FailOn, LifecycleArgs, StartupArgs};
+use crate::output::OutputOptions;
 use crate::progress::{ProgressExit, run_progress};
-use crate::progress_render::{ProgressPresentation, progress_renderer};
+use crate::progress_render::{
+    ProgressPresentation, ProgressRenderer, StatusProgressRenderer,
+    progress_renderer,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct LifecycleOptions {
@@ -20,12 +38,212 @@ pub struct LifecycleOptions {
     pub wait: WaitTarget,
 }
 
+/// Appends the finished command to `nirion history`. A history that
+/// can't be written only warns, as the command itself already ran.
+pub fn record_lifecycle_history(
+    command: &str,
+    target: &TargetSelector,
+    projects: &Projects,
+    result: &anyhow::Result<()>,
+) {
+    let error = result
+        .as_ref()
+        .err()
+        .map(|error| format!("{error:#}"));
+    let entry = HistoryEntry::now(command, target, projects, error);
+    let recorded =
+        state_dir().and_then(|dir| record_history(&history_file(&dir), &entry));
+    if let Err(error) = recorded {
+        eprintln!(
+            "{} failed to record history: {error:#}",
+            "warning:".yellow()
+        );
+    }
+}
+
+/// Writes `gauges` to the textfile in `dir`. Failing to do so doesn't
+/// fail the command that produced them.
+pub fn write_textfile_metrics(
+    dir: &Path,
+    gauges: &[Gauge],
+) {
+    if let Err(error) = write_textfile(dir, gauges) {
+        eprintln!("{} failed to write metrics: {error:#}", "warning:".yellow());
+    }
+}
+
+/// Runs the `phase` hooks of the projects `target` selects, printing
+/// their output under the project's name. Hooks belong to whole
+/// projects, so a single service runs none. The first failing hook
+/// fails the phase unless it is marked `ignoreFailure`.
+pub async fn run_project_hooks(
+    context: &NirionContext,
+    target: &TargetSelector,
+    phase: HookPhase,
+) -> anyhow::Result<()> {
+    if matches!(target, TargetSelector::Service(_)) {
+        return Ok(());
+    }
+
+    let quiet = OutputOptions::get().quiet;
+    for project_name in selected_project_names(target, &context.projects) {
+        let Some(project) = context.projects.get(&project_name) else {
+            continue;
+        };
+        let hooks = project.hooks.get(phase);
+        if hooks.is_empty() {
+            continue;
+        }
+
+        if !quiet {
+            println!(
+                "[{}] {}",
+                project_name.as_str().cyan(),
+                phase.as_str().grey()
+            );
+        }
+        for hook in hooks {
+            let result = run_hook(project, hook, |event| match event {
+                ProcessEvent::StdoutLine(line) if !quiet => println!("{line}"),
+                ProcessEvent::StderrLine(line) => eprintln!("{line}"),
+                _ => {}
+            })
+            .await;
+            match result {
+                Ok(()) => {}
+                Err(error) if hook.ignore_failure => eprintln!(
+                    "{} [{project_name}] ignoring failed {phase} hook: {error:#}",
+                    "warning:".yellow()
+                ),
+                Err(error) => {
+                    return Err(error.context(format!(
+                        "{phase} hook of {project_name} failed"
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn run_lifecycle_command(
     context: &NirionContext,
     target: &TargetSelector,
     args: &[&str],
     options: LifecycleOptions,
 ) -> anyhow::Result<()> {
+    run_lifecycle_command_with_statuses(context, target, args, options)
+        .await
+        .map(|_| ())
+}
+
+/// Like [`run_lifecycle_command`], but also returns the last status
+/// seen for each project. Projects are only queried when the
+/// presentation or the wait target needs their status.
+pub async fn run_lifecycle_command_with_statuses(
+    context: &NirionContext,
+    target: &TargetSelector,
+    args: &[&str],
+    options: LifecycleOptions,
+) -> anyhow::Result<BTreeMap<String, ProjectStatus>> {
+    let renderer = progress_renderer(options.presentation);
+    run_with_renderer(context, target, args, options, renderer).await
+}
+
+/// Runs a compose command that brings containers up, such as `up -d` or
+/// `start`, waiting for healthchecks unless skipped and then reporting
+/// declared services that got no container.
+pub async fn run_startup_command(
+    context: &NirionContext,
+    target: &TargetSelector,
+    args: &[&str],
+    lifecycle: &LifecycleArgs,
+    startup: &StartupArgs,
+) -> anyhow::Result<()> {
+    let statuses = run_lifecycle_command_with_statuses(
+        context,
+        target,
+        args,
+        lifecycle.options(startup.wait_target()),
+    )
+    .await?;
+
+    if startup.wait_target() == WaitTarget::Healthy {
+        report_stragglers(context, target, &statuses);
+    }
+
+    if startup.failure_logs > 0 {
+        report_failed_containers(
+            context,
+            target,
+            &statuses,
+            startup.failure_logs,
+        )
+        .await;
+    }
+
+    let fail_on_missing = startup.fails_on(FailOn::Missing);
+    let missing =
+        report_missing_services(context, target, statuses, fail_on_missing)
+            .await?;
+    if missing > 0 && fail_on_missing {
+        anyhow::bail!(
+            "{missing} declared service{} never got a container",
+            if missing == 1 { "" } else { "s" }
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs a compose command that takes containers down, such as `stop` or
+/// `down`, until none of the selected containers are running.
+pub async fn run_shutdown_command(
+    context: &NirionContext,
+    target: &TargetSelector,
+    args: &[&str],
+    lifecycle: &LifecycleArgs,
+) -> anyhow::Result<()> {
+    run_lifecycle_command(
+        context,
+        target,
+        args,
+        lifecycle.options(WaitTarget::Stopped),
+    )
+    .await
+}
+
+/// Pulls the images of every selected project, keeping the per-project
+/// pull progress visible even for projects that already have containers.
+pub async fn run_pull_phase(
+    context: &NirionContext,
+    target: &TargetSelector,
+    options: LifecycleOptions,
+) -> anyhow::Result<()> {
+    let options = LifecycleOptions {
+        wait: WaitTarget::NoWait,
+        ..options
+    };
+    let renderer: Box<dyn ProgressRenderer> = match options.presentation {
+        ProgressPresentation::Progress => {
+            Box::new(StatusProgressRenderer::with_spinner().pulling())
+        }
+        presentation => progress_renderer(presentation),
+    };
+
+    run_with_renderer(context, target, &["pull"], options, renderer)
+        .await
+        .map(|_| ())
+}
+
+async fn run_with_renderer(
+    context: &NirionContext,
+    target: &TargetSelector,
+    args: &[&str],
+    options: LifecycleOptions,
+    renderer: Box<dyn ProgressRenderer>,
+) -> anyhow::Result<BTreeMap<String, ProjectStatus>> {
     let args = args
         .iter()
         .map(|arg| arg.to_string())
@@ -37,33 +255,291 @@ pub async fn run_lifecycle_command(
         ComposeConcurrency::Jobs(options.jobs),
     );
 
-    let renderer = progress_renderer(options.presentation);
-
     let needs_status = renderer.needs_status_during_compose()
-        || (options.wait == WaitTarget::Healthchecks
+        || (matches!(options.wait, WaitTarget::Healthy | WaitTarget::Stopped)
             && !wait_finished(
                 target,
                 &context.projects,
                 &BTreeMap::new(),
-                WaitTarget::Healthchecks,
+                options.wait,
             ));
     let status_events = if needs_status {
-        status_stream(context, target.clone(), options.refresh_interval)
+        DockerMonitor::builder(options.refresh_interval)
+            .spawn(context, target)
+            .into_events()
     } else {
         stream::pending().boxed()
     };
 
-    match run_progress(
+    let outcome = run_progress(
         context,
         target,
         compose_events,
         status_events,
         renderer,
         options.wait,
+        options.refresh_interval,
     )
-    .await?
-    {
-        ProgressExit::Completed => Ok(()),
+    .await?;
+
+    match outcome.exit {
+        ProgressExit::Completed => Ok(outcome.statuses),
         ProgressExit::Cancelled => Err(anyhow::anyhow!("interrupted")),
     }
 }
+
+/// Prints the last `lines` lines of output of every selected container
+/// that exited with an error or is unhealthy, plus its latest
+/// healthcheck result if it has one.
+async fn report_failed_containers(
+    context: &NirionContext,
+    target: &TargetSelector,
+    statuses: &BTreeMap<String, ProjectStatus>,
+    lines: usize,
+) {
+    for (project, status) in statuses {
+        let mut status = status.clone();
+        if status.containers().any(|container| {
+            container.state == ServiceState::Unhealthy
+                && container.last_health_check.is_none()
+        }) {
+            inspect_unhealthy_containers(&context.docker_command, &mut status)
+                .await
+                .ok();
+        }
+
+        for container in status.containers() {
+            if !matches!(
+                container.state,
+                ServiceState::Failed | ServiceState::Unhealthy
+            ) {
+                continue;
+            }
+            if matches!(target, TargetSelector::Service(selector)
+                if selector.service != container.service)
+            {
+                continue;
+            }
+
+            let logs = tail_container_logs(context, &container.id, lines)
+                .await
+                .unwrap_or_else(|error| vec![format!("({error:#})")]);
+            eprintln!("{}", format_failed_container(project, container, &logs));
+        }
+    }
+}
+
+fn format_failed_container(
+    project: &str,
+    container: &ServiceStatus,
+    logs: &[String],
+) -> String {
+    let state = match (&container.state, container.exit_code) {
+        (ServiceState::Failed, Some(code)) => {
+            format!("exited with code {code}")
+        }
+        (state, _) => state.as_str().to_string(),
+    };
+    let mut message = format!(
+        "{} [{project}] {} {}",
+        "✗".red(),
+        container.container_name,
+        state.red()
+    );
+    for line in logs {
+        message.push_str(&format!("\n    {line}"));
+    }
+    if let Some(health) = &container.last_health_check {
+        message.push_str(&format!(
+            "\n  {}",
+            format!("last healthcheck (exit {}):", health.exit_code).yellow()
+        ));
+        for line in health.output.lines() {
+            message.push_str(&format!("\n    {line}"));
+        }
+    }
+    message
+}
+
+/// Warns about each project that settled with services failing their
+/// healthcheck or crash-looping, which the healthy wait stopped waiting on.
+fn report_stragglers(
+    context: &NirionContext,
+    target: &TargetSelector,
+    statuses: &BTreeMap<String, ProjectStatus>,
+) {
+    for project_name in selected_project_names(target, &context.projects) {
+        let stragglers = project_stragglers(
+            target,
+            &project_name,
+            &context.projects,
+            statuses,
+        );
+        if !stragglers.is_empty() {
+            eprintln!("{}", format_stragglers(&project_name, &stragglers));
+        }
+    }
+}
+
+fn format_stragglers(
+    project: &str,
+    stragglers: &[(String, ServiceState)],
+) -> String {
+    let services = stragglers
+        .iter()
+        .map(|(service, state)| format!("{service} ({})", state.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{} [{project}] settled without passing healthchecks: {services}",
+        "warning:".yellow()
+    )
+}
+
+/// Warns about every selected service that has no container after startup
+/// and returns how many there are. Only projects with a known status are
+/// checked, unless `query_unknown` asks to query the others.
+async fn report_missing_services(
+    context: &NirionContext,
+    target: &TargetSelector,
+    mut statuses: BTreeMap<String, ProjectStatus>,
+    query_unknown: bool,
+) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for project_name in selected_project_names(target, &context.projects) {
+        let Some(project) = context.projects.get(&project_name) else {
+            continue;
+        };
+        let status = match statuses.remove(&project_name) {
+            Some(status) => status,
+            None if query_unknown => {
+                query_project_status(context, &project_name).await?
+            }
+            None => continue,
+        };
+
+        let mut missing = status.missing_services(project);
+        if let TargetSelector::Service(selector) = target {
+            missing.retain(|service| *service == selector.service);
+        }
+        if missing.is_empty() {
+            continue;
+        }
+
+        count += missing.len();
+        eprintln!(
+            "{}",
+            format_missing_services(
+                &project_name,
+                &missing,
+                &status.undeclared_services(project)
+            )
+        );
+    }
+
+    Ok(count)
+}
+
+fn format_missing_services(
+    project: &str,
+    missing: &[&str],
+    undeclared: &[&str],
+) -> String {
+    let mut message = format!(
+        "{} [{project}] declared but not created: {}\n  \
+         → are they behind a compose profile that isn't enabled?",
+        "warning:".yellow(),
+        missing.join(", ")
+    );
+    if !undeclared.is_empty() {
+        message.push_str(&format!(
+            "\n  → compose created {} instead; service names in the \
+             compose file must match the nirion project file",
+            undeclared.join(", ")
+        ));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+
+    #[test]
+    fn stragglers_are_listed_with_their_state() {
+        let stragglers = [
+            ("jellyfin".to_string(), ServiceState::Unhealthy),
+            ("sonarr".to_string(), ServiceState::Unhealthy),
+        ];
+
+        assert_eq!(
+            strip_ansi_codes(&format_stragglers("media", &stragglers)),
+            "warning: [media] settled without passing healthchecks: \
+             jellyfin (unhealthy), sonarr (unhealthy)"
+        );
+    }
+
+    #[test]
+    fn failed_containers_show_their_logs_and_last_healthcheck() {
+        let container: ServiceStatus =
+            serde_json::from_value(serde_json::json!({
+                "id": "abc",
+                "service": "web",
+                "container_name": "media-web-1",
+                "index": 1,
+                "image": "nginx",
+                "state": "unhealthy",
+                "health": "unhealthy",
+                "exit_code": null,
+                "running_for": null,
+                "status": null,
+                "ports": [],
+                "networks": [],
+                "last_health_check": {
+                    "exit_code": 1,
+                    "output": "curl: (7) connection refused"
+                }
+            }))
+            .unwrap();
+
+        let message = format_failed_container(
+            "media",
+            &container,
+            &["listening on :81".to_string()],
+        );
+
+        assert_eq!(
+            strip_ansi_codes(&message),
+            "✗ [media] media-web-1 unhealthy\n    listening on :81\n  \
+             last healthcheck (exit 1):\n    curl: (7) connection refused"
+        );
+
+        let exited = ServiceStatus {
+            state: ServiceState::Failed,
+            exit_code: Some(2),
+            last_health_check: None,
+            ..container
+        };
+        assert_eq!(
+            strip_ansi_codes(&format_failed_container("media", &exited, &[])),
+            "✗ [media] media-web-1 exited with code 2"
+        );
+    }
+
+    #[test]
+    fn missing_services_suggest_profiles_and_name_mismatches() {
+        let message = format_missing_services("media", &["sonarr"], &[]);
+        assert_eq!(
+            strip_ansi_codes(&message),
+            "warning: [media] declared but not created: sonarr\n  \
+             → are they behind a compose profile that isn't enabled?"
+        );
+
+        let message =
+            format_missing_services("media", &["web", "db"], &["webapp"]);
+        let message = strip_ansi_codes(&message);
+        assert!(message.contains("declared but not created: web, db"));
+        assert!(message.contains("compose created webapp instead"));
+    }
+}
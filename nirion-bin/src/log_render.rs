@@ -1,7 +1,10 @@
-use std::io::{Write, stderr, stdout};
+use std::{
+    collections::BTreeMap,
+    io::{Write, stderr, stdout},
+};
 
-use nirion_lib::logs::{LogEvent, LogLine, LogSource};
-use nirion_tui_lib::color::Colorize;
+use nirion_lib::logs::{LogEvent, LogLine, LogSource, MergedLine};
+use nirion_tui_lib::color::{Color, Colorize};
 
 use crate::commands::logs::{LogEventsMode, LogLabelFormat};
 
@@ -11,6 +14,17 @@ pub struct LogRenderer {
     follow: bool,
 }
 
+/// Label colors of the services in a merged stream, in order of their
+/// first line.
+const MERGED_COLORS: &[Color] = &[
+    Color::Cyan,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::Yellow,
+    Color::Red,
+];
+
 enum LogLabelColor {
     Stdout,
     Stderr,
@@ -96,6 +110,43 @@ impl LogRenderer {
         Ok(())
     }
 
+    /// Writes the lines of a merged stream, each service's label in a
+    /// color of its own when `color` is set. Timestamps are only kept
+    /// with `timestamps`.
+    pub fn write_merged(
+        &self,
+        out: &mut impl Write,
+        lines: &[MergedLine],
+        timestamps: bool,
+        color: bool,
+    ) -> anyhow::Result<()> {
+        let mut colors = BTreeMap::new();
+        for line in lines {
+            let text = match line.timestamp.filter(|_| timestamps) {
+                Some(timestamp) => {
+                    format!("{} {}", timestamp.to_rfc3339(), line.text)
+                }
+                None => line.text.clone(),
+            };
+            let Some(label) = self.format_label(&line.source) else {
+                writeln!(out, "{text}")?;
+                continue;
+            };
+
+            let label = if color {
+                let next = MERGED_COLORS[colors.len() % MERGED_COLORS.len()];
+                let label_color = *colors
+                    .entry(label.clone())
+                    .or_insert(next);
+                label.fg(label_color).to_string()
+            } else {
+                label
+            };
+            writeln!(out, "[{label}] {text}")?;
+        }
+        Ok(())
+    }
+
     fn show_events(&self) -> bool {
         match self.events {
             LogEventsMode::Auto => self.follow,
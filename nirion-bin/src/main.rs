@@ -1,33 +1,70 @@
-use crate::commands::{Commands, handle_command};
-use clap::{CommandFactory, Parser};
+use crate::banner::EffectiveConfig;
+use crate::commands::maintenance::exclude_projects_in_maintenance;
+use crate::commands::{Commands, handle_command, needs_project_file};
+use crate::foreground::ChildExit;
+use crate::lifecycle::record_lifecycle_history;
+use crate::output::{ComposeWarningFilter, OutputOptions};
+use crate::status_display::warn_unrecognized_states;
+use crate::validate::{check_image_mismatches, warn_service_mismatches};
+use anyhow::Context;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use clap_complete::{ArgValueCompleter, CompletionCandidate};
+use nirion_lib::compose_file::check_compose_files;
 use nirion_lib::config::{
-    build_nix_project_file, load_auth_config, load_locked_images,
-    load_projects, nix_config_target,
+    ProjectSource, load_auth_config, load_projects, nix_config_target,
 };
 use nirion_lib::context::NirionContext;
-use nirion_lib::docker::DockerCommand;
+use nirion_lib::daemon::{DAEMON_PROBE_TIMEOUT, probe_daemon};
+use nirion_lib::docker::{DockerCommand, show_helper_containers};
+use nirion_lib::keyring::{Keyring, merge_keyring_entries};
 use nirion_lib::lock::LockedImages;
+use nirion_lib::lock_store::LockStore;
 use nirion_lib::projects::{
-    Projects, ServiceSelector, TargetSelector, parse_selector,
-    parse_service_selector,
+    ProjectNames, Projects, ServiceSelector, TargetSelector, get_images,
+    parse_selector, parse_service_selector, selected_project_names,
 };
 use nirion_oci_lib::client::NirionOciClient;
-use nirion_tui_lib::color::Colorize;
+use nirion_oci_lib::http::HttpConfig;
+use nirion_tui_lib::color::{Colorize, disable_colors};
+use std::io::Read;
 use std::sync::{Arc, OnceLock};
 use std::{ffi::OsString, path::PathBuf};
 
+mod banner;
 mod commands;
+mod completion;
 mod docker;
+mod foreground;
+mod format_template;
 mod health_render;
+mod impact;
 mod lifecycle;
 mod log_render;
+mod monitor_view;
+mod output;
 mod progress;
 mod progress_render;
+mod prompt;
+mod stats_render;
 mod status_display;
+mod update_progress;
+mod updates_render;
+mod validate;
 
 pub static PROJECTS: OnceLock<Projects> = OnceLock::new();
 
+/// Where [`PROJECTS`] were loaded from, for loading them again as of an
+/// older commit.
+pub static PROJECT_SOURCE: OnceLock<ProjectSource> = OnceLock::new();
+
+/// Selectors only parse once the project file is loaded; before that,
+/// [`main`] only looks for commands that work without one.
+fn loaded_projects() -> Result<&'static Projects, String> {
+    PROJECTS
+        .get()
+        .ok_or_else(|| "projects are not loaded yet".to_string())
+}
+
 pub trait ClapSelector {
     fn clap_parse(s: &str) -> Result<Self, String>
     where
@@ -38,13 +75,7 @@ pub trait ClapSelector {
 
 impl ClapSelector for TargetSelector {
     fn clap_parse(s: &str) -> Result<Self, String> {
-        parse_selector(
-            s,
-            PROJECTS
-                .get()
-                .expect("PROJECTS not initialized"),
-        )
-        .map_err(|e| e.to_string())
+        parse_selector(s, loaded_projects()?).map_err(|e| e.to_string())
     }
 
     fn clap_completer() -> ArgValueCompleter {
@@ -59,9 +90,8 @@ pub fn target_selector_completer(
 
     let projects = tokio::task::block_in_place(|| {
         tokio::runtime::Handle::current()
-            .block_on(core_cli.files.get_projects())
-    })
-    .unwrap_or_default();
+            .block_on(core_cli.files.completion_projects())
+    });
 
     let mut completions = vec![];
 
@@ -114,13 +144,7 @@ pub fn target_selector_completer(
 
 impl ClapSelector for ServiceSelector {
     fn clap_parse(s: &str) -> Result<Self, String> {
-        parse_service_selector(
-            s,
-            PROJECTS
-                .get()
-                .expect("PROJECTS not initialized"),
-        )
-        .map_err(|e| e.to_string())
+        parse_service_selector(s, loaded_projects()?).map_err(|e| e.to_string())
     }
 
     fn clap_completer() -> ArgValueCompleter {
@@ -135,9 +159,8 @@ pub fn service_selector_completer(
 
     let projects = tokio::task::block_in_place(|| {
         tokio::runtime::Handle::current()
-            .block_on(core_cli.files.get_projects())
-    })
-    .unwrap_or_default();
+            .block_on(core_cli.files.completion_projects())
+    });
 
     let mut completions = vec![];
 
@@ -205,10 +228,25 @@ struct FileCli {
     #[arg(long, env = "NIRION_LOCK_FILE", hide_env_values = true)]
     lock_file: Option<PathBuf>,
 
-    /// Path to the project file
+    /// Directory with one lock file per project, used instead of a
+    /// single lock file
+    #[arg(
+        long,
+        env = "NIRION_LOCK_DIR",
+        hide_env_values = true,
+        conflicts_with = "lock_file"
+    )]
+    lock_dir: Option<PathBuf>,
+
+    /// Path to the project file, or - to read it from stdin
     #[arg(long, env = "NIRION_PROJECT_FILE", hide_env_values = true)]
     project_file: Option<PathBuf>,
 
+    /// Lowercase and strip compose project names docker compose would
+    /// reject, instead of refusing the project file
+    #[arg(long, env = "NIRION_NORMALIZE_PROJECT_NAMES")]
+    normalize_project_names: bool,
+
     /// Evaluate a nix target to build the project file
     #[arg(long, conflicts_with = "project_file")]
     nix_eval: bool,
@@ -233,9 +271,11 @@ struct FileCli {
 }
 
 impl FileCli {
-    async fn get_lock_file(&self) -> anyhow::Result<PathBuf> {
-        if let Some(file) = &self.lock_file {
-            Ok(file.clone())
+    async fn get_lock_store(&self) -> anyhow::Result<LockStore> {
+        if let Some(dir) = &self.lock_dir {
+            Ok(LockStore::Dir(dir.clone()))
+        } else if let Some(file) = &self.lock_file {
+            Ok(LockStore::File(file.clone()))
         } else {
             anyhow::bail!(
                 "{}\n\n{}",
@@ -245,17 +285,11 @@ impl FileCli {
         }
     }
 
-    async fn get_locked_images(&self) -> anyhow::Result<LockedImages> {
-        let lock_file = self.get_lock_file().await?;
-        load_locked_images(&lock_file)
-    }
-
-    async fn get_project_file(&self) -> anyhow::Result<PathBuf> {
+    fn project_source(&self) -> anyhow::Result<ProjectSource> {
         if self.nix_eval {
             let nix_eval_target = self
                 .nix_target
-                .as_ref()
-                .map(&String::as_str)
+                .as_deref()
                 .map(nix_config_target)
                 .or_else(|| {
                     self.raw_nix_target
@@ -263,9 +297,12 @@ impl FileCli {
                         .map(|t| t.to_string())
                 })
                 .ok_or_else(|| anyhow::anyhow!("No nix target specified"))?;
-            build_nix_project_file(&nix_eval_target).await
+            Ok(ProjectSource::Nix(nix_eval_target))
         } else if let Some(project_file) = &self.project_file {
-            Ok(project_file.clone())
+            if project_file.as_os_str() == "-" {
+                return Ok(ProjectSource::Stdin);
+            }
+            Ok(ProjectSource::File(project_file.clone()))
         } else {
             anyhow::bail!(
                 "{}\n\n{}",
@@ -275,21 +312,195 @@ impl FileCli {
         }
     }
 
-    async fn get_projects(&self) -> anyhow::Result<Projects> {
-        let project_file = self.get_project_file().await?;
-        load_projects(&project_file)
+    /// The projects, and the file they were read from unless piped in.
+    async fn get_projects(
+        &self
+    ) -> anyhow::Result<(Projects, Option<PathBuf>)> {
+        let names = if self.normalize_project_names {
+            ProjectNames::Normalize
+        } else {
+            ProjectNames::Reject
+        };
+        match self.project_source()? {
+            ProjectSource::Stdin => {
+                let mut json = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut json)
+                    .context("Failed to read projects file from stdin")?;
+                let projects = Projects::from_json(&json, names)
+                    .context("Failed to parse projects file")?;
+                Ok((projects, None))
+            }
+            source => {
+                let file = source.project_file().await?;
+                Ok((load_projects(&file, names)?, Some(file)))
+            }
+        }
     }
+
+    /// The projects for shell completion, which must never wait on
+    /// stdin: none when they would be piped in, or can't be loaded.
+    async fn completion_projects(&self) -> Projects {
+        if matches!(self.project_source(), Ok(ProjectSource::Stdin)) {
+            return Projects::default();
+        }
+        self.get_projects()
+            .await
+            .map(|(projects, _)| projects)
+            .unwrap_or_default()
+    }
+}
+
+fn parse_default_target(value: &str) -> Result<(String, String), String> {
+    let (command, selector) = value
+        .split_once('=')
+        .filter(|(command, selector)| {
+            !command.is_empty() && !selector.is_empty()
+        })
+        .ok_or_else(|| format!("expected COMMAND=SELECTOR, got `{value}`"))?;
+    if Cli::command()
+        .find_subcommand(command)
+        .is_none()
+    {
+        return Err(format!("unknown command `{command}`"));
+    }
+    Ok((command.to_string(), selector.to_string()))
+}
+
+fn parse_registry_mirror(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .filter(|(upstream, mirror)| !upstream.is_empty() && !mirror.is_empty())
+        .map(|(upstream, mirror)| (upstream.to_string(), mirror.to_string()))
+        .ok_or_else(|| format!("expected REGISTRY=MIRROR, got `{value}`"))
 }
 
+const EXAMPLES: &str = "\
+Examples:
+  # Start every project, quoting * to keep it from the shell
+  nirion up '*'
+  # Restart a single service of the media project
+  nirion restart media.jellyfin
+  # The same selector spelled as flags
+  nirion restart --project media --service jellyfin
+  # Update the lock entries of every linuxserver image
+  nirion update --image 'lscr.io/linuxserver/*'";
+
+/// Manage Docker Compose projects declared with Nix, with their images
+/// pinned in a lock file
 #[derive(Parser)]
-#[command(name = "nirion")]
+#[command(name = "nirion", after_help = EXAMPLES)]
 struct Cli {
     #[command(flatten)]
     files: FileCli,
 
+    /// Registry credentials in docker's config.json format
     #[arg(long, env = "NIRION_AUTH_FILE", hide_env_values = true)]
     auth_file: Option<PathBuf>,
 
+    /// Additional PEM root certificate to trust for registries
+    #[arg(
+        long,
+        env = "NIRION_REGISTRY_CA",
+        value_name = "PEM",
+        value_delimiter = ','
+    )]
+    registry_ca: Vec<PathBuf>,
+
+    /// Registry (`host[:port]`) to talk to over plain HTTP
+    #[arg(long, visible_alias = "plain-http", value_name = "HOST")]
+    insecure_registry: Vec<String>,
+
+    /// Resolve images of a registry through a mirror, e.g.
+    /// `docker.io=mirror.lan`
+    #[arg(
+        long,
+        env = "NIRION_REGISTRY_MIRROR",
+        value_name = "REGISTRY=MIRROR",
+        value_delimiter = ',',
+        value_parser = parse_registry_mirror
+    )]
+    registry_mirror: Vec<(String, String)>,
+
+    /// Print only errors and results: no progress, headers or summaries
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print the project, lock and auth file in effect, where each came
+    /// from, and the docker versions on stderr before running the command
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// The target a command uses when run without one, e.g. `ps=media`;
+    /// repeatable
+    #[arg(
+        long,
+        global = true,
+        env = "NIRION_DEFAULT_TARGET",
+        value_name = "COMMAND=SELECTOR",
+        value_delimiter = ',',
+        value_parser = parse_default_target
+    )]
+    default_target: Vec<(String, String)>,
+
+    /// Make down, stop and restart require a target instead of falling
+    /// back to `*`
+    #[arg(long, global = true, env = "NIRION_STRICT_TARGETS")]
+    strict_targets: bool,
+
+    /// Fail instead of warning when a compose file runs another image
+    /// than the project file names, checked before up, lock and update
+    #[arg(long, global = true, env = "NIRION_STRICT_IMAGES")]
+    strict_images: bool,
+
+    /// Answer yes to every confirmation, e.g. before taking down all
+    /// projects
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Don't animate progress, but still print summaries
+    #[arg(long, global = true)]
+    no_progress: bool,
+
+    /// Print without colors; setting NO_COLOR does the same
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Print image digests in full instead of their first 12 characters;
+    /// JSON output always has them in full
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "no-trunc",
+        env = "NIRION_FULL_DIGESTS"
+    )]
+    full_digests: bool,
+
+    /// Pass every line compose prints to stderr through, including the
+    /// warnings hidden by default
+    #[arg(long, global = true)]
+    show_compose_warnings: bool,
+
+    /// Also hide compose stderr lines containing this text; repeatable
+    #[arg(
+        long,
+        global = true,
+        value_name = "TEXT",
+        env = "NIRION_HIDE_COMPOSE_WARNINGS",
+        value_delimiter = ','
+    )]
+    hide_compose_warning: Vec<String>,
+
+    /// Warn about services that are only declared in the project file or
+    /// only in the compose file before running the command
+    #[arg(long, global = true)]
+    validate: bool,
+
+    /// Include throwaway containers, nirion's own and those of `docker
+    /// compose run`, in statuses; they are hidden by default
+    #[arg(long, global = true)]
+    show_helpers: bool,
+
     #[arg(long, hide = true, value_name = "PROGRAM")]
     docker_command: Option<PathBuf>,
 
@@ -304,7 +515,30 @@ impl Cli {
     async fn get_auth(
         &self
     ) -> anyhow::Result<nirion_oci_lib::client::AuthConfig> {
-        load_auth_config(self.auth_file.as_deref())
+        let mut auth = load_auth_config(self.auth_file.as_deref())?;
+        if self.command.needs_registry_auth() {
+            match Keyring::default().entries().await {
+                Ok(Some(entries)) => merge_keyring_entries(&mut auth, entries),
+                Ok(None) => {}
+                Err(error) => eprintln!(
+                    "{} could not read registry credentials from the \
+                     keyring: {error:#}",
+                    "warning:".yellow()
+                ),
+            }
+        }
+        Ok(auth)
+    }
+
+    fn http_config(&self) -> anyhow::Result<HttpConfig> {
+        let mut http = HttpConfig::from_env();
+        for path in &self.registry_ca {
+            http.add_root_certificate_file(path)?;
+        }
+        for registry in &self.insecure_registry {
+            http.add_insecure_registry(registry.clone());
+        }
+        Ok(http)
     }
 
     fn docker_command(&self) -> DockerCommand {
@@ -320,45 +554,214 @@ impl Cli {
     }
 }
 
+/// Where the projects came from, for messages about the project file.
+fn describe_project_source(source: Option<&ProjectSource>) -> String {
+    match source {
+        Some(ProjectSource::File(path)) => path.display().to_string(),
+        Some(ProjectSource::Nix(target)) => {
+            format!("the project file built from {target}")
+        }
+        Some(ProjectSource::Stdin) | None => {
+            "the project file read from stdin".to_string()
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    clap_complete::CompleteEnv::with_factory(|| Cli::command()).complete();
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
 
-    let core_cli = CoreCli::parse();
-
-    let lock_file = core_cli.files.get_lock_file().await?;
-    let locked_images = core_cli
-        .files
-        .get_locked_images()
-        .await?;
+    let core_matches = CoreCli::command().get_matches();
+    let core_cli =
+        CoreCli::from_arg_matches(&core_matches).unwrap_or_else(|e| e.exit());
+    let mut args = core_cli.args;
+    args.insert(0, Cli::command().get_name().to_string());
 
-    let projects = core_cli.files.get_projects().await?;
+    // Selectors can't parse before the projects are loaded, so errors are
+    // ignored while looking for the subcommand.
+    let subcommand = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+        .ok()
+        .and_then(|matches| {
+            matches
+                .subcommand_name()
+                .map(str::to_string)
+        });
+    let (projects, project_file) = if needs_project_file(subcommand.as_deref())
+    {
+        let loaded = core_cli.files.get_projects().await?;
+        PROJECT_SOURCE
+            .set(core_cli.files.project_source()?)
+            .map_err(|_| {
+                anyhow::anyhow!("PROJECT_SOURCE already initialized")
+            })?;
+        loaded
+    } else {
+        (Projects::default(), None)
+    };
+    for (key, original) in projects.renamed() {
+        eprintln!(
+            "{} compose project name '{original}' normalized to '{}'; \
+             select the project as '{key}'",
+            "warning:".yellow(),
+            projects[key].name
+        );
+    }
 
     PROJECTS
         .set(projects.clone())
         .map_err(|_| anyhow::anyhow!("PROJECTS already initialized"))?;
 
-    let mut args = core_cli.args;
-    args.insert(0, Cli::command().get_name().to_string());
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let defaulted = cli.command.apply_default_target(
+        &matches,
+        &cli.default_target,
+        cli.strict_targets,
+        &projects,
+    );
+    if let Err(error) = defaulted.and_then(|()| {
+        cli.command
+            .apply_selector_flags(&projects)
+    }) {
+        Cli::command()
+            .error(clap::error::ErrorKind::ValueValidation, error)
+            .exit();
+    }
 
-    let cli = Cli::parse_from(args);
+    let lock_store = if cli.command.needs_lock_file() {
+        Some(core_cli.files.get_lock_store().await?)
+    } else if cli.command.reads_lock_file() {
+        core_cli
+            .files
+            .get_lock_store()
+            .await
+            .ok()
+    } else {
+        None
+    };
+    if cli.verbose {
+        EffectiveConfig {
+            file_matches: &core_matches,
+            matches: &matches,
+            project_source: PROJECT_SOURCE.get(),
+            project_file: project_file.as_deref(),
+            lock_store: lock_store
+                .clone()
+                .or(core_cli
+                    .files
+                    .get_lock_store()
+                    .await
+                    .ok())
+                .as_ref(),
+            reads_lock: lock_store.is_some(),
+            auth_file: cli.auth_file.as_deref(),
+            projects: &projects,
+            docker_command: &cli.docker_command(),
+        }
+        .print()
+        .await;
+    }
+
+    let locked_images = match &lock_store {
+        Some(lock_store) => {
+            let mut locked_images = match lock_store.load() {
+                Err(_) if cli.command.tolerates_broken_lock_file() => {
+                    LockedImages::default()
+                }
+                loaded => loaded?,
+            };
+            locked_images.fill_missing_images(&get_images(
+                &TargetSelector::All,
+                &projects,
+            )?);
+            locked_images
+        }
+        None => LockedImages::default(),
+    };
+
+    if cli.no_color {
+        disable_colors();
+    }
+    OutputOptions {
+        quiet: cli.quiet,
+        no_progress: cli.no_progress,
+        full_digests: cli.full_digests,
+    }
+    .init()?;
+    prompt::assume_yes(cli.yes)?;
+    show_helper_containers(cli.show_helpers);
+    ComposeWarningFilter::new(
+        cli.show_compose_warnings,
+        &cli.hide_compose_warning,
+    )
+    .init()?;
 
     let auth = cli.get_auth().await?;
-    let oci_client = Arc::new(
-        NirionOciClient::builder()
-            .auth(auth)
-            .build(),
-    );
+    let mut oci_client = NirionOciClient::builder()
+        .auth(auth)
+        .http(&cli.http_config()?)?;
+    for (upstream, mirror) in &cli.registry_mirror {
+        oci_client = oci_client.mirror(upstream, mirror);
+    }
+    let oci_client = Arc::new(oci_client.build());
 
     let context = NirionContext {
         projects,
         locked_images,
-        lock_file,
+        lock_store,
         oci_client,
         docker_command: cli.docker_command(),
     };
+    let context = match cli.command.maintenance_target() {
+        Some((command, target)) => {
+            exclude_projects_in_maintenance(command, target, context)?
+        }
+        None => context,
+    };
 
-    handle_command(&cli.command, &context).await?;
+    if context.projects.is_empty() && cli.command.stops_without_projects() {
+        if !cli.quiet {
+            eprintln!(
+                "No projects defined in {}",
+                describe_project_source(PROJECT_SOURCE.get())
+            );
+        }
+        return Ok(());
+    }
+
+    if cli.validate {
+        warn_service_mismatches(&context.projects);
+    }
+    check_compose_files(
+        &context.projects,
+        &cli.command
+            .compose_projects(&context.projects),
+    )?;
+    if let Some(target) = cli.command.image_check_target() {
+        check_image_mismatches(
+            &context.projects,
+            &selected_project_names(target, &context.projects),
+            cli.strict_images,
+        )?;
+    }
+
+    if cli.command.needs_daemon() {
+        probe_daemon(&context.docker_command, DAEMON_PROBE_TIMEOUT).await?;
+    }
+
+    let result = handle_command(&cli.command, &context).await;
+    warn_unrecognized_states();
+    if let Some((command, target)) = cli.command.history_target() {
+        record_lifecycle_history(command, target, &context.projects, &result);
+    }
+    if let Err(error) = result {
+        if let Some(exit) = error.downcast_ref::<ChildExit>() {
+            std::process::exit(exit.code);
+        }
+        return Err(error);
+    }
 
     Ok(())
 }
@@ -0,0 +1,402 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, IsTerminal, Read},
+    sync::mpsc,
+    thread,
+};
+
+use nirion_lib::docker::ProjectStatus;
+use nirion_tui_lib::{
+    ansi::lpad_ansi, color::Colorize, terminal::TerminalModeGuard,
+};
+use serde::{Deserialize, Serialize};
+
+/// A key pressed while the monitor runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Key {
+    Char(char),
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Escape,
+}
+
+/// The keys in a chunk read from the terminal. Arrow keys arrive as
+/// escape sequences; other control characters are dropped.
+pub(crate) fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut chars = text.chars().peekable();
+    let mut keys = Vec::new();
+
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' if matches!(chars.peek(), Some('[' | 'O')) => {
+                chars.next();
+                match chars.next() {
+                    Some('A') => Key::Up,
+                    Some('B') => Key::Down,
+                    _ => continue,
+                }
+            }
+            '\x1b' => Key::Escape,
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// Key presses on stdin, read on a thread of their own. The terminal
+/// stays unbuffered until this is dropped.
+pub(crate) struct KeyInput {
+    keys: mpsc::Receiver<Key>,
+    _mode: TerminalModeGuard,
+}
+
+impl KeyInput {
+    /// Starts reading keys, or `None` if stdin isn't a terminal.
+    pub(crate) fn spawn() -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+
+        let mode = TerminalModeGuard::unbuffered();
+        let (sender, keys) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin().lock();
+            let mut buffer = [0; 64];
+            while let Ok(read @ 1..) = stdin.read(&mut buffer) {
+                for key in parse_keys(&buffer[..read]) {
+                    if sender.send(key).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Some(Self { keys, _mode: mode })
+    }
+
+    /// The keys pressed since the last call.
+    pub(crate) fn take(&self) -> Vec<Key> {
+        self.keys.try_iter().collect()
+    }
+}
+
+/// Order of the projects in the monitor.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MonitorSort {
+    #[default]
+    Name,
+    /// Projects with problems first, then by name.
+    Problems,
+}
+
+impl MonitorSort {
+    fn next(self) -> Self {
+        match self {
+            MonitorSort::Name => MonitorSort::Problems,
+            MonitorSort::Problems => MonitorSort::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MonitorSort::Name => "name",
+            MonitorSort::Problems => "problems",
+        }
+    }
+}
+
+/// Which projects the monitor shows and how, changed with keys: `p`
+/// hides healthy projects, `/` edits the name filter, `s` switches the
+/// sort order, and enter lists the services of the selected project.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct MonitorView {
+    pub(crate) only_problems: bool,
+    pub(crate) filter: String,
+    pub(crate) sort: MonitorSort,
+    pub(crate) expanded: BTreeSet<String>,
+    /// Whether keys currently go to the filter.
+    editing: bool,
+    /// Index of the selected project among the visible ones.
+    selected: usize,
+}
+
+impl MonitorView {
+    pub(crate) fn new(
+        only_problems: bool,
+        filter: String,
+        sort: MonitorSort,
+        expanded: BTreeSet<String>,
+    ) -> Self {
+        Self {
+            only_problems,
+            filter,
+            sort,
+            expanded,
+            ..Self::default()
+        }
+    }
+
+    /// `projects` as shown: filtered by name and, with only problems on,
+    /// by `has_problems`, then sorted.
+    pub(crate) fn visible(
+        &self,
+        projects: &[String],
+        has_problems: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let mut visible = projects
+            .iter()
+            .filter(|name| self.matches(name))
+            .filter(|name| !self.only_problems || has_problems(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        match self.sort {
+            MonitorSort::Name => visible.sort(),
+            MonitorSort::Problems => {
+                visible.sort_by_key(|name| (!has_problems(name), name.clone()))
+            }
+        }
+        visible
+    }
+
+    pub(crate) fn matches(
+        &self,
+        project: &str,
+    ) -> bool {
+        project
+            .to_lowercase()
+            .contains(&self.filter.to_lowercase())
+    }
+
+    /// Applies `key`, with `visible` the projects currently shown.
+    pub(crate) fn press(
+        &mut self,
+        key: Key,
+        visible: &[String],
+    ) {
+        if self.editing {
+            match key {
+                Key::Enter => self.editing = false,
+                Key::Escape => {
+                    self.editing = false;
+                    self.filter.clear();
+                }
+                Key::Backspace => {
+                    self.filter.pop();
+                }
+                Key::Char(c) => self.filter.push(c),
+                Key::Up | Key::Down => return,
+            }
+            self.selected = 0;
+            return;
+        }
+
+        match key {
+            Key::Char('p') => self.only_problems = !self.only_problems,
+            Key::Char('/') => self.editing = true,
+            Key::Char('s') => self.sort = self.sort.next(),
+            Key::Escape => self.filter.clear(),
+            Key::Up | Key::Char('k') => {
+                self.selected = self.selected.saturating_sub(1)
+            }
+            Key::Down | Key::Char('j') => {
+                self.selected =
+                    (self.selected + 1).min(visible.len().saturating_sub(1))
+            }
+            Key::Enter | Key::Char(' ') => {
+                if let Some(project) = visible.get(self.selected)
+                    && !self.expanded.remove(project)
+                {
+                    self.expanded.insert(project.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The selected index among `count` visible projects, which may
+    /// have shrunk since the selection moved.
+    pub(crate) fn selected(
+        &mut self,
+        count: usize,
+    ) -> usize {
+        self.selected = self
+            .selected
+            .min(count.saturating_sub(1));
+        self.selected
+    }
+
+    /// A line per container of the expanded projects among `projects`.
+    pub(crate) fn service_lines(
+        &self,
+        projects: &[String],
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> Vec<String> {
+        let containers = projects
+            .iter()
+            .filter(|project| self.expanded.contains(*project))
+            .filter_map(|project| Some((project, statuses.get(project)?)))
+            .flat_map(|(project, status)| {
+                status
+                    .containers()
+                    .map(move |container| {
+                        let label = match container.index {
+                            1 => format!("{project}.{}", container.service),
+                            index => {
+                                format!(
+                                    "{project}.{}#{index}",
+                                    container.service
+                                )
+                            }
+                        };
+                        let state = container
+                            .status
+                            .clone()
+                            .unwrap_or_else(|| container.state.as_str().into());
+                        (label, state)
+                    })
+            })
+            .collect::<Vec<_>>();
+        let width = containers
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        containers
+            .iter()
+            .map(|(label, state)| {
+                format!(
+                    "  {}  {state}",
+                    lpad_ansi(&label.as_str().grey().to_string(), width)
+                )
+            })
+            .collect()
+    }
+
+    /// The filter and the keys, for the last line of the monitor.
+    pub(crate) fn help_line(&self) -> String {
+        let filter = if self.editing {
+            format!("/{}▏ ", self.filter)
+        } else if !self.filter.is_empty() {
+            format!("filter: {}  ", self.filter)
+        } else {
+            String::new()
+        };
+        let keys = if self.editing {
+            "enter done · esc clear".to_string()
+        } else {
+            format!(
+                "p {} · / filter · s sort: {} · ↑↓ enter expand",
+                if self.only_problems {
+                    "all"
+                } else {
+                    "problems"
+                },
+                self.sort.label()
+            )
+        };
+
+        format!("{filter}{}", keys.grey())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parse_keys_reads_characters_and_escape_sequences() {
+        assert_eq!(
+            parse_keys("p/\x1b[A\x1b[B\x1bOA\r\x7f\x1b\x01é".as_bytes()),
+            vec![
+                Key::Char('p'),
+                Key::Char('/'),
+                Key::Up,
+                Key::Down,
+                Key::Up,
+                Key::Enter,
+                Key::Backspace,
+                Key::Escape,
+                Key::Char('é'),
+            ]
+        );
+    }
+
+    #[test]
+    fn visible_filters_and_sorts_projects() {
+        let projects = names(&["media", "db", "web", "mail"]);
+        let problems = |name: &str| name == "web";
+        let mut view = MonitorView {
+            filter: "M".into(),
+            ..MonitorView::default()
+        };
+
+        assert_eq!(
+            view.visible(&projects, problems),
+            names(&["mail", "media"])
+        );
+
+        view.filter.clear();
+        view.sort = MonitorSort::Problems;
+        assert_eq!(
+            view.visible(&projects, problems),
+            names(&["web", "db", "mail", "media"])
+        );
+
+        view.only_problems = true;
+        assert_eq!(view.visible(&projects, problems), names(&["web"]));
+    }
+
+    #[test]
+    fn keys_edit_the_filter_until_enter() {
+        let mut view = MonitorView::default();
+
+        for key in [
+            Key::Char('/'),
+            Key::Char('w'),
+            Key::Char('p'),
+            Key::Backspace,
+            Key::Char('e'),
+            Key::Enter,
+            Key::Char('p'),
+        ] {
+            view.press(key, &[]);
+        }
+
+        assert_eq!(view.filter, "we");
+        assert!(view.only_problems);
+        assert!(!view.editing);
+    }
+
+    #[test]
+    fn enter_toggles_the_selected_project() {
+        let visible = names(&["db", "web"]);
+        let mut view = MonitorView::default();
+
+        view.press(Key::Down, &visible);
+        view.press(Key::Down, &visible);
+        view.press(Key::Enter, &visible);
+        assert_eq!(view.expanded, BTreeSet::from(["web".to_string()]));
+
+        view.press(Key::Char(' '), &visible);
+        assert!(view.expanded.is_empty());
+        assert_eq!(view.selected(1), 0);
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::OnceLock;
+
+use crate::{
+    progress_render::ProgressPresentation, update_progress::ProgressMode,
+};
+
+static OUTPUT: OnceLock<OutputOptions> = OnceLock::new();
+
+static COMPOSE_WARNINGS: OnceLock<ComposeWarningFilter> = OnceLock::new();
+
+/// Compose warnings hidden unless `--show-compose-warnings` is given.
+pub const DEFAULT_HIDDEN_COMPOSE_WARNINGS: &[&str] =
+    &["the attribute `version` is obsolete"];
+
+/// What the global `--quiet` and `--no-progress` flags leave on screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputOptions {
+    /// Only errors and results; no progress, headers or summaries.
+    pub quiet: bool,
+    /// No spinners or repainted bars, but summaries are still printed.
+    pub no_progress: bool,
+    /// Digests printed in full rather than shortened.
+    pub full_digests: bool,
+}
+
+impl OutputOptions {
+    pub fn init(self) -> anyhow::Result<()> {
+        OUTPUT
+            .set(self)
+            .map_err(|_| anyhow::anyhow!("output options already initialized"))
+    }
+
+    pub fn get() -> Self {
+        OUTPUT
+            .get()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// `digest` the way it is shown to people: shortened with
+    /// [`short_digest`] unless `--full-digests` was given.
+    pub fn digest(
+        self,
+        digest: &str,
+    ) -> &str {
+        if self.full_digests {
+            digest
+        } else {
+            short_digest(digest)
+        }
+    }
+
+    pub fn animated(self) -> bool {
+        !self.quiet && !self.no_progress
+    }
+
+    pub fn progress_mode(
+        self,
+        requested: ProgressMode,
+    ) -> ProgressMode {
+        if self.animated() {
+            requested
+        } else {
+            ProgressMode::None
+        }
+    }
+
+    pub fn presentation(
+        self,
+        plain: bool,
+    ) -> ProgressPresentation {
+        if self.quiet {
+            ProgressPresentation::Hidden
+        } else if plain {
+            ProgressPresentation::Plain
+        } else if self.no_progress {
+            ProgressPresentation::Summary
+        } else {
+            ProgressPresentation::Progress
+        }
+    }
+}
+
+/// The first 12 hex characters of `digest`, without the `sha256:`
+/// prefix, like docker shows image ids.
+pub fn short_digest(digest: &str) -> &str {
+    let hex = digest
+        .split_once(':')
+        .map_or(digest, |(_, hex)| hex);
+    &hex[..hex.len().min(12)]
+}
+
+/// Which lines of compose's stderr are dropped instead of passed on to
+/// nirion's stderr: those containing one of the patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeWarningFilter {
+    patterns: Vec<String>,
+}
+
+impl Default for ComposeWarningFilter {
+    fn default() -> Self {
+        Self::new(false, &[])
+    }
+}
+
+impl ComposeWarningFilter {
+    /// The default patterns plus `hidden`, or nothing at all with
+    /// `show_all`.
+    pub fn new(
+        show_all: bool,
+        hidden: &[String],
+    ) -> Self {
+        let patterns = if show_all {
+            Vec::new()
+        } else {
+            DEFAULT_HIDDEN_COMPOSE_WARNINGS
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .chain(hidden.iter().cloned())
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        };
+        Self { patterns }
+    }
+
+    pub fn init(self) -> anyhow::Result<()> {
+        COMPOSE_WARNINGS.set(self).map_err(|_| {
+            anyhow::anyhow!("compose warning filter already initialized")
+        })
+    }
+
+    pub fn get() -> &'static Self {
+        COMPOSE_WARNINGS.get_or_init(Self::default)
+    }
+
+    pub fn hides(
+        &self,
+        line: &str,
+    ) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| line.contains(pattern.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_are_shortened_unless_asked_for_in_full() {
+        let digest = "sha256:0123456789abcdef0123";
+        assert_eq!(OutputOptions::default().digest(digest), "0123456789ab");
+        assert_eq!(short_digest("abc"), "abc");
+
+        let full = OutputOptions {
+            full_digests: true,
+            ..Default::default()
+        };
+        assert_eq!(full.digest(digest), digest);
+    }
+
+    #[test]
+    fn quiet_wins_over_no_progress_and_plain() {
+        let quiet = OutputOptions {
+            quiet: true,
+            no_progress: true,
+            ..Default::default()
+        };
+        assert_eq!(quiet.presentation(true), ProgressPresentation::Hidden);
+        assert_eq!(quiet.progress_mode(ProgressMode::Full), ProgressMode::None);
+
+        let no_progress = OutputOptions {
+            no_progress: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            no_progress.presentation(false),
+            ProgressPresentation::Summary
+        );
+        assert_eq!(no_progress.presentation(true), ProgressPresentation::Plain);
+        assert_eq!(
+            OutputOptions::default().progress_mode(ProgressMode::Compact),
+            ProgressMode::Compact
+        );
+    }
+
+    #[test]
+    fn compose_warnings_are_hidden_by_pattern_unless_shown() {
+        let obsolete = "WARN[0000] compose.yml: the attribute `version` is \
+                        obsolete, it will be ignored";
+        let hidden = ["pull access denied".to_string()];
+
+        let filter = ComposeWarningFilter::new(false, &hidden);
+        assert!(filter.hides(obsolete));
+        assert!(filter.hides("Error: pull access denied for foo"));
+        assert!(!filter.hides("Container myapp-web-1  Started"));
+
+        assert!(!ComposeWarningFilter::new(true, &hidden).hides(obsolete));
+    }
+}
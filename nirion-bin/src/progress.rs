@@ -4,9 +4,10 @@ use nirion_lib::{
     docker::{ProjectStatus, ProjectStatusEvent, query_project_status},
     events::{ComposeEvent, ProcessEvent},
     projects::{Projects, selected_project_names},
-    wait::{WaitTarget, wait_finished},
+    wait::{WaitTarget, project_stragglers, project_wait_finished},
 };
 use std::collections::BTreeMap;
+use tokio::time::{Duration, MissedTickBehavior};
 
 use crate::TargetSelector;
 use crate::progress_render::ProgressRenderer;
@@ -17,8 +18,56 @@ pub(crate) enum ProgressExit {
     Cancelled,
 }
 
+pub(crate) struct ProgressOutcome {
+    pub(crate) exit: ProgressExit,
+    /// Last known status of each project; projects that were never
+    /// queried are missing.
+    pub(crate) statuses: BTreeMap<String, ProjectStatus>,
+}
+
+/// Where a single project is in a lifecycle operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ProjectPhase {
+    Running,
+    WaitingForHealth,
+    WaitingForStop,
+    Done,
+    /// Settled a healthy wait with services that didn't pass their
+    /// healthcheck.
+    Unhealthy,
+    Failed,
+}
+
+impl ProjectPhase {
+    pub(crate) fn is_finished(self) -> bool {
+        matches!(
+            self,
+            ProjectPhase::Done | ProjectPhase::Unhealthy | ProjectPhase::Failed
+        )
+    }
+
+    fn after_compose(wait: WaitTarget) -> Self {
+        match wait {
+            WaitTarget::Healthy => ProjectPhase::WaitingForHealth,
+            WaitTarget::Stopped => ProjectPhase::WaitingForStop,
+            WaitTarget::NoWait | WaitTarget::Forever => ProjectPhase::Done,
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            ProjectPhase::Running => "running",
+            ProjectPhase::WaitingForHealth => "waiting for health",
+            ProjectPhase::WaitingForStop => "waiting for stop",
+            ProjectPhase::Done => "done",
+            ProjectPhase::Unhealthy => "unhealthy",
+            ProjectPhase::Failed => "failed",
+        }
+    }
+}
+
 struct ProgressState {
-    running: BTreeMap<String, bool>,
+    phases: BTreeMap<String, ProjectPhase>,
     statuses: BTreeMap<String, ProjectStatus>,
     compose_finished: bool,
     status_finished: bool,
@@ -29,9 +78,9 @@ struct ProgressState {
 impl ProgressState {
     fn new(selected: &[String]) -> Self {
         Self {
-            running: selected
+            phases: selected
                 .iter()
-                .map(|name| (name.clone(), true))
+                .map(|name| (name.clone(), ProjectPhase::Running))
                 .collect(),
             statuses: BTreeMap::new(),
             compose_finished: false,
@@ -43,26 +92,82 @@ impl ProgressState {
 
     fn ready(
         &self,
-        target: &TargetSelector,
-        projects: &Projects,
         wait: WaitTarget,
     ) -> bool {
         self.cancelled
             || self.error.is_some()
             || (wait == WaitTarget::Forever && self.status_finished)
-            || (self.compose_finished
-                && wait_finished(target, projects, &self.statuses, wait))
+            || (wait != WaitTarget::Forever
+                && self.compose_finished
+                && self
+                    .phases
+                    .values()
+                    .all(|phase| phase.is_finished()))
     }
 
-    fn stop_running_projects(&mut self) {
-        for value in self.running.values_mut() {
-            *value = false;
+    fn finish_project(
+        &mut self,
+        project: &str,
+        success: bool,
+        wait: WaitTarget,
+    ) {
+        let phase = match (success, wait) {
+            (false, _) => ProjectPhase::Failed,
+            (true, WaitTarget::Healthy) => ProjectPhase::WaitingForHealth,
+            (true, _) => ProjectPhase::Done,
+        };
+        self.phases
+            .insert(project.to_string(), phase);
+    }
+
+    /// Settles each waiting project on its own, so one that never gets
+    /// healthy doesn't keep the others' rows spinning.
+    fn update_wait_phases(
+        &mut self,
+        target: &TargetSelector,
+        projects: &Projects,
+        wait: WaitTarget,
+    ) {
+        for (name, phase) in self.phases.iter_mut() {
+            if !matches!(
+                phase,
+                ProjectPhase::WaitingForHealth | ProjectPhase::WaitingForStop
+            ) || !project_wait_finished(
+                target,
+                name,
+                projects,
+                &self.statuses,
+                wait,
+            ) {
+                continue;
+            }
+            let stragglers =
+                project_stragglers(target, name, projects, &self.statuses);
+            *phase = if wait == WaitTarget::Healthy && !stragglers.is_empty() {
+                ProjectPhase::Unhealthy
+            } else {
+                ProjectPhase::Done
+            };
         }
     }
 
-    fn finish_compose(&mut self) {
+    fn finish_running_projects(
+        &mut self,
+        phase: ProjectPhase,
+    ) {
+        for value in self.phases.values_mut() {
+            if *value == ProjectPhase::Running {
+                *value = phase;
+            }
+        }
+    }
+
+    fn finish_compose(
+        &mut self,
+        wait: WaitTarget,
+    ) {
         self.compose_finished = true;
-        self.stop_running_projects();
+        self.finish_running_projects(ProjectPhase::after_compose(wait));
     }
 
     fn fail(
@@ -70,12 +175,40 @@ impl ProgressState {
         error: anyhow::Error,
     ) {
         self.error = Some(error);
-        self.finish_compose();
+        self.compose_finished = true;
+        self.finish_running_projects(ProjectPhase::Failed);
     }
 
     fn cancel(&mut self) {
         self.cancelled = true;
-        self.stop_running_projects();
+        for value in self.phases.values_mut() {
+            if !value.is_finished() {
+                *value = ProjectPhase::Failed;
+            }
+        }
+    }
+
+    fn handle_compose_event(
+        &mut self,
+        event: &ComposeEvent,
+        wait: WaitTarget,
+    ) {
+        match event {
+            ComposeEvent::ProjectStarted { project } => {
+                self.phases
+                    .insert(project.clone(), ProjectPhase::Running);
+            }
+            ComposeEvent::ProjectFailed { project, .. } => {
+                self.finish_project(project, false, wait);
+            }
+            ComposeEvent::Process {
+                project: Some(project),
+                event: ProcessEvent::Exited(status),
+            } => {
+                self.finish_project(project, status.success, wait);
+            }
+            ComposeEvent::Process { .. } => {}
+        }
     }
 
     fn handle_status_event(
@@ -108,7 +241,8 @@ pub(crate) async fn run_progress(
     status_events: impl Stream<Item = anyhow::Result<ProjectStatusEvent>>,
     mut renderer: impl ProgressRenderer,
     wait: WaitTarget,
-) -> anyhow::Result<ProgressExit> {
+    refresh_interval: Duration,
+) -> anyhow::Result<ProgressOutcome> {
     tokio::pin!(compose_stream);
     tokio::pin!(status_events);
     let cancel = tokio::signal::ctrl_c();
@@ -117,21 +251,29 @@ pub(crate) async fn run_progress(
     let selected = selected_project_names(target, &context.projects);
     let mut state = ProgressState::new(&selected);
 
-    renderer.start(context, &selected, &state.running, &state.statuses)?;
+    renderer.start(context, &selected, &state.phases, &state.statuses)?;
+
+    // Events only update the state; redrawing happens on this ticker, so
+    // chatty compose output can't drive the render rate.
+    let mut ticker = tokio::time::interval(refresh_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
-    while !state.ready(target, &context.projects, wait) {
+    while !state.ready(wait) {
         tokio::select! {
             _ = &mut cancel => {
                 state.cancel();
             }
+            _ = ticker.tick() => {
+                renderer.tick(context, &selected, &state.phases, &state.statuses)?;
+            }
             event = compose_stream.next(), if !state.compose_finished => {
                 match event {
                     Some(Ok(event)) => {
-                        handle_compose_event(&event, &mut state.running);
+                        state.handle_compose_event(&event, wait);
                         renderer.compose_event(&event)?;
                     }
                     Some(Err(error)) => state.fail(error),
-                    None => state.finish_compose(),
+                    None => state.finish_compose(wait),
                 }
             }
             event = status_events.next(), if !state.status_finished => {
@@ -139,7 +281,7 @@ pub(crate) async fn run_progress(
             }
         }
 
-        renderer.tick(context, &selected, &state.running, &state.statuses)?;
+        state.update_wait_phases(target, &context.projects, wait);
     }
 
     if !state.cancelled
@@ -149,38 +291,18 @@ pub(crate) async fn run_progress(
         refresh_statuses(context, &selected, &mut state.statuses).await?;
     }
 
-    renderer.finish(context, &selected, &state.running, &state.statuses)?;
-
-    if state.cancelled {
-        return Ok(ProgressExit::Cancelled);
-    }
+    renderer.finish(context, &selected, &state.phases, &state.statuses)?;
 
-    if let Some(error) = state.error {
-        return Err(error);
-    }
+    let exit = match (state.cancelled, state.error) {
+        (true, _) => ProgressExit::Cancelled,
+        (false, Some(error)) => return Err(error),
+        (false, None) => ProgressExit::Completed,
+    };
 
-    Ok(ProgressExit::Completed)
-}
-
-fn handle_compose_event(
-    event: &ComposeEvent,
-    running: &mut BTreeMap<String, bool>,
-) {
-    match event {
-        ComposeEvent::ProjectStarted { project } => {
-            running.insert(project.clone(), true);
-        }
-        ComposeEvent::ProjectFailed { project, .. } => {
-            running.insert(project.clone(), false);
-        }
-        ComposeEvent::Process {
-            project: Some(project),
-            event: ProcessEvent::Exited(_),
-        } => {
-            running.insert(project.clone(), false);
-        }
-        ComposeEvent::Process { .. } => {}
-    }
+    Ok(ProgressOutcome {
+        exit,
+        statuses: state.statuses,
+    })
 }
 
 async fn refresh_statuses(
@@ -199,48 +321,88 @@ async fn refresh_statuses(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use nirion_lib::events::ExitStatus;
-    use std::collections::BTreeMap;
+    use futures::stream;
+    use nirion_lib::{
+        docker::DockerCommand, events::ExitStatus, lock::LockedImages,
+    };
+    use nirion_oci_lib::client::NirionOciClient;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    struct CountingRenderer(Arc<AtomicUsize>);
+
+    impl ProgressRenderer for CountingRenderer {
+        fn tick(
+            &mut self,
+            _context: &NirionContext,
+            _selected: &[String],
+            _phases: &BTreeMap<String, ProjectPhase>,
+            _statuses: &BTreeMap<String, ProjectStatus>,
+        ) -> anyhow::Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn context() -> NirionContext {
+        NirionContext {
+            projects: serde_json::from_str(
+                r#"{"app": {"name": "app", "dockerCompose": "compose.yml", "services": {}}}"#,
+            )
+            .unwrap(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::default(),
+        }
+    }
+
+    fn state() -> ProgressState {
+        ProgressState::new(&["app".to_string()])
+    }
+
+    fn exited(success: bool) -> ComposeEvent {
+        ComposeEvent::Process {
+            project: Some("app".to_string()),
+            event: ProcessEvent::Exited(ExitStatus {
+                code: Some(if success { 0 } else { 1 }),
+                success,
+            }),
+        }
+    }
 
     #[test]
-    fn handle_compose_event_updates_running_state() {
-        let mut running = BTreeMap::new();
+    fn handle_compose_event_updates_project_phase() {
+        let mut state = state();
 
-        handle_compose_event(
+        state.handle_compose_event(
             &ComposeEvent::ProjectStarted {
                 project: "app".to_string(),
             },
-            &mut running,
+            WaitTarget::NoWait,
         );
-        assert_eq!(running.get("app"), Some(&true));
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Running));
 
-        handle_compose_event(
-            &ComposeEvent::Process {
-                project: Some("app".to_string()),
-                event: ProcessEvent::Exited(ExitStatus {
-                    code: Some(0),
-                    success: true,
-                }),
-            },
-            &mut running,
-        );
-        assert_eq!(running.get("app"), Some(&false));
+        state.handle_compose_event(&exited(true), WaitTarget::NoWait);
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Done));
 
-        handle_compose_event(
+        state.handle_compose_event(
             &ComposeEvent::ProjectFailed {
                 project: "app".to_string(),
                 error: "failed".to_string(),
             },
-            &mut running,
+            WaitTarget::NoWait,
         );
-        assert_eq!(running.get("app"), Some(&false));
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Failed));
     }
 
     #[test]
     fn handle_compose_event_ignores_unscoped_process_events() {
-        let mut running = BTreeMap::from([("app".to_string(), true)]);
+        let mut state = state();
 
-        handle_compose_event(
+        state.handle_compose_event(
             &ComposeEvent::Process {
                 project: None,
                 event: ProcessEvent::Exited(ExitStatus {
@@ -248,9 +410,143 @@ mod tests {
                     success: true,
                 }),
             },
-            &mut running,
+            WaitTarget::NoWait,
         );
 
-        assert_eq!(running.get("app"), Some(&true));
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Running));
+    }
+
+    #[test]
+    fn successful_exit_waits_for_health_when_requested() {
+        let mut state = state();
+
+        state.handle_compose_event(&exited(true), WaitTarget::Healthy);
+
+        assert_eq!(
+            state.phases.get("app"),
+            Some(&ProjectPhase::WaitingForHealth)
+        );
+        state.compose_finished = true;
+        assert!(!state.ready(WaitTarget::Healthy));
+    }
+
+    #[test]
+    fn update_health_phases_completes_projects_individually() {
+        let projects: Projects = serde_json::from_str(
+            r#"{
+  "app": {"name": "app", "dockerCompose": "a.yml", "services": {
+    "web": {"image": "nginx", "healthcheck": true, "restart": null}}},
+  "slow": {"name": "slow", "dockerCompose": "s.yml", "services": {
+    "db": {"image": "postgres", "healthcheck": true, "restart": null}}}
+}"#,
+        )
+        .unwrap();
+        let mut state =
+            ProgressState::new(&["app".to_string(), "slow".to_string()]);
+        state.finish_compose(WaitTarget::Healthy);
+        state.statuses.insert(
+            "app".to_string(),
+            ProjectStatus::from_json(
+                r#"{"ID":"1","Name":"app-web-1","Service":"web","Image":"nginx","State":"running","Health":"healthy"}"#,
+            )
+            .unwrap(),
+        );
+
+        state.update_wait_phases(
+            &TargetSelector::All,
+            &projects,
+            WaitTarget::Healthy,
+        );
+
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Done));
+        assert_eq!(
+            state.phases.get("slow"),
+            Some(&ProjectPhase::WaitingForHealth)
+        );
+        assert!(!state.ready(WaitTarget::Healthy));
+    }
+
+    #[test]
+    fn unhealthy_project_settles_without_holding_up_the_rest() {
+        let projects: Projects = serde_json::from_str(
+            r#"{
+  "app": {"name": "app", "dockerCompose": "a.yml", "services": {
+    "web": {"image": "nginx", "healthcheck": true, "restart": null}}},
+  "broken": {"name": "broken", "dockerCompose": "b.yml", "services": {
+    "db": {"image": "postgres", "healthcheck": true, "restart": null}}}
+}"#,
+        )
+        .unwrap();
+        let mut state =
+            ProgressState::new(&["app".to_string(), "broken".to_string()]);
+        state.finish_compose(WaitTarget::Healthy);
+        for (project, json) in [
+            (
+                "app",
+                r#"{"ID":"1","Name":"app-web-1","Service":"web","Image":"nginx","State":"running","Health":"healthy"}"#,
+            ),
+            (
+                "broken",
+                r#"{"ID":"2","Name":"broken-db-1","Service":"db","Image":"postgres","State":"running","Health":"unhealthy"}"#,
+            ),
+        ] {
+            state.statuses.insert(
+                project.to_string(),
+                ProjectStatus::from_json(json).unwrap(),
+            );
+        }
+
+        state.update_wait_phases(
+            &TargetSelector::All,
+            &projects,
+            WaitTarget::Healthy,
+        );
+
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Done));
+        assert_eq!(state.phases.get("broken"), Some(&ProjectPhase::Unhealthy));
+        assert!(state.ready(WaitTarget::Healthy));
+    }
+
+    #[test]
+    fn failed_compose_marks_running_projects_failed() {
+        let mut state = state();
+
+        state.fail(anyhow::anyhow!("boom"));
+
+        assert_eq!(state.phases.get("app"), Some(&ProjectPhase::Failed));
+        assert!(state.ready(WaitTarget::Healthy));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn redraws_at_the_refresh_interval() {
+        let ticks = Arc::new(AtomicUsize::new(0));
+        // Compose produces a burst of output, then runs quietly for a
+        // second before exiting.
+        let compose = stream::iter((0..100).map(|i| {
+            Ok(ComposeEvent::Process {
+                project: Some("app".to_string()),
+                event: ProcessEvent::StdoutLine(format!("line {i}")),
+            })
+        }))
+        .chain(
+            stream::once(tokio::time::sleep(Duration::from_secs(1)))
+                .map(|_| Ok(exited(true))),
+        );
+
+        let outcome = run_progress(
+            &context(),
+            &TargetSelector::All,
+            compose,
+            stream::pending(),
+            CountingRenderer(ticks.clone()),
+            WaitTarget::NoWait,
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.exit, ProgressExit::Completed);
+        let ticks = ticks.load(Ordering::SeqCst);
+        assert!((10..=11).contains(&ticks), "{ticks} redraws");
     }
 }
@@ -1,39 +1,226 @@
 use nirion_lib::{
     context::NirionContext,
-    docker::ProjectStatus,
+    daemon::DaemonWatch,
+    docker::{ProjectState, ProjectStatus, ServiceState},
     events::{ComposeEvent, ProcessEvent},
-    projects::Projects,
+    maintenance::MaintenanceMarker,
+    projects::{Project, Projects},
+    pull_progress::PullProgress,
 };
 use nirion_tui_lib::{
     color::{Colorize, GREY},
     line_renderer::LineRenderer,
-    spinner::Spinner,
+    spinner::{DOTS, PULSE, Spinner},
     status::{Status, StatusEntry},
     terminal::{HiddenCursorGuard, terminal_width},
 };
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
-use crate::status_display::{project_state_icon, project_status_segments};
+use crate::commands::maintenance::maintenance_label;
+use crate::monitor_view::{KeyInput, MonitorView};
+use crate::output::ComposeWarningFilter;
+use crate::progress::ProjectPhase;
+use crate::stats_render::StatsView;
+use crate::status_display::{
+    foreign_containers_label, project_state_icon, project_status_segments,
+    unknown_states_label,
+};
+use crate::updates_render::UpdatesView;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProgressPresentation {
     Progress,
     Plain,
+    /// The final status only, printed once nothing is running anymore.
+    Summary,
     Hidden,
 }
 
 fn empty_status() -> ProjectStatus {
-    ProjectStatus {
-        services: BTreeMap::new(),
+    ProjectStatus::from_containers([])
+}
+
+pub(crate) struct ProgressSpinners {
+    work: Spinner,
+    health: Spinner,
+}
+
+impl Default for ProgressSpinners {
+    fn default() -> Self {
+        Self {
+            work: Spinner::new(DOTS, Duration::from_millis(80)),
+            health: Spinner::new(PULSE, Duration::from_millis(200)),
+        }
+    }
+}
+
+/// A project is waiting for healthchecks when one of its healthchecked
+/// services has a container that has not reported a health result yet.
+fn awaiting_healthchecks(
+    project: &Project,
+    status: &ProjectStatus,
+) -> bool {
+    project
+        .services
+        .iter()
+        .filter(|(_, service)| service.healthcheck)
+        .flat_map(|(name, _)| status.replicas(name))
+        .any(|service| {
+            matches!(
+                service.state,
+                ServiceState::Created
+                    | ServiceState::Starting
+                    | ServiceState::Running
+                    | ServiceState::Restarting
+            )
+        })
+}
+
+/// Remembers the first restart count seen for each container so that
+/// containers restarting while we watch can be flagged, even when docker
+/// already reports them as running again.
+#[derive(Debug, Default)]
+pub(crate) struct RestartTracker {
+    baseline: BTreeMap<String, u32>,
+}
+
+impl RestartTracker {
+    fn observe(
+        &mut self,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) {
+        for container in statuses
+            .values()
+            .flat_map(ProjectStatus::containers)
+        {
+            if let Some(details) = &container.details {
+                self.baseline
+                    .entry(container.id.clone())
+                    .or_insert(details.restart_count);
+            }
+        }
+    }
+
+    /// The highest restart count among containers of the project that
+    /// restarted since they were first observed.
+    fn restarted(
+        &self,
+        status: &ProjectStatus,
+    ) -> Option<u32> {
+        status
+            .containers()
+            .filter_map(|container| {
+                let count = container
+                    .details
+                    .as_ref()?
+                    .restart_count;
+                let baseline = *self.baseline.get(&container.id)?;
+                (count > baseline).then_some(count)
+            })
+            .max()
+    }
+}
+
+/// The line shown under the status box while compose is still pulling
+/// a project's images, e.g. `app ↓ pulling 3/7 layers, 12.4 MB/s`.
+fn pull_line(
+    name: &str,
+    pull: &PullProgress,
+) -> String {
+    let mut line = format!(
+        "pulling {}/{} layers",
+        pull.layers_done(),
+        pull.layers_total()
+    );
+    if let Some(rate) = pull.bytes_per_second() {
+        line.push_str(&format!(", {:.1} MB/s", rate / 1e6));
     }
+
+    format!("{} {} {}", name, "↓".cyan(), line.grey())
+}
+
+fn project_icon(
+    spinners: Option<&ProgressSpinners>,
+    phase: ProjectPhase,
+    project: &Project,
+    status: &ProjectStatus,
+) -> String {
+    let state = status.project_state();
+
+    match (spinners, phase) {
+        (_, ProjectPhase::Failed) => "✗".red().to_string(),
+        (_, ProjectPhase::Unhealthy) => "✗".yellow().to_string(),
+        (Some(spinners), ProjectPhase::Running) => {
+            spinners.work.get().yellow().to_string()
+        }
+        (Some(spinners), ProjectPhase::WaitingForHealth) => {
+            spinners.health.get().cyan().to_string()
+        }
+        (Some(spinners), ProjectPhase::WaitingForStop) => {
+            spinners.work.get().cyan().to_string()
+        }
+        (Some(spinners), _) if awaiting_healthchecks(project, status) => {
+            spinners.health.get().cyan().to_string()
+        }
+        (Some(spinners), _) if state == ProjectState::Starting => {
+            spinners.work.get().cyan().to_string()
+        }
+        _ => project_state_icon(&state),
+    }
+}
+
+fn phase_summary(
+    selected: &[String],
+    phases: &BTreeMap<String, ProjectPhase>,
+) -> String {
+    let count = |phase| {
+        selected
+            .iter()
+            .filter(|name| phases.get(*name) == Some(&phase))
+            .count()
+    };
+
+    [
+        (ProjectPhase::Done, count(ProjectPhase::Done)),
+        (
+            ProjectPhase::WaitingForHealth,
+            count(ProjectPhase::WaitingForHealth),
+        ),
+        (ProjectPhase::Running, count(ProjectPhase::Running)),
+        (ProjectPhase::Unhealthy, count(ProjectPhase::Unhealthy)),
+        (ProjectPhase::Failed, count(ProjectPhase::Failed)),
+    ]
+    .into_iter()
+    .filter(|(_, count)| *count > 0)
+    .map(|(phase, count)| {
+        let text = format!("{count} {}", phase.label());
+        match phase {
+            ProjectPhase::Done => text.green().to_string(),
+            ProjectPhase::Failed | ProjectPhase::Unhealthy => {
+                text.red().to_string()
+            }
+            _ => text.yellow().to_string(),
+        }
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_status(
-    spinner: Option<&Spinner>,
+    spinners: Option<&ProgressSpinners>,
+    show_phase: bool,
     selected: &[String],
-    running: &BTreeMap<String, bool>,
+    phases: &BTreeMap<String, ProjectPhase>,
     statuses: &BTreeMap<String, ProjectStatus>,
     projects: &Projects,
+    restarts: &RestartTracker,
+    stats: Option<&StatsView>,
+    updates: Option<&UpdatesView>,
+    maintenance: &BTreeMap<String, MaintenanceMarker>,
 ) -> Status {
     let mut entries = Vec::new();
 
@@ -44,25 +231,58 @@ fn create_status(
             .unwrap_or_else(empty_status);
         let project = &projects[name];
 
-        let icon = if let Some(spinner) = spinner
-            && *running.get(name).unwrap_or(&false)
-        {
-            spinner.get().yellow().to_string()
-        } else {
-            project_state_icon(&project_status.project_state())
-        };
+        let phase = phases
+            .get(name)
+            .copied()
+            .unwrap_or(ProjectPhase::Done);
+        let icon = project_icon(spinners, phase, project, &project_status);
 
         let prefix = format!("{icon} {name}");
 
-        let progressing = project_status.progressing();
-        let num_services = project.services.len();
-        let mut segments = project_status_segments(&project_status);
+        let counts = project_status.progress_counts(project);
+        let mut segments = project_status_segments(&project_status, project);
 
-        for _ in 0..(num_services.saturating_sub(segments.len())) {
-            segments.push(GREY);
-        }
+        segments.resize(
+            (counts.expected + counts.completed).max(segments.len()),
+            GREY,
+        );
 
-        let suffix = format!("({progressing}/{num_services})    ");
+        let mut suffix =
+            format!("({}/{})", counts.progressing, counts.expected);
+        if counts.completed > 0 {
+            suffix.push_str(&format!(
+                " {}",
+                format!("{} completed", counts.completed).cyan()
+            ));
+        }
+        if let Some(states) = unknown_states_label(&project_status) {
+            suffix.push_str(&format!(" {states}"));
+        }
+        if let Some(foreign) = foreign_containers_label(&project_status) {
+            suffix.push_str(&format!(" {foreign}"));
+        }
+        if let Some(count) = restarts.restarted(&project_status) {
+            suffix.push_str(&format!(
+                " {}",
+                format!("↻ restarting ({count})").yellow()
+            ));
+        }
+        if let Some(usage) = stats.and_then(|stats| stats.project_label(name)) {
+            suffix.push_str(&format!(" {usage}"));
+        }
+        if let Some(label) =
+            updates.and_then(|updates| updates.project_label(name))
+        {
+            suffix.push_str(&format!(" {label}"));
+        }
+        if let Some(marker) = maintenance.get(name) {
+            suffix
+                .push_str(&format!(" {}", maintenance_label(marker).yellow()));
+        }
+        if show_phase {
+            suffix.push_str(&format!(" {}", phase.label().grey()));
+        }
+        suffix.push_str("    ");
 
         entries.push(StatusEntry {
             prefix,
@@ -83,7 +303,7 @@ pub(crate) trait ProgressRenderer {
         &mut self,
         _context: &NirionContext,
         _selected: &[String],
-        _running: &BTreeMap<String, bool>,
+        _phases: &BTreeMap<String, ProjectPhase>,
         _statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
         Ok(())
@@ -100,7 +320,7 @@ pub(crate) trait ProgressRenderer {
         &mut self,
         _context: &NirionContext,
         _selected: &[String],
-        _running: &BTreeMap<String, bool>,
+        _phases: &BTreeMap<String, ProjectPhase>,
         _statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
         Ok(())
@@ -110,7 +330,7 @@ pub(crate) trait ProgressRenderer {
         &mut self,
         _context: &NirionContext,
         _selected: &[String],
-        _running: &BTreeMap<String, bool>,
+        _phases: &BTreeMap<String, ProjectPhase>,
         _statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
         Ok(())
@@ -129,10 +349,56 @@ where
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> anyhow::Result<()> {
+        (**self).start(context, selected, phases, statuses)
+    }
+
+    fn compose_event(
+        &mut self,
+        event: &ComposeEvent,
+    ) -> anyhow::Result<()> {
+        (**self).compose_event(event)
+    }
+
+    fn tick(
+        &mut self,
+        context: &NirionContext,
+        selected: &[String],
+        phases: &BTreeMap<String, ProjectPhase>,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> anyhow::Result<()> {
+        (**self).tick(context, selected, phases, statuses)
+    }
+
+    fn finish(
+        &mut self,
+        context: &NirionContext,
+        selected: &[String],
+        phases: &BTreeMap<String, ProjectPhase>,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> anyhow::Result<()> {
+        (**self).finish(context, selected, phases, statuses)
+    }
+}
+
+impl<T> ProgressRenderer for &mut T
+where
+    T: ProgressRenderer + ?Sized,
+{
+    fn needs_status_during_compose(&self) -> bool {
+        (**self).needs_status_during_compose()
+    }
+
+    fn start(
+        &mut self,
+        context: &NirionContext,
+        selected: &[String],
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
-        (**self).start(context, selected, running, statuses)
+        (**self).start(context, selected, phases, statuses)
     }
 
     fn compose_event(
@@ -146,25 +412,36 @@ where
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
-        (**self).tick(context, selected, running, statuses)
+        (**self).tick(context, selected, phases, statuses)
     }
 
     fn finish(
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
-        (**self).finish(context, selected, running, statuses)
+        (**self).finish(context, selected, phases, statuses)
     }
 }
 
 pub(crate) struct StatusProgressRenderer {
-    spinner: Option<Spinner>,
+    spinners: Option<ProgressSpinners>,
+    compose: bool,
+    view: MonitorView,
+    keys: Option<KeyInput>,
+    pulling: bool,
+    restarts: RestartTracker,
+    pulls: BTreeMap<String, PullProgress>,
+    project_errors: BTreeMap<String, String>,
+    stats: Option<StatsView>,
+    updates: Option<UpdatesView>,
+    maintenance: BTreeMap<String, MaintenanceMarker>,
+    daemon: Option<DaemonWatch>,
     lines: LineRenderer,
     cursor: Option<HiddenCursorGuard>,
 }
@@ -172,64 +449,358 @@ pub(crate) struct StatusProgressRenderer {
 impl StatusProgressRenderer {
     pub(crate) fn with_spinner() -> Self {
         Self {
-            spinner: Some(Spinner::default()),
+            spinners: Some(ProgressSpinners::default()),
+            compose: true,
+            view: MonitorView::default(),
+            keys: None,
+            pulling: false,
+            restarts: RestartTracker::default(),
+            pulls: BTreeMap::new(),
+            project_errors: BTreeMap::new(),
+            stats: None,
+            updates: None,
+            maintenance: BTreeMap::new(),
+            daemon: None,
             lines: LineRenderer::default(),
             cursor: None,
         }
     }
 
-    pub(crate) fn without_spinner() -> Self {
+    /// Renders status updates only, animating projects that are still
+    /// starting or waiting for healthchecks.
+    pub(crate) fn status_only() -> Self {
         Self {
-            spinner: None,
+            spinners: Some(ProgressSpinners::default()),
+            compose: false,
+            view: MonitorView::default(),
+            keys: None,
+            pulling: false,
+            restarts: RestartTracker::default(),
+            pulls: BTreeMap::new(),
+            project_errors: BTreeMap::new(),
+            stats: None,
+            updates: None,
+            maintenance: BTreeMap::new(),
+            daemon: None,
             lines: LineRenderer::default(),
             cursor: None,
         }
     }
 
-    fn spinner(&self) -> Option<&Spinner> {
-        self.spinner.as_ref()
+    /// Draws nothing while the command runs and prints the final status
+    /// once, with static icons in place of the spinners.
+    pub(crate) fn summary_only() -> Self {
+        Self {
+            spinners: None,
+            ..Self::with_spinner()
+        }
+    }
+
+    fn animated(&self) -> bool {
+        self.spinners.is_some()
+    }
+
+    /// Filters and sorts the projects, e.g. hiding those whose
+    /// containers are all up and not restarting.
+    pub(crate) fn view(
+        mut self,
+        view: MonitorView,
+    ) -> Self {
+        self.view = view;
+        self
+    }
+
+    /// Lets `keys` change the view while the status is drawn, with the
+    /// selected project marked and the keys listed below.
+    pub(crate) fn keys(
+        mut self,
+        keys: Option<KeyInput>,
+    ) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// The view as last drawn, with the changes made by key presses.
+    pub(crate) fn current_view(&self) -> &MonitorView {
+        &self.view
+    }
+
+    /// Keeps pull progress visible for projects that already have
+    /// containers, for a dedicated `compose pull` run.
+    pub(crate) fn pulling(mut self) -> Self {
+        self.pulling = true;
+        self
+    }
+
+    /// Projects that can't be monitored, shown as a row with the reason
+    /// in place of their status.
+    pub(crate) fn project_errors(
+        mut self,
+        project_errors: BTreeMap<String, String>,
+    ) -> Self {
+        self.project_errors = project_errors;
+        self
+    }
+
+    /// Adds the CPU usage to each project row and a line of CPU and
+    /// memory sparklines per service below the status.
+    pub(crate) fn stats(
+        mut self,
+        stats: Option<StatsView>,
+    ) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Adds a badge to projects with newer images available and a line
+    /// per service with the versions below the status.
+    pub(crate) fn updates(
+        mut self,
+        updates: Option<UpdatesView>,
+    ) -> Self {
+        self.updates = updates;
+        self
+    }
+
+    /// Marks the projects in maintenance with a wrench and the reason.
+    pub(crate) fn maintenance(
+        mut self,
+        maintenance: BTreeMap<String, MaintenanceMarker>,
+    ) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Shows a banner above the status while `daemon` can't reach the
+    /// Docker daemon; the statuses below are the last ones seen.
+    pub(crate) fn daemon_watch(
+        mut self,
+        daemon: DaemonWatch,
+    ) -> Self {
+        self.daemon = Some(daemon);
+        self
+    }
+
+    fn has_problems(
+        &self,
+        status: Option<&ProjectStatus>,
+    ) -> bool {
+        let Some(status) = status else {
+            return true;
+        };
+
+        !matches!(
+            status.project_state(),
+            ProjectState::Healthy | ProjectState::Running
+        ) || self
+            .restarts
+            .restarted(status)
+            .is_some()
+    }
+
+    fn render(
+        &mut self,
+        context: &NirionContext,
+        selected: &[String],
+        phases: &BTreeMap<String, ProjectPhase>,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> String {
+        self.restarts.observe(statuses);
+        if let Some(stats) = &mut self.stats {
+            stats.observe(statuses);
+        }
+        if let Some(updates) = &mut self.updates {
+            updates.observe();
+        }
+
+        let selected = selected
+            .iter()
+            .filter(|name| !self.project_errors.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+        let problems = selected
+            .iter()
+            .filter(|name| self.has_problems(statuses.get(*name)))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let has_problems = |name: &str| problems.contains(name);
+        for key in self
+            .keys
+            .iter()
+            .flat_map(KeyInput::take)
+        {
+            let visible = self
+                .view
+                .visible(&selected, has_problems);
+            self.view.press(key, &visible);
+        }
+        let selected = self
+            .view
+            .visible(&selected, has_problems);
+        let project_errors = self
+            .project_errors
+            .iter()
+            .filter(|(name, _)| self.view.matches(name))
+            .collect::<Vec<_>>();
+
+        let mut rendered = if !selected.is_empty() {
+            let mut status = create_status(
+                self.spinners.as_ref(),
+                self.compose,
+                &selected,
+                phases,
+                statuses,
+                &context.projects,
+                &self.restarts,
+                self.stats.as_ref(),
+                self.updates.as_ref(),
+                &self.maintenance,
+            );
+            if self.keys.is_some() {
+                let index = self.view.selected(selected.len());
+                let entry = &mut status.entries[index];
+                entry.prefix = format!("{} {}", "›".cyan(), entry.prefix);
+            }
+            status.render(terminal_width())
+        } else if !project_errors.is_empty() {
+            String::new()
+        } else if !self.view.filter.is_empty() {
+            format!("{}", "no matching projects".grey())
+        } else if self.view.only_problems {
+            format!("{} {}", "✓".green(), "no problems".grey())
+        } else {
+            String::new()
+        };
+
+        for name in &selected {
+            if let Some(line) = self.pull_status(name, phases, statuses) {
+                rendered.push('\n');
+                rendered.push_str(&line);
+            }
+        }
+        for line in self
+            .stats
+            .iter()
+            .flat_map(|stats| stats.service_lines(&selected))
+        {
+            rendered.push('\n');
+            rendered.push_str(&line);
+        }
+        for line in self
+            .updates
+            .iter()
+            .flat_map(|updates| updates.service_lines(&selected))
+        {
+            rendered.push('\n');
+            rendered.push_str(&line);
+        }
+        for line in self
+            .view
+            .service_lines(&selected, statuses)
+        {
+            rendered.push('\n');
+            rendered.push_str(&line);
+        }
+        for (name, error) in project_errors {
+            if !rendered.is_empty() {
+                rendered.push('\n');
+            }
+            rendered.push_str(&format!("{} {name} {}", "✗".red(), error.red()));
+        }
+
+        let unreachable = self
+            .daemon
+            .as_ref()
+            .is_some_and(|daemon| !daemon.reachable());
+        if unreachable {
+            rendered = format!(
+                "{} {}\n{rendered}",
+                "⚠".yellow(),
+                "docker daemon unreachable, retrying…".yellow()
+            );
+        }
+        if self.keys.is_some() {
+            if !rendered.is_empty() {
+                rendered.push('\n');
+            }
+            rendered.push_str(&self.view.help_line());
+        }
+
+        rendered
+    }
+
+    /// Pull progress is only interesting until the first container
+    /// shows up; after that the status bar says more, unless pulling is
+    /// all this run does.
+    fn pull_status(
+        &self,
+        name: &str,
+        phases: &BTreeMap<String, ProjectPhase>,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) -> Option<String> {
+        let pull = self
+            .pulls
+            .get(name)
+            .filter(|pull| !pull.is_empty())?;
+        let has_containers = statuses
+            .get(name)
+            .is_some_and(|status| status.containers().next().is_some());
+        let running = phases.get(name) == Some(&ProjectPhase::Running);
+
+        (running && (self.pulling || !has_containers))
+            .then(|| pull_line(name, pull))
     }
 }
 
 impl ProgressRenderer for StatusProgressRenderer {
     fn needs_status_during_compose(&self) -> bool {
-        self.spinner.is_some()
+        self.compose
     }
 
     fn start(
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
+        if !self.animated() {
+            return Ok(());
+        }
         self.cursor = Some(HiddenCursorGuard::hide()?);
-        let progress = create_status(
-            self.spinner(),
-            selected,
-            running,
-            statuses,
-            &context.projects,
-        )
-        .render(terminal_width());
+        let progress = self.render(context, selected, phases, statuses);
         self.lines.start(&progress)
     }
 
+    fn compose_event(
+        &mut self,
+        event: &ComposeEvent,
+    ) -> anyhow::Result<()> {
+        if let ComposeEvent::Process {
+            project: Some(project),
+            event:
+                ProcessEvent::StdoutLine(line) | ProcessEvent::StderrLine(line),
+        } = event
+        {
+            self.pulls
+                .entry(project.clone())
+                .or_default()
+                .observe(line, Instant::now());
+        }
+        Ok(())
+    }
+
     fn tick(
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
-        let progress = create_status(
-            self.spinner(),
-            selected,
-            running,
-            statuses,
-            &context.projects,
-        )
-        .render(terminal_width());
+        if !self.animated() {
+            return Ok(());
+        }
+        let progress = self.render(context, selected, phases, statuses);
         self.lines.render(&progress)
     }
 
@@ -237,18 +808,18 @@ impl ProgressRenderer for StatusProgressRenderer {
         &mut self,
         context: &NirionContext,
         selected: &[String],
-        running: &BTreeMap<String, bool>,
+        phases: &BTreeMap<String, ProjectPhase>,
         statuses: &BTreeMap<String, ProjectStatus>,
     ) -> anyhow::Result<()> {
-        let progress = create_status(
-            self.spinner(),
-            selected,
-            running,
-            statuses,
-            &context.projects,
-        )
-        .render(terminal_width());
-        self.lines.finish(&progress)
+        let progress = self.render(context, selected, phases, statuses);
+
+        if !self.compose {
+            return self.lines.finish(&progress);
+        }
+
+        let summary = phase_summary(selected, phases);
+        self.lines
+            .finish(&format!("{progress}\n{summary}"))
     }
 }
 
@@ -276,6 +847,9 @@ pub(crate) fn progress_renderer(
             Box::new(StatusProgressRenderer::with_spinner())
         }
         ProgressPresentation::Plain => Box::new(PlainRenderer),
+        ProgressPresentation::Summary => {
+            Box::new(StatusProgressRenderer::summary_only())
+        }
         ProgressPresentation::Hidden => Box::new(HiddenRenderer),
     }
 }
@@ -297,7 +871,7 @@ fn render_process_event(event: &ProcessEvent) {
     match event {
         ProcessEvent::StdoutLine(line) => println!("{}", line),
         ProcessEvent::StderrLine(line) => {
-            if !line.contains("the attribute `version` is obsolete") {
+            if !ComposeWarningFilter::get().hides(line) {
                 eprintln!("{}", line);
             }
         }
@@ -308,7 +882,14 @@ fn render_process_event(event: &ProcessEvent) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use nirion_lib::docker::{Port, ServiceState, ServiceStatus};
+    use crate::monitor_view::MonitorSort;
+    use nirion_lib::{
+        docker::{ContainerDetails, DockerCommand, Port, ServiceStatus},
+        lock::LockedImages,
+    };
+    use nirion_oci_lib::client::NirionOciClient;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+    use std::sync::Arc;
 
     fn projects() -> Projects {
         serde_json::from_str(
@@ -336,14 +917,18 @@ mod tests {
             id: format!("{service}-id"),
             service: service.to_string(),
             container_name: service.to_string(),
+            index: 1,
             image: "image".to_string(),
             state,
             health: None,
             exit_code: None,
             running_for: None,
+            created_at: None,
             status: None,
             ports: Vec::<Port>::new(),
             networks: Vec::new(),
+            details: None,
+            last_health_check: None,
         }
     }
 
@@ -351,11 +936,21 @@ mod tests {
     fn create_status_uses_empty_status_when_project_has_no_status() {
         let projects = projects();
         let selected = vec!["app".to_string()];
-        let running = BTreeMap::new();
+        let phases = BTreeMap::new();
         let statuses = BTreeMap::new();
 
-        let status =
-            create_status(None, &selected, &running, &statuses, &projects);
+        let status = create_status(
+            None,
+            false,
+            &selected,
+            &phases,
+            &statuses,
+            &projects,
+            &RestartTracker::default(),
+            None,
+            None,
+            &BTreeMap::new(),
+        );
 
         assert_eq!(status.entries.len(), 1);
         assert_eq!(status.entries[0].segments, vec![GREY, GREY]);
@@ -366,30 +961,88 @@ mod tests {
     fn create_status_pads_missing_service_segments() {
         let projects = projects();
         let selected = vec!["app".to_string()];
-        let running = BTreeMap::from([("app".to_string(), false)]);
+        let phases = BTreeMap::from([("app".to_string(), ProjectPhase::Done)]);
         let statuses = BTreeMap::from([(
             "app".to_string(),
             ProjectStatus {
                 services: BTreeMap::from([(
                     "web".to_string(),
-                    service_status("web", ServiceState::Healthy),
+                    vec![service_status("web", ServiceState::Healthy)],
                 )]),
+                foreign: Vec::new(),
             },
         )]);
 
-        let status =
-            create_status(None, &selected, &running, &statuses, &projects);
+        let status = create_status(
+            None,
+            false,
+            &selected,
+            &phases,
+            &statuses,
+            &projects,
+            &RestartTracker::default(),
+            None,
+            None,
+            &BTreeMap::new(),
+        );
 
         assert_eq!(status.entries.len(), 1);
         assert_eq!(status.entries[0].segments.len(), 2);
         assert_eq!(
             status.entries[0].segments[0],
-            project_status_segments(&statuses["app"])[0]
+            project_status_segments(&statuses["app"], &projects["app"])[0]
         );
         assert_eq!(status.entries[0].segments[1], GREY);
         assert_eq!(status.entries[0].suffix, "(1/2)    ");
     }
 
+    #[test]
+    fn create_status_reports_completed_one_shots_outside_the_ratio() {
+        let projects: Projects = serde_json::from_str(
+            r#"
+{
+  "app": {
+    "name": "app",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "web": {"image": "nginx", "healthcheck": false, "restart": "always"},
+      "migrate": {"image": "migrate", "healthcheck": false, "restart": "no"}
+    }
+  }
+}
+"#,
+        )
+        .unwrap();
+        let selected = vec!["app".to_string()];
+        let phases = BTreeMap::from([("app".to_string(), ProjectPhase::Done)]);
+        let statuses = BTreeMap::from([(
+            "app".to_string(),
+            ProjectStatus::from_containers([
+                service_status("web", ServiceState::Running),
+                service_status("migrate", ServiceState::Succeeded),
+            ]),
+        )]);
+
+        let status = create_status(
+            None,
+            false,
+            &selected,
+            &phases,
+            &statuses,
+            &projects,
+            &RestartTracker::default(),
+            None,
+            None,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(status.entries[0].segments.len(), 2);
+        assert_eq!(
+            strip_ansi_codes(&status.entries[0].suffix),
+            "(1/1) 1 completed    "
+        );
+    }
+
     #[test]
     fn status_progress_renderer_needs_status_when_using_spinner() {
         assert!(
@@ -399,25 +1052,371 @@ mod tests {
     }
 
     #[test]
-    fn status_progress_renderer_without_spinner_is_status_only() {
+    fn status_progress_renderer_status_only_skips_compose_status() {
         assert!(
-            !StatusProgressRenderer::without_spinner()
+            !StatusProgressRenderer::status_only()
                 .needs_status_during_compose()
         );
     }
 
+    #[test]
+    fn awaiting_healthchecks_only_counts_existing_unreported_containers() {
+        let projects = projects();
+        let project = &projects["app"];
+        let status = |state| ProjectStatus {
+            services: BTreeMap::from([(
+                "web".to_string(),
+                vec![service_status("web", state)],
+            )]),
+            foreign: Vec::new(),
+        };
+
+        assert!(awaiting_healthchecks(
+            project,
+            &status(ServiceState::Running)
+        ));
+        assert!(!awaiting_healthchecks(
+            project,
+            &status(ServiceState::Healthy)
+        ));
+        assert!(!awaiting_healthchecks(project, &empty_status()));
+    }
+
+    #[test]
+    fn project_icon_distinguishes_compose_and_healthcheck_phases() {
+        let projects = projects();
+        let project = &projects["app"];
+        let spinners = ProgressSpinners::default();
+        let waiting = ProjectStatus {
+            services: BTreeMap::from([(
+                "web".to_string(),
+                vec![service_status("web", ServiceState::Running)],
+            )]),
+            foreign: Vec::new(),
+        };
+
+        let running = strip_ansi_codes(&project_icon(
+            Some(&spinners),
+            ProjectPhase::Running,
+            project,
+            &waiting,
+        ))
+        .to_string();
+        let health = strip_ansi_codes(&project_icon(
+            Some(&spinners),
+            ProjectPhase::WaitingForHealth,
+            project,
+            &waiting,
+        ))
+        .to_string();
+
+        assert!(DOTS.contains(&running.as_str()));
+        assert!(PULSE.contains(&health.as_str()));
+        assert_eq!(
+            strip_ansi_codes(&project_icon(
+                None,
+                ProjectPhase::Done,
+                project,
+                &waiting
+            )),
+            "✓"
+        );
+        assert_eq!(
+            strip_ansi_codes(&project_icon(
+                Some(&spinners),
+                ProjectPhase::Failed,
+                project,
+                &waiting
+            )),
+            "✗"
+        );
+    }
+
+    #[test]
+    fn create_status_shows_phase_label_when_requested() {
+        let projects = projects();
+        let selected = vec!["app".to_string()];
+        let phases = BTreeMap::from([(
+            "app".to_string(),
+            ProjectPhase::WaitingForHealth,
+        )]);
+
+        let status = create_status(
+            None,
+            true,
+            &selected,
+            &phases,
+            &BTreeMap::new(),
+            &projects,
+            &RestartTracker::default(),
+            None,
+            None,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(
+            strip_ansi_codes(&status.entries[0].suffix),
+            "(0/2) waiting for health    "
+        );
+    }
+
+    #[test]
+    fn create_status_marks_projects_in_maintenance() {
+        let maintenance = BTreeMap::from([(
+            "app".to_string(),
+            MaintenanceMarker {
+                reason: Some("db migration".to_string()),
+                user: "alice".to_string(),
+                since: "2026-03-01T12:00:00Z".parse().unwrap(),
+                until: None,
+            },
+        )]);
+
+        let status = create_status(
+            None,
+            false,
+            &["app".to_string()],
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &projects(),
+            &RestartTracker::default(),
+            None,
+            None,
+            &maintenance,
+        );
+
+        assert_eq!(
+            strip_ansi_codes(&status.entries[0].suffix),
+            "(0/2) 🔧 maintenance: db migration    "
+        );
+    }
+
+    #[test]
+    fn restart_tracker_flags_containers_restarting_while_observed() {
+        let with_restarts = |count| {
+            BTreeMap::from([(
+                "app".to_string(),
+                ProjectStatus::from_containers([ServiceStatus {
+                    details: Some(ContainerDetails {
+                        started_at: None,
+                        restart_count: count,
+                        oom_killed: false,
+                    }),
+                    ..service_status("web", ServiceState::Running)
+                }]),
+            )])
+        };
+        let mut restarts = RestartTracker::default();
+
+        let first = with_restarts(3);
+        restarts.observe(&first);
+        assert_eq!(restarts.restarted(&first["app"]), None);
+
+        let later = with_restarts(5);
+        restarts.observe(&later);
+        assert_eq!(restarts.restarted(&later["app"]), Some(5));
+
+        let status = create_status(
+            None,
+            false,
+            &["app".to_string()],
+            &BTreeMap::new(),
+            &later,
+            &projects(),
+            &restarts,
+            None,
+            None,
+            &BTreeMap::new(),
+        );
+        assert_eq!(
+            strip_ansi_codes(&status.entries[0].suffix),
+            "(1/2) ↻ restarting (5)    "
+        );
+    }
+
+    #[test]
+    fn only_problems_hides_projects_that_are_up() {
+        let renderer =
+            StatusProgressRenderer::status_only().view(MonitorView::new(
+                true,
+                String::new(),
+                MonitorSort::Name,
+                BTreeSet::new(),
+            ));
+        let status = |state| {
+            ProjectStatus::from_containers([service_status("web", state)])
+        };
+
+        assert!(!renderer.has_problems(Some(&status(ServiceState::Healthy))));
+        assert!(!renderer.has_problems(Some(&status(ServiceState::Running))));
+        assert!(renderer.has_problems(Some(&status(ServiceState::Failed))));
+        assert!(renderer.has_problems(Some(&status(ServiceState::Starting))));
+        assert!(renderer.has_problems(None));
+    }
+
+    #[test]
+    fn project_errors_replace_the_status_row() {
+        let context = NirionContext {
+            projects: projects(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::default(),
+        };
+        let mut renderer = StatusProgressRenderer::summary_only()
+            .view(MonitorView::new(
+                true,
+                String::new(),
+                MonitorSort::Name,
+                BTreeSet::new(),
+            ))
+            .project_errors(BTreeMap::from([(
+                "gone".to_string(),
+                "/nix/store/gone.yml (not found)".to_string(),
+            )]));
+        let statuses = BTreeMap::from([(
+            "app".to_string(),
+            ProjectStatus::from_containers([
+                service_status("web", ServiceState::Healthy),
+                service_status("db", ServiceState::Healthy),
+            ]),
+        )]);
+
+        let rendered = renderer.render(
+            &context,
+            &["app".to_string(), "gone".to_string()],
+            &BTreeMap::new(),
+            &statuses,
+        );
+
+        assert_eq!(
+            strip_ansi_codes(&rendered),
+            "✗ gone /nix/store/gone.yml (not found)"
+        );
+    }
+
+    #[test]
+    fn view_filters_projects_and_lists_expanded_services() {
+        let context = NirionContext {
+            projects: projects(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::default(),
+        };
+        let mut renderer = StatusProgressRenderer::summary_only()
+            .view(MonitorView::new(
+                false,
+                "AP".into(),
+                MonitorSort::Name,
+                BTreeSet::from(["app".to_string()]),
+            ))
+            .project_errors(BTreeMap::from([(
+                "gone".to_string(),
+                "/nix/store/gone.yml (not found)".to_string(),
+            )]));
+        let statuses = BTreeMap::from([(
+            "app".to_string(),
+            ProjectStatus::from_containers([
+                service_status("web", ServiceState::Healthy),
+                service_status("db", ServiceState::Failed),
+            ]),
+        )]);
+
+        let rendered = strip_ansi_codes(&renderer.render(
+            &context,
+            &["app".to_string(), "gone".to_string()],
+            &BTreeMap::new(),
+            &statuses,
+        ))
+        .to_string();
+
+        assert!(!rendered.contains("gone"));
+        assert!(
+            rendered.ends_with("\n  app.db   failed\n  app.web  healthy"),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn phase_summary_counts_each_phase() {
+        let selected = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let phases = BTreeMap::from([
+            ("a".to_string(), ProjectPhase::Done),
+            ("b".to_string(), ProjectPhase::Done),
+            ("c".to_string(), ProjectPhase::Failed),
+        ]);
+
+        assert_eq!(
+            strip_ansi_codes(&phase_summary(&selected, &phases)),
+            "2 done, 1 failed"
+        );
+    }
+
     #[test]
     fn create_status_formats_status_without_spinner() {
         let projects = projects();
         let selected = vec!["app".to_string()];
-        let running = BTreeMap::new();
+        let phases = BTreeMap::new();
         let statuses = BTreeMap::new();
 
-        let output =
-            create_status(None, &selected, &running, &statuses, &projects)
-                .render(80);
+        let output = create_status(
+            None,
+            false,
+            &selected,
+            &phases,
+            &statuses,
+            &projects,
+            &RestartTracker::default(),
+            None,
+            None,
+            &BTreeMap::new(),
+        )
+        .render(80);
 
         assert!(output.contains("app"));
         assert!(output.contains("(0/2)"));
     }
+
+    #[test]
+    fn pull_status_shows_until_first_container_appears() {
+        let mut renderer = StatusProgressRenderer::with_spinner();
+        let phases =
+            BTreeMap::from([("app".to_string(), ProjectPhase::Running)]);
+        let mut statuses = BTreeMap::new();
+        let pulling = |line: &str| ComposeEvent::Process {
+            project: Some("app".to_string()),
+            event: ProcessEvent::StderrLine(line.to_string()),
+        };
+
+        renderer
+            .compose_event(&pulling(" web Pulling "))
+            .unwrap();
+        assert_eq!(renderer.pull_status("app", &phases, &statuses), None);
+
+        for line in [
+            " a2abf6c4d29d Pulling fs layer ",
+            " 8a1e25ce7c4f Pull complete ",
+        ] {
+            renderer
+                .compose_event(&pulling(line))
+                .unwrap();
+        }
+        let line = renderer
+            .pull_status("app", &phases, &statuses)
+            .unwrap();
+        assert_eq!(strip_ansi_codes(&line), "app ↓ pulling 1/2 layers");
+
+        statuses.insert(
+            "app".to_string(),
+            ProjectStatus {
+                services: BTreeMap::from([(
+                    "web".to_string(),
+                    vec![service_status("web", ServiceState::Created)],
+                )]),
+                foreign: Vec::new(),
+            },
+        );
+        assert_eq!(renderer.pull_status("app", &phases, &statuses), None);
+    }
 }
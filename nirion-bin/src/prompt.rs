@@ -0,0 +1,212 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    sync::OnceLock,
+};
+
+use nirion_tui_lib::color::Colorize;
+
+static ASSUME_YES: OnceLock<bool> = OnceLock::new();
+
+/// Answers every confirmation with yes, from the global `--yes`.
+pub fn assume_yes(yes: bool) -> anyhow::Result<()> {
+    ASSUME_YES
+        .set(yes)
+        .map_err(|_| anyhow::anyhow!("--yes already initialized"))
+}
+
+/// Whether confirmations are answered with yes without asking.
+pub fn assumes_yes() -> bool {
+    ASSUME_YES
+        .get()
+        .copied()
+        .unwrap_or_default()
+}
+
+/// A yes/no question asked on stderr.
+#[derive(Debug, Clone)]
+pub struct Confirm {
+    question: String,
+    default: bool,
+    destructive: bool,
+}
+
+impl Confirm {
+    /// A question answered with `default` by just pressing enter.
+    pub fn new(
+        question: impl Into<String>,
+        default: bool,
+    ) -> Self {
+        Self {
+            question: question.into(),
+            default,
+            destructive: false,
+        }
+    }
+
+    /// Highlights the question as something that is hard to undo.
+    pub fn destructive(mut self) -> Self {
+        self.destructive = true;
+        self
+    }
+
+    /// Yes with `--yes`, otherwise the answer from the terminal. Without
+    /// a terminal to ask on, fails and points at `--yes`.
+    pub fn ask(&self) -> anyhow::Result<bool> {
+        if assumes_yes() {
+            return Ok(true);
+        }
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "{}\nrefusing without a terminal to confirm on; pass --yes \
+                 to go ahead",
+                self.question
+            );
+        }
+        self.ask_with(std::io::stdin().lock(), std::io::stderr())
+    }
+
+    fn ask_with(
+        &self,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> anyhow::Result<bool> {
+        let hint = if self.default { "[Y/n]" } else { "[y/N]" };
+        let question = if self.destructive {
+            self.question
+                .as_str()
+                .red()
+                .bold()
+                .for_stderr()
+                .to_string()
+        } else {
+            self.question.clone()
+        };
+
+        loop {
+            write!(output, "{question} {hint} ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(false);
+            }
+
+            match line.trim().to_lowercase().as_str() {
+                "" => return Ok(self.default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => writeln!(output, "{}", "Please answer y or n".red())?,
+            }
+        }
+    }
+}
+
+/// Reads one line from stdin. On a terminal `label` is asked for first,
+/// and with `hidden` the answer isn't echoed; piped input is read as it
+/// is, e.g. a password from a secret manager.
+pub fn read_line(
+    label: &str,
+    hidden: bool,
+) -> anyhow::Result<String> {
+    let stdin = std::io::stdin();
+    let terminal = stdin.is_terminal();
+    if terminal {
+        eprint!("{label}: ");
+        std::io::stderr().flush()?;
+    }
+
+    let mut line = String::new();
+    {
+        let _echo = (terminal && hidden)
+            .then(EchoOff::new)
+            .transpose()?;
+        stdin.lock().read_line(&mut line)?;
+    }
+    if terminal && hidden {
+        eprintln!();
+    }
+
+    Ok(line
+        .trim_end_matches(['\r', '\n'])
+        .to_string())
+}
+
+/// Turns off terminal echo on stdin until dropped.
+struct EchoOff {
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+impl EchoOff {
+    #[cfg(unix)]
+    fn new() -> anyhow::Result<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in before
+        // it is read.
+        unsafe {
+            let mut saved = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            let mut quiet = saved;
+            quiet.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &quiet);
+            Ok(Self { saved })
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> anyhow::Result<Self> {
+        Ok(Self {})
+    }
+}
+
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in new.
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(
+        confirm: &Confirm,
+        input: &str,
+    ) -> (bool, String) {
+        let mut output = Vec::new();
+        let answer = confirm
+            .ask_with(input.as_bytes(), &mut output)
+            .unwrap();
+        (answer, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn answers_fall_back_to_the_default_and_reprompt_on_garbage() {
+        let confirm = Confirm::new("Take down 3 projects?", false);
+        let default_yes = Confirm::new("Take down 3 projects?", true);
+
+        assert_eq!(
+            answer(&confirm, "\n"),
+            (false, "Take down 3 projects? [y/N] ".to_string())
+        );
+        assert!(answer(&default_yes, "\n").0);
+        assert!(answer(&confirm, " YES \n").0);
+        assert!(!answer(&default_yes, "n\n").0);
+
+        let (yes, output) = answer(&confirm, "maybe\ny\n");
+        assert!(yes);
+        assert_eq!(output.matches("[y/N]").count(), 2);
+    }
+
+    #[test]
+    fn closed_input_answers_no() {
+        let confirm = Confirm::new("Go?", true);
+        assert!(!answer(&confirm, "").0);
+    }
+}
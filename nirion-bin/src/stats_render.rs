@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use nirion_lib::{
+    docker::{DockerCommand, ProjectStatus, ServiceState},
+    stats::{ResourceUsage, StatsHistory, StatsSampler},
+};
+use nirion_tui_lib::{ansi::lpad_ansi, color::Colorize, sparkline::sparkline};
+
+/// Samples shown in a service's sparklines.
+const SPARKLINE_WIDTH: usize = 20;
+
+/// Sparklines scale to their peak, but never below this many percent, so
+/// an idle service doesn't draw noise as full-height spikes.
+const MIN_SPARKLINE_SCALE: f64 = 1.0;
+
+/// `docker stats` samples of the monitored containers and their recent
+/// history, for the monitor's `--stats` view.
+pub(crate) struct StatsView {
+    sampler: StatsSampler,
+    history: StatsHistory,
+}
+
+impl StatsView {
+    pub(crate) fn spawn(
+        docker_command: DockerCommand,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            sampler: StatsSampler::spawn(docker_command, interval),
+            history: StatsHistory::default(),
+        }
+    }
+
+    /// Points the sampler at the running containers of `statuses` and
+    /// records the latest sample, if a new one arrived.
+    pub(crate) fn observe(
+        &mut self,
+        statuses: &BTreeMap<String, ProjectStatus>,
+    ) {
+        let running = statuses
+            .values()
+            .flat_map(|status| status.containers())
+            .filter(|container| {
+                matches!(
+                    container.state,
+                    ServiceState::Running
+                        | ServiceState::Healthy
+                        | ServiceState::Unhealthy
+                        | ServiceState::Starting
+                )
+            })
+            .map(|container| container.container_name.clone())
+            .collect();
+        self.sampler.watch(running);
+
+        if let Some(sample) = self.sampler.take_sample() {
+            self.history.record(statuses, &sample);
+        }
+    }
+
+    /// The CPU usage of the whole project, for its status row.
+    pub(crate) fn project_label(
+        &self,
+        project: &str,
+    ) -> Option<String> {
+        self.history
+            .project_usage(project)
+            .map(|usage| format!("cpu {:.1}%", usage.cpu_percent))
+    }
+
+    /// A line per sampled service of `projects`, with CPU and memory
+    /// sparklines of the recent samples.
+    pub(crate) fn service_lines(
+        &self,
+        projects: &[String],
+    ) -> Vec<String> {
+        let services = projects
+            .iter()
+            .flat_map(|project| {
+                self.history
+                    .services(project)
+                    .map(move |(service, history)| {
+                        (format!("{project}.{service}"), history)
+                    })
+            })
+            .collect::<Vec<_>>();
+        let width = services
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        services
+            .iter()
+            .map(|(label, history)| {
+                let recent = history
+                    .iter()
+                    .skip(
+                        history
+                            .len()
+                            .saturating_sub(SPARKLINE_WIDTH),
+                    )
+                    .copied()
+                    .collect::<Vec<_>>();
+                format!(
+                    "  {}  cpu {}  mem {}",
+                    lpad_ansi(&label.as_str().grey().to_string(), width),
+                    usage_trend(&recent, |usage| usage.cpu_percent),
+                    usage_trend(&recent, |usage| usage.memory_percent),
+                )
+            })
+            .collect()
+    }
+}
+
+/// A sparkline of `value` over `samples` followed by the latest value.
+fn usage_trend(
+    samples: &[ResourceUsage],
+    value: impl Fn(&ResourceUsage) -> f64,
+) -> String {
+    let values = samples
+        .iter()
+        .map(value)
+        .collect::<Vec<_>>();
+    let peak = values
+        .iter()
+        .copied()
+        .fold(MIN_SPARKLINE_SCALE, f64::max);
+    let latest = values
+        .last()
+        .copied()
+        .unwrap_or_default();
+
+    format!(
+        "{} {:>5.1}%",
+        lpad_ansi(
+            &sparkline(values, peak)
+                .cyan()
+                .to_string(),
+            SPARKLINE_WIDTH
+        ),
+        latest
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+
+    #[test]
+    fn usage_trend_scales_to_the_peak_and_shows_the_latest_value() {
+        let samples = [2.0, 4.0, 8.0].map(|cpu_percent| ResourceUsage {
+            cpu_percent,
+            memory_percent: 0.5,
+        });
+
+        assert_eq!(
+            strip_ansi_codes(&usage_trend(&samples, |usage| usage.cpu_percent)),
+            format!("▃▅█{}   8.0%", " ".repeat(SPARKLINE_WIDTH - 3))
+        );
+        assert_eq!(
+            strip_ansi_codes(&usage_trend(&samples, |usage| {
+                usage.memory_percent
+            })),
+            format!("▅▅▅{}   0.5%", " ".repeat(SPARKLINE_WIDTH - 3))
+        );
+    }
+}
@@ -1,5 +1,11 @@
-use nirion_lib::docker::{
-    ProjectState, ProjectStatus, ServiceState, ServiceStatus,
+use std::{collections::BTreeSet, time::Duration};
+
+use nirion_lib::{
+    docker::{
+        ProjectState, ProjectStatus, ServiceState, ServiceStatus,
+        take_unrecognized_states,
+    },
+    projects::Project,
 };
 use nirion_tui_lib::color::{Color, Colorize, DARK_GREY, GREY};
 
@@ -17,35 +23,64 @@ pub fn project_state_icon(state: &ProjectState) -> String {
     }
 }
 
-pub fn project_status_segments(status: &ProjectStatus) -> Vec<Color> {
-    let mut services: Vec<&ServiceStatus> = status.services.values().collect();
+pub fn project_status_segments(
+    status: &ProjectStatus,
+    project: &Project,
+) -> Vec<Color> {
+    let mut services: Vec<&ServiceStatus> = status.containers().collect();
 
-    services.sort_by_key(|s| service_state_order(&s.state));
+    services.sort_by_key(|s| segment_order(s, project));
 
     services
         .into_iter()
-        .map(|s| service_state_color(&s.state))
+        .map(|s| segment_color(s, project))
         .collect()
 }
 
+/// Long-running services that exited with 0 were stopped rather than
+/// completed, so they sort and render with the inactive containers.
+fn exited_long_running(
+    service: &ServiceStatus,
+    project: &Project,
+) -> bool {
+    service.state == ServiceState::Succeeded && !service.is_completed(project)
+}
+
+fn segment_order(
+    service: &ServiceStatus,
+    project: &Project,
+) -> usize {
+    if exited_long_running(service, project) {
+        service_state_order(&ServiceState::Created)
+    } else {
+        service_state_order(&service.state)
+    }
+}
+
+fn segment_color(
+    service: &ServiceStatus,
+    project: &Project,
+) -> Color {
+    if exited_long_running(service, project) {
+        DARK_GREY
+    } else {
+        service_state_color(&service.state)
+    }
+}
+
 fn service_state_order(state: &ServiceState) -> usize {
-    let order = [
-        ServiceState::Healthy,
-        ServiceState::Succeeded,
-        ServiceState::Running,
-        ServiceState::Paused,
-        ServiceState::Starting,
-        ServiceState::Restarting,
-        ServiceState::Failed,
-        ServiceState::Unhealthy,
-        ServiceState::Created,
-        ServiceState::Unknown,
-    ];
-
-    order
-        .iter()
-        .position(|s| s == state)
-        .unwrap_or_default()
+    match state {
+        ServiceState::Healthy => 0,
+        ServiceState::Succeeded => 1,
+        ServiceState::Running => 2,
+        ServiceState::Paused => 3,
+        ServiceState::Starting => 4,
+        ServiceState::Restarting => 5,
+        ServiceState::Failed => 6,
+        ServiceState::Unhealthy => 7,
+        ServiceState::Created => 8,
+        ServiceState::Unknown(_) => 9,
+    }
 }
 
 fn service_state_color(state: &ServiceState) -> Color {
@@ -59,7 +94,75 @@ fn service_state_color(state: &ServiceState) -> Color {
         ServiceState::Failed => Color::Magenta,
         ServiceState::Healthy => Color::Green,
         ServiceState::Unhealthy => Color::Red,
-        ServiceState::Unknown => GREY,
+        ServiceState::Unknown(_) => GREY,
+    }
+}
+
+/// The unrecognized states among `status`'s containers, dimmed, so the
+/// raw value compose reported stays visible.
+pub fn unknown_states_label(status: &ProjectStatus) -> Option<String> {
+    let states = status
+        .containers()
+        .filter_map(|container| match &container.state {
+            ServiceState::Unknown(raw) => Some(raw.as_str()),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    if states.is_empty() {
+        return None;
+    }
+
+    let states = states
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(
+        format!("state: {states}")
+            .grey()
+            .to_string(),
+    )
+}
+
+/// How many containers share the project's compose name without having
+/// been created from its compose file, see [`ProjectStatus::foreign`].
+pub fn foreign_containers_label(status: &ProjectStatus) -> Option<String> {
+    match status.foreign.len() {
+        0 => None,
+        count => Some(
+            format!("{count} not managed by this project file")
+                .yellow()
+                .to_string(),
+        ),
+    }
+}
+
+/// The two largest units of `uptime`, like `2h13m` or `5m07s`, so an
+/// uptime column stays narrow and its values line up.
+pub fn format_uptime(uptime: Duration) -> String {
+    let seconds = uptime.as_secs();
+    let (minutes, hours, days) =
+        (seconds / 60, seconds / 3600, seconds / 86400);
+    if days > 0 {
+        format!("{days}d{:02}h", hours % 24)
+    } else if hours > 0 {
+        format!("{hours}h{:02}m", minutes % 60)
+    } else if minutes > 0 {
+        format!("{minutes}m{:02}s", seconds % 60)
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Warns once per container state compose reported that nirion doesn't
+/// recognize yet.
+pub fn warn_unrecognized_states() {
+    for state in take_unrecognized_states() {
+        eprintln!(
+            "{} docker reported the container state '{state}', which nirion \
+             doesn't recognize; please report it at \
+             https://github.com/FlorianNAdam/nirion/issues",
+            "warning:".yellow()
+        );
     }
 }
 
@@ -77,17 +180,38 @@ mod tests {
             id: format!("{service}-id"),
             service: service.to_string(),
             container_name: service.to_string(),
+            index: 1,
             image: "image".to_string(),
             state,
             health: None,
             exit_code: None,
             running_for: None,
+            created_at: None,
             status: None,
             ports: Vec::new(),
             networks: Vec::new(),
+            details: None,
+            last_health_check: None,
         }
     }
 
+    fn project(restart: &[(&str, Option<&str>)]) -> Project {
+        serde_json::from_value(serde_json::json!({
+            "name": "app",
+            "dockerCompose": "compose.yml",
+            "services": restart
+                .iter()
+                .map(|(name, restart)| {
+                    (
+                        name.to_string(),
+                        serde_json::json!({"image": "image", "restart": restart}),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>(),
+        }))
+        .unwrap()
+    }
+
     #[test]
     fn project_state_icon_renders_expected_visible_symbols() {
         let cases = [
@@ -111,21 +235,22 @@ mod tests {
             services: BTreeMap::from([
                 (
                     "failed".to_string(),
-                    service_status("failed", ServiceState::Failed),
+                    vec![service_status("failed", ServiceState::Failed)],
                 ),
                 (
                     "healthy".to_string(),
-                    service_status("healthy", ServiceState::Healthy),
+                    vec![service_status("healthy", ServiceState::Healthy)],
                 ),
                 (
                     "running".to_string(),
-                    service_status("running", ServiceState::Running),
+                    vec![service_status("running", ServiceState::Running)],
                 ),
             ]),
+            foreign: Vec::new(),
         };
 
         assert_eq!(
-            project_status_segments(&status),
+            project_status_segments(&status, &project(&[])),
             vec![
                 service_state_color(&ServiceState::Healthy),
                 service_state_color(&ServiceState::Running),
@@ -134,6 +259,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn project_status_segments_separates_completed_one_shots_from_exits() {
+        let status = ProjectStatus::from_containers([
+            service_status("web", ServiceState::Succeeded),
+            service_status("migrate", ServiceState::Succeeded),
+            service_status("db", ServiceState::Running),
+        ]);
+        let project = project(&[
+            ("web", Some("unless-stopped")),
+            ("migrate", Some("no")),
+            ("db", Some("always")),
+        ]);
+
+        assert_eq!(
+            project_status_segments(&status, &project),
+            vec![
+                service_state_color(&ServiceState::Succeeded),
+                service_state_color(&ServiceState::Running),
+                DARK_GREY,
+            ]
+        );
+    }
+
     #[test]
     fn service_state_color_keeps_semantic_groups_distinct() {
         let healthy = service_state_color(&ServiceState::Healthy);
@@ -145,7 +293,10 @@ mod tests {
         let neutral = service_state_color(&ServiceState::Created);
         let active = service_state_color(&ServiceState::Starting);
 
-        assert_eq!(neutral, service_state_color(&ServiceState::Unknown));
+        assert_eq!(
+            neutral,
+            service_state_color(&ServiceState::Unknown("dead".into()))
+        );
         assert_eq!(active, service_state_color(&ServiceState::Restarting));
 
         let primary_colors =
@@ -158,4 +309,47 @@ mod tests {
 
         assert_ne!(neutral, active);
     }
+
+    #[test]
+    fn unknown_states_sort_last_and_keep_their_raw_value() {
+        let status = ProjectStatus::from_containers([
+            service_status("web", ServiceState::Unknown("dead".into())),
+            service_status("db", ServiceState::Created),
+            service_status("cache", ServiceState::Unknown("dead".into())),
+        ]);
+
+        assert_eq!(
+            project_status_segments(&status, &project(&[])),
+            vec![GREY, GREY, GREY]
+        );
+        assert!(
+            service_state_order(&ServiceState::Unknown("dead".into()))
+                > service_state_order(&ServiceState::Created)
+        );
+        assert_eq!(
+            unknown_states_label(&status)
+                .map(|label| strip_ansi_codes(&label).to_string()),
+            Some("state: dead".to_string())
+        );
+        assert_eq!(
+            unknown_states_label(&ProjectStatus::from_containers([
+                service_status("db", ServiceState::Created)
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn uptimes_show_their_two_largest_units() {
+        assert_eq!(format_uptime(Duration::from_secs(42)), "42s");
+        assert_eq!(format_uptime(Duration::from_secs(5 * 60 + 7)), "5m07s");
+        assert_eq!(
+            format_uptime(Duration::from_secs(2 * 3600 + 13 * 60 + 59)),
+            "2h13m"
+        );
+        assert_eq!(
+            format_uptime(Duration::from_secs(3 * 86400 + 4 * 3600)),
+            "3d04h"
+        );
+    }
 }
@@ -0,0 +1,359 @@
+use std::{collections::BTreeMap, io::IsTerminal, time::Duration};
+
+use clap::ValueEnum;
+use futures::{StreamExt, stream::BoxStream};
+use nirion_lib::events::LockUpdateEvent;
+use nirion_tui_lib::{
+    line_renderer::LineRenderer,
+    spinner::Spinner,
+    terminal::{HiddenCursorGuard, terminal_height, terminal_width},
+};
+
+use crate::{
+    commands::lock::{
+        format_lock_update_event, format_quiet_lock_update_event,
+    },
+    output::OutputOptions,
+};
+
+/// Rows left free below the spinners so the shell prompt and the first
+/// lines of the final output never push them off screen.
+const TERMINAL_MARGIN: usize = 4;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Full on terminals tall enough for every job, compact otherwise
+    Auto,
+    /// One spinner per image being checked
+    Full,
+    /// A single line naming the images being checked
+    Compact,
+    /// No progress output, only the results
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProgressLayout {
+    /// Plain `Checking ...` / `Resolved ...` lines for non-terminals.
+    Lines,
+    Full,
+    Compact,
+    Hidden,
+}
+
+impl ProgressMode {
+    fn layout(
+        self,
+        is_terminal: bool,
+        jobs: usize,
+        height: usize,
+    ) -> ProgressLayout {
+        match self {
+            ProgressMode::None => ProgressLayout::Hidden,
+            _ if !is_terminal => ProgressLayout::Lines,
+            ProgressMode::Full => ProgressLayout::Full,
+            ProgressMode::Compact => ProgressLayout::Compact,
+            ProgressMode::Auto if jobs < visible_rows(height) => {
+                ProgressLayout::Full
+            }
+            ProgressMode::Auto => ProgressLayout::Compact,
+        }
+    }
+}
+
+fn visible_rows(height: usize) -> usize {
+    height
+        .saturating_sub(TERMINAL_MARGIN)
+        .max(2)
+}
+
+/// The images currently being checked, in the order they started.
+#[derive(Debug, Default)]
+struct UpdateProgress {
+    total: usize,
+    resolved: usize,
+    checking: BTreeMap<usize, (String, String)>,
+    next: usize,
+}
+
+impl UpdateProgress {
+    fn new(total: usize) -> Self {
+        Self {
+            total,
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether `event` was a per-image progress event.
+    fn observe(
+        &mut self,
+        event: &LockUpdateEvent,
+    ) -> bool {
+        match event {
+            LockUpdateEvent::ImageStarted { service, image } => {
+                self.checking
+                    .insert(self.next, (service.clone(), image.clone()));
+                self.next += 1;
+                true
+            }
+            LockUpdateEvent::ImageResolved { service, .. } => {
+                self.checking
+                    .retain(|_, (checking, _)| checking != service);
+                self.resolved += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn counter(&self) -> String {
+        format!("resolved {}/{}", self.resolved, self.total)
+    }
+
+    /// One spinner per image, capped at `rows` lines including the
+    /// counter; images that don't fit are summarized as queued.
+    fn render_full(
+        &self,
+        frame: &str,
+        rows: usize,
+    ) -> String {
+        let fits = self.checking.len() < rows;
+        let shown = if fits {
+            self.checking.len()
+        } else {
+            rows.saturating_sub(2)
+        };
+
+        let mut lines = self
+            .checking
+            .values()
+            .take(shown)
+            .map(|(service, image)| format!("{frame} {service}: {image}"))
+            .collect::<Vec<_>>();
+        if !fits {
+            lines.push(format!(
+                "  … {} more checking",
+                self.checking.len() - shown
+            ));
+        }
+        lines.push(self.counter());
+        lines.join("\n")
+    }
+
+    fn render_compact(
+        &self,
+        frame: &str,
+        width: usize,
+    ) -> String {
+        let mut line = format!("{frame} {}", self.counter());
+        if !self.checking.is_empty() {
+            let services = self
+                .checking
+                .values()
+                .map(|(service, _)| service.as_str())
+                .collect::<Vec<_>>();
+            line.push_str(&format!(", checking: {}", services.join(", ")));
+        }
+        truncate(&line, width)
+    }
+}
+
+fn truncate(
+    line: &str,
+    width: usize,
+) -> String {
+    if line.chars().count() <= width {
+        return line.to_string();
+    }
+    let mut truncated = line
+        .chars()
+        .take(width.saturating_sub(1))
+        .collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+/// Prints lock update events, drawing per-image progress according to
+/// `mode` unless `output` turns it off. `on_event` sees every event
+/// before it is printed.
+pub(crate) async fn print_lock_update_events(
+    mut events: BoxStream<'static, anyhow::Result<LockUpdateEvent>>,
+    total: usize,
+    jobs: usize,
+    mode: ProgressMode,
+    output: OutputOptions,
+    mut on_event: impl FnMut(&LockUpdateEvent),
+) -> anyhow::Result<()> {
+    let layout = output.progress_mode(mode).layout(
+        std::io::stdout().is_terminal(),
+        jobs,
+        terminal_height(),
+    );
+    let mut progress = UpdateProgress::new(total);
+    let mut lines = LineRenderer::default();
+    let mut cursor = None;
+    let spinner = Spinner::default();
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+
+    let render = |progress: &UpdateProgress| match layout {
+        ProgressLayout::Full => progress
+            .render_full(&spinner.get(), visible_rows(terminal_height())),
+        _ => progress.render_compact(&spinner.get(), terminal_width()),
+    };
+    let animated =
+        matches!(layout, ProgressLayout::Full | ProgressLayout::Compact);
+
+    loop {
+        let event = tokio::select! {
+            event = events.next() => event,
+            _ = ticker.tick(), if animated && cursor.is_some() => {
+                lines.render(&render(&progress))?;
+                continue;
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                if cursor.take().is_some() {
+                    lines.finish("")?;
+                }
+                return Err(error);
+            }
+        };
+        on_event(&event);
+
+        if matches!(event, LockUpdateEvent::TagSearchCapped { .. }) {
+            if !output.quiet {
+                if cursor.take().is_some() {
+                    lines.finish("")?;
+                }
+                eprintln!("{}", format_lock_update_event(event));
+            }
+            continue;
+        }
+
+        if !progress.observe(&event) {
+            // Clear the spinners before anything else is printed so no
+            // bar fragments end up between the result lines.
+            if cursor.take().is_some() {
+                lines.finish("")?;
+            }
+            let message = if output.quiet {
+                format_quiet_lock_update_event(event)
+            } else {
+                Some(format_lock_update_event(event))
+            };
+            if let Some(message) = message {
+                println!("{message}");
+            }
+            continue;
+        }
+
+        match layout {
+            ProgressLayout::Lines => {
+                println!("{}", format_lock_update_event(event))
+            }
+            ProgressLayout::Hidden => {}
+            ProgressLayout::Full | ProgressLayout::Compact => {
+                if cursor.is_none() {
+                    cursor = Some(HiddenCursorGuard::hide()?);
+                }
+                lines.render(&render(&progress))?;
+            }
+        }
+    }
+
+    if cursor.take().is_some() {
+        lines.finish("")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_lib::events::DigestSource;
+
+    fn started(service: &str) -> LockUpdateEvent {
+        LockUpdateEvent::ImageStarted {
+            service: service.to_string(),
+            image: format!("{service}:latest"),
+        }
+    }
+
+    fn resolved(service: &str) -> LockUpdateEvent {
+        LockUpdateEvent::ImageResolved {
+            service: service.to_string(),
+            source: DigestSource::Registry,
+        }
+    }
+
+    #[test]
+    fn auto_layout_depends_on_terminal_and_height() {
+        assert_eq!(
+            ProgressMode::Auto.layout(false, 16, 50),
+            ProgressLayout::Lines
+        );
+        assert_eq!(
+            ProgressMode::Auto.layout(true, 4, 50),
+            ProgressLayout::Full
+        );
+        assert_eq!(
+            ProgressMode::Auto.layout(true, 16, 12),
+            ProgressLayout::Compact
+        );
+        assert_eq!(
+            ProgressMode::Full.layout(true, 16, 12),
+            ProgressLayout::Full
+        );
+        assert_eq!(
+            ProgressMode::None.layout(false, 1, 50),
+            ProgressLayout::Hidden
+        );
+    }
+
+    #[test]
+    fn full_render_caps_spinners_to_visible_rows() {
+        let mut progress = UpdateProgress::new(6);
+        for service in ["a", "b", "c", "d", "e"] {
+            progress.observe(&started(service));
+        }
+        progress.observe(&resolved("b"));
+
+        assert_eq!(
+            progress.render_full("*", 4),
+            "* a: a:latest\n* c: c:latest\n  … 2 more checking\nresolved 1/6"
+        );
+        assert_eq!(
+            progress
+                .render_full("*", 10)
+                .lines()
+                .count(),
+            5
+        );
+    }
+
+    #[test]
+    fn compact_render_lists_services_within_width() {
+        let mut progress = UpdateProgress::new(3);
+        progress.observe(&started("app.web"));
+        progress.observe(&started("app.db"));
+
+        assert_eq!(
+            progress.render_compact("*", 80),
+            "* resolved 0/3, checking: app.web, app.db"
+        );
+        assert_eq!(progress.render_compact("*", 12), "* resolved …");
+    }
+
+    #[test]
+    fn result_events_are_not_progress() {
+        let mut progress = UpdateProgress::new(1);
+        assert!(!progress.observe(&LockUpdateEvent::UpToDate));
+        assert!(progress.observe(&started("a")));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use nirion_lib::{
+    context::NirionContext,
+    lock::{DiffEntry, VersionedImage},
+    update_check::UpdateChecker,
+};
+use nirion_tui_lib::{ansi::lpad_ansi, color::Colorize};
+
+use crate::output::OutputOptions;
+
+/// Concurrent digest fetches of a background check, below `update`'s
+/// default as nobody is waiting for the result.
+const CHECK_JOBS: usize = 4;
+
+/// The outcome of the latest background update check, for the
+/// monitor's `--check-updates` view.
+pub(crate) struct UpdatesView {
+    checker: UpdateChecker,
+    updates: Vec<DiffEntry>,
+}
+
+impl UpdatesView {
+    pub(crate) fn spawn(
+        context: &NirionContext,
+        images: BTreeMap<String, String>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            checker: UpdateChecker::spawn(
+                context, images, interval, CHECK_JOBS,
+            ),
+            updates: Vec::new(),
+        }
+    }
+
+    /// Picks up the result of a check that finished since the last call.
+    pub(crate) fn observe(&mut self) {
+        if let Some(updates) = self.checker.take_updates() {
+            self.updates = updates;
+        }
+    }
+
+    fn project_updates<'a>(
+        &'a self,
+        project: &'a str,
+    ) -> impl Iterator<Item = &'a DiffEntry> {
+        self.updates.iter().filter(move |diff| {
+            diff.service()
+                .split_once('.')
+                .is_some_and(|(name, _)| name == project)
+        })
+    }
+
+    /// `⬆ 2 updates` for projects with newer images available.
+    pub(crate) fn project_label(
+        &self,
+        project: &str,
+    ) -> Option<String> {
+        let count = self.project_updates(project).count();
+        (count > 0).then(|| {
+            let noun = if count == 1 { "update" } else { "updates" };
+            format!("⬆ {count} {noun}")
+                .magenta()
+                .to_string()
+        })
+    }
+
+    /// A line per service of `projects` with a newer image available,
+    /// from the locked version to the new one.
+    pub(crate) fn service_lines(
+        &self,
+        projects: &[String],
+    ) -> Vec<String> {
+        let updates = projects
+            .iter()
+            .flat_map(|project| self.project_updates(project))
+            .filter_map(|diff| match diff {
+                DiffEntry::Updated { service, old, new } => {
+                    Some((service.as_str(), old, new))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let width = updates
+            .iter()
+            .map(|(service, ..)| service.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        updates
+            .iter()
+            .map(|(service, old, new)| {
+                format!(
+                    "  {}  {} {} -> {}",
+                    lpad_ansi(&service.grey().to_string(), width),
+                    "⬆".magenta(),
+                    version(old),
+                    version(new)
+                )
+            })
+            .collect()
+    }
+}
+
+/// The image's version, or its digest if it has none.
+fn version(image: &VersionedImage) -> String {
+    image
+        .version
+        .clone()
+        .unwrap_or_else(|| {
+            OutputOptions::get()
+                .digest(&image.digest)
+                .to_string()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nirion_lib::{docker::DockerCommand, lock::LockedImages};
+    use nirion_oci_lib::client::NirionOciClient;
+    use nirion_tui_lib::ansi::strip_ansi_codes;
+    use std::sync::Arc;
+
+    fn image(
+        version: Option<&str>,
+        digest: &str,
+    ) -> VersionedImage {
+        VersionedImage {
+            image: "nginx:latest".to_string(),
+            version: version.map(str::to_string),
+            digest: digest.to_string(),
+            size: None,
+            source: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_are_counted_per_project_and_listed_per_service() {
+        let context = NirionContext {
+            projects: Default::default(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::default(),
+        };
+        let mut view = UpdatesView::spawn(
+            &context,
+            BTreeMap::new(),
+            Duration::from_secs(3600),
+        );
+        view.updates = vec![
+            DiffEntry::Updated {
+                service: "app.web".to_string(),
+                old: image(Some("1.0.0"), "sha256:aaaa"),
+                new: image(Some("1.2.3"), "sha256:bbbb"),
+            },
+            DiffEntry::Updated {
+                service: "app.db".to_string(),
+                old: image(None, "sha256:cccc"),
+                new: image(None, "sha256:dddd"),
+            },
+            DiffEntry::Updated {
+                service: "other.web".to_string(),
+                old: image(Some("1"), "sha256:eeee"),
+                new: image(Some("2"), "sha256:ffff"),
+            },
+        ];
+
+        let label = |project| {
+            view.project_label(project)
+                .map(|label| strip_ansi_codes(&label).to_string())
+        };
+        assert_eq!(label("app").as_deref(), Some("⬆ 2 updates"));
+        assert_eq!(label("other").as_deref(), Some("⬆ 1 update"));
+        assert_eq!(label("unknown"), None);
+
+        let lines = view
+            .service_lines(&["app".to_string()])
+            .iter()
+            .map(|line| strip_ansi_codes(line).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            ["  app.web  ⬆ 1.0.0 -> 1.2.3", "  app.db   ⬆ cccc -> dddd",]
+        );
+    }
+}
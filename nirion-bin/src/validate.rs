@@ -0,0 +1,104 @@
+use nirion_lib::{
+    compose_file::{image_mismatches, service_mismatch},
+    projects::Projects,
+};
+use nirion_tui_lib::color::Colorize;
+
+/// Warns about projects whose services don't match their compose file,
+/// which otherwise only shows up as containers missing from the status.
+pub fn warn_service_mismatches(projects: &Projects) {
+    for (name, project) in projects.iter() {
+        let mismatch = match service_mismatch(project) {
+            Ok(Some(mismatch)) => mismatch,
+            Ok(None) => continue,
+            Err(error) => {
+                eprintln!(
+                    "{} [{name}] can't check services: {error:#}",
+                    "warning:".yellow()
+                );
+                continue;
+            }
+        };
+
+        let mut sides = vec![];
+        if !mismatch.only_in_project.is_empty() {
+            sides.push(format!(
+                "only in the project file: {}",
+                mismatch.only_in_project.join(", ")
+            ));
+        }
+        if !mismatch.only_in_compose.is_empty() {
+            sides.push(format!(
+                "only in {}: {}",
+                project.docker_compose.display(),
+                mismatch.only_in_compose.join(", ")
+            ));
+        }
+        eprintln!(
+            "{} [{name}] services {}",
+            "warning:".yellow(),
+            sides.join("; ")
+        );
+    }
+}
+
+/// Checks that the `selected` projects' compose files run the images the
+/// project file names, which the lock file is made from. Mismatches are
+/// listed as a warning, or fail with `strict`. Unreadable compose files
+/// are left to the compose file check.
+pub fn check_image_mismatches(
+    projects: &Projects,
+    selected: &[String],
+    strict: bool,
+) -> anyhow::Result<()> {
+    let mut rows = vec![(
+        "SERVICE".to_string(),
+        "PROJECT FILE".to_string(),
+        "COMPOSE FILE".to_string(),
+    )];
+    for name in selected {
+        let Some(project) = projects.get(name) else {
+            continue;
+        };
+        for mismatch in image_mismatches(project).unwrap_or_default() {
+            rows.push((
+                format!("{name}.{}", mismatch.service),
+                mismatch.project_image,
+                mismatch.compose_image,
+            ));
+        }
+    }
+    if rows.len() == 1 {
+        return Ok(());
+    }
+
+    let width = |column: fn(&(String, String, String)) -> &String| {
+        rows.iter()
+            .map(|row| column(row).chars().count())
+            .max()
+            .unwrap_or_default()
+    };
+    let (service_width, image_width) =
+        (width(|row| &row.0), width(|row| &row.1));
+    let table = rows
+        .iter()
+        .map(|(service, project_image, compose_image)| {
+            format!(
+                "  {service:service_width$}  {project_image:image_width$}  \
+                 {compose_image}"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let summary = format!(
+        "{} service image(s) differ between the project file and the \
+         compose files; lock and update pin the project file's",
+        rows.len() - 1
+    );
+
+    if strict {
+        anyhow::bail!("{summary}:\n{table}");
+    }
+    eprintln!("{} {summary}:\n{table}", "warning:".yellow());
+    Ok(())
+}
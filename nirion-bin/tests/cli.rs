@@ -1,10 +1,11 @@
 use std::{
     env, fs,
+    io::Write,
     os::unix::fs::PermissionsExt,
-    path::Path,
+    path::{Path, PathBuf},
     process::{Command, Stdio},
     thread,
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 
 use nirion_tui_lib::ansi::strip_ansi_codes;
@@ -120,6 +121,10 @@ fn write_completion_projects(path: &Path) {
 }"#,
     )
     .unwrap();
+    for compose in ["app.yml", "app2.yml", "auth.yml"] {
+        fs::write(path.parent().unwrap().join(compose), "services: {}\n")
+            .unwrap();
+    }
 }
 
 fn write_fake_docker(
@@ -210,8 +215,18 @@ fn nirion_command(
     lock_file: &Path,
     docker_script: &Path,
 ) -> Command {
+    // The fixtures name a relative `compose.yml`, which nirion checks for
+    // before running compose.
+    let dir = project_file.parent().unwrap();
+    let compose = dir.join("compose.yml");
+    if !compose.exists() {
+        fs::write(&compose, "services: {}\n").unwrap();
+    }
+
     let mut command = Command::new(env!("CARGO_BIN_EXE_nirion"));
     command
+        .current_dir(dir)
+        .env("NIRION_STATE_DIR", state_dir_for(project_file))
         .arg("--project-file")
         .arg(project_file)
         .arg("--lock-file")
@@ -223,12 +238,22 @@ fn nirion_command(
     command
 }
 
+/// Keeps status snapshots written by `ps` and completion out of the
+/// user's state directory.
+fn state_dir_for(project_file: &Path) -> PathBuf {
+    project_file
+        .parent()
+        .unwrap()
+        .join("state")
+}
+
 fn fish_completion(
     project_file: &Path,
     words: &[&str],
 ) -> String {
     let output = Command::new(env!("CARGO_BIN_EXE_nirion"))
         .env("COMPLETE", "fish")
+        .env("NIRION_STATE_DIR", state_dir_for(project_file))
         .env("NIRION_PROJECT_FILE", project_file)
         .arg("--")
         .args(words)
@@ -291,6 +316,20 @@ fn ps_status_json() -> &'static str {
     r#"[{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx:latest","State":"running","Health":"healthy","ExitCode":0,"RunningFor":"2 minutes","Status":"Up 2 minutes (healthy)","Ports":"127.0.0.1:8080-8081->80-81/tcp","Networks":"default"}]"#
 }
 
+fn web_replicas_json() -> String {
+    ["abc", "def"]
+        .iter()
+        .enumerate()
+        .map(|(i, id)| {
+            format!(
+                r#"{{"ID":"{id}","Name":"myapp-web-{index}","Service":"web","Image":"nginx:latest","State":"running","Labels":"com.docker.compose.container-number={index}"}}"#,
+                index = i + 1
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn write_fake_health_docker(
     path: &Path,
     args_file: &Path,
@@ -335,6 +374,199 @@ fn version_does_not_require_files() {
     );
 }
 
+#[test]
+fn completions_print_static_scripts_without_files() {
+    let completions = |shell: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_nirion"))
+            .env_remove("NIRION_LOCK_FILE")
+            .env_remove("NIRION_PROJECT_FILE")
+            .args(["completions", shell])
+            .output()
+            .unwrap();
+        assert_success(&output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let bash = completions("bash");
+    assert!(bash.contains("_nirion()"));
+    assert!(bash.contains("nirion__subcmd__unpause"));
+    assert!(completions("zsh").starts_with("#compdef nirion"));
+    assert!(completions("fish").contains("-a \"up\""));
+}
+
+#[test]
+fn man_writes_a_page_per_command_without_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_nirion"))
+        .env_remove("NIRION_LOCK_FILE")
+        .env_remove("NIRION_PROJECT_FILE")
+        .args(["man", "--output-dir"])
+        .arg(dir.path().join("man1"))
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let page = |name: &str| {
+        std::fs::read_to_string(dir.path().join("man1").join(name)).ok()
+    };
+    assert!(
+        page("nirion.1")
+            .unwrap()
+            .contains(".SH SELECTORS")
+    );
+    assert!(page("nirion-up.1").is_some());
+    assert!(page("nirion-auth-check.1").is_some());
+    assert_eq!(page("nirion-man.1"), None);
+}
+
+#[test]
+fn read_only_commands_do_not_require_the_lock_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    write_projects(&project_file);
+    let without_lock = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_nirion"))
+            .env_remove("NIRION_LOCK_FILE")
+            .env_remove("NIRION_LOCK_DIR")
+            .env("NIRION_STATE_DIR", state_dir_for(&project_file))
+            .arg("--project-file")
+            .arg(&project_file)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = without_lock(&["list"]);
+    assert_success(&output);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("myapp")
+    );
+
+    for args in [&["update"][..], &["cat", "myapp", "--pinned"]] {
+        let output = without_lock(args);
+        assert_failure(&output);
+        assert!(
+            String::from_utf8(output.stderr)
+                .unwrap()
+                .contains("No lock file specified")
+        );
+    }
+}
+
+#[test]
+fn project_file_can_be_piped_in_on_stdin() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    write_completion_projects(&project_file);
+    let stdin_command = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_nirion"));
+        command
+            .env_remove("NIRION_LOCK_FILE")
+            .env("NIRION_STATE_DIR", state_dir_for(&project_file))
+            .env("NIRION_PROJECT_FILE", "-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        command
+    };
+
+    let mut child = stdin_command()
+        .arg("list")
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&fs::read(&project_file).unwrap())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("auth"));
+
+    // Completion leaves stdin alone instead of waiting for it to close.
+    let mut child = stdin_command()
+        .env("COMPLETE", "fish")
+        .args(["--", "nirion", "up", "a"])
+        .spawn()
+        .unwrap();
+    let _stdin = child.stdin.take();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("completion waited on stdin");
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_success(&child.wait_with_output().unwrap());
+}
+
+#[test]
+fn validate_warns_about_services_declared_on_one_side() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let compose_file = dir.path().join("compose.yml");
+    fs::write(&compose_file, "services:\n  frontend:\n    image: nginx\n")
+        .unwrap();
+    write_projects_with_compose(&project_file, compose_file.to_str().unwrap());
+    let lock_file = dir.path().join("lock.json");
+    let docker = dir.path().join("docker");
+
+    let output = nirion_command(&project_file, &lock_file, &docker)
+        .args(["--validate", "list"])
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stderr = strip_ansi_codes(&String::from_utf8(output.stderr).unwrap())
+        .to_string();
+    assert_eq!(
+        stderr,
+        format!(
+            "warning: [myapp] services only in the project file: web; \
+             only in {}: frontend\n",
+            compose_file.display()
+        )
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker)
+        .arg("list")
+        .output()
+        .unwrap();
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn missing_compose_file_fails_before_running_docker() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    write_projects_with_compose(&project_file, "/nix/store/gone-compose.yml");
+    let lock_file = dir.path().join("lock.json");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["up", "--plain", "myapp"])
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("myapp: /nix/store/gone-compose.yml ("));
+    assert!(stderr.contains("nixos-rebuild switch"));
+    assert!(!args_file.exists());
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("list")
+        .output()
+        .unwrap();
+    assert_success(&output);
+}
+
 #[test]
 fn fish_completion_suggests_subcommands() {
     let output = Command::new(env!("CARGO_BIN_EXE_nirion"))
@@ -381,6 +613,41 @@ fn fish_completion_after_dot_requires_exact_project_match() {
     assert!(!output.contains("app2.web\n"));
 }
 
+#[test]
+fn fish_completion_for_exec_offers_running_services_from_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    write_completion_projects(&project_file);
+
+    let state_dir = state_dir_for(&project_file);
+    fs::create_dir_all(&state_dir).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    fs::write(
+        state_dir.join("status-cache.json"),
+        format!(
+            r#"{{"app": {{"taken_at": {now}, "status": {{"services": {{
+                "web": [{{"id": "1", "service": "web", "container_name": "app-web-1",
+                    "index": 1, "image": "nginx", "state": "healthy", "health": "healthy",
+                    "exit_code": null, "running_for": null, "status": null,
+                    "ports": [], "networks": []}}]
+            }}}}}}}}"#
+        ),
+    )
+    .unwrap();
+
+    let exec = fish_completion(&project_file, &["nirion", "exec", "app."]);
+    assert_eq!(exec, "app.web\thealthy\n");
+
+    let logs = fish_completion(&project_file, &["nirion", "logs", "app"]);
+    assert!(logs.contains("app\n"));
+    assert!(logs.contains("app.web\thealthy\n"));
+    assert!(!logs.contains("app.worker"));
+    assert!(logs.contains("app2.web\n"));
+}
+
 #[test]
 fn fish_completion_for_service_selector_suggests_services_only() {
     let dir = tempfile::tempdir().unwrap();
@@ -471,6 +738,75 @@ fn up_service_target_appends_service_name() {
     );
 }
 
+#[test]
+fn profile_flag_is_passed_to_compose() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["down", "myapp", "--plain", "--profile", "debug"])
+        .args(["--profile", "backup"])
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let invocations = fs::read_to_string(args_file).unwrap();
+    assert!(
+        invocations
+            .split("---\n")
+            .any(|invocation| invocation
+                == "compose\n--file\ncompose.yml\n--project-name\nmyapp\n\
+                    --profile\ndebug\n--profile\nbackup\ndown\n"),
+        "{invocations}"
+    );
+}
+
+#[test]
+fn lifecycle_commands_accept_the_common_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+
+    for (command, verb) in [
+        ("up", "up"),
+        ("down", "down"),
+        ("start", "start"),
+        ("stop", "stop"),
+        ("restart", "restart"),
+        ("reload", "up"),
+        ("pause", "pause"),
+        ("unpause", "unpause"),
+    ] {
+        fs::remove_file(&args_file).ok();
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .args([command, "myapp", "--plain", "--refresh", "1s"])
+            .args(["--jobs", "1", "--profile", "debug"])
+            .output()
+            .unwrap();
+
+        assert_success(&output);
+        let invocations = fs::read_to_string(&args_file).unwrap();
+        assert!(
+            invocations
+                .split("---\n")
+                .any(|invocation| invocation.starts_with(&format!(
+                    "compose\n--file\ncompose.yml\n--project-name\nmyapp\n\
+                     --profile\ndebug\n{verb}\n"
+                ))),
+            "{command}: {invocations}"
+        );
+    }
+}
+
 #[test]
 fn up_quiet_suppresses_compose_output() {
     let dir = tempfile::tempdir().unwrap();
@@ -604,6 +940,40 @@ fn inspect_container_pretty_prints_docker_output_by_default() {
     );
 }
 
+#[test]
+fn inspect_container_health_prints_the_health_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_inspect_container_docker(
+        &docker_script,
+        &args_file,
+        r#"[{"ExitCode":1,"Output":"refused"}]"#,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["inspect", "container", "myapp.web", "--health"])
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"refused\""));
+    assert!(
+        fs::read_to_string(args_file)
+            .unwrap()
+            .contains("inspect\n--format\n{{json .State.Health.Log}}\nabc\n")
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["inspect", "container", "myapp.web", "--health", "-f", "x"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+}
+
 #[test]
 fn inspect_project_target_prints_service_outputs() {
     let dir = tempfile::tempdir().unwrap();
@@ -708,6 +1078,52 @@ fn inspect_all_target_prints_project_outputs() {
     );
 }
 
+#[test]
+fn inspect_path_prints_the_field_of_each_service() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        r#"[{"Id":"image-id","RepoDigests":["nginx@sha256:abc"]}]"#,
+        "",
+        0,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["inspect", "image", "*", "--path", "RepoDigests.0"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "myapp.web: nginx@sha256:abc\n"
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["inspect", "image", "myapp", "--raw"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let document: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["myapp.web"][0]["Id"], "image-id");
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["inspect", "image", "myapp", "--path", "/0/Missing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("myapp.web: path /0/Missing not found")
+    );
+}
+
 #[test]
 fn health_logs_prints_healthcheck_entries() {
     let dir = tempfile::tempdir().unwrap();
@@ -785,9 +1201,11 @@ fn compose_passthrough_commands_are_wired() {
         (&["down", "--plain"], "down\n"),
         (&["start", "--plain"], "start\n"),
         (&["stop", "--plain"], "stop\n"),
-        (&["restart", "--plain"], "restart\n"),
+        (&["pause", "--plain"], "pause\n"),
+        (&["unpause", "--plain"], "unpause\n"),
+        (&["pause", "myapp.web"], "pause\nweb\n"),
+        (&["restart", "--plain", "--yes"], "restart\n"),
         (&["pull"], "pull\n"),
-        (&["top"], "top\n"),
         (&["volumes"], "volumes\n--format\ntable\n"),
         (&["compose-exec", "*", "pull"], "pull\n"),
     ];
@@ -799,7 +1217,7 @@ fn compose_passthrough_commands_are_wired() {
         let docker_script = dir.path().join("fake-docker.sh");
         let args_file = dir.path().join("docker-args");
         write_projects(&project_file);
-        write_fake_docker(&docker_script, &args_file, "", "", 0);
+        write_fake_docker_append(&docker_script, &args_file, "", "", 0);
 
         let output = nirion_command(&project_file, &lock_file, &docker_script)
             .args(*args)
@@ -807,50 +1225,255 @@ fn compose_passthrough_commands_are_wired() {
             .unwrap();
 
         assert_success(&output);
-        assert_eq!(
-            fs::read_to_string(args_file).unwrap(),
-            format!(
-                "compose\n--file\ncompose.yml\n--project-name\nmyapp\n{}",
-                expected_command_args
-            ),
-            "failed command case: {args:?}"
+        let expected = format!(
+            "compose\n--file\ncompose.yml\n--project-name\nmyapp\n{}",
+            expected_command_args
+        );
+        let invocations = fs::read_to_string(args_file).unwrap();
+        assert!(
+            invocations
+                .split("---\n")
+                .any(|invocation| invocation == expected),
+            "failed command case: {args:?}\n{invocations}"
         );
     }
 }
 
-fn write_fake_logs_docker(
-    path: &Path,
-    args_file: &Path,
-    stdout: &str,
-    stderr: &str,
-) {
-    fs::write(
-        path,
-        format!(
-            r#"printf '%s\n' '---' >> '{}'
-printf '%s\n' "$@" >> '{}'
-case "$1" in
-  compose)
-    printf '%s\n' '{}'
-    ;;
-  inspect)
-    printf '%s\n' true
-    ;;
-  logs)
-    printf '%s\n' '{}'
-    printf '%s\n' '{}' >&2
-    ;;
-esac
-"#,
-            args_file.display(),
-            args_file.display(),
-            ps_status_json(),
-            stdout,
-            stderr,
-        ),
-    )
-    .unwrap();
-    let mut permissions = fs::metadata(path)
+#[test]
+fn invalid_compose_project_names_fail_early_unless_normalized() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    let contents = fs::read_to_string(&project_file)
+        .unwrap()
+        .replace(r#""name": "myapp""#, r#""name": "MyApp""#);
+    fs::write(&project_file, contents).unwrap();
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["volumes", "myapp"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'MyApp'"), "{stderr}");
+    assert!(stderr.contains("--normalize-project-names"), "{stderr}");
+    assert!(!args_file.exists());
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .env("NIRION_NORMALIZE_PROJECT_NAMES", "true")
+        .args(["volumes", "myapp"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'MyApp' normalized to 'myapp'"), "{stderr}");
+    assert!(
+        fs::read_to_string(&args_file)
+            .unwrap()
+            .contains("--project-name\nmyapp\nvolumes\n")
+    );
+}
+
+#[test]
+fn compose_stderr_goes_to_stderr_without_the_hidden_warnings() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    write_projects(&project_file);
+    fs::write(
+        &docker_script,
+        r#"[ "$1" = version ] && exit 0
+echo 'local     myapp_data'
+echo 'WARN[0000] compose.yml: the attribute `version` is obsolete' >&2
+echo 'first warning' >&2
+echo 'local     myapp_cache'
+echo 'second warning' >&2
+"#,
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .args(["volumes", "myapp"])
+            .args(extra)
+            .output()
+            .unwrap();
+        assert_success(&output);
+        (
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    };
+
+    let (stdout, stderr) = run(&[]);
+    assert!(
+        stdout.contains("local     myapp_data\nlocal     myapp_cache\n"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("warning"), "{stdout}");
+    assert!(
+        stderr.contains("first warning\nsecond warning\n"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("is obsolete"), "{stderr}");
+
+    let (_, stderr) = run(&["--hide-compose-warning", "first"]);
+    assert!(!stderr.contains("first warning"), "{stderr}");
+    assert!(stderr.contains("second warning"), "{stderr}");
+
+    let (_, stderr) = run(&["--show-compose-warnings"]);
+    assert!(stderr.contains("is obsolete"), "{stderr}");
+}
+
+#[test]
+fn down_all_asks_for_confirmation_above_the_threshold() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_completion_projects(&project_file);
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+    let downs = || {
+        fs::read_to_string(&args_file)
+            .unwrap_or_default()
+            .split("---\n")
+            .filter(|invocation| invocation.ends_with("\ndown\n"))
+            .count()
+    };
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["down", "--plain"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Take down all 3 projects?"), "{stderr}");
+    assert!(stderr.contains("pass --yes"), "{stderr}");
+    assert_eq!(downs(), 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["down", "app", "--plain"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(downs(), 1);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["down", "--plain", "--confirm-above", "3"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(downs(), 4);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["-y", "down", "--plain"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(downs(), 7);
+}
+
+#[test]
+fn strict_targets_require_an_explicit_or_configured_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_completion_projects(&project_file);
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+    let downs = || {
+        fs::read_to_string(&args_file)
+            .unwrap_or_default()
+            .split("---\n")
+            .filter(|invocation| invocation.ends_with("\ndown\n"))
+            .map(|invocation| {
+                invocation
+                    .lines()
+                    .nth(2)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["--strict-targets", "down", "--plain"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("specify a target, or pass '*' explicitly"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("'*' is app, app2, auth"), "{stderr}");
+    assert!(downs().is_empty());
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .env("NIRION_DEFAULT_TARGET", "ps=auth,down=app2")
+        .args(["--strict-targets", "down", "--plain"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(downs(), ["app2.yml"]);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["--strict-targets", "-y", "down", "*", "--plain"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(downs().len(), 4);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["--default-target", "nope=app", "ps"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("unknown command `nope`")
+    );
+}
+
+fn write_fake_logs_docker(
+    path: &Path,
+    args_file: &Path,
+    stdout: &str,
+    stderr: &str,
+) {
+    fs::write(
+        path,
+        format!(
+            r#"printf '%s\n' '---' >> '{}'
+printf '%s\n' "$@" >> '{}'
+case "$1" in
+  compose)
+    printf '%s\n' '{}'
+    ;;
+  inspect)
+    printf '%s\n' true
+    ;;
+  logs)
+    printf '%s\n' '{}'
+    printf '%s\n' '{}' >&2
+    ;;
+esac
+"#,
+            args_file.display(),
+            args_file.display(),
+            ps_status_json(),
+            stdout,
+            stderr,
+        ),
+    )
+    .unwrap();
+    let mut permissions = fs::metadata(path)
         .unwrap()
         .permissions();
     permissions.set_mode(0o755);
@@ -990,6 +1613,30 @@ fn logs_until_conflicts_with_follow() {
     assert!(String::from_utf8_lossy(&output.stderr).contains("--follow"));
 }
 
+#[test]
+fn logs_rejects_unparseable_since_before_running_docker() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("logs")
+        .arg("--since")
+        .arg("1 hour ago")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--since"), "{stderr}");
+    assert!(stderr.contains("such as 1h30m"), "{stderr}");
+    assert!(!args_file.exists());
+}
+
 #[test]
 fn logs_follow_reattaches_when_reader_exits_for_same_container() {
     let dir = tempfile::tempdir().unwrap();
@@ -1005,7 +1652,7 @@ fn logs_follow_reattaches_when_reader_exits_for_same_container() {
         .arg("myapp.web")
         .arg("--follow")
         .arg("--refresh")
-        .arg("10ms")
+        .arg("50ms")
         .arg("--events")
         .arg("never")
         .stdout(Stdio::null())
@@ -1101,7 +1748,7 @@ esac
         .arg("myapp.web")
         .arg("--follow")
         .arg("--refresh")
-        .arg("10ms")
+        .arg("50ms")
         .arg("--events")
         .arg("never")
         .stdout(Stdio::null())
@@ -1201,7 +1848,7 @@ esac
         .arg("myapp.web")
         .arg("--follow")
         .arg("--refresh")
-        .arg("10ms")
+        .arg("50ms")
         .arg("--events")
         .arg("never")
         .stdout(Stdio::null())
@@ -1332,6 +1979,54 @@ fn list_service_target_prints_only_selected_service() {
     assert!(!stdout.contains("- web"));
 }
 
+#[test]
+fn project_and_service_flags_select_like_the_positional() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_completion_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    let list = |args: &[&str]| {
+        nirion_command(&project_file, &lock_file, &docker_script)
+            .arg("list")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = list(&["--project", "app", "--service", "worker"]);
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&list(&["app.worker"]).stdout)
+    );
+
+    let conflict = list(&["app", "--project", "app"]);
+    assert!(!conflict.status.success());
+    assert!(
+        String::from_utf8_lossy(&conflict.stderr)
+            .contains("cannot be used with")
+    );
+
+    let unknown = list(&["--project", "app", "--service", "nope"]);
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("nope"));
+
+    let service_only =
+        nirion_command(&project_file, &lock_file, &docker_script)
+            .args(["env", "--project", "app"])
+            .output()
+            .unwrap();
+    assert!(!service_only.status.success());
+    assert!(
+        String::from_utf8_lossy(&service_only.stderr)
+            .contains("--service is required")
+    );
+}
+
 #[test]
 fn cat_prints_project_and_service_compose() {
     let dir = tempfile::tempdir().unwrap();
@@ -1379,42 +2074,142 @@ services:
 }
 
 #[test]
-fn cat_all_prints_each_project_with_heading() {
+fn cat_pinned_resolve_rewrites_images_from_compose_config() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
-    let app_compose = dir.path().join("app.yml");
-    let auth_compose = dir.path().join("auth.yml");
     let docker_script = dir.path().join("fake-docker.sh");
     let args_file = dir.path().join("docker-args");
-
+    write_projects(&project_file);
     fs::write(
-        &app_compose,
-        r#"
-services:
-  web:
-    image: nginx:latest
-"#,
+        &lock_file,
+        r#"{
+  "myapp.web": {
+    "image": "nginx:latest",
+    "version": "1.27.0",
+    "digest": "sha256:bbbb"
+  }
+}"#,
     )
     .unwrap();
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        "services:\n  web:\n    image: nginx:latest",
+        "",
+        0,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("cat")
+        .arg("--pinned")
+        .arg("--resolve")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("image: nginx@sha256:bbbb"));
+    let args = fs::read_to_string(args_file).unwrap();
+    assert!(args.ends_with("config\n"));
+}
+
+#[test]
+fn env_reports_drift_and_redacts_secrets() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let compose_file = dir.path().join("compose.yml");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
     fs::write(
-        &auth_compose,
-        r#"
-services:
-  server:
-    image: authelia/authelia:latest
-"#,
+        &compose_file,
+        "services:\n  web:\n    image: nginx:latest\n    env_file: web.env\n",
     )
     .unwrap();
     fs::write(
-        &project_file,
-        format!(
-            r#"{{
-  "app": {{
-    "name": "app",
-    "dockerCompose": "{}",
-    "services": {{
-      "web": {{"image": "nginx:latest", "healthcheck": false, "restart": null}}
+        dir.path().join("web.env"),
+        "MODE=new\nDB_PASSWORD=hunter2\nADDED=1\n",
+    )
+    .unwrap();
+    write_projects_with_compose(&project_file, &compose_file.to_string_lossy());
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_inspect_container_docker(
+        &docker_script,
+        &args_file,
+        r#"["MODE=old","DB_PASSWORD=hunter2"]"#,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("env")
+        .arg("--json")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {"key": "ADDED", "value": "1", "source": dir.path().join("web.env").to_string_lossy(), "state": "missing"},
+            {"key": "DB_PASSWORD", "value": "********", "source": dir.path().join("web.env").to_string_lossy(), "state": "match"},
+            {"key": "MODE", "value": "new", "source": dir.path().join("web.env").to_string_lossy(), "state": "differs", "container_value": "old"},
+        ])
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("env")
+        .arg("--show-secrets")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(stdout.contains("hunter2"));
+    assert!(stdout.contains("differs (old)"));
+}
+
+#[test]
+fn cat_all_prints_each_project_with_heading() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let app_compose = dir.path().join("app.yml");
+    let auth_compose = dir.path().join("auth.yml");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+
+    fs::write(
+        &app_compose,
+        r#"
+services:
+  web:
+    image: nginx:latest
+"#,
+    )
+    .unwrap();
+    fs::write(
+        &auth_compose,
+        r#"
+services:
+  server:
+    image: authelia/authelia:latest
+"#,
+    )
+    .unwrap();
+    fs::write(
+        &project_file,
+        format!(
+            r#"{{
+  "app": {{
+    "name": "app",
+    "dockerCompose": "{}",
+    "services": {{
+      "web": {{"image": "nginx:latest", "healthcheck": false, "restart": null}}
     }}
   }},
   "auth": {{
@@ -1478,50 +2273,35 @@ fn ps_prints_status_and_collapsed_ports_from_docker_json() {
 }
 
 #[test]
-fn ps_all_prints_status_for_all_projects() {
+fn refresh_below_minimum_is_rejected_and_bare_seconds_warn() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
     let docker_script = dir.path().join("fake-docker.sh");
     let args_file = dir.path().join("docker-args");
-    write_completion_projects(&project_file);
+    write_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker_append(
-        &docker_script,
-        &args_file,
-        ps_status_json(),
-        "",
-        0,
-    );
+    write_fake_docker(&docker_script, &args_file, ps_status_json(), "", 0);
 
     let output = nirion_command(&project_file, &lock_file, &docker_script)
-        .arg("ps")
-        .arg("*")
+        .args(["down", "--refresh", "10ms"])
         .output()
         .unwrap();
+    assert_failure(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("at least 50ms"));
 
-    assert_success(&output);
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stdout = strip_ansi_codes(&stdout);
-    assert!(stdout.contains("[app]"));
-    assert!(stdout.contains("[app2]"));
-    assert!(stdout.contains("[auth]"));
-    assert_eq!(stdout.matches("myapp-web-1").count(), 3);
-
-    let args = fs::read_to_string(args_file).unwrap();
-    assert!(args.contains(
-        "compose\n-f\napp.yml\n--project-name\napp\nps\n-a\n--format\njson\n"
-    ));
-    assert!(args.contains(
-        "compose\n-f\napp2.yml\n--project-name\napp2\nps\n-a\n--format\njson\n"
-    ));
-    assert!(args.contains(
-        "compose\n-f\nauth.yml\n--project-name\nauth\nps\n-a\n--format\njson\n"
-    ));
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["monitor", "--quiet", "--refresh", "1"])
+        .output()
+        .unwrap();
+    assert!(
+        strip_ansi_codes(&String::from_utf8_lossy(&output.stderr))
+            .contains("warning: reading --refresh 1 as seconds")
+    );
 }
 
 #[test]
-fn ps_service_prints_only_selected_service() {
+fn quiet_ps_prints_only_container_rows() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
@@ -1529,34 +2309,31 @@ fn ps_service_prints_only_selected_service() {
     let args_file = dir.path().join("docker-args");
     write_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker(
-        &docker_script,
-        &args_file,
-        r#"[{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx:latest","State":"running","Health":"healthy","ExitCode":0,"RunningFor":"2 minutes","Status":"Up 2 minutes (healthy)","Ports":"","Networks":"default"},{"ID":"def","Name":"myapp-db-1","Service":"db","Image":"postgres:16","State":"running","Health":null,"ExitCode":0,"RunningFor":"3 minutes","Status":"Up 3 minutes","Ports":"","Networks":"default"}]"#,
-        "",
-        0,
-    );
+    write_fake_docker(&docker_script, &args_file, ps_status_json(), "", 0);
 
     let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("--quiet")
         .arg("ps")
-        .arg("myapp.web")
+        .arg("myapp")
         .output()
         .unwrap();
 
     assert_success(&output);
     let stdout = String::from_utf8_lossy(&output.stdout);
     let stdout = strip_ansi_codes(&stdout);
-    assert!(stdout.contains("[myapp]"));
-    assert!(stdout.contains("myapp-web-1"));
-    assert!(!stdout.contains("myapp-db-1"));
-    assert_eq!(
-        fs::read_to_string(args_file).unwrap(),
-        "compose\n-f\ncompose.yml\n--project-name\nmyapp\nps\n-a\n--format\njson\n"
+    assert!(!stdout.contains("[myapp]"));
+    assert!(!stdout.contains("created"));
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert!(!lines.is_empty());
+    assert!(
+        lines
+            .iter()
+            .all(|line| line.starts_with("myapp-"))
     );
 }
 
 #[test]
-fn exec_forwards_options_and_command() {
+fn monitor_refuses_to_run_without_progress() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
@@ -1564,146 +2341,1270 @@ fn exec_forwards_options_and_command() {
     let args_file = dir.path().join("docker-args");
     write_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    write_fake_docker(&docker_script, &args_file, ps_status_json(), "", 0);
 
-    let output = nirion_command(&project_file, &lock_file, &docker_script)
-        .arg("exec")
-        .arg("myapp.web")
-        .arg("-T")
-        .arg("-u")
-        .arg("1000:1000")
-        .arg("-w")
-        .arg("/srv")
-        .arg("--index")
-        .arg("2")
-        .arg("-e")
-        .arg("FOO=bar")
-        .arg("--privileged")
-        .arg("printenv")
-        .output()
-        .unwrap();
+    for flag in ["-q", "--no-progress"] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .arg("monitor")
+            .arg(flag)
+            .output()
+            .unwrap();
 
-    assert_success(&output);
-    assert_eq!(
-        fs::read_to_string(args_file).unwrap(),
-        "compose\n--file\ncompose.yml\n--project-name\nmyapp\nexec\n-T\n-u\n1000:1000\n-w\n/srv\n--index\n2\n-e\nFOO=bar\n--privileged\nweb\nprintenv\n"
-    );
+        assert_failure(&output);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("nirion ps"));
+        assert!(output.stdout.is_empty());
+    }
 }
 
 #[test]
-fn exec_requires_command() {
+fn ps_json_includes_inspected_runtime_details() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
     let docker_script = dir.path().join("fake-docker.sh");
-    let args_file = dir.path().join("docker-args");
     write_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    fs::write(
+        &docker_script,
+        format!(
+            r#"if [ "$1" = inspect ]; then
+  printf '%s\n' '[{{"Id":"abc","RestartCount":2,"State":{{"StartedAt":"2024-05-01T10:00:00Z","OOMKilled":false}}}}]'
+  exit 0
+fi
+printf '%s\n' '{}'
+"#,
+            ps_status_json()
+        ),
+    )
+    .unwrap();
 
     let output = nirion_command(&project_file, &lock_file, &docker_script)
-        .arg("exec")
+        .arg("ps")
         .arg("myapp.web")
+        .arg("--json")
         .output()
         .unwrap();
 
-    assert_failure(&output);
-    assert!(
-        String::from_utf8_lossy(&output.stderr)
-            .contains("No command specified for exec")
-    );
+    assert_success(&output);
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    let web = &json["myapp"]["web"][0];
+    assert_eq!(web["container_name"], "myapp-web-1");
+    assert_eq!(web["details"]["restart_count"], 2);
+    assert_eq!(web["details"]["started_at"], "2024-05-01T10:00:00Z");
+}
+
+/// A `docker ps --format json` line for a compose container, with a
+/// label value holding a comma like compose's list of config files.
+fn docker_ps_line(
+    project: &str,
+    service: &str,
+) -> String {
+    format!(
+        r#"{{"ID":"{project}-{service}-id","Names":"{project}-{service}-1","Image":"nginx:latest","State":"running","Status":"Up 2 minutes (healthy)","RunningFor":"2 minutes ago","Ports":"","Networks":"default","Labels":"com.docker.compose.config-files=/srv/{project}.yml,/srv/override.yml,com.docker.compose.project={project},com.docker.compose.service={service}"}}"#
+    )
 }
 
 #[test]
-fn reload_plain_runs_down_then_up() {
+fn ps_all_lists_every_project_with_a_single_docker_ps() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
     let docker_script = dir.path().join("fake-docker.sh");
     let args_file = dir.path().join("docker-args");
-    write_projects(&project_file);
+    write_completion_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+    let lines = [
+        docker_ps_line("app", "web"),
+        docker_ps_line("app", "worker"),
+        docker_ps_line("auth", "server"),
+        r#"{"ID":"zzz","Names":"standalone","Image":"redis","State":"running","Labels":""}"#
+            .to_string(),
+    ];
+    write_fake_docker_append(
+        &docker_script,
+        &args_file,
+        &lines.join("\n"),
+        "",
+        0,
+    );
 
     let output = nirion_command(&project_file, &lock_file, &docker_script)
-        .arg("reload")
-        .arg("myapp")
-        .arg("--plain")
+        .arg("ps")
+        .arg("*")
         .output()
         .unwrap();
 
     assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(stdout.contains("[app]"));
+    assert!(stdout.contains("[app2]"));
+    assert!(stdout.contains("[auth]"));
+    assert!(stdout.contains("app-web-1"));
+    assert!(stdout.contains("app-worker-1"));
+    assert!(stdout.contains("auth-server-1"));
+    assert!(stdout.contains("healthy"));
+    assert!(!stdout.contains("standalone"));
+
+    let args = fs::read_to_string(args_file).unwrap();
     assert_eq!(
-        fs::read_to_string(args_file).unwrap(),
-        "---\ncompose\n--file\ncompose.yml\n--project-name\nmyapp\ndown\n---\ncompose\n--file\ncompose.yml\n--project-name\nmyapp\nup\n-d\n"
+        args.matches("---\nps\n-a\n--no-trunc\n--format\njson\n")
+            .count(),
+        1,
+        "{args}"
     );
+    assert!(!args.contains("---\ncompose\n"), "{args}");
 }
 
 #[test]
-fn lock_and_update_report_no_images() {
+fn ps_marks_containers_from_another_compose_file() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
     let docker_script = dir.path().join("fake-docker.sh");
     let args_file = dir.path().join("docker-args");
-    write_empty_projects(&project_file);
+    write_projects(&project_file);
     fs::write(&lock_file, "{}").unwrap();
-    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    let container = |id: &str, name: &str, config_files: &str| {
+        format!(
+            r#"{{"ID":"{id}","Name":"{name}","Service":"web","Image":"nginx:latest","State":"running","Status":"Up 2 minutes","Labels":"com.docker.compose.project=myapp,com.docker.compose.project.config_files={config_files}"}}"#
+        )
+    };
+    let lines = [
+        container(
+            "abc",
+            "myapp-web-1",
+            &dir.path()
+                .join("compose.yml")
+                .to_string_lossy(),
+        ),
+        container("def", "stray-web", "/home/someone/stray/compose.yml"),
+    ];
+    write_fake_docker(&docker_script, &args_file, &lines.join("\n"), "", 0);
 
-    for command in ["lock", "update"] {
-        let output = nirion_command(&project_file, &lock_file, &docker_script)
-            .arg(command)
-            .arg("empty")
-            .output()
-            .unwrap();
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("ps")
+        .arg("myapp")
+        .output()
+        .unwrap();
 
-        assert_success(&output);
-        assert!(
-            String::from_utf8_lossy(&output.stdout)
-                .contains("No images found to update"),
-            "failed command: {command}"
-        );
-    }
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    let stray = lines
+        .iter()
+        .position(|line| line.contains("stray-web"))
+        .unwrap();
+    assert!(
+        lines[stray + 1].contains("not managed by this project file"),
+        "{stdout}"
+    );
+    assert_eq!(stdout.matches("not managed").count(), 1, "{stdout}");
+    assert!(stdout.contains("myapp-web-1"));
 }
 
 #[test]
-fn lock_skips_services_that_already_have_lock_entries() {
+fn ps_service_prints_only_selected_service() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
     let docker_script = dir.path().join("fake-docker.sh");
     let args_file = dir.path().join("docker-args");
-    write_projects_with_compose(&project_file, "compose.yml");
-    fs::write(
-        &lock_file,
-        r#"{
-  "myapp.web": {
-    "image": "nginx:latest",
-    "version": "1.25.0",
-    "digest": "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
-  }
-}"#,
-    )
-    .unwrap();
-    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        r#"[{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx:latest","State":"running","Health":"healthy","ExitCode":0,"RunningFor":"2 minutes","Status":"Up 2 minutes (healthy)","Ports":"","Networks":"default"},{"ID":"def","Name":"myapp-db-1","Service":"db","Image":"postgres:16","State":"running","Health":null,"ExitCode":0,"RunningFor":"3 minutes","Status":"Up 3 minutes","Ports":"","Networks":"default"}]"#,
+        "",
+        0,
+    );
 
-    let original_lock = fs::read_to_string(&lock_file).unwrap();
     let output = nirion_command(&project_file, &lock_file, &docker_script)
-        .arg("lock")
+        .arg("ps")
         .arg("myapp.web")
         .output()
         .unwrap();
 
     assert_success(&output);
-    assert!(
-        String::from_utf8_lossy(&output.stdout)
-            .contains("No images found to update")
-    );
-    assert_eq!(fs::read_to_string(lock_file).unwrap(), original_lock);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(stdout.contains("[myapp]"));
+    assert!(stdout.contains("myapp-web-1"));
+    assert!(!stdout.contains("myapp-db-1"));
+    assert_eq!(
+        fs::read_to_string(args_file).unwrap(),
+        "compose\n-f\ncompose.yml\n--project-name\nmyapp\nps\n-a\n--format\njson\n"
+    );
+}
+
+#[test]
+fn ps_uptime_threshold_shows_only_recently_created_containers() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    // Docker's format, e.g. `2024-05-01 10:00:00 +0000 UTC`.
+    let created_at = |ago: u64| {
+        let at = SystemTime::now() - Duration::from_secs(ago);
+        humantime::format_rfc3339_seconds(at)
+            .to_string()
+            .replace('T', " ")
+            .replace('Z', " +0000 UTC")
+    };
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        &format!(
+            r#"[{{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx:latest","State":"running","RunningFor":"il y a 3 minutes","CreatedAt":"{}","Status":"Up 3 minutes","Ports":"","Networks":"default"}},{{"ID":"def","Name":"myapp-db-1","Service":"db","Image":"postgres:16","State":"running","RunningFor":"2 days ago","CreatedAt":"{}","Status":"Up 2 days","Ports":"","Networks":"default"}}]"#,
+            created_at(3 * 60 + 7),
+            created_at(2 * 86400 + 3600),
+        ),
+        "",
+        0,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "myapp"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(
+        stdout.contains("3m07s") || stdout.contains("3m08s"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("2d01h"), "{stdout}");
+    assert!(!stdout.contains("il y a"), "{stdout}");
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "myapp", "--uptime-threshold", "10m"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(stdout.contains("myapp-web-1"), "{stdout}");
+    assert!(!stdout.contains("myapp-db-1"), "{stdout}");
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "myapp", "--uptime-threshold", "1m", "--json"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "{}");
+}
+
+#[test]
+fn ps_format_prints_each_container_through_the_template() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, &web_replicas_json(), "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args([
+            "ps",
+            "myapp",
+            "--format",
+            r"{{.Project}}/{{.Name}}\t{{.State}}",
+        ])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "myapp/myapp-web-1\trunning\nmyapp/myapp-web-2\trunning\n"
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "myapp", "--format", "{{json .}}"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let first: serde_json::Value = serde_json::from_str(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(first["Service"], "web");
+    assert_eq!(first["Image"], "nginx:latest");
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "--format", "{{.Names}}"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("unknown field .Names; valid fields are Name, Service"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn no_color_turns_off_forced_colors() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, &web_replicas_json(), "", 0);
+
+    let ps = |no_color: bool| {
+        let mut command =
+            nirion_command(&project_file, &lock_file, &docker_script);
+        command
+            .env("CLICOLOR_FORCE", "1")
+            .args(["ps", "myapp"]);
+        if no_color {
+            command.arg("--no-color");
+        }
+        let output = command.output().unwrap();
+        assert_success(&output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert!(ps(false).contains('\x1b'));
+    let plain = ps(true);
+    assert!(!plain.contains('\x1b'), "{plain}");
+    assert!(plain.contains("myapp-web-1"), "{plain}");
+}
+
+#[test]
+fn exec_forwards_options_and_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, &web_replicas_json(), "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("exec")
+        .arg("myapp.web")
+        .arg("-T")
+        .arg("-u")
+        .arg("1000:1000")
+        .arg("-w")
+        .arg("/srv")
+        .arg("--index")
+        .arg("2")
+        .arg("-e")
+        .arg("FOO=bar")
+        .arg("--privileged")
+        .arg("printenv")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert_eq!(
+        fs::read_to_string(args_file).unwrap(),
+        "compose\n--file\ncompose.yml\n--project-name\nmyapp\nexec\n-T\n-u\n1000:1000\n-w\n/srv\n--index\n2\n-e\nFOO=bar\n--privileged\nweb\nprintenv\n"
+    );
+}
+
+#[test]
+fn exec_and_compose_exec_exit_with_the_child_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "child output", "", 7);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["exec", "myapp.web", "false"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Error"));
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["compose-exec", "myapp.web", "--", "logs", "-f"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "child output\n");
+    assert_eq!(
+        fs::read_to_string(args_file).unwrap(),
+        "compose\n--file\ncompose.yml\n--project-name\nmyapp\nlogs\n-f\nweb\n"
+    );
+}
+
+#[test]
+fn exec_rejects_index_beyond_replica_count() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, &web_replicas_json(), "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("exec")
+        .arg("myapp.web")
+        .arg("--index")
+        .arg("3")
+        .arg("true")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(
+            "myapp.web has no replica with index 3 (available: 1, 2)"
+        )
+    );
+    assert!(
+        !fs::read_to_string(args_file)
+            .unwrap()
+            .contains("exec")
+    );
+}
+
+#[test]
+fn exec_requires_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("exec")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("No command specified for exec")
+    );
+}
+
+#[test]
+fn exec_record_appends_history_and_history_lists_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let state_dir = dir.path().join("state");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    for cmd in ["date", "uptime"] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .env("NIRION_STATE_DIR", &state_dir)
+            .arg("exec")
+            .arg("--record")
+            .arg("myapp.web")
+            .arg(cmd)
+            .output()
+            .unwrap();
+        assert_success(&output);
+    }
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .env("NIRION_STATE_DIR", &state_dir)
+        .arg("exec")
+        .arg("--history")
+        .arg("myapp.web")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("  1") && lines[0].ends_with("uptime"));
+    assert!(lines[1].starts_with("  2") && lines[1].ends_with("date"));
+}
+
+#[test]
+fn exec_all_runs_in_running_services_and_reports_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    fs::write(
+        &docker_script,
+        format!(
+            r#"for arg in "$@"; do
+  if [ "$arg" = "ps" ]; then
+    printf '%s\n' '{}'
+    exit 0
+  fi
+done
+echo "flushed"
+echo "cache busy" >&2
+exit 4
+"#,
+            ps_status_json()
+        ),
+    )
+    .unwrap();
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("exec-all")
+        .arg("myapp")
+        .arg("--continue-on-error")
+        .arg("--")
+        .arg("flush")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = strip_ansi_codes(&stdout);
+    assert!(stdout.contains("myapp.web (exit 4)"));
+    assert!(stdout.contains("  flushed"));
+    assert!(stdout.contains("0/1 service(s) succeeded"));
+    assert!(!stdout.contains("cache busy"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(strip_ansi_codes(&stderr).contains("  cache busy"));
+    assert!(stderr.contains("command failed in 1 service(s): myapp.web"));
+}
+
+#[test]
+fn unreachable_daemon_fails_once_before_running_the_command() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker_append(
+        &docker_script,
+        &args_file,
+        "",
+        "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?",
+        1,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["up", "*", "--plain"])
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Cannot connect").count(), 1);
+    assert!(stderr.contains("cannot reach the Docker daemon"));
+    assert!(stderr.contains("hint: check that it is running"));
+    assert_eq!(
+        fs::read_to_string(args_file).unwrap(),
+        "---\nversion\n--format\n{{.Server.Version}}\n"
+    );
+}
+
+#[test]
+fn reload_plain_runs_down_then_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker_append(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("reload")
+        .arg("myapp")
+        .arg("--plain")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert_eq!(
+        fs::read_to_string(args_file).unwrap(),
+        "---\nversion\n--format\n{{.Server.Version}}\n---\ncompose\n--file\ncompose.yml\n--project-name\nmyapp\ndown\n---\ncompose\n--file\ncompose.yml\n--project-name\nmyapp\nup\n-d\n"
+    );
+}
+
+#[test]
+fn lock_and_update_report_no_images() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_empty_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    for command in ["lock", "update"] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .arg(command)
+            .arg("empty")
+            .output()
+            .unwrap();
+
+        assert_success(&output);
+        assert!(
+            String::from_utf8_lossy(&output.stdout)
+                .contains("No images found to update"),
+            "failed command: {command}"
+        );
+    }
+}
+
+#[test]
+fn lock_skips_services_that_already_have_lock_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects_with_compose(&project_file, "compose.yml");
+    fs::write(
+        &lock_file,
+        r#"{
+  "myapp.web": {
+    "image": "nginx:latest",
+    "version": "1.25.0",
+    "digest": "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+  }
+}"#,
+    )
+    .unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let original_lock = fs::read_to_string(&lock_file).unwrap();
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("lock")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout)
+            .contains("No images found to update")
+    );
+    assert_eq!(fs::read_to_string(lock_file).unwrap(), original_lock);
+}
+
+#[test]
+fn lock_warns_when_the_compose_file_runs_another_image() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(
+        dir.path().join("compose.yml"),
+        "services:\n  web:\n    image: nginx:1.25\n",
+    )
+    .unwrap();
+    let lock = r#"{
+  "myapp.web": {
+    "image": "nginx:latest",
+    "version": "1.25.0",
+    "digest": "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+  }
+}"#;
+    fs::write(&lock_file, lock).unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("lock")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stderr =
+        strip_ansi_codes(&String::from_utf8_lossy(&output.stderr)).to_string();
+    assert!(
+        stderr.contains("1 service image(s) differ between the project file"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("  myapp.web  nginx:latest  nginx:1.25"),
+        "{stderr}"
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["lock", "--strict-images"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    assert_eq!(fs::read_to_string(&lock_file).unwrap(), lock);
+}
+
+#[test]
+fn lock_prefer_local_takes_digests_from_pulled_images() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let digest = format!("sha256:{}", "b".repeat(64));
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        &format!(
+            r#"[{{"RepoDigests":["docker.io/library/nginx@{digest}"],"Config":{{"Labels":{{"org.opencontainers.image.version":"1.27.3"}}}}}}]"#
+        ),
+        "",
+        0,
+    );
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["lock", "--prefer-local", "--progress", "none"])
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert_eq!(
+        fs::read_to_string(&args_file).unwrap(),
+        "image\ninspect\nnginx:latest\n"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        strip_ansi_codes(&stdout)
+            .contains("Digests of myapp.web taken from local images")
+    );
+    let locked: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&lock_file).unwrap()).unwrap();
+    assert_eq!(locked["images"]["myapp.web"]["digest"], digest);
+    assert_eq!(locked["images"]["myapp.web"]["version"], "1.27.3");
+}
+
+#[test]
+fn lock_and_update_skip_local_builds() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    fs::write(
+        &project_file,
+        r#"{
+  "myapp": {
+    "name": "myapp",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "web": {
+        "image": "myapp-web",
+        "resolvedImage": null,
+        "restart": "always",
+        "build": true
+      }
+    }
+  }
+}"#,
+    )
+    .unwrap();
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    for command in ["lock", "update"] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .arg(command)
+            .output()
+            .unwrap();
+
+        assert_success(&output);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("myapp.web: myapp-web skipped (local build)"),
+            "failed command: {command}\n{stdout}"
+        );
+        assert!(stdout.contains("No images found to update"));
+    }
+    assert_eq!(fs::read_to_string(&lock_file).unwrap(), "{}");
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["update", "--json"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["skipped"], serde_json::json!(["myapp.web"]));
+
+    // Nothing changed, so a summary left over from an earlier run goes.
+    let summary = dir.path().join("summary.md");
+    fs::write(&summary, "Update locked images: 1 updated\n").unwrap();
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("update")
+        .arg("--summary-file")
+        .arg(&summary)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert!(!summary.exists());
+}
+
+#[test]
+fn update_skips_only_images_built_on_the_host() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+
+    // A registry that turns every anonymous request away, like Docker
+    // Hub does for names it doesn't know and for bad credentials alike.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let registry = listener
+        .local_addr()
+        .unwrap()
+        .to_string();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            // Read the whole request, lest closing with unread bytes
+            // resets the connection before the client sees the 401.
+            let mut request = Vec::new();
+            let mut chunk = [0; 4096];
+            while !request
+                .windows(4)
+                .any(|end| end == b"\r\n\r\n")
+            {
+                match std::io::Read::read(&mut stream, &mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&chunk[..read]),
+                }
+            }
+            let _ = stream.write_all(
+                b"HTTP/1.1 401 Unauthorized\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            );
+        }
+    });
+    fs::write(
+        &project_file,
+        format!(
+            r#"{{
+  "myapp": {{
+    "name": "myapp",
+    "dockerCompose": "compose.yml",
+    "services": {{
+      "built": {{"image": "{registry}/built", "restart": null}},
+      "pulled": {{"image": "{registry}/pulled", "restart": null}},
+      "missing": {{"image": "{registry}/missing", "restart": null}}
+    }}
+  }}
+}}"#
+        ),
+    )
+    .unwrap();
+    fs::write(&lock_file, "{}").unwrap();
+    // Docker has `built` without and `pulled` with a repository digest.
+    fs::write(
+        &docker_script,
+        format!(
+            r#"case "$*" in
+"image inspect {registry}/built")
+    printf '%s\n' '[{{"Id":"sha256:aaa","RepoDigests":[]}}]' ;;
+"image inspect {registry}/pulled")
+    printf '%s\n' '[{{"Id":"sha256:bbb","RepoDigests":["{registry}/pulled@sha256:{digest}"]}}]' ;;
+*)
+    exit 1 ;;
+esac
+"#,
+            digest = "b".repeat(64),
+        ),
+    )
+    .unwrap();
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("--insecure-registry")
+        .arg(&registry)
+        .args(["update", "--json"])
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["skipped"], serde_json::json!(["myapp.built"]));
+    assert_eq!(report["failures"]["failed"], 2);
+    let group = &report["failures"]["groups"][0];
+    assert_eq!(group["cause"], "auth_required", "{report}");
+    assert_eq!(fs::read_to_string(lock_file).unwrap(), "{}");
+}
+
+#[test]
+fn update_invalid_image_reference_does_not_rewrite_lock_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    fs::write(
+        &project_file,
+        r#"{
+  "myapp": {
+    "name": "myapp",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "web": {
+        "image": "not a valid image",
+        "healthcheck": false,
+        "restart": null
+      }
+    }
+  }
+}"#,
+    )
+    .unwrap();
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("update")
+        .arg("myapp.web")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout).to_lowercase();
+    assert!(stdout.contains("image"));
+    assert!(
+        stdout.contains("invalid")
+            || (stdout.contains("not") && stdout.contains("valid"))
+    );
+    assert!(!stdout.contains("lock file updated successfully"));
+    assert_eq!(fs::read_to_string(lock_file).unwrap(), "{}");
+}
+
+#[test]
+fn update_single_service_skips_progress_and_partial_keeps_failed_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    fs::write(
+        &project_file,
+        r#"{
+  "myapp": {
+    "name": "myapp",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "web": {"image": "not a valid image", "healthcheck": false, "restart": null}
+    }
+  }
+}"#,
+    )
+    .unwrap();
+    let lock = r#"{"myapp.web":{"image":"nginx:1.27","version":"1.27","digest":"sha256:aaaa"}}"#;
+    fs::write(&lock_file, lock).unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["update", "myapp.web", "--partial"])
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Checking myapp.web"), "{stdout}");
+    assert!(stdout.contains("myapp.web"), "{stdout}");
+    assert_eq!(fs::read_to_string(lock_file).unwrap(), lock);
+}
+
+/// `PATH` with `dir` first, so nirion runs the fake `secret-tool` in it.
+fn path_with(dir: &Path) -> std::ffi::OsString {
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::join_paths(
+        std::iter::once(dir.to_path_buf()).chain(env::split_paths(&path)),
+    )
+    .unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn auth_credentials_round_trip_through_the_keyring() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let bin = dir.path().join("bin");
+    let secret_tool = bin.join("secret-tool");
+    fs::create_dir(&bin).unwrap();
+    fs::write(
+        &project_file,
+        r#"{
+  "myapp": {
+    "name": "myapp",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "web": {"image": "ghcr.io/me/web:1", "healthcheck": false, "restart": null}
+    }
+  }
+}"#,
+    )
+    .unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    fs::write(
+        &secret_tool,
+        format!(
+            r#"#!/bin/sh
+case "$1" in
+  store) printf 'secret = %s\n' "$(cat)" >> '{store}' ;;
+  search) cat '{store}' 2>/dev/null ;;
+  clear) : > '{store}' ;;
+esac
+"#,
+            store = dir.path().join("keyring").display()
+        ),
+    )
+    .unwrap();
+    let mut permissions = fs::metadata(&secret_tool)
+        .unwrap()
+        .permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(&secret_tool, permissions).unwrap();
+    let nirion = |args: &[&str]| {
+        let mut command =
+            nirion_command(&project_file, &lock_file, &docker_script);
+        command
+            .env("PATH", path_with(&bin))
+            .args(args);
+        command
+    };
+
+    let mut set = nirion(&["auth", "set", "ghcr.io", "--username", "me"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    set.stdin
+        .take()
+        .unwrap()
+        .write_all(b"hunter2\n")
+        .unwrap();
+    assert_success(&set.wait_with_output().unwrap());
+
+    let output = nirion(&["auth", "list"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout =
+        strip_ansi_codes(&String::from_utf8_lossy(&output.stdout)).to_string();
+    assert!(stdout.contains("ghcr.io"), "{stdout}");
+    assert!(stdout.contains("basic"), "{stdout}");
+    assert!(!stdout.contains("hunter2"), "{stdout}");
+
+    let output = nirion(&["registries", "--json"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report["registries"][0]["authenticated"],
+        serde_json::json!(["myapp.web"])
+    );
+
+    assert_success(
+        &nirion(&["auth", "remove", "ghcr.io"])
+            .output()
+            .unwrap(),
+    );
+    let output = nirion(&["auth", "remove", "ghcr.io"])
+        .output()
+        .unwrap();
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("no credentials for ghcr.io in the keyring")
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unreachable_keyring_only_warns() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let bin = dir.path().join("bin");
+    let secret_tool = bin.join("secret-tool");
+    let secret_tool_args = dir.path().join("secret-tool-args");
+    fs::create_dir(&bin).unwrap();
+    write_projects(&project_file);
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    write_fake_docker(
+        &secret_tool,
+        &secret_tool_args,
+        "",
+        "Cannot autolaunch D-Bus without X11 $DISPLAY",
+        1,
+    );
+    let mut script = fs::read_to_string(&secret_tool).unwrap();
+    script.insert_str(0, "#!/bin/sh\n");
+    fs::write(&secret_tool, script).unwrap();
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .env("PATH", path_with(&bin))
+        .arg("registries")
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("could not read registry credentials from the keyring"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Cannot autolaunch D-Bus"), "{stderr}");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn auth_commands_fail_clearly_without_secret_tool() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    for command in [&["auth", "list"][..], &["auth", "remove", "ghcr.io"]] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .env("PATH", dir.path().join("empty-bin"))
+            .args(command)
+            .output()
+            .unwrap();
+
+        assert_failure(&output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(
+                "secret-tool not found; install libsecret's secret-tool to \
+                 use the keyring"
+            ),
+            "{command:?}: {stderr}"
+        );
+    }
+}
+
+#[test]
+fn auth_check_fails_when_a_registry_rejects_the_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let auth_file = dir.path().join("auth.json");
+    write_projects(&project_file);
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    // A registry that wants basic credentials and accepts none.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let registry = listener
+        .local_addr()
+        .unwrap()
+        .to_string();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = [0; 4096];
+            let _ = std::io::Read::read(&mut stream, &mut request);
+            let _ = stream.write_all(
+                b"HTTP/1.1 401 Unauthorized\r\nwww-authenticate: Basic realm=\"test\"\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+            );
+        }
+    });
+    fs::write(
+        &auth_file,
+        format!(
+            r#"{{"{registry}": {{"type": "basic", "username": "me", "password": "wrong", "insecure": true}}}}"#
+        ),
+    )
+    .unwrap();
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .env("NIRION_AUTH_FILE", &auth_file)
+        .env("PATH", dir.path().join("empty-bin"))
+        .args(["auth", "check", "--json"])
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let checks: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(checks[0]["scope"], registry.as_str());
+    assert_eq!(checks[0]["username"], "me");
+    assert_eq!(checks[0]["anonymous"]["status"], "denied");
+    assert_eq!(checks[0]["authenticated"]["status"], "denied");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1 of 1 credential was rejected"),
+        "{stderr}"
+    );
 }
 
 #[test]
-fn update_invalid_image_reference_does_not_rewrite_lock_file() {
+fn registry_ca_must_be_a_pem_certificate() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let ca_file = dir.path().join("ca.pem");
+    write_projects(&project_file);
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+    fs::write(&ca_file, "not a certificate").unwrap();
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .arg("--registry-ca")
+        .arg(&ca_file)
+        .arg("update")
+        .output()
+        .unwrap();
+
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not a PEM encoded certificate"));
+    assert!(!args_file.exists());
+}
+
+#[test]
+fn update_json_groups_failures_by_cause() {
     let dir = tempfile::tempdir().unwrap();
     let project_file = dir.path().join("projects.json");
     let lock_file = dir.path().join("nirion.lock");
@@ -1716,11 +3617,8 @@ fn update_invalid_image_reference_does_not_rewrite_lock_file() {
     "name": "myapp",
     "dockerCompose": "compose.yml",
     "services": {
-      "web": {
-        "image": "not a valid image",
-        "healthcheck": false,
-        "restart": null
-      }
+      "web": {"image": "not a valid image", "healthcheck": false, "restart": null},
+      "db": {"image": "Also Invalid", "healthcheck": false, "restart": null}
     }
   }
 }"#,
@@ -1731,17 +3629,466 @@ fn update_invalid_image_reference_does_not_rewrite_lock_file() {
 
     let output = nirion_command(&project_file, &lock_file, &docker_script)
         .arg("update")
-        .arg("myapp.web")
+        .arg("--json")
         .output()
         .unwrap();
 
     assert_failure(&output);
-    let stdout = String::from_utf8_lossy(&output.stdout).to_lowercase();
-    assert!(stdout.contains("image"));
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["changes"], serde_json::json!([]));
+    assert_eq!(report["groups"], serde_json::json!([]));
+    assert_eq!(report["failures"]["failed"], 2);
+    let group = &report["failures"]["groups"][0];
+    assert_eq!(group["cause"], "invalid_reference");
+    assert_eq!(group["failures"][0]["service"], "myapp.db");
+    assert_eq!(fs::read_to_string(lock_file).unwrap(), "{}");
+}
+
+fn api_get(
+    addr: &str,
+    path: &str,
+    token: Option<&str>,
+) -> (String, serde_json::Value) {
+    use std::io::Read;
+
+    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+    let authorization = token
+        .map(|token| format!("Authorization: Bearer {token}\r\n"))
+        .unwrap_or_default();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: {addr}\r\n{authorization}\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.lines().next().unwrap().to_string();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[test]
+fn api_serves_projects_status_and_drift_behind_a_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(
+        &lock_file,
+        r#"{
+  "myapp.web": {
+    "image": "nginx:latest",
+    "version": "1.27.0",
+    "digest": "sha256:bbbb"
+  }
+}"#,
+    )
+    .unwrap();
+    write_fake_docker(
+        &docker_script,
+        &args_file,
+        r#"{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx@sha256:cccc","State":"running"}"#,
+        "",
+        0,
+    );
+
+    let mut child = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["api", "--listen", "127.0.0.1:0", "--token", "secret"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    std::io::BufRead::read_line(
+        &mut std::io::BufReader::new(child.stdout.take().unwrap()),
+        &mut line,
+    )
+    .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .unwrap_or_else(|| panic!("unexpected output: {line}"))
+        .to_string();
+
+    let (status, body) = api_get(&addr, "/projects", None);
+    assert!(status.starts_with("HTTP/1.1 401"), "{status}");
+    assert_eq!(body["error"], "missing or wrong bearer token");
+
+    let (status, body) = api_get(&addr, "/projects", Some("secret"));
+    assert!(status.starts_with("HTTP/1.1 200"), "{status}");
+    assert_eq!(body["myapp"]["services"]["web"]["image"], "nginx:latest");
+
+    let (_, body) = api_get(&addr, "/status/myapp", Some("secret"));
+    assert_eq!(body["services"]["web"][0]["container_name"], "myapp-web-1");
+
+    let (_, body) = api_get(&addr, "/diff", Some("secret"));
+    assert_eq!(
+        body,
+        serde_json::json!([{
+            "service": "myapp.web",
+            "locked": "sha256:bbbb",
+            "state": "differs",
+            "running": "sha256:cccc"
+        }])
+    );
+
+    let (status, _) = api_get(&addr, "/status/nope", Some("secret"));
+    assert!(status.starts_with("HTTP/1.1 404"), "{status}");
+
+    stop_child(&mut child);
+}
+
+#[test]
+fn adopt_writes_a_project_file_that_drives_ps() {
+    let dir = tempfile::tempdir().unwrap();
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    let compose_file = dir.path().join("blog.yml");
+    fs::write(&compose_file, "services: {}\n").unwrap();
+    let labels = |project: &str, service: &str| {
+        format!(
+            r#""com.docker.compose.project": "{project}", "com.docker.compose.service": "{service}", "com.docker.compose.project.config_files": "{}""#,
+            compose_file.display()
+        )
+    };
+    let inspect = format!(
+        r#"[
+  {{"Id": "web-id", "Config": {{"Image": "nginx:1.27", "Labels": {{{}}}, "Healthcheck": {{"Test": ["CMD", "true"]}}}}, "HostConfig": {{"RestartPolicy": {{"Name": "always"}}}}}},
+  {{"Id": "db-id", "Config": {{"Image": "postgres:16", "Labels": {{{}}}}}, "HostConfig": {{"RestartPolicy": {{"Name": "no"}}}}}},
+  {{"Id": "other-id", "Config": {{"Image": "redis", "Labels": {{{}}}}}}}
+]"#,
+        labels("blog", "web"),
+        labels("blog", "db"),
+        labels("other", "cache"),
+    );
+    fs::write(
+        &docker_script,
+        format!(
+            r#"printf '%s\n' '---' >> '{args}'
+printf '%s\n' "$@" >> '{args}'
+case "$1" in
+  ps)
+    printf '%s\n' '{{"ID":"web-id"}}' '{{"ID":"db-id"}}' '{{"ID":"other-id"}}'
+    ;;
+  inspect)
+    printf '%s\n' '{inspect}'
+    ;;
+  compose)
+    printf '%s\n' '[{{"ID":"web-id","Name":"blog-web-1","Service":"web","Image":"nginx:1.27","State":"running","Status":"Up 2 minutes","Ports":"","Networks":"default"}}]'
+    ;;
+esac
+"#,
+            args = args_file.display(),
+        ),
+    )
+    .unwrap();
+    let nirion = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_nirion"));
+        command
+            .current_dir(dir.path())
+            .env_remove("NIRION_LOCK_FILE")
+            .env_remove("NIRION_PROJECT_FILE")
+            .env("NIRION_STATE_DIR", dir.path().join("state"))
+            .arg("--docker-command")
+            .arg("/bin/sh")
+            .arg("--docker-command-arg")
+            .arg(&docker_script)
+            .stdin(Stdio::null());
+        command
+    };
+
+    let output = nirion().arg("adopt").output().unwrap();
+    assert!(!output.status.success());
     assert!(
-        stdout.contains("invalid")
-            || (stdout.contains("not") && stdout.contains("valid"))
+        String::from_utf8_lossy(&output.stderr).contains("pass --yes"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let project_file = dir.path().join("projects.json");
+    let output = nirion()
+        .args(["adopt", "blog", "--output"])
+        .arg(&project_file)
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let adopted: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&project_file).unwrap())
+            .unwrap();
+    assert_eq!(
+        adopted,
+        serde_json::json!({
+            "blog": {
+                "name": "blog",
+                "dockerCompose": compose_file,
+                "services": {
+                    "db": {
+                        "image": "postgres:16",
+                        "resolvedImage": null,
+                        "healthcheck": false,
+                        "restart": "no"
+                    },
+                    "web": {
+                        "image": "nginx:1.27",
+                        "resolvedImage": null,
+                        "healthcheck": true,
+                        "restart": "always"
+                    }
+                }
+            }
+        })
+    );
+
+    let lock_file = dir.path().join("nirion.lock");
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "blog"])
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(strip_ansi_codes(&stdout).contains("blog-web-1"), "{stdout}");
+
+    let output = nirion()
+        .args(["adopt", "nope"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'nope'"));
+}
+
+#[test]
+fn lint_reports_findings_and_honors_deny_and_lint_ignore() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(
+        dir.path().join("compose.yml"),
+        "services:\n  web:\n    image: nginx:latest\n    restart: always\n",
+    )
+    .unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 1);
+    let lint = |args: &[&str]| {
+        nirion_command(&project_file, &lock_file, &docker_script)
+            .arg("lint")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = lint(&[]);
+    assert_success(&output);
+    let stdout =
+        strip_ansi_codes(&String::from_utf8_lossy(&output.stdout)).to_string();
+    assert!(
+        stdout.contains("warning[NL001] myapp.web: image `nginx:latest`"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("compose.yml:3 (services.web.image)"));
+    assert!(stdout.contains("warning[NL005] myapp.web:"));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("0 error(s), 2 warning(s)")
+    );
+    assert!(!args_file.exists(), "lint must not run docker");
+
+    let output = lint(&["--deny", "warnings", "--json"]);
+    assert!(!output.status.success());
+    let findings: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(findings[0]["code"], "NL001");
+    assert_eq!(findings[0]["location"]["line"], 3);
+
+    let projects = fs::read_to_string(&project_file)
+        .unwrap()
+        .replace(
+            r#""restart": null"#,
+            r#""restart": null, "lintIgnore": ["NL001", "restarting-one-shot"]"#,
+        );
+    fs::write(&project_file, projects).unwrap();
+    assert_success(&lint(&["--deny", "warnings"]));
+}
+
+#[test]
+fn verbose_prints_the_effective_configuration_on_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "27.3.1", "", 0);
+
+    let run = |verbose: bool| {
+        let mut command =
+            nirion_command(&project_file, &lock_file, &docker_script);
+        command.args(["cat", "myapp"]);
+        if verbose {
+            command.arg("-v");
+        }
+        let output = command.output().unwrap();
+        assert_success(&output);
+        output
+    };
+
+    let verbose = run(true);
+    assert_eq!(verbose.stdout, run(false).stdout);
+    let stderr = strip_ansi_codes(&String::from_utf8_lossy(&verbose.stderr))
+        .into_owned();
+    let line = |name: &str| {
+        stderr
+            .lines()
+            .find(|line| line.trim_start().starts_with(name))
+            .unwrap_or_else(|| panic!("no {name} in {stderr}"))
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(
+        line("project file"),
+        format!("project file {} (--project-file)", project_file.display())
+    );
+    assert_eq!(
+        line("lock file"),
+        format!(
+            "lock file {} (--lock-file, not read by this command)",
+            lock_file.display()
+        )
+    );
+    assert_eq!(line("auth file"), "auth file none (not set)");
+    assert_eq!(
+        line("loaded"),
+        "loaded 1 projects, 1 services (project file)"
+    );
+    assert_eq!(line("docker"), "docker 27.3.1 (docker version)");
+    assert_eq!(line("compose"), "compose 27.3.1 (docker compose version)");
+}
+
+#[test]
+fn ps_hides_helper_containers_unless_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    write_projects(&project_file);
+    fs::write(&lock_file, "{}").unwrap();
+    let json = [
+        r#"{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx:latest","State":"running","Labels":"com.docker.compose.oneoff=False"}"#,
+        r#"{"ID":"def","Name":"myapp-web-run-8f2c","Service":"web","Image":"nginx:latest","State":"running","Labels":"com.docker.compose.oneoff=True"}"#,
+    ]
+    .join("\n");
+    write_fake_docker(&docker_script, &args_file, &json, "", 0);
+
+    let ps = |show_helpers: bool| {
+        let mut command =
+            nirion_command(&project_file, &lock_file, &docker_script);
+        command.args(["ps", "myapp", "--format", "{{.Name}}"]);
+        if show_helpers {
+            command.arg("--show-helpers");
+        }
+        let output = command.output().unwrap();
+        assert_success(&output);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(ps(false), "myapp-web-1\n");
+    assert_eq!(ps(true), "myapp-web-1\nmyapp-web-run-8f2c\n");
+}
+
+#[test]
+fn empty_project_file_is_reported_without_touching_docker() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    fs::write(&project_file, "{}").unwrap();
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    for command in ["list", "ps", "up", "monitor", "update"] {
+        let output = nirion_command(&project_file, &lock_file, &docker_script)
+            .arg(command)
+            .output()
+            .unwrap();
+        assert_success(&output);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            format!("No projects defined in {}\n", project_file.display()),
+            "{command}"
+        );
+        assert!(output.stdout.is_empty(), "{command}");
+    }
+    assert!(!args_file.exists());
+
+    let output = nirion_command(&project_file, &lock_file, &docker_script)
+        .args(["ps", "myapp"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("the project file defines no projects")
+    );
+}
+
+#[test]
+fn project_without_services_is_listed_as_such() {
+    let dir = tempfile::tempdir().unwrap();
+    let project_file = dir.path().join("projects.json");
+    let lock_file = dir.path().join("nirion.lock");
+    let docker_script = dir.path().join("fake-docker.sh");
+    let args_file = dir.path().join("docker-args");
+    fs::write(
+        &project_file,
+        r#"{"myapp": {"name": "myapp", "dockerCompose": "compose.yml", "services": {}}}"#,
+    )
+    .unwrap();
+    fs::write(&lock_file, "{}").unwrap();
+    write_fake_docker(&docker_script, &args_file, "", "", 0);
+
+    let run = |args: &[&str]| {
+        nirion_command(&project_file, &lock_file, &docker_script)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let list = run(&["list"]);
+    assert_success(&list);
+    assert_eq!(
+        String::from_utf8_lossy(&list.stdout),
+        "Projects:\n- myapp\n"
+    );
+
+    let ps = run(&["ps", "myapp"]);
+    assert_success(&ps);
+    assert_eq!(
+        strip_ansi_codes(&String::from_utf8_lossy(&ps.stdout)),
+        "[myapp]  created  status  ports\n  no services defined\n\n"
+    );
+
+    let update = run(&["update"]);
+    assert_success(&update);
+    assert!(
+        String::from_utf8_lossy(&update.stdout)
+            .contains("No images found to update")
+    );
+
+    let selector = run(&["ps", "myapp.web"]);
+    assert!(!selector.status.success());
+    assert!(
+        String::from_utf8_lossy(&selector.stderr)
+            .contains("project 'myapp' defines no services")
     );
-    assert!(!stdout.contains("lock file updated successfully"));
-    assert_eq!(fs::read_to_string(lock_file).unwrap(), "{}");
 }
@@ -0,0 +1,1410 @@
+mod support;
+
+use nirion_oci_lib::mock_registry::MockRegistry;
+use serde_json::json;
+use support::{
+    DIGEST_A, Harness, LockFixture, ProjectsFixture, Scenario, assert_failure,
+    assert_success, container, replica, stdout,
+};
+
+const VERSION_LABEL: &str = "org.opencontainers.image.version";
+const SOURCE_LABEL: &str = "org.opencontainers.image.source";
+
+fn two_projects() -> ProjectsFixture {
+    ProjectsFixture::new()
+        .project("myapp", "myapp.yml")
+        .service("web", "nginx:latest")
+        .service("worker", "alpine:latest")
+        .project("other", "other.yml")
+        .service("api", "node:22")
+}
+
+#[test]
+fn ps_lists_every_project_and_replica() {
+    let second = replica("myapp", "web", "def", 2);
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[container("myapp", "web", "abc"), second]),
+    );
+
+    let output = harness.run(&["ps"]);
+
+    assert_success(&output);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("[myapp]"));
+    assert!(stdout.contains("[other]"));
+    assert!(stdout.contains("myapp-web-1 [1/2]"));
+    assert!(stdout.contains("myapp-web-2 [2/2]"));
+    let invocations = harness.invocations();
+    assert_eq!(
+        invocations[0],
+        ["version", "--format", "{{.Server.Version}}"]
+    );
+    assert_eq!(
+        invocations[1..],
+        [[
+            "ps",
+            "-a",
+            "--no-trunc",
+            "--format",
+            "json",
+            "--filter",
+            "label=com.docker.compose.project",
+            "--filter",
+            "label=com.docker.compose.oneoff=False",
+        ]]
+    );
+}
+
+#[test]
+fn ps_hides_profile_disabled_services_for_one_project_and_for_all() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[
+            container("myapp", "web", "abc"),
+            container("myapp", "debug", "def"),
+        ]),
+    );
+    std::fs::write(
+        harness.path().join("myapp.yml"),
+        "services:\n  web:\n    image: nginx:latest\n  debug:\n    \
+         image: busybox\n    profiles: [debug]\n",
+    )
+    .unwrap();
+
+    // One project goes through compose ps, several through docker ps.
+    let one = harness.run(&["ps", "myapp", "--json"]);
+    let all = harness.run(&["ps", "--json"]);
+
+    assert_success(&one);
+    assert_success(&all);
+    let services = |output: &std::process::Output| {
+        let projects: serde_json::Value =
+            serde_json::from_str(&stdout(output)).unwrap();
+        projects["myapp"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(services(&one), ["web"]);
+    assert_eq!(services(&all), ["web"]);
+
+    let enabled = harness.run(&["ps", "--json", "--profile", "debug"]);
+    assert_success(&enabled);
+    assert_eq!(services(&enabled), ["debug", "web"]);
+}
+
+#[test]
+fn ps_reports_compose_failure() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().fail("compose * ps *", "daemon unavailable", 1),
+    );
+
+    let output = harness.run(&["ps", "myapp"]);
+
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("daemon unavailable")
+    );
+}
+
+#[test]
+fn top_notes_projects_without_running_containers() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new()
+            .respond(
+                "compose -f myapp.yml * ps *",
+                container("myapp", "web", "abc").to_string(),
+            )
+            .respond("compose * top*", "UID PID CMD"),
+    );
+
+    let output = harness.run(&["top"]);
+
+    assert_success(&output);
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains("[myapp]\nUID PID CMD\n\n[other]\n(no containers)")
+    );
+    assert_eq!(
+        harness.invocations_with(&["top"]),
+        vec![vec![
+            "compose",
+            "--file",
+            "myapp.yml",
+            "--project-name",
+            "myapp",
+            "top"
+        ]]
+    );
+}
+
+#[test]
+fn top_appends_the_service_of_a_service_selector() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[container("myapp", "web", "abc")]),
+    );
+
+    let output = harness.run(&["top", "myapp.web"]);
+
+    assert_success(&output);
+    assert_eq!(
+        harness.invocations_with(&["top"]),
+        vec![vec![
+            "compose",
+            "--file",
+            "myapp.yml",
+            "--project-name",
+            "myapp",
+            "top",
+            "web"
+        ]]
+    );
+
+    let output = harness.run(&["top", "myapp.worker"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("(no containers)"));
+}
+
+#[test]
+fn up_quiet_waits_for_healthchecks_before_exiting() {
+    let projects = ProjectsFixture::new()
+        .project("myapp", "compose.yml")
+        .service_with("web", json!({"image": "nginx", "healthcheck": true}));
+    let mut web = container("myapp", "web", "abc");
+    web["Health"] = json!("healthy");
+    let harness = Harness::new(
+        projects,
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[web]),
+    );
+
+    let output = harness.run(&["up", "--quiet", "--refresh", "50ms"]);
+
+    assert_success(&output);
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        harness.invocations_with(&["up", "-d"]),
+        vec![vec![
+            "compose",
+            "--file",
+            "compose.yml",
+            "--project-name",
+            "myapp",
+            "up",
+            "-d"
+        ]]
+    );
+    assert!(
+        !harness
+            .invocations_with(&["ps", "-a"])
+            .is_empty()
+    );
+}
+
+#[test]
+fn up_reports_declared_services_without_containers() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("worker", "alpine:latest"),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[container("myapp", "web", "abc")]),
+    );
+
+    let output = harness.run(&["up", "--skip-healthcheck"]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("[myapp] declared but not created: worker"));
+
+    let output = harness.run(&[
+        "up",
+        "--quiet",
+        "--skip-healthcheck",
+        "--fail-on",
+        "missing",
+    ]);
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("1 declared service never got a container")
+    );
+}
+
+#[test]
+fn up_writes_textfile_metrics_per_service() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("worker", "alpine:latest"),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[container("myapp", "web", "abc")]),
+    );
+    let textfile_dir = harness.path().join("textfile");
+    std::fs::create_dir(&textfile_dir).unwrap();
+
+    let output = harness.run(&[
+        "up",
+        "--quiet",
+        "--skip-healthcheck",
+        "--textfile-dir",
+        textfile_dir.to_str().unwrap(),
+    ]);
+    assert_success(&output);
+
+    let metrics =
+        std::fs::read_to_string(textfile_dir.join("nirion.prom")).unwrap();
+    assert!(metrics.contains(
+        "nirion_up_failed_services{project=\"myapp\",service=\"web\"} 0\n\
+         nirion_up_failed_services{project=\"myapp\",service=\"worker\"} 1\n"
+    ));
+    assert!(metrics.contains("\nnirion_last_up_success 1\n"));
+}
+
+#[test]
+fn start_shares_up_startup_flags() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("worker", "alpine:latest"),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&[container("myapp", "web", "abc")]),
+    );
+
+    let output = harness.run(&[
+        "start",
+        "--quiet",
+        "--skip-healthcheck",
+        "--profile",
+        "extra",
+        "--fail-on",
+        "missing",
+        "myapp.worker",
+    ]);
+
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("1 declared service never got a container")
+    );
+    assert_eq!(
+        harness
+            .invocations_with(&["--profile", "extra", "start", "worker"])
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn up_shows_the_log_tail_of_failed_containers() {
+    let mut crashed = container("myapp", "web", "abc");
+    crashed["State"] = "exited".into();
+    crashed["ExitCode"] = 1.into();
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest"),
+        LockFixture::new(),
+        Scenario::new()
+            .compose_ps(&[crashed])
+            .respond(
+                "logs --timestamps --tail 5 abc",
+                "2024-05-01T10:00:00Z bind: address already in use",
+            ),
+    );
+
+    let output = harness.run(&["up", "--failure-logs", "5"]);
+
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("myapp-web-1 exited with code 1"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("    bind: address already in use"));
+
+    let output = harness.run(&["up", "--failure-logs", "0"]);
+    assert_success(&output);
+    assert_eq!(
+        harness
+            .invocations_with(&["logs"])
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn ps_shows_the_failing_healthcheck_of_unhealthy_containers() {
+    let mut unhealthy = container("myapp", "web", "abc");
+    unhealthy["Health"] = "unhealthy".into();
+    unhealthy["Status"] = "Up 2 minutes (unhealthy)".into();
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("db", "postgres:latest"),
+        LockFixture::new(),
+        Scenario::new()
+            .compose_ps(&[unhealthy, container("myapp", "db", "def")])
+            .respond(
+                "inspect abc",
+                r#"[{"Id":"abc","State":{"Health":{"Log":[
+                    {"ExitCode":1,"Output":"curl: (7) refused\n"}]}}}]"#,
+            ),
+    );
+
+    let output = harness.run(&["ps"]);
+
+    assert_success(&output);
+    let stdout = stdout(&output);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    let web = lines
+        .iter()
+        .position(|line| line.contains("myapp-web-1"))
+        .unwrap();
+    assert_eq!(
+        lines[web + 1].trim(),
+        "↳ healthcheck exit 1: curl: (7) refused"
+    );
+    assert_eq!(
+        harness
+            .invocations_with(&["inspect"])
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn up_quiet_reports_failed_project() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().fail("compose *myapp up*", "pull access denied", 1),
+    );
+
+    let output = harness.run(&["up", "--quiet", "--skip-healthcheck"]);
+
+    assert_failure(&output);
+    assert_eq!(
+        harness
+            .invocations_with(&["up", "-d"])
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn lock_leaves_locked_services_untouched() {
+    let lock = LockFixture::new().locked(
+        "myapp.web",
+        "nginx:latest",
+        Some("1.25.0"),
+        DIGEST_A,
+    );
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest"),
+        lock,
+        Scenario::new(),
+    );
+    let original = harness.lock_contents();
+
+    let output = harness.run(&["lock", "myapp"]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("No images found to update"));
+    assert_eq!(harness.lock_contents(), original);
+    assert!(harness.invocations().is_empty());
+}
+
+#[test]
+fn lock_force_resolves_existing_entries_again() {
+    let lock = LockFixture::new().locked(
+        "myapp.web",
+        "not a valid image",
+        Some("1.25.0"),
+        DIGEST_A,
+    );
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "not a valid image"),
+        lock,
+        Scenario::new(),
+    );
+    let original = harness.lock_contents();
+
+    let output = harness.run(&["lock"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("No images found to update"));
+
+    let output = harness.run(&["lock", "--force", "myapp.web"]);
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("1 of 1 images failed to resolve")
+    );
+    assert_eq!(harness.lock_contents(), original);
+}
+
+#[test]
+fn update_changed_since_only_touches_images_changed_in_git() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "not a valid image")
+            .service("db", "postgres:16"),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+    let project_file = harness.project_file();
+    let current = std::fs::read_to_string(&project_file).unwrap();
+    std::fs::write(
+        &project_file,
+        current.replace("not a valid image", "nginx:1.26"),
+    )
+    .unwrap();
+    for args in [
+        &["init", "-q"][..],
+        &["add", "."],
+        &["commit", "-q", "-m", "projects"],
+    ] {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(harness.path())
+            .args(["-c", "user.name=nirion", "-c", "user.email=nirion@test"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+    std::fs::write(&project_file, current).unwrap();
+
+    let output = harness.run(&["update", "--changed-since", "HEAD"]);
+    assert_failure(&output);
+    assert!(stdout(&output).starts_with(
+        "1 service image(s) changed since HEAD:\n  myapp.web not a valid image\n"
+    ));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("1 of 1 images failed to resolve")
+    );
+
+    let output = harness.run(&["lock", "--changed-since", "no-such-ref"]);
+    assert_failure(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("at no-such-ref"));
+    assert_eq!(harness.lock_contents(), "{}");
+}
+
+#[test]
+fn lock_resolves_new_services_through_the_registry() {
+    let registry = MockRegistry::start();
+    let digest =
+        registry.push("acme/web", "1", &[(VERSION_LABEL, "1.0.0")], 12_000_000);
+    let image = format!("{}/acme/web:1", registry.host());
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", &image),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["--insecure-registry", registry.host(), "lock"]);
+
+    assert_success(&output);
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains(&format!(
+            "  Added:\n    myapp.web  1.0.0  {}  12 MB\n",
+            &digest[7..19]
+        )),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Lock file updated successfully"));
+    let locked: serde_json::Value =
+        serde_json::from_str(&harness.lock_contents()).unwrap();
+    assert_eq!(
+        locked["images"]["myapp.web"],
+        json!({
+            "image": image,
+            "version": "1.0.0",
+            "digest": digest,
+            "size": 12_000_000,
+        })
+    );
+}
+
+#[test]
+fn update_moves_stale_entries_to_the_registry_digest() {
+    let registry = MockRegistry::start();
+    let old = registry.push("acme/web", "1", &[(VERSION_LABEL, "1.0.0")], 1);
+    let new = registry.push(
+        "acme/web",
+        "1",
+        &[
+            (VERSION_LABEL, "1.1.0"),
+            (SOURCE_LABEL, "https://github.com/acme/web"),
+        ],
+        1,
+    );
+    let image = format!("{}/acme/web:1", registry.host());
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", &image),
+        LockFixture::new().locked("myapp.web", &image, Some("1.0.0"), &old),
+        Scenario::new(),
+    );
+    let summary = harness.path().join("summary.md");
+
+    let output = harness
+        .nirion()
+        .args(["--insecure-registry", registry.host(), "update"])
+        .arg("--summary-file")
+        .arg(&summary)
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    let stdout = stdout(&output);
+    assert!(
+        stdout.contains(&format!(
+            "  Updated:\n    myapp.web  1.0.0 → 1.1.0  {} → {}  1 B\n",
+            &old[7..19],
+            &new[7..19]
+        )),
+        "{stdout}"
+    );
+    let locked: serde_json::Value =
+        serde_json::from_str(&harness.lock_contents()).unwrap();
+    assert_eq!(locked["images"]["myapp.web"]["digest"], new);
+    assert_eq!(locked["images"]["myapp.web"]["version"], "1.1.0");
+    assert_eq!(
+        std::fs::read_to_string(summary).unwrap(),
+        format!(
+            "Update locked images: 1 updated\n\n\
+             - `myapp.web`: 1.0.0 (`{}`) → 1.1.0 (`{}`) \
+             ([changelog](https://github.com/acme/web/releases))\n",
+            &old[7..19],
+            &new[7..19]
+        )
+    );
+}
+
+#[test]
+fn update_rejects_invalid_reference_without_touching_lock() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "not a valid image"),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["update", "myapp.web"]);
+
+    assert_failure(&output);
+    assert_eq!(harness.lock_contents(), "{}");
+    assert!(harness.invocations().is_empty());
+}
+
+#[test]
+fn up_pull_first_pulls_every_project_before_starting() {
+    let harness =
+        Harness::new(two_projects(), LockFixture::new(), Scenario::new());
+
+    let output =
+        harness.run(&["up", "--pull-first", "--quiet", "--skip-healthcheck"]);
+
+    assert_success(&output);
+    let invocations = harness.invocations();
+    let last_pull = invocations
+        .iter()
+        .rposition(|args| args.last().map(String::as_str) == Some("pull"))
+        .unwrap();
+    let first_up = invocations
+        .iter()
+        .position(|args| args.ends_with(&["up".into(), "-d".into()]))
+        .unwrap();
+    assert!(last_pull < first_up);
+    assert_eq!(
+        harness
+            .invocations_with(&["pull"])
+            .len(),
+        2
+    );
+    assert_eq!(
+        harness
+            .invocations_with(&["up", "-d"])
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn up_pull_first_starts_nothing_when_a_pull_fails() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().fail("compose *other pull", "registry unavailable", 1),
+    );
+
+    let output =
+        harness.run(&["up", "--pull-first", "--quiet", "--skip-healthcheck"]);
+
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no containers were started"));
+    assert!(stderr.contains("registry unavailable"));
+    assert!(
+        harness
+            .invocations_with(&["up", "-d"])
+            .is_empty()
+    );
+}
+
+#[test]
+fn up_recreates_containers_behind_a_tag_at_the_locked_digest() {
+    let mut worker = container("myapp", "worker", "def");
+    worker["Image"] = json!("alpine:latest");
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("worker", "alpine:latest"),
+        LockFixture::new()
+            .locked("myapp.web", "nginx:latest", None, DIGEST_A)
+            .locked("myapp.worker", "alpine:latest", None, DIGEST_A),
+        Scenario::new()
+            .compose_ps(&[container("myapp", "web", "abc"), worker])
+            .respond(
+                "image inspect nginx:latest",
+                json!([{"Id": "sha256:new", "RepoDigests": [format!("nginx@{DIGEST_A}")]}])
+                    .to_string(),
+            )
+            .respond(
+                "image inspect alpine:latest",
+                json!([{"Id": "sha256:alpine", "RepoDigests": [format!("alpine@{DIGEST_A}")]}])
+                    .to_string(),
+            )
+            .respond("inspect abc", r#"[{"Image": "sha256:old"}]"#)
+            .respond("inspect def", r#"[{"Image": "sha256:alpine"}]"#),
+    );
+    std::fs::write(
+        harness.path().join("compose.yml"),
+        "services:\n  web:\n    image: nginx:latest\n  worker:\n    \
+         image: alpine:latest\n",
+    )
+    .unwrap();
+
+    let output = harness.run(&["up", "--skip-healthcheck"]);
+
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "Recreating myapp.web: its containers run an older image than \
+             nginx:latest, which is at the locked aaaaaaaaaaaa now"
+        ),
+        "{stderr}"
+    );
+    let recreated = harness.invocations_with(&["--force-recreate"]);
+    assert_eq!(recreated.len(), 1);
+    assert!(recreated[0].ends_with(&[
+        "up".into(),
+        "-d".into(),
+        "--force-recreate".into(),
+        "--no-deps".into(),
+        "web".into()
+    ]));
+    assert_eq!(
+        harness
+            .invocations_with(&["up", "-d"])
+            .len(),
+        2
+    );
+
+    let output =
+        harness.run(&["up", "--skip-healthcheck", "--no-auto-recreate"]);
+
+    assert_success(&output);
+    assert_eq!(
+        harness
+            .invocations_with(&["--force-recreate"])
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn up_warns_instead_of_recreating_from_a_stale_compose_file() {
+    let old_digest = format!("sha256:{}", "b".repeat(64));
+    let mut web = container("myapp", "web", "abc");
+    web["Image"] = json!(format!("nginx@{old_digest}"));
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest"),
+        LockFixture::new().locked("myapp.web", "nginx:latest", None, DIGEST_A),
+        Scenario::new().compose_ps(&[web]),
+    );
+    std::fs::write(
+        harness.path().join("compose.yml"),
+        format!("services:\n  web:\n    image: nginx:latest@{old_digest}\n"),
+    )
+    .unwrap();
+
+    let output = harness.run(&["up", "--skip-healthcheck"]);
+
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "warning: myapp.web: the lock pins aaaaaaaaaaaa, but its compose \
+             file still pins bbbbbbbbbbbb; rebuild it"
+        ),
+        "{stderr}"
+    );
+    assert!(
+        harness
+            .invocations_with(&["--force-recreate"])
+            .is_empty()
+    );
+}
+
+#[test]
+fn project_hooks_run_around_up_and_down() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .hooks(json!({
+                "preUp": ["echo \"$NIRION_PROJECT $NIRION_COMPOSE_FILE\" > pre-up"],
+                "postUp": [
+                    {"command": "exit 4", "ignoreFailure": true},
+                    "echo cache warmed"
+                ],
+                "preDown": ["echo dumping; exit 1"]
+            })),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["up", "--plain", "--skip-healthcheck"]);
+
+    assert_success(&output);
+    assert_eq!(
+        std::fs::read_to_string(harness.path().join("pre-up")).unwrap(),
+        format!(
+            "myapp {}\n",
+            harness
+                .path()
+                .join("compose.yml")
+                .display()
+        )
+    );
+    assert!(stdout(&output).contains("[myapp] postUp\ncache warmed\n"));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("[myapp] ignoring failed postUp hook: `exit 4` failed")
+    );
+
+    let output = harness.run(&["down", "--plain"]);
+
+    assert_failure(&output);
+    assert!(stdout(&output).contains("dumping"));
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("preDown hook of myapp failed")
+    );
+    assert!(
+        harness
+            .invocations_with(&["down"])
+            .is_empty()
+    );
+
+    let output = harness.run(&["down", "--plain", "--no-hooks"]);
+
+    assert_success(&output);
+    assert!(!stdout(&output).contains("dumping"));
+    assert_eq!(
+        harness
+            .invocations_with(&["down"])
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn reload_runs_down_and_up_hooks_around_each_half() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .hooks(json!({
+                "preDown": ["echo preDown >> hooks"],
+                "postDown": ["echo postDown >> hooks"],
+                "preUp": ["echo preUp >> hooks"],
+                "postUp": ["echo postUp >> hooks"]
+            })),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["reload", "--plain", "--skip-healthcheck"]);
+
+    assert_success(&output);
+    assert_eq!(
+        std::fs::read_to_string(harness.path().join("hooks")).unwrap(),
+        "preDown\npostDown\npreUp\npostUp\n"
+    );
+
+    let output =
+        harness.run(&["reload", "--plain", "--skip-healthcheck", "--no-hooks"]);
+
+    assert_success(&output);
+    assert_eq!(
+        std::fs::read_to_string(harness.path().join("hooks")).unwrap(),
+        "preDown\npostDown\npreUp\npostUp\n"
+    );
+    assert_eq!(
+        harness
+            .invocations_with(&["down"])
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn lock_migrate_upgrades_digest_only_lock_file() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest"),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+    std::fs::write(
+        harness.lock_file(),
+        json!({"myapp.web": DIGEST_A, "gone.db": DIGEST_A}).to_string(),
+    )
+    .unwrap();
+
+    let output = harness.run(&["lock", "migrate"]);
+
+    assert_success(&output);
+    let migrated = stdout(&output);
+    assert!(migrated.contains("from lock schema v0 to v2"));
+    assert!(migrated.contains("myapp.web: image set to nginx:latest"));
+    assert!(migrated.contains("gone.db: not in the project file"));
+
+    let lock: serde_json::Value =
+        serde_json::from_str(&harness.lock_contents()).unwrap();
+    assert_eq!(lock["version"], 2);
+    assert_eq!(
+        lock["images"]["myapp.web"],
+        json!({"image": "nginx:latest", "version": null, "digest": DIGEST_A})
+    );
+
+    let output = harness.run(&["lock", "migrate"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("already uses lock schema v2"));
+}
+
+#[test]
+fn refuses_lock_file_from_newer_nirion() {
+    let harness =
+        Harness::new(two_projects(), LockFixture::new(), Scenario::new());
+    std::fs::write(harness.lock_file(), r#"{"version":9,"images":{}}"#)
+        .unwrap();
+
+    let output = harness.run(&["lock"]);
+
+    assert_failure(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("schema version 9"), "{stderr}");
+
+    // Commands that never touch the lock file don't read it.
+    assert_success(&harness.run(&["ps"]));
+}
+
+#[test]
+fn lock_split_writes_per_project_files_usable_with_lock_dir() {
+    let lock = LockFixture::new()
+        .locked("myapp.web", "nginx:latest", Some("1.25.0"), DIGEST_A)
+        .locked("myapp.worker", "alpine:latest", None, DIGEST_A)
+        .locked("other.api", "node:22", Some("22.1.0"), DIGEST_A);
+    let harness = Harness::new(two_projects(), lock, Scenario::new());
+    let dir = harness.path().join("locks");
+
+    let output = harness.run(&["lock", "split", dir.to_str().unwrap()]);
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("Split 3 entries into 2 project lock"));
+    let myapp: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(dir.join("myapp.lock")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(myapp["images"]["web"]["version"], "1.25.0");
+    assert!(myapp["images"]["worker"].is_object());
+
+    let output = harness
+        .nirion_with_lock_dir(&dir)
+        .args(["lock"])
+        .output()
+        .unwrap();
+
+    assert_success(&output);
+    assert!(stdout(&output).contains("No images found to update"));
+}
+
+#[test]
+fn registries_missing_auth_fails_for_private_registry() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service("web", "nginx:latest")
+            .service("api", "ghcr.io/acme/api:1"),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["registries"]);
+    assert_success(&output);
+    let table = stdout(&output);
+    assert!(table.contains("ghcr.io"));
+    assert!(table.contains("missing"));
+    assert!(table.contains("index.docker.io"));
+
+    let output = harness.run(&["registries", "--missing-auth", "--json"]);
+    assert_failure(&output);
+    let report: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["registries"][0]["registry"], "ghcr.io");
+    assert_eq!(
+        report["registries"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn lifecycle_commands_are_recorded_in_the_history() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "compose.yml")
+            .service_with(
+                "web",
+                json!({
+                    "image": "nginx:latest",
+                    "resolvedImage": format!("nginx:latest@{DIGEST_A}")
+                }),
+            )
+            .service("worker", "alpine:latest"),
+        LockFixture::new(),
+        Scenario::new()
+            .compose_ps(&[container("myapp", "web", "abc")])
+            .fail("compose * restart *", "restart failed", 1),
+    );
+
+    assert_success(&harness.run(&["stop", "--quiet", "myapp"]));
+    assert_failure(&harness.run(&["restart", "--quiet", "myapp.worker"]));
+
+    let output = harness.run(&["history", "myapp.web", "--json"]);
+    assert_success(&output);
+    let entries: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(entries.as_array().unwrap().len(), 1);
+    assert_eq!(entries[0]["command"], "stop");
+    assert_eq!(entries[0]["target"], "myapp");
+    assert_eq!(entries[0]["outcome"], "success");
+    assert_eq!(entries[0]["services"], json!({ "myapp.web": DIGEST_A }));
+
+    let output = harness.run(&["history", "myapp", "-n", "1"]);
+    assert_success(&output);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("restart myapp.worker"));
+    assert!(stdout.contains("failed:"));
+    assert!(!stdout.contains("stop"));
+}
+
+fn web_replicas(count: u32) -> Vec<serde_json::Value> {
+    (1..=count)
+        .map(|index| replica("myapp", "web", &format!("id{index}"), index))
+        .collect()
+}
+
+#[test]
+fn exec_all_replicas_labels_output_and_exits_with_the_worst_code() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new()
+            .compose_ps(&web_replicas(3))
+            .respond("compose * exec * --index 1 web hostname", "host-1")
+            .fail("compose * exec * --index 2 web hostname", "boom", 3)
+            .respond("compose * exec * --index 3 web hostname", "host-3"),
+    );
+
+    let output =
+        harness.run(&["exec", "myapp.web", "--all-replicas", "hostname"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    let stdout = stdout(&output);
+    for expected in [
+        "✓ myapp.web[1]",
+        "  host-1",
+        "✗ myapp.web[2] (exit 3)",
+        "✓ myapp.web[3]",
+        "2/3 replica(s) succeeded",
+    ] {
+        assert!(stdout.contains(expected), "{expected} in {stdout}");
+    }
+    assert!(String::from_utf8_lossy(&output.stderr).contains("boom"));
+}
+
+#[test]
+fn exec_index_range_runs_only_the_selected_replicas() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new().compose_ps(&web_replicas(3)),
+    );
+
+    let output =
+        harness.run(&["exec", "myapp.web", "--index", "2-3", "hostname"]);
+
+    assert_success(&output);
+    let indices = harness
+        .invocations()
+        .into_iter()
+        .filter(|invocation| invocation.contains(&"exec".to_string()))
+        .map(|invocation| {
+            let at = invocation
+                .iter()
+                .position(|arg| arg == "--index")
+                .unwrap();
+            invocation[at + 1].clone()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(indices, ["2", "3"]);
+
+    let output =
+        harness.run(&["exec", "myapp.web", "--index", "3-4", "hostname"]);
+    assert_failure(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "myapp.web has no replica with index 4 (available: 1, 2, 3)"
+    ));
+}
+
+#[test]
+fn logs_merge_sorts_every_service_into_one_stream() {
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new(),
+        Scenario::new()
+            .compose_ps(&[
+                container("myapp", "web", "web1"),
+                container("myapp", "worker", "wrk1"),
+            ])
+            .respond(
+                "logs --timestamps web1",
+                "2026-03-01T10:00:01Z request received\n\
+                 2026-03-01T10:00:03Z responded 500",
+            )
+            .respond(
+                "logs --timestamps wrk1",
+                "2026-03-01T10:00:02Z job failed\n\
+                 traceback line",
+            ),
+    );
+
+    let output = harness.run(&["logs", "myapp", "--merge"]);
+
+    assert_success(&output);
+    assert_eq!(
+        stdout(&output),
+        "[myapp.web] request received\n\
+         [myapp.worker] job failed\n\
+         [myapp.worker] traceback line\n\
+         [myapp.web] responded 500\n"
+    );
+
+    let merged = harness.path().join("merged.log");
+    let output = harness.run(&[
+        "logs",
+        "myapp",
+        "--merge",
+        "--timestamps",
+        "--output-file",
+        &merged.to_string_lossy(),
+    ]);
+
+    assert_success(&output);
+    assert!(stdout(&output).is_empty());
+    let contents = std::fs::read_to_string(merged).unwrap();
+    assert!(
+        contents.starts_with(
+            "[myapp.web] 2026-03-01T10:00:01+00:00 request received\n"
+        ),
+        "{contents}"
+    );
+    assert_eq!(contents.lines().count(), 4);
+}
+
+#[test]
+fn update_by_image_lists_matching_services_without_resolving() {
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("cache", "cache.yml")
+            .service("redis", "redis:7")
+            .service("web", "nginx:latest")
+            .project("queue", "queue.yml")
+            .service("broker", "docker.io/library/redis:6.2"),
+        LockFixture::new(),
+        Scenario::new(),
+    );
+
+    let output = harness.run(&["update", "--image", "redis", "--list-matches"]);
+    assert_success(&output);
+    assert_eq!(stdout(&output), "cache.redis\nqueue.broker\n");
+
+    let output = harness.run(&[
+        "update",
+        "queue",
+        "--image",
+        "*/redis",
+        "--list-matches",
+    ]);
+    assert_success(&output);
+    assert_eq!(stdout(&output), "queue.broker\n");
+
+    let output = harness.run(&["lock", "--image", "postgres"]);
+    assert_success(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("no service image matches postgres")
+    );
+    assert_eq!(harness.lock_contents(), "{}");
+    assert!(harness.invocations().is_empty());
+}
+
+#[test]
+fn maintenance_keeps_lifecycle_commands_away_from_a_project() {
+    let harness =
+        Harness::new(two_projects(), LockFixture::new(), Scenario::new());
+    let up = |harness: &Harness, target: &str| {
+        harness.run(&["up", target, "--plain", "--skip-healthcheck"])
+    };
+
+    let output = harness.run(&[
+        "maintenance",
+        "on",
+        "myapp",
+        "--reason",
+        "db migration",
+    ]);
+    assert_success(&output);
+
+    let output = harness.run(&["maintenance", "list"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("db migration"));
+
+    let output = up(&harness, "myapp.web");
+    assert_failure(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("myapp is in maintenance (db migration, set by")
+    );
+    assert!(
+        harness
+            .invocations_with(&["up"])
+            .is_empty()
+    );
+
+    let output = up(&harness, "*");
+    assert_success(&output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("skipping myapp, which is in maintenance")
+    );
+    let projects = |harness: &Harness| {
+        harness
+            .invocations_with(&["up"])
+            .into_iter()
+            .map(|args| args[4].clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(projects(&harness), ["other"]);
+
+    assert_success(&harness.run(&["maintenance", "off", "myapp"]));
+    assert_success(&up(&harness, "myapp"));
+    assert_eq!(projects(&harness), ["other", "myapp"]);
+    assert_failure(&harness.run(&["maintenance", "off", "myapp"]));
+}
+
+#[test]
+fn impact_preview_shows_healthy_services_and_lock_changes() {
+    let old_digest = format!("sha256:{}", "b".repeat(64));
+    let mut web = container("myapp", "web", "abc");
+    web["Image"] = json!(format!("nginx@{old_digest}"));
+    let mut worker = container("myapp", "worker", "def");
+    worker["Image"] = json!(format!("alpine@{DIGEST_A}"));
+    let harness = Harness::new(
+        ProjectsFixture::new()
+            .project("myapp", "myapp.yml")
+            .service("web", "nginx:latest")
+            .service("worker", "alpine:latest")
+            .project("other", "other.yml")
+            .service("web", "nginx:latest"),
+        LockFixture::new()
+            .locked("myapp.web", "nginx:latest", None, DIGEST_A)
+            .locked("myapp.worker", "alpine:latest", None, DIGEST_A),
+        Scenario::new().compose_ps(&[web, worker]),
+    );
+    let stderr = |output: &std::process::Output| {
+        String::from_utf8_lossy(&output.stderr).to_string()
+    };
+    let preview = "down * affects 2 projects, 3 services:\n  \
+                   myapp  2 services  2 healthy (web, worker)  1 lock change (web)\n";
+
+    let output = harness.run(&["down", "--plain"]);
+
+    assert_failure(&output);
+    let down_stderr = stderr(&output);
+    assert!(down_stderr.contains(preview), "{down_stderr}");
+    assert!(
+        down_stderr.contains("  other  1 service   none healthy\n"),
+        "{down_stderr}"
+    );
+    assert!(down_stderr.contains("Take down all 2 projects?"));
+    assert!(
+        harness
+            .invocations_with(&["down"])
+            .is_empty()
+    );
+
+    let output = harness.run(&["restart", "--plain"]);
+    assert_failure(&output);
+    let restart_stderr = stderr(&output);
+    assert!(
+        restart_stderr.contains("restart * affects 2 projects"),
+        "{restart_stderr}"
+    );
+    assert!(
+        restart_stderr.contains("Run restart *?"),
+        "{restart_stderr}"
+    );
+    assert!(
+        harness
+            .invocations_with(&["restart"])
+            .is_empty()
+    );
+
+    let output = harness.run(&["-y", "restart", "--plain"]);
+    assert_success(&output);
+    assert!(!stderr(&output).contains("affects"));
+
+    let output = harness.run(&["-y", "stop", "myapp", "--plain", "--preview"]);
+    assert_success(&output);
+    assert!(stderr(&output).contains(
+        "stop myapp affects 1 project, 2 services:\n  \
+         myapp  2 services  2 healthy (web, worker)  1 lock change (web)\n"
+    ));
+}
+
+#[test]
+fn bundle_collects_the_state_and_records_what_failed() {
+    let mut unhealthy = container("myapp", "web", "abc");
+    unhealthy["Health"] = "unhealthy".into();
+    unhealthy["Status"] = "Up 2 minutes (unhealthy)".into();
+    let harness = Harness::new(
+        two_projects(),
+        LockFixture::new().locked("myapp.web", "nginx:latest", None, DIGEST_A),
+        Scenario::new()
+            .respond(
+                "compose -f *myapp.yml* config",
+                "services:\n  web:\n    image: nginx:latest\n    \
+                 environment:\n      DB_PASSWORD: hunter2\n      TZ: UTC\n",
+            )
+            .fail("compose -f *other.yml* config", "no such file", 1)
+            .compose_ps(&[unhealthy, container("myapp", "worker", "def")])
+            .respond(
+                "logs --timestamps --tail 200 abc",
+                "2026-03-01T12:00:00.000000000Z connection refused",
+            )
+            .respond("version", "Client: Docker Engine 28.0.0")
+            .respond("compose version", "Docker Compose version v2.33.0"),
+    );
+
+    let output = harness.run(&["bundle", "--output", "support.tar.gz"]);
+    assert_success(&output);
+
+    let archive =
+        std::fs::File::open(harness.path().join("support.tar.gz")).unwrap();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    let files = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry
+                .path()
+                .unwrap()
+                .strip_prefix("nirion-bundle")
+                .unwrap()
+                .display()
+                .to_string();
+            let mut contents = String::new();
+            std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+            (path, contents)
+        })
+        .collect::<std::collections::BTreeMap<_, _>>();
+
+    assert_eq!(
+        files.keys().collect::<Vec<_>>(),
+        [
+            "checks.json",
+            "compose/myapp.yml",
+            "lock/nirion.lock",
+            "logs/myapp/myapp-web-1.log",
+            "manifest.json",
+            "projects.json",
+            "status.json",
+            "versions/compose.txt",
+            "versions/docker.txt",
+        ]
+    );
+    assert!(files["compose/myapp.yml"].contains("DB_PASSWORD: '********'"));
+    assert!(files["compose/myapp.yml"].contains("TZ: UTC"));
+    assert_eq!(files["logs/myapp/myapp-web-1.log"], "connection refused\n");
+    assert!(files["lock/nirion.lock"].contains(DIGEST_A));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&files["manifest.json"]).unwrap();
+    let errors = manifest["parts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|part| part.get("error").is_some())
+        .map(|part| part["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(errors, ["compose config of other"]);
+}
@@ -0,0 +1,471 @@
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use serde_json::{Map, Value, json};
+use tempfile::TempDir;
+
+pub const DIGEST_A: &str =
+    "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+/// One canned docker response, selected by matching the space-joined
+/// arguments against a glob where `*` matches anything.
+#[derive(Debug, Clone)]
+struct Response {
+    pattern: String,
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+/// Describes how the fake docker answers. The first matching response
+/// wins; unmatched invocations succeed silently.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    responses: Vec<Response>,
+}
+
+impl Scenario {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn respond(
+        mut self,
+        pattern: &str,
+        stdout: impl Into<String>,
+    ) -> Self {
+        self.responses.push(Response {
+            pattern: pattern.to_string(),
+            stdout: stdout.into(),
+            stderr: String::new(),
+            exit_code: 0,
+        });
+        self
+    }
+
+    pub fn fail(
+        mut self,
+        pattern: &str,
+        stderr: impl Into<String>,
+        exit_code: i32,
+    ) -> Self {
+        self.responses.push(Response {
+            pattern: pattern.to_string(),
+            stdout: String::new(),
+            stderr: stderr.into(),
+            exit_code,
+        });
+        self
+    }
+
+    /// Answers `compose ps` for any project with the given containers,
+    /// and the `docker ps` listing several projects at once with all of
+    /// them.
+    pub fn compose_ps(
+        self,
+        containers: &[Value],
+    ) -> Self {
+        let lines = containers
+            .iter()
+            .map(Value::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let docker_ps_lines = containers
+            .iter()
+            .map(|container| {
+                let mut container = container.clone();
+                container["Names"] = container["Name"].clone();
+                container.to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.respond("compose * ps *", lines)
+            .respond("ps -a *", docker_ps_lines)
+    }
+
+    fn script(
+        &self,
+        log: &Path,
+    ) -> String {
+        let mut script = format!(
+            "#!/bin/sh\nprintf '%s\\n' '---' \"$@\" >> {}\ncase \"$*\" in\n",
+            quote(&log.to_string_lossy())
+        );
+
+        for response in &self.responses {
+            script.push_str(&format!(
+                "  {})\n    printf '%s' {}\n    printf '%s' {} >&2\n    exit {}\n    ;;\n",
+                glob(&response.pattern),
+                quote(&with_newline(&response.stdout)),
+                quote(&with_newline(&response.stderr)),
+                response.exit_code,
+            ));
+        }
+
+        script.push_str("esac\nexit 0\n");
+        script
+    }
+}
+
+fn with_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{text}\n")
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Quotes the literal parts of a glob so spaces and shell metacharacters
+/// match verbatim while `*` keeps its meaning.
+fn glob(pattern: &str) -> String {
+    pattern
+        .split('*')
+        .map(|part| {
+            if part.is_empty() {
+                String::new()
+            } else {
+                quote(part)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("*")
+}
+
+/// A compose `ps --format json` entry for a running container, with the
+/// labels `docker ps` tells projects apart by.
+pub fn container(
+    project: &str,
+    service: &str,
+    id: &str,
+) -> Value {
+    replica(project, service, id, 1)
+}
+
+/// Like [`container`], for replica `index` of the service.
+pub fn replica(
+    project: &str,
+    service: &str,
+    id: &str,
+    index: u32,
+) -> Value {
+    json!({
+        "ID": id,
+        "Name": format!("{project}-{service}-{index}"),
+        "Project": project,
+        "Service": service,
+        "Labels": format!(
+            "com.docker.compose.container-number={index},\
+             com.docker.compose.project={project},\
+             com.docker.compose.service={service}"
+        ),
+        "Image": "nginx:latest",
+        "State": "running",
+        "Health": "",
+        "ExitCode": 0,
+        "RunningFor": "2 minutes",
+        "Status": "Up 2 minutes",
+        "Ports": "",
+        "Networks": "default"
+    })
+}
+
+/// Builds the projects file consumed via `--project-file`.
+#[derive(Debug, Clone, Default)]
+pub struct ProjectsFixture {
+    projects: Map<String, Value>,
+    current: Option<String>,
+}
+
+impl ProjectsFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn project(
+        mut self,
+        name: &str,
+        compose: &str,
+    ) -> Self {
+        self.projects.insert(
+            name.to_string(),
+            json!({"name": name, "dockerCompose": compose, "services": {}}),
+        );
+        self.current = Some(name.to_string());
+        self
+    }
+
+    /// Sets the `hooks` of the most recently added project.
+    pub fn hooks(
+        mut self,
+        hooks: Value,
+    ) -> Self {
+        let current = self
+            .current
+            .as_ref()
+            .expect("add a project before its hooks");
+        self.projects[current]["hooks"] = hooks;
+        self
+    }
+
+    /// Adds a service to the most recently added project.
+    pub fn service(
+        self,
+        name: &str,
+        image: &str,
+    ) -> Self {
+        self.service_with(name, json!({"image": image}))
+    }
+
+    pub fn service_with(
+        mut self,
+        name: &str,
+        mut service: Value,
+    ) -> Self {
+        let defaults =
+            json!({"healthcheck": false, "restart": null, "image": null});
+        for (key, value) in defaults.as_object().unwrap() {
+            service
+                .as_object_mut()
+                .unwrap()
+                .entry(key)
+                .or_insert(value.clone());
+        }
+
+        let current = self
+            .current
+            .as_ref()
+            .expect("add a project before its services");
+        self.projects[current]["services"]
+            .as_object_mut()
+            .unwrap()
+            .insert(name.to_string(), service);
+        self
+    }
+
+    /// Writes the projects file and an empty compose file for every
+    /// project, resolved against the file's directory.
+    fn write(
+        &self,
+        path: &Path,
+    ) {
+        fs::write(path, serde_json::to_string_pretty(&self.projects).unwrap())
+            .unwrap();
+
+        let dir = path.parent().unwrap();
+        for project in self.projects.values() {
+            let compose = dir.join(
+                project["dockerCompose"]
+                    .as_str()
+                    .unwrap(),
+            );
+            fs::write(compose, "services: {}\n").unwrap();
+        }
+    }
+}
+
+/// Builds the lock file, keyed by `project.service`.
+#[derive(Debug, Clone, Default)]
+pub struct LockFixture {
+    images: Map<String, Value>,
+}
+
+impl LockFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn locked(
+        mut self,
+        target: &str,
+        image: &str,
+        version: Option<&str>,
+        digest: &str,
+    ) -> Self {
+        self.images.insert(
+            target.to_string(),
+            json!({"image": image, "version": version, "digest": digest}),
+        );
+        self
+    }
+
+    fn write(
+        &self,
+        path: &Path,
+    ) {
+        fs::write(path, serde_json::to_string_pretty(&self.images).unwrap())
+            .unwrap();
+    }
+}
+
+/// A temp directory holding the projects file, lock file and fake
+/// docker for one test.
+pub struct Harness {
+    dir: TempDir,
+}
+
+impl Harness {
+    pub fn new(
+        projects: ProjectsFixture,
+        lock: LockFixture,
+        scenario: Scenario,
+    ) -> Self {
+        let harness = Self {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        projects.write(&harness.project_file());
+        lock.write(&harness.lock_file());
+        harness.install(&scenario);
+        harness
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn project_file(&self) -> PathBuf {
+        self.path().join("projects.json")
+    }
+
+    pub fn lock_file(&self) -> PathBuf {
+        self.path().join("nirion.lock")
+    }
+
+    fn bin_dir(&self) -> PathBuf {
+        self.path().join("bin")
+    }
+
+    fn log_file(&self) -> PathBuf {
+        self.path().join("docker.log")
+    }
+
+    /// Replaces the fake docker's responses, keeping the invocation log.
+    pub fn install(
+        &self,
+        scenario: &Scenario,
+    ) {
+        let docker = self.bin_dir().join("docker");
+        fs::create_dir_all(self.bin_dir()).unwrap();
+        fs::write(&docker, scenario.script(&self.log_file())).unwrap();
+
+        let mut permissions = fs::metadata(&docker)
+            .unwrap()
+            .permissions();
+        permissions.set_mode(0o755);
+        fs::set_permissions(&docker, permissions).unwrap();
+    }
+
+    /// A nirion command that resolves `docker` from the fake on `PATH`.
+    pub fn nirion(&self) -> Command {
+        let mut command = self.nirion_without_lock();
+        command
+            .arg("--lock-file")
+            .arg(self.lock_file());
+        command
+    }
+
+    /// Like [`Self::nirion`], with per-project lock files in `dir`.
+    pub fn nirion_with_lock_dir(
+        &self,
+        dir: &Path,
+    ) -> Command {
+        let mut command = self.nirion_without_lock();
+        command.arg("--lock-dir").arg(dir);
+        command
+    }
+
+    fn nirion_without_lock(&self) -> Command {
+        let path = env::var_os("PATH").unwrap_or_default();
+        let path = env::join_paths(
+            std::iter::once(self.bin_dir()).chain(env::split_paths(&path)),
+        )
+        .unwrap();
+
+        let mut command = Command::new(env!("CARGO_BIN_EXE_nirion"));
+        command
+            .current_dir(self.path())
+            .env("PATH", path)
+            .env("NIRION_STATE_DIR", self.path().join("state"))
+            .arg("--project-file")
+            .arg(self.project_file());
+        command
+    }
+
+    pub fn run(
+        &self,
+        args: &[&str],
+    ) -> Output {
+        self.nirion()
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    /// Every docker invocation so far, in order.
+    pub fn invocations(&self) -> Vec<Vec<String>> {
+        let log = fs::read_to_string(self.log_file()).unwrap_or_default();
+        let mut invocations = Vec::new();
+
+        for line in log.lines() {
+            if line == "---" {
+                invocations.push(Vec::new());
+            } else if let Some(current) = invocations.last_mut() {
+                current.push(line.to_string());
+            }
+        }
+
+        invocations
+    }
+
+    /// Invocations whose arguments contain `needle` as a contiguous run.
+    pub fn invocations_with(
+        &self,
+        needle: &[&str],
+    ) -> Vec<Vec<String>> {
+        self.invocations()
+            .into_iter()
+            .filter(|args| {
+                args.windows(needle.len())
+                    .any(|window| window == needle)
+            })
+            .collect()
+    }
+
+    pub fn lock_contents(&self) -> String {
+        fs::read_to_string(self.lock_file()).unwrap()
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    nirion_tui_lib::ansi::strip_ansi_codes(&String::from_utf8_lossy(
+        &output.stdout,
+    ))
+    .to_string()
+}
+
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "stdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+pub fn assert_failure(output: &Output) {
+    assert!(
+        !output.status.success(),
+        "stdout:\n{}\nstderr:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
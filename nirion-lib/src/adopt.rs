@@ -0,0 +1,296 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    docker::{
+        COMPOSE_CONFIG_FILES_LABEL, COMPOSE_PROJECT_LABEL,
+        COMPOSE_SERVICE_LABEL, DockerCommand, compose_containers_json,
+        inspect_containers,
+    },
+    hooks::ProjectHooks,
+    projects::{Project, ProjectName, ProjectNames, Projects, Service},
+};
+
+/// A compose project found through its containers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredProject {
+    pub name: String,
+    /// The compose files the containers were created from, in the order
+    /// compose was given them. Empty if compose didn't record them.
+    pub compose_files: Vec<PathBuf>,
+    pub services: BTreeMap<String, DiscoveredService>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredService {
+    /// The image reference the container was created with.
+    pub image: String,
+    /// Docker's restart policy; `None` if the container has none.
+    pub restart: Option<String>,
+    pub healthcheck: bool,
+}
+
+impl DiscoveredProject {
+    /// The project file entry, or `None` without a compose file to point
+    /// it at. A project takes a single compose file, so only the first of
+    /// several is kept.
+    pub fn to_project(&self) -> Option<Project> {
+        let compose_file = self.compose_files.first()?;
+        Some(Project {
+            name: ProjectName(self.name.clone()),
+            docker_compose: compose_file.clone(),
+            services: self
+                .services
+                .iter()
+                .map(|(name, service)| {
+                    let service = Service {
+                        image: Some(service.image.clone()),
+                        resolved_image: None,
+                        healthcheck: service.healthcheck,
+                        restart: service.restart.clone(),
+                        profiles: Vec::new(),
+                        lint_ignore: Vec::new(),
+                        build: false,
+                    };
+                    (name.clone(), service)
+                })
+                .collect(),
+            profiles: Vec::new(),
+            hooks: ProjectHooks::default(),
+        })
+    }
+}
+
+/// Every compose project with a container on the host, including stopped
+/// ones, by compose project name.
+pub async fn discover_compose_projects(
+    docker_command: &DockerCommand
+) -> anyhow::Result<Vec<DiscoveredProject>> {
+    #[derive(Deserialize)]
+    struct Listed {
+        #[serde(rename = "ID")]
+        id: String,
+    }
+
+    let ids = compose_containers_json(docker_command)
+        .await?
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Listed>(line).ok())
+        .map(|listed| listed.id)
+        .collect::<Vec<_>>();
+    let containers: Vec<AdoptedContainer> =
+        inspect_containers(docker_command, &ids).await?;
+    Ok(group_by_project(containers))
+}
+
+/// A project file holding `projects`, checked by loading it the way
+/// every other command does.
+pub fn adopted_project_file(projects: &[Project]) -> anyhow::Result<String> {
+    let projects = projects
+        .iter()
+        .map(|project| (project.name.0.clone(), project))
+        .collect::<BTreeMap<_, _>>();
+    let json = serde_json::to_string_pretty(&projects)?;
+    Projects::from_json(&json, ProjectNames::Reject)?;
+    Ok(json)
+}
+
+#[derive(Debug, Deserialize)]
+struct AdoptedContainer {
+    #[serde(rename = "Config")]
+    config: AdoptedConfig,
+    #[serde(rename = "HostConfig", default)]
+    host_config: AdoptedHostConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdoptedConfig {
+    #[serde(rename = "Image")]
+    image: String,
+    #[serde(rename = "Labels", default)]
+    labels: BTreeMap<String, String>,
+    #[serde(rename = "Healthcheck")]
+    healthcheck: Option<AdoptedHealthcheck>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdoptedHealthcheck {
+    #[serde(rename = "Test", default)]
+    test: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AdoptedHostConfig {
+    #[serde(rename = "RestartPolicy")]
+    restart_policy: Option<AdoptedRestartPolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdoptedRestartPolicy {
+    #[serde(rename = "Name", default)]
+    name: String,
+}
+
+/// Replicas of a service share their settings, so the first container of
+/// each service stands for it.
+fn group_by_project(
+    containers: Vec<AdoptedContainer>
+) -> Vec<DiscoveredProject> {
+    let mut projects: BTreeMap<String, DiscoveredProject> = BTreeMap::new();
+    for container in containers {
+        let labels = &container.config.labels;
+        let (Some(name), Some(service)) = (
+            labels.get(COMPOSE_PROJECT_LABEL),
+            labels.get(COMPOSE_SERVICE_LABEL),
+        ) else {
+            continue;
+        };
+
+        let project = projects
+            .entry(name.clone())
+            .or_insert_with(|| DiscoveredProject {
+                name: name.clone(),
+                compose_files: labels
+                    .get(COMPOSE_CONFIG_FILES_LABEL)
+                    .map(|files| compose_files(files))
+                    .unwrap_or_default(),
+                services: BTreeMap::new(),
+            });
+        project
+            .services
+            .entry(service.clone())
+            .or_insert_with(|| DiscoveredService {
+                image: container.config.image.clone(),
+                restart: container
+                    .host_config
+                    .restart_policy
+                    .as_ref()
+                    .map(|policy| policy.name.clone())
+                    .filter(|name| !name.is_empty()),
+                healthcheck: container
+                    .config
+                    .healthcheck
+                    .as_ref()
+                    .is_some_and(|check| {
+                        check
+                            .test
+                            .first()
+                            .is_some_and(|test| test != "NONE")
+                    }),
+            });
+    }
+    projects.into_values().collect()
+}
+
+/// Compose joins the files with commas, without escaping any in a path.
+fn compose_files(files: &str) -> Vec<PathBuf> {
+    files
+        .split(',')
+        .filter(|file| !file.is_empty())
+        .map(Path::new)
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containers_are_grouped_into_adoptable_projects() {
+        let containers: Vec<AdoptedContainer> =
+            serde_json::from_value(serde_json::json!([
+                {
+                    "Config": {
+                        "Image": "nginx:1.27",
+                        "Labels": {
+                            "com.docker.compose.project": "blog",
+                            "com.docker.compose.service": "web",
+                            "com.docker.compose.project.config_files":
+                                "/srv/blog/compose.yml,/srv/blog/override.yml"
+                        },
+                        "Healthcheck": {"Test": ["CMD", "curl", "localhost"]}
+                    },
+                    "HostConfig": {"RestartPolicy": {"Name": "unless-stopped"}}
+                },
+                {
+                    "Config": {
+                        "Image": "nginx:1.27",
+                        "Labels": {
+                            "com.docker.compose.project": "blog",
+                            "com.docker.compose.service": "web"
+                        }
+                    }
+                },
+                {
+                    "Config": {
+                        "Image": "blog-migrate",
+                        "Labels": {
+                            "com.docker.compose.project": "blog",
+                            "com.docker.compose.service": "migrate"
+                        },
+                        "Healthcheck": {"Test": ["NONE"]}
+                    },
+                    "HostConfig": {"RestartPolicy": {"Name": ""}}
+                },
+                {
+                    "Config": {
+                        "Image": "redis",
+                        "Labels": {"com.docker.compose.project": "cache"}
+                    }
+                }
+            ]))
+            .unwrap();
+
+        let projects = group_by_project(containers);
+        assert_eq!(projects.len(), 1);
+        let blog = &projects[0];
+        assert_eq!(
+            blog.compose_files,
+            [
+                PathBuf::from("/srv/blog/compose.yml"),
+                PathBuf::from("/srv/blog/override.yml")
+            ]
+        );
+        assert_eq!(
+            blog.services["web"],
+            DiscoveredService {
+                image: "nginx:1.27".into(),
+                restart: Some("unless-stopped".into()),
+                healthcheck: true,
+            }
+        );
+        assert_eq!(
+            blog.services["migrate"],
+            DiscoveredService {
+                image: "blog-migrate".into(),
+                restart: None,
+                healthcheck: false,
+            }
+        );
+
+        let json = adopted_project_file(&[blog.to_project().unwrap()]).unwrap();
+        let loaded = Projects::from_json(&json, ProjectNames::Reject).unwrap();
+        let project = &loaded["blog"];
+        assert_eq!(
+            project.docker_compose,
+            PathBuf::from("/srv/blog/compose.yml")
+        );
+        assert!(project.services["migrate"].is_one_shot());
+        assert!(!project.services["web"].is_one_shot());
+    }
+
+    #[test]
+    fn projects_without_a_recorded_compose_file_cannot_be_adopted() {
+        let project = DiscoveredProject {
+            name: "legacy".into(),
+            compose_files: Vec::new(),
+            services: BTreeMap::new(),
+        };
+        assert!(project.to_project().is_none());
+    }
+}
@@ -7,7 +7,7 @@ use tokio::io::{AsyncBufReadExt, BufReader};
 use crate::{
     context::NirionContext,
     events::{ComposeEvent, ProcessEvent},
-    projects::{ProjectName, TargetSelector},
+    projects::{Project, TargetSelector},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,12 +59,8 @@ fn compose_stream_single(
             }
             TargetSelector::Project(proj) => {
                 let project = context.projects[&proj.name].clone();
-                let mut stream = compose_cmd(
-                    context,
-                    project.docker_compose,
-                    project.name,
-                    args.clone(),
-                );
+                let mut stream =
+                    compose_cmd(context, project, None, args.clone());
 
                 while let Some(event) = stream.next().await {
                     match event {
@@ -87,14 +83,11 @@ fn compose_stream_single(
             }
             TargetSelector::Service(sel) => {
                 let project = context.projects[&sel.project].clone();
-                let mut cmd_args = args.clone();
-                cmd_args.push(sel.service.clone());
-
                 let mut stream = compose_cmd(
                     context,
-                    project.docker_compose,
-                    project.name,
-                    cmd_args,
+                    project,
+                    Some(sel.service.clone()),
+                    args.clone(),
                 );
 
                 while let Some(event) = stream.next().await {
@@ -150,12 +143,7 @@ fn compose_stream_all(
                             project: name.clone(),
                         }));
 
-                    let mut stream = compose_cmd(
-                        context,
-                        project.docker_compose,
-                        project.name,
-                        args,
-                    );
+                    let mut stream = compose_cmd(context, project, None, args);
 
                     while let Some(event) = stream.next().await {
                         match event {
@@ -200,19 +188,35 @@ fn compose_stream_all(
     rx.boxed()
 }
 
-fn compose_cmd(
-    context: NirionContext,
-    compose_file: String,
-    project_name: ProjectName,
-    args: Vec<String>,
-) -> BoxStream<'static, anyhow::Result<ProcessEvent>> {
+/// The arguments after `docker compose` that run `args` for `project`.
+/// A `service` is appended last, narrowing the command to it.
+pub fn compose_args(
+    project: &Project,
+    service: Option<&str>,
+    args: &[String],
+) -> Vec<String> {
     let mut cmd_args = vec![
         "--file".to_string(),
-        compose_file,
+        project
+            .docker_compose
+            .to_string_lossy()
+            .into_owned(),
         "--project-name".to_string(),
-        project_name.deref().to_string(),
+        project.name.deref().to_string(),
     ];
-    cmd_args.extend(args);
+    cmd_args.extend(project.profile_args());
+    cmd_args.extend_from_slice(args);
+    cmd_args.extend(service.map(str::to_string));
+    cmd_args
+}
+
+fn compose_cmd(
+    context: NirionContext,
+    project: Project,
+    service: Option<String>,
+    args: Vec<String>,
+) -> BoxStream<'static, anyhow::Result<ProcessEvent>> {
+    let cmd_args = compose_args(&project, service.as_deref(), &args);
 
     run_docker_compose(context, cmd_args)
 }
@@ -315,9 +319,7 @@ mod tests {
     use crate::projects::Projects;
     use crate::{docker::DockerCommand, lock::LockedImages};
     use nirion_oci_lib::client::NirionOciClient;
-    use std::{
-        fs, os::unix::fs::PermissionsExt, path::Path, path::PathBuf, sync::Arc,
-    };
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path, sync::Arc};
 
     async fn collect_events(
         stream: BoxStream<'static, anyhow::Result<ProcessEvent>>
@@ -368,7 +370,7 @@ exit {exit_code}
         NirionContext {
             projects: projects(),
             locked_images: LockedImages::default(),
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -528,16 +530,55 @@ exit 0
         );
     }
 
+    #[test]
+    fn compose_args_per_selector() {
+        let projects = projects();
+        let args = ["top".to_string()];
+
+        assert_eq!(
+            compose_args(&projects["api"], None, &args),
+            ["--file", "api.yml", "--project-name", "api", "top"]
+        );
+        assert_eq!(
+            compose_args(&projects["api"], Some("web"), &args),
+            ["--file", "api.yml", "--project-name", "api", "top", "web"]
+        );
+
+        let mut project = projects["worker"].clone();
+        project.profiles = vec!["debug".into()];
+        assert_eq!(
+            compose_args(&project, Some("jobs"), &args),
+            [
+                "--file",
+                "worker.yml",
+                "--project-name",
+                "worker",
+                "--profile",
+                "debug",
+                "top",
+                "jobs"
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn compose_cmd_builds_args_and_streams_events() {
         let dir = tempfile::tempdir().unwrap();
         let args_file = dir.path().join("args");
         let docker = write_fake_docker(dir.path(), &args_file, 0);
 
+        let project = serde_json::from_value(serde_json::json!({
+            "name": "myapp",
+            "dockerCompose": "compose.yml",
+            "services": {},
+            "profiles": ["debug"]
+        }))
+        .unwrap();
+
         let events = collect_events(compose_cmd(
             context(fake_docker_command(&docker)),
-            "compose.yml".into(),
-            ProjectName("myapp".into()),
+            project,
+            None,
             vec!["logs".into()],
         ))
         .await;
@@ -545,7 +586,8 @@ exit 0
         assert!(events.iter().all(Result::is_ok));
         assert_eq!(
             fs::read_to_string(args_file).unwrap(),
-            "compose\n--file\ncompose.yml\n--project-name\nmyapp\nlogs\n"
+            "compose\n--file\ncompose.yml\n--project-name\nmyapp\n\
+             --profile\ndebug\nlogs\n"
         );
     }
 
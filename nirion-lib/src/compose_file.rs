@@ -1,15 +1,28 @@
-use std::fs;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    ops::Deref,
+    path::Path,
+};
 
+use anyhow::Context;
+use nirion_oci_lib::{client::NirionOciClient, oci_client::Reference};
 use serde_yaml_ng::{Mapping, Value};
 
-use crate::projects::Project;
+use crate::{
+    docker::DockerCommand,
+    lock::LockedImages,
+    projects::{Project, Projects},
+};
 
-pub fn load_compose(path: &str) -> anyhow::Result<Value> {
-    let data = fs::read_to_string(path)
-        .map_err(|e| anyhow::anyhow!("Failed reading {}: {}", path, e))?;
+pub fn load_compose(path: impl AsRef<Path>) -> anyhow::Result<Value> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!("Failed reading {}: {}", path.display(), e)
+    })?;
 
     serde_yaml_ng::from_str::<Value>(&data).map_err(|e| {
-        anyhow::anyhow!("Compose file parse error in {}: {}", path, e)
+        anyhow::anyhow!("Compose file parse error in {}: {}", path.display(), e)
     })
 }
 
@@ -17,13 +30,263 @@ pub fn full_compose(project: &Project) -> anyhow::Result<Value> {
     load_compose(&project.docker_compose)
 }
 
+/// Services compose leaves out with `project`'s profiles: those whose
+/// `profiles` in the compose file name none of them. Falls back to the
+/// profiles in the project file if the compose file can't be read.
+pub fn disabled_services(project: &Project) -> BTreeSet<String> {
+    let Ok(compose) = full_compose(project) else {
+        return project
+            .services
+            .iter()
+            .filter(|(_, service)| !project.service_enabled(service))
+            .map(|(name, _)| name.clone())
+            .collect();
+    };
+
+    compose
+        .get("services")
+        .and_then(Value::as_mapping)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, service)| {
+            let profiles = service.get("profiles")?.as_sequence()?;
+            let enabled = profiles
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|profile| {
+                    project
+                        .profiles
+                        .iter()
+                        .any(|p| p == profile)
+                });
+            if profiles.is_empty() || enabled {
+                return None;
+            }
+            name.as_str().map(str::to_string)
+        })
+        .collect()
+}
+
+/// Selected projects whose compose file can't be opened, with the
+/// reason, e.g. a nix store path that was garbage collected.
+pub fn unreadable_compose_files(
+    projects: &Projects,
+    selected: &[String],
+) -> BTreeMap<String, String> {
+    selected
+        .iter()
+        .filter_map(|name| {
+            let project = projects.get(name)?;
+            let error = fs::File::open(&project.docker_compose).err()?;
+            Some((
+                name.clone(),
+                format!("{} ({error})", project.docker_compose.display()),
+            ))
+        })
+        .collect()
+}
+
+/// Fails with one error naming every selected project whose compose file
+/// can't be opened, before docker compose fails on each of them.
+pub fn check_compose_files(
+    projects: &Projects,
+    selected: &[String],
+) -> anyhow::Result<()> {
+    let unreadable = unreadable_compose_files(projects, selected);
+    if unreadable.is_empty() {
+        return Ok(());
+    }
+
+    let files = unreadable
+        .iter()
+        .map(|(project, file)| format!("  {project}: {file}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    anyhow::bail!(
+        "compose files can't be read:\n{files}\n\
+         The project file may point at a generation that was garbage \
+         collected; run `nixos-rebuild switch`, or re-run with --nix-eval \
+         to build a current project file."
+    )
+}
+
+/// Services declared on only one side of a project, e.g. after renaming a
+/// service in the compose file but not in the project file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceMismatch {
+    /// In the project file, but not in the compose file.
+    pub only_in_project: Vec<String>,
+    /// In the compose file, but not in the project file.
+    pub only_in_compose: Vec<String>,
+}
+
+/// Cross-references the services of `project` with its compose file.
+/// `None` if both declare the same services.
+pub fn service_mismatch(
+    project: &Project
+) -> anyhow::Result<Option<ServiceMismatch>> {
+    let compose = full_compose(project)?;
+    let compose_services = compose
+        .get("services")
+        .and_then(Value::as_mapping)
+        .map(|services| {
+            services
+                .keys()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default();
+
+    let only_in_project = project
+        .services
+        .keys()
+        .filter(|service| !compose_services.contains(*service))
+        .cloned()
+        .collect::<Vec<_>>();
+    let only_in_compose = compose_services
+        .into_iter()
+        .filter(|service| !project.services.contains_key(service))
+        .collect::<Vec<_>>();
+
+    if only_in_project.is_empty() && only_in_compose.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(ServiceMismatch {
+        only_in_project,
+        only_in_compose,
+    }))
+}
+
+/// A service whose image in the project file isn't the one its compose
+/// file runs, so the lock file pins an image compose never pulls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageMismatch {
+    pub service: String,
+    pub project_image: String,
+    pub compose_image: String,
+}
+
+/// Compares the image of each service of `project` in the project file
+/// with the one in its compose file. A digest the compose file pins the
+/// image to is ignored, as are services built locally, declared on one
+/// side only, or whose compose image has variables compose fills in.
+pub fn image_mismatches(
+    project: &Project
+) -> anyhow::Result<Vec<ImageMismatch>> {
+    let compose = full_compose(project)?;
+    let services = compose
+        .get("services")
+        .and_then(Value::as_mapping);
+
+    let mut mismatches = Vec::new();
+    for (name, service) in &project.services {
+        let Some(project_image) = service
+            .image
+            .as_deref()
+            .filter(|_| !service.build)
+        else {
+            continue;
+        };
+        let Some(compose_image) = services
+            .and_then(|services| services.get(name.as_str()))
+            .and_then(|definition| definition.get("image"))
+            .and_then(Value::as_str)
+            .filter(|image| !image.contains('$'))
+        else {
+            continue;
+        };
+
+        if !same_image(project_image, compose_image) {
+            mismatches.push(ImageMismatch {
+                service: name.clone(),
+                project_image: project_image.to_string(),
+                compose_image: compose_image.to_string(),
+            });
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Whether two references name the same repository and tag, however
+/// they are spelled, e.g. `nginx` and `docker.io/library/nginx:latest`.
+fn same_image(
+    a: &str,
+    b: &str,
+) -> bool {
+    let without_digest = |image: &str| {
+        image
+            .split_once('@')
+            .map_or(image, |(name, _)| name)
+            .to_string()
+    };
+    let (a, b) = (without_digest(a), without_digest(b));
+    match (
+        Reference::try_from(a.as_str()),
+        Reference::try_from(b.as_str()),
+    ) {
+        (Ok(a), Ok(b)) => {
+            a.resolve_registry() == b.resolve_registry()
+                && a.repository() == b.repository()
+                && a.tag() == b.tag()
+        }
+        _ => a == b,
+    }
+}
+
+pub async fn resolved_compose(
+    docker_command: &DockerCommand,
+    project: &Project,
+) -> anyhow::Result<Value> {
+    let output = docker_command
+        .command()
+        .arg("compose")
+        .arg("-f")
+        .arg(&project.docker_compose)
+        .arg("--project-name")
+        .arg(project.name.deref())
+        .args(project.profile_args())
+        .arg("config")
+        .output()
+        .await
+        .context("failed to execute docker compose config")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "docker compose config failed with status {}{}{}",
+            output.status,
+            if stderr.trim().is_empty() { "" } else { ": " },
+            stderr.trim()
+        );
+    }
+
+    let yaml = String::from_utf8_lossy(&output.stdout);
+    serde_yaml_ng::from_str::<Value>(&yaml).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to parse docker compose config output for project `{}`: {}",
+            project.name,
+            e
+        )
+    })
+}
+
 pub fn service_compose(
     project_name: &str,
     project: &Project,
     service_name: &str,
 ) -> anyhow::Result<Value> {
     let compose = load_compose(&project.docker_compose)?;
+    extract_service(&compose, project_name, service_name)
+}
 
+/// Extracts a single service together with the top-level volumes and
+/// networks it references, so the result is a valid compose file on its own.
+pub fn extract_service(
+    compose: &Value,
+    project_name: &str,
+    service_name: &str,
+) -> anyhow::Result<Value> {
     let services = compose.get("services").ok_or_else(|| {
         anyhow::anyhow!("No `services:` section in compose file")
     })?;
@@ -33,7 +296,7 @@ pub fn service_compose(
         .ok_or_else(|| anyhow::anyhow!("`services:` is not a mapping"))?;
 
     let service_value = services_map
-        .get(&Value::String(service_name.to_string()))
+        .get(Value::String(service_name.to_string()))
         .ok_or_else(|| {
             anyhow::anyhow!(
                 "Service `{}` not found in compose file for project `{}`",
@@ -50,9 +313,160 @@ pub fn service_compose(
     );
     root_map.insert(Value::String("services".into()), Value::Mapping(svc_map));
 
+    let sections = [
+        ("volumes", referenced_volumes(service_value)),
+        ("networks", referenced_networks(service_value)),
+    ];
+    for (section, names) in sections {
+        let Some(entries) = compose
+            .get(section)
+            .and_then(Value::as_mapping)
+        else {
+            continue;
+        };
+
+        let selected: Mapping = entries
+            .iter()
+            .filter(|(name, _)| {
+                name.as_str()
+                    .is_some_and(|name| names.contains(name))
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        if !selected.is_empty() {
+            root_map.insert(
+                Value::String(section.into()),
+                Value::Mapping(selected),
+            );
+        }
+    }
+
     Ok(Value::Mapping(root_map))
 }
 
+fn referenced_volumes(service: &Value) -> BTreeSet<String> {
+    let Some(volumes) = service
+        .get("volumes")
+        .and_then(Value::as_sequence)
+    else {
+        return BTreeSet::new();
+    };
+
+    volumes
+        .iter()
+        .filter_map(|volume| match volume {
+            Value::String(short) => short
+                .split_once(':')
+                .map(|(source, _)| source),
+            Value::Mapping(long) => long
+                .get("source")
+                .and_then(Value::as_str),
+            _ => None,
+        })
+        .filter(|source| is_named_volume(source))
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_named_volume(source: &str) -> bool {
+    !source.is_empty() && !source.starts_with(['/', '.', '~', '$'])
+}
+
+fn referenced_networks(service: &Value) -> BTreeSet<String> {
+    if service.get("network_mode").is_some() {
+        return BTreeSet::new();
+    }
+
+    match service.get("networks") {
+        Some(Value::Sequence(networks)) => networks
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::Mapping(networks)) => networks
+            .keys()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => BTreeSet::from(["default".to_string()]),
+    }
+}
+
+/// Rewrites each service's `image:` to the locked `repo@digest` reference.
+/// Services whose configured image differs from the locked one are left
+/// untouched, matching how the nix module decides what to pin.
+/// Points the services of `compose` at their locked digests. Images of
+/// a registry with a mirror on `oci_client` are pinned to the mirror,
+/// as the lock only records the upstream reference.
+pub fn pin_compose(
+    compose: &mut Value,
+    project_name: &str,
+    project: &Project,
+    locked_images: &LockedImages,
+    oci_client: &NirionOciClient,
+) {
+    let Some(services) = compose
+        .get_mut("services")
+        .and_then(Value::as_mapping_mut)
+    else {
+        return;
+    };
+
+    for (name, service) in services.iter_mut() {
+        let Some(name) = name.as_str() else {
+            continue;
+        };
+        let Some(configured) = project.services.get(name) else {
+            continue;
+        };
+        let key = format!("{project_name}.{name}");
+        let Some(locked) = locked_images.get(&key) else {
+            continue;
+        };
+        if configured.image.as_deref() != Some(locked.image.as_str()) {
+            continue;
+        }
+        let Some(service) = service.as_mapping_mut() else {
+            continue;
+        };
+
+        service.insert(
+            Value::String("image".into()),
+            Value::String(mirrored_image(
+                oci_client,
+                pinned_image(&locked.image, &locked.digest),
+            )),
+        );
+    }
+}
+
+fn pinned_image(
+    image: &str,
+    digest: &str,
+) -> String {
+    let image = image
+        .split_once('@')
+        .map_or(image, |(repo, _)| repo);
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    let repo = match image[name_start..].rfind(':') {
+        Some(i) => &image[..name_start + i],
+        None => image,
+    };
+
+    format!("{}@{}", repo, digest)
+}
+
+fn mirrored_image(
+    oci_client: &NirionOciClient,
+    image: String,
+) -> String {
+    Reference::try_from(image.as_str())
+        .ok()
+        .and_then(|reference| oci_client.mirror_reference(&reference))
+        .map_or(image, |mirrored| mirrored.whole())
+}
+
 pub fn compose_to_string(compose: &Value) -> anyhow::Result<String> {
     serde_yaml_ng::to_string(compose).map_err(|e| {
         anyhow::anyhow!("Failed to pretty-print compose file: {}", e)
@@ -75,8 +489,10 @@ mod tests {
     fn project(path: String) -> Project {
         Project {
             name: ProjectName("myapp".into()),
-            docker_compose: path,
+            docker_compose: path.into(),
             services: BTreeMap::new(),
+            profiles: vec![],
+            hooks: Default::default(),
         }
     }
 
@@ -113,6 +529,118 @@ services:
         );
     }
 
+    #[test]
+    fn check_compose_files_names_every_unreadable_file() {
+        let (_dir, path) = write_compose("services: {}");
+        let projects: Projects = serde_json::from_value(serde_json::json!({
+            "ok": {"name": "ok", "dockerCompose": path, "services": {}},
+            "gone": {
+                "name": "gone",
+                "dockerCompose": "/nix/store/gone-compose.yml",
+                "services": {}
+            },
+            "unselected": {
+                "name": "unselected",
+                "dockerCompose": "/nix/store/also-gone.yml",
+                "services": {}
+            }
+        }))
+        .unwrap();
+
+        let error = check_compose_files(
+            &projects,
+            &["ok".to_string(), "gone".to_string()],
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("  gone: /nix/store/gone-compose.yml ("));
+        assert!(!error.contains("also-gone"));
+        assert!(error.contains("nixos-rebuild switch"));
+
+        assert!(check_compose_files(&projects, &["ok".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn service_mismatch_lists_services_declared_on_one_side() {
+        let (_dir, path) = write_compose(
+            r#"
+services:
+  web:
+    image: nginx
+  frontend:
+    image: node
+"#,
+        );
+        let service: crate::projects::Service = serde_json::from_value(
+            serde_json::json!({"image": "nginx", "restart": null}),
+        )
+        .unwrap();
+        let mut project = project(path);
+        project
+            .services
+            .insert("web".into(), service.clone());
+        project
+            .services
+            .insert("ui".into(), service);
+
+        assert_eq!(
+            service_mismatch(&project).unwrap(),
+            Some(ServiceMismatch {
+                only_in_project: vec!["ui".into()],
+                only_in_compose: vec!["frontend".into()],
+            })
+        );
+
+        project.services.remove("ui");
+        project
+            .services
+            .insert("frontend".into(), project.services["web"].clone());
+        assert_eq!(service_mismatch(&project).unwrap(), None);
+    }
+
+    #[test]
+    fn image_mismatches_compare_references_not_spellings() {
+        let (_dir, path) = write_compose(
+            r#"
+services:
+  web:
+    image: docker.io/library/nginx:1.27@sha256:0000
+  db:
+    image: postgres:15
+  cache:
+    image: redis:${REDIS_TAG}
+  app:
+    build: .
+    image: myapp
+"#,
+        );
+        let service = |image: &str, build: bool| {
+            let mut service: crate::projects::Service = serde_json::from_value(
+                serde_json::json!({"image": image, "restart": null}),
+            )
+            .unwrap();
+            service.build = build;
+            service
+        };
+        let mut project = project(path);
+        project.services = BTreeMap::from([
+            ("web".to_string(), service("nginx:1.27", false)),
+            ("db".to_string(), service("postgres:16", false)),
+            ("cache".to_string(), service("redis:7", false)),
+            ("app".to_string(), service("other", true)),
+            ("worker".to_string(), service("busybox", false)),
+        ]);
+
+        assert_eq!(
+            image_mismatches(&project).unwrap(),
+            [ImageMismatch {
+                service: "db".into(),
+                project_image: "postgres:16".into(),
+                compose_image: "postgres:15".into(),
+            }]
+        );
+    }
+
     #[test]
     fn full_compose_loads_project_compose_file() {
         let (_dir, path) = write_compose("services: {}");
@@ -167,6 +695,221 @@ services:
         );
     }
 
+    #[test]
+    fn service_compose_includes_referenced_volumes_and_networks() {
+        let (_dir, path) = write_compose(
+            r#"
+services:
+  web:
+    image: nginx
+    volumes:
+      - data:/data
+      - ./config:/etc/nginx:ro
+      - type: volume
+        source: cache
+        target: /cache
+    networks:
+      - frontend
+  db:
+    image: postgres
+    volumes:
+      - dbdata:/var/lib/postgresql
+volumes:
+  data: {}
+  cache: {}
+  dbdata: {}
+networks:
+  frontend: {}
+  backend: {}
+"#,
+        );
+
+        let compose = service_compose("myapp", &project(path), "web").unwrap();
+        let volumes = compose["volumes"].as_mapping().unwrap();
+        assert!(volumes.contains_key("data"));
+        assert!(volumes.contains_key("cache"));
+        assert!(!volumes.contains_key("dbdata"));
+        let networks = compose["networks"]
+            .as_mapping()
+            .unwrap();
+        assert!(networks.contains_key("frontend"));
+        assert!(!networks.contains_key("backend"));
+    }
+
+    #[test]
+    fn service_compose_includes_default_network_when_unspecified() {
+        let (_dir, path) = write_compose(
+            r#"
+services:
+  web:
+    image: nginx
+networks:
+  default:
+    name: shared
+"#,
+        );
+
+        let compose = service_compose("myapp", &project(path), "web").unwrap();
+        assert_eq!(compose["networks"]["default"]["name"], "shared");
+        assert!(compose.get("volumes").is_none());
+    }
+
+    fn locked(
+        image: &str,
+        digest: &str,
+    ) -> LockedImages {
+        let mut locked_images = LockedImages::default();
+        locked_images.insert(
+            "myapp.web".into(),
+            crate::lock::VersionedImage {
+                image: image.into(),
+                version: None,
+                digest: digest.into(),
+                size: None,
+                source: None,
+            },
+        );
+        locked_images
+    }
+
+    fn project_with_image(image: &str) -> Project {
+        let mut project = project("compose.yml".into());
+        project.services.insert(
+            "web".into(),
+            crate::projects::Service {
+                image: Some(image.into()),
+                resolved_image: None,
+                healthcheck: false,
+                restart: None,
+                profiles: vec![],
+                lint_ignore: Vec::new(),
+                build: false,
+            },
+        );
+        project
+    }
+
+    #[test]
+    fn pin_compose_rewrites_locked_images() {
+        let mut compose = serde_yaml_ng::from_str::<Value>(
+            "services: {web: {image: 'ghcr.io/acme/web:1.2'}, db: {image: postgres}}",
+        )
+        .unwrap();
+
+        pin_compose(
+            &mut compose,
+            "myapp",
+            &project_with_image("ghcr.io/acme/web:1.2"),
+            &locked("ghcr.io/acme/web:1.2", "sha256:abc"),
+            &NirionOciClient::builder().build(),
+        );
+
+        assert_eq!(
+            compose["services"]["web"]["image"],
+            "ghcr.io/acme/web@sha256:abc"
+        );
+        assert_eq!(compose["services"]["db"]["image"], "postgres");
+    }
+
+    #[test]
+    fn pin_compose_skips_outdated_lock_entries() {
+        let mut compose = serde_yaml_ng::from_str::<Value>(
+            "services: {web: {image: nginx:2}}",
+        )
+        .unwrap();
+
+        pin_compose(
+            &mut compose,
+            "myapp",
+            &project_with_image("nginx:2"),
+            &locked("nginx:1", "sha256:abc"),
+            &NirionOciClient::builder().build(),
+        );
+
+        assert_eq!(compose["services"]["web"]["image"], "nginx:2");
+    }
+
+    #[test]
+    fn pin_compose_pins_mirrored_registries_to_the_mirror() {
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let mut compose = serde_yaml_ng::from_str::<Value>(
+            "services: {web: {image: 'nginx:1'}}",
+        )
+        .unwrap();
+
+        pin_compose(
+            &mut compose,
+            "myapp",
+            &project_with_image("nginx:1"),
+            &locked("nginx:1", &digest),
+            &NirionOciClient::builder()
+                .mirror("docker.io", "mirror.lan")
+                .build(),
+        );
+
+        assert_eq!(
+            compose["services"]["web"]["image"],
+            format!("mirror.lan/library/nginx@{digest}").as_str()
+        );
+    }
+
+    #[test]
+    fn pinned_image_keeps_registry_port() {
+        assert_eq!(
+            pinned_image("localhost:5000/web", "sha256:abc"),
+            "localhost:5000/web@sha256:abc"
+        );
+        assert_eq!(
+            pinned_image("localhost:5000/web:1@sha256:old", "sha256:abc"),
+            "localhost:5000/web@sha256:abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn resolved_compose_parses_docker_compose_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        std::fs::write(
+            &script,
+            "printf '%s\\n' \"$@\" > \"$(dirname \"$0\")/args\"\n\
+             printf 'services:\\n  web:\\n    image: nginx\\n'\n",
+        )
+        .unwrap();
+
+        let compose = resolved_compose(
+            &DockerCommand::with_args("/bin/sh", [script]),
+            &project("compose.yml".into()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(compose["services"]["web"]["image"], "nginx");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("args")).unwrap(),
+            "compose\n-f\ncompose.yml\n--project-name\nmyapp\nconfig\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn resolved_compose_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        std::fs::write(&script, "echo 'bad interpolation' >&2\nexit 1\n")
+            .unwrap();
+
+        let err = resolved_compose(
+            &DockerCommand::with_args("/bin/sh", [script]),
+            &project("compose.yml".into()),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("bad interpolation")
+        );
+    }
+
     #[test]
     fn compose_to_string_serializes_yaml() {
         let compose = serde_yaml_ng::from_str::<Value>("services: {}").unwrap();
@@ -7,7 +7,10 @@ use anyhow::Context;
 use nirion_oci_lib::client::AuthConfig;
 use tokio::process::Command;
 
-use crate::{lock::LockedImages, projects::Projects};
+use crate::{
+    lock::LockedImages,
+    projects::{ProjectNames, Projects},
+};
 
 #[cfg(test)]
 static TEST_NIX_CMD: std::sync::Mutex<Option<Vec<String>>> =
@@ -26,15 +29,44 @@ pub fn load_locked_images(lock_file: &Path) -> anyhow::Result<LockedImages> {
     Ok(locked_images)
 }
 
-pub fn load_projects(project_file: &Path) -> anyhow::Result<Projects> {
+pub fn load_projects(
+    project_file: &Path,
+    names: ProjectNames,
+) -> anyhow::Result<Projects> {
     let project_data = fs::read_to_string(project_file)
         .context("Failed to read projects file")?;
-    let projects = serde_json::from_str(&project_data)
+    let projects = Projects::from_json(&project_data, names)
         .context("Failed to parse projects file")?;
 
     Ok(projects)
 }
 
+/// Where the project file comes from, kept so it can be loaded again,
+/// e.g. as of another git commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectSource {
+    /// A JSON project file.
+    File(PathBuf),
+    /// A nix target that builds the project file.
+    Nix(String),
+    /// Piped in on stdin, given as `--project-file -`. It is read once;
+    /// there is no file to come back to.
+    Stdin,
+}
+
+impl ProjectSource {
+    /// The project file, built first for a nix target.
+    pub async fn project_file(&self) -> anyhow::Result<PathBuf> {
+        match self {
+            ProjectSource::File(path) => Ok(path.clone()),
+            ProjectSource::Nix(target) => build_nix_project_file(target).await,
+            ProjectSource::Stdin => anyhow::bail!(
+                "the project file was read from stdin and can't be read again"
+            ),
+        }
+    }
+}
+
 pub fn load_auth_config(
     auth_file: Option<&Path>
 ) -> anyhow::Result<AuthConfig> {
@@ -65,6 +97,42 @@ pub fn nix_config_target(target: &str) -> String {
     )
 }
 
+pub async fn build_nix_project_file(
+    nix_eval_target: &str
+) -> anyhow::Result<PathBuf> {
+    let output = nix_command()
+        .args(["build", nix_eval_target, "--no-link", "--print-out-paths"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "nix build failed with status {}{}{}",
+            output.status,
+            if stderr.trim().is_empty() { "" } else { ": " },
+            stderr.trim()
+        );
+    }
+
+    let raw_path = str::from_utf8(&output.stdout)?
+        .trim()
+        .to_string();
+
+    Ok(PathBuf::from(raw_path))
+}
+
+fn nix_command() -> Command {
+    #[cfg(test)]
+    if let Some(cmd) = TEST_NIX_CMD.lock().unwrap().clone() {
+        let mut command = Command::new(&cmd[0]);
+        command.args(&cmd[1..]);
+        return command;
+    }
+
+    Command::new("nix")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,7 +266,7 @@ exit {exit_code}
         )
         .unwrap();
 
-        let result = load_projects(&path).unwrap();
+        let result = load_projects(&path, ProjectNames::Reject).unwrap();
         assert!(result.contains_key("myapp"));
         assert_eq!(
             result["myapp"].services["web"]
@@ -210,7 +278,10 @@ exit {exit_code}
 
     #[test]
     fn load_projects_missing_file_errors() {
-        let result = load_projects(Path::new("/nonexistent/projects.json"));
+        let result = load_projects(
+            Path::new("/nonexistent/projects.json"),
+            ProjectNames::Reject,
+        );
         assert!(result.is_err());
     }
 
@@ -219,7 +290,7 @@ exit {exit_code}
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("projects.json");
         std::fs::write(&path, "not json").unwrap();
-        assert!(load_projects(&path).is_err());
+        assert!(load_projects(&path, ProjectNames::Reject).is_err());
     }
 
     #[test]
@@ -329,39 +400,3 @@ exit {exit_code}
         assert!(!err.to_string().is_empty());
     }
 }
-
-pub async fn build_nix_project_file(
-    nix_eval_target: &str
-) -> anyhow::Result<PathBuf> {
-    let output = nix_command()
-        .args(["build", nix_eval_target, "--no-link", "--print-out-paths"])
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!(
-            "nix build failed with status {}{}{}",
-            output.status,
-            if stderr.trim().is_empty() { "" } else { ": " },
-            stderr.trim()
-        );
-    }
-
-    let raw_path = str::from_utf8(&output.stdout)?
-        .trim()
-        .to_string();
-
-    Ok(PathBuf::from(raw_path))
-}
-
-fn nix_command() -> Command {
-    #[cfg(test)]
-    if let Some(cmd) = TEST_NIX_CMD.lock().unwrap().clone() {
-        let mut command = Command::new(&cmd[0]);
-        command.args(&cmd[1..]);
-        return command;
-    }
-
-    Command::new("nix")
-}
@@ -1,14 +1,40 @@
-use std::{path::PathBuf, sync::Arc};
+use std::sync::Arc;
 
 use nirion_oci_lib::client::NirionOciClient;
 
-use crate::{docker::DockerCommand, lock::LockedImages, projects::Projects};
+use crate::{
+    docker::DockerCommand, lock::LockedImages, lock_store::LockStore,
+    projects::Projects,
+};
 
 #[derive(Clone)]
 pub struct NirionContext {
     pub projects: Projects,
+    /// Empty unless the command needs the lock file.
     pub locked_images: LockedImages,
-    pub lock_file: PathBuf,
+    /// `None` when the command doesn't need the lock file.
+    pub lock_store: Option<LockStore>,
     pub oci_client: Arc<NirionOciClient>,
     pub docker_command: DockerCommand,
 }
+
+impl NirionContext {
+    pub fn lock_store(&self) -> anyhow::Result<&LockStore> {
+        self.lock_store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No lock file specified"))
+    }
+
+    /// A copy of this context with `profiles` enabled in every project,
+    /// e.g. from `--profile` on the command line.
+    pub fn with_profiles(
+        &self,
+        profiles: &[String],
+    ) -> Self {
+        let mut context = self.clone();
+        context
+            .projects
+            .enable_profiles(profiles);
+        context
+    }
+}
@@ -0,0 +1,271 @@
+use std::{fmt::Display, time::Duration};
+
+use anyhow::Context;
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::docker::DockerCommand;
+
+/// How long [`probe_daemon`] waits for the daemon to answer.
+pub const DAEMON_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the docker CLI connects when `DOCKER_HOST` isn't set.
+#[cfg(windows)]
+const DEFAULT_DOCKER_HOST: &str = "npipe:////./pipe/docker_engine";
+#[cfg(not(windows))]
+const DEFAULT_DOCKER_HOST: &str = "unix:///var/run/docker.sock";
+
+/// How to check on the daemon; Docker Desktop runs it on macOS and
+/// Windows.
+#[cfg(target_os = "linux")]
+const START_HINT: &str = "`systemctl status docker`";
+#[cfg(not(target_os = "linux"))]
+const START_HINT: &str = "Docker Desktop";
+
+/// What the docker CLI prints when it can't talk to the daemon.
+const UNREACHABLE_MESSAGES: &[&str] = &[
+    "Cannot connect to the Docker daemon",
+    "Is the docker daemon running",
+    "error during connect",
+    "permission denied while trying to connect to the Docker daemon",
+];
+
+/// The docker CLI runs, but the daemon behind it doesn't answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonUnreachable {
+    pub reason: String,
+}
+
+impl Display for DaemonUnreachable {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let host = std::env::var("DOCKER_HOST")
+            .ok()
+            .filter(|host| !host.is_empty())
+            .unwrap_or_else(|| DEFAULT_DOCKER_HOST.to_string());
+        write!(
+            f,
+            "cannot reach the Docker daemon: {}\n\
+             hint: check that it is running ({START_HINT}) \
+             and that {host} is accessible to this user",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for DaemonUnreachable {}
+
+/// Whether `error`, usually from a failed docker command, means the
+/// daemon couldn't be reached rather than that the command itself failed.
+pub fn is_daemon_unreachable(error: &anyhow::Error) -> bool {
+    if error.is::<DaemonUnreachable>() {
+        return true;
+    }
+    reports_unreachable(&format!("{error:#}"))
+}
+
+fn reports_unreachable(message: &str) -> bool {
+    UNREACHABLE_MESSAGES
+        .iter()
+        .any(|pattern| message.contains(pattern))
+}
+
+/// Runs `docker version` against the daemon. Fails with
+/// [`DaemonUnreachable`] if the daemon doesn't answer within `timeout` or
+/// the CLI reports it can't connect; any other failure is left for the
+/// actual command to report.
+pub async fn probe_daemon(
+    docker_command: &DockerCommand,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let output = docker_command
+        .command()
+        .arg("version")
+        .arg("--format")
+        .arg("{{.Server.Version}}")
+        .kill_on_drop(true)
+        .output();
+    let output = match tokio::time::timeout(timeout, output).await {
+        Ok(output) => output.with_context(|| {
+            format!("failed to execute {}", docker_command.program.display())
+        })?,
+        Err(_) => {
+            return Err(DaemonUnreachable {
+                reason: format!(
+                    "no answer within {}",
+                    humanize_timeout(timeout)
+                ),
+            }
+            .into());
+        }
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && reports_unreachable(&stderr) {
+        let reason = stderr
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        return Err(DaemonUnreachable { reason }.into());
+    }
+
+    Ok(())
+}
+
+fn humanize_timeout(timeout: Duration) -> String {
+    if timeout.subsec_millis() == 0 {
+        format!("{}s", timeout.as_secs())
+    } else {
+        format!("{}ms", timeout.as_millis())
+    }
+}
+
+/// Probes the daemon in the background every interval until dropped, for
+/// long-running views that keep going while it is away.
+#[derive(Debug)]
+pub struct DaemonWatch {
+    reachable: watch::Receiver<bool>,
+    task: JoinHandle<()>,
+}
+
+impl DaemonWatch {
+    /// Must be called from within a tokio runtime. The daemon counts as
+    /// reachable until the first probe says otherwise.
+    pub fn spawn(
+        docker_command: DockerCommand,
+        interval: Duration,
+    ) -> Self {
+        let (tx, reachable) = watch::channel(true);
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                ticker.tick().await;
+                let result =
+                    probe_daemon(&docker_command, DAEMON_PROBE_TIMEOUT).await;
+                let reachable = !matches!(
+                    &result,
+                    Err(error) if is_daemon_unreachable(error)
+                );
+                tx.send_if_modified(|current| {
+                    let changed = *current != reachable;
+                    *current = reachable;
+                    changed
+                });
+                if tx.is_closed() {
+                    return;
+                }
+            }
+        });
+
+        Self { reachable, task }
+    }
+
+    /// The result of the latest probe.
+    pub fn reachable(&self) -> bool {
+        *self.reachable.borrow()
+    }
+}
+
+impl Drop for DaemonWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn docker(script: &str) -> (tempfile::TempDir, DockerCommand) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("docker");
+        fs::write(&path, script).unwrap();
+        let command = DockerCommand::with_args(
+            "/bin/sh",
+            [path.to_string_lossy().to_string()],
+        );
+        (dir, command)
+    }
+
+    #[tokio::test]
+    async fn probe_reports_an_unreachable_daemon_once_with_a_hint() {
+        let (_dir, command) = docker(
+            "echo 'Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?' >&2; exit 1",
+        );
+
+        let error = probe_daemon(&command, DAEMON_PROBE_TIMEOUT)
+            .await
+            .unwrap_err();
+
+        assert!(is_daemon_unreachable(&error));
+        let message = error.to_string();
+        assert!(message.starts_with(
+            "cannot reach the Docker daemon: Cannot connect to the Docker daemon"
+        ));
+        assert!(message.contains(START_HINT));
+    }
+
+    #[tokio::test]
+    async fn probe_times_out_and_ignores_other_failures() {
+        let (_dir, hanging) = docker("sleep 5");
+        let error = probe_daemon(&hanging, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("no answer within 50ms")
+        );
+
+        let (_dir, other) = docker("echo 'unknown flag' >&2; exit 125");
+        assert!(
+            probe_daemon(&other, DAEMON_PROBE_TIMEOUT)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_follows_the_daemon_going_away_and_coming_back() {
+        let (dir, command) = docker(
+            "[ -e \"$(dirname \"$0\")/down\" ] && { echo 'Cannot connect to the Docker daemon' >&2; exit 1; }; exit 0",
+        );
+        let down = dir.path().join("down");
+        fs::write(&down, "").unwrap();
+        let watch = DaemonWatch::spawn(command, Duration::from_millis(10));
+        let wait_for = |reachable: bool| {
+            let mut updates = watch.reachable.clone();
+            async move {
+                tokio::time::timeout(
+                    Duration::from_secs(5),
+                    updates.wait_for(|current| *current == reachable),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            }
+        };
+
+        wait_for(false).await;
+        assert!(!watch.reachable());
+        fs::remove_file(&down).unwrap();
+        wait_for(true).await;
+        assert!(watch.reachable());
+    }
+
+    #[test]
+    fn docker_connection_errors_are_recognized() {
+        assert!(is_daemon_unreachable(&anyhow::anyhow!(
+            "docker compose ps failed: error during connect: Get \"http://%2F%2F.%2Fpipe%2Fdocker_engine/v1.45/containers/json\""
+        )));
+        assert!(!is_daemon_unreachable(&anyhow::anyhow!(
+            "no such service: web"
+        )));
+    }
+}
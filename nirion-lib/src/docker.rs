@@ -1,19 +1,27 @@
 use std::{
-    collections::BTreeMap, ffi::OsString, ops::Deref, path::PathBuf,
-    time::Duration,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ffi::OsString,
+    fmt::Display,
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
 use anyhow::Context;
-use futures::{
-    StreamExt, channel::mpsc, stream::BoxStream, stream::select_all,
-};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tokio::process::Command;
 
+use crate::compose_file::disabled_services;
 use crate::context::NirionContext;
-use crate::projects::{
-    Project, ProjectName, Projects, TargetSelector, selected_project_names,
-};
+use crate::monitor::DockerMonitor;
+use crate::projects::{Project, Service, TargetSelector};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DockerCommand {
@@ -59,27 +67,27 @@ pub async fn query_project_status(
     context: &NirionContext,
     project_name: &str,
 ) -> anyhow::Result<ProjectStatus> {
-    let project = &context.projects[project_name];
     query_project_status_for_command(
         &context.docker_command,
-        &project.docker_compose,
-        &project.name,
+        &context.projects[project_name],
     )
     .await
 }
 
-async fn query_project_status_for_command(
+/// Queries a project without a [`NirionContext`], e.g. from shell
+/// completion where only the project file has been loaded.
+pub async fn query_project_status_for_command(
     docker_command: &DockerCommand,
-    compose_file: &str,
-    project_name: &ProjectName,
+    project: &Project,
 ) -> anyhow::Result<ProjectStatus> {
     let output = docker_command
         .command()
         .arg("compose")
         .arg("-f")
-        .arg(compose_file)
+        .arg(&project.docker_compose)
         .arg("--project-name")
-        .arg(project_name.deref())
+        .arg(project.name.deref())
+        .args(project.profile_args())
         .arg("ps")
         .arg("-a")
         .arg("--format")
@@ -100,102 +108,297 @@ async fn query_project_status_for_command(
 
     let json = String::from_utf8_lossy(&output.stdout).to_string();
 
-    ProjectStatus::from_json(&json)
+    ProjectStatus::from_project_json(&json, project)
 }
 
-#[derive(Debug, Clone)]
-pub struct ProjectStatusEvent {
-    pub project: String,
-    pub status: ProjectStatus,
+pub(crate) const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+pub(crate) const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+pub(crate) const COMPOSE_CONFIG_FILES_LABEL: &str =
+    "com.docker.compose.project.config_files";
+pub(crate) const COMPOSE_WORKING_DIR_LABEL: &str =
+    "com.docker.compose.project.working_dir";
+const COMPOSE_ONEOFF_LABEL: &str = "com.docker.compose.oneoff";
+const COMPOSE_CONTAINER_NUMBER_LABEL: &str =
+    "com.docker.compose.container-number";
+
+/// Set to `true` on every throwaway container nirion starts itself, so
+/// that statuses can tell them from the project's own containers.
+pub const NIRION_HELPER_LABEL: &str = "io.nirion.helper";
+
+static SHOW_HELPERS: AtomicBool = AtomicBool::new(false);
+
+/// Whether statuses include helper containers: nirion's own, labeled
+/// [`NIRION_HELPER_LABEL`], and the one-off containers of `docker
+/// compose run`. They are left out by default, so that they don't count
+/// toward a project's state anywhere statuses are shown.
+pub fn show_helper_containers(show: bool) {
+    SHOW_HELPERS.store(show, Ordering::Relaxed);
 }
 
-pub fn status_stream(
-    context: &NirionContext,
-    target: TargetSelector,
-    refresh_interval: Duration,
-) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
-    status_stream_for_command(
-        context.docker_command.clone(),
-        target,
-        context.projects.clone(),
-        refresh_interval,
-    )
+fn shows_helper_containers() -> bool {
+    SHOW_HELPERS.load(Ordering::Relaxed)
 }
 
-fn status_stream_for_command(
-    docker_command: DockerCommand,
-    target: TargetSelector,
-    projects: Projects,
-    refresh_interval: Duration,
-) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
-    let selected = selected_project_names(&target, &projects);
-    let streams = selected
+/// Queries several projects with a single `docker ps` rather than a
+/// `docker compose ps` each, telling containers apart by the labels
+/// compose puts on them. Projects without containers get an empty
+/// status.
+pub async fn query_project_statuses<'a>(
+    docker_command: &DockerCommand,
+    projects: impl IntoIterator<Item = (&'a str, &'a Project)>,
+) -> anyhow::Result<BTreeMap<String, ProjectStatus>> {
+    let by_compose_name =
+        docker_ps_containers(&compose_containers_json(docker_command).await?);
+    projects
         .into_iter()
-        .filter_map(|name| {
-            let project = projects.get(&name)?.clone();
-            Some(project_status_stream_for_command(
-                docker_command.clone(),
-                name,
-                project,
-                refresh_interval,
-            ))
+        .map(|(name, project)| {
+            let containers = by_compose_name
+                .get(project.name.deref())
+                .cloned()
+                .unwrap_or_default();
+            let status = ProjectStatus::from_container_infos(
+                containers,
+                Some(&ComposeOwner::of(project)),
+            )?;
+            Ok((name.to_string(), status))
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
 
-    select_all(streams).boxed()
+/// `docker ps --format json` lines of every container compose created,
+/// whatever the project.
+pub(crate) async fn compose_containers_json(
+    docker_command: &DockerCommand
+) -> anyhow::Result<String> {
+    let mut command = docker_command.command();
+    command
+        .args(["ps", "-a", "--no-trunc", "--format", "json"])
+        .arg("--filter")
+        .arg(format!("label={COMPOSE_PROJECT_LABEL}"));
+    // Containers of `docker compose run`, which are helpers too; asking
+    // docker to leave them out saves parsing them.
+    if !shows_helper_containers() {
+        command
+            .arg("--filter")
+            .arg(format!("label={COMPOSE_ONEOFF_LABEL}=False"));
+    }
+    let output = command
+        .output()
+        .await
+        .context("failed to execute docker ps")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "docker ps failed with status {}{}{}",
+            output.status,
+            if stderr.trim().is_empty() { "" } else { ": " },
+            stderr.trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
-fn project_status_stream_for_command(
-    docker_command: DockerCommand,
-    name: String,
-    project: Project,
-    refresh_interval: Duration,
-) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
-    let (tx, rx) = mpsc::unbounded();
+/// Like [`query_project_status`], but also fills in
+/// [`ServiceStatus::details`].
+pub async fn query_project_status_detailed(
+    context: &NirionContext,
+    project_name: &str,
+) -> anyhow::Result<ProjectStatus> {
+    let mut status = query_project_status(context, project_name).await?;
+    inspect_container_details(&context.docker_command, &mut status).await?;
+    Ok(status)
+}
 
-    tokio::spawn(async move {
-        let mut first_poll = true;
+/// Fetches start time, restart count and OOM state for every container
+/// of the project with a single `docker inspect`. Containers that vanish
+/// between `ps` and `inspect` simply keep `details: None`.
+pub async fn inspect_container_details(
+    docker_command: &DockerCommand,
+    status: &mut ProjectStatus,
+) -> anyhow::Result<()> {
+    inspect_all_container_details(docker_command, [status]).await
+}
 
-        loop {
-            match query_project_status_for_command(
-                &docker_command,
-                &project.docker_compose,
-                &project.name,
-            )
-            .await
-            {
-                Ok(status) => {
-                    if tx
-                        .unbounded_send(Ok(ProjectStatusEvent {
-                            project: name.clone(),
-                            status,
-                        }))
-                        .is_err()
-                    {
-                        return;
-                    }
-                }
-                Err(error) if first_poll => {
-                    if tx.unbounded_send(Err(error)).is_err() {
-                        return;
-                    }
-                }
-                Err(_) => {
-                    if tx.is_closed() {
-                        return;
-                    }
-                }
-            }
+/// [`inspect_container_details`] for several projects at once, still
+/// with a single `docker inspect`.
+pub async fn inspect_all_container_details<'a>(
+    docker_command: &DockerCommand,
+    statuses: impl IntoIterator<Item = &'a mut ProjectStatus>,
+) -> anyhow::Result<()> {
+    let mut statuses = statuses.into_iter().collect::<Vec<_>>();
+    let ids = statuses
+        .iter()
+        .flat_map(|status| status.containers())
+        .map(|container| container.id.clone())
+        .collect::<Vec<_>>();
+    let inspected: Vec<InspectedContainer> =
+        inspect_containers(docker_command, &ids).await?;
+
+    for container in statuses
+        .iter_mut()
+        .flat_map(|status| status.services.values_mut().flatten())
+    {
+        let inspected = inspected
+            .iter()
+            .find(|inspected| inspected.id.starts_with(&container.id));
+        container.details = inspected.map(|inspected| ContainerDetails {
+            started_at: inspected.state.started_at.clone(),
+            restart_count: inspected.restart_count,
+            oom_killed: inspected.state.oom_killed,
+        });
+        container.last_health_check =
+            inspected.and_then(InspectedContainer::last_health_check);
+    }
+
+    Ok(())
+}
 
-            first_poll = false;
-            tokio::time::sleep(refresh_interval).await;
+/// Fills in [`ServiceStatus::last_health_check`] for the unhealthy
+/// containers only, so a plain status query stays one `docker inspect`
+/// away from explaining them without inspecting every container.
+pub async fn inspect_unhealthy_containers(
+    docker_command: &DockerCommand,
+    status: &mut ProjectStatus,
+) -> anyhow::Result<()> {
+    inspect_all_unhealthy_containers(docker_command, [status]).await
+}
+
+/// [`inspect_unhealthy_containers`] for several projects at once, still
+/// with a single `docker inspect`.
+pub async fn inspect_all_unhealthy_containers<'a>(
+    docker_command: &DockerCommand,
+    statuses: impl IntoIterator<Item = &'a mut ProjectStatus>,
+) -> anyhow::Result<()> {
+    let mut statuses = statuses.into_iter().collect::<Vec<_>>();
+    let ids = statuses
+        .iter()
+        .flat_map(|status| status.containers())
+        .filter(|container| container.state == ServiceState::Unhealthy)
+        .map(|container| container.id.clone())
+        .collect::<Vec<_>>();
+    let inspected: Vec<InspectedContainer> =
+        inspect_containers(docker_command, &ids).await?;
+
+    for container in statuses
+        .iter_mut()
+        .flat_map(|status| status.services.values_mut().flatten())
+    {
+        if let Some(inspected) = inspected
+            .iter()
+            .find(|inspected| inspected.id.starts_with(&container.id))
+        {
+            container.last_health_check = inspected.last_health_check();
         }
-    });
+    }
 
-    rx.boxed()
+    Ok(())
 }
 
+/// `docker inspect` of the containers with `ids`, each read into `T`.
+pub(crate) async fn inspect_containers<T: DeserializeOwned>(
+    docker_command: &DockerCommand,
+    ids: &[String],
+) -> anyhow::Result<Vec<T>> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let output = docker_command
+        .command()
+        .arg("inspect")
+        .args(ids)
+        .output()
+        .await
+        .context("failed to execute docker inspect")?;
+
+    // docker inspect exits non-zero when any ID is gone but still prints
+    // the containers it found.
+    Ok(serde_json::from_slice(&output.stdout).unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectedContainerImage {
+    #[serde(rename = "Image")]
+    image: String,
+}
+
+/// The ID of the image each of the containers with `ids` was created
+/// from; containers that are gone by now are left out.
+pub async fn container_image_ids(
+    docker_command: &DockerCommand,
+    ids: &[String],
+) -> anyhow::Result<Vec<String>> {
+    let containers: Vec<InspectedContainerImage> =
+        inspect_containers(docker_command, ids).await?;
+    Ok(containers
+        .into_iter()
+        .map(|container| container.image)
+        .collect())
+}
+
+/// An image pulled on this host, as `docker image inspect` reports it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LocalImage {
+    #[serde(rename = "Id", default)]
+    pub id: String,
+    /// `repository@digest` for every repository the image was pulled
+    /// from; empty for images only built or tagged here.
+    #[serde(rename = "RepoDigests", default)]
+    pub repo_digests: Vec<String>,
+    #[serde(rename = "Config", default)]
+    pub config: LocalImageConfig,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LocalImageConfig {
+    #[serde(rename = "Labels", default)]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+/// `docker image inspect` of `image`, or `None` if it isn't on this host
+/// or docker can't be asked.
+pub async fn inspect_local_image(
+    docker_command: &DockerCommand,
+    image: &str,
+) -> Option<LocalImage> {
+    let output = docker_command
+        .command()
+        .args(["image", "inspect", image])
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())?;
+    serde_json::from_slice::<Vec<LocalImage>>(&output.stdout)
+        .ok()?
+        .into_iter()
+        .next()
+}
+
+#[derive(Debug, Clone)]
+pub struct ProjectStatusEvent {
+    pub project: String,
+    pub status: ProjectStatus,
+}
+
+/// Polls the projects selected by `target` with an event after every
+/// poll, see [`DockerMonitor`].
+pub fn status_stream(
+    context: &NirionContext,
+    target: TargetSelector,
+    refresh_interval: Duration,
+) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
+    DockerMonitor::builder(refresh_interval)
+        .every_poll(true)
+        .spawn(context, &target)
+        .into_events()
+}
+
+/// Serialized as a lowercase string; unrecognized states as their raw
+/// value.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
 pub enum ServiceState {
     Created,
     Starting,
@@ -206,26 +409,150 @@ pub enum ServiceState {
     Failed,
     Healthy,
     Unhealthy,
-    Unknown,
+    /// A container state nirion doesn't recognize, with the raw value
+    /// compose reported.
+    Unknown(String),
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ServiceStatus {
     pub id: String,
     pub service: String,
     pub container_name: String,
+    /// Replica number of the container within its service, starting at 1.
+    pub index: u32,
     pub image: String,
     pub state: ServiceState,
     pub health: Option<String>,
     pub exit_code: Option<i64>,
+    /// Docker's own wording, e.g. "About an hour ago"; only shown when
+    /// `created_at` is missing.
     pub running_for: Option<String>,
+    /// When the container was created, see [`ServiceStatus::uptime`].
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
     pub status: Option<String>,
     pub ports: Vec<Port>,
     pub networks: Vec<String>,
+    /// Runtime details from `docker inspect`; only filled in by the
+    /// detailed status queries.
+    #[serde(default)]
+    pub details: Option<ContainerDetails>,
+    /// The latest healthcheck probe, from `docker inspect`; filled in by
+    /// the detailed status queries and, for unhealthy containers, by
+    /// [`inspect_unhealthy_containers`].
+    #[serde(default)]
+    pub last_health_check: Option<HealthCheckResult>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ContainerDetails {
+    pub started_at: Option<String>,
+    pub restart_count: u32,
+    pub oom_killed: bool,
+}
+
+/// Probe output beyond this many characters is cut off; the full history
+/// is available through `nirion inspect container --health`.
+const HEALTH_CHECK_OUTPUT_LIMIT: usize = 200;
+
+/// The result of one healthcheck probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HealthCheckResult {
+    pub exit_code: i64,
+    /// The probe's output, trimmed and truncated.
+    pub output: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectedContainer {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "RestartCount", default)]
+    restart_count: u32,
+    #[serde(rename = "State")]
+    state: InspectedState,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectedState {
+    #[serde(rename = "StartedAt")]
+    started_at: Option<String>,
+    #[serde(rename = "OOMKilled", default)]
+    oom_killed: bool,
+    #[serde(rename = "Health")]
+    health: Option<InspectedHealth>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectedHealth {
+    #[serde(rename = "Log", default)]
+    log: Vec<InspectedHealthProbe>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectedHealthProbe {
+    #[serde(rename = "ExitCode")]
+    exit_code: i64,
+    #[serde(rename = "Output", default)]
+    output: String,
+}
+
+impl InspectedContainer {
+    fn last_health_check(&self) -> Option<HealthCheckResult> {
+        let probe = self.state.health.as_ref()?.log.last()?;
+        let output = probe.output.trim();
+        let output = match output
+            .char_indices()
+            .nth(HEALTH_CHECK_OUTPUT_LIMIT)
+        {
+            Some((end, _)) => format!("{}…", &output[..end]),
+            None => output.to_string(),
+        };
+
+        Some(HealthCheckResult {
+            exit_code: probe.exit_code,
+            output,
+        })
+    }
+}
+
+impl ServiceStatus {
+    /// A one-shot container that exited successfully. Services missing
+    /// from the project file fall back to compose's default restart
+    /// policy, which is "no".
+    pub fn is_completed(
+        &self,
+        project: &Project,
+    ) -> bool {
+        self.state == ServiceState::Succeeded
+            && project
+                .services
+                .get(&self.service)
+                .is_none_or(Service::is_one_shot)
+    }
+
+    /// How long ago the container was created, or `None` if docker
+    /// didn't say. A creation time ahead of `now`, from clock skew,
+    /// counts as just created.
+    pub fn uptime(
+        &self,
+        now: SystemTime,
+    ) -> Option<Duration> {
+        let created_at = SystemTime::from(self.created_at?);
+        Some(
+            now.duration_since(created_at)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct Port {
     pub external: Option<ExternalPort>,
     pub port: u16,
@@ -234,14 +561,54 @@ pub struct Port {
 
 #[allow(unused)]
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ExternalPort {
     pub ip: String,
     pub port: u16,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Containers of a project grouped by service name. Each service holds
+/// one entry per replica, ordered by replica index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub struct ProjectStatus {
-    pub services: BTreeMap<String, ServiceStatus>,
+    pub services: BTreeMap<String, Vec<ServiceStatus>>,
+    /// Containers under the project's compose name that were created
+    /// from another compose file, like a stack started by hand that
+    /// happens to share the name. They are kept out of `services`, so
+    /// they don't count toward the project's state.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub foreign: Vec<ServiceStatus>,
+}
+
+/// How one replica differs between two snapshots of a project, see
+/// [`ProjectStatus::diff`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "change")]
+pub enum ServiceChange {
+    Added {
+        service: String,
+        index: u32,
+        state: ServiceState,
+    },
+    Removed {
+        service: String,
+        index: u32,
+        state: ServiceState,
+    },
+    StateChanged {
+        service: String,
+        index: u32,
+        from: ServiceState,
+        to: ServiceState,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProgressCounts {
+    pub progressing: usize,
+    pub expected: usize,
+    pub completed: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -255,7 +622,7 @@ pub enum ProjectState {
     Unknown,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 struct ContainerInfo {
     #[serde(rename = "ID")]
     id: String,
@@ -273,23 +640,316 @@ struct ContainerInfo {
     exit_code: Option<i64>,
     #[serde(rename = "RunningFor")]
     running_for: Option<String>,
+    #[serde(rename = "CreatedAt")]
+    created_at: Option<String>,
+    #[serde(rename = "Status")]
+    status: Option<String>,
+    #[serde(rename = "Ports")]
+    ports: Option<String>,
+    #[serde(rename = "Networks")]
+    networks: Option<String>,
+    #[serde(rename = "Labels")]
+    labels: Option<String>,
+}
+
+impl ContainerInfo {
+    /// The replica number compose labels the container with, or 1 for a
+    /// container without the label. Names can't tell: `container_name`
+    /// may end in anything, and the default ones may be cut short.
+    fn replica_index(&self) -> u32 {
+        parse_labels(
+            self.labels
+                .as_deref()
+                .unwrap_or_default(),
+        )
+        .get(COMPOSE_CONTAINER_NUMBER_LABEL)
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(1)
+    }
+
+    /// A throwaway container rather than one of the project's own, see
+    /// [`show_helper_containers`].
+    fn is_helper(&self) -> bool {
+        let labels = parse_labels(
+            self.labels
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        labels
+            .get(NIRION_HELPER_LABEL)
+            .is_some_and(|value| value == "true")
+            || labels
+                .get(COMPOSE_ONEOFF_LABEL)
+                .is_some_and(|value| value == "True")
+    }
+}
+
+/// A line of `docker ps --format json`. Unlike compose's output it names
+/// neither the service nor the health; those come from the compose
+/// labels and [`status_text_health`].
+#[derive(Debug, Deserialize)]
+struct PsContainerInfo {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(rename = "Names")]
+    names: String,
+    #[serde(rename = "Image")]
+    image: String,
+    #[serde(rename = "State")]
+    state: String,
+    #[serde(rename = "RunningFor")]
+    running_for: Option<String>,
+    #[serde(rename = "CreatedAt")]
+    created_at: Option<String>,
     #[serde(rename = "Status")]
     status: Option<String>,
     #[serde(rename = "Ports")]
     ports: Option<String>,
     #[serde(rename = "Networks")]
     networks: Option<String>,
+    #[serde(rename = "Labels", default)]
+    labels: String,
+}
+
+impl PsContainerInfo {
+    /// The compose project of the container and the container as compose
+    /// would have listed it.
+    fn into_compose(self) -> Option<(String, ContainerInfo)> {
+        let labels = parse_labels(&self.labels);
+        let project = labels
+            .get(COMPOSE_PROJECT_LABEL)?
+            .clone();
+        let service = labels
+            .get(COMPOSE_SERVICE_LABEL)?
+            .clone();
+        let status = self
+            .status
+            .as_deref()
+            .unwrap_or_default();
+        let health = status_text_health(status);
+        let exit_code = status_text_exit_code(status);
+
+        let container = ContainerInfo {
+            id: self.id,
+            name: self
+                .names
+                .split(',')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            service,
+            image: self.image,
+            state: self.state,
+            health,
+            exit_code,
+            running_for: self.running_for,
+            created_at: self.created_at,
+            status: self.status,
+            ports: self.ports,
+            networks: self.networks,
+            labels: Some(self.labels),
+        };
+        Some((project, container))
+    }
+}
+
+// `docker ps` has no field for the health or the exit code; both are
+// only part of its human-readable `Status`, such as "Up 3 hours
+// (healthy)" or "Exited (3) 2 minutes ago". These two functions are the
+// only places that read that text, so a change in docker's wording is
+// fixed here.
+
+/// The health in a `docker ps` status: `None` without a healthcheck,
+/// and the raw value for a health docker reports that nirion doesn't
+/// know, which counts as plain running.
+fn status_text_health(status: &str) -> Option<String> {
+    if status.contains("(healthy)") {
+        return Some("healthy".to_string());
+    }
+    if status.contains("(unhealthy)") {
+        return Some("unhealthy".to_string());
+    }
+    let (_, rest) = status.split_once("(health: ")?;
+    let (health, _) = rest.split_once(')')?;
+    Some(health.to_string())
+}
+
+/// The exit code in the status of an exited container.
+fn status_text_exit_code(status: &str) -> Option<i64> {
+    status
+        .strip_prefix("Exited (")
+        .and_then(|rest| rest.split_once(')'))
+        .and_then(|(code, _)| code.parse().ok())
+}
+
+/// The compose containers in `docker ps --format json` output, by compose
+/// project name.
+fn docker_ps_containers(json: &str) -> BTreeMap<String, Vec<ContainerInfo>> {
+    let mut projects: BTreeMap<String, Vec<ContainerInfo>> = BTreeMap::new();
+    for container in json
+        .lines()
+        .filter_map(|line| serde_json::from_str::<PsContainerInfo>(line).ok())
+    {
+        if let Some((project, container)) = container.into_compose() {
+            projects
+                .entry(project)
+                .or_default()
+                .push(container);
+        }
+    }
+    projects
+}
+
+/// Splits the `key=value,...` list docker prints labels as. Values aren't
+/// escaped, so a segment without `=`, like the second file in compose's
+/// list of config files, continues the value before it.
+fn parse_labels(labels: &str) -> BTreeMap<String, String> {
+    let mut parsed = BTreeMap::new();
+    let mut last: Option<String> = None;
+    for segment in labels.split(',') {
+        match (segment.split_once('='), &last) {
+            (Some((key, value)), _) => {
+                parsed.insert(key.to_string(), value.to_string());
+                last = Some(key.to_string());
+            }
+            (None, Some(key)) => {
+                if let Some(value) = parsed.get_mut(key) {
+                    value.push(',');
+                    value.push_str(segment);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+    parsed
+}
+
+/// Tells the containers compose created from a project's compose file
+/// from others under the same compose project name, by the config files
+/// or, failing that, the working directory compose labels them with.
+/// Both ways of querying a project go through it, so they also agree on
+/// the services left out for their disabled profiles.
+struct ComposeOwner {
+    compose_file: PathBuf,
+    disabled_services: BTreeSet<String>,
+}
+
+impl ComposeOwner {
+    fn of(project: &Project) -> Self {
+        Self {
+            compose_file: canonical(&project.docker_compose),
+            disabled_services: disabled_services(project),
+        }
+    }
+
+    /// Whether the container belongs to a service whose profiles aren't
+    /// enabled, e.g. one left over from a run with `--profile`.
+    fn hides(
+        &self,
+        container: &ContainerInfo,
+    ) -> bool {
+        self.disabled_services
+            .contains(&container.service)
+    }
+
+    /// Containers without either label, e.g. from an older compose, are
+    /// given the benefit of the doubt.
+    fn owns(
+        &self,
+        container: &ContainerInfo,
+    ) -> bool {
+        let labels = parse_labels(
+            container
+                .labels
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        if let Some(files) = labels.get(COMPOSE_CONFIG_FILES_LABEL) {
+            return files
+                .split(',')
+                .any(|file| self.same_file(&canonical(Path::new(file))));
+        }
+        if let Some(dir) = labels.get(COMPOSE_WORKING_DIR_LABEL) {
+            return self
+                .compose_file
+                .parent()
+                .is_some_and(|parent| canonical(Path::new(dir)) == parent);
+        }
+        true
+    }
+
+    /// A rebuilt project file puts the compose file at a new nix store
+    /// path that differs only in the hash, while the containers keep the
+    /// path they were created from until they're recreated.
+    fn same_file(
+        &self,
+        file: &Path,
+    ) -> bool {
+        fn without_store_hash(path: &Path) -> Option<String> {
+            let rest = path
+                .strip_prefix("/nix/store")
+                .ok()?
+                .to_str()?;
+            let (_, name) = rest.split_once('-')?;
+            Some(name.to_string())
+        }
+
+        file == self.compose_file
+            || without_store_hash(file).is_some_and(|name| {
+                without_store_hash(&self.compose_file) == Some(name)
+            })
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
 impl ProjectStatus {
+    pub fn from_containers(
+        containers: impl IntoIterator<Item = ServiceStatus>
+    ) -> Self {
+        let mut services: BTreeMap<String, Vec<ServiceStatus>> =
+            BTreeMap::new();
+
+        for container in containers {
+            services
+                .entry(container.service.clone())
+                .or_default()
+                .push(container);
+        }
+
+        for replicas in services.values_mut() {
+            replicas.sort_by_key(|replica| replica.index);
+        }
+
+        ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        }
+    }
+
     pub fn from_json(json: &str) -> anyhow::Result<Self> {
-        let mut project = ProjectStatus {
-            services: BTreeMap::new(),
-        };
+        Self::parse_json(json, None)
+    }
 
+    /// Like [`Self::from_json`], moving the containers compose didn't
+    /// create from `project`'s compose file to [`Self::foreign`] and
+    /// leaving out those of services whose profiles aren't enabled.
+    pub fn from_project_json(
+        json: &str,
+        project: &Project,
+    ) -> anyhow::Result<Self> {
+        Self::parse_json(json, Some(&ComposeOwner::of(project)))
+    }
+
+    fn parse_json(
+        json: &str,
+        owner: Option<&ComposeOwner>,
+    ) -> anyhow::Result<Self> {
         let json = json.trim();
         if json.is_empty() || json == "[]" {
-            return Ok(project);
+            return Ok(Self::from_containers([]));
         }
 
         let containers: Vec<ContainerInfo> = if json.starts_with('[') {
@@ -301,9 +961,41 @@ impl ProjectStatus {
                 .collect()
         };
 
+        Self::from_container_infos(containers, owner)
+    }
+
+    /// Parses `docker ps --format json` into a status per compose
+    /// project, keyed by the compose project name. Containers compose
+    /// didn't create are left out.
+    pub fn from_docker_ps_json(
+        json: &str
+    ) -> anyhow::Result<BTreeMap<String, Self>> {
+        docker_ps_containers(json)
+            .into_iter()
+            .map(|(project, containers)| {
+                Ok((project, Self::from_container_infos(containers, None)?))
+            })
+            .collect()
+    }
+
+    fn from_container_infos(
+        containers: Vec<ContainerInfo>,
+        owner: Option<&ComposeOwner>,
+    ) -> anyhow::Result<Self> {
+        let mut services = Vec::with_capacity(containers.len());
+        let mut foreign = Vec::new();
+
+        let show_helpers = shows_helper_containers();
         for c in containers {
+            if !show_helpers && c.is_helper()
+                || owner.is_some_and(|owner| owner.hides(&c))
+            {
+                continue;
+            }
+            let owned = owner.is_none_or(|owner| owner.owns(&c));
             let ports_c = c.ports.clone();
             let state = ServiceState::from_container(&c);
+            let index = c.replica_index();
             let networks = c
                 .networks
                 .unwrap_or_default()
@@ -323,30 +1015,156 @@ impl ProjectStatus {
                 .map(|ports| ports.into_iter().flatten().collect())
                 .context(format!("Failed to parse ports: {:?}", ports_c))?;
 
-            project.services.insert(
-                c.service.clone(),
-                ServiceStatus {
-                    id: c.id,
-                    service: c.service,
-                    container_name: c.name,
-                    image: c.image,
-                    state,
-                    health: c.health,
-                    exit_code: c.exit_code,
-                    running_for: c.running_for,
-                    status: c.status,
-                    ports,
-                    networks,
-                },
-            );
+            let container = ServiceStatus {
+                id: c.id,
+                service: c.service,
+                index,
+                container_name: c.name,
+                image: c.image,
+                state,
+                health: c.health,
+                exit_code: c.exit_code,
+                running_for: c.running_for,
+                created_at: c
+                    .created_at
+                    .as_deref()
+                    .and_then(parse_created_at),
+                status: c.status,
+                ports,
+                networks,
+                details: None,
+                last_health_check: None,
+            };
+            if owned {
+                services.push(container);
+            } else {
+                foreign.push(container);
+            }
         }
 
-        Ok(project)
+        let mut status = Self::from_containers(services);
+        status.foreign = foreign;
+        Ok(status)
     }
 
-    pub fn progressing(&self) -> usize {
+    /// All containers of the project, replica by replica.
+    pub fn containers(&self) -> impl Iterator<Item = &ServiceStatus> {
+        self.services.values().flatten()
+    }
+
+    pub fn replicas(
+        &self,
+        service: &str,
+    ) -> &[ServiceStatus] {
         self.services
-            .values()
+            .get(service)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Services `project` declares that have no container at all, e.g.
+    /// behind a compose profile the project file doesn't know about or
+    /// named differently in the compose file. Services whose profiles
+    /// aren't enabled are expected to be absent and not reported.
+    pub fn missing_services<'a>(
+        &self,
+        project: &'a Project,
+    ) -> Vec<&'a str> {
+        project
+            .services
+            .iter()
+            .filter(|(name, service)| {
+                project.service_enabled(service)
+                    && self.replicas(name).is_empty()
+            })
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// The replicas that appeared, disappeared or changed state since
+    /// `old`, ordered by service and replica index. Replicas are matched
+    /// by index, so a recreated container in the same state is no change.
+    pub fn diff(
+        &self,
+        old: &ProjectStatus,
+    ) -> Vec<ServiceChange> {
+        fn replicas(
+            status: &ProjectStatus
+        ) -> BTreeMap<(&str, u32), &ServiceStatus> {
+            status
+                .containers()
+                .map(|container| {
+                    ((container.service.as_str(), container.index), container)
+                })
+                .collect()
+        }
+        let old = replicas(old);
+        let new = replicas(self);
+
+        let keys = old
+            .keys()
+            .chain(new.keys())
+            .collect::<BTreeSet<_>>();
+        keys.into_iter()
+            .filter_map(|key @ (service, index)| {
+                let (service, index) = (service.to_string(), *index);
+                match (old.get(key), new.get(key)) {
+                    (None, Some(new)) => Some(ServiceChange::Added {
+                        service,
+                        index,
+                        state: new.state.clone(),
+                    }),
+                    (Some(old), None) => Some(ServiceChange::Removed {
+                        service,
+                        index,
+                        state: old.state.clone(),
+                    }),
+                    (Some(old), Some(new)) if old.state != new.state => {
+                        Some(ServiceChange::StateChanged {
+                            service,
+                            index,
+                            from: old.state.clone(),
+                            to: new.state.clone(),
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Services with containers that `project` doesn't declare.
+    pub fn undeclared_services(
+        &self,
+        project: &Project,
+    ) -> Vec<&str> {
+        self.services
+            .iter()
+            .filter(|(service, replicas)| {
+                !replicas.is_empty() && !project.services.contains_key(*service)
+            })
+            .map(|(service, _)| service.as_str())
+            .collect()
+    }
+
+    /// Looks up one replica of a service, or the first one when no
+    /// index is given.
+    pub fn replica(
+        &self,
+        service: &str,
+        index: Option<u32>,
+    ) -> Option<&ServiceStatus> {
+        let replicas = self.replicas(service);
+        match index {
+            Some(index) => replicas
+                .iter()
+                .find(|replica| replica.index == index),
+            None => replicas.first(),
+        }
+    }
+
+    pub fn progressing(&self) -> usize {
+        self.containers()
             .filter(|s| {
                 use ServiceState::*;
                 matches!(
@@ -362,6 +1180,30 @@ impl ProjectStatus {
             .count()
     }
 
+    /// Counts for a project's progress ratio. Completed one-shots are
+    /// reported on their own so they don't hold the ratio below 100%,
+    /// and scaled services expect one container per running replica.
+    pub fn progress_counts(
+        &self,
+        project: &Project,
+    ) -> ProgressCounts {
+        let completed = self
+            .containers()
+            .filter(|container| container.is_completed(project))
+            .count();
+        let expected = project
+            .services
+            .keys()
+            .map(|name| self.replicas(name).len().max(1))
+            .sum::<usize>();
+
+        ProgressCounts {
+            progressing: self.progressing() - completed,
+            expected: expected.saturating_sub(completed),
+            completed,
+        }
+    }
+
     pub fn project_state(&self) -> ProjectState {
         if self.services.is_empty() {
             return ProjectState::Empty;
@@ -370,8 +1212,7 @@ impl ProjectStatus {
         use ServiceState::*;
 
         let states: Vec<&ServiceState> = self
-            .services
-            .values()
+            .containers()
             .map(|s| &s.state)
             .collect();
 
@@ -397,6 +1238,20 @@ impl ProjectStatus {
     }
 }
 
+/// Docker prints creation times like `2024-05-01 10:00:00 +0200 CEST`.
+/// The zone abbreviation is dropped, the offset already pins the time.
+fn parse_created_at(created_at: &str) -> Option<DateTime<Utc>> {
+    let without_zone = created_at
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<_>>()
+        .join(" ");
+    DateTime::parse_from_str(&without_zone, "%Y-%m-%d %H:%M:%S%.f %z")
+        .or_else(|_| DateTime::parse_from_rfc3339(created_at))
+        .ok()
+        .map(|created_at| created_at.with_timezone(&Utc))
+}
+
 fn parse_port_mapping(port_str: &str) -> anyhow::Result<Vec<Port>> {
     let (port_str, proto) = port_str
         .split_once('/')
@@ -462,7 +1317,88 @@ fn parse_port_range(port_str: &str) -> anyhow::Result<Vec<u16>> {
     }
 }
 
+/// Replicas picked with `--index`: one replica number or an inclusive
+/// range such as `1-2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicaRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl ReplicaRange {
+    /// The replica number, if the range names just one.
+    pub fn single(&self) -> Option<u32> {
+        (self.start == self.end).then_some(self.start)
+    }
+
+    pub fn contains(
+        &self,
+        index: u32,
+    ) -> bool {
+        (self.start..=self.end).contains(&index)
+    }
+}
+
+impl FromStr for ReplicaRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |index: &str| {
+            index
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|index| *index > 0)
+                .ok_or_else(|| {
+                    format!(
+                        "expected a replica number from 1 or a range like \
+                         1-2, got {value}"
+                    )
+                })
+        };
+        let (start, end) = match value.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => {
+                let index = parse(value)?;
+                (index, index)
+            }
+        };
+        if start > end {
+            return Err(format!("replica range {value} is descending"));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl Display for ReplicaRange {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self.single() {
+            Some(index) => write!(f, "{index}"),
+            None => write!(f, "{}-{}", self.start, self.end),
+        }
+    }
+}
+
 impl ServiceState {
+    /// The lowercase name used in JSON output.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ServiceState::Created => "created",
+            ServiceState::Starting => "starting",
+            ServiceState::Running => "running",
+            ServiceState::Paused => "paused",
+            ServiceState::Restarting => "restarting",
+            ServiceState::Succeeded => "succeeded",
+            ServiceState::Failed => "failed",
+            ServiceState::Healthy => "healthy",
+            ServiceState::Unhealthy => "unhealthy",
+            ServiceState::Unknown(raw) => raw,
+        }
+    }
+
     fn from_container(c: &ContainerInfo) -> Self {
         match c.state.as_str() {
             "created" => ServiceState::Created,
@@ -478,24 +1414,85 @@ impl ServiceState {
                 Some(_) => ServiceState::Failed,
                 None => ServiceState::Failed,
             },
-            _ => ServiceState::Unknown,
+            raw => {
+                record_unrecognized_state(raw);
+                ServiceState::Unknown(raw.to_string())
+            }
+        }
+    }
+}
+
+impl From<ServiceState> for String {
+    fn from(state: ServiceState) -> Self {
+        match state {
+            ServiceState::Unknown(raw) => raw,
+            state => state.as_str().to_string(),
+        }
+    }
+}
+
+/// Names are matched case-insensitively, so status caches written while
+/// the variant names were serialized still read back.
+impl From<String> for ServiceState {
+    fn from(value: String) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "created" => ServiceState::Created,
+            "starting" => ServiceState::Starting,
+            "running" => ServiceState::Running,
+            "paused" => ServiceState::Paused,
+            "restarting" => ServiceState::Restarting,
+            "succeeded" => ServiceState::Succeeded,
+            "failed" => ServiceState::Failed,
+            "healthy" => ServiceState::Healthy,
+            "unhealthy" => ServiceState::Unhealthy,
+            _ => ServiceState::Unknown(value),
         }
     }
 }
 
+/// Unrecognized states seen by [`ServiceState::from_container`];
+/// `true` once they have been handed out to be reported.
+static UNRECOGNIZED_STATES: Mutex<BTreeMap<String, bool>> =
+    Mutex::new(BTreeMap::new());
+
+fn record_unrecognized_state(raw: &str) {
+    if let Ok(mut states) = UNRECOGNIZED_STATES.lock() {
+        states
+            .entry(raw.to_string())
+            .or_insert(false);
+    }
+}
+
+/// Container states compose reported that nirion doesn't recognize and
+/// that haven't been returned by an earlier call, so each one is
+/// reported once per run.
+pub fn take_unrecognized_states() -> BTreeSet<String> {
+    let Ok(mut states) = UNRECOGNIZED_STATES.lock() else {
+        return BTreeSet::new();
+    };
+
+    states
+        .iter_mut()
+        .filter(|(_, reported)| !**reported)
+        .map(|(state, reported)| {
+            *reported = true;
+            state.clone()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        context::NirionContext, lock::LockedImages, projects::ProjectSelector,
+        context::NirionContext,
+        lock::LockedImages,
+        projects::{ProjectSelector, Projects},
     };
+    use futures::StreamExt;
     use nirion_oci_lib::client::NirionOciClient;
     use std::{
-        fs,
-        io::Write,
-        os::unix::fs::PermissionsExt,
-        path::{Path, PathBuf},
-        sync::Arc,
+        fs, io::Write, os::unix::fs::PermissionsExt, path::Path, sync::Arc,
         time::Duration,
     };
 
@@ -521,7 +1518,7 @@ mod tests {
         NirionContext {
             projects: projects(),
             locked_images: LockedImages::default(),
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -606,14 +1603,91 @@ exit {exit_code}
             .await
             .unwrap();
 
-        assert_eq!(status.services["web"].id, "container-123");
-        assert_eq!(status.services["web"].state, ServiceState::Healthy);
+        assert_eq!(status.services["web"][0].id, "container-123");
+        assert_eq!(status.services["web"][0].state, ServiceState::Healthy);
         assert_eq!(
             fs::read_to_string(args_file).unwrap(),
             "compose\n-f\ncompose.yml\n--project-name\nmyapp\nps\n-a\n--format\njson\n"
         );
     }
 
+    #[tokio::test]
+    async fn query_project_status_detailed_inspects_all_containers_at_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            format!(
+                r#"if [ "$1" = inspect ]; then
+  printf '%s\n' "$@" > '{}'
+  printf '%s\n' '[{{"Id":"aaa111","RestartCount":4,"State":{{"StartedAt":"2024-05-01T10:00:00.123Z","OOMKilled":true}}}}]'
+  echo 'Error: No such object: bbb' >&2
+  exit 1
+fi
+printf '%s\n' '{}'
+printf '%s\n' '{}'
+"#,
+                dir.path().join("inspect-args").display(),
+                compose_ps_service("web", "aaa"),
+                compose_ps_service("db", "bbb"),
+            ),
+        )
+        .unwrap();
+        let context = context(fake_docker_command(&script.to_string_lossy()));
+
+        let status = query_project_status_detailed(&context, "myapp")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("inspect-args")).unwrap(),
+            "inspect\nbbb\naaa\n"
+        );
+        assert_eq!(
+            status.services["web"][0].details,
+            Some(ContainerDetails {
+                started_at: Some("2024-05-01T10:00:00.123Z".into()),
+                restart_count: 4,
+                oom_killed: true,
+            })
+        );
+        assert_eq!(status.services["db"][0].details, None);
+    }
+
+    #[test]
+    fn last_health_check_is_the_latest_probe_truncated() {
+        let inspected: InspectedContainer =
+            serde_json::from_value(serde_json::json!({
+                "Id": "aaa",
+                "State": {
+                    "StartedAt": null,
+                    "Health": {
+                        "Status": "unhealthy",
+                        "Log": [
+                            {"ExitCode": 0, "Output": "ok"},
+                            {"ExitCode": 1, "Output": format!(
+                                "  {}\n", "x".repeat(300)
+                            )}
+                        ]
+                    }
+                }
+            }))
+            .unwrap();
+
+        let check = inspected.last_health_check().unwrap();
+        assert_eq!(check.exit_code, 1);
+        assert_eq!(
+            check.output,
+            format!("{}…", "x".repeat(HEALTH_CHECK_OUTPUT_LIMIT))
+        );
+
+        let inspected: InspectedContainer = serde_json::from_value(
+            serde_json::json!({"Id": "bbb", "State": {"StartedAt": null}}),
+        )
+        .unwrap();
+        assert_eq!(inspected.last_health_check(), None);
+    }
+
     #[tokio::test]
     async fn status_stream_emits_initial_status() {
         let dir = tempfile::tempdir().unwrap();
@@ -637,7 +1711,7 @@ exit {exit_code}
         let event = stream.next().await.unwrap().unwrap();
 
         assert_eq!(event.project, "myapp");
-        assert_eq!(event.status.services["web"].id, "container-abc");
+        assert_eq!(event.status.services["web"][0].id, "container-abc");
     }
 
     #[tokio::test]
@@ -672,17 +1746,105 @@ exit {exit_code}
             id: "abc".into(),
             service: "web".into(),
             container_name: "web-1".into(),
+            index: 1,
             image: "nginx:latest".into(),
             state,
             health: None,
             exit_code: None,
             running_for: None,
+            created_at: None,
             status: None,
             ports: vec![],
             networks: vec![],
+            details: None,
+            last_health_check: None,
         }
     }
 
+    #[test]
+    fn diff_reports_added_removed_and_changed_replicas() {
+        let old = ProjectStatus::from_containers([
+            service(ServiceState::Starting),
+            ServiceStatus {
+                service: "db".into(),
+                ..service(ServiceState::Running)
+            },
+            ServiceStatus {
+                service: "cache".into(),
+                ..service(ServiceState::Running)
+            },
+        ]);
+        let new = ProjectStatus::from_containers([
+            service(ServiceState::Healthy),
+            ServiceStatus {
+                index: 2,
+                ..service(ServiceState::Starting)
+            },
+            ServiceStatus {
+                id: "recreated".into(),
+                service: "db".into(),
+                ..service(ServiceState::Running)
+            },
+        ]);
+
+        assert_eq!(
+            new.diff(&old),
+            [
+                ServiceChange::Removed {
+                    service: "cache".into(),
+                    index: 1,
+                    state: ServiceState::Running,
+                },
+                ServiceChange::StateChanged {
+                    service: "web".into(),
+                    index: 1,
+                    from: ServiceState::Starting,
+                    to: ServiceState::Healthy,
+                },
+                ServiceChange::Added {
+                    service: "web".into(),
+                    index: 2,
+                    state: ServiceState::Starting,
+                },
+            ]
+        );
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn status_json_uses_lowercase_states_and_snake_case_fields() {
+        let status = ProjectStatus::from_containers([ServiceStatus {
+            ports: vec![Port {
+                external: Some(ExternalPort {
+                    ip: "0.0.0.0".into(),
+                    port: 8080,
+                }),
+                port: 80,
+                proto: "tcp".into(),
+            }],
+            ..service(ServiceState::Unknown("dead".into()))
+        }]);
+        let json = serde_json::to_value(&status).unwrap();
+
+        let web = &json["services"]["web"][0];
+        assert_eq!(web["state"], "dead");
+        assert_eq!(web["container_name"], "web-1");
+        assert_eq!(web["ports"][0]["external"]["port"], 8080);
+        assert_eq!(
+            serde_json::from_value::<ProjectStatus>(json).unwrap(),
+            status
+        );
+
+        assert_eq!(
+            serde_json::to_value(ServiceState::Succeeded).unwrap(),
+            "succeeded"
+        );
+        assert_eq!(
+            serde_json::from_str::<ServiceState>(r#""Running""#).unwrap(),
+            ServiceState::Running
+        );
+    }
+
     fn container_info(
         state: &str,
         health: Option<&str>,
@@ -697,12 +1859,190 @@ exit {exit_code}
             health: health.map(str::to_string),
             exit_code,
             running_for: None,
+            created_at: None,
             status: None,
             ports: None,
             networks: None,
+            labels: None,
+        }
+    }
+
+    #[test]
+    fn docker_ps_output_is_split_into_compose_projects() {
+        let json = [
+            r#"{"ID":"a1","Names":"app-web-2","Image":"nginx@sha256:abc","State":"running","Status":"Up 3 hours (unhealthy)","Ports":"0.0.0.0:8080->80/tcp","Networks":"app_default","Labels":"com.docker.compose.config-files=/a.yml,/b.yml,com.docker.compose.container-number=2,com.docker.compose.project=app,com.docker.compose.service=web"}"#,
+            r#"{"ID":"a2","Names":"app-migrate-1","Image":"app","State":"exited","Status":"Exited (3) 2 minutes ago","Labels":"com.docker.compose.project=app,com.docker.compose.service=migrate"}"#,
+            r#"{"ID":"b1","Names":"blog-db-1","Image":"postgres","State":"running","Status":"Up 3 hours (health: starting)","Labels":"com.docker.compose.service=db,com.docker.compose.project=blog"}"#,
+            r#"{"ID":"c1","Names":"standalone","Image":"redis","State":"running","Labels":""}"#,
+        ]
+        .join("\n");
+
+        let projects = ProjectStatus::from_docker_ps_json(&json).unwrap();
+
+        assert_eq!(projects.keys().collect::<Vec<_>>(), ["app", "blog"]);
+        let web = &projects["app"].services["web"][0];
+        assert_eq!(web.index, 2);
+        assert_eq!(web.state, ServiceState::Unhealthy);
+        assert_eq!(web.ports[0].port, 80);
+        assert_eq!(web.networks, ["app_default"]);
+        let migrate = &projects["app"].services["migrate"][0];
+        assert_eq!(migrate.exit_code, Some(3));
+        assert_eq!(migrate.state, ServiceState::Failed);
+        let db = &projects["blog"].services["db"][0];
+        assert_eq!(db.health.as_deref(), Some("starting"));
+        assert_eq!(db.state, ServiceState::Running);
+    }
+
+    #[test]
+    fn docker_ps_status_text_is_read_for_health_and_exit_code() {
+        for (status, health, exit_code) in [
+            ("Up 3 hours (healthy)", Some("healthy"), None),
+            ("Up 3 hours (unhealthy)", Some("unhealthy"), None),
+            ("Up 5 seconds (health: starting)", Some("starting"), None),
+            ("Up 5 seconds (health: degraded)", Some("degraded"), None),
+            ("Up 3 hours (Paused)", None, None),
+            ("Up 3 hours", None, None),
+            ("Exited (137) 2 minutes ago", None, Some(137)),
+            ("Created", None, None),
+            ("", None, None),
+        ] {
+            assert_eq!(
+                status_text_health(status).as_deref(),
+                health,
+                "{status}"
+            );
+            assert_eq!(status_text_exit_code(status), exit_code, "{status}");
         }
     }
 
+    #[test]
+    fn containers_from_another_compose_file_are_foreign() {
+        let dir = tempfile::tempdir().unwrap();
+        let compose = dir.path().join("compose.yml");
+        fs::write(&compose, "services: {}\n").unwrap();
+        let project = serde_json::from_value::<Project>(serde_json::json!({
+            "name": "myapp",
+            "dockerCompose": compose,
+            "services": {}
+        }))
+        .unwrap();
+        let line = |service: &str, labels: &str| {
+            serde_json::json!({
+                "ID": service,
+                "Name": format!("myapp-{service}-1"),
+                "Service": service,
+                "Image": "nginx",
+                "State": "running",
+                "Labels": labels,
+            })
+            .to_string()
+        };
+        let json = [
+            line(
+                "web",
+                &format!(
+                    "com.docker.compose.project.config_files={},\
+                     com.docker.compose.project=myapp",
+                    compose.display()
+                ),
+            ),
+            line(
+                "stray",
+                "com.docker.compose.project.config_files=/home/me/old/compose.yml,\
+                 /home/me/old/override.yml",
+            ),
+            line(
+                "db",
+                &format!(
+                    "com.docker.compose.project.working_dir={}",
+                    dir.path().display()
+                ),
+            ),
+            line("legacy", ""),
+        ]
+        .join("\n");
+
+        let status = ProjectStatus::from_project_json(&json, &project).unwrap();
+
+        assert_eq!(
+            status
+                .services
+                .keys()
+                .collect::<Vec<_>>(),
+            ["db", "legacy", "web"]
+        );
+        assert_eq!(status.foreign.len(), 1);
+        assert_eq!(status.foreign[0].service, "stray");
+        assert_eq!(status.project_state(), ProjectState::Running);
+    }
+
+    #[test]
+    fn a_rebuilt_nix_store_compose_file_still_owns_its_containers() {
+        let owner = ComposeOwner {
+            compose_file: "/nix/store/bbbb-docker-compose-myapp.yml".into(),
+            disabled_services: BTreeSet::new(),
+        };
+
+        assert!(
+            owner.same_file(Path::new(
+                "/nix/store/aaaa-docker-compose-myapp.yml"
+            ))
+        );
+        assert!(
+            !owner.same_file(Path::new(
+                "/nix/store/aaaa-docker-compose-other.yml"
+            ))
+        );
+        assert!(!owner.same_file(Path::new("/srv/docker-compose-myapp.yml")));
+    }
+
+    #[test]
+    fn missing_and_undeclared_services_compare_project_and_containers() {
+        let project = serde_json::from_value::<Project>(serde_json::json!({
+            "name": "myapp",
+            "dockerCompose": "compose.yml",
+            "services": {
+                "web": {"image": "nginx", "restart": null},
+                "worker": {"image": "alpine", "restart": null}
+            }
+        }))
+        .unwrap();
+        let status = ProjectStatus::from_containers([
+            service(ServiceState::Running),
+            ServiceStatus {
+                service: "webapp".into(),
+                ..service(ServiceState::Running)
+            },
+        ]);
+
+        assert_eq!(status.missing_services(&project), ["worker"]);
+        assert_eq!(status.undeclared_services(&project), ["webapp"]);
+    }
+
+    #[test]
+    fn missing_services_skip_services_behind_disabled_profiles() {
+        let mut project = serde_json::from_value::<Project>(serde_json::json!({
+            "name": "myapp",
+            "dockerCompose": "compose.yml",
+            "services": {
+                "web": {"image": "nginx", "restart": null},
+                "debug": {"image": "busybox", "restart": null, "profiles": ["debug"]}
+            }
+        }))
+        .unwrap();
+        let status =
+            ProjectStatus::from_containers([service(ServiceState::Running)]);
+
+        assert!(
+            status
+                .missing_services(&project)
+                .is_empty()
+        );
+
+        project.profiles = vec!["debug".into()];
+        assert_eq!(status.missing_services(&project), ["debug"]);
+    }
+
     #[test]
     fn parse_port_mapping_parses_unmapped_port() {
         let ports = parse_port_mapping("80/tcp").unwrap();
@@ -795,7 +2135,7 @@ exit {exit_code}
         let json = r#"{"ID":"abc","Name":"web-1","Service":"web","Image":"nginx","State":"running","Health":"healthy","ExitCode":null,"RunningFor":"2 minutes","Status":null,"Ports":"0.0.0.0:8080->80/tcp","Networks":"bridge"}"#;
         let status = ProjectStatus::from_json(json).unwrap();
         assert_eq!(status.services.len(), 1);
-        let svc = &status.services["web"];
+        let svc = &status.services["web"][0];
         assert_eq!(svc.state, ServiceState::Healthy);
         assert_eq!(svc.ports.len(), 1);
         assert_eq!(svc.networks, vec!["bridge"]);
@@ -806,7 +2146,7 @@ exit {exit_code}
         let json = r#"[{"ID":"abc","Name":"web-1","Service":"web","Image":"nginx","State":"running","Health":null,"ExitCode":null,"RunningFor":"2 minutes","Status":null,"Ports":"","Networks":""}]"#;
         let status = ProjectStatus::from_json(json).unwrap();
         assert_eq!(status.services.len(), 1);
-        assert_eq!(status.services["web"].state, ServiceState::Running);
+        assert_eq!(status.services["web"][0].state, ServiceState::Running);
     }
 
     #[test]
@@ -833,8 +2173,122 @@ exit {exit_code}
         let json = "{\"ID\":\"1\",\"Name\":\"a\",\"Service\":\"web\",\"Image\":\"nginx\",\"State\":\"running\",\"Health\":\"healthy\",\"ExitCode\":null,\"RunningFor\":null,\"Status\":null,\"Ports\":\"\",\"Networks\":\"\"}\n{\"ID\":\"2\",\"Name\":\"b\",\"Service\":\"db\",\"Image\":\"postgres\",\"State\":\"exited\",\"Health\":null,\"ExitCode\":0,\"RunningFor\":null,\"Status\":null,\"Ports\":\"\",\"Networks\":\"\"}";
         let status = ProjectStatus::from_json(json).unwrap();
         assert_eq!(status.services.len(), 2);
-        assert_eq!(status.services["web"].state, ServiceState::Healthy);
-        assert_eq!(status.services["db"].state, ServiceState::Succeeded);
+        assert_eq!(status.services["web"][0].state, ServiceState::Healthy);
+        assert_eq!(status.services["db"][0].state, ServiceState::Succeeded);
+    }
+
+    #[test]
+    fn project_status_from_json_leaves_out_helper_containers() {
+        let json = r#"[
+{"ID":"1","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running","Labels":"com.docker.compose.oneoff=False"},
+{"ID":"2","Name":"myapp-web-run-8f2c","Service":"web","Image":"nginx","State":"running","Labels":"com.docker.compose.oneoff=True"},
+{"ID":"3","Name":"myapp-backup-1","Service":"backup","Image":"restic","State":"exited","ExitCode":0,"Labels":"io.nirion.helper=true"}
+]"#;
+        let status = ProjectStatus::from_json(json).unwrap();
+
+        assert_eq!(
+            status
+                .containers()
+                .map(|container| container.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1"]
+        );
+    }
+
+    #[test]
+    fn project_status_from_json_keeps_every_replica_of_a_service() {
+        let json = r#"[
+{"ID":"3","Name":"myapp-web-3","Service":"web","Image":"nginx","State":"running","Health":"healthy","Labels":"com.docker.compose.container-number=3"},
+{"ID":"1","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running","Health":"healthy","Labels":"com.docker.compose.container-number=1"},
+{"ID":"2","Name":"myapp-web-2","Service":"web","Image":"nginx","State":"running","Health":"starting","Labels":"com.docker.compose.container-number=2"},
+{"ID":"4","Name":"myapp-db-1","Service":"db","Image":"postgres","State":"running"}
+]"#;
+        let status = ProjectStatus::from_json(json).unwrap();
+
+        assert_eq!(status.services.len(), 2);
+        assert_eq!(
+            status
+                .replicas("web")
+                .iter()
+                .map(|replica| (replica.index, replica.id.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "1"), (2, "2"), (3, "3")]
+        );
+        assert_eq!(status.containers().count(), 4);
+        assert_eq!(status.progressing(), 4);
+        assert_eq!(
+            status
+                .replica("web", Some(2))
+                .unwrap()
+                .id,
+            "2"
+        );
+        assert_eq!(status.replica("web", None).unwrap().id, "1");
+        assert!(status.replica("web", Some(4)).is_none());
+        assert!(status.replicas("cache").is_empty());
+    }
+
+    #[test]
+    fn project_state_considers_every_replica() {
+        let json = "{\"ID\":\"1\",\"Name\":\"myapp-web-1\",\"Service\":\"web\",\"Image\":\"nginx\",\"State\":\"running\",\"Health\":\"healthy\"}\n{\"ID\":\"2\",\"Name\":\"myapp-web-2\",\"Service\":\"web\",\"Image\":\"nginx\",\"State\":\"exited\",\"ExitCode\":1}";
+        let status = ProjectStatus::from_json(json).unwrap();
+
+        assert_eq!(status.project_state(), ProjectState::Degraded);
+    }
+
+    #[test]
+    fn replica_index_comes_from_the_container_number_label() {
+        let index = |name: &str, labels: Option<&str>| {
+            let labels = labels
+                .map(|labels| format!(r#","Labels":"{labels}""#))
+                .unwrap_or_default();
+            let json = format!(
+                r#"{{"ID":"1","Name":"{name}","Service":"web","Image":"nginx","State":"running"{labels}}}"#
+            );
+            ProjectStatus::from_json(&json)
+                .unwrap()
+                .services["web"][0]
+                .index
+        };
+
+        assert_eq!(
+            index(
+                "myapp-web-12",
+                Some("com.docker.compose.container-number=12")
+            ),
+            12
+        );
+        assert_eq!(
+            index("web-7", Some("com.docker.compose.container-number=2")),
+            2
+        );
+        assert_eq!(index("myapp-web-3", None), 1);
+        assert_eq!(
+            index("myapp-web-3", Some("com.docker.compose.oneoff=False")),
+            1
+        );
+    }
+
+    #[test]
+    fn uptime_is_computed_from_the_creation_time() {
+        let status = ProjectStatus::from_json(
+            r#"{"ID":"1","Name":"app-web-1","Service":"web","Image":"nginx","State":"running","RunningFor":"Vor 2 Stunden","CreatedAt":"2024-05-01 10:00:00 +0200 CEST","Status":"Up"}"#,
+        )
+        .unwrap();
+        let web = &status.services["web"][0];
+        let created_at = web.created_at.unwrap();
+        assert_eq!(created_at.to_rfc3339(), "2024-05-01T08:00:00+00:00");
+
+        let now = SystemTime::from(created_at) + Duration::from_secs(7980);
+        assert_eq!(web.uptime(now), Some(Duration::from_secs(7980)));
+        let skewed = SystemTime::from(created_at) - Duration::from_secs(5);
+        assert_eq!(web.uptime(skewed), Some(Duration::ZERO));
+
+        assert_eq!(
+            parse_created_at("2024-05-01T08:00:00.123Z"),
+            Some(created_at + chrono::Duration::milliseconds(123))
+        );
+        assert_eq!(parse_created_at("2 hours ago"), None);
     }
 
     #[test]
@@ -916,18 +2370,23 @@ exit {exit_code}
     }
 
     #[test]
-    fn service_state_from_container_maps_unknown_state_to_unknown() {
+    fn service_state_from_container_keeps_unrecognized_states() {
         let container = container_info("garbage", None, None);
         assert_eq!(
             ServiceState::from_container(&container),
-            ServiceState::Unknown
+            ServiceState::Unknown("garbage".into())
         );
+        ServiceState::from_container(&container);
+
+        assert!(take_unrecognized_states().contains("garbage"));
+        assert!(!take_unrecognized_states().contains("garbage"));
     }
 
     #[test]
     fn project_state_empty() {
         let status = ProjectStatus {
             services: BTreeMap::new(),
+            foreign: Vec::new(),
         };
         assert_eq!(status.project_state(), ProjectState::Empty);
     }
@@ -937,19 +2396,22 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Healthy,
                 ..service(ServiceState::Healthy)
-            },
+            }],
         );
         services.insert(
             "b".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Succeeded,
                 ..service(ServiceState::Succeeded)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Healthy);
     }
 
@@ -958,19 +2420,22 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Healthy,
                 ..service(ServiceState::Healthy)
-            },
+            }],
         );
         services.insert(
             "b".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Failed,
                 ..service(ServiceState::Failed)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Degraded);
     }
 
@@ -979,12 +2444,15 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Starting,
                 ..service(ServiceState::Starting)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Starting);
     }
 
@@ -993,12 +2461,15 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Running,
                 ..service(ServiceState::Running)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Running);
     }
 
@@ -1007,12 +2478,15 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Paused,
                 ..service(ServiceState::Paused)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Paused);
     }
 
@@ -1021,12 +2495,15 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Created,
                 ..service(ServiceState::Created)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.project_state(), ProjectState::Unknown);
     }
 
@@ -1035,26 +2512,29 @@ exit {exit_code}
         let mut services = BTreeMap::new();
         services.insert(
             "a".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Running,
                 ..service(ServiceState::Running)
-            },
+            }],
         );
         services.insert(
             "b".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Healthy,
                 ..service(ServiceState::Healthy)
-            },
+            }],
         );
         services.insert(
             "c".into(),
-            ServiceStatus {
+            vec![ServiceStatus {
                 state: ServiceState::Failed,
                 ..service(ServiceState::Failed)
-            },
+            }],
         );
-        let status = ProjectStatus { services };
+        let status = ProjectStatus {
+            services,
+            foreign: Vec::new(),
+        };
         assert_eq!(status.progressing(), 2);
     }
 
@@ -1062,7 +2542,84 @@ exit {exit_code}
     fn progressing_zero_when_empty() {
         let status = ProjectStatus {
             services: BTreeMap::new(),
+            foreign: Vec::new(),
         };
         assert_eq!(status.progressing(), 0);
     }
+
+    fn one_shot_project() -> Project {
+        serde_json::from_str(
+            r#"{
+  "name": "myapp",
+  "dockerCompose": "compose.yml",
+  "services": {
+    "web": {"image": "nginx", "restart": "always"},
+    "migrate": {"image": "migrate", "restart": "no"}
+  }
+}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn is_completed_only_applies_to_successful_one_shots() {
+        let project = one_shot_project();
+        let status = |name: &str, state| ServiceStatus {
+            service: name.into(),
+            ..service(state)
+        };
+
+        assert!(
+            status("migrate", ServiceState::Succeeded).is_completed(&project)
+        );
+        assert!(
+            !status("migrate", ServiceState::Failed).is_completed(&project)
+        );
+        assert!(!status("web", ServiceState::Succeeded).is_completed(&project));
+        assert!(
+            status("adhoc", ServiceState::Succeeded).is_completed(&project)
+        );
+    }
+
+    #[test]
+    fn progress_counts_exclude_completed_one_shots() {
+        let project = one_shot_project();
+        let status = ProjectStatus::from_containers([
+            ServiceStatus {
+                service: "migrate".into(),
+                ..service(ServiceState::Succeeded)
+            },
+            service(ServiceState::Running),
+            ServiceStatus {
+                index: 2,
+                ..service(ServiceState::Starting)
+            },
+        ]);
+
+        assert_eq!(
+            status.progress_counts(&project),
+            ProgressCounts {
+                progressing: 2,
+                expected: 2,
+                completed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn replica_ranges_parse_single_indices_and_ranges() {
+        let single = "2".parse::<ReplicaRange>().unwrap();
+        assert_eq!(single.single(), Some(2));
+        assert_eq!(single.to_string(), "2");
+
+        let range = "1-3".parse::<ReplicaRange>().unwrap();
+        assert_eq!(range, ReplicaRange { start: 1, end: 3 });
+        assert_eq!(range.single(), None);
+        assert!(range.contains(3) && !range.contains(4));
+        assert_eq!(range.to_string(), "1-3");
+
+        for invalid in ["0", "3-1", "a-2", "1-", ""] {
+            assert!(invalid.parse::<ReplicaRange>().is_err(), "{invalid}");
+        }
+    }
 }
@@ -0,0 +1,216 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use nirion_oci_lib::version::compare_versions;
+use serde::Serialize;
+
+use crate::{
+    history::{HistoryEntry, HistoryFilter, history_file, read_history},
+    lock::{DiffEntry, VersionedImage},
+    lock_store::LockStore,
+    projects::TargetSelector,
+    state::state_dir,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DowngradeReason {
+    /// The new version is older than the locked one.
+    OlderVersion,
+    /// The service was deployed with the new digest before.
+    PreviousDigest,
+}
+
+impl DowngradeReason {
+    pub fn describe(self) -> &'static str {
+        match self {
+            DowngradeReason::OlderVersion => "older than the locked version",
+            DowngradeReason::PreviousDigest => "digest was deployed before",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SuspectedDowngrade {
+    pub service: String,
+    pub old: VersionedImage,
+    pub new: VersionedImage,
+    pub reason: DowngradeReason,
+}
+
+impl SuspectedDowngrade {
+    /// `diff` if it is an update to an older version, or to a digest in
+    /// `previous_digests` for its service. A changed image reference is a
+    /// deliberate re-pin, so only updates of the same reference count.
+    pub fn detect(
+        diff: &DiffEntry,
+        previous_digests: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Option<Self> {
+        let DiffEntry::Updated { service, old, new } = diff else {
+            return None;
+        };
+        if old.image != new.image || old.digest == new.digest {
+            return None;
+        }
+
+        let older = old
+            .version
+            .as_deref()
+            .zip(new.version.as_deref())
+            .and_then(|(old, new)| compare_versions(new, old))
+            == Some(Ordering::Less);
+        let reason = if older {
+            DowngradeReason::OlderVersion
+        } else if previous_digests
+            .get(service)
+            .is_some_and(|digests| digests.contains(&new.digest))
+        {
+            DowngradeReason::PreviousDigest
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            service: service.clone(),
+            old: old.clone(),
+            new: new.clone(),
+            reason,
+        })
+    }
+}
+
+/// The digests each `project.service` was deployed with, by `entries`.
+pub fn previous_digests(
+    entries: &[HistoryEntry]
+) -> BTreeMap<String, BTreeSet<String>> {
+    let mut digests = BTreeMap::<_, BTreeSet<_>>::new();
+    for entry in entries {
+        for (service, digest) in &entry.services {
+            if let Some(digest) = digest {
+                digests
+                    .entry(service.clone())
+                    .or_default()
+                    .insert(digest.clone());
+            }
+        }
+    }
+    digests
+}
+
+/// [`previous_digests`] from the deployment history in the state
+/// directory. Without a readable history only the version check is left,
+/// so errors are treated as there being none.
+pub fn read_previous_digests() -> BTreeMap<String, BTreeSet<String>> {
+    let filter = HistoryFilter {
+        target: TargetSelector::All,
+        since: None,
+        until: None,
+    };
+    state_dir()
+        .and_then(|dir| read_history(&history_file(&dir), &filter))
+        .map(|entries| previous_digests(&entries))
+        .unwrap_or_default()
+}
+
+/// Writes the new images of `downgrades` held back by an update, on top
+/// of whatever the lock file holds now.
+pub fn accept_downgrades(
+    lock_store: &LockStore,
+    downgrades: &[SuspectedDowngrade],
+) -> anyhow::Result<()> {
+    let previous = lock_store.load()?;
+    let mut locked_images = previous.clone();
+    for downgrade in downgrades {
+        locked_images.insert(downgrade.service.clone(), downgrade.new.clone());
+    }
+    lock_store.write(&previous, &locked_images)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(
+        version: Option<&str>,
+        digest: &str,
+    ) -> VersionedImage {
+        VersionedImage {
+            image: "nginx:1".to_string(),
+            version: version.map(str::to_string),
+            digest: digest.to_string(),
+            size: None,
+            source: None,
+        }
+    }
+
+    fn updated(
+        old: VersionedImage,
+        new: VersionedImage,
+    ) -> DiffEntry {
+        DiffEntry::Updated {
+            service: "myapp.web".to_string(),
+            old,
+            new,
+        }
+    }
+
+    fn reason(
+        diff: &DiffEntry,
+        previous: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Option<DowngradeReason> {
+        SuspectedDowngrade::detect(diff, previous).map(|d| d.reason)
+    }
+
+    #[test]
+    fn detect_flags_older_versions_and_previously_deployed_digests() {
+        let previous = BTreeMap::from([(
+            "myapp.web".to_string(),
+            BTreeSet::from(["sha256:old".to_string()]),
+        )]);
+
+        let older = updated(
+            image(Some("1.25.3"), "sha256:b"),
+            image(Some("1.25.2"), "sha256:a"),
+        );
+        assert_eq!(
+            reason(&older, &previous),
+            Some(DowngradeReason::OlderVersion)
+        );
+
+        let redeployed =
+            updated(image(None, "sha256:current"), image(None, "sha256:old"));
+        assert_eq!(
+            reason(&redeployed, &previous),
+            Some(DowngradeReason::PreviousDigest)
+        );
+
+        let newer = updated(
+            image(Some("1.25.2"), "sha256:a"),
+            image(Some("1.25.3"), "sha256:b"),
+        );
+        assert_eq!(reason(&newer, &previous), None);
+        assert_eq!(reason(&redeployed, &BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn detect_ignores_changed_image_references() {
+        let previous = BTreeMap::from([(
+            "myapp.web".to_string(),
+            BTreeSet::from(["sha256:old".to_string()]),
+        )]);
+        let repinned = updated(
+            VersionedImage {
+                image: "nginx:1.25".to_string(),
+                ..image(Some("1.25.3"), "sha256:current")
+            },
+            VersionedImage {
+                image: "nginx:1.24".to_string(),
+                ..image(Some("1.24.0"), "sha256:old")
+            },
+        );
+        assert_eq!(reason(&repinned, &previous), None);
+    }
+}
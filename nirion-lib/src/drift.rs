@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{docker::ProjectStatus, lock::LockedImages};
+
+/// How the containers of a locked service compare to its lock entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum ImageDrift {
+    /// Every container runs the locked digest.
+    Match,
+    /// A container runs another digest.
+    Differs { running: String },
+    /// The containers run an image reference without a digest, so there
+    /// is nothing to compare.
+    Unpinned { running: String },
+    /// The service has no container.
+    NotRunning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceDrift {
+    /// `project.service`, like lock file keys.
+    pub service: String,
+    pub locked: String,
+    #[serde(flatten)]
+    pub drift: ImageDrift,
+}
+
+/// Whether recreating a locked service with its compose file can bring
+/// it onto the locked digest, judged by the image the compose file names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockDeployment {
+    /// The compose file pins the locked digest, and compose recreates
+    /// containers created from anything else by itself.
+    Pinned,
+    /// The compose file pins another digest, which every recreate runs
+    /// until the compose file is built again from the lock.
+    StaleComposeFile { pinned: String },
+    /// The compose file names a tag, which runs whatever image the tag
+    /// points at on this host.
+    Tag,
+}
+
+impl LockDeployment {
+    pub fn of(
+        compose_image: &str,
+        locked_digest: &str,
+    ) -> Self {
+        match compose_image.split_once('@') {
+            Some((_, digest)) if digest == locked_digest => Self::Pinned,
+            Some((_, digest)) => Self::StaleComposeFile {
+                pinned: digest.to_string(),
+            },
+            None => Self::Tag,
+        }
+    }
+}
+
+/// Compares the lock entries of the projects in `statuses` with the
+/// images their containers run. Entries of projects missing from
+/// `statuses` are left out.
+pub fn image_drift(
+    locked: &LockedImages,
+    statuses: &BTreeMap<String, ProjectStatus>,
+) -> Vec<ServiceDrift> {
+    locked
+        .iter()
+        .filter_map(|(key, image)| {
+            let (project, service) = key.split_once('.')?;
+            let status = statuses.get(project)?;
+            let replicas = status
+                .services
+                .get(service)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            let mismatch = replicas
+                .iter()
+                .map(|replica| {
+                    let digest = replica
+                        .image
+                        .split_once('@')
+                        .map(|(_, digest)| digest);
+                    (replica.image.as_str(), digest)
+                })
+                .find(|(_, digest)| *digest != Some(image.digest.as_str()));
+            let drift = match mismatch {
+                _ if replicas.is_empty() => ImageDrift::NotRunning,
+                None => ImageDrift::Match,
+                Some((_, Some(digest))) => ImageDrift::Differs {
+                    running: digest.to_string(),
+                },
+                Some((running, None)) => ImageDrift::Unpinned {
+                    running: running.to_string(),
+                },
+            };
+
+            Some(ServiceDrift {
+                service: key.to_string(),
+                locked: image.digest.clone(),
+                drift,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        docker::{ServiceState, ServiceStatus},
+        lock::VersionedImage,
+    };
+
+    fn container(
+        service: &str,
+        image: &str,
+    ) -> ServiceStatus {
+        ServiceStatus {
+            id: format!("{service}-id"),
+            service: service.to_string(),
+            container_name: format!("app-{service}-1"),
+            index: 1,
+            image: image.to_string(),
+            state: ServiceState::Running,
+            health: None,
+            exit_code: None,
+            running_for: None,
+            created_at: None,
+            status: None,
+            ports: Vec::new(),
+            networks: Vec::new(),
+            details: None,
+            last_health_check: None,
+        }
+    }
+
+    #[test]
+    fn running_images_are_compared_with_the_locked_digest() {
+        let mut locked = LockedImages::default();
+        for (service, digest) in [
+            ("app.web", "sha256:aaa"),
+            ("app.db", "sha256:bbb"),
+            ("app.cache", "sha256:ccc"),
+            ("app.worker", "sha256:ddd"),
+            ("other.api", "sha256:eee"),
+        ] {
+            locked.insert(
+                service.into(),
+                VersionedImage {
+                    image: "image".into(),
+                    version: None,
+                    digest: digest.into(),
+                    size: None,
+                    source: None,
+                },
+            );
+        }
+        let statuses = BTreeMap::from([(
+            "app".to_string(),
+            ProjectStatus::from_containers([
+                container("web", "nginx@sha256:aaa"),
+                container("db", "postgres@sha256:old"),
+                container("cache", "redis:7"),
+            ]),
+        )]);
+
+        let drift = image_drift(&locked, &statuses)
+            .into_iter()
+            .map(|service| (service.service, service.drift))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            drift,
+            [
+                (
+                    "app.cache".to_string(),
+                    ImageDrift::Unpinned {
+                        running: "redis:7".into()
+                    }
+                ),
+                (
+                    "app.db".to_string(),
+                    ImageDrift::Differs {
+                        running: "sha256:old".into()
+                    }
+                ),
+                ("app.web".to_string(), ImageDrift::Match),
+                ("app.worker".to_string(), ImageDrift::NotRunning),
+            ]
+        );
+    }
+
+    #[test]
+    fn lock_deployment_compares_the_compose_pin_with_the_lock() {
+        assert_eq!(
+            LockDeployment::of("nginx:1.27@sha256:aaa", "sha256:aaa"),
+            LockDeployment::Pinned
+        );
+        assert_eq!(
+            LockDeployment::of("nginx:1.27@sha256:old", "sha256:aaa"),
+            LockDeployment::StaleComposeFile {
+                pinned: "sha256:old".to_string()
+            }
+        );
+        assert_eq!(
+            LockDeployment::of("nginx:1.27", "sha256:aaa"),
+            LockDeployment::Tag
+        );
+    }
+}
@@ -0,0 +1,773 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_yaml_ng::Value;
+
+use crate::{
+    compose_file::load_compose, context::NirionContext,
+    docker::query_project_status, paths::resolve_relative_to,
+    projects::ServiceSelector,
+};
+
+const SECRET_MARKERS: &[&str] = &[
+    "SECRET",
+    "PASSWORD",
+    "PASSWD",
+    "TOKEN",
+    "CREDENTIAL",
+    "PRIVATE",
+    "AUTH",
+];
+
+/// Markers that only count as a whole `_`-separated word of the key, as
+/// they are part of too many harmless ones, like `KEYBOARD` or `MONKEY`.
+const SECRET_WORDS: &[&str] = &["KEY", "KEYS"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvFile {
+    pub path: PathBuf,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvVar {
+    pub key: String,
+    /// The value as compose sets it, or as written in the compose file
+    /// if it can't be resolved.
+    pub value: String,
+    pub source: String,
+    /// Whether `value` is what compose sets. It isn't for variables
+    /// without a value or interpolating ones that nirion's environment
+    /// doesn't have, which compose may have been run with.
+    #[serde(skip)]
+    pub resolved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum EnvDrift {
+    Match,
+    Differs { container_value: String },
+    Missing,
+    NotRunning,
+    Unresolved,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvComparison {
+    #[serde(flatten)]
+    pub var: EnvVar,
+    #[serde(flatten)]
+    pub drift: EnvDrift,
+}
+
+/// What the values of secret variables are shown as.
+pub const REDACTED: &str = "********";
+
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_uppercase();
+    SECRET_MARKERS
+        .iter()
+        .any(|marker| key.contains(marker))
+        || key
+            .split('_')
+            .any(|word| SECRET_WORDS.contains(&word))
+}
+
+/// Replaces every value stored under a secret-looking key in `value` with
+/// [`REDACTED`], e.g. before a compose file is shared. `KEY=value` list
+/// entries, as compose accepts for `environment`, are redacted too.
+pub fn redact_secrets(value: &mut Value) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let secret = key.as_str().is_some_and(is_secret_key);
+                match value {
+                    Value::Bool(_) | Value::Number(_) | Value::String(_)
+                        if secret =>
+                    {
+                        *value = Value::String(REDACTED.to_string());
+                    }
+                    value => redact_secrets(value),
+                }
+            }
+        }
+        Value::Sequence(entries) => {
+            for entry in entries {
+                match entry {
+                    Value::String(assignment) => redact_assignment(assignment),
+                    entry => redact_secrets(entry),
+                }
+            }
+        }
+        Value::Tagged(tagged) => redact_secrets(&mut tagged.value),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {}
+    }
+}
+
+fn redact_assignment(assignment: &mut String) {
+    let Some((key, _)) = assignment.split_once('=') else {
+        return;
+    };
+    let is_variable = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_variable && is_secret_key(key) {
+        *assignment = format!("{key}={REDACTED}");
+    }
+}
+
+/// Lists the env files of a service in the order compose reads them,
+/// resolved relative to the directory of the compose file.
+pub fn service_env_files(
+    compose_file: &Path,
+    service: &Value,
+) -> Vec<EnvFile> {
+    let entries = match service.get("env_file") {
+        Some(Value::String(path)) => vec![(path.as_str(), true)],
+        Some(Value::Sequence(entries)) => entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Value::String(path) => Some((path.as_str(), true)),
+                Value::Mapping(entry) => {
+                    let path = entry.get("path")?.as_str()?;
+                    let required = entry
+                        .get("required")
+                        .and_then(Value::as_bool)
+                        .unwrap_or(true);
+                    Some((path, required))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    entries
+        .into_iter()
+        .map(|(path, required)| EnvFile {
+            path: resolve_relative_to(compose_file, path),
+            required,
+        })
+        .collect()
+}
+
+pub fn parse_env_file(contents: &str) -> Vec<(String, String)> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line
+                .strip_prefix("export ")
+                .unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            Some((key.trim().to_string(), parse_env_value(value.trim())))
+        })
+        .collect()
+}
+
+fn parse_env_value(value: &str) -> String {
+    if let Some(inner) = value
+        .strip_prefix('\'')
+        .and_then(|v| v.split_once('\''))
+    {
+        return inner.0.to_string();
+    }
+
+    if let Some(inner) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(other) => out.push(other),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        return out;
+    }
+
+    match value.find(" #") {
+        Some(i) => value[..i].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// The `environment:` entries of a service; `None` for entries without
+/// a value, which compose takes from its own environment.
+fn environment_section(service: &Value) -> Vec<(String, Option<String>)> {
+    match service.get("environment") {
+        Some(Value::Mapping(entries)) => entries
+            .iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => Some(s.clone()),
+                    Value::Number(n) => Some(n.to_string()),
+                    Value::Bool(b) => Some(b.to_string()),
+                    Value::Null => None,
+                    _ => return None,
+                };
+                Some((key.as_str()?.to_string(), value))
+            })
+            .collect(),
+        Some(Value::Sequence(entries)) => entries
+            .iter()
+            .filter_map(Value::as_str)
+            .map(|entry| match entry.split_once('=') {
+                Some((key, value)) => {
+                    (key.to_string(), Some(value.to_string()))
+                }
+                None => (entry.to_string(), None),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The variables compose interpolates the compose file with: its own
+/// environment, which nirion passes on, over the `.env` file next to the
+/// compose file.
+fn compose_environment(compose_file: &Path) -> BTreeMap<String, String> {
+    let mut env = fs::read_to_string(resolve_relative_to(compose_file, ".env"))
+        .map(|contents| parse_env_file(&contents))
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    env.extend(std::env::vars());
+    env
+}
+
+/// Substitutes `$VAR`, `${VAR}` and the `${VAR:-default}`, `${VAR-default}`,
+/// `${VAR:?error}`, `${VAR?error}`, `${VAR:+alternative}` and
+/// `${VAR+alternative}` forms compose knows, with `$$` for a literal `$`.
+/// `None` if a variable without a default isn't in `env`.
+fn interpolate(
+    value: &str,
+    env: &BTreeMap<String, String>,
+) -> Option<String> {
+    let mut interpolated = String::new();
+    let mut rest = value;
+    while let Some(at) = rest.find('$') {
+        interpolated.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            interpolated.push('$');
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let end = closing_brace(braced)?;
+            interpolated.push_str(&expand(&braced[..end], env)?);
+            rest = &braced[end + 1..];
+        } else {
+            let (name, after) = rest.split_at(variable_name_len(rest));
+            if name.is_empty() {
+                interpolated.push('$');
+            } else {
+                interpolated.push_str(env.get(name)?);
+            }
+            rest = after;
+        }
+    }
+    interpolated.push_str(rest);
+    Some(interpolated)
+}
+
+/// The value of one `${...}` expression, without the braces.
+fn expand(
+    expression: &str,
+    env: &BTreeMap<String, String>,
+) -> Option<String> {
+    let (name, modifier) = expression.split_at(variable_name_len(expression));
+    let value = env.get(name);
+    if modifier.is_empty() {
+        return value.cloned();
+    }
+
+    let (operator, word) = [":-", ":?", ":+", "-", "?", "+"]
+        .into_iter()
+        .find_map(|operator| {
+            modifier
+                .strip_prefix(operator)
+                .map(|word| (operator, word))
+        })?;
+    // The forms with a colon treat an empty variable as unset.
+    let set = match operator.starts_with(':') {
+        true => value.filter(|value| !value.is_empty()),
+        false => value,
+    };
+    match (operator.trim_start_matches(':'), set) {
+        ("-", Some(value)) | ("?", Some(value)) => Some(value.clone()),
+        ("-", None) | ("+", Some(_)) => interpolate(word, env),
+        ("+", None) => Some(String::new()),
+        _ => None,
+    }
+}
+
+fn variable_name_len(value: &str) -> usize {
+    value
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(value.len())
+}
+
+/// The index of the `}` closing an expression, past nested ones in its
+/// default.
+fn closing_brace(value: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, c) in value.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Merges the service's env files and `environment:` section. Later env
+/// files override earlier ones and `environment:` overrides all of them.
+/// `environment:` values are interpolated like compose does; those that
+/// can't be are left as written and marked unresolved.
+pub fn service_env(
+    compose_file: &Path,
+    target: &ServiceSelector,
+) -> anyhow::Result<Vec<EnvVar>> {
+    let compose = load_compose(compose_file)?;
+    let service = compose
+        .get("services")
+        .and_then(|services| services.get(&target.service))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Service `{}` not found in compose file for project `{}`",
+                target.service,
+                target.project
+            )
+        })?;
+
+    let mut merged = BTreeMap::new();
+
+    for env_file in service_env_files(compose_file, service) {
+        let contents = match fs::read_to_string(&env_file.path) {
+            Ok(contents) => contents,
+            Err(_) if !env_file.required => continue,
+            Err(e) => anyhow::bail!(
+                "Failed reading env file {}: {}",
+                env_file.path.display(),
+                e
+            ),
+        };
+
+        let source = env_file.path.display().to_string();
+        for (key, value) in parse_env_file(&contents) {
+            merged.insert(key, (value, source.clone(), true));
+        }
+    }
+
+    let env = compose_environment(compose_file);
+    for (key, value) in environment_section(service) {
+        let resolved = match &value {
+            Some(value) => interpolate(value, &env),
+            None => env.get(&key).cloned(),
+        };
+        let (value, resolved) = match resolved {
+            Some(resolved) => (resolved, true),
+            None => (value.unwrap_or_default(), false),
+        };
+        merged.insert(key, (value, "environment".to_string(), resolved));
+    }
+
+    Ok(merged
+        .into_iter()
+        .map(|(key, (value, source, resolved))| EnvVar {
+            key,
+            value,
+            source,
+            resolved,
+        })
+        .collect())
+}
+
+/// Reads `Config.Env` of the service's container, or `None` when the
+/// service has no container.
+pub async fn container_env(
+    context: &NirionContext,
+    target: &ServiceSelector,
+) -> anyhow::Result<Option<BTreeMap<String, String>>> {
+    let status = query_project_status(context, &target.project).await?;
+    let Some(service) = status.replica(&target.service, None) else {
+        return Ok(None);
+    };
+
+    let output = context
+        .docker_command
+        .command()
+        .arg("inspect")
+        .arg("--format")
+        .arg("{{json .Config.Env}}")
+        .arg(&service.id)
+        .output()
+        .await
+        .context("failed to execute docker inspect")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!(
+            "docker inspect failed with status {}{}{}",
+            output.status,
+            if stderr.trim().is_empty() { "" } else { ": " },
+            stderr.trim()
+        );
+    }
+
+    let entries: Option<Vec<String>> =
+        serde_json::from_slice(&output.stdout)
+            .context("failed to parse container environment")?;
+
+    Ok(Some(
+        entries
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                entry
+                    .split_once('=')
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+            })
+            .collect(),
+    ))
+}
+
+pub fn compare_env(
+    vars: Vec<EnvVar>,
+    container: Option<&BTreeMap<String, String>>,
+) -> Vec<EnvComparison> {
+    vars.into_iter()
+        .map(|var| {
+            let drift = match container.map(|env| env.get(&var.key)) {
+                None => EnvDrift::NotRunning,
+                Some(_) if !var.resolved => EnvDrift::Unresolved,
+                Some(None) => EnvDrift::Missing,
+                Some(Some(value)) if *value == var.value => EnvDrift::Match,
+                Some(Some(value)) => EnvDrift::Differs {
+                    container_value: value.clone(),
+                },
+            };
+            EnvComparison { var, drift }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        docker::DockerCommand, lock::LockedImages, projects::Projects,
+    };
+    use nirion_oci_lib::client::NirionOciClient;
+    use std::sync::Arc;
+
+    fn selector() -> ServiceSelector {
+        ServiceSelector {
+            project: "myapp".into(),
+            service: "web".into(),
+        }
+    }
+
+    #[test]
+    fn parse_env_file_handles_comments_quotes_and_export() {
+        let vars = parse_env_file(
+            r#"
+# comment
+export A=1
+B = two # trailing
+C='single # kept'
+D="line\nbreak"
+NOVALUE
+"#,
+        );
+
+        assert_eq!(
+            vars,
+            vec![
+                ("A".into(), "1".into()),
+                ("B".into(), "two".into()),
+                ("C".into(), "single # kept".into()),
+                ("D".into(), "line\nbreak".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn service_env_files_resolves_relative_paths_and_long_syntax() {
+        let service = serde_yaml_ng::from_str::<Value>(
+            "env_file: [a.env, {path: /abs/b.env, required: false}]",
+        )
+        .unwrap();
+
+        let files =
+            service_env_files(Path::new("/srv/app/compose.yml"), &service);
+
+        assert_eq!(
+            files,
+            vec![
+                EnvFile {
+                    path: PathBuf::from("/srv/app/a.env"),
+                    required: true
+                },
+                EnvFile {
+                    path: PathBuf::from("/abs/b.env"),
+                    required: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn service_env_merges_in_compose_precedence_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.env"), "A=from-a\nB=from-a\n").unwrap();
+        fs::write(dir.path().join("b.env"), "B=from-b\nC=from-b\n").unwrap();
+        let compose = dir.path().join("compose.yml");
+        fs::write(
+            &compose,
+            r#"
+services:
+  web:
+    env_file:
+      - a.env
+      - b.env
+      - path: missing.env
+        required: false
+    environment:
+      C: from-environment
+"#,
+        )
+        .unwrap();
+
+        let vars = service_env(&compose, &selector()).unwrap();
+        let values = vars
+            .iter()
+            .map(|v| (v.key.as_str(), v.value.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![("A", "from-a"), ("B", "from-b"), ("C", "from-environment")]
+        );
+        assert!(vars[1].source.ends_with("b.env"));
+        assert_eq!(vars[2].source, "environment");
+    }
+
+    #[test]
+    fn service_env_resolves_environment_like_compose() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(".env"),
+            "NIRION_TEST_HOST=db\nNIRION_TEST_EMPTY=\n",
+        )
+        .unwrap();
+        let compose = dir.path().join("compose.yml");
+        fs::write(
+            &compose,
+            r#"
+services:
+  web:
+    environment:
+      - NIRION_TEST_HOST
+      - URL=postgres://${NIRION_TEST_HOST}:$${PORT}/app
+      - PORT=${NIRION_TEST_PORT:-5432}
+      - MODE=${NIRION_TEST_EMPTY:-dev}${NIRION_TEST_EMPTY-unused}
+      - TLS=${NIRION_TEST_HOST:+on}
+      - REPLICA=${NIRION_TEST_UNSET:-${NIRION_TEST_HOST}-2}
+      - NIRION_TEST_UNSET
+      - USER=${NIRION_TEST_UNSET}
+"#,
+        )
+        .unwrap();
+
+        let vars = service_env(&compose, &selector()).unwrap();
+        let values = vars
+            .iter()
+            .map(|v| (v.key.as_str(), v.value.as_str(), v.resolved))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            vec![
+                ("MODE", "dev", true),
+                ("NIRION_TEST_HOST", "db", true),
+                ("NIRION_TEST_UNSET", "", false),
+                ("PORT", "5432", true),
+                ("REPLICA", "db-2", true),
+                ("TLS", "on", true),
+                ("URL", "postgres://db:${PORT}/app", true),
+                ("USER", "${NIRION_TEST_UNSET}", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn service_env_reports_missing_required_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let compose = dir.path().join("compose.yml");
+        fs::write(&compose, "services: {web: {env_file: gone.env}}").unwrap();
+
+        let err = service_env(&compose, &selector()).unwrap_err();
+
+        assert!(
+            err.to_string()
+                .contains("Failed reading env file")
+        );
+    }
+
+    #[test]
+    fn compare_env_flags_drift() {
+        let var = |key: &str, value: &str| EnvVar {
+            key: key.into(),
+            value: value.into(),
+            source: "env".into(),
+            resolved: true,
+        };
+        let container = BTreeMap::from([
+            ("A".to_string(), "1".to_string()),
+            ("B".to_string(), "old".to_string()),
+        ]);
+
+        let drift = compare_env(
+            vec![var("A", "1"), var("B", "new"), var("C", "3")],
+            Some(&container),
+        )
+        .into_iter()
+        .map(|c| c.drift)
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            drift,
+            vec![
+                EnvDrift::Match,
+                EnvDrift::Differs {
+                    container_value: "old".into()
+                },
+                EnvDrift::Missing,
+            ]
+        );
+        assert_eq!(
+            compare_env(vec![var("A", "1")], None)[0].drift,
+            EnvDrift::NotRunning
+        );
+        let unresolved = EnvVar {
+            resolved: false,
+            ..var("B", "${B}")
+        };
+        assert_eq!(
+            compare_env(vec![unresolved], Some(&container))[0].drift,
+            EnvDrift::Unresolved
+        );
+    }
+
+    #[test]
+    fn is_secret_key_matches_common_names() {
+        assert!(is_secret_key("DB_PASSWORD"));
+        assert!(is_secret_key("api_token"));
+        assert!(is_secret_key("API_KEY"));
+        assert!(is_secret_key("key_file"));
+        assert!(!is_secret_key("TZ"));
+        assert!(!is_secret_key("KEYBOARD_LAYOUT"));
+        assert!(!is_secret_key("MONKEY_MODE"));
+    }
+
+    #[test]
+    fn redact_secrets_masks_secret_keys_at_any_depth() {
+        let mut compose: Value = serde_yaml_ng::from_str(
+            r#"
+services:
+  db:
+    image: postgres
+    environment:
+      POSTGRES_PASSWORD: hunter2
+      POSTGRES_DB: app
+      API_TOKEN: 42
+  worker:
+    environment:
+      - SECRET_KEY=abc
+      - TZ=UTC
+    command: ["sh", "-c", "echo token=1"]
+secrets:
+  db_password:
+    file: ./db_password.txt
+"#,
+        )
+        .unwrap();
+        redact_secrets(&mut compose);
+
+        let expected: Value = serde_yaml_ng::from_str(
+            r#"
+services:
+  db:
+    image: postgres
+    environment:
+      POSTGRES_PASSWORD: "********"
+      POSTGRES_DB: app
+      API_TOKEN: "********"
+  worker:
+    environment:
+      - SECRET_KEY=********
+      - TZ=UTC
+    command: ["sh", "-c", "echo token=1"]
+secrets:
+  db_password:
+    file: ./db_password.txt
+"#,
+        )
+        .unwrap();
+        assert_eq!(compose, expected);
+    }
+
+    #[tokio::test]
+    async fn container_env_reads_config_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            r#"if [ "$1" = "compose" ]; then
+  printf '%s\n' '{"ID":"abc","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running"}'
+  exit 0
+fi
+printf '%s\n' '["A=1","PATH=/bin"]'
+"#,
+        )
+        .unwrap();
+        let context = NirionContext {
+            projects: serde_json::from_value::<Projects>(serde_json::json!({
+                "myapp": {
+                    "name": "myapp",
+                    "dockerCompose": "compose.yml",
+                    "services": {}
+                }
+            }))
+            .unwrap(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::with_args("/bin/sh", [script]),
+        };
+
+        let env = container_env(&context, &selector())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(env.get("A").map(String::as_str), Some("1"));
+        assert_eq!(env.get("PATH").map(String::as_str), Some("/bin"));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::lock::DiffEntry;
+use crate::{
+    downgrade::SuspectedDowngrade, lock::DiffEntry,
+    resolve_failure::FailureReport,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcessEvent {
@@ -34,14 +37,53 @@ pub enum WaitEvent {
 #[derive(Debug, Clone)]
 pub enum LockUpdateEvent {
     NoImages,
-    ImageStarted { service: String, image: String },
-    ImageResolved { service: String },
+    ImageStarted {
+        service: String,
+        image: String,
+    },
+    ImageResolved {
+        service: String,
+        source: DigestSource,
+    },
+    /// A locally built image, left out of the lock file.
+    ImageSkipped {
+        service: String,
+        image: String,
+    },
+    /// Looking for the version of `image` among its tags stopped after
+    /// `searched` tags, so the version may be missing or not the best
+    /// match.
+    TagSearchCapped {
+        service: String,
+        image: String,
+        searched: usize,
+    },
     UpToDate,
-    ChangesDetected { diffs: Vec<DiffEntry> },
+    /// Updates that look like they move back to an older image. Unless
+    /// `held` is false, they are left out of the changes and not written.
+    DowngradesSuspected {
+        downgrades: Vec<SuspectedDowngrade>,
+        held: bool,
+    },
+    ChangesDetected {
+        diffs: Vec<DiffEntry>,
+    },
+    /// Some images could not be resolved; the lock file is not written.
+    ResolutionFailed {
+        report: FailureReport,
+    },
     WritingLockFile,
     LockFileWritten,
 }
 
+/// Where the digest of a resolved image came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestSource {
+    Registry,
+    /// The copy pulled on this host, see `lock --prefer-local`.
+    LocalImage,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ExitStatus {
     pub code: Option<i32>,
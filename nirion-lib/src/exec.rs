@@ -1,10 +1,22 @@
-use std::ops::Deref;
+use std::{
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use anyhow::Context;
+use futures::{StreamExt, stream::BoxStream};
+use tokio::process::Command;
 
 use crate::{
     context::NirionContext,
-    projects::{Projects, ServiceSelector},
+    docker::{ReplicaRange, ServiceState, query_project_status},
+    events::ExitStatus,
+    projects::{
+        Projects, ServiceSelector, TargetSelector, selected_project_names,
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -20,33 +32,215 @@ pub struct ExecRequest {
     pub cmd: Vec<String>,
 }
 
-pub async fn exec(
+/// Builds the `docker compose exec` command for `request`, checking
+/// `--index` against the service's replicas first. Running it is left to
+/// the caller, which decides how to attach it to the terminal.
+pub async fn exec_command(
     context: &NirionContext,
     request: &ExecRequest,
-) -> anyhow::Result<()> {
-    let project_name = &request.target.project;
-    let service_name = &request.target.service;
+) -> anyhow::Result<Command> {
     let cmd_args = build_exec_args(&context.projects, request)?;
 
-    let status = context
+    if let Some(index) = request.index {
+        select_replicas(
+            context,
+            &request.target,
+            Some(ReplicaRange {
+                start: index,
+                end: index,
+            }),
+        )
+        .await?;
+    }
+
+    let mut command = context.docker_command.command();
+    command.arg("compose").args(&cmd_args);
+    Ok(command)
+}
+
+/// The replica numbers of the service that `range` picks, or all of
+/// them without one. Rejects a range naming a replica the service doesn't
+/// currently have before anything is handed to compose.
+pub async fn select_replicas(
+    context: &NirionContext,
+    target: &ServiceSelector,
+    range: Option<ReplicaRange>,
+) -> anyhow::Result<Vec<u32>> {
+    let status = query_project_status(context, &target.project).await?;
+    let mut available = status
+        .replicas(&target.service)
+        .iter()
+        .map(|replica| replica.index)
+        .collect::<Vec<_>>();
+    available.sort_unstable();
+    available.dedup();
+
+    pick_replicas(target, &available, range)
+}
+
+fn pick_replicas(
+    target: &ServiceSelector,
+    available: &[u32],
+    range: Option<ReplicaRange>,
+) -> anyhow::Result<Vec<u32>> {
+    if available.is_empty() {
+        anyhow::bail!(
+            "{}.{} has no containers",
+            target.project,
+            target.service
+        );
+    }
+    let Some(range) = range else {
+        return Ok(available.to_vec());
+    };
+
+    let missing = (range.start..=range.end)
+        .filter(|index| !available.contains(index))
+        .map(|index| index.to_string())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "{}.{} has no replica with index {} (available: {})",
+            target.project,
+            target.service,
+            missing.join(", "),
+            available
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(available
+        .iter()
+        .copied()
+        .filter(|index| range.contains(*index))
+        .collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecOutput {
+    pub target: ServiceSelector,
+    /// The replica the command ran in, if the request named one.
+    pub index: Option<u32>,
+    pub exit: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs the request without a TTY and captures its output instead of
+/// attaching it to the terminal.
+pub async fn exec_captured(
+    context: &NirionContext,
+    request: &ExecRequest,
+) -> anyhow::Result<ExecOutput> {
+    let request = ExecRequest {
+        no_tty: true,
+        ..request.clone()
+    };
+    let cmd_args = build_exec_args(&context.projects, &request)?;
+
+    let output = context
         .docker_command
         .command()
         .arg("compose")
         .args(&cmd_args)
-        .status()
+        .kill_on_drop(true)
+        .output()
         .await
         .context("failed to execute docker compose exec")?;
 
-    if !status.success() {
-        anyhow::bail!(
-            "Command failed in {}.{} with status {}",
-            project_name,
-            service_name,
-            status
-        );
+    Ok(ExecOutput {
+        target: request.target,
+        index: request.index,
+        exit: output.status.into(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+/// Expands a target selector to the services that currently have a
+/// running container.
+pub async fn running_services(
+    context: &NirionContext,
+    target: &TargetSelector,
+) -> anyhow::Result<Vec<ServiceSelector>> {
+    let mut services = Vec::new();
+
+    for project_name in selected_project_names(target, &context.projects) {
+        let status = query_project_status(context, &project_name).await?;
+
+        for (service_name, replicas) in status.services {
+            if let TargetSelector::Service(sel) = target
+                && sel.service != service_name
+            {
+                continue;
+            }
+
+            if replicas.iter().any(|service| {
+                matches!(
+                    service.state,
+                    ServiceState::Running
+                        | ServiceState::Healthy
+                        | ServiceState::Unhealthy
+                        | ServiceState::Starting
+                )
+            }) {
+                services.push(ServiceSelector {
+                    project: project_name.clone(),
+                    service: service_name,
+                });
+            }
+        }
     }
 
-    Ok(())
+    Ok(services)
+}
+
+/// Runs one captured exec per request with at most `parallel` in flight.
+/// Results are yielded in completion order; dropping the stream kills the
+/// commands that are still running. A command that can't be started
+/// yields a failed output with the error as its stderr.
+///
+/// With `fail_fast`, no more requests are started after the first
+/// failure, while those already running are waited for and yielded.
+pub fn exec_all_stream(
+    context: NirionContext,
+    requests: Vec<ExecRequest>,
+    parallel: usize,
+    fail_fast: bool,
+) -> BoxStream<'static, ExecOutput> {
+    let failed = Arc::new(AtomicBool::new(false));
+    let stop = Arc::clone(&failed);
+    futures::stream::iter(requests)
+        .take_while(move |_| {
+            futures::future::ready(!stop.load(Ordering::Relaxed))
+        })
+        .map(move |request| {
+            let context = context.clone();
+            async move {
+                exec_captured(&context, &request)
+                    .await
+                    .unwrap_or_else(|error| ExecOutput {
+                        target: request.target,
+                        index: request.index,
+                        exit: ExitStatus {
+                            code: None,
+                            success: false,
+                        },
+                        stdout: String::new(),
+                        stderr: format!("{error:#}"),
+                    })
+            }
+        })
+        .buffer_unordered(parallel.max(1))
+        .inspect(move |output| {
+            if fail_fast && !output.exit.success {
+                failed.store(true, Ordering::Relaxed);
+            }
+        })
+        .boxed()
 }
 
 fn build_exec_args(
@@ -90,11 +284,15 @@ fn build_exec_args(
     let project = &projects[project_name];
     let mut cmd_args = vec![
         "--file".to_string(),
-        project.docker_compose.clone(),
+        project
+            .docker_compose
+            .to_string_lossy()
+            .into_owned(),
         "--project-name".to_string(),
         project.name.deref().to_string(),
-        "exec".to_string(),
     ];
+    cmd_args.extend(project.profile_args());
+    cmd_args.push("exec".to_string());
     cmd_args.extend(common_args);
     cmd_args.push(service_name.clone());
     cmd_args.extend(request.cmd.clone());
@@ -107,8 +305,8 @@ mod tests {
     use super::*;
     use crate::{docker::DockerCommand, lock::LockedImages};
     use nirion_oci_lib::client::NirionOciClient;
+    use std::sync::Arc;
     use std::{fs, os::unix::fs::PermissionsExt, path::Path};
-    use std::{path::PathBuf, sync::Arc};
 
     fn write_fake_docker(
         dir: &Path,
@@ -151,7 +349,7 @@ exit {exit_code}
         NirionContext {
             projects: projects(),
             locked_images: LockedImages::default(),
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -268,18 +466,22 @@ exit {exit_code}
     }
 
     #[tokio::test]
-    async fn exec_runs_docker_compose_exec() {
+    async fn exec_command_runs_docker_compose_exec() {
         let dir = tempfile::tempdir().unwrap();
         let args_file = dir.path().join("args");
-        let docker = write_fake_docker(dir.path(), &args_file, 0);
+        let docker = write_fake_docker(dir.path(), &args_file, 7);
 
-        exec(
+        let status = exec_command(
             &context(fake_docker_command(&docker)),
             &request(vec!["true"]),
         )
         .await
+        .unwrap()
+        .status()
+        .await
         .unwrap();
 
+        assert_eq!(status.code(), Some(7));
         assert_eq!(
             fs::read_to_string(args_file).unwrap(),
             "compose\n--file\ncompose.yml\n--project-name\nmyapp\nexec\nweb\ntrue\n"
@@ -287,39 +489,160 @@ exit {exit_code}
     }
 
     #[tokio::test]
-    async fn exec_reports_failed_status() {
+    async fn exec_captured_forces_no_tty_and_collects_output() {
         let dir = tempfile::tempdir().unwrap();
-        let args_file = dir.path().join("args");
-        let docker = write_fake_docker(dir.path(), &args_file, 7);
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            "printf '%s\\n' \"$@\" > \"$(dirname \"$0\")/args\"\n\
+             echo out\necho err >&2\nexit 3\n",
+        )
+        .unwrap();
 
-        let err = exec(
-            &context(fake_docker_command(&docker)),
-            &request(vec!["false"]),
+        let output = exec_captured(
+            &context(fake_docker_command(&script.to_string_lossy())),
+            &request(vec!["date"]),
         )
         .await
-        .unwrap_err();
+        .unwrap();
 
+        assert_eq!(output.exit.code, Some(3));
+        assert!(!output.exit.success);
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
         assert!(
-            err.to_string()
-                .contains("Command failed in myapp.web with status")
+            fs::read_to_string(dir.path().join("args"))
+                .unwrap()
+                .contains("exec\n-T\nweb\ndate\n")
         );
     }
 
     #[tokio::test]
-    async fn exec_reports_spawn_failure() {
+    async fn running_services_skips_stopped_containers() {
         let dir = tempfile::tempdir().unwrap();
-        let missing_docker = dir.path().join("missing-docker");
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            r#"printf '%s\n' '{"ID":"a","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running"}'
+printf '%s\n' '{"ID":"b","Name":"myapp-job-1","Service":"job","Image":"alpine","State":"exited","ExitCode":0}'
+"#,
+        )
+        .unwrap();
 
-        let err = exec(
-            &context(DockerCommand::new(missing_docker)),
-            &request(vec!["true"]),
+        let services = running_services(
+            &context(fake_docker_command(&script.to_string_lossy())),
+            &TargetSelector::All,
         )
         .await
-        .unwrap_err();
+        .unwrap();
+
+        assert_eq!(
+            services,
+            vec![ServiceSelector {
+                project: "myapp".into(),
+                service: "web".into(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn exec_all_stream_runs_every_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let args_file = dir.path().join("args");
+        let docker = write_fake_docker(dir.path(), &args_file, 0);
+
+        let outputs = exec_all_stream(
+            context(fake_docker_command(&docker)),
+            vec![request(vec!["true"]), request(vec!["true"])],
+            1,
+            false,
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs.iter().all(|o| o.exit.success));
+    }
 
+    #[tokio::test]
+    async fn exec_all_stream_lets_running_commands_finish_after_a_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            "for command; do :; done\n\
+             case \"$command\" in\n\
+             fail) exit 1 ;;\n\
+             slow) sleep 0.3; echo done ;;\n\
+             esac\n",
+        )
+        .unwrap();
+
+        let outputs = exec_all_stream(
+            context(fake_docker_command(&script.to_string_lossy())),
+            vec![
+                request(vec!["slow"]),
+                request(vec!["fail"]),
+                request(vec!["later"]),
+            ],
+            2,
+            true,
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        let outcomes = outputs
+            .iter()
+            .map(|output| (output.exit.success, output.stdout.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes, [(false, ""), (true, "done\n")]);
+    }
+
+    #[tokio::test]
+    async fn exec_all_stream_reports_commands_that_fail_to_start() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let outputs = exec_all_stream(
+            context(DockerCommand::new(dir.path().join("missing-docker"))),
+            vec![request(vec!["true"]), request(vec!["true"])],
+            1,
+            false,
+        )
+        .collect::<Vec<_>>()
+        .await;
+
+        assert_eq!(outputs.len(), 2);
+        assert!(!outputs[0].exit.success);
+        assert_eq!(outputs[0].exit.code, None);
         assert!(
-            err.to_string()
+            outputs[0]
+                .stderr
                 .contains("failed to execute docker compose exec")
         );
     }
+
+    #[test]
+    fn pick_replicas_checks_every_index_of_the_range() {
+        let target = ServiceSelector {
+            project: "myapp".into(),
+            service: "web".into(),
+        };
+
+        assert_eq!(
+            pick_replicas(&target, &[1, 2, 3], None).unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            pick_replicas(&target, &[1, 2, 3], Some("2-3".parse().unwrap()))
+                .unwrap(),
+            [2, 3]
+        );
+        assert_eq!(
+            pick_replicas(&target, &[1, 2], Some("2-4".parse().unwrap()))
+                .unwrap_err()
+                .to_string(),
+            "myapp.web has no replica with index 3, 4 (available: 1, 2)"
+        );
+        assert!(pick_replicas(&target, &[], None).is_err());
+    }
 }
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    projects::ServiceSelector,
+    state::{append_jsonl, current_user, read_jsonl},
+};
+
+pub const EXEC_HISTORY_FILE: &str = "exec-history.jsonl";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecHistoryEntry {
+    pub timestamp: String,
+    pub user: String,
+    pub target: String,
+    pub command: Vec<String>,
+}
+
+impl ExecHistoryEntry {
+    pub fn now(
+        target: &ServiceSelector,
+        command: &[String],
+    ) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            user: current_user(),
+            target: format!("{}.{}", target.project, target.service),
+            command: command.to_vec(),
+        }
+    }
+}
+
+pub fn exec_history_file(state_dir: &Path) -> PathBuf {
+    state_dir.join(EXEC_HISTORY_FILE)
+}
+
+/// Appends one entry, see [`append_jsonl`].
+pub fn record_exec(
+    path: &Path,
+    entry: &ExecHistoryEntry,
+) -> anyhow::Result<()> {
+    append_jsonl(path, entry)
+}
+
+/// Returns the most recent distinct commands run against `target`,
+/// newest first. Unparseable lines are skipped.
+pub fn read_exec_history(
+    path: &Path,
+    target: &ServiceSelector,
+    limit: usize,
+) -> anyhow::Result<Vec<ExecHistoryEntry>> {
+    let target = format!("{}.{}", target.project, target.service);
+    let mut entries: Vec<ExecHistoryEntry> = Vec::new();
+
+    for entry in read_jsonl::<ExecHistoryEntry>(path)?
+        .into_iter()
+        .rev()
+        .filter(|entry| entry.target == target)
+    {
+        if entries
+            .iter()
+            .any(|seen| seen.command == entry.command)
+        {
+            continue;
+        }
+        entries.push(entry);
+        if entries.len() == limit {
+            break;
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Write};
+
+    fn target(service: &str) -> ServiceSelector {
+        ServiceSelector {
+            project: "myapp".into(),
+            service: service.into(),
+        }
+    }
+
+    fn entry(
+        service: &str,
+        command: &str,
+    ) -> ExecHistoryEntry {
+        ExecHistoryEntry {
+            timestamp: "2026-01-01T00:00:00+00:00".into(),
+            user: "ops".into(),
+            target: format!("myapp.{service}"),
+            command: command
+                .split(' ')
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn record_exec_creates_state_dir_and_appends_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exec_history_file(&dir.path().join("nested"));
+
+        record_exec(&path, &entry("web", "date")).unwrap();
+        record_exec(&path, &entry("db", "psql -c select")).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+    }
+
+    #[test]
+    fn read_exec_history_filters_dedupes_and_orders_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exec_history_file(dir.path());
+        for e in [
+            entry("web", "date"),
+            entry("db", "psql"),
+            entry("web", "uptime"),
+            entry("web", "date"),
+        ] {
+            record_exec(&path, &e).unwrap();
+        }
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        let history = read_exec_history(&path, &target("web"), 10).unwrap();
+
+        assert_eq!(
+            history
+                .iter()
+                .map(|e| e.command.join(" "))
+                .collect::<Vec<_>>(),
+            vec!["date", "uptime"]
+        );
+    }
+
+    #[test]
+    fn read_exec_history_handles_missing_file_and_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = exec_history_file(dir.path());
+        assert!(
+            read_exec_history(&path, &target("web"), 5)
+                .unwrap()
+                .is_empty()
+        );
+
+        record_exec(&path, &entry("web", "a")).unwrap();
+        record_exec(&path, &entry("web", "b")).unwrap();
+        assert_eq!(
+            read_exec_history(&path, &target("web"), 1)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
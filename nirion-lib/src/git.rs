@@ -0,0 +1,250 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tokio::process::Command;
+
+use crate::{
+    config::{ProjectSource, build_nix_project_file},
+    projects::{ProjectNames, Projects},
+};
+
+/// The projects `source` defined at `git_ref`. A project file is read
+/// with `git show`; a nix target is built from a temporary worktree
+/// checked out at `git_ref`, which needs a flake in a local checkout.
+///
+/// Invalid compose project names are normalized rather than rejected:
+/// the old file only serves to compare images against.
+pub async fn projects_at_ref(
+    source: &ProjectSource,
+    git_ref: &str,
+) -> anyhow::Result<Projects> {
+    let json = match source {
+        ProjectSource::File(path) => file_at_ref(path, git_ref)
+            .await
+            .with_context(|| {
+                format!("cannot read {} at {git_ref}", path.display())
+            })?,
+        ProjectSource::Nix(target) => nix_build_at_ref(target, git_ref)
+            .await
+            .with_context(|| format!("cannot build {target} at {git_ref}"))?,
+        ProjectSource::Stdin => anyhow::bail!(
+            "the project file was read from stdin, so there is no file to \
+             read at {git_ref}"
+        ),
+    };
+
+    Projects::from_json(&json, ProjectNames::Normalize).with_context(|| {
+        format!("failed to parse the project file at {git_ref}")
+    })
+}
+
+/// Keeps the services in `images` whose image reference differs from the
+/// one in `old`, including services `old` doesn't have.
+pub fn retain_changed_images(
+    images: &mut BTreeMap<String, String>,
+    old: &BTreeMap<String, String>,
+) {
+    images.retain(|service, image| old.get(service) != Some(image));
+}
+
+async fn file_at_ref(
+    path: &Path,
+    git_ref: &str,
+) -> anyhow::Result<String> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .context("the project file path has no file name")?
+        .to_string_lossy();
+
+    git(dir, &["show", &format!("{git_ref}:./{name}")]).await
+}
+
+async fn nix_build_at_ref(
+    target: &str,
+    git_ref: &str,
+) -> anyhow::Result<String> {
+    let (flake, attribute) = target
+        .split_once('#')
+        .filter(|(flake, _)| flake.is_empty() || flake.starts_with(['.', '/']))
+        .context(
+            "only flakes in a local checkout, like .#host, are supported",
+        )?;
+    let flake = fs::canonicalize(if flake.is_empty() { "." } else { flake })
+        .with_context(|| format!("failed to resolve {flake}"))?;
+
+    let root = PathBuf::from(
+        git(&flake, &["rev-parse", "--show-toplevel"])
+            .await?
+            .trim(),
+    );
+    let worktree = Worktree::add(&root, git_ref).await?;
+    let flake = worktree.path.join(
+        flake
+            .strip_prefix(&root)
+            .unwrap_or(Path::new("")),
+    );
+
+    let project_file =
+        build_nix_project_file(&format!("{}#{attribute}", flake.display()))
+            .await?;
+    fs::read_to_string(&project_file)
+        .with_context(|| format!("failed to read {}", project_file.display()))
+}
+
+/// A detached checkout of a ref next to the repository, removed again
+/// when dropped.
+struct Worktree {
+    repository: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    async fn add(
+        repository: &Path,
+        git_ref: &str,
+    ) -> anyhow::Result<Self> {
+        let path = std::env::temp_dir()
+            .join(format!("nirion-worktree-{}", std::process::id()));
+        git(
+            repository,
+            &[
+                "worktree",
+                "add",
+                "--detach",
+                &path.to_string_lossy(),
+                git_ref,
+            ],
+        )
+        .await?;
+
+        Ok(Self {
+            repository: repository.to_path_buf(),
+            path,
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let _ = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.repository)
+            .args(["worktree", "remove", "--force"])
+            .arg(&self.path)
+            .output();
+    }
+}
+
+async fn git(
+    dir: &Path,
+    args: &[&str],
+) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await
+        .context("failed to execute git")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::{TargetSelector, get_images};
+
+    fn projects_json(web: &str) -> String {
+        format!(
+            r#"{{
+                "app": {{
+                    "name": "app",
+                    "dockerCompose": "compose.yml",
+                    "services": {{
+                        "web": {{"image": "{web}", "restart": null}},
+                        "db": {{"image": "postgres:16", "restart": null}}
+                    }}
+                }}
+            }}"#
+        )
+    }
+
+    fn run_git(
+        dir: &Path,
+        args: &[&str],
+    ) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=nirion", "-c", "user.email=nirion@test"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[tokio::test]
+    async fn only_services_whose_image_changed_since_the_ref_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("projects.json");
+        run_git(dir.path(), &["init", "-q"]);
+        fs::write(&path, projects_json("nginx:1.26")).unwrap();
+        run_git(dir.path(), &["add", "projects.json"]);
+        run_git(dir.path(), &["commit", "-q", "-m", "projects"]);
+        fs::write(&path, projects_json("nginx:1.27")).unwrap();
+
+        let source = ProjectSource::File(path.clone());
+        let old = projects_at_ref(&source, "HEAD")
+            .await
+            .unwrap();
+        let current = Projects::from_json(
+            &fs::read_to_string(&path).unwrap(),
+            ProjectNames::Reject,
+        )
+        .unwrap();
+        let mut images = get_images(&TargetSelector::All, &current).unwrap();
+        retain_changed_images(
+            &mut images,
+            &get_images(&TargetSelector::All, &old).unwrap(),
+        );
+
+        assert_eq!(
+            images,
+            BTreeMap::from([("app.web".to_string(), "nginx:1.27".to_string())])
+        );
+
+        let error = projects_at_ref(&source, "no-such-ref")
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("at no-such-ref"), "{error:#}");
+    }
+
+    #[tokio::test]
+    async fn only_local_flakes_can_be_built_at_another_ref() {
+        let source = ProjectSource::Nix("github:acme/infra#host".into());
+        let error = projects_at_ref(&source, "HEAD")
+            .await
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("like .#host"));
+    }
+}
@@ -555,7 +555,7 @@ fn sources_from_status(
     status
         .services
         .iter()
-        .filter(|(service, _)| service_selected(target, project, service))
+        .filter(|(service, _)| target.selects_service(project, service))
         .filter(|(service, _)| {
             project_config
                 .services
@@ -563,13 +563,15 @@ fn sources_from_status(
                 .map(|service| service.healthcheck)
                 .unwrap_or(false)
         })
-        .map(|(service_name, service)| {
-            HealthLogSource::new(
-                project,
-                service_name.clone(),
-                service.id.clone(),
-                service.container_name.clone(),
-            )
+        .flat_map(|(service_name, replicas)| {
+            replicas.iter().map(|service| {
+                HealthLogSource::new(
+                    project,
+                    service_name.clone(),
+                    service.id.clone(),
+                    service.container_name.clone(),
+                )
+            })
         })
         .collect()
 }
@@ -590,20 +592,6 @@ async fn inspect_health_log_entries(
     }
 }
 
-fn service_selected(
-    target: &TargetSelector,
-    project: &str,
-    service: &str,
-) -> bool {
-    match target {
-        TargetSelector::All => true,
-        TargetSelector::Project(sel) => sel.name == project,
-        TargetSelector::Service(sel) => {
-            sel.project == project && sel.service == service
-        }
-    }
-}
-
 fn parse_health_log_entry(
     entry: DockerHealthLogEntry
 ) -> anyhow::Result<HealthLogEntry> {
@@ -667,11 +655,7 @@ mod tests {
     use futures::StreamExt;
     use nirion_oci_lib::client::NirionOciClient;
     use std::{
-        fs,
-        io::Write,
-        os::unix::fs::PermissionsExt,
-        path::{Path, PathBuf},
-        sync::Arc,
+        fs, io::Write, os::unix::fs::PermissionsExt, path::Path, sync::Arc,
         time::Duration,
     };
 
@@ -704,7 +688,7 @@ mod tests {
         NirionContext {
             projects,
             locked_images: LockedImages::default(),
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -0,0 +1,334 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    projects::{Projects, TargetSelector},
+    state::{append_jsonl, current_user, read_jsonl, truncate_jsonl},
+};
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// Entries kept in the history file; recording a new one drops the
+/// oldest beyond this.
+pub const HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// RFC 3339, in UTC.
+    pub timestamp: String,
+    pub user: String,
+    pub command: String,
+    pub target: String,
+    /// The affected services as `project.service`, with the digest of
+    /// the image they were deployed with; `None` for services without a
+    /// pinned image.
+    pub services: BTreeMap<String, Option<String>>,
+    pub outcome: HistoryOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// An entry for `command` run against `target` just now. `error` is
+    /// the reason it failed, if it did.
+    pub fn now(
+        command: &str,
+        target: &TargetSelector,
+        projects: &Projects,
+        error: Option<String>,
+    ) -> Self {
+        let services = projects
+            .iter()
+            .flat_map(|(project_name, project)| {
+                project
+                    .services
+                    .iter()
+                    .filter(move |(service_name, _)| {
+                        target.selects_service(project_name, service_name)
+                    })
+                    .map(move |(service_name, service)| {
+                        let digest = service
+                            .resolved_image
+                            .as_deref()
+                            .and_then(|image| image.split_once('@'))
+                            .map(|(_, digest)| digest.to_string());
+                        (format!("{project_name}.{service_name}"), digest)
+                    })
+            })
+            .collect();
+
+        Self {
+            timestamp: Utc::now().to_rfc3339(),
+            user: current_user(),
+            command: command.to_string(),
+            target: target.to_string(),
+            services,
+            outcome: match error {
+                Some(_) => HistoryOutcome::Failure,
+                None => HistoryOutcome::Success,
+            },
+            error,
+        }
+    }
+
+    fn time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|time| time.with_timezone(&Utc))
+    }
+}
+
+pub fn history_file(state_dir: &Path) -> PathBuf {
+    state_dir.join(HISTORY_FILE)
+}
+
+/// Appends `entry` and drops entries beyond [`HISTORY_LIMIT`].
+pub fn record_history(
+    path: &Path,
+    entry: &HistoryEntry,
+) -> anyhow::Result<()> {
+    append_jsonl(path, entry)?;
+    truncate_jsonl(path, HISTORY_LIMIT)
+}
+
+/// A point in time for `--since` and `--until`: an RFC 3339 timestamp or
+/// a `YYYY-MM-DD` date, which means midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryTime(pub DateTime<Utc>);
+
+impl FromStr for HistoryTime {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Self(time.with_timezone(&Utc)));
+        }
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(|date| {
+                Self(
+                    date.and_time(Default::default())
+                        .and_utc(),
+                )
+            })
+            .map_err(|_| {
+                format!(
+                    "expected an RFC 3339 timestamp or a YYYY-MM-DD date, \
+                     got {value}"
+                )
+            })
+    }
+}
+
+/// Which entries `nirion history` shows.
+#[derive(Debug, Clone)]
+pub struct HistoryFilter {
+    pub target: TargetSelector,
+    pub since: Option<HistoryTime>,
+    pub until: Option<HistoryTime>,
+}
+
+impl HistoryFilter {
+    /// `entry` with only the services the target selects, or `None` if
+    /// it affected none of them or falls outside the time range.
+    pub fn apply(
+        &self,
+        mut entry: HistoryEntry,
+    ) -> Option<HistoryEntry> {
+        if self.since.is_some() || self.until.is_some() {
+            let time = entry.time()?;
+            if self
+                .since
+                .is_some_and(|since| time < since.0)
+                || self
+                    .until
+                    .is_some_and(|until| time > until.0)
+            {
+                return None;
+            }
+        }
+
+        entry.services.retain(|service, _| {
+            service
+                .split_once('.')
+                .is_some_and(|(project, service)| {
+                    self.target
+                        .selects_service(project, service)
+                })
+        });
+        (!entry.services.is_empty()).then_some(entry)
+    }
+}
+
+/// The entries matching `filter`, oldest first.
+pub fn read_history(
+    path: &Path,
+    filter: &HistoryFilter,
+) -> anyhow::Result<Vec<HistoryEntry>> {
+    Ok(read_jsonl::<HistoryEntry>(path)?
+        .into_iter()
+        .filter_map(|entry| filter.apply(entry))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::{ProjectSelector, ServiceSelector};
+
+    fn projects() -> Projects {
+        serde_json::from_value(serde_json::json!({
+            "cloud": {
+                "name": "cloud",
+                "dockerCompose": "cloud.yml",
+                "services": {
+                    "app": {
+                        "image": "nextcloud:29",
+                        "resolvedImage": "nextcloud:29@sha256:aaa",
+                        "restart": "always"
+                    },
+                    "cron": {
+                        "image": null,
+                        "resolvedImage": null,
+                        "restart": "always"
+                    }
+                }
+            },
+            "web": {
+                "name": "web",
+                "dockerCompose": "web.yml",
+                "services": {
+                    "nginx": {
+                        "image": "nginx",
+                        "resolvedImage": "nginx@sha256:bbb",
+                        "restart": "always"
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    fn entry_at(
+        timestamp: &str,
+        target: &TargetSelector,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: timestamp.to_string(),
+            ..HistoryEntry::now("restart", target, &projects(), None)
+        }
+    }
+
+    fn cloud() -> TargetSelector {
+        TargetSelector::Project(ProjectSelector {
+            name: "cloud".into(),
+        })
+    }
+
+    #[test]
+    fn entries_record_the_digest_of_each_selected_service() {
+        let entry = HistoryEntry::now(
+            "restart",
+            &cloud(),
+            &projects(),
+            Some("boom".into()),
+        );
+
+        assert_eq!(entry.target, "cloud");
+        assert_eq!(
+            entry.services,
+            BTreeMap::from([
+                ("cloud.app".to_string(), Some("sha256:aaa".to_string())),
+                ("cloud.cron".to_string(), None),
+            ])
+        );
+        assert_eq!(entry.outcome, HistoryOutcome::Failure);
+        assert_eq!(entry.error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn history_is_filtered_by_target_and_time_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = history_file(dir.path());
+        for entry in [
+            entry_at("2026-03-01T10:00:00+00:00", &TargetSelector::All),
+            entry_at("2026-03-02T10:00:00+00:00", &cloud()),
+            entry_at(
+                "2026-03-03T10:00:00+00:00",
+                &TargetSelector::Service(ServiceSelector {
+                    project: "web".into(),
+                    service: "nginx".into(),
+                }),
+            ),
+        ] {
+            record_history(&path, &entry).unwrap();
+        }
+
+        let app = HistoryFilter {
+            target: TargetSelector::Service(ServiceSelector {
+                project: "cloud".into(),
+                service: "app".into(),
+            }),
+            since: None,
+            until: None,
+        };
+        let entries = read_history(&path, &app).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.target.as_str())
+                .collect::<Vec<_>>(),
+            ["*", "cloud"]
+        );
+        assert_eq!(
+            entries[0]
+                .services
+                .keys()
+                .collect::<Vec<_>>(),
+            ["cloud.app"]
+        );
+
+        let since = HistoryFilter {
+            target: TargetSelector::All,
+            since: Some("2026-03-02".parse().unwrap()),
+            until: Some("2026-03-02T23:00:00Z".parse().unwrap()),
+        };
+        assert_eq!(
+            read_history(&path, &since)
+                .unwrap()
+                .iter()
+                .map(|entry| entry.target.as_str())
+                .collect::<Vec<_>>(),
+            ["cloud"]
+        );
+    }
+
+    #[test]
+    fn history_times_accept_timestamps_and_dates() {
+        assert_eq!(
+            "2026-03-02"
+                .parse::<HistoryTime>()
+                .unwrap(),
+            "2026-03-02T00:00:00Z"
+                .parse::<HistoryTime>()
+                .unwrap()
+        );
+        assert!(
+            "yesterday"
+                .parse::<HistoryTime>()
+                .is_err()
+        );
+    }
+}
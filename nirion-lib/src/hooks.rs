@@ -0,0 +1,258 @@
+use std::{fmt::Display, process::Stdio};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+
+use crate::{events::ProcessEvent, projects::Project};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectHooks {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_up: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_up: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_down: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_down: Vec<Hook>,
+}
+
+impl ProjectHooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_up.is_empty()
+            && self.post_up.is_empty()
+            && self.pre_down.is_empty()
+            && self.post_down.is_empty()
+    }
+
+    pub fn get(
+        &self,
+        phase: HookPhase,
+    ) -> &[Hook] {
+        match phase {
+            HookPhase::PreUp => &self.pre_up,
+            HookPhase::PostUp => &self.post_up,
+            HookPhase::PreDown => &self.pre_down,
+            HookPhase::PostDown => &self.post_down,
+        }
+    }
+}
+
+/// A shell command, written either as a plain string or as
+/// `{ "command": ..., "ignoreFailure": true }`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "HookEntry")]
+pub struct Hook {
+    pub command: String,
+    /// Carry on with the operation if the command fails.
+    #[serde(rename = "ignoreFailure", default)]
+    pub ignore_failure: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HookEntry {
+    Command(String),
+    Hook {
+        command: String,
+        #[serde(rename = "ignoreFailure", default)]
+        ignore_failure: bool,
+    },
+}
+
+impl From<HookEntry> for Hook {
+    fn from(entry: HookEntry) -> Self {
+        match entry {
+            HookEntry::Command(command) => Hook {
+                command,
+                ignore_failure: false,
+            },
+            HookEntry::Hook {
+                command,
+                ignore_failure,
+            } => Hook {
+                command,
+                ignore_failure,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookPhase {
+    PreUp,
+    PostUp,
+    PreDown,
+    PostDown,
+}
+
+impl HookPhase {
+    /// The name the hook list has in the project file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookPhase::PreUp => "preUp",
+            HookPhase::PostUp => "postUp",
+            HookPhase::PreDown => "preDown",
+            HookPhase::PostDown => "postDown",
+        }
+    }
+}
+
+impl Display for HookPhase {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Runs `hook` with `sh -c` in the directory of the project's compose
+/// file, passing each line it prints to `on_output` as it comes. The
+/// command sees `NIRION_PROJECT` and `NIRION_COMPOSE_FILE`.
+pub async fn run_hook(
+    project: &Project,
+    hook: &Hook,
+    mut on_output: impl FnMut(ProcessEvent),
+) -> anyhow::Result<()> {
+    // Hooks run elsewhere, so a relative compose file is resolved first.
+    let compose_file = std::path::absolute(&project.docker_compose)?;
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&hook.command)
+        .env("NIRION_PROJECT", &project.name.0)
+        .env("NIRION_COMPOSE_FILE", &compose_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = compose_file.parent() {
+        command.current_dir(dir);
+    }
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("failed to run `{}`", hook.command))?;
+    let mut stdout = BufReader::new(
+        child
+            .stdout
+            .take()
+            .context("failed to capture stdout")?,
+    )
+    .lines();
+    let mut stderr = BufReader::new(
+        child
+            .stderr
+            .take()
+            .context("failed to capture stderr")?,
+    )
+    .lines();
+
+    let (mut stdout_open, mut stderr_open) = (true, true);
+    while stdout_open || stderr_open {
+        tokio::select! {
+            line = stdout.next_line(), if stdout_open => match line? {
+                Some(line) => on_output(ProcessEvent::StdoutLine(line)),
+                None => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line? {
+                Some(line) => on_output(ProcessEvent::StderrLine(line)),
+                None => stderr_open = false,
+            },
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        anyhow::bail!("`{}` failed with {status}", hook.command);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::ProjectName;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn hooks_are_strings_or_objects() {
+        let hooks: ProjectHooks = serde_json::from_str(
+            r#"{
+                "preDown": ["pg_dump app > dump.sql"],
+                "postUp": [{"command": "curl localhost/warm", "ignoreFailure": true}]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            hooks.get(HookPhase::PreDown),
+            [Hook {
+                command: "pg_dump app > dump.sql".to_string(),
+                ignore_failure: false,
+            }]
+        );
+        assert_eq!(
+            hooks.get(HookPhase::PostUp),
+            [Hook {
+                command: "curl localhost/warm".to_string(),
+                ignore_failure: true,
+            }]
+        );
+        assert!(hooks.get(HookPhase::PreUp).is_empty());
+    }
+
+    #[tokio::test]
+    async fn run_hook_runs_next_to_the_compose_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = Project {
+            name: ProjectName("myapp".into()),
+            docker_compose: dir.path().join("compose.yml"),
+            services: BTreeMap::new(),
+            profiles: Vec::new(),
+            hooks: ProjectHooks::default(),
+        };
+        let hook = |command: &str| Hook {
+            command: command.to_string(),
+            ignore_failure: false,
+        };
+
+        let mut lines = Vec::new();
+        run_hook(
+            &project,
+            &hook("pwd; echo \"$NIRION_PROJECT $NIRION_COMPOSE_FILE\" >&2"),
+            |event| match event {
+                ProcessEvent::StdoutLine(line)
+                | ProcessEvent::StderrLine(line) => lines.push(line),
+                ProcessEvent::Exited(_) => {}
+            },
+        )
+        .await
+        .unwrap();
+
+        let dir = dir.path().canonicalize().unwrap();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                dir.display().to_string(),
+                format!("myapp {}", dir.join("compose.yml").display()),
+            ]
+        );
+
+        let error = run_hook(&project, &hook("exit 3"), |_| {})
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("`exit 3` failed"),
+            "{error}"
+        );
+    }
+}
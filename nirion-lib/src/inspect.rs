@@ -7,40 +7,59 @@ use crate::{
     projects::{ProjectSelector, ServiceSelector},
 };
 
+/// Inspect outputs of several services, labelled `project.service`.
+/// Services that could not be inspected are kept apart so the outputs
+/// can be printed before the failures are reported.
+#[derive(Debug, Default)]
+pub struct InspectResults {
+    pub outputs: Vec<(String, String)>,
+    pub failures: Vec<String>,
+}
+
+impl InspectResults {
+    pub fn extend(
+        &mut self,
+        other: InspectResults,
+    ) {
+        self.outputs.extend(other.outputs);
+        self.failures.extend(other.failures);
+    }
+
+    /// `Err` naming every service that failed.
+    pub fn check(&self) -> Result<()> {
+        if !self.failures.is_empty() {
+            anyhow::bail!(
+                "failed to inspect {} service(s): {}",
+                self.failures.len(),
+                self.failures.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
 pub async fn inspect_project_images(
     context: &NirionContext,
     target: &ProjectSelector,
     format: &str,
     raw: bool,
-) -> anyhow::Result<Vec<String>> {
-    let mut failures = Vec::new();
-    let mut outputs = Vec::new();
+) -> InspectResults {
+    let mut results = InspectResults::default();
 
-    for service in context.projects[&target.name]
-        .services
-        .keys()
-    {
-        let service_selector = ServiceSelector {
-            project: target.name.to_string(),
-            service: service.to_string(),
-        };
+    for service_selector in project_services(context, target) {
+        let label = format!(
+            "{}.{}",
+            service_selector.project, service_selector.service
+        );
         match inspect_image(context, &service_selector, format, raw).await {
-            Ok(output) => outputs.push(output),
-            Err(e) => {
-                failures.push(format!("{}.{}: {}", target.name, service, e))
-            }
+            Ok(output) => results.outputs.push((label, output)),
+            Err(e) => results
+                .failures
+                .push(format!("{label}: {e}")),
         }
     }
 
-    if !failures.is_empty() {
-        anyhow::bail!(
-            "failed to inspect {} service(s): {}",
-            failures.len(),
-            failures.join("; ")
-        );
-    }
-
-    Ok(outputs)
+    results
 }
 
 pub async fn inspect_project_containers(
@@ -48,35 +67,37 @@ pub async fn inspect_project_containers(
     target: &ProjectSelector,
     format: &str,
     raw: bool,
-) -> anyhow::Result<Vec<String>> {
-    let mut failures = Vec::new();
-    let mut outputs = Vec::new();
+) -> InspectResults {
+    let mut results = InspectResults::default();
 
-    for service in context.projects[&target.name]
-        .services
-        .keys()
-    {
-        let service_selector = ServiceSelector {
-            project: target.name.to_string(),
-            service: service.to_string(),
-        };
+    for service_selector in project_services(context, target) {
+        let label = format!(
+            "{}.{}",
+            service_selector.project, service_selector.service
+        );
         match inspect_container(context, &service_selector, format, raw).await {
-            Ok(output) => outputs.push(output),
-            Err(e) => {
-                failures.push(format!("{}.{}: {}", target.name, service, e))
-            }
+            Ok(output) => results.outputs.push((label, output)),
+            Err(e) => results
+                .failures
+                .push(format!("{label}: {e}")),
         }
     }
 
-    if !failures.is_empty() {
-        anyhow::bail!(
-            "failed to inspect {} service(s): {}",
-            failures.len(),
-            failures.join("; ")
-        );
-    }
+    results
+}
 
-    Ok(outputs)
+fn project_services(
+    context: &NirionContext,
+    target: &ProjectSelector,
+) -> Vec<ServiceSelector> {
+    context.projects[&target.name]
+        .services
+        .keys()
+        .map(|service| ServiceSelector {
+            project: target.name.to_string(),
+            service: service.to_string(),
+        })
+        .collect()
 }
 
 pub async fn inspect_image(
@@ -141,8 +162,7 @@ pub async fn inspect_container(
     let project_status = query_project_status(context, &target.project).await?;
 
     let service_status = project_status
-        .services
-        .get(&target.service)
+        .replica(&target.service, None)
         .ok_or_else(|| {
             anyhow::anyhow!("Service {} missing from status", &target.service)
         })?;
@@ -177,6 +197,48 @@ pub async fn inspect_container(
     }
 }
 
+/// Looks up `path` in the JSON `output` of an inspect. `path` is either a
+/// JSON pointer (`/Config/Image`) or a dotted path (`Config.Image`,
+/// `Mounts.0.Source`). The array `docker inspect` wraps a single result in
+/// is looked through when the path doesn't match it.
+pub fn extract_path(
+    output: &str,
+    path: &str,
+) -> Result<Value> {
+    let document = serde_json::from_str::<Value>(output)
+        .context("inspect output is not JSON")?;
+    let pointer = json_pointer(path);
+
+    document
+        .pointer(&pointer)
+        .or_else(|| match &document {
+            Value::Array(items) if items.len() == 1 => {
+                items[0].pointer(&pointer)
+            }
+            _ => None,
+        })
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("path {path} not found"))
+}
+
+fn json_pointer(path: &str) -> String {
+    if path.starts_with('/') {
+        return path.to_string();
+    }
+
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            format!(
+                "/{}",
+                segment
+                    .replace('~', "~0")
+                    .replace('/', "~1")
+            )
+        })
+        .collect()
+}
+
 fn pretty_json(string: &str) -> String {
     fn inner(string: &str) -> anyhow::Result<String> {
         let json = serde_json::from_str::<Value>(string)?;
@@ -200,11 +262,7 @@ mod tests {
     };
     use nirion_oci_lib::client::NirionOciClient;
     use std::{
-        fs,
-        io::Write,
-        os::unix::fs::PermissionsExt,
-        path::{Path, PathBuf},
-        sync::Arc,
+        fs, io::Write, os::unix::fs::PermissionsExt, path::Path, sync::Arc,
     };
 
     fn projects() -> Projects {
@@ -246,7 +304,7 @@ mod tests {
         NirionContext {
             projects,
             locked_images,
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -489,6 +547,8 @@ exit {inspect_exit_code}
                 image: "nginx:latest".into(),
                 version: Some("1.28.0".into()),
                 digest: "sha256:current-lock".into(),
+                size: None,
+                source: None,
             },
         );
         let projects: Projects = serde_json::from_value(serde_json::json!({
@@ -719,7 +779,7 @@ exit {inspect_exit_code}
         }))
         .unwrap();
 
-        let outputs = inspect_project_images(
+        let results = inspect_project_images(
             &context(
                 fake_docker_command(&docker),
                 projects,
@@ -731,11 +791,15 @@ exit {inspect_exit_code}
             "{{json .}}",
             true,
         )
-        .await
-        .unwrap();
+        .await;
 
-        assert_eq!(outputs.len(), 1);
-        assert_json_eq(&outputs[0], serde_json::json!({ "ok": true }));
+        results.check().unwrap();
+        assert_eq!(results.outputs.len(), 1);
+        assert_eq!(results.outputs[0].0, "myapp.web");
+        assert_json_eq(
+            &results.outputs[0].1,
+            serde_json::json!({ "ok": true }),
+        );
     }
 
     #[tokio::test]
@@ -744,7 +808,7 @@ exit {inspect_exit_code}
         let args_file = dir.path().join("args");
         let docker = write_fake_docker(dir.path(), &args_file, "{}", "", 0);
 
-        let err = inspect_project_images(
+        let results = inspect_project_images(
             &context(
                 fake_docker_command(&docker),
                 projects(),
@@ -756,13 +820,40 @@ exit {inspect_exit_code}
             "{{json .}}",
             true,
         )
-        .await
-        .unwrap_err();
+        .await;
 
-        let err = err.to_string();
+        assert_eq!(results.outputs.len(), 1);
+        assert_eq!(results.outputs[0].0, "myapp.web");
+        let err = results.check().unwrap_err().to_string();
         assert!(err.contains("failed to inspect 1 service(s)"));
         assert!(
             err.contains("myapp.worker: Image missing from service worker")
         );
     }
+
+    #[test]
+    fn extract_path_accepts_pointers_and_dotted_paths() {
+        let output =
+            r#"[{"Config":{"Image":"nginx"},"Mounts":[{"Source":"/srv"}]}]"#;
+
+        assert_eq!(
+            extract_path(output, "Config.Image").unwrap(),
+            serde_json::json!("nginx")
+        );
+        assert_eq!(
+            extract_path(output, "/0/Mounts/0/Source").unwrap(),
+            serde_json::json!("/srv")
+        );
+        assert_eq!(
+            extract_path(output, "Mounts.0").unwrap(),
+            serde_json::json!({ "Source": "/srv" })
+        );
+        assert_eq!(
+            extract_path(output, "Config.Missing")
+                .unwrap_err()
+                .to_string(),
+            "path Config.Missing not found"
+        );
+        assert!(extract_path("not json", "Config").is_err());
+    }
 }
@@ -0,0 +1,266 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use nirion_oci_lib::{auth::RegistryAuth, client::AuthConfig};
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+use {
+    anyhow::Context,
+    nirion_oci_lib::client::normalize_scope,
+    serde::{Deserialize, Serialize},
+    std::{
+        io::ErrorKind,
+        process::{Output, Stdio},
+    },
+    tokio::{io::AsyncWriteExt, process::Command},
+};
+
+/// The `service` attribute of every entry nirion stores.
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+const SERVICE: &str = "nirion";
+
+/// Registry credentials in the desktop keyring, reached through
+/// libsecret's `secret-tool`, so Linux only. Without the `keyring`
+/// feature or elsewhere, reading it finds nothing and changing it fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keyring {
+    pub program: PathBuf,
+}
+
+/// The secret of an entry. It names its scope itself, so listing the
+/// entries only needs the secrets `secret-tool search` prints.
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+#[derive(Serialize, Deserialize)]
+struct StoredAuth {
+    registry: String,
+    auth: RegistryAuth,
+}
+
+impl Keyring {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    /// The error for a keyring that can't be reached at all.
+    pub fn unavailable(&self) -> anyhow::Error {
+        if !cfg!(feature = "keyring") {
+            anyhow::anyhow!(
+                "this nirion was built without the `keyring` feature, so \
+                 it can't use the keyring"
+            )
+        } else if !cfg!(target_os = "linux") {
+            anyhow::anyhow!(
+                "the keyring is only supported on Linux, through \
+                 libsecret's secret-tool; use an auth file on this platform"
+            )
+        } else {
+            anyhow::anyhow!(
+                "{} not found; install libsecret's secret-tool to use the \
+                 keyring",
+                self.program.display()
+            )
+        }
+    }
+}
+
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+impl Keyring {
+    /// Stores `auth` for `scope`, replacing what was stored for it.
+    pub async fn store(
+        &self,
+        scope: &str,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<()> {
+        let scope = normalize_scope(scope);
+        let secret = serde_json::to_string(&StoredAuth {
+            registry: scope.clone(),
+            auth: auth.clone(),
+        })?;
+
+        let mut child = Command::new(&self.program)
+            .arg("store")
+            .arg(format!("--label=nirion: {scope}"))
+            .args(["service", SERVICE, "registry", &scope])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| self.run_error(error))?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .context("secret-tool has no stdin")?;
+        stdin
+            .write_all(secret.as_bytes())
+            .await?;
+        drop(stdin);
+
+        self.check(&child.wait_with_output().await?)
+    }
+
+    /// Every stored entry by scope; `None` if `secret-tool` isn't
+    /// installed, which means there is no keyring to read.
+    pub async fn entries(
+        &self
+    ) -> anyhow::Result<Option<BTreeMap<String, RegistryAuth>>> {
+        let output = Command::new(&self.program)
+            .args(["search", "--all", "--unlock", "service", SERVICE])
+            .stdin(Stdio::null())
+            .output()
+            .await;
+        let output = match output {
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return Ok(None);
+            }
+            output => output.map_err(|error| self.run_error(error))?,
+        };
+
+        // Finding nothing is a failure without a message.
+        if !output.status.success() && !output.stderr.trim_ascii().is_empty() {
+            self.check(&output)?;
+        }
+
+        Ok(Some(parse_entries(&String::from_utf8_lossy(
+            &output.stdout,
+        ))))
+    }
+
+    pub async fn remove(
+        &self,
+        scope: &str,
+    ) -> anyhow::Result<()> {
+        let output = Command::new(&self.program)
+            .args(["clear", "service", SERVICE, "registry"])
+            .arg(normalize_scope(scope))
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|error| self.run_error(error))?;
+
+        self.check(&output)
+    }
+
+    fn run_error(
+        &self,
+        error: std::io::Error,
+    ) -> anyhow::Error {
+        if error.kind() == ErrorKind::NotFound {
+            return self.unavailable();
+        }
+        anyhow::Error::new(error)
+            .context(format!("failed to run {}", self.program.display()))
+    }
+
+    fn check(
+        &self,
+        output: &Output,
+    ) -> anyhow::Result<()> {
+        if output.status.success() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "{} failed with {}: {}",
+            self.program.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+}
+
+#[cfg(not(all(feature = "keyring", target_os = "linux")))]
+impl Keyring {
+    pub async fn store(
+        &self,
+        _scope: &str,
+        _auth: &RegistryAuth,
+    ) -> anyhow::Result<()> {
+        Err(self.unavailable())
+    }
+
+    /// Always `None`: there is no keyring to read.
+    pub async fn entries(
+        &self
+    ) -> anyhow::Result<Option<BTreeMap<String, RegistryAuth>>> {
+        Ok(None)
+    }
+
+    pub async fn remove(
+        &self,
+        _scope: &str,
+    ) -> anyhow::Result<()> {
+        Err(self.unavailable())
+    }
+}
+
+impl Default for Keyring {
+    /// `secret-tool` from `PATH`.
+    fn default() -> Self {
+        Self::new("secret-tool")
+    }
+}
+
+/// Adds the keyring `entries` for scopes `auth` has no credentials for,
+/// so the auth file wins over the keyring.
+pub fn merge_keyring_entries(
+    auth: &mut AuthConfig,
+    entries: BTreeMap<String, RegistryAuth>,
+) {
+    for (scope, entry) in entries {
+        auth.sources
+            .entry(scope)
+            .or_insert(entry);
+    }
+}
+
+#[cfg(all(feature = "keyring", target_os = "linux"))]
+fn parse_entries(stdout: &str) -> BTreeMap<String, RegistryAuth> {
+    stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("secret = "))
+        .filter_map(|secret| serde_json::from_str::<StoredAuth>(secret).ok())
+        .map(|stored| (stored.registry, stored.auth))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "keyring", target_os = "linux"))]
+    #[test]
+    fn parse_entries_reads_nirion_secrets_only() {
+        let stdout = r#"[/org/freedesktop/secrets/collection/login/1]
+label = nirion: ghcr.io
+secret = {"registry":"ghcr.io","auth":{"type":"basic","username":"me","password":"pw"}}
+created = 2026-10-01 10:00:00
+[/org/freedesktop/secrets/collection/login/2]
+label = something else
+secret = hunter2
+"#;
+
+        assert_eq!(
+            parse_entries(stdout),
+            BTreeMap::from([(
+                "ghcr.io".to_string(),
+                RegistryAuth::basic("me", "pw")
+            )])
+        );
+    }
+
+    #[test]
+    fn auth_file_entries_win_over_the_keyring() {
+        let mut auth = AuthConfig::default();
+        auth.add_auth("ghcr.io".to_string(), RegistryAuth::bearer("file"));
+
+        merge_keyring_entries(
+            &mut auth,
+            BTreeMap::from([
+                ("ghcr.io".to_string(), RegistryAuth::bearer("keyring")),
+                ("quay.io".to_string(), RegistryAuth::bearer("keyring")),
+            ]),
+        );
+
+        assert_eq!(auth.sources["ghcr.io"], RegistryAuth::bearer("file"));
+        assert_eq!(auth.sources["quay.io"], RegistryAuth::bearer("keyring"));
+    }
+}
@@ -1,14 +1,40 @@
+pub mod adopt;
 pub mod compose;
 pub mod compose_file;
 pub mod config;
 pub mod context;
+pub mod daemon;
 pub mod docker;
+pub mod downgrade;
+pub mod drift;
+pub mod env;
 pub mod events;
 pub mod exec;
+pub mod exec_history;
+pub mod git;
 pub mod health;
+pub mod history;
+pub mod hooks;
 pub mod inspect;
+pub mod keyring;
+pub mod lint;
 pub mod lock;
+pub mod lock_store;
 pub mod lock_update;
 pub mod logs;
+pub mod maintenance;
+pub mod monitor;
+pub mod paths;
 pub mod projects;
+pub mod pull_progress;
+pub mod registries;
+pub mod resolve_failure;
+pub mod state;
+pub mod stats;
+pub mod status_cache;
+pub mod textfile;
+pub mod update_check;
 pub mod wait;
+
+pub use docker::{ProjectState, ProjectStatus, ServiceState, ServiceStatus};
+pub use monitor::{DockerMonitor, DockerProjectMonitor, MonitorState};
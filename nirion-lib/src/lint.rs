@@ -0,0 +1,638 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_yaml_ng::Value;
+
+use crate::projects::{
+    Project, Projects, Service, TargetSelector, selected_project_names,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// What a finding is about. Each has a stable code and a name, either
+/// of which suppresses it through a service's `lintIgnore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(into = "&'static str")]
+pub enum LintCode {
+    /// An image without a tag or tagged `latest`; its lock entry moves
+    /// with every push.
+    UnpinnedImage,
+    /// A service built from a `build:` section, which no registry has
+    /// an image for.
+    LocalBuild,
+    /// The same `container_name` in two services, so docker refuses to
+    /// create the second.
+    ContainerNameCollision,
+    /// The project file waits for a healthcheck the compose file doesn't
+    /// define.
+    MissingHealthcheck,
+    /// A one-shot service compose restarts whenever it exits.
+    RestartingOneShot,
+}
+
+impl LintCode {
+    pub fn code(self) -> &'static str {
+        match self {
+            LintCode::UnpinnedImage => "NL001",
+            LintCode::LocalBuild => "NL002",
+            LintCode::ContainerNameCollision => "NL003",
+            LintCode::MissingHealthcheck => "NL004",
+            LintCode::RestartingOneShot => "NL005",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LintCode::UnpinnedImage => "unpinned-image",
+            LintCode::LocalBuild => "local-build",
+            LintCode::ContainerNameCollision => "container-name-collision",
+            LintCode::MissingHealthcheck => "missing-healthcheck",
+            LintCode::RestartingOneShot => "restarting-one-shot",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            LintCode::ContainerNameCollision => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+
+    /// Whether `service` suppresses this finding.
+    fn ignored_by(
+        self,
+        service: &Service,
+    ) -> bool {
+        service
+            .lint_ignore
+            .iter()
+            .any(|ignored| {
+                ignored.eq_ignore_ascii_case(self.code())
+                    || ignored == self.name()
+            })
+    }
+}
+
+impl From<LintCode> for &'static str {
+    fn from(code: LintCode) -> Self {
+        code.code()
+    }
+}
+
+/// Where in a compose file a finding points.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub file: PathBuf,
+    /// 1-based; `None` if the key couldn't be found in the file's text.
+    pub line: Option<usize>,
+    /// The key the finding is about, like `services.web.image`.
+    pub key: String,
+}
+
+impl Display for Location {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        write!(f, " ({})", self.key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub code: LintCode,
+    pub severity: Severity,
+    pub project: String,
+    pub service: String,
+    pub message: String,
+    pub location: Location,
+}
+
+/// The findings for the services `target` selects, ordered by project,
+/// service and code. Container names are compared across every project,
+/// so a collision with an unselected project is still found.
+pub fn lint_projects(
+    projects: &Projects,
+    target: &TargetSelector,
+) -> anyhow::Result<Vec<Finding>> {
+    let selected = selected_project_names(target, projects);
+    let mut composes = BTreeMap::new();
+    for (name, project) in projects.iter() {
+        match read_compose(project) {
+            Ok(compose) => {
+                composes.insert(name, compose);
+            }
+            // Other projects only matter for their container names.
+            Err(_)
+                if !selected
+                    .iter()
+                    .any(|selected| selected == name) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut findings = Vec::new();
+    for name in selected {
+        let Some((text, compose)) = composes.get(name.as_str()) else {
+            continue;
+        };
+        let lint = ProjectLint {
+            name: &name,
+            project: &projects[&name],
+            text,
+            compose,
+        };
+        findings.extend(lint.findings());
+    }
+    findings.extend(container_name_collisions(projects, &composes));
+
+    findings.retain(|finding| {
+        target.selects_service(&finding.project, &finding.service)
+            && !projects[&finding.project]
+                .services
+                .get(&finding.service)
+                .is_some_and(|service| finding.code.ignored_by(service))
+    });
+    findings.sort_by(|a, b| {
+        (&a.project, &a.service, a.code).cmp(&(&b.project, &b.service, b.code))
+    });
+    Ok(findings)
+}
+
+/// The compose file's text, for locating findings, and its parsed YAML.
+fn read_compose(project: &Project) -> anyhow::Result<(String, Value)> {
+    let file = &project.docker_compose;
+    let text = fs::read_to_string(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let compose = serde_yaml_ng::from_str::<Value>(&text)
+        .with_context(|| format!("failed to parse {}", file.display()))?;
+    Ok((text, compose))
+}
+
+struct ProjectLint<'a> {
+    name: &'a str,
+    project: &'a Project,
+    text: &'a str,
+    compose: &'a Value,
+}
+
+impl ProjectLint<'_> {
+    fn findings(&self) -> Vec<Finding> {
+        let completed = completed_dependencies(self.compose);
+        let mut findings = Vec::new();
+        for (service, definition) in compose_services(self.compose) {
+            let claimed = self.project.services.get(service);
+
+            if definition.get("build").is_some() {
+                // Marked in the project file, it is already kept out of
+                // the lock file.
+                if !claimed.is_some_and(|service| service.build) {
+                    findings.push(self.finding(
+                        LintCode::LocalBuild,
+                        service,
+                        "build",
+                        "the image is built locally, so it can't be locked \
+                         or updated from a registry; mark it with `build` \
+                         in the project file",
+                    ));
+                }
+            } else if let Some(image) = definition
+                .get("image")
+                .and_then(Value::as_str)
+                .filter(|image| is_unpinned(image))
+            {
+                findings.push(self.finding(
+                    LintCode::UnpinnedImage,
+                    service,
+                    "image",
+                    format!(
+                        "image `{image}` has no version tag; its lock entry \
+                         changes whenever `latest` is pushed"
+                    ),
+                ));
+            }
+
+            if claimed.is_some_and(|service| service.healthcheck)
+                && !defines_healthcheck(definition)
+            {
+                findings.push(self.finding(
+                    LintCode::MissingHealthcheck,
+                    service,
+                    "healthcheck",
+                    "the project file waits for its healthcheck, but the \
+                     compose file defines none; unless the image has one, it \
+                     never becomes healthy",
+                ));
+            }
+
+            let restart = definition
+                .get("restart")
+                .and_then(Value::as_str);
+            let one_shot = completed.contains_key(service)
+                || claimed.is_some_and(Service::is_one_shot);
+            if let Some(restart @ ("always" | "unless-stopped")) = restart
+                && one_shot
+            {
+                let why = match completed.get(service) {
+                    Some(dependent) => format!(
+                        "`{dependent}` waits for it to complete successfully"
+                    ),
+                    None => "the project file has no restart policy for it"
+                        .to_string(),
+                };
+                findings.push(self.finding(
+                    LintCode::RestartingOneShot,
+                    service,
+                    "restart",
+                    format!(
+                        "the service runs once, as {why}, but `restart: \
+                         {restart}` starts it again whenever it exits"
+                    ),
+                ));
+            }
+        }
+        findings
+    }
+
+    fn finding(
+        &self,
+        code: LintCode,
+        service: &str,
+        key: &str,
+        message: impl Into<String>,
+    ) -> Finding {
+        Finding {
+            code,
+            severity: code.severity(),
+            project: self.name.to_string(),
+            service: service.to_string(),
+            message: message.into(),
+            location: locate(
+                &self.project.docker_compose,
+                self.text,
+                service,
+                Some(key),
+            ),
+        }
+    }
+}
+
+/// Services sharing a `container_name`, each reported with the other
+/// services it collides with.
+fn container_name_collisions(
+    projects: &Projects,
+    composes: &BTreeMap<&str, (String, Value)>,
+) -> Vec<Finding> {
+    let mut by_name: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for (project, (_, compose)) in composes {
+        for (service, definition) in compose_services(compose) {
+            if let Some(name) = definition
+                .get("container_name")
+                .and_then(Value::as_str)
+            {
+                by_name
+                    .entry(name)
+                    .or_default()
+                    .push((*project, service));
+            }
+        }
+    }
+
+    let mut findings = Vec::new();
+    for (container_name, services) in by_name {
+        if services.len() < 2 {
+            continue;
+        }
+        for &(project, service) in &services {
+            let others = services
+                .iter()
+                .filter(|other| **other != (project, service))
+                .map(|(project, service)| format!("{project}.{service}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let compose_file = &projects[project].docker_compose;
+            findings.push(Finding {
+                code: LintCode::ContainerNameCollision,
+                severity: LintCode::ContainerNameCollision.severity(),
+                project: project.to_string(),
+                service: service.to_string(),
+                message: format!(
+                    "container name `{container_name}` is also used by \
+                     {others}; only one of them can be created"
+                ),
+                location: locate(
+                    compose_file,
+                    &composes[project].0,
+                    service,
+                    Some("container_name"),
+                ),
+            });
+        }
+    }
+    findings
+}
+
+fn compose_services(compose: &Value) -> impl Iterator<Item = (&str, &Value)> {
+    compose
+        .get("services")
+        .and_then(Value::as_mapping)
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, definition)| Some((name.as_str()?, definition)))
+}
+
+/// Services that another waits on with `service_completed_successfully`,
+/// with the name of one such dependent.
+fn completed_dependencies(compose: &Value) -> BTreeMap<String, String> {
+    let mut completed = BTreeMap::new();
+    for (dependent, definition) in compose_services(compose) {
+        let Some(depends_on) = definition
+            .get("depends_on")
+            .and_then(Value::as_mapping)
+        else {
+            continue;
+        };
+        for (dependency, condition) in depends_on {
+            let waits_for_completion = condition
+                .get("condition")
+                .and_then(Value::as_str)
+                == Some("service_completed_successfully");
+            if let (Some(dependency), true) =
+                (dependency.as_str(), waits_for_completion)
+            {
+                completed
+                    .entry(dependency.to_string())
+                    .or_insert_with(|| dependent.to_string());
+            }
+        }
+    }
+    completed
+}
+
+/// Images referenced without a digest and without a tag, or tagged
+/// `latest`. Interpolated references can't be judged and pass.
+fn is_unpinned(image: &str) -> bool {
+    if image.contains('@') || image.contains('$') {
+        return false;
+    }
+    let name = image
+        .rsplit('/')
+        .next()
+        .unwrap_or(image);
+    match name.split_once(':') {
+        Some((_, tag)) => tag == "latest",
+        None => true,
+    }
+}
+
+fn defines_healthcheck(definition: &Value) -> bool {
+    let Some(healthcheck) = definition.get("healthcheck") else {
+        return false;
+    };
+    let disabled = healthcheck
+        .get("disable")
+        .and_then(Value::as_bool)
+        .unwrap_or_default();
+    let test_none = healthcheck
+        .get("test")
+        .is_some_and(|test| match test {
+            Value::String(test) => test == "NONE",
+            Value::Sequence(test) => {
+                test.first().and_then(Value::as_str) == Some("NONE")
+            }
+            _ => false,
+        });
+    !disabled && !test_none
+}
+
+/// Finds `key` in `service`'s block of the compose file's text by
+/// indentation, as the parsed YAML keeps no positions. Falls back to the
+/// service's own line when the key is absent, and to no line at all.
+fn locate(
+    file: &Path,
+    text: &str,
+    service: &str,
+    key: Option<&str>,
+) -> Location {
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let is_key = |line: &str, key: &str| {
+        let trimmed = line.trim_start();
+        [key.to_string(), format!("\"{key}\""), format!("'{key}'")]
+            .iter()
+            .any(|quoted| {
+                trimmed
+                    .strip_prefix(quoted.as_str())
+                    .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+    };
+    let content = |line: &&str| {
+        let trimmed = line.trim_start();
+        !trimmed.is_empty() && !trimmed.starts_with('#')
+    };
+
+    let lines = text.lines().collect::<Vec<_>>();
+    let services = lines
+        .iter()
+        .position(|line| indent(line) == 0 && is_key(line, "services"));
+    let service_line = services.and_then(|start| {
+        lines
+            .iter()
+            .enumerate()
+            .skip(start + 1)
+            .filter(|(_, line)| content(line))
+            .take_while(|(_, line)| indent(line) > 0)
+            .find(|(_, line)| is_key(line, service))
+            .map(|(i, _)| i)
+    });
+    let key_line = service_line.and_then(|start| {
+        let service_indent = indent(lines[start]);
+        lines
+            .iter()
+            .enumerate()
+            .skip(start + 1)
+            .filter(|(_, line)| content(line))
+            .take_while(|(_, line)| indent(line) > service_indent)
+            .find(|(_, line)| key.is_some_and(|key| is_key(line, key)))
+            .map(|(i, _)| i)
+    });
+
+    Location {
+        file: file.to_path_buf(),
+        line: key_line.or(service_line).map(|i| i + 1),
+        key: match key {
+            Some(key) => format!("services.{service}.{key}"),
+            None => format!("services.{service}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::projects::ProjectNames;
+
+    fn projects(
+        dir: &Path,
+        composes: &[(&str, &str)],
+        services: serde_json::Value,
+    ) -> Projects {
+        let mut json = serde_json::Map::new();
+        for (name, compose) in composes {
+            let file = dir.join(format!("{name}.yml"));
+            fs::write(&file, compose).unwrap();
+            json.insert(
+                name.to_string(),
+                serde_json::json!({
+                    "name": name,
+                    "dockerCompose": file,
+                    "services": services[name],
+                }),
+            );
+        }
+        Projects::from_json(
+            &serde_json::Value::Object(json).to_string(),
+            ProjectNames::Reject,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn compose_files_are_checked_for_what_nirion_cannot_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let projects = projects(
+            dir.path(),
+            &[
+                (
+                    "app",
+                    "services:\n  \
+                       web:\n    \
+                         image: nginx\n    \
+                         container_name: proxy\n  \
+                       api:\n    \
+                         # built from the checkout\n    \
+                         build: .\n    \
+                         healthcheck:\n      \
+                           disable: true\n  \
+                       migrate:\n    \
+                         image: app/migrate:1.2\n    \
+                         restart: always\n  \
+                       worker:\n    \
+                         image: app/worker:latest\n    \
+                         restart: unless-stopped\n    \
+                         depends_on:\n      \
+                           migrate:\n        \
+                             condition: service_completed_successfully\n",
+                ),
+                (
+                    "edge",
+                    "services:\n  \
+                       proxy:\n    \
+                         image: traefik@sha256:abc\n    \
+                         container_name: proxy\n",
+                ),
+            ],
+            serde_json::json!({
+                "app": {
+                    "web": {"image": "nginx", "restart": "always"},
+                    "api": {"image": null, "restart": "always", "healthcheck": true},
+                    "migrate": {"image": "app/migrate:1.2", "restart": "always"},
+                    "worker": {
+                        "image": "app/worker:latest",
+                        "restart": "unless-stopped",
+                        "lintIgnore": ["NL001"]
+                    }
+                },
+                "edge": {
+                    "proxy": {"image": "traefik", "restart": "always"}
+                }
+            }),
+        );
+
+        let findings = lint_projects(&projects, &TargetSelector::All).unwrap();
+        let summary = findings
+            .iter()
+            .map(|finding| {
+                (
+                    format!("{}.{}", finding.project, finding.service),
+                    finding.code.name(),
+                    finding.location.line,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("app.api".to_string(), "local-build", Some(7)),
+                ("app.api".to_string(), "missing-healthcheck", Some(8)),
+                ("app.migrate".to_string(), "restarting-one-shot", Some(12)),
+                ("app.web".to_string(), "unpinned-image", Some(3)),
+                ("app.web".to_string(), "container-name-collision", Some(4)),
+                (
+                    "edge.proxy".to_string(),
+                    "container-name-collision",
+                    Some(4)
+                ),
+            ]
+        );
+        assert!(
+            findings[2]
+                .message
+                .contains("`worker` waits")
+        );
+        assert_eq!(findings[4].severity, Severity::Error);
+        assert!(
+            findings[4]
+                .message
+                .contains("edge.proxy")
+        );
+
+        let selector =
+            crate::projects::parse_selector("edge", &projects).unwrap();
+        let findings = lint_projects(&projects, &selector).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].location.to_string(),
+            format!(
+                "{}:4 (services.proxy.container_name)",
+                dir.path().join("edge.yml").display()
+            )
+        );
+    }
+
+    #[test]
+    fn only_untagged_and_latest_images_are_unpinned() {
+        assert!(is_unpinned("nginx"));
+        assert!(is_unpinned("nginx:latest"));
+        assert!(is_unpinned("registry.local:5000/nginx"));
+        assert!(!is_unpinned("registry.local:5000/nginx:1.27"));
+        assert!(!is_unpinned("nginx@sha256:abc"));
+        assert!(!is_unpinned("nginx:${NGINX_TAG}"));
+    }
+}
@@ -1,8 +1,46 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::Error as _};
+use serde_json::Value;
 use std::collections::BTreeMap;
 
 pub use nirion_oci_lib::version::VersionedImage;
 
+/// The lock file schema this version of nirion writes.
+///
+/// * v0: a flat map of service to digest
+/// * v1: a flat map of service to [`VersionedImage`]
+/// * v2: `{"version": 2, "images": {...}}` with v1 entries
+pub const LOCK_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct LockFileRef<'a> {
+    version: u32,
+    images: &'a BTreeMap<String, VersionedImage>,
+}
+
+/// Detects the schema of a parsed lock file. Only objects carrying a
+/// numeric `version` next to `images` are versioned; anything else is
+/// one of the flat historical formats.
+pub fn lock_schema_version(value: &Value) -> anyhow::Result<u32> {
+    let Some(object) = value.as_object() else {
+        anyhow::bail!("lock file must be a JSON object");
+    };
+
+    if let (Some(version), true) =
+        (object.get("version"), object.contains_key("images"))
+    {
+        let version = version.as_u64().ok_or_else(|| {
+            anyhow::anyhow!("lock file version must be a number")
+        })?;
+        return Ok(u32::try_from(version)?);
+    }
+
+    if !object.is_empty() && object.values().all(Value::is_string) {
+        Ok(0)
+    } else {
+        Ok(1)
+    }
+}
+
 #[derive(Default, Clone, PartialEq)]
 pub struct LockedImages {
     locked_images: BTreeMap<String, VersionedImage>,
@@ -13,10 +51,8 @@ impl<'de> Deserialize<'de> for LockedImages {
     where
         D: serde::Deserializer<'de>,
     {
-        let locked_images =
-            BTreeMap::<String, VersionedImage>::deserialize(deserializer)?;
-
-        Ok(Self { locked_images })
+        let value = Value::deserialize(deserializer)?;
+        Self::from_value(value).map_err(D::Error::custom)
     }
 }
 
@@ -28,11 +64,89 @@ impl Serialize for LockedImages {
     where
         S: serde::Serializer,
     {
-        self.locked_images.serialize(serializer)
+        LockFileRef {
+            version: LOCK_SCHEMA_VERSION,
+            images: &self.locked_images,
+        }
+        .serialize(serializer)
     }
 }
 
 impl LockedImages {
+    /// Reads any known lock file schema, upgrading older ones in memory.
+    /// Digest-only (v0) entries come back with an empty image, see
+    /// [`Self::fill_missing_images`].
+    pub fn from_value(mut value: Value) -> anyhow::Result<Self> {
+        let schema = lock_schema_version(&value)?;
+        if value.get("images").is_some() && value.get("version").is_some() {
+            value = value["images"].take();
+        }
+
+        let locked_images = match schema {
+            0 => serde_json::from_value::<BTreeMap<String, String>>(value)?
+                .into_iter()
+                .map(|(service, digest)| {
+                    let image = VersionedImage {
+                        image: String::new(),
+                        version: None,
+                        digest,
+                        size: None,
+                        source: None,
+                    };
+                    (service, image)
+                })
+                .collect(),
+            1..=LOCK_SCHEMA_VERSION => serde_json::from_value(value)?,
+            version => anyhow::bail!(
+                "lock file uses schema version {version}, but this nirion \
+                 only understands up to version {LOCK_SCHEMA_VERSION}; \
+                 please upgrade nirion"
+            ),
+        };
+
+        Ok(Self { locked_images })
+    }
+
+    /// Fills in the image of entries migrated from the digest-only
+    /// format, using the images currently configured per service.
+    /// Returns the services that were filled.
+    pub fn fill_missing_images(
+        &mut self,
+        images: &BTreeMap<String, String>,
+    ) -> Vec<String> {
+        let mut filled = Vec::new();
+
+        for (service, locked) in &mut self.locked_images {
+            if !locked.image.is_empty() {
+                continue;
+            }
+            if let Some(image) = images.get(service) {
+                locked.image = image.clone();
+                filled.push(service.clone());
+            }
+        }
+
+        filled
+    }
+
+    /// The one on-disk rendering of a lock file: current schema, entries
+    /// sorted by key, fields in [`VersionedImage`] declaration order,
+    /// two-space indent and a trailing newline. Every writer goes through
+    /// this so identical content always produces identical bytes.
+    pub fn to_pretty_string(&self) -> anyhow::Result<String> {
+        let mut output = serde_json::to_string_pretty(self)?;
+        output.push('\n');
+        Ok(output)
+    }
+
+    pub fn len(&self) -> usize {
+        self.locked_images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locked_images.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&str, &VersionedImage)> {
         self.locked_images
             .iter()
@@ -109,7 +223,8 @@ impl LockedImages {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "lowercase")]
 pub enum DiffEntry {
     Added {
         service: String,
@@ -126,6 +241,83 @@ pub enum DiffEntry {
     },
 }
 
+impl DiffEntry {
+    pub fn service(&self) -> &str {
+        match self {
+            DiffEntry::Added { service, .. }
+            | DiffEntry::Removed { service, .. }
+            | DiffEntry::Updated { service, .. } => service,
+        }
+    }
+
+    /// The locked image before the change; `None` if it was added.
+    pub fn old_image(&self) -> Option<&VersionedImage> {
+        match self {
+            DiffEntry::Added { .. } => None,
+            DiffEntry::Removed { old, .. } | DiffEntry::Updated { old, .. } => {
+                Some(old)
+            }
+        }
+    }
+
+    /// The locked image after the change; `None` if it was removed.
+    pub fn new_image(&self) -> Option<&VersionedImage> {
+        match self {
+            DiffEntry::Removed { .. } => None,
+            DiffEntry::Added { new, .. } | DiffEntry::Updated { new, .. } => {
+                Some(new)
+            }
+        }
+    }
+}
+
+/// One change shared by every service in `services`: the same image
+/// going from the same digest and version to the same new ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffGroup {
+    pub services: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<VersionedImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<VersionedImage>,
+}
+
+impl DiffGroup {
+    /// The image the group is about, as written in the compose files.
+    pub fn image(&self) -> &str {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .map(|image| image.image.as_str())
+            .unwrap_or_default()
+    }
+}
+
+/// Groups `diffs` by identical changes, in the order each change first
+/// appears. Services whose image or resulting version differ from every
+/// other end up in a group of their own.
+pub fn group_diffs(diffs: &[DiffEntry]) -> Vec<DiffGroup> {
+    let mut groups: Vec<DiffGroup> = Vec::new();
+
+    for entry in diffs {
+        let (old, new) = (entry.old_image(), entry.new_image());
+        match groups.iter_mut().find(|group| {
+            group.old.as_ref() == old && group.new.as_ref() == new
+        }) {
+            Some(group) => group
+                .services
+                .push(entry.service().to_string()),
+            None => groups.push(DiffGroup {
+                services: vec![entry.service().to_string()],
+                old: old.cloned(),
+                new: new.cloned(),
+            }),
+        }
+    }
+
+    groups
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -139,6 +331,8 @@ mod tests {
             image: image.to_string(),
             version: Some(version.to_string()),
             digest: digest.to_string(),
+            size: None,
+            source: None,
         }
     }
 
@@ -228,6 +422,38 @@ mod tests {
         assert_eq!(updated.len(), 1);
     }
 
+    #[test]
+    fn diffs_group_by_identical_image_changes() {
+        let mut a = LockedImages::default();
+        let mut b = LockedImages::default();
+        for service in ["a.db", "b.db", "c.db"] {
+            a.insert(service.into(), img("postgres:16", "16.3", "sha256:old"));
+            b.insert(service.into(), img("postgres:16", "16.4", "sha256:new"));
+        }
+        a.insert("d.db".into(), img("postgres:16", "16.2", "sha256:older"));
+        b.insert("d.db".into(), img("postgres:16", "16.4", "sha256:new"));
+        b.insert("a.web".into(), img("nginx", "1.27", "sha256:web"));
+
+        let groups = group_diffs(&a.diff(&b));
+
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| (group.image(), group.services.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "postgres:16",
+                    vec!["a.db".into(), "b.db".into(), "c.db".into()]
+                ),
+                ("nginx", vec!["a.web".into()]),
+                ("postgres:16", vec!["d.db".into()]),
+            ]
+        );
+        assert_eq!(groups[0].old.as_ref().unwrap().digest, "sha256:old");
+        assert!(groups[1].old.is_none());
+    }
+
     #[test]
     fn deserialize_full_format() {
         let json = r#"{"myapp.web":{"image":"nginx","version":"1.0","digest":"sha256:aaa"}}"#;
@@ -237,8 +463,131 @@ mod tests {
     }
 
     #[test]
-    fn deserialize_rejects_digest_only_format() {
+    fn deserialize_migrates_digest_only_format() {
         let json = r#"{"myapp.web":"sha256:aaa"}"#;
-        assert!(serde_json::from_str::<LockedImages>(json).is_err());
+        let mut locked: LockedImages = serde_json::from_str(json).unwrap();
+
+        let web = locked.get("myapp.web").unwrap();
+        assert_eq!(web.digest, "sha256:aaa");
+        assert_eq!(web.image, "");
+
+        let filled = locked.fill_missing_images(&BTreeMap::from([(
+            "myapp.web".to_string(),
+            "nginx:latest".to_string(),
+        )]));
+        assert_eq!(filled, vec!["myapp.web".to_string()]);
+        assert_eq!(locked.get("myapp.web").unwrap().image, "nginx:latest");
+    }
+
+    #[test]
+    fn serializes_current_schema() {
+        let mut locked = LockedImages::default();
+        locked.insert("myapp.web".into(), img("nginx", "1.0", "sha256:aaa"));
+
+        let value = serde_json::to_value(&locked).unwrap();
+
+        assert_eq!(value["version"], LOCK_SCHEMA_VERSION);
+        assert_eq!(value["images"]["myapp.web"]["digest"], "sha256:aaa");
+    }
+
+    #[test]
+    fn every_schema_round_trips_to_current() {
+        let expected = {
+            let mut locked = LockedImages::default();
+            locked
+                .insert("myapp.web".into(), img("nginx", "1.0", "sha256:aaa"));
+            locked
+        };
+
+        for (schema, json) in [
+            (
+                1,
+                r#"{"myapp.web":{"image":"nginx","version":"1.0","digest":"sha256:aaa"}}"#,
+            ),
+            (
+                2,
+                r#"{"version":2,"images":{"myapp.web":{"image":"nginx","version":"1.0","digest":"sha256:aaa"}}}"#,
+            ),
+        ] {
+            let value: Value = serde_json::from_str(json).unwrap();
+            assert_eq!(lock_schema_version(&value).unwrap(), schema);
+
+            let locked: LockedImages = serde_json::from_value(value).unwrap();
+            assert!(locked == expected, "schema v{schema}");
+
+            let written = serde_json::to_string(&locked).unwrap();
+            let reread: LockedImages = serde_json::from_str(&written).unwrap();
+            assert!(reread == expected, "schema v{schema} after rewrite");
+        }
+
+        let mut migrated: LockedImages =
+            serde_json::from_str(r#"{"myapp.web":"sha256:aaa"}"#).unwrap();
+        migrated.fill_missing_images(&BTreeMap::from([(
+            "myapp.web".to_string(),
+            "nginx".to_string(),
+        )]));
+        let written = serde_json::to_string(&migrated).unwrap();
+        let reread: LockedImages = serde_json::from_str(&written).unwrap();
+        assert_eq!(reread.get("myapp.web").unwrap().digest, "sha256:aaa");
+        assert_eq!(reread.get("myapp.web").unwrap().version, None);
+    }
+
+    #[test]
+    fn empty_lock_file_is_flat_schema() {
+        let value: Value = serde_json::from_str("{}").unwrap();
+        assert_eq!(lock_schema_version(&value).unwrap(), 1);
+        assert!(
+            serde_json::from_value::<LockedImages>(value)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn pretty_string_is_independent_of_insertion_order() {
+        let entries = [
+            ("zeta.web", img("nginx", "1.0", "sha256:aaa")),
+            ("alpha.db", img("postgres", "16", "sha256:bbb")),
+            ("mid.api", img("node", "22", "sha256:ccc")),
+        ];
+        let mut forward = LockedImages::default();
+        forward.extend(
+            entries
+                .iter()
+                .cloned()
+                .map(|(key, image)| (key.to_string(), image)),
+        );
+        let mut backward = LockedImages::default();
+        for (key, image) in entries.iter().rev().cloned() {
+            backward.insert(key.to_string(), image);
+        }
+
+        let forward = forward.to_pretty_string().unwrap();
+
+        assert_eq!(forward, backward.to_pretty_string().unwrap());
+        assert!(forward.ends_with("}\n"));
+        assert!(forward.starts_with("{\n  \"version\": 2,\n  \"images\": {\n"));
+        let image = forward.find("\"image\"").unwrap();
+        let version = forward
+            .find("\"version\": \"16\"")
+            .unwrap();
+        let digest = forward.find("\"digest\"").unwrap();
+        assert!(
+            forward.find("alpha.db").unwrap()
+                < forward.find("mid.api").unwrap()
+        );
+        assert!(image < version && version < digest);
+    }
+
+    #[test]
+    fn rejects_future_schema() {
+        let json = r#"{"version":3,"images":{}}"#;
+        let error = serde_json::from_str::<LockedImages>(json)
+            .err()
+            .unwrap()
+            .to_string();
+
+        assert!(error.contains("schema version 3"), "{error}");
+        assert!(error.contains("upgrade nirion"), "{error}");
     }
 }
@@ -0,0 +1,255 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    config::load_locked_images,
+    lock::{LockedImages, VersionedImage},
+};
+
+const PROJECT_LOCK_EXTENSION: &str = "lock";
+
+/// Where locked images are persisted: one file for every project, or a
+/// directory holding one `<project>.lock` per project whose keys are
+/// service names without the project prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockStore {
+    File(PathBuf),
+    Dir(PathBuf),
+}
+
+impl LockStore {
+    pub fn path(&self) -> &Path {
+        match self {
+            LockStore::File(path) | LockStore::Dir(path) => path,
+        }
+    }
+
+    /// Loads the unified `project.service` view, merging per-project
+    /// files in directory mode. A missing file or directory is empty.
+    pub fn load(&self) -> anyhow::Result<LockedImages> {
+        match self {
+            LockStore::File(path) => load_locked_images(path),
+            LockStore::Dir(dir) => load_lock_dir(dir),
+        }
+    }
+
+    /// Persists `new`, returning the files that were written or removed.
+    /// In directory mode only projects whose entries differ from
+    /// `previous` are touched.
+    pub fn write(
+        &self,
+        previous: &LockedImages,
+        new: &LockedImages,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        match self {
+            LockStore::File(path) => {
+                write_lock_file(path, new)?;
+                Ok(vec![path.clone()])
+            }
+            LockStore::Dir(dir) => write_lock_dir(dir, previous, new),
+        }
+    }
+}
+
+pub fn project_lock_file(
+    dir: &Path,
+    project: &str,
+) -> PathBuf {
+    dir.join(format!("{project}.{PROJECT_LOCK_EXTENSION}"))
+}
+
+fn write_lock_file(
+    path: &Path,
+    images: &LockedImages,
+) -> anyhow::Result<()> {
+    fs::write(path, images.to_pretty_string()?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn load_lock_dir(dir: &Path) -> anyhow::Result<LockedImages> {
+    let mut locked_images = LockedImages::default();
+    if !dir.exists() {
+        return Ok(locked_images);
+    }
+
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            != Some(PROJECT_LOCK_EXTENSION)
+        {
+            continue;
+        }
+        let Some(project) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+        else {
+            continue;
+        };
+
+        let images = load_locked_images(&path)
+            .with_context(|| format!("in {}", path.display()))?;
+        locked_images.extend(images.iter().map(|(service, image)| {
+            (format!("{project}.{service}"), image.clone())
+        }));
+    }
+
+    Ok(locked_images)
+}
+
+/// Splits `project.service` keys into per-project maps keyed by service.
+fn by_project(
+    images: &LockedImages
+) -> BTreeMap<&str, BTreeMap<&str, &VersionedImage>> {
+    let mut projects = BTreeMap::<_, BTreeMap<_, _>>::new();
+    for (key, image) in images.iter() {
+        let (project, service) = key.split_once('.').unwrap_or((key, ""));
+        projects
+            .entry(project)
+            .or_default()
+            .insert(service, image);
+    }
+    projects
+}
+
+fn write_lock_dir(
+    dir: &Path,
+    previous: &LockedImages,
+    new: &LockedImages,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let previous = by_project(previous);
+    let new = by_project(new);
+    let mut touched = Vec::new();
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create {}", dir.display()))?;
+
+    for (project, images) in &new {
+        if previous.get(project) == Some(images) {
+            continue;
+        }
+
+        let mut project_images = LockedImages::default();
+        project_images.extend(
+            images.iter().map(|(service, image)| {
+                (service.to_string(), (*image).clone())
+            }),
+        );
+        let path = project_lock_file(dir, project);
+        write_lock_file(&path, &project_images)?;
+        touched.push(path);
+    }
+
+    for project in previous.keys() {
+        if new.contains_key(project) {
+            continue;
+        }
+        let path = project_lock_file(dir, project);
+        if path.exists() {
+            fs::remove_file(&path).with_context(|| {
+                format!("failed to remove {}", path.display())
+            })?;
+            touched.push(path);
+        }
+    }
+
+    Ok(touched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(digest: &str) -> VersionedImage {
+        VersionedImage {
+            image: "nginx:latest".to_string(),
+            version: None,
+            digest: digest.to_string(),
+            size: None,
+            source: None,
+        }
+    }
+
+    fn locked(entries: &[(&str, &str)]) -> LockedImages {
+        let mut locked = LockedImages::default();
+        locked.extend(
+            entries
+                .iter()
+                .map(|(key, digest)| (key.to_string(), image(digest))),
+        );
+        locked
+    }
+
+    #[test]
+    fn dir_round_trips_with_project_prefix_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LockStore::Dir(dir.path().join("locks"));
+        let images = locked(&[
+            ("app.web", "sha256:a"),
+            ("app.db", "sha256:b"),
+            ("other.api", "sha256:c"),
+        ]);
+
+        let written = store
+            .write(&LockedImages::default(), &images)
+            .unwrap();
+
+        assert_eq!(written.len(), 2);
+        let app: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(project_lock_file(store.path(), "app"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(app["images"]["web"]["digest"], "sha256:a");
+        assert!(store.load().unwrap() == images);
+    }
+
+    #[test]
+    fn dir_only_rewrites_changed_projects() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LockStore::Dir(dir.path().to_path_buf());
+        let before =
+            locked(&[("app.web", "sha256:a"), ("other.api", "sha256:c")]);
+        store
+            .write(&LockedImages::default(), &before)
+            .unwrap();
+
+        let after =
+            locked(&[("app.web", "sha256:z"), ("other.api", "sha256:c")]);
+        let written = store.write(&before, &after).unwrap();
+
+        assert_eq!(written, vec![project_lock_file(dir.path(), "app")]);
+        assert!(store.load().unwrap() == after);
+    }
+
+    #[test]
+    fn dir_removes_files_of_projects_without_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LockStore::Dir(dir.path().to_path_buf());
+        let before =
+            locked(&[("app.web", "sha256:a"), ("other.api", "sha256:c")]);
+        store
+            .write(&LockedImages::default(), &before)
+            .unwrap();
+
+        store
+            .write(&before, &locked(&[("app.web", "sha256:a")]))
+            .unwrap();
+
+        assert!(!project_lock_file(dir.path(), "other").exists());
+    }
+
+    #[test]
+    fn missing_dir_loads_empty() {
+        let store = LockStore::Dir(PathBuf::from("/nonexistent/locks"));
+        assert!(store.load().unwrap().is_empty());
+    }
+}
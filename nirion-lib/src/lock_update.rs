@@ -1,40 +1,128 @@
 use futures::{FutureExt, stream::FuturesUnordered};
 use futures::{StreamExt, channel::mpsc, stream::BoxStream};
-use nirion_oci_lib::{client::NirionOciClient, oci_client::Reference};
+use nirion_oci_lib::{
+    client::{NirionOciClient, ResolvedImage},
+    oci::get_version_from_labels,
+    oci_client::Reference,
+};
 use std::{
     collections::{BTreeMap, HashMap},
-    fs,
     sync::Arc,
 };
 use tokio::sync::RwLock;
 
 use crate::{
     context::NirionContext,
-    events::LockUpdateEvent,
+    docker::{DockerCommand, inspect_local_image},
+    downgrade::{SuspectedDowngrade, read_previous_digests},
+    events::{DigestSource, LockUpdateEvent},
     lock::{LockedImages, VersionedImage},
+    lock_store::LockStore,
+    resolve_failure::{FailureReport, ImageFailure},
 };
 
+/// Resolves `images` from their lock entries and writes what changed.
+///
+/// Normally nothing is written if any image fails. With `partial`, the
+/// images that resolved are written anyway and the failed ones keep
+/// their current entries; the stream still ends with the error.
+///
+/// Updates to an older version, or to a digest the service was deployed
+/// with before, are reported as suspected downgrades and keep their
+/// current entries unless `allow_downgrade` is set.
 pub fn image_update_stream(
     context: &NirionContext,
     images: BTreeMap<String, String>,
     jobs: usize,
+    partial: bool,
+    allow_downgrade: bool,
+) -> BoxStream<'static, anyhow::Result<LockUpdateEvent>> {
+    lock_stream(
+        context,
+        images,
+        jobs,
+        Resolve::Update {
+            partial,
+            allow_downgrade,
+        },
+    )
+}
+
+/// Like [`image_update_stream`], but only reports what an update would
+/// change. Nothing is written, and images that fail to resolve are left
+/// out of the changes; the stream still ends with the error.
+pub fn image_check_stream(
+    context: &NirionContext,
+    images: BTreeMap<String, String>,
+    jobs: usize,
+) -> BoxStream<'static, anyhow::Result<LockUpdateEvent>> {
+    lock_stream(context, images, jobs, Resolve::Check)
+}
+
+/// Like [`image_update_stream`], but resolves every image as if it had no
+/// lock entry yet: at the version and digest its configured reference
+/// points to now, never at a newer version an update would pick.
+///
+/// With `prefer_local`, images pulled on this host are locked at the
+/// digest they were pulled at, which may be older than what their tag
+/// points to now, and only the others are looked up in the registry.
+pub fn image_lock_stream(
+    context: &NirionContext,
+    images: BTreeMap<String, String>,
+    jobs: usize,
+    prefer_local: bool,
+) -> BoxStream<'static, anyhow::Result<LockUpdateEvent>> {
+    let local_images = prefer_local.then(|| context.docker_command.clone());
+    lock_stream(context, images, jobs, Resolve::Fresh { local_images })
+}
+
+/// How images that already have a lock entry are resolved.
+#[derive(Debug, Clone)]
+enum Resolve {
+    /// From the entry, following its version. `partial` writes the
+    /// images that resolved even if others failed, `allow_downgrade`
+    /// the ones that look like downgrades.
+    Update {
+        partial: bool,
+        allow_downgrade: bool,
+    },
+    /// Like an update, without writing anything.
+    Check,
+    /// From the configured reference alone, or from the image pulled on
+    /// this host if docker is given to ask for it.
+    Fresh { local_images: Option<DockerCommand> },
+}
+
+fn lock_stream(
+    context: &NirionContext,
+    images: BTreeMap<String, String>,
+    jobs: usize,
+    resolve: Resolve,
 ) -> BoxStream<'static, anyhow::Result<LockUpdateEvent>> {
     let client = context.oci_client.clone();
     let locked_images = context.locked_images.clone();
-    let lock_file = context.lock_file.clone();
+    let lock_store = context.lock_store().cloned();
+    let docker_command = context.docker_command.clone();
     let (event_tx, event_rx) = mpsc::unbounded();
 
     tokio::spawn(async move {
-        if let Err(error) = image_update_stream_inner(
-            client,
-            locked_images,
-            lock_file,
-            images,
-            jobs,
-            Some(event_tx.clone()),
-        )
-        .await
-        {
+        let result = match lock_store {
+            Ok(lock_store) => {
+                image_update_stream_inner(
+                    client,
+                    docker_command,
+                    locked_images,
+                    lock_store,
+                    images,
+                    jobs,
+                    resolve,
+                    Some(event_tx.clone()),
+                )
+                .await
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
             let _ = event_tx.unbounded_send(Err(error));
         }
     });
@@ -42,12 +130,15 @@ pub fn image_update_stream(
     event_rx.boxed()
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn image_update_stream_inner(
     client: Arc<NirionOciClient>,
+    docker_command: DockerCommand,
     locked_images: LockedImages,
-    lock_file: std::path::PathBuf,
+    lock_store: LockStore,
     images: BTreeMap<String, String>,
     jobs: usize,
+    resolve: Resolve,
     event_tx: Option<mpsc::UnboundedSender<anyhow::Result<LockUpdateEvent>>>,
 ) -> anyhow::Result<()> {
     if images.is_empty() {
@@ -55,65 +146,173 @@ async fn image_update_stream_inner(
         return Ok(());
     }
 
-    let digest_cache: Arc<RwLock<HashMap<String, VersionedImage>>> =
+    let digest_cache: Arc<RwLock<HashMap<String, ResolvedImage>>> =
         Arc::new(RwLock::new(HashMap::new()));
     let semaphore = Arc::new(tokio::sync::Semaphore::new(jobs.max(1)));
     let mut futures = FuturesUnordered::new();
 
-    for (service, image) in images {
-        emit_event(
-            &event_tx,
-            LockUpdateEvent::ImageStarted {
-                service: service.clone(),
-                image: image.clone(),
-            },
-        );
-
+    for (service, image) in images.clone() {
         let client = Arc::clone(&client);
         let semaphore = Arc::clone(&semaphore);
         let digest_cache = Arc::clone(&digest_cache);
-        let current_versioned_image = locked_images.get(&service).cloned();
+        let docker_command = docker_command.clone();
+        let (current_versioned_image, local_images) = match &resolve {
+            Resolve::Update { .. } | Resolve::Check => {
+                (locked_images.get(&service).cloned(), None)
+            }
+            Resolve::Fresh { local_images } => (None, local_images.clone()),
+        };
         let event_tx = event_tx.clone();
 
         futures.push(
             async move {
                 let _permit = semaphore.acquire().await.unwrap();
+                emit_event(
+                    &event_tx,
+                    LockUpdateEvent::ImageStarted {
+                        service: service.clone(),
+                        image: image.clone(),
+                    },
+                );
 
-                let versioned_image = if let Some(mut current) =
-                    current_versioned_image
-                {
+                let local = match &local_images {
+                    Some(docker_command) => {
+                        local_versioned_image(docker_command, &image).await
+                    }
+                    None => None,
+                };
+                let source = match local {
+                    Some(_) => DigestSource::LocalImage,
+                    None => DigestSource::Registry,
+                };
+                let versioned_image = if let Some(local) = local {
+                    Ok(ResolvedImage {
+                        image: local,
+                        tag_search_capped_after: None,
+                    })
+                } else if let Some(mut current) = current_versioned_image {
                     current.image = image.clone();
                     get_cached_updated_image(&client, &current, &digest_cache)
-                        .await?
+                        .await
                 } else {
-                    get_cached_image(&client, &image, &digest_cache).await?
+                    get_cached_image(&client, &image, &digest_cache).await
                 };
 
                 emit_event(
                     &event_tx,
                     LockUpdateEvent::ImageResolved {
                         service: service.clone(),
+                        source,
                     },
                 );
 
-                Ok::<_, anyhow::Error>((service, versioned_image))
+                match versioned_image {
+                    Ok(versioned_image) => Ok((service, versioned_image)),
+                    Err(error) => {
+                        let (cause, failure) =
+                            ImageFailure::classify(service, image, &error);
+                        // References that don't parse can't name an
+                        // image on the host either.
+                        let local_build = failure.registry.is_some()
+                            && is_local_build(&docker_command, &failure.image)
+                                .await;
+                        Err((cause, failure, local_build))
+                    }
+                }
             }
             .boxed(),
         );
     }
 
     let mut new_locked_images = locked_images.clone();
+    let mut failures = Vec::new();
 
     while let Some(result) = futures.next().await {
-        let (service, versioned_image) = result?;
-        new_locked_images.insert(service, versioned_image);
+        match result {
+            Ok((service, resolved)) => {
+                if let Some(searched) = resolved.tag_search_capped_after {
+                    emit_event(
+                        &event_tx,
+                        LockUpdateEvent::TagSearchCapped {
+                            service: service.clone(),
+                            image: resolved.image.image.clone(),
+                            searched,
+                        },
+                    );
+                }
+                new_locked_images.insert(service, resolved.image);
+            }
+            Err((_, failure, true)) => {
+                emit_event(
+                    &event_tx,
+                    LockUpdateEvent::ImageSkipped {
+                        service: failure.service,
+                        image: failure.image,
+                    },
+                );
+            }
+            Err((cause, failure, false)) => failures.push((cause, failure)),
+        }
     }
 
-    let diffs = locked_images.diff(&new_locked_images);
+    let mut failed = None;
+    if !failures.is_empty() {
+        let report = FailureReport::new(&images, failures);
+        let summary = anyhow::anyhow!(
+            "{} of {} images failed to resolve",
+            report.failed,
+            report.total
+        );
+        emit_event(&event_tx, LockUpdateEvent::ResolutionFailed { report });
+        if !matches!(
+            resolve,
+            Resolve::Update { partial: true, .. } | Resolve::Check
+        ) {
+            return Err(summary);
+        }
+        failed = Some(summary);
+    }
+    // Failed services were never replaced in the copy, so a partial
+    // write keeps their old entries.
+    let mut diffs = locked_images.diff(&new_locked_images);
+
+    let mut downgrades = Vec::new();
+    if let Resolve::Update {
+        allow_downgrade, ..
+    } = resolve
+    {
+        let previous_digests = read_previous_digests();
+        downgrades = diffs
+            .iter()
+            .filter_map(|diff| {
+                SuspectedDowngrade::detect(diff, &previous_digests)
+            })
+            .collect::<Vec<_>>();
+        if !downgrades.is_empty() {
+            if !allow_downgrade {
+                for downgrade in &downgrades {
+                    new_locked_images.insert(
+                        downgrade.service.clone(),
+                        downgrade.old.clone(),
+                    );
+                }
+                diffs = locked_images.diff(&new_locked_images);
+            }
+            emit_event(
+                &event_tx,
+                LockUpdateEvent::DowngradesSuspected {
+                    downgrades: downgrades.clone(),
+                    held: !allow_downgrade,
+                },
+            );
+        }
+    }
 
     if diffs.is_empty() {
-        emit_event(&event_tx, LockUpdateEvent::UpToDate);
-        return Ok(());
+        if failed.is_none() && downgrades.is_empty() {
+            emit_event(&event_tx, LockUpdateEvent::UpToDate);
+        }
+        return failed.map_or(Ok(()), Err);
     }
 
     emit_event(
@@ -122,14 +321,16 @@ async fn image_update_stream_inner(
             diffs: diffs.clone(),
         },
     );
+    if matches!(resolve, Resolve::Check) {
+        return failed.map_or(Ok(()), Err);
+    }
     emit_event(&event_tx, LockUpdateEvent::WritingLockFile);
 
-    let new_lock_file = serde_json::to_string_pretty(&new_locked_images)?;
-    fs::write(lock_file, new_lock_file)?;
+    lock_store.write(&locked_images, &new_locked_images)?;
 
     emit_event(&event_tx, LockUpdateEvent::LockFileWritten);
 
-    Ok(())
+    failed.map_or(Ok(()), Err)
 }
 
 fn emit_event(
@@ -141,11 +342,61 @@ fn emit_event(
     }
 }
 
+/// Whether `image` was built or tagged on this host rather than pulled:
+/// docker has it, but without a digest from any repository. Only then is
+/// a failure to resolve it skipped; the failure itself can't tell, as
+/// Docker Hub answers both unknown names and bad credentials with 401.
+async fn is_local_build(
+    docker_command: &DockerCommand,
+    image: &str,
+) -> bool {
+    inspect_local_image(docker_command, image)
+        .await
+        .is_some_and(|local| local.repo_digests.is_empty())
+}
+
+/// The lock entry for `image` from the copy pulled on this host: the
+/// digest its repository served when it was pulled and the version from
+/// its label. `None` if it wasn't pulled from that repository, e.g. when
+/// it was built here. The size is unknown without the manifest.
+async fn local_versioned_image(
+    docker_command: &DockerCommand,
+    image: &str,
+) -> Option<VersionedImage> {
+    let reference = Reference::try_from(image).ok()?;
+    let local = inspect_local_image(docker_command, image).await?;
+    let digest = local
+        .repo_digests
+        .iter()
+        .filter_map(|repo_digest| {
+            Reference::try_from(repo_digest.as_str()).ok()
+        })
+        .find(|pulled| {
+            pulled.resolve_registry() == reference.resolve_registry()
+                && pulled.repository() == reference.repository()
+        })?
+        .digest()?
+        .to_string();
+
+    let labels = local.config.labels.as_ref();
+    Some(VersionedImage {
+        image: image.to_string(),
+        version: labels.and_then(get_version_from_labels),
+        digest,
+        size: None,
+        source: labels.and_then(|labels| {
+            labels
+                .get("org.opencontainers.image.source")
+                .cloned()
+        }),
+    })
+}
+
 async fn get_cached_image(
     client: &NirionOciClient,
     image: &str,
-    cache: &Arc<RwLock<HashMap<String, VersionedImage>>>,
-) -> anyhow::Result<VersionedImage> {
+    cache: &Arc<RwLock<HashMap<String, ResolvedImage>>>,
+) -> anyhow::Result<ResolvedImage> {
     if let Some(existing) = {
         let locked_cache = cache.read().await;
         locked_cache.get(image).cloned()
@@ -154,17 +405,43 @@ async fn get_cached_image(
     }
 
     let reference = Reference::try_from(image)?;
-    let mut versioned_image = client
+    let mut resolved = client
         .get_versioned_image(&reference)
         .await?;
-    versioned_image.image = image.to_string();
+    resolved.image.image = image.to_string();
+
+    {
+        let mut locked_cache = cache.write().await;
+        locked_cache.insert(image.to_string(), resolved.clone());
+    }
+
+    Ok(resolved)
+}
+
+async fn get_cached_updated_image(
+    client: &NirionOciClient,
+    versioned_image: &VersionedImage,
+    cache: &Arc<RwLock<HashMap<String, ResolvedImage>>>,
+) -> anyhow::Result<ResolvedImage> {
+    let image = versioned_image.image.as_str();
+
+    if let Some(existing) = {
+        let locked_cache = cache.read().await;
+        locked_cache.get(image).cloned()
+    } {
+        return Ok(existing);
+    }
+
+    let resolved = client
+        .get_updated_versioned_image(versioned_image)
+        .await?;
 
     {
         let mut locked_cache = cache.write().await;
-        locked_cache.insert(image.to_string(), versioned_image.clone());
+        locked_cache.insert(image.to_string(), resolved.clone());
     }
 
-    Ok(versioned_image)
+    Ok(resolved)
 }
 
 #[cfg(test)]
@@ -176,11 +453,13 @@ mod tests {
     };
     use futures::StreamExt;
     use nirion_oci_lib::{
-        oci_client::secrets::RegistryAuth,
-        test_registry::{RegistryHandle, http_nirion_client},
+        mock_registry::MockRegistry, oci_client::client::ClientProtocol,
     };
     use std::path::PathBuf;
 
+    const OLD_DIGEST: &str = "sha256:0000000000000000000000000000000000000000000000000000000000000000";
+    const VERSION_LABEL: &str = "org.opencontainers.image.version";
+
     fn image(
         image: &str,
         version: &str,
@@ -190,6 +469,8 @@ mod tests {
             image: image.to_string(),
             version: Some(version.to_string()),
             digest: digest.to_string(),
+            size: None,
+            source: None,
         }
     }
 
@@ -201,14 +482,49 @@ mod tests {
         NirionContext {
             projects: Projects::default(),
             locked_images,
-            lock_file,
+            lock_store: Some(LockStore::File(lock_file)),
             oci_client: Arc::new(client),
             docker_command: DockerCommand::default(),
         }
     }
 
-    fn localhost_image(image: &str) -> String {
-        image.replacen("127.0.0.1", "localhost", 1)
+    /// A mock registry with `app:1.2.3` pushed as `image` at `digest`.
+    struct Registry {
+        mock: MockRegistry,
+        image: String,
+        digest: String,
+    }
+
+    impl Registry {
+        fn start() -> Self {
+            let mock = MockRegistry::start();
+            let digest =
+                mock.push("app", "1.2.3", &[(VERSION_LABEL, "1.2.3")], 1000);
+            Self {
+                image: format!("{}/app:1.2.3", mock.host()),
+                mock,
+                digest,
+            }
+        }
+
+        /// A client speaking plain HTTP to the registry.
+        fn client(&self) -> NirionOciClient {
+            NirionOciClient::builder()
+                .oci_client_protocol(ClientProtocol::HttpsExcept(vec![
+                    self.mock.host().to_string(),
+                ]))
+                .build()
+        }
+
+        /// A client resolving Docker Hub images through the registry.
+        fn docker_hub_mirror(&self) -> NirionOciClient {
+            NirionOciClient::builder()
+                .oci_client_protocol(ClientProtocol::HttpsExcept(vec![
+                    self.mock.host().to_string(),
+                ]))
+                .mirror("docker.io", self.mock.host())
+                .build()
+        }
     }
 
     async fn collect_events(
@@ -241,6 +557,8 @@ mod tests {
             ),
             BTreeMap::new(),
             1,
+            false,
+            false,
         );
 
         assert!(matches!(
@@ -255,27 +573,19 @@ mod tests {
 
     #[tokio::test]
     async fn adds_new_image_and_writes_lock_file() -> anyhow::Result<()> {
-        let handle = RegistryHandle::start_anonymous().await?;
-        let test_image = handle
-            .push(
-                "library/nirion-lock-update",
-                "1.2.3",
-                &RegistryAuth::Anonymous,
-            )
-            .await?;
+        let registry = Registry::start();
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
         let events = collect_events(image_update_stream(
             &context(
-                http_nirion_client().build(),
+                registry.client(),
                 LockedImages::default(),
                 lock_file.clone(),
             ),
-            BTreeMap::from([(
-                "app.web".to_string(),
-                test_image.reference.to_string(),
-            )]),
+            BTreeMap::from([("app.web".to_string(), registry.image.clone())]),
             1,
+            false,
+            false,
         ))
         .await?;
 
@@ -283,16 +593,13 @@ mod tests {
             events.iter().any(|event| matches!(
                 event,
                 LockUpdateEvent::ChangesDetected { diffs }
-                    if matches!(diffs.as_slice(), [DiffEntry::Added { service, new }] if service == "app.web" && new.digest == test_image.digest)
+                    if matches!(diffs.as_slice(), [DiffEntry::Added { service, new }] if service == "app.web" && new.digest == registry.digest)
             ))
         );
 
         let written = written_lock_file(lock_file)?;
-        assert_eq!(written.get("app.web").unwrap().digest, test_image.digest);
-        assert_eq!(
-            written.get("app.web").unwrap().image,
-            test_image.reference.to_string()
-        );
+        assert_eq!(written.get("app.web").unwrap().digest, registry.digest);
+        assert_eq!(written.get("app.web").unwrap().image, registry.image);
 
         Ok(())
     }
@@ -300,65 +607,52 @@ mod tests {
     #[tokio::test]
     async fn new_image_preserves_configured_image_string() -> anyhow::Result<()>
     {
-        let handle = RegistryHandle::start_anonymous().await?;
-        let test_image = handle
-            .push_anonymous("nirion-lock-update-preserve", "1.2.3")
-            .await?;
-        let configured_image =
-            localhost_image(&test_image.reference.to_string());
+        let registry = Registry::start();
+        registry.mock.push(
+            "library/app",
+            "1.2.3",
+            &[(VERSION_LABEL, "1.2.3")],
+            1000,
+        );
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
 
         collect_events(image_update_stream(
             &context(
-                http_nirion_client().build(),
+                registry.docker_hub_mirror(),
                 LockedImages::default(),
                 lock_file.clone(),
             ),
-            BTreeMap::from([("app.web".to_string(), configured_image.clone())]),
+            BTreeMap::from([("app.web".to_string(), "app:1.2.3".to_string())]),
             1,
+            false,
+            false,
         ))
         .await?;
 
         let written = written_lock_file(lock_file)?;
-        assert_eq!(written.get("app.web").unwrap().image, configured_image);
+        assert_eq!(written.get("app.web").unwrap().image, "app:1.2.3");
 
         Ok(())
     }
 
     #[tokio::test]
     async fn unchanged_locked_image_reports_up_to_date() -> anyhow::Result<()> {
-        let handle = RegistryHandle::start_anonymous().await?;
-        let test_image = handle
-            .push(
-                "library/nirion-lock-update",
-                "1.2.3",
-                &RegistryAuth::Anonymous,
-            )
-            .await?;
+        let registry = Registry::start();
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
         let mut locked_images = LockedImages::default();
         locked_images.insert(
             "app.web".to_string(),
-            image(
-                &test_image.reference.to_string(),
-                "1.2.3",
-                &test_image.digest,
-            ),
+            image(&registry.image, "1.2.3", &registry.digest),
         );
 
         let events = collect_events(image_update_stream(
-            &context(
-                http_nirion_client().build(),
-                locked_images,
-                lock_file.clone(),
-            ),
-            BTreeMap::from([(
-                "app.web".to_string(),
-                test_image.reference.to_string(),
-            )]),
+            &context(registry.client(), locked_images, lock_file.clone()),
+            BTreeMap::from([("app.web".to_string(), registry.image.clone())]),
             1,
+            false,
+            false,
         ))
         .await?;
 
@@ -375,37 +669,21 @@ mod tests {
     #[tokio::test]
     async fn stale_locked_image_updates_digest_and_writes_lock_file()
     -> anyhow::Result<()> {
-        let handle = RegistryHandle::start_anonymous().await?;
-        let test_image = handle
-            .push(
-                "library/nirion-lock-update",
-                "1.2.3",
-                &RegistryAuth::Anonymous,
-            )
-            .await?;
+        let registry = Registry::start();
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
         let mut locked_images = LockedImages::default();
         locked_images.insert(
             "app.web".to_string(),
-            image(
-                &test_image.reference.to_string(),
-                "1.0.0",
-                "sha256:0000000000000000000000000000000000000000000000000000000000000000",
-            ),
+            image(&registry.image, "1.0.0", OLD_DIGEST),
         );
 
         let events = collect_events(image_update_stream(
-            &context(
-                http_nirion_client().build(),
-                locked_images,
-                lock_file.clone(),
-            ),
-            BTreeMap::from([(
-                "app.web".to_string(),
-                test_image.reference.to_string(),
-            )]),
+            &context(registry.client(), locked_images, lock_file.clone()),
+            BTreeMap::from([("app.web".to_string(), registry.image.clone())]),
             1,
+            false,
+            false,
         ))
         .await?;
 
@@ -413,15 +691,12 @@ mod tests {
             events.iter().any(|event| matches!(
                 event,
                 LockUpdateEvent::ChangesDetected { diffs }
-                    if matches!(diffs.as_slice(), [DiffEntry::Updated { service, new, .. }] if service == "app.web" && new.digest == test_image.digest)
+                    if matches!(diffs.as_slice(), [DiffEntry::Updated { service, new, .. }] if service == "app.web" && new.digest == registry.digest)
             ))
         );
         let written = written_lock_file(lock_file)?;
-        assert_eq!(written.get("app.web").unwrap().digest, test_image.digest);
-        assert_eq!(
-            written.get("app.web").unwrap().image,
-            test_image.reference.to_string()
-        );
+        assert_eq!(written.get("app.web").unwrap().digest, registry.digest);
+        assert_eq!(written.get("app.web").unwrap().image, registry.image);
 
         Ok(())
     }
@@ -429,32 +704,31 @@ mod tests {
     #[tokio::test]
     async fn unchanged_digest_with_changed_image_string_writes_lock_file()
     -> anyhow::Result<()> {
-        let handle = RegistryHandle::start_anonymous().await?;
-        let test_image = handle
-            .push_anonymous("nirion-lock-update-image-change", "1.2.3")
-            .await?;
-        let configured_image =
-            localhost_image(&test_image.reference.to_string());
+        let registry = Registry::start();
+        let digest = registry.mock.push(
+            "library/app",
+            "1.2.3",
+            &[(VERSION_LABEL, "1.2.3")],
+            1000,
+        );
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
         let mut locked_images = LockedImages::default();
         locked_images.insert(
             "app.web".to_string(),
-            image(
-                &test_image.reference.to_string(),
-                "1.2.3",
-                &test_image.digest,
-            ),
+            image("docker.io/library/app:1.2.3", "1.2.3", &digest),
         );
 
         let events = collect_events(image_update_stream(
             &context(
-                http_nirion_client().build(),
+                registry.docker_hub_mirror(),
                 locked_images,
                 lock_file.clone(),
             ),
-            BTreeMap::from([("app.web".to_string(), configured_image.clone())]),
+            BTreeMap::from([("app.web".to_string(), "app:1.2.3".to_string())]),
             1,
+            false,
+            false,
         ))
         .await?;
 
@@ -462,22 +736,110 @@ mod tests {
             events.iter().any(|event| matches!(
                 event,
                 LockUpdateEvent::ChangesDetected { diffs }
-                    if matches!(diffs.as_slice(), [DiffEntry::Updated { service, new, .. }] if service == "app.web" && new.digest == test_image.digest)
+                    if matches!(diffs.as_slice(), [DiffEntry::Updated { service, new, .. }] if service == "app.web" && new.digest == digest)
             ))
         );
         let written = written_lock_file(lock_file)?;
-        assert_eq!(written.get("app.web").unwrap().image, configured_image);
+        assert_eq!(written.get("app.web").unwrap().image, "app:1.2.3");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partial_update_writes_resolved_images_and_keeps_failed_entries()
+    -> anyhow::Result<()> {
+        let registry = Registry::start();
+        let dir = tempfile::tempdir()?;
+        let lock_file = dir.path().join("nirion.lock");
+        let broken = image(
+            "registry.test/broken:1",
+            "1",
+            "sha256:1111111111111111111111111111111111111111111111111111111111111111",
+        );
+        let mut locked_images = LockedImages::default();
+        locked_images.insert("app.broken".to_string(), broken.clone());
+        locked_images.insert(
+            "app.web".to_string(),
+            image(&registry.image, "1.0.0", OLD_DIGEST),
+        );
+
+        let mut events = image_update_stream(
+            &context(registry.client(), locked_images, lock_file.clone()),
+            BTreeMap::from([
+                ("app.broken".to_string(), "not a valid image".to_string()),
+                ("app.web".to_string(), registry.image.clone()),
+            ]),
+            1,
+            true,
+            false,
+        );
+        let mut changed = Vec::new();
+        let mut error = None;
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(LockUpdateEvent::ChangesDetected { diffs }) => {
+                    changed = diffs
+                        .iter()
+                        .map(|diff| diff.service().to_string())
+                        .collect();
+                }
+                Ok(_) => {}
+                Err(err) => error = Some(err),
+            }
+        }
+
+        assert!(error.is_some());
+        assert_eq!(changed, ["app.web"]);
+        let written = written_lock_file(lock_file)?;
+        assert_eq!(written.get("app.broken"), Some(&broken));
+        assert_eq!(written.get("app.web").unwrap().digest, registry.digest);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn check_reports_changes_without_writing() -> anyhow::Result<()> {
+        let registry = Registry::start();
+        let dir = tempfile::tempdir()?;
+        let lock_file = dir.path().join("nirion.lock");
+        let mut locked_images = LockedImages::default();
+        locked_images.insert(
+            "app.web".to_string(),
+            image(&registry.image, "1.0.0", OLD_DIGEST),
+        );
+
+        let events = collect_events(image_check_stream(
+            &context(registry.client(), locked_images, lock_file.clone()),
+            BTreeMap::from([("app.web".to_string(), registry.image.clone())]),
+            1,
+        ))
+        .await?;
+
+        assert!(
+            events.iter().any(|event| matches!(
+                event,
+                LockUpdateEvent::ChangesDetected { diffs }
+                    if matches!(diffs.as_slice(), [DiffEntry::Updated { service, new, .. }] if service == "app.web" && new.digest == registry.digest)
+            ))
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, LockUpdateEvent::WritingLockFile))
+        );
+        assert!(!lock_file.exists());
 
         Ok(())
     }
 
     #[tokio::test]
     async fn invalid_image_reference_returns_error() -> anyhow::Result<()> {
+        let registry = Registry::start();
         let dir = tempfile::tempdir()?;
         let lock_file = dir.path().join("nirion.lock");
         let result = collect_events(image_update_stream(
             &context(
-                http_nirion_client().build(),
+                registry.client(),
                 LockedImages::default(),
                 lock_file.clone(),
             ),
@@ -486,6 +848,8 @@ mod tests {
                 "not a valid image".to_string(),
             )]),
             1,
+            false,
+            false,
         ))
         .await;
 
@@ -500,29 +864,3 @@ mod tests {
         Ok(())
     }
 }
-
-async fn get_cached_updated_image(
-    client: &NirionOciClient,
-    versioned_image: &VersionedImage,
-    cache: &Arc<RwLock<HashMap<String, VersionedImage>>>,
-) -> anyhow::Result<VersionedImage> {
-    let image = versioned_image.image.as_str();
-
-    if let Some(existing) = {
-        let locked_cache = cache.read().await;
-        locked_cache.get(image).cloned()
-    } {
-        return Ok(existing);
-    }
-
-    let versioned_image = client
-        .get_updated_versioned_image(versioned_image)
-        .await?;
-
-    {
-        let mut locked_cache = cache.write().await;
-        locked_cache.insert(image.to_string(), versioned_image.clone());
-    }
-
-    Ok(versioned_image)
-}
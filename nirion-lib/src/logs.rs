@@ -1,6 +1,9 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
 
 use anyhow::Context;
+use chrono::{
+    DateTime, Days, FixedOffset, Local, SecondsFormat, TimeZone, Utc,
+};
 use futures::{StreamExt, channel::mpsc, stream::BoxStream};
 use tokio::{
     io::{AsyncBufReadExt, AsyncRead, BufReader},
@@ -11,7 +14,8 @@ use tokio::{
 use crate::{
     context::NirionContext,
     docker::{
-        ProjectStatus, ServiceState, query_project_status, status_stream,
+        ProjectStatus, ReplicaRange, ServiceState, query_project_status,
+        status_stream,
     },
     projects::{TargetSelector, selected_project_names},
 };
@@ -20,10 +24,81 @@ use crate::{
 pub struct LogStreamOptions {
     pub follow: bool,
     pub refresh_interval: Duration,
-    pub since: Option<String>,
-    pub until: Option<String>,
+    pub since: Option<LogTime>,
+    pub until: Option<LogTime>,
     pub tail: Option<String>,
     pub timestamps: bool,
+    /// Only these replicas of each selected service.
+    pub replicas: Option<ReplicaRange>,
+}
+
+/// A `--since` or `--until` bound. It is resolved to an instant once, so
+/// every container gets the same one, including those that only show up
+/// while following.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogTime(pub DateTime<Utc>);
+
+impl LogTime {
+    /// Reads `value` as a duration before `now` such as `1h30m`, an RFC
+    /// 3339 timestamp, or `today` or `yesterday` for the start of that
+    /// day in the time zone of `now`.
+    pub fn parse_at<Tz: TimeZone>(
+        value: &str,
+        now: DateTime<Tz>,
+    ) -> Result<Self, String> {
+        let days_ago = match value {
+            "today" => Some(0),
+            "yesterday" => Some(1),
+            _ => None,
+        };
+        if let Some(days) = days_ago {
+            let timezone = now.timezone();
+            return now
+                .date_naive()
+                .checked_sub_days(Days::new(days))
+                .and_then(|date| {
+                    timezone
+                        .from_local_datetime(&date.and_time(Default::default()))
+                        .earliest()
+                })
+                .map(|time| Self(time.with_timezone(&Utc)))
+                .ok_or_else(|| {
+                    format!("{value} has no start in this time zone")
+                });
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+            return Ok(Self(time.with_timezone(&Utc)));
+        }
+        humantime::parse_duration(value)
+            .ok()
+            .and_then(|duration| chrono::Duration::from_std(duration).ok())
+            .and_then(|duration| {
+                now.with_timezone(&Utc)
+                    .checked_sub_signed(duration)
+            })
+            .map(Self)
+            .ok_or_else(|| {
+                format!(
+                    "expected a duration such as 1h30m, an RFC 3339 timestamp \
+                     such as 2026-07-18T09:30:00Z, today or yesterday; got \
+                     {value}"
+                )
+            })
+    }
+
+    /// The timestamp passed to `docker logs`.
+    pub fn docker_arg(&self) -> String {
+        self.0
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+}
+
+impl FromStr for LogTime {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::parse_at(value, Local::now())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -148,7 +223,12 @@ impl FollowLogCoordinator {
         status: &ProjectStatus,
         readers: &mut JoinSet<Option<LogSource>>,
     ) {
-        let sources = sources_from_status(&self.target, project, status);
+        let sources = sources_from_status(
+            &self.target,
+            self.options.replicas,
+            project,
+            status,
+        );
         let current = sources
             .iter()
             .map(|source| (source.key(), source.clone()))
@@ -289,7 +369,7 @@ async fn bounded_logs(
     options: LogStreamOptions,
     tx: LogEventTx,
 ) {
-    match query_sources(&context, &target).await {
+    match query_sources(&context, &target, options.replicas).await {
         Ok(sources) => {
             for source in sources {
                 tokio::spawn(read_logs(
@@ -368,12 +448,14 @@ async fn follow_logs(
 async fn query_sources(
     context: &NirionContext,
     target: &TargetSelector,
+    replicas: Option<ReplicaRange>,
 ) -> anyhow::Result<Vec<LogSource>> {
     let mut sources = Vec::new();
 
     for project in selected_project_names(target, &context.projects) {
         let status = query_project_status(context, &project).await?;
-        sources.extend(sources_from_status(target, &project, &status));
+        sources
+            .extend(sources_from_status(target, replicas, &project, &status));
     }
 
     Ok(sources)
@@ -381,6 +463,7 @@ async fn query_sources(
 
 fn sources_from_status(
     target: &TargetSelector,
+    replicas: Option<ReplicaRange>,
     project: &str,
     status: &ProjectStatus,
 ) -> Vec<LogSource> {
@@ -388,7 +471,11 @@ fn sources_from_status(
         .services
         .iter()
         .filter(|(service, _)| service_selected(target, project, service))
-        .map(|(_, service)| {
+        .flat_map(|(_, replicas)| replicas)
+        .filter(|service| {
+            replicas.is_none_or(|replicas| replicas.contains(service.index))
+        })
+        .map(|service| {
             LogSource::new(
                 project,
                 service.service.clone(),
@@ -545,10 +632,14 @@ fn docker_logs_command(
         command.arg("--timestamps");
     }
     if let Some(since) = &options.since {
-        command.arg("--since").arg(since);
+        command
+            .arg("--since")
+            .arg(since.docker_arg());
     }
     if let Some(until) = &options.until {
-        command.arg("--until").arg(until);
+        command
+            .arg("--until")
+            .arg(until.docker_arg());
     }
     if let Some(tail) = &options.tail {
         command.arg("--tail").arg(tail);
@@ -561,6 +652,139 @@ fn docker_logs_command(
     command
 }
 
+/// The last `lines` lines a container wrote, stdout and stderr
+/// interleaved by their docker timestamps, e.g. to show why it failed.
+pub async fn tail_container_logs(
+    context: &NirionContext,
+    container_id: &str,
+    lines: usize,
+) -> anyhow::Result<Vec<String>> {
+    let output = context
+        .docker_command
+        .command()
+        .arg("logs")
+        .arg("--timestamps")
+        .arg("--tail")
+        .arg(lines.to_string())
+        .arg(container_id)
+        .output()
+        .await
+        .context("failed to execute docker logs")?;
+
+    // stderr carries the container's own stderr, so it is no error message.
+    if !output.status.success() {
+        anyhow::bail!("docker logs failed with status {}", output.status);
+    }
+
+    let mut merged = merge_timestamped_lines(
+        &String::from_utf8_lossy(&output.stdout),
+        &String::from_utf8_lossy(&output.stderr),
+    );
+    let skip = merged.len().saturating_sub(lines);
+    Ok(merged.split_off(skip))
+}
+
+/// Merges two streams of `docker logs --timestamps` lines and strips the
+/// timestamps. Lines without a parsable timestamp stay where their own
+/// stream put them.
+fn merge_timestamped_lines(
+    stdout: &str,
+    stderr: &str,
+) -> Vec<String> {
+    let split = |output: &str| {
+        output
+            .lines()
+            .map(split_timestamp)
+            .collect::<Vec<_>>()
+    };
+
+    let mut stdout = split(stdout).into_iter().peekable();
+    let mut stderr = split(stderr).into_iter().peekable();
+    let mut merged = Vec::new();
+    loop {
+        let take_stderr = match (stdout.peek(), stderr.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => true,
+            (Some(_), None) => false,
+            (Some((Some(out), _)), Some((Some(err), _))) => err < out,
+            _ => false,
+        };
+        let next = if take_stderr {
+            stderr.next()
+        } else {
+            stdout.next()
+        };
+        merged.extend(next.map(|(_, text)| text));
+    }
+    merged
+}
+
+/// A line collected for [`merge_log_lines`], with the docker timestamp
+/// split off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedLine {
+    pub source: LogSource,
+    pub stderr: bool,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub text: String,
+}
+
+/// Sorts `docker logs --timestamps` lines from several containers into
+/// one chronological stream. Each container's lines keep their order: a
+/// line without a timestamp, or with one older than the line before it,
+/// as from a clock that was set back, sorts right after that line.
+pub fn merge_log_lines(
+    lines: impl IntoIterator<Item = LogEvent>
+) -> Vec<MergedLine> {
+    let mut latest = BTreeMap::<(String, bool), DateTime<FixedOffset>>::new();
+    let mut merged = lines
+        .into_iter()
+        .filter_map(|event| match event {
+            LogEvent::StdoutLine(line) => Some((line, false)),
+            LogEvent::StderrLine(line) => Some((line, true)),
+            _ => None,
+        })
+        .map(|(line, stderr)| {
+            let (timestamp, text) = split_timestamp(&line.line);
+            let stream = (line.source.container_id.clone(), stderr);
+            let key = match (timestamp, latest.get(&stream)) {
+                (Some(timestamp), Some(previous)) if timestamp < *previous => {
+                    Some(*previous)
+                }
+                (Some(timestamp), _) => {
+                    latest.insert(stream, timestamp);
+                    Some(timestamp)
+                }
+                (None, previous) => previous.copied(),
+            };
+            let line = MergedLine {
+                source: line.source,
+                stderr,
+                timestamp,
+                text,
+            };
+            (key, line)
+        })
+        .collect::<Vec<_>>();
+
+    merged.sort_by_key(|(key, _)| *key);
+    merged
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect()
+}
+
+/// The leading RFC 3339 timestamp `docker logs --timestamps` adds, and
+/// the rest of the line.
+fn split_timestamp(line: &str) -> (Option<DateTime<FixedOffset>>, String) {
+    line.split_once(' ')
+        .and_then(|(timestamp, text)| {
+            let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+            Some((Some(timestamp), text.to_string()))
+        })
+        .unwrap_or_else(|| (None, line.to_string()))
+}
+
 async fn read_lines(
     stream: impl AsyncRead + Unpin + Send + 'static,
     event: fn(LogLine) -> LogEvent,
@@ -590,19 +814,14 @@ mod tests {
     };
     use futures::StreamExt;
     use nirion_oci_lib::client::NirionOciClient;
-    use std::{
-        fs,
-        os::unix::fs::PermissionsExt,
-        path::{Path, PathBuf},
-        sync::Arc,
-    };
+    use std::{fs, os::unix::fs::PermissionsExt, path::Path, sync::Arc};
     use tokio::io::AsyncWriteExt;
 
     fn context(docker_command: DockerCommand) -> NirionContext {
         NirionContext {
             projects: Default::default(),
             locked_images: LockedImages::default(),
-            lock_file: PathBuf::from("lock.json"),
+            lock_store: None,
             oci_client: Arc::new(NirionOciClient::builder().build()),
             docker_command,
         }
@@ -628,6 +847,7 @@ mod tests {
             until: None,
             tail: None,
             timestamps: false,
+            replicas: None,
         }
     }
 
@@ -660,24 +880,23 @@ mod tests {
             id: format!("{name}-id"),
             service: name.to_string(),
             container_name: format!("project-{name}-1"),
+            index: 1,
             image: "image".to_string(),
             state,
             health: None,
             exit_code,
             running_for: None,
+            created_at: None,
             status: None,
             ports: Vec::new(),
             networks: Vec::new(),
+            details: None,
+            last_health_check: None,
         }
     }
 
     fn status(services: Vec<ServiceStatus>) -> ProjectStatus {
-        ProjectStatus {
-            services: services
-                .into_iter()
-                .map(|service| (service.service.clone(), service))
-                .collect(),
-        }
+        ProjectStatus::from_containers(services)
     }
 
     #[test]
@@ -689,7 +908,7 @@ mod tests {
         ]);
 
         let sources =
-            sources_from_status(&TargetSelector::All, "project", &status);
+            sources_from_status(&TargetSelector::All, None, "project", &status);
 
         assert_eq!(sources.len(), 3);
         assert!(
@@ -726,6 +945,7 @@ mod tests {
             &TargetSelector::Project(ProjectSelector {
                 name: "project".to_string(),
             }),
+            None,
             "project",
             &status,
         );
@@ -736,6 +956,7 @@ mod tests {
                 project: "project".to_string(),
                 service: "web".to_string(),
             }),
+            None,
             "project",
             &status,
         );
@@ -746,12 +967,42 @@ mod tests {
             &TargetSelector::Project(ProjectSelector {
                 name: "other".to_string(),
             }),
+            None,
             "project",
             &status,
         );
         assert!(other_project_sources.is_empty());
     }
 
+    #[test]
+    fn sources_from_status_keeps_only_the_selected_replicas() {
+        let status = status(
+            (1..=3)
+                .map(|index| ServiceStatus {
+                    id: format!("web-{index}"),
+                    index,
+                    container_name: format!("project-web-{index}"),
+                    ..service("web", ServiceState::Running, None)
+                })
+                .collect(),
+        );
+
+        let sources = sources_from_status(
+            &TargetSelector::All,
+            Some("2-3".parse().unwrap()),
+            "project",
+            &status,
+        );
+
+        assert_eq!(
+            sources
+                .iter()
+                .map(|source| source.container_name.as_str())
+                .collect::<Vec<_>>(),
+            ["project-web-2", "project-web-3"]
+        );
+    }
+
     #[test]
     fn log_source_builds_lifecycle_and_line_events() {
         let running =
@@ -786,8 +1037,12 @@ mod tests {
         let context = context(DockerCommand::new("docker"));
         let mut options = options(true);
         options.timestamps = true;
-        options.since = Some("2026-07-18T00:00:00Z".to_string());
-        options.until = Some("2026-07-19T00:00:00Z".to_string());
+        options.since = Some("2026-07-18T00:00:00Z".parse().unwrap());
+        options.until = Some(
+            "2026-07-19T02:00:00+02:00"
+                .parse()
+                .unwrap(),
+        );
         options.tail = Some("42".to_string());
         let source = source();
 
@@ -814,6 +1069,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn log_time_accepts_durations_timestamps_and_day_shortcuts() {
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let now = offset
+            .with_ymd_and_hms(2026, 7, 18, 9, 30, 0)
+            .unwrap();
+        let parse =
+            |value| LogTime::parse_at(value, now).map(|time| time.docker_arg());
+
+        assert_eq!(parse("1h30m").unwrap(), "2026-07-18T06:00:00Z");
+        assert_eq!(parse("today").unwrap(), "2026-07-17T22:00:00Z");
+        assert_eq!(parse("yesterday").unwrap(), "2026-07-16T22:00:00Z");
+        assert_eq!(
+            parse("2026-07-18T09:30:00.5+02:00").unwrap(),
+            "2026-07-18T07:30:00.500Z"
+        );
+        assert!(
+            parse("1 hour ago")
+                .unwrap_err()
+                .starts_with("expected a duration such as 1h30m")
+        );
+    }
+
     #[tokio::test]
     async fn container_is_running_reads_docker_inspect_output() {
         let dir = tempfile::tempdir().unwrap();
@@ -1156,4 +1434,57 @@ esac
 
         assert_eq!(coordinator.attached[&key], new);
     }
+
+    #[test]
+    fn merged_tail_interleaves_stdout_and_stderr_by_timestamp() {
+        let stdout = "2024-05-01T10:00:00.1Z starting\n\
+                      2024-05-01T10:00:00.3Z listening";
+        let stderr = "2024-05-01T10:00:00.12Z config missing\n\
+                      no timestamp";
+
+        assert_eq!(
+            merge_timestamped_lines(stdout, stderr),
+            ["starting", "config missing", "listening", "no timestamp"]
+        );
+    }
+
+    #[test]
+    fn merged_lines_are_chronological_and_keep_per_container_order() {
+        let web = LogSource::new("app", "web", "w", "app-web-1", None, false);
+        let db = LogSource::new("app", "db", "d", "app-db-1", None, false);
+        let line = |source: &LogSource, line: &str| {
+            LogEvent::StdoutLine(source.log_line(line.to_string()))
+        };
+        let events = [
+            LogEvent::SourceAttached(web.clone()),
+            line(&web, "2026-03-01T10:00:01Z GET /"),
+            line(&web, "  at handler"),
+            line(&web, "2026-03-01T10:00:04Z GET /health"),
+            line(&db, "2026-03-01T10:00:02Z connection accepted"),
+            line(&db, "2026-03-01T09:59:00Z clock set back"),
+            line(&db, "2026-03-01T10:00:05Z checkpoint"),
+        ];
+
+        let merged = merge_log_lines(events);
+
+        assert_eq!(
+            merged
+                .iter()
+                .map(|line| (line.source.service.as_str(), line.text.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("web", "GET /"),
+                ("web", "  at handler"),
+                ("db", "connection accepted"),
+                ("db", "clock set back"),
+                ("web", "GET /health"),
+                ("db", "checkpoint"),
+            ]
+        );
+        assert_eq!(merged[1].timestamp, None);
+        assert_eq!(
+            merged[3].timestamp,
+            DateTime::parse_from_rfc3339("2026-03-01T09:59:00Z").ok()
+        );
+    }
 }
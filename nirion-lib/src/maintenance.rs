@@ -0,0 +1,200 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::state::current_user;
+
+pub const MAINTENANCE_FILE: &str = "maintenance.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceMarker {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub user: String,
+    pub since: DateTime<Utc>,
+    /// When the marker clears itself; `None` keeps it until it is
+    /// turned off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceMarker {
+    /// A marker set by the current user just now, lasting `duration` if
+    /// given. Times are kept to the second, which is how they are shown.
+    pub fn now(
+        reason: Option<String>,
+        duration: Option<Duration>,
+    ) -> anyhow::Result<Self> {
+        let since = Utc::now().trunc_subsecs(0);
+        let until = duration
+            .map(|duration| {
+                chrono::Duration::from_std(duration)
+                    .ok()
+                    .and_then(|duration| since.checked_add_signed(duration))
+                    .context("maintenance duration is too long")
+            })
+            .transpose()?;
+
+        Ok(Self {
+            reason,
+            user: current_user(),
+            since,
+            until,
+        })
+    }
+
+    fn expired_at(
+        &self,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.until
+            .is_some_and(|until| until <= now)
+    }
+}
+
+pub fn maintenance_file(state_dir: &Path) -> PathBuf {
+    state_dir.join(MAINTENANCE_FILE)
+}
+
+/// The active markers, keyed by project. Expired markers are left out,
+/// and a missing file has none.
+pub fn read_maintenance(
+    path: &Path
+) -> anyhow::Result<BTreeMap<String, MaintenanceMarker>> {
+    read_maintenance_at(path, Utc::now())
+}
+
+fn read_maintenance_at(
+    path: &Path,
+    now: DateTime<Utc>,
+) -> anyhow::Result<BTreeMap<String, MaintenanceMarker>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(BTreeMap::new());
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+
+    let mut markers: BTreeMap<String, MaintenanceMarker> =
+        serde_json::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+    markers.retain(|_, marker| !marker.expired_at(now));
+    Ok(markers)
+}
+
+/// Puts `project` into maintenance, replacing any marker it already has.
+pub fn set_maintenance(
+    path: &Path,
+    project: &str,
+    marker: MaintenanceMarker,
+) -> anyhow::Result<()> {
+    let mut markers = read_maintenance(path)?;
+    markers.insert(project.to_string(), marker);
+    write_maintenance(path, &markers)
+}
+
+/// Takes `project` out of maintenance, returning the marker it had, if
+/// it was still active.
+pub fn clear_maintenance(
+    path: &Path,
+    project: &str,
+) -> anyhow::Result<Option<MaintenanceMarker>> {
+    let mut markers = read_maintenance(path)?;
+    let removed = markers.remove(project);
+    write_maintenance(path, &markers)?;
+    Ok(removed)
+}
+
+/// Rewrites the file with `markers`, which also drops the expired ones
+/// that were left out when reading it.
+fn write_maintenance(
+    path: &Path,
+    markers: &BTreeMap<String, MaintenanceMarker>,
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!("failed to create {}", parent.display())
+        })?;
+    }
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(markers)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markers_are_set_read_back_and_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = maintenance_file(&dir.path().join("state"));
+        assert!(
+            read_maintenance(&path)
+                .unwrap()
+                .is_empty()
+        );
+
+        let marker =
+            MaintenanceMarker::now(Some("db migration".into()), None).unwrap();
+        set_maintenance(&path, "media", marker.clone()).unwrap();
+        set_maintenance(
+            &path,
+            "web",
+            MaintenanceMarker::now(None, None).unwrap(),
+        )
+        .unwrap();
+
+        let markers = read_maintenance(&path).unwrap();
+        assert_eq!(markers.keys().collect::<Vec<_>>(), ["media", "web"]);
+        assert_eq!(markers["media"], marker);
+
+        assert_eq!(clear_maintenance(&path, "media").unwrap(), Some(marker));
+        assert_eq!(clear_maintenance(&path, "media").unwrap(), None);
+        assert_eq!(
+            read_maintenance(&path)
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            ["web"]
+        );
+    }
+
+    #[test]
+    fn markers_clear_themselves_once_they_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = maintenance_file(dir.path());
+        let marker =
+            MaintenanceMarker::now(None, Some(Duration::from_secs(3600)))
+                .unwrap();
+        let until = marker.until.unwrap();
+        assert_eq!(until - marker.since, chrono::Duration::hours(1));
+        set_maintenance(&path, "media", marker).unwrap();
+
+        assert_eq!(
+            read_maintenance_at(&path, until - chrono::Duration::seconds(1))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            read_maintenance_at(&path, until)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
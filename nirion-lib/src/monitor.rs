@@ -0,0 +1,580 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures::{StreamExt, stream::BoxStream, stream::select_all};
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    context::NirionContext,
+    daemon::is_daemon_unreachable,
+    docker::{
+        DockerCommand, ProjectStatus, ProjectStatusEvent,
+        inspect_all_container_details, inspect_container_details,
+        query_project_status_for_command, query_project_statuses,
+    },
+    projects::{Project, TargetSelector, selected_project_names},
+};
+
+/// What a [`DockerProjectMonitor`] knows about its project.
+#[derive(Debug, Clone)]
+pub enum MonitorState {
+    /// The first query hasn't finished yet.
+    Pending,
+    /// The result of the latest successful query.
+    Status(ProjectStatus),
+    /// The first query failed. The monitor gives up, as a project that
+    /// can't be queried once rarely recovers on its own; only an
+    /// unreachable daemon is waited out.
+    Failed(Arc<anyhow::Error>),
+}
+
+/// Failures are only equal to themselves, so a new failure is always a
+/// change.
+impl PartialEq for MonitorState {
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool {
+        match (self, other) {
+            (MonitorState::Pending, MonitorState::Pending) => true,
+            (MonitorState::Status(left), MonitorState::Status(right)) => {
+                left == right
+            }
+            (MonitorState::Failed(left), MonitorState::Failed(right)) => {
+                Arc::ptr_eq(left, right)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl MonitorState {
+    pub fn status(&self) -> Option<&ProjectStatus> {
+        match self {
+            MonitorState::Status(status) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+/// Polls a single project in a background task, which stops when the
+/// monitor is dropped, or when the last of the monitors sharing it is.
+#[derive(Debug)]
+pub struct DockerProjectMonitor {
+    name: String,
+    state: watch::Receiver<MonitorState>,
+    /// Only held to keep the task polling.
+    _task: Arc<PollTask>,
+}
+
+/// Aborts the polling task once no monitor holds it anymore.
+#[derive(Debug)]
+struct PollTask(JoinHandle<()>);
+
+impl Drop for PollTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl DockerProjectMonitor {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The latest state, without waiting for the next poll.
+    pub fn state(&self) -> MonitorState {
+        self.state.borrow().clone()
+    }
+
+    /// The latest status, or `None` before the first successful query.
+    pub fn status(&self) -> Option<ProjectStatus> {
+        self.state.borrow().status().cloned()
+    }
+
+    /// A receiver that is notified when the state changes, or after every
+    /// poll if the monitor was built with
+    /// [`DockerMonitorBuilder::every_poll`].
+    pub fn subscribe(&self) -> watch::Receiver<MonitorState> {
+        self.state.clone()
+    }
+}
+
+async fn poll_project(
+    docker_command: DockerCommand,
+    project: Project,
+    options: DockerMonitorBuilder,
+    tx: watch::Sender<MonitorState>,
+) {
+    let mut first_poll = true;
+    // Polls start every `refresh_interval` rather than that long after
+    // the previous one finished; a slow daemon skips ticks instead of
+    // queueing a burst of queries.
+    let mut ticker = tokio::time::interval(options.refresh_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+        match query_project_status_for_command(&docker_command, &project).await
+        {
+            Ok(mut status) => {
+                // Details are a best-effort extra; a failed inspect
+                // should not hide the status itself.
+                if options.detailed {
+                    inspect_container_details(&docker_command, &mut status)
+                        .await
+                        .ok();
+                }
+                publish(&tx, status, options.every_poll);
+            }
+            // The daemon being away says nothing about the project, so
+            // the monitor keeps polling until it is back.
+            Err(error) if is_daemon_unreachable(&error) => {
+                if tx.is_closed() {
+                    return;
+                }
+                continue;
+            }
+            Err(error) if first_poll => {
+                tx.send_replace(MonitorState::Failed(Arc::new(error)));
+                return;
+            }
+            Err(_) => {}
+        }
+        if tx.is_closed() {
+            return;
+        }
+
+        first_poll = false;
+    }
+}
+
+/// Like [`poll_project`] for several projects at once, with one
+/// `docker ps` per poll instead of a `docker compose ps` per project.
+async fn poll_projects(
+    docker_command: DockerCommand,
+    projects: Vec<(String, Project, watch::Sender<MonitorState>)>,
+    options: DockerMonitorBuilder,
+) {
+    let mut first_poll = true;
+    let mut ticker = tokio::time::interval(options.refresh_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let all_closed = || {
+        projects
+            .iter()
+            .all(|(_, _, tx)| tx.is_closed())
+    };
+
+    loop {
+        ticker.tick().await;
+        let queried = query_project_statuses(
+            &docker_command,
+            projects
+                .iter()
+                .map(|(name, project, _)| (name.as_str(), project)),
+        )
+        .await;
+        match queried {
+            Ok(mut statuses) => {
+                if options.detailed {
+                    inspect_all_container_details(
+                        &docker_command,
+                        statuses.values_mut(),
+                    )
+                    .await
+                    .ok();
+                }
+                for (name, _, tx) in &projects {
+                    if let Some(status) = statuses.remove(name) {
+                        publish(tx, status, options.every_poll);
+                    }
+                }
+            }
+            Err(error) if is_daemon_unreachable(&error) => {
+                if all_closed() {
+                    return;
+                }
+                continue;
+            }
+            Err(error) if first_poll => {
+                let error = Arc::new(error);
+                for (_, _, tx) in &projects {
+                    tx.send_replace(MonitorState::Failed(error.clone()));
+                }
+                return;
+            }
+            Err(_) => {}
+        }
+        if all_closed() {
+            return;
+        }
+
+        first_poll = false;
+    }
+}
+
+fn publish(
+    tx: &watch::Sender<MonitorState>,
+    status: ProjectStatus,
+    every_poll: bool,
+) {
+    let state = MonitorState::Status(status);
+    if every_poll {
+        tx.send_replace(state);
+    } else {
+        tx.send_if_modified(|current| {
+            let changed = *current != state;
+            if changed {
+                *current = state;
+            }
+            changed
+        });
+    }
+}
+
+/// Builds a [`DockerMonitor`].
+#[derive(Debug, Clone)]
+pub struct DockerMonitorBuilder {
+    refresh_interval: Duration,
+    detailed: bool,
+    every_poll: bool,
+}
+
+impl DockerMonitorBuilder {
+    /// Also inspect the containers on every poll, so statuses carry
+    /// [`crate::docker::ContainerDetails`].
+    pub fn detailed(
+        mut self,
+        detailed: bool,
+    ) -> Self {
+        self.detailed = detailed;
+        self
+    }
+
+    /// Notify subscribers after every poll, even if nothing changed, for
+    /// consumers that look at more than the status itself.
+    pub fn every_poll(
+        mut self,
+        every_poll: bool,
+    ) -> Self {
+        self.every_poll = every_poll;
+        self
+    }
+
+    /// Starts polling `project`. Must be called from within a tokio
+    /// runtime.
+    pub fn spawn_project(
+        &self,
+        docker_command: DockerCommand,
+        name: impl Into<String>,
+        project: Project,
+    ) -> DockerProjectMonitor {
+        let (tx, rx) = watch::channel(MonitorState::Pending);
+        let task = tokio::spawn(poll_project(
+            docker_command,
+            project,
+            self.clone(),
+            tx,
+        ));
+
+        DockerProjectMonitor {
+            name: name.into(),
+            state: rx,
+            _task: Arc::new(PollTask(task)),
+        }
+    }
+
+    /// Starts a monitor for each project selected by `target`.
+    pub fn spawn(
+        self,
+        context: &NirionContext,
+        target: &TargetSelector,
+    ) -> DockerMonitor {
+        let names = selected_project_names(target, &context.projects);
+        self.spawn_projects(context, names)
+    }
+
+    /// Starts a monitor for each of the named projects, skipping names
+    /// that aren't in the project file. More than one project share a
+    /// single polling task.
+    pub fn spawn_projects(
+        self,
+        context: &NirionContext,
+        names: impl IntoIterator<Item = String>,
+    ) -> DockerMonitor {
+        let projects = names
+            .into_iter()
+            .filter_map(|name| {
+                let project = context.projects.get(&name)?.clone();
+                Some((name, project))
+            })
+            .collect::<Vec<_>>();
+
+        if projects.len() <= 1 {
+            return DockerMonitor {
+                projects: projects
+                    .into_iter()
+                    .map(|(name, project)| {
+                        self.spawn_project(
+                            context.docker_command.clone(),
+                            name,
+                            project,
+                        )
+                    })
+                    .collect(),
+            };
+        }
+
+        let (polled, receivers): (Vec<_>, Vec<_>) = projects
+            .into_iter()
+            .map(|(name, project)| {
+                let (tx, rx) = watch::channel(MonitorState::Pending);
+                ((name.clone(), project, tx), (name, rx))
+            })
+            .unzip();
+        let task = Arc::new(PollTask(tokio::spawn(poll_projects(
+            context.docker_command.clone(),
+            polled,
+            self,
+        ))));
+
+        DockerMonitor {
+            projects: receivers
+                .into_iter()
+                .map(|(name, state)| DockerProjectMonitor {
+                    name,
+                    state,
+                    _task: task.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Monitors for a set of projects, each polled until it is dropped.
+/// Several projects share one task that lists them with a single
+/// `docker ps`.
+#[derive(Debug)]
+pub struct DockerMonitor {
+    projects: Vec<DockerProjectMonitor>,
+}
+
+impl DockerMonitor {
+    pub fn builder(refresh_interval: Duration) -> DockerMonitorBuilder {
+        DockerMonitorBuilder {
+            refresh_interval,
+            detailed: false,
+            every_poll: false,
+        }
+    }
+
+    /// The monitors, ordered like the selected projects.
+    pub fn projects(&self) -> &[DockerProjectMonitor] {
+        &self.projects
+    }
+
+    pub fn project(
+        &self,
+        name: &str,
+    ) -> Option<&DockerProjectMonitor> {
+        self.projects
+            .iter()
+            .find(|monitor| monitor.name == name)
+    }
+
+    /// The latest status of every project that has been queried
+    /// successfully at least once.
+    pub fn snapshot(&self) -> BTreeMap<String, ProjectStatus> {
+        self.projects
+            .iter()
+            .filter_map(|monitor| {
+                Some((monitor.name.clone(), monitor.status()?))
+            })
+            .collect()
+    }
+
+    /// Turns the monitors into a stream with an event per notification of
+    /// [`DockerProjectMonitor::subscribe`]. A project whose first query
+    /// fails yields that error once.
+    pub fn into_events(
+        self
+    ) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
+        let streams = self
+            .projects
+            .into_iter()
+            .map(project_events)
+            .collect::<Vec<_>>();
+
+        select_all(streams).boxed()
+    }
+}
+
+fn project_events(
+    monitor: DockerProjectMonitor
+) -> BoxStream<'static, anyhow::Result<ProjectStatusEvent>> {
+    let updates = monitor.subscribe();
+    futures::stream::unfold(Some((monitor, updates)), |state| async move {
+        let (monitor, mut updates) = state?;
+        updates.changed().await.ok()?;
+
+        let state = updates.borrow_and_update().clone();
+        match state {
+            MonitorState::Pending => unreachable!("never sent"),
+            MonitorState::Status(status) => {
+                let event = ProjectStatusEvent {
+                    project: monitor.name.clone(),
+                    status,
+                };
+                Some((Ok(event), Some((monitor, updates))))
+            }
+            MonitorState::Failed(error) => {
+                Some((Err(anyhow::anyhow!("{error:#}")), None))
+            }
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lock::LockedImages, projects::ProjectSelector};
+    use nirion_oci_lib::client::NirionOciClient;
+    use std::fs;
+
+    fn context(script: &str) -> NirionContext {
+        NirionContext {
+            projects: serde_json::from_value(serde_json::json!({
+                "myapp": {
+                    "name": "myapp",
+                    "dockerCompose": "compose.yml",
+                    "services": {}
+                },
+                "other": {
+                    "name": "other",
+                    "dockerCompose": "other.yml",
+                    "services": {}
+                }
+            }))
+            .unwrap(),
+            locked_images: LockedImages::default(),
+            lock_store: None,
+            oci_client: Arc::new(NirionOciClient::builder().build()),
+            docker_command: DockerCommand::with_args("/bin/sh", [script]),
+        }
+    }
+
+    #[tokio::test]
+    async fn monitors_keep_the_latest_status_of_each_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        // Both projects come from one `docker ps`, told apart by label.
+        fs::write(
+            &script,
+            r#"[ "$1" = ps ] || exit 1
+for project in myapp other; do
+  printf '{"ID":"%s","Names":"%s-web-1","Image":"nginx","State":"running","Labels":"com.docker.compose.project=%s,com.docker.compose.service=web"}\n' "$project" "$project" "$project"
+done"#,
+        )
+        .unwrap();
+        let context = context(&script.to_string_lossy());
+
+        let monitor = DockerMonitor::builder(Duration::from_secs(60))
+            .spawn(&context, &TargetSelector::All);
+        for project in monitor.projects() {
+            project
+                .subscribe()
+                .wait_for(|state| state.status().is_some())
+                .await
+                .unwrap();
+        }
+
+        let snapshot = monitor.snapshot();
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), ["myapp", "other"]);
+        assert_eq!(snapshot["other"].services["web"][0].id, "other");
+        assert_eq!(
+            monitor
+                .project("myapp")
+                .and_then(|project| project.status())
+                .map(|status| status.services["web"][0].id.clone()),
+            Some("myapp".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_first_query_ends_the_project_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        fs::write(&script, "echo boom >&2; exit 3").unwrap();
+        let context = context(&script.to_string_lossy());
+
+        let monitor = DockerMonitor::builder(Duration::from_millis(10)).spawn(
+            &context,
+            &TargetSelector::Project(ProjectSelector {
+                name: "myapp".into(),
+            }),
+        );
+        let mut events = monitor.into_events();
+
+        let error = events
+            .next()
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(error.to_string().contains("boom"));
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_only_notified_of_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let id_file = dir.path().join("id");
+        fs::write(&id_file, "a").unwrap();
+        let script = dir.path().join("docker");
+        fs::write(
+            &script,
+            format!(
+                r#"printf '{{"ID":"%s","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running"}}\n' "$(cat '{}')""#,
+                id_file.display()
+            ),
+        )
+        .unwrap();
+        let context = context(&script.to_string_lossy());
+        let target = TargetSelector::Project(ProjectSelector {
+            name: "myapp".into(),
+        });
+
+        let changes = DockerMonitor::builder(Duration::from_millis(10))
+            .spawn(&context, &target);
+        let polls = DockerMonitor::builder(Duration::from_millis(10))
+            .every_poll(true)
+            .spawn(&context, &target);
+        let mut changes = changes.projects()[0].subscribe();
+        let mut polls = polls.projects()[0].subscribe();
+        for updates in [&mut changes, &mut polls] {
+            updates
+                .wait_for(|state| state.status().is_some())
+                .await
+                .unwrap();
+            updates.mark_unchanged();
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!changes.has_changed().unwrap());
+        assert!(polls.has_changed().unwrap());
+
+        fs::write(&id_file, "b").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changes.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changes
+                .borrow()
+                .status()
+                .unwrap()
+                .services["web"][0]
+                .id,
+            "b"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+
+/// Project and compose files may have been written on another platform,
+/// so both count, the way docker compose treats them on Windows.
+const SEPARATORS: &[char] = &['/', '\\'];
+
+/// Whether `path` is absolute in unix (`/srv`) or Windows (`C:\srv`,
+/// `C:/srv`, `\\server\share`) syntax.
+pub fn is_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    let drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && SEPARATORS.contains(&(bytes[2] as char));
+    drive || path.starts_with(SEPARATORS)
+}
+
+/// Resolves `path` the way compose resolves `env_file` and similar
+/// entries: absolute paths as they are, relative ones against the
+/// directory containing `file`.
+pub fn resolve_relative_to(
+    file: &Path,
+    path: &str,
+) -> PathBuf {
+    if is_absolute(path) {
+        return PathBuf::from(path);
+    }
+
+    let file = file.to_string_lossy();
+    match file.rfind(SEPARATORS) {
+        Some(index) => PathBuf::from(format!("{}{path}", &file[..=index])),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_paths_are_recognized_in_both_syntaxes() {
+        for path in [
+            "/srv/a.env",
+            "\\srv\\a.env",
+            "C:\\srv\\a.env",
+            "c:/srv/a.env",
+        ] {
+            assert!(is_absolute(path), "{path}");
+        }
+        for path in ["a.env", "./a.env", "..\\a.env", "C:a.env"] {
+            assert!(!is_absolute(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn relative_paths_join_the_directory_of_the_file() {
+        let cases = [
+            ("/srv/app/compose.yml", "a.env", "/srv/app/a.env"),
+            ("/srv/app/compose.yml", "/abs/b.env", "/abs/b.env"),
+            ("C:\\srv\\app\\compose.yml", "a.env", "C:\\srv\\app\\a.env"),
+            ("C:\\srv\\app\\compose.yml", "D:\\b.env", "D:\\b.env"),
+            (
+                "C:/srv/app/compose.yml",
+                "env\\a.env",
+                "C:/srv/app/env\\a.env",
+            ),
+            ("compose.yml", "a.env", "a.env"),
+        ];
+
+        for (file, path, expected) in cases {
+            assert_eq!(
+                resolve_relative_to(Path::new(file), path),
+                PathBuf::from(expected),
+                "{file} + {path}"
+            );
+        }
+    }
+}
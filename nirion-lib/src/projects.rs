@@ -2,13 +2,30 @@ use std::{
     collections::BTreeMap,
     fmt::Display,
     ops::{Deref, Index},
+    path::PathBuf,
+    str::FromStr,
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::Error as _};
+
+use crate::hooks::ProjectHooks;
 
 #[derive(Default, Clone)]
 pub struct Projects {
     projects: BTreeMap<String, Project>,
+    /// The compose project names rewritten by
+    /// [`ProjectNames::Normalize`], by project.
+    renamed: BTreeMap<String, ProjectName>,
+}
+
+/// What to do with compose project names docker compose would reject.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProjectNames {
+    /// Fail to load the project file.
+    #[default]
+    Reject,
+    /// Pass compose the [`ProjectName::normalized`] name instead.
+    Normalize,
 }
 
 impl<'de> Deserialize<'de> for Projects {
@@ -17,8 +34,7 @@ impl<'de> Deserialize<'de> for Projects {
         D: serde::Deserializer<'de>,
     {
         let projects = BTreeMap::<String, Project>::deserialize(deserializer)?;
-
-        Ok(Self { projects })
+        Self::new(projects, ProjectNames::Reject).map_err(D::Error::custom)
     }
 }
 
@@ -35,6 +51,91 @@ impl Serialize for Projects {
 }
 
 impl Projects {
+    fn new(
+        mut projects: BTreeMap<String, Project>,
+        names: ProjectNames,
+    ) -> Result<Self, String> {
+        // Selectors and lock file keys are `project.service`, split at the
+        // first dot, so only service names may contain dots.
+        if let Some(name) = projects
+            .keys()
+            .find(|name| name.contains('.'))
+        {
+            return Err(format!(
+                "project name '{name}' contains '.', which separates project \
+                 and service in selectors and lock file keys; rename the \
+                 project"
+            ));
+        }
+
+        let invalid = projects
+            .iter()
+            .filter(|(_, project)| !project.name.is_valid())
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        if invalid.is_empty() {
+            return Ok(Self {
+                projects,
+                renamed: BTreeMap::new(),
+            });
+        }
+
+        match names {
+            ProjectNames::Reject => {
+                let listed = invalid
+                    .iter()
+                    .map(|key| format!("'{}'", projects[key].name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Err(format!(
+                    "invalid compose project name(s) {listed}: docker compose \
+                     only accepts names matching {PROJECT_NAME_PATTERN}; \
+                     rename the project(s) or pass \
+                     --normalize-project-names"
+                ))
+            }
+            ProjectNames::Normalize => {
+                let mut renamed = BTreeMap::new();
+                for key in invalid {
+                    let project = projects
+                        .get_mut(&key)
+                        .expect("invalid names come from the map");
+                    let normalized = project.name.normalized();
+                    if !normalized.is_valid() {
+                        return Err(format!(
+                            "compose project name '{}' has no valid \
+                             normalized form; rename the project",
+                            project.name
+                        ));
+                    }
+                    renamed.insert(
+                        key,
+                        std::mem::replace(&mut project.name, normalized),
+                    );
+                }
+                Ok(Self { projects, renamed })
+            }
+        }
+    }
+
+    /// Parses a project file, handling invalid compose project names as
+    /// `names` says.
+    pub fn from_json(
+        json: &str,
+        names: ProjectNames,
+    ) -> anyhow::Result<Self> {
+        let projects = serde_json::from_str(json)?;
+        Self::new(projects, names).map_err(anyhow::Error::msg)
+    }
+
+    /// The projects whose compose project name was normalized, with the
+    /// name from the project file. Selectors keep using the project's key.
+    pub fn renamed(&self) -> impl Iterator<Item = (&str, &ProjectName)> {
+        self.renamed
+            .iter()
+            .map(|(key, original)| (key.as_str(), original))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (&str, &Project)> {
         self.projects
             .iter()
@@ -48,12 +149,40 @@ impl Projects {
         self.projects.contains_key(key)
     }
 
+    /// Whether the project file defines no projects at all, like the `{}`
+    /// a fresh NixOS module writes.
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty()
+    }
+
     pub fn get(
         &self,
         key: &str,
     ) -> Option<&Project> {
         self.projects.get(key)
     }
+
+    pub fn remove(
+        &mut self,
+        key: &str,
+    ) -> Option<Project> {
+        self.projects.remove(key)
+    }
+
+    /// Enables `profiles` in every project, on top of the profiles from
+    /// the project file.
+    pub fn enable_profiles(
+        &mut self,
+        profiles: &[String],
+    ) {
+        for project in self.projects.values_mut() {
+            for profile in profiles {
+                if !project.profiles.contains(profile) {
+                    project.profiles.push(profile.clone());
+                }
+            }
+        }
+    }
 }
 
 impl Index<&str> for Projects {
@@ -67,27 +196,97 @@ impl Index<&str> for Projects {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProjectSelector {
     pub name: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ServiceSelector {
     pub project: String,
     pub service: String,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TargetSelector {
     All,
     Project(ProjectSelector),
     Service(ServiceSelector),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl TargetSelector {
+    /// Whether `project.service` is one of the services this selects.
+    pub fn selects_service(
+        &self,
+        project: &str,
+        service: &str,
+    ) -> bool {
+        match self {
+            TargetSelector::All => true,
+            TargetSelector::Project(sel) => sel.name == project,
+            TargetSelector::Service(sel) => {
+                sel.project == project && sel.service == service
+            }
+        }
+    }
+}
+
+/// Formats like the selector is written on the command line.
+impl Display for TargetSelector {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            TargetSelector::All => write!(f, "*"),
+            TargetSelector::Project(sel) => write!(f, "{}", sel.name),
+            TargetSelector::Service(sel) => {
+                write!(f, "{}.{}", sel.project, sel.service)
+            }
+        }
+    }
+}
+
+/// The project names docker compose accepts.
+pub const PROJECT_NAME_PATTERN: &str = "^[a-z0-9][a-z0-9_-]*$";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ProjectName(pub String);
 
+impl ProjectName {
+    /// Whether docker compose accepts this as a project name, see
+    /// [`PROJECT_NAME_PATTERN`].
+    pub fn is_valid(&self) -> bool {
+        let mut chars = self.0.chars();
+        chars.next().is_some_and(|first| {
+            first.is_ascii_lowercase() || first.is_ascii_digit()
+        }) && chars.all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_'
+        })
+    }
+
+    /// The name the way docker compose normalizes names it derives
+    /// itself: lowercased, other characters dropped and leading `-` and
+    /// `_` trimmed. Empty if nothing valid is left.
+    pub fn normalized(&self) -> ProjectName {
+        let name = self
+            .0
+            .to_lowercase()
+            .chars()
+            .filter(|c| {
+                c.is_ascii_lowercase()
+                    || c.is_ascii_digit()
+                    || *c == '-'
+                    || *c == '_'
+            })
+            .collect::<String>();
+        ProjectName(
+            name.trim_start_matches(['-', '_'])
+                .to_string(),
+        )
+    }
+}
+
 impl Display for ProjectName {
     fn fmt(
         &self,
@@ -97,9 +296,9 @@ impl Display for ProjectName {
     }
 }
 
-impl Into<String> for ProjectName {
-    fn into(self) -> String {
-        self.0
+impl From<ProjectName> for String {
+    fn from(name: ProjectName) -> Self {
+        name.0
     }
 }
 
@@ -115,8 +314,37 @@ impl Deref for ProjectName {
 pub struct Project {
     pub name: ProjectName,
     #[serde(rename = "dockerCompose")]
-    pub docker_compose: String,
+    pub docker_compose: PathBuf,
     pub services: BTreeMap<String, Service>,
+    /// Compose profiles enabled for every compose invocation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
+    /// Commands run before and after `up` and `down`.
+    #[serde(default, skip_serializing_if = "ProjectHooks::is_empty")]
+    pub hooks: ProjectHooks,
+}
+
+impl Project {
+    /// The `--profile` arguments for this project's enabled profiles.
+    pub fn profile_args(&self) -> Vec<String> {
+        self.profiles
+            .iter()
+            .flat_map(|profile| ["--profile".to_string(), profile.clone()])
+            .collect()
+    }
+
+    /// Whether compose creates `service` with this project's profiles.
+    /// Services without profiles are always enabled.
+    pub fn service_enabled(
+        &self,
+        service: &Service,
+    ) -> bool {
+        service.profiles.is_empty()
+            || service
+                .profiles
+                .iter()
+                .any(|profile| self.profiles.contains(profile))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -127,6 +355,33 @@ pub struct Service {
     #[serde(default)]
     pub healthcheck: bool,
     pub restart: Option<String>,
+    /// Compose profiles gating this service; empty if it always runs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
+    /// `nirion lint` findings suppressed for this service, by code or
+    /// name.
+    #[serde(
+        rename = "lintIgnore",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub lint_ignore: Vec<String>,
+    /// Built locally from a `build:` section, so its image is never
+    /// looked up in a registry, locked or updated.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub build: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Service {
+    /// Services without a restart policy (or with `restart: "no"`) are
+    /// expected to run once and exit, like migrations or init jobs.
+    pub fn is_one_shot(&self) -> bool {
+        matches!(self.restart.as_deref(), None | Some("no"))
+    }
 }
 
 pub fn parse_selector(
@@ -137,11 +392,16 @@ pub fn parse_selector(
     if s == "*" {
         return Ok(TargetSelector::All);
     }
+    if projects.is_empty() {
+        anyhow::bail!(
+            "Target '{s}' not found: the project file defines no projects"
+        );
+    }
 
     let parts: Vec<&str> = s.splitn(2, '.').collect();
     match parts.as_slice() {
         [project_name] => {
-            if projects.contains_key(*project_name) {
+            if projects.contains_key(project_name) {
                 Ok(TargetSelector::Project(ProjectSelector {
                     name: project_name.to_string(),
                 }))
@@ -150,7 +410,7 @@ pub fn parse_selector(
             }
         }
         [project_name, service_name] => {
-            if let Some(proj) = projects.get(*project_name) {
+            if let Some(proj) = projects.get(project_name) {
                 if proj
                     .services
                     .contains_key(*service_name)
@@ -159,6 +419,11 @@ pub fn parse_selector(
                         project: project_name.to_string(),
                         service: service_name.to_string(),
                     }))
+                } else if proj.services.is_empty() {
+                    anyhow::bail!(
+                        "Service '{service_name}' not found: project \
+                         '{project_name}' defines no services"
+                    );
                 } else {
                     anyhow::bail!(
                         "Service '{}' not found in project '{}'",
@@ -188,41 +453,195 @@ pub fn parse_service_selector(
     }
 }
 
+/// One service's configured image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceImage {
+    pub project: String,
+    pub service: String,
+    pub image: String,
+}
+
+impl ServiceImage {
+    /// The `project.service` identifier lock files and selectors use.
+    pub fn service_ref(&self) -> String {
+        format!("{}.{}", self.project, self.service)
+    }
+}
+
+/// The images of every service selected by `target`, ordered by project
+/// then service. Services without an image or built locally are skipped.
+pub fn get_service_images(
+    target: &TargetSelector,
+    projects: &Projects,
+) -> anyhow::Result<Vec<ServiceImage>> {
+    select_service_images(target, projects, false)
+}
+
+/// The images of the services selected by `target` that are marked as
+/// built locally, which [`get_service_images`] leaves out.
+pub fn get_local_build_images(
+    target: &TargetSelector,
+    projects: &Projects,
+) -> anyhow::Result<Vec<ServiceImage>> {
+    select_service_images(target, projects, true)
+}
+
+fn select_service_images(
+    target: &TargetSelector,
+    projects: &Projects,
+    local_builds: bool,
+) -> anyhow::Result<Vec<ServiceImage>> {
+    let project = |name: &str| {
+        projects
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Project '{name}' not found"))
+    };
+    let service_image = |project: &str, service: &str, config: &Service| {
+        config
+            .image
+            .as_ref()
+            .filter(|_| config.build == local_builds)
+            .map(|image| ServiceImage {
+                project: project.to_string(),
+                service: service.to_string(),
+                image: image.clone(),
+            })
+    };
+
+    let images = match target {
+        TargetSelector::All => projects
+            .iter()
+            .flat_map(|(project_name, project)| {
+                project.services.iter().filter_map(
+                    move |(service_name, service)| {
+                        service_image(project_name, service_name, service)
+                    },
+                )
+            })
+            .collect(),
+        TargetSelector::Project(selector) => project(&selector.name)?
+            .services
+            .iter()
+            .filter_map(|(service_name, service)| {
+                service_image(&selector.name, service_name, service)
+            })
+            .collect(),
+        TargetSelector::Service(selector) => {
+            let service = project(&selector.project)?
+                .services
+                .get(&selector.service)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Service '{}' not found in project '{}'",
+                        selector.service,
+                        selector.project
+                    )
+                })?;
+            service_image(&selector.project, &selector.service, service)
+                .into_iter()
+                .collect()
+        }
+    };
+
+    Ok(images)
+}
+
+/// [`get_service_images`] keyed by `project.service`.
 pub fn get_images(
     target: &TargetSelector,
     projects: &Projects,
-) -> BTreeMap<String, String> {
-    let mut images = BTreeMap::new();
-    match target {
-        TargetSelector::All => {
-            for (project_name, project) in projects.iter() {
-                for (service_name, service) in project.services.iter() {
-                    let identifier = format!("{project_name}.{service_name}");
-                    if let Some(image) = &service.image {
-                        images.insert(identifier, image.to_string());
-                    };
-                }
-            }
+) -> anyhow::Result<BTreeMap<String, String>> {
+    Ok(get_service_images(target, projects)?
+        .into_iter()
+        .map(|image| (image.service_ref(), image.image))
+        .collect())
+}
+
+/// An `--image` filter on image references: a glob with `*` and `?` that
+/// must match the whole repository, or otherwise a substring of it. Tags
+/// and digests are ignored on both sides, so `redis` and `*/redis:7` both
+/// match `docker.io/library/redis:7.2`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImagePattern(String);
+
+impl FromStr for ImagePattern {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let pattern = repository(value.trim());
+        if pattern.is_empty() {
+            return Err(format!("expected an image pattern, got `{value}`"));
         }
-        TargetSelector::Project(proj) => {
-            let project = &projects.get(&proj.name).unwrap();
-            for (service_name, service) in project.services.iter() {
-                let identifier = format!("{}.{}", proj.name, service_name);
-                if let Some(image) = &service.image {
-                    images.insert(identifier, image.to_string());
-                };
-            }
+        Ok(Self(pattern.to_string()))
+    }
+}
+
+impl Display for ImagePattern {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ImagePattern {
+    pub fn matches(
+        &self,
+        image: &str,
+    ) -> bool {
+        let repository = repository(image);
+        if self.0.contains(['*', '?']) {
+            glob_matches(self.0.as_bytes(), repository.as_bytes())
+        } else {
+            repository.contains(&self.0)
+        }
+    }
+}
+
+/// `image` without its tag and digest.
+fn repository(image: &str) -> &str {
+    let image = image
+        .split_once('@')
+        .map_or(image, |(name, _)| name);
+    match image.rfind(':') {
+        Some(colon) if !image[colon..].contains('/') => &image[..colon],
+        _ => image,
+    }
+}
+
+fn glob_matches(
+    pattern: &[u8],
+    text: &[u8],
+) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, _) => text.is_empty(),
+        (Some((b'*', rest)), _) => {
+            glob_matches(rest, text)
+                || (!text.is_empty() && glob_matches(pattern, &text[1..]))
         }
-        TargetSelector::Service(img) => {
-            let project = &projects.get(&img.project).unwrap();
-            let service = &project.services[&img.service];
-            let identifier = format!("{}.{}", img.project, img.service);
-            if let Some(image) = &service.image {
-                images.insert(identifier, image.to_string());
-            };
+        (Some((b'?', rest)), Some((_, text))) => glob_matches(rest, text),
+        (Some((expected, rest)), Some((actual, text))) => {
+            expected == actual && glob_matches(rest, text)
         }
+        (Some(_), None) => false,
+    }
+}
+
+/// Keeps the images matched by any of `patterns`; all of them if there
+/// are none.
+pub fn retain_images_matching(
+    images: &mut BTreeMap<String, String>,
+    patterns: &[ImagePattern],
+) {
+    if patterns.is_empty() {
+        return;
     }
-    images
+    images.retain(|_, image| {
+        patterns
+            .iter()
+            .any(|pattern| pattern.matches(image))
+    });
 }
 
 pub fn selected_project_names(
@@ -258,6 +677,9 @@ mod tests {
                             resolved_image: None,
                             healthcheck: true,
                             restart: None,
+                            profiles: vec![],
+                            lint_ignore: Vec::new(),
+                            build: false,
                         },
                     ),
                     (
@@ -267,10 +689,15 @@ mod tests {
                             resolved_image: None,
                             healthcheck: false,
                             restart: None,
+                            profiles: vec![],
+                            lint_ignore: Vec::new(),
+                            build: false,
                         },
                     ),
                 ]
                 .into(),
+                profiles: vec![],
+                hooks: ProjectHooks::default(),
             },
         );
         projects.projects.insert(
@@ -285,9 +712,14 @@ mod tests {
                         resolved_image: None,
                         healthcheck: true,
                         restart: Some("always".into()),
+                        profiles: vec![],
+                        lint_ignore: Vec::new(),
+                        build: false,
                     },
                 )]
                 .into(),
+                profiles: vec![],
+                hooks: ProjectHooks::default(),
             },
         );
         projects
@@ -341,6 +773,34 @@ mod tests {
         assert!(parse_selector("nonexistent.web", &projects).is_err());
     }
 
+    #[test]
+    fn parse_selector_mentions_empty_project_files_and_projects() {
+        assert_eq!(
+            parse_selector("myapp", &Projects::default())
+                .unwrap_err()
+                .to_string(),
+            "Target 'myapp' not found: the project file defines no projects"
+        );
+        assert!(matches!(
+            parse_selector("*", &Projects::default()),
+            Ok(TargetSelector::All)
+        ));
+
+        let mut projects = test_projects();
+        projects
+            .projects
+            .get_mut("myapp")
+            .unwrap()
+            .services
+            .clear();
+        assert_eq!(
+            parse_selector("myapp.web", &projects)
+                .unwrap_err()
+                .to_string(),
+            "Service 'web' not found: project 'myapp' defines no services"
+        );
+    }
+
     #[test]
     fn parse_selector_trims_whitespace() {
         let projects = test_projects();
@@ -371,7 +831,7 @@ mod tests {
     #[test]
     fn get_images_all() {
         let projects = test_projects();
-        let images = get_images(&TargetSelector::All, &projects);
+        let images = get_images(&TargetSelector::All, &projects).unwrap();
         assert_eq!(images.len(), 3);
         assert_eq!(images["api.server"], "node:20");
         assert_eq!(images["myapp.web"], "nginx:latest");
@@ -384,7 +844,7 @@ mod tests {
         let sel = TargetSelector::Project(ProjectSelector {
             name: "myapp".into(),
         });
-        let images = get_images(&sel, &projects);
+        let images = get_images(&sel, &projects).unwrap();
         assert_eq!(images.len(), 2);
         assert!(images.contains_key("myapp.web"));
         assert!(images.contains_key("myapp.db"));
@@ -397,7 +857,7 @@ mod tests {
             project: "myapp".into(),
             service: "web".into(),
         });
-        let images = get_images(&sel, &projects);
+        let images = get_images(&sel, &projects).unwrap();
         assert_eq!(images.len(), 1);
         assert_eq!(images["myapp.web"], "nginx:latest");
     }
@@ -417,13 +877,234 @@ mod tests {
                     resolved_image: None,
                     healthcheck: false,
                     restart: None,
+                    profiles: vec![],
+                    lint_ignore: Vec::new(),
+                    build: false,
                 },
             );
-        let images = get_images(&TargetSelector::All, &projects);
+        let images = get_images(&TargetSelector::All, &projects).unwrap();
         assert_eq!(images.len(), 3);
         assert!(!images.contains_key("myapp.worker"));
     }
 
+    #[test]
+    fn local_builds_are_listed_apart_from_registry_images() {
+        let mut projects = test_projects();
+        let web = projects
+            .projects
+            .get_mut("myapp")
+            .unwrap()
+            .services
+            .get_mut("web")
+            .unwrap();
+        web.image = Some("myapp-web".into());
+        web.build = true;
+
+        let images = get_images(&TargetSelector::All, &projects).unwrap();
+        assert!(!images.contains_key("myapp.web"));
+        assert!(images.contains_key("myapp.db"));
+
+        let builds =
+            get_local_build_images(&TargetSelector::All, &projects).unwrap();
+        assert_eq!(
+            builds,
+            [ServiceImage {
+                project: "myapp".into(),
+                service: "web".into(),
+                image: "myapp-web".into(),
+            }]
+        );
+
+        let json = serde_json::to_value(&projects).unwrap();
+        assert_eq!(json["myapp"]["services"]["web"]["build"], true);
+        assert!(
+            json["myapp"]["services"]["db"]
+                .get("build")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn get_service_images_keeps_project_and_service_apart() {
+        let mut projects = test_projects();
+        let mut web = projects.projects["myapp"].services["web"].clone();
+        web.image = Some("nginx:2".into());
+        projects
+            .projects
+            .get_mut("myapp")
+            .unwrap()
+            .services
+            .insert("web.v2".into(), web);
+
+        let images = get_service_images(
+            &TargetSelector::Project(ProjectSelector {
+                name: "myapp".into(),
+            }),
+            &projects,
+        )
+        .unwrap();
+
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[2].project, "myapp");
+        assert_eq!(images[2].service, "web.v2");
+        assert_eq!(images[2].service_ref(), "myapp.web.v2");
+        assert_eq!(
+            get_images(&TargetSelector::All, &projects).unwrap()["myapp.web.v2"],
+            "nginx:2"
+        );
+    }
+
+    #[test]
+    fn deserialization_rejects_dotted_project_names() {
+        let error = serde_json::from_str::<Projects>(
+            r#"{"my.app": {"name": "my.app", "dockerCompose": "c.yml", "services": {}}}"#,
+        )
+        .err()
+        .unwrap();
+
+        assert!(
+            error
+                .to_string()
+                .contains("project name 'my.app'")
+        );
+    }
+
+    #[test]
+    fn project_names_follow_compose_rules() {
+        for name in ["myapp", "my-app_2", "0app"] {
+            assert!(ProjectName(name.into()).is_valid(), "{name}");
+        }
+        for name in ["", "MyApp", "my app", "-app", "_app", "äpp"] {
+            assert!(!ProjectName(name.into()).is_valid(), "{name}");
+        }
+
+        assert_eq!(
+            ProjectName("_My App.v2".into()).normalized(),
+            ProjectName("myappv2".into())
+        );
+        assert_eq!(
+            ProjectName("--".into()).normalized(),
+            ProjectName(String::new())
+        );
+    }
+
+    const INVALID_NAMES: &str = r#"{
+        "MyApp": {"name": "MyApp", "dockerCompose": "a.yml", "services": {}},
+        "ok": {"name": "ok", "dockerCompose": "b.yml", "services": {}},
+        "web": {"name": "Web Site", "dockerCompose": "c.yml", "services": {}}
+    }"#;
+
+    #[test]
+    fn invalid_compose_names_are_all_listed() {
+        let error = serde_json::from_str::<Projects>(INVALID_NAMES)
+            .err()
+            .unwrap()
+            .to_string();
+
+        assert!(error.contains("'MyApp', 'Web Site'"), "{error}");
+        assert!(error.contains(PROJECT_NAME_PATTERN), "{error}");
+        assert!(
+            Projects::from_json(INVALID_NAMES, ProjectNames::Reject).is_err()
+        );
+    }
+
+    #[test]
+    fn normalized_names_keep_their_selectors_and_round_trip() {
+        let projects =
+            Projects::from_json(INVALID_NAMES, ProjectNames::Normalize)
+                .unwrap();
+
+        assert_eq!(
+            projects.renamed().collect::<Vec<_>>(),
+            [
+                ("MyApp", &ProjectName("MyApp".into())),
+                ("web", &ProjectName("Web Site".into())),
+            ]
+        );
+        assert_eq!(projects["MyApp"].name, ProjectName("myapp".into()));
+        assert_eq!(projects["web"].name, ProjectName("website".into()));
+        assert_eq!(
+            parse_selector("MyApp", &projects).unwrap(),
+            TargetSelector::Project(ProjectSelector {
+                name: "MyApp".into(),
+            })
+        );
+
+        let json = serde_json::to_string(&projects).unwrap();
+        let reloaded = serde_json::from_str::<Projects>(&json).unwrap();
+        assert_eq!(reloaded.renamed().count(), 0);
+        assert_eq!(
+            reloaded
+                .iter()
+                .map(|(key, project)| (key, project.name.to_string()))
+                .collect::<Vec<_>>(),
+            [
+                ("MyApp", "myapp".to_string()),
+                ("ok", "ok".to_string()),
+                ("web", "website".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn image_patterns_match_repositories_regardless_of_tag() {
+        let pattern = |value: &str| value.parse::<ImagePattern>().unwrap();
+
+        assert!(pattern("redis").matches("redis:7"));
+        assert!(pattern("redis").matches("docker.io/library/redis@sha256:aa"));
+        assert!(pattern("redis:6").matches("redis:7"));
+        assert!(!pattern("redis").matches("postgres:16"));
+        assert!(pattern("*/redis").matches("docker.io/library/redis:7"));
+        assert!(!pattern("*/redis").matches("ghcr.io/acme/redis-exporter"));
+        assert!(pattern("ghcr.io/acme/*").matches("ghcr.io/acme/web:1"));
+        assert!(pattern("localhost:5000/ap?").matches("localhost:5000/app:1"));
+        assert!("".parse::<ImagePattern>().is_err());
+
+        let mut images =
+            get_images(&TargetSelector::All, &test_projects()).unwrap();
+        retain_images_matching(
+            &mut images,
+            &[pattern("nginx"), pattern("node")],
+        );
+        assert_eq!(
+            images.keys().collect::<Vec<_>>(),
+            ["api.server", "myapp.web"]
+        );
+    }
+
+    #[test]
+    fn selectors_split_at_first_dot_so_services_may_contain_dots() {
+        let projects: Projects = serde_json::from_str(
+            r#"{"app": {"name": "app", "dockerCompose": "c.yml", "services": {
+                "web.v2": {"image": "nginx", "healthcheck": false, "restart": null}}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            parse_selector("app.web.v2", &projects).unwrap(),
+            TargetSelector::Service(ServiceSelector {
+                project: "app".into(),
+                service: "web.v2".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn get_service_images_errors_on_unknown_targets() {
+        let projects = test_projects();
+
+        let missing_project = TargetSelector::Project(ProjectSelector {
+            name: "nope".into(),
+        });
+        let missing_service = TargetSelector::Service(ServiceSelector {
+            project: "myapp".into(),
+            service: "nope".into(),
+        });
+
+        assert!(get_service_images(&missing_project, &projects).is_err());
+        assert!(get_service_images(&missing_service, &projects).is_err());
+    }
+
     #[test]
     fn selected_project_names_all() {
         let projects = test_projects();
@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, time::Instant};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct LayerProgress {
+    current: u64,
+    total: Option<u64>,
+    done: bool,
+}
+
+/// Layer download progress scraped from the plain progress lines docker
+/// compose writes while pulling images, e.g.
+/// `a2abf6c4d29d Downloading [==>   ]  1.2MB/31.4MB`.
+#[derive(Debug, Clone, Default)]
+pub struct PullProgress {
+    layers: BTreeMap<String, LayerProgress>,
+    started: Option<Instant>,
+    last_update: Option<Instant>,
+}
+
+impl PullProgress {
+    /// Feeds one line of compose output, returning whether it was a
+    /// layer progress line.
+    pub fn observe(
+        &mut self,
+        line: &str,
+        now: Instant,
+    ) -> bool {
+        let Some((id, rest)) = line.trim().split_once(' ') else {
+            return false;
+        };
+        if !is_layer_id(id) {
+            return false;
+        }
+
+        let rest = rest.trim();
+        let layer = self
+            .layers
+            .entry(id.to_string())
+            .or_default();
+
+        if let Some(progress) = rest.strip_prefix("Downloading") {
+            if let Some((current, total)) = parse_transfer(progress) {
+                layer.current = current;
+                layer.total = Some(total);
+                self.started.get_or_insert(now);
+                self.last_update = Some(now);
+            }
+        } else if rest.starts_with("Download complete")
+            || rest.starts_with("Verifying Checksum")
+        {
+            if let Some(total) = layer.total {
+                layer.current = total;
+            }
+            self.last_update = Some(now);
+        } else if rest.starts_with("Pull complete")
+            || rest.starts_with("Already exists")
+        {
+            if let Some(total) = layer.total {
+                layer.current = total;
+            }
+            layer.done = true;
+        }
+
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn layers_total(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn layers_done(&self) -> usize {
+        self.layers
+            .values()
+            .filter(|layer| layer.done)
+            .count()
+    }
+
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.layers
+            .values()
+            .map(|layer| layer.current)
+            .sum()
+    }
+
+    /// Average download rate in bytes per second since the first
+    /// downloaded byte, measured up to the latest download update.
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        let elapsed = self
+            .last_update?
+            .duration_since(self.started?)
+            .as_secs_f64();
+        (elapsed > 0.0).then(|| self.downloaded_bytes() as f64 / elapsed)
+    }
+}
+
+fn is_layer_id(id: &str) -> bool {
+    id.len() == 12
+        && id
+            .chars()
+            .all(|c| c.is_ascii_hexdigit())
+}
+
+/// Parses the `1.2MB/31.4MB` tail of a download line, skipping the bar.
+fn parse_transfer(progress: &str) -> Option<(u64, u64)> {
+    let transfer = progress.rsplit(']').next()?.trim();
+    let (current, total) = transfer.split_once('/')?;
+    Some((parse_size(current)?, parse_size(total)?))
+}
+
+/// Parses docker's decimal human sizes such as `512B`, `1.049kB` or
+/// `29.13MB`.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.trim().parse().ok()?;
+
+    let factor = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "tb" => 1e12,
+        _ => return None,
+    };
+
+    Some((number * factor) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_size_handles_docker_units() {
+        assert_eq!(parse_size("512B"), Some(512));
+        assert_eq!(parse_size("1.5kB"), Some(1500));
+        assert_eq!(parse_size("29.13MB"), Some(29_130_000));
+        assert_eq!(parse_size("2GB"), Some(2_000_000_000));
+        assert_eq!(parse_size("12 parsecs"), None);
+    }
+
+    #[test]
+    fn ignores_lines_that_are_not_layer_progress() {
+        let mut progress = PullProgress::default();
+        let now = Instant::now();
+
+        assert!(!progress.observe(" web Pulling ", now));
+        assert!(!progress.observe(" Container app-web-1  Started", now));
+        assert!(!progress.observe("", now));
+        assert!(progress.is_empty());
+    }
+
+    #[test]
+    fn tracks_layers_bytes_and_rate() {
+        let mut progress = PullProgress::default();
+        let start = Instant::now();
+
+        for line in [
+            " a2abf6c4d29d Pulling fs layer ",
+            " 8a1e25ce7c4f Already exists ",
+            " a2abf6c4d29d Downloading [>      ]  1MB/10MB",
+            " b4b2d4ab1fc7 Pulling fs layer ",
+        ] {
+            assert!(progress.observe(line, start));
+        }
+        progress.observe(
+            " a2abf6c4d29d Downloading [===>   ]  5MB/10MB",
+            start + Duration::from_secs(2),
+        );
+
+        assert_eq!(progress.layers_total(), 3);
+        assert_eq!(progress.layers_done(), 1);
+        assert_eq!(progress.downloaded_bytes(), 5_000_000);
+        assert_eq!(progress.bytes_per_second(), Some(2_500_000.0));
+
+        progress.observe(
+            " a2abf6c4d29d Pull complete ",
+            start + Duration::from_secs(3),
+        );
+
+        assert_eq!(progress.layers_done(), 2);
+        assert_eq!(progress.downloaded_bytes(), 10_000_000);
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use nirion_oci_lib::{
+    auth::RegistryAuth, client::AuthConfig, oci_client::Reference,
+};
+use serde::Serialize;
+
+use crate::projects::{Projects, TargetSelector, get_service_images};
+
+/// The registry docker.io references resolve to.
+pub const DOCKER_HUB_REGISTRY: &str = "index.docker.io";
+
+/// How the configured images use one registry.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegistryUsage {
+    pub registry: String,
+    /// `project.service` to image reference.
+    pub services: BTreeMap<String, String>,
+    /// Services whose image has credentials configured.
+    pub authenticated: Vec<String>,
+}
+
+impl RegistryUsage {
+    pub fn image_count(&self) -> usize {
+        let mut images = self
+            .services
+            .values()
+            .collect::<Vec<_>>();
+        images.sort();
+        images.dedup();
+        images.len()
+    }
+
+    pub fn is_docker_hub(&self) -> bool {
+        self.registry == DOCKER_HUB_REGISTRY
+    }
+
+    /// Registries other than Docker Hub are assumed private, so every
+    /// service pulling from them should have credentials.
+    pub fn missing_auth(&self) -> bool {
+        !self.is_docker_hub() && self.authenticated.len() < self.services.len()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegistryReport {
+    pub registries: Vec<RegistryUsage>,
+    /// `project.service` to image references that failed to parse.
+    pub invalid: BTreeMap<String, String>,
+}
+
+/// Groups every configured service image by its resolved registry.
+pub fn registry_report(
+    projects: &Projects,
+    auth: &AuthConfig,
+) -> anyhow::Result<RegistryReport> {
+    let mut registries = BTreeMap::<String, RegistryUsage>::new();
+    let mut invalid = BTreeMap::new();
+
+    for service_image in get_service_images(&TargetSelector::All, projects)? {
+        let service = service_image.service_ref();
+        let image = service_image.image;
+        let Ok(reference) = image.parse::<Reference>() else {
+            invalid.insert(service, image);
+            continue;
+        };
+
+        let registry = reference.resolve_registry().to_string();
+        let usage = registries
+            .entry(registry.clone())
+            .or_insert_with(|| RegistryUsage {
+                registry,
+                ..Default::default()
+            });
+        if auth.auth_for(&reference) != RegistryAuth::Anonymous {
+            usage
+                .authenticated
+                .push(service.clone());
+        }
+        usage.services.insert(service, image);
+    }
+
+    Ok(RegistryReport {
+        registries: registries.into_values().collect(),
+        invalid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projects() -> Projects {
+        serde_json::from_str(
+            r#"{
+  "app": {"name": "app", "dockerCompose": "a.yml", "services": {
+    "web": {"image": "nginx:latest", "healthcheck": false, "restart": null},
+    "api": {"image": "ghcr.io/acme/api:1", "healthcheck": false, "restart": null},
+    "worker": {"image": "ghcr.io/acme/worker:1", "healthcheck": false, "restart": null},
+    "broken": {"image": "Not Valid", "healthcheck": false, "restart": null}}},
+  "other": {"name": "other", "dockerCompose": "o.yml", "services": {
+    "proxy": {"image": "docker.io/library/nginx:latest", "healthcheck": false, "restart": null}}}
+}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn groups_services_by_resolved_registry() {
+        let report =
+            registry_report(&projects(), &AuthConfig::default()).unwrap();
+
+        let registries = report
+            .registries
+            .iter()
+            .map(|usage| (usage.registry.as_str(), usage.services.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(registries, [("ghcr.io", 2), (DOCKER_HUB_REGISTRY, 2)]);
+        assert_eq!(report.registries[1].image_count(), 2);
+        assert_eq!(
+            report
+                .invalid
+                .keys()
+                .collect::<Vec<_>>(),
+            ["app.broken"]
+        );
+    }
+
+    #[test]
+    fn flags_private_registries_without_credentials() {
+        let mut auth = AuthConfig::default();
+        auth.add_auth(
+            "ghcr.io/acme/api".to_string(),
+            RegistryAuth::basic("ci", "token"),
+        );
+
+        let report = registry_report(&projects(), &auth).unwrap();
+        let ghcr = &report.registries[0];
+        let hub = &report.registries[1];
+
+        assert_eq!(ghcr.authenticated, ["app.api"]);
+        assert!(ghcr.missing_auth());
+        assert!(!hub.missing_auth());
+
+        auth.add_auth("ghcr.io".to_string(), RegistryAuth::basic("ci", "t"));
+        let report = registry_report(&projects(), &auth).unwrap();
+        assert!(!report.registries[0].missing_auth());
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+
+use nirion_oci_lib::{
+    http::{is_timeout, registry_status},
+    oci_client::Reference,
+};
+use serde::Serialize;
+
+/// Why resolving an image's digest failed.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCause {
+    AuthRequired,
+    NotFound,
+    RateLimited,
+    Timeout,
+    InvalidReference,
+    Other,
+}
+
+impl FailureCause {
+    /// Classifies on the HTTP status or OCI error code the registry
+    /// answered with. Errors that carry neither, like the Docker CLI's,
+    /// only count by status when their message says `status <code>`.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        let message = error
+            .chain()
+            .map(|cause| cause.to_string().to_ascii_lowercase())
+            .collect::<Vec<_>>()
+            .join(": ");
+        let status = registry_status(error).or_else(|| {
+            [429, 401, 403, 404]
+                .into_iter()
+                .find(|code| message.contains(&format!("status {code}")))
+        });
+
+        if message.contains("invalid reference format") {
+            FailureCause::InvalidReference
+        } else if status == Some(429) {
+            FailureCause::RateLimited
+        } else if matches!(status, Some(401 | 403)) {
+            FailureCause::AuthRequired
+        } else if status == Some(404) {
+            FailureCause::NotFound
+        } else if is_timeout(error) || message.contains("timed out") {
+            FailureCause::Timeout
+        } else {
+            FailureCause::Other
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FailureCause::AuthRequired => "auth required",
+            FailureCause::NotFound => "not found",
+            FailureCause::RateLimited => "rate limited",
+            FailureCause::Timeout => "timeout",
+            FailureCause::InvalidReference => "invalid reference",
+            FailureCause::Other => "other error",
+        }
+    }
+
+    fn hint(
+        self,
+        registries: &[&str],
+    ) -> String {
+        match self {
+            FailureCause::AuthRequired => format!(
+                "configure auth for {} in the auth file",
+                registries.join(", ")
+            ),
+            FailureCause::NotFound => "image or tag removed upstream — update \
+                                       the project file"
+                .to_string(),
+            FailureCause::RateLimited => "retry later, lower --jobs or \
+                                          configure auth to raise the limit"
+                .to_string(),
+            FailureCause::Timeout => "check connectivity and proxy settings \
+                                      for the registry"
+                .to_string(),
+            FailureCause::InvalidReference => {
+                "fix the image reference in the project file".to_string()
+            }
+            FailureCause::Other => "see the errors above".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImageFailure {
+    pub service: String,
+    pub image: String,
+    /// `None` when the image reference doesn't parse.
+    pub registry: Option<String>,
+    pub error: String,
+}
+
+impl ImageFailure {
+    /// Records why resolving `image` failed. References that don't parse
+    /// are always [`FailureCause::InvalidReference`].
+    pub fn classify(
+        service: String,
+        image: String,
+        error: &anyhow::Error,
+    ) -> (FailureCause, Self) {
+        let registry = Reference::try_from(image.as_str())
+            .ok()
+            .map(|reference| reference.resolve_registry().to_string());
+        let cause = match registry {
+            Some(_) => FailureCause::classify(error),
+            None => FailureCause::InvalidReference,
+        };
+
+        let failure = Self {
+            service,
+            image,
+            registry,
+            error: format!("{error:#}"),
+        };
+        (cause, failure)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FailureGroup {
+    pub cause: FailureCause,
+    /// Registries where every image failed for this cause, reported
+    /// once instead of per image.
+    pub registries: Vec<String>,
+    /// Failures not covered by `registries`.
+    pub failures: Vec<ImageFailure>,
+    pub hint: String,
+}
+
+impl FailureGroup {
+    pub fn image_count(
+        &self,
+        report: &FailureReport,
+    ) -> usize {
+        self.failures.len()
+            + self
+                .registries
+                .iter()
+                .map(|registry| report.registry_images(registry))
+                .sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FailureReport {
+    pub total: usize,
+    pub failed: usize,
+    pub groups: Vec<FailureGroup>,
+    /// Number of images per registry, to size collapsed groups.
+    #[serde(skip)]
+    registry_images: BTreeMap<String, usize>,
+}
+
+impl FailureReport {
+    /// Groups `failures` by cause. `images` are all images that were
+    /// being resolved (`project.service` to reference) and decide
+    /// whether a whole registry failed.
+    pub fn new(
+        images: &BTreeMap<String, String>,
+        failures: Vec<(FailureCause, ImageFailure)>,
+    ) -> Self {
+        let mut registry_images = BTreeMap::<String, usize>::new();
+        for image in images.values() {
+            if let Ok(reference) = Reference::try_from(image.as_str()) {
+                *registry_images
+                    .entry(reference.resolve_registry().to_string())
+                    .or_default() += 1;
+            }
+        }
+
+        let failed = failures.len();
+        let mut by_cause = BTreeMap::<FailureCause, Vec<ImageFailure>>::new();
+        for (cause, failure) in failures {
+            by_cause
+                .entry(cause)
+                .or_default()
+                .push(failure);
+        }
+
+        let groups = by_cause
+            .into_iter()
+            .map(|(cause, failures)| group(cause, failures, &registry_images))
+            .collect();
+
+        Self {
+            total: images.len(),
+            failed,
+            groups,
+            registry_images,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn registry_images(
+        &self,
+        registry: &str,
+    ) -> usize {
+        self.registry_images
+            .get(registry)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+fn group(
+    cause: FailureCause,
+    mut failures: Vec<ImageFailure>,
+    registry_images: &BTreeMap<String, usize>,
+) -> FailureGroup {
+    failures.sort_by(|a, b| a.service.cmp(&b.service));
+
+    let mut failed_per_registry = BTreeMap::<&str, usize>::new();
+    for failure in &failures {
+        if let Some(registry) = &failure.registry {
+            *failed_per_registry
+                .entry(registry)
+                .or_default() += 1;
+        }
+    }
+    let mentioned = failed_per_registry
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let hint = cause.hint(&mentioned);
+
+    let registries = if cause == FailureCause::AuthRequired {
+        failed_per_registry
+            .iter()
+            .filter(|(registry, failed)| {
+                *failed > &1 && registry_images.get(**registry) == Some(failed)
+            })
+            .map(|(registry, _)| registry.to_string())
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    failures.retain(|failure| {
+        failure
+            .registry
+            .as_ref()
+            .is_none_or(|registry| !registries.contains(registry))
+    });
+
+    FailureGroup {
+        cause,
+        registries,
+        failures,
+        hint,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nirion_oci_lib::oci_client::errors::OciDistributionError;
+
+    use super::*;
+
+    /// An error envelope carrying the OCI error `code`.
+    fn registry_error(code: &str) -> OciDistributionError {
+        OciDistributionError::RegistryError {
+            envelope: serde_json::from_str(&format!(
+                r#"{{"errors":[{{"code":"{code}","message":"m"}}]}}"#
+            ))
+            .unwrap(),
+            url: "https://registry.example/v2/".to_string(),
+        }
+    }
+
+    fn failure(
+        service: &str,
+        image: &str,
+        code: &str,
+    ) -> (FailureCause, ImageFailure) {
+        ImageFailure::classify(
+            service.to_string(),
+            image.to_string(),
+            &anyhow::Error::new(registry_error(code)),
+        )
+    }
+
+    #[test]
+    fn classifies_common_registry_errors() {
+        let classify = |error: OciDistributionError| {
+            FailureCause::classify(
+                &anyhow::Error::new(error).context("resolving"),
+            )
+        };
+
+        assert_eq!(
+            classify(OciDistributionError::UnauthorizedError {
+                url: "https://ghcr.io/v2/".to_string(),
+            }),
+            FailureCause::AuthRequired
+        );
+        assert_eq!(
+            classify(registry_error("DENIED")),
+            FailureCause::AuthRequired
+        );
+        assert_eq!(
+            classify(OciDistributionError::ImageManifestNotFoundError(
+                "nginx:nope".to_string()
+            )),
+            FailureCause::NotFound
+        );
+        assert_eq!(
+            classify(registry_error("MANIFEST_UNKNOWN")),
+            FailureCause::NotFound
+        );
+        assert_eq!(
+            classify(registry_error("TOOMANYREQUESTS")),
+            FailureCause::RateLimited
+        );
+        assert_eq!(
+            classify(OciDistributionError::ServerError {
+                code: 429,
+                url: "https://registry.lan/v2/".to_string(),
+                message: String::new(),
+            }),
+            FailureCause::RateLimited
+        );
+
+        let classify = |message: &str| {
+            FailureCause::classify(
+                &anyhow::anyhow!(message.to_string()).context("resolving"),
+            )
+        };
+        assert_eq!(
+            classify("unexpected status 401 from registry.lan"),
+            FailureCause::AuthRequired
+        );
+        assert_eq!(classify("operation timed out"), FailureCause::Timeout);
+        assert_eq!(
+            classify("invalid reference format"),
+            FailureCause::InvalidReference
+        );
+        assert_eq!(classify("connection reset"), FailureCause::Other);
+        assert_eq!(
+            classify("no space left writing app-404.tar"),
+            FailureCause::Other
+        );
+        assert_eq!(
+            classify("image acme/app:1.401 not found"),
+            FailureCause::Other
+        );
+
+        let (cause, failure) = ImageFailure::classify(
+            "app.web".to_string(),
+            "Not Valid".to_string(),
+            &anyhow::anyhow!("boom"),
+        );
+        assert_eq!(cause, FailureCause::InvalidReference);
+        assert_eq!(failure.registry, None);
+    }
+
+    #[test]
+    fn collapses_registries_where_every_image_needs_auth() {
+        let images = BTreeMap::from([
+            ("a.api".to_string(), "ghcr.io/acme/api:1".to_string()),
+            ("a.worker".to_string(), "ghcr.io/acme/worker:1".to_string()),
+            ("b.app".to_string(), "registry.lan:5000/app:1".to_string()),
+            ("b.web".to_string(), "registry.lan:5000/web:1".to_string()),
+            ("c.db".to_string(), "postgres:99".to_string()),
+        ]);
+        let report = FailureReport::new(
+            &images,
+            vec![
+                failure("a.api", "ghcr.io/acme/api:1", "UNAUTHORIZED"),
+                failure("a.worker", "ghcr.io/acme/worker:1", "UNAUTHORIZED"),
+                failure("b.app", "registry.lan:5000/app:1", "DENIED"),
+                failure("c.db", "postgres:99", "MANIFEST_UNKNOWN"),
+            ],
+        );
+
+        assert_eq!(report.failed, 4);
+        let auth = &report.groups[0];
+        assert_eq!(auth.cause, FailureCause::AuthRequired);
+        assert_eq!(auth.registries, ["ghcr.io"]);
+        assert_eq!(auth.failures.len(), 1);
+        assert_eq!(auth.failures[0].service, "b.app");
+        assert_eq!(auth.image_count(&report), 3);
+        assert!(
+            auth.hint
+                .contains("ghcr.io, registry.lan:5000")
+        );
+
+        let not_found = &report.groups[1];
+        assert_eq!(not_found.cause, FailureCause::NotFound);
+        assert_eq!(
+            not_found.failures[0]
+                .registry
+                .as_deref(),
+            Some("index.docker.io")
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Directory for nirion's persistent runtime state. `NIRION_STATE_DIR`
+/// takes precedence, then `$XDG_STATE_HOME/nirion`, then the platform's
+/// location: `~/.local/state/nirion` on Linux,
+/// `~/Library/Application Support/nirion` on macOS and
+/// `%LOCALAPPDATA%\nirion` on Windows.
+pub fn state_dir() -> anyhow::Result<PathBuf> {
+    resolve_state_dir(
+        env::var_os("NIRION_STATE_DIR"),
+        env::var_os("XDG_STATE_HOME"),
+        dirs::state_dir().or_else(dirs::data_local_dir),
+    )
+}
+
+fn resolve_state_dir(
+    nirion_state_dir: Option<OsString>,
+    xdg_state_home: Option<OsString>,
+    platform_state_dir: Option<PathBuf>,
+) -> anyhow::Result<PathBuf> {
+    let non_empty = |value: Option<OsString>| value.filter(|v| !v.is_empty());
+
+    if let Some(dir) = non_empty(nirion_state_dir) {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = non_empty(xdg_state_home) {
+        return Ok(PathBuf::from(dir).join("nirion"));
+    }
+    if let Some(dir) = platform_state_dir {
+        return Ok(dir.join("nirion"));
+    }
+
+    anyhow::bail!(
+        "Could not determine state directory; set NIRION_STATE_DIR or HOME"
+    )
+}
+
+/// The login name recorded in state entries: `$USER`, or `%USERNAME%` on
+/// Windows.
+pub fn current_user() -> String {
+    ["USER", "USERNAME"]
+        .into_iter()
+        .find_map(|name| {
+            env::var(name)
+                .ok()
+                .filter(|user| !user.is_empty())
+        })
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Appends `entry` to a JSON lines state file as a single line. The line
+/// is written with one `write_all` on an `O_APPEND` handle, so concurrent
+/// nirion processes never interleave partial entries.
+pub fn append_jsonl(
+    path: &Path,
+    entry: &impl Serialize,
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!("failed to create {}", parent.display())
+        })?;
+    }
+
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .with_context(|| format!("failed to append to {}", path.display()))
+}
+
+/// The entries of a JSON lines state file, oldest first. A missing file
+/// has none, and lines that don't parse are skipped.
+pub fn read_jsonl<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Drops all but the last `max_lines` lines of a JSON lines state file.
+/// The file is replaced in one rename; an entry appended by another
+/// process while it is rewritten can be lost, which is acceptable for
+/// the bounded histories this is meant for.
+pub fn truncate_jsonl(
+    path: &Path,
+    max_lines: usize,
+) -> anyhow::Result<()> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+    let lines = contents.lines().collect::<Vec<_>>();
+    if lines.len() <= max_lines {
+        return Ok(());
+    }
+
+    let mut kept = lines[lines.len() - max_lines..].join("\n");
+    kept.push('\n');
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, kept)
+        .and_then(|_| fs::rename(&tmp, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os(value: &str) -> Option<OsString> {
+        Some(OsString::from(value))
+    }
+
+    #[test]
+    fn explicit_state_dir_wins() {
+        assert_eq!(
+            resolve_state_dir(
+                os("/state"),
+                os("/xdg"),
+                Some("/home/u/.local/state".into())
+            )
+            .unwrap(),
+            PathBuf::from("/state")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_xdg_then_the_platform_directory() {
+        let platform = || Some(PathBuf::from("/home/u/.local/state"));
+        assert_eq!(
+            resolve_state_dir(None, os("/xdg"), platform()).unwrap(),
+            PathBuf::from("/xdg/nirion")
+        );
+        assert_eq!(
+            resolve_state_dir(os(""), None, platform()).unwrap(),
+            PathBuf::from("/home/u/.local/state/nirion")
+        );
+    }
+
+    #[test]
+    fn fails_without_any_location() {
+        assert!(resolve_state_dir(None, None, None).is_err());
+    }
+
+    #[test]
+    fn jsonl_files_are_appended_read_back_and_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("nested")
+            .join("entries.jsonl");
+        assert!(
+            read_jsonl::<u32>(&path)
+                .unwrap()
+                .is_empty()
+        );
+        truncate_jsonl(&path, 2).unwrap();
+
+        for entry in 1..=4u32 {
+            append_jsonl(&path, &entry).unwrap();
+        }
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        assert_eq!(read_jsonl::<u32>(&path).unwrap(), [1, 2, 3, 4]);
+
+        truncate_jsonl(&path, 3).unwrap();
+        assert_eq!(read_jsonl::<u32>(&path).unwrap(), [3, 4]);
+        truncate_jsonl(&path, 3).unwrap();
+        assert_eq!(read_jsonl::<u32>(&path).unwrap(), [3, 4]);
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::docker::{DockerCommand, ProjectStatus};
+
+/// Samples kept per service; older ones are dropped.
+pub const STATS_HISTORY_LEN: usize = 60;
+
+/// CPU and memory usage of a container, or summed over the replicas of a
+/// service.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// Percent of one CPU, so busy multi-core containers exceed 100.
+    pub cpu_percent: f64,
+    pub memory_percent: f64,
+}
+
+impl std::ops::Add for ResourceUsage {
+    type Output = Self;
+
+    fn add(
+        self,
+        other: Self,
+    ) -> Self {
+        Self {
+            cpu_percent: self.cpu_percent + other.cpu_percent,
+            memory_percent: self.memory_percent + other.memory_percent,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsLine {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "CPUPerc")]
+    cpu: String,
+    #[serde(rename = "MemPerc")]
+    memory: String,
+}
+
+fn parse_percent(value: &str) -> f64 {
+    value
+        .trim()
+        .trim_end_matches('%')
+        .parse()
+        .unwrap_or_default()
+}
+
+/// Parses `docker stats --format json` output into usage by container
+/// name, skipping lines that aren't stats.
+pub fn parse_stats(json: &str) -> BTreeMap<String, ResourceUsage> {
+    json.lines()
+        .filter_map(|line| serde_json::from_str::<StatsLine>(line).ok())
+        .map(|line| {
+            let usage = ResourceUsage {
+                cpu_percent: parse_percent(&line.cpu),
+                memory_percent: parse_percent(&line.memory),
+            };
+            (line.name, usage)
+        })
+        .collect()
+}
+
+/// One `docker stats --no-stream` sample of the named containers.
+pub async fn sample_container_stats(
+    docker_command: &DockerCommand,
+    names: &[String],
+) -> anyhow::Result<BTreeMap<String, ResourceUsage>> {
+    if names.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let output = docker_command
+        .command()
+        .arg("stats")
+        .arg("--no-stream")
+        .arg("--format")
+        .arg("json")
+        .args(names)
+        .output()
+        .await
+        .context("failed to execute docker stats")?;
+
+    // A container that stopped since it was listed fails the whole call,
+    // which is only worth reporting when nothing was sampled at all.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() && stdout.trim().is_empty() {
+        anyhow::bail!(
+            "docker stats failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(parse_stats(&stdout))
+}
+
+/// The last [`STATS_HISTORY_LEN`] samples of each service, oldest first.
+#[derive(Debug, Default, Clone)]
+pub struct StatsHistory {
+    services: BTreeMap<(String, String), VecDeque<ResourceUsage>>,
+}
+
+impl StatsHistory {
+    /// Adds `sample`, summed over replicas, to each service in
+    /// `statuses` that has a sampled container. Services no longer in
+    /// `statuses` lose their history.
+    pub fn record(
+        &mut self,
+        statuses: &BTreeMap<String, ProjectStatus>,
+        sample: &BTreeMap<String, ResourceUsage>,
+    ) {
+        self.services
+            .retain(|(project, service), _| {
+                statuses
+                    .get(project)
+                    .is_some_and(|status| status.services.contains_key(service))
+            });
+
+        for (project, status) in statuses {
+            for (service, replicas) in &status.services {
+                let usage = replicas
+                    .iter()
+                    .filter_map(|replica| sample.get(&replica.container_name))
+                    .copied()
+                    .reduce(|total, usage| total + usage);
+                let Some(usage) = usage else {
+                    continue;
+                };
+
+                let history = self
+                    .services
+                    .entry((project.clone(), service.clone()))
+                    .or_default();
+                if history.len() == STATS_HISTORY_LEN {
+                    history.pop_front();
+                }
+                history.push_back(usage);
+            }
+        }
+    }
+
+    /// The services of `project` with a history, by name.
+    pub fn services<'a>(
+        &'a self,
+        project: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a VecDeque<ResourceUsage>)> {
+        self.services
+            .iter()
+            .filter(move |((name, _), _)| name == project)
+            .map(|((_, service), history)| (service.as_str(), history))
+    }
+
+    /// The latest usage summed over the services of `project`.
+    pub fn project_usage(
+        &self,
+        project: &str,
+    ) -> Option<ResourceUsage> {
+        self.services(project)
+            .filter_map(|(_, history)| history.back().copied())
+            .reduce(|total, usage| total + usage)
+    }
+}
+
+/// Runs `docker stats` every interval for the containers last passed to
+/// [`StatsSampler::watch`], until dropped.
+#[derive(Debug)]
+pub struct StatsSampler {
+    containers: watch::Sender<Vec<String>>,
+    samples: watch::Receiver<BTreeMap<String, ResourceUsage>>,
+    task: JoinHandle<()>,
+}
+
+impl StatsSampler {
+    /// Must be called from within a tokio runtime.
+    pub fn spawn(
+        docker_command: DockerCommand,
+        interval: Duration,
+    ) -> Self {
+        let (containers, containers_rx) = watch::channel(Vec::new());
+        let (samples_tx, samples) = watch::channel(BTreeMap::new());
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                ticker.tick().await;
+                let names = containers_rx.borrow().clone();
+                if names.is_empty() {
+                    continue;
+                }
+                // Sampling is a best-effort extra; a failed run is
+                // retried on the next tick.
+                let Ok(sample) =
+                    sample_container_stats(&docker_command, &names).await
+                else {
+                    continue;
+                };
+                if samples_tx.send(sample).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            containers,
+            samples,
+            task,
+        }
+    }
+
+    /// Sets the container names sampled from the next tick on.
+    pub fn watch(
+        &self,
+        mut names: Vec<String>,
+    ) {
+        names.sort();
+        self.containers
+            .send_if_modified(|current| {
+                let changed = *current != names;
+                if changed {
+                    *current = names;
+                }
+                changed
+            });
+    }
+
+    /// The sample taken since the previous call, if any.
+    pub fn take_sample(&mut self) -> Option<BTreeMap<String, ResourceUsage>> {
+        self.samples
+            .has_changed()
+            .unwrap_or(false)
+            .then(|| self.samples.borrow_and_update().clone())
+    }
+}
+
+impl Drop for StatsSampler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(json: &str) -> BTreeMap<String, ProjectStatus> {
+        BTreeMap::from([(
+            "myapp".to_string(),
+            ProjectStatus::from_json(json).unwrap(),
+        )])
+    }
+
+    #[test]
+    fn parse_stats_reads_cpu_and_memory_percentages() {
+        let stats = parse_stats(
+            r#"{"BlockIO":"0B / 0B","CPUPerc":"12.50%","Container":"abc","ID":"abc","MemPerc":"1.25%","MemUsage":"10MiB / 800MiB","Name":"myapp-web-1","NetIO":"0B / 0B","PIDs":"3"}
+not json
+{"CPUPerc":"--","MemPerc":"--","Name":"myapp-db-1"}"#,
+        );
+
+        assert_eq!(
+            stats["myapp-web-1"],
+            ResourceUsage {
+                cpu_percent: 12.5,
+                memory_percent: 1.25,
+            }
+        );
+        assert_eq!(stats["myapp-db-1"], ResourceUsage::default());
+        assert_eq!(stats.len(), 2);
+    }
+
+    #[test]
+    fn history_sums_replicas_is_bounded_and_forgets_removed_services() {
+        let usage = |cpu_percent| ResourceUsage {
+            cpu_percent,
+            memory_percent: 1.0,
+        };
+        let sample = BTreeMap::from([
+            ("myapp-web-1".to_string(), usage(2.0)),
+            ("myapp-web-2".to_string(), usage(3.0)),
+            ("myapp-db-1".to_string(), usage(1.0)),
+        ]);
+        let both = statuses(
+            r#"{"ID":"1","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running"}
+{"ID":"2","Name":"myapp-web-2","Service":"web","Image":"nginx","State":"running"}
+{"ID":"3","Name":"myapp-db-1","Service":"db","Image":"postgres","State":"running"}"#,
+        );
+        let mut history = StatsHistory::default();
+
+        for _ in 0..STATS_HISTORY_LEN + 5 {
+            history.record(&both, &sample);
+        }
+
+        let services = history
+            .services("myapp")
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(services["web"].len(), STATS_HISTORY_LEN);
+        assert_eq!(
+            services["web"].back(),
+            Some(&ResourceUsage {
+                cpu_percent: 5.0,
+                memory_percent: 2.0,
+            })
+        );
+        assert_eq!(
+            history
+                .project_usage("myapp")
+                .map(|usage| usage.cpu_percent),
+            Some(6.0)
+        );
+
+        let web_only = statuses(
+            r#"{"ID":"1","Name":"myapp-web-1","Service":"web","Image":"nginx","State":"running"}"#,
+        );
+        history.record(&web_only, &sample);
+        assert_eq!(
+            history
+                .services("myapp")
+                .map(|(service, _)| service)
+                .collect::<Vec<_>>(),
+            ["web"]
+        );
+        assert!(history.project_usage("other").is_none());
+    }
+
+    #[tokio::test]
+    async fn sampler_reports_stats_of_the_watched_containers() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("docker");
+        std::fs::write(
+            &script,
+            r#"shift 4; for name in "$@"; do printf '{"Name":"%s","CPUPerc":"4.00%%","MemPerc":"2.00%%"}\n' "$name"; done"#,
+        )
+        .unwrap();
+        let mut sampler = StatsSampler::spawn(
+            DockerCommand::with_args(
+                "/bin/sh",
+                [script.to_string_lossy().to_string()],
+            ),
+            Duration::from_millis(10),
+        );
+
+        sampler.watch(vec!["myapp-web-1".to_string()]);
+        let sample = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(sample) = sampler.take_sample() {
+                    return sample;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(
+            sample,
+            BTreeMap::from([(
+                "myapp-web-1".to_string(),
+                ResourceUsage {
+                    cpu_percent: 4.0,
+                    memory_percent: 2.0,
+                }
+            )])
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::docker::ProjectStatus;
+
+pub const STATUS_CACHE_FILE: &str = "status-cache.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedStatus {
+    /// Unix timestamp in seconds.
+    taken_at: i64,
+    status: ProjectStatus,
+}
+
+pub fn status_cache_file(state_dir: &Path) -> PathBuf {
+    state_dir.join(STATUS_CACHE_FILE)
+}
+
+/// Snapshots taken within `max_age`, keyed by project. A missing or
+/// unreadable cache is treated as empty.
+pub fn read_status_cache(
+    path: &Path,
+    max_age: Duration,
+) -> BTreeMap<String, ProjectStatus> {
+    read_status_cache_at(path, chrono::Utc::now().timestamp(), max_age)
+}
+
+fn read_status_cache_at(
+    path: &Path,
+    now: i64,
+    max_age: Duration,
+) -> BTreeMap<String, ProjectStatus> {
+    let cache = fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| {
+            serde_json::from_str::<BTreeMap<String, CachedStatus>>(&contents)
+                .ok()
+        })
+        .unwrap_or_default();
+
+    cache
+        .into_iter()
+        .filter(|(_, cached)| {
+            now.saturating_sub(cached.taken_at) <= max_age.as_secs() as i64
+        })
+        .map(|(project, cached)| (project, cached.status))
+        .collect()
+}
+
+/// Replaces the snapshot of `project`, keeping those of other projects.
+/// The file is swapped in with a rename so readers never see it half
+/// written.
+pub fn write_status_cache(
+    path: &Path,
+    project: &str,
+    status: &ProjectStatus,
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| {
+            format!("failed to create {}", parent.display())
+        })?;
+    }
+
+    let mut cache = fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| {
+            serde_json::from_str::<BTreeMap<String, serde_json::Value>>(
+                &contents,
+            )
+            .ok()
+        })
+        .unwrap_or_default();
+    cache.insert(
+        project.to_string(),
+        serde_json::to_value(CachedStatus {
+            taken_at: chrono::Utc::now().timestamp(),
+            status: status.clone(),
+        })?,
+    );
+
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string(&cache)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_AGE: Duration = Duration::from_secs(60);
+
+    fn status() -> ProjectStatus {
+        ProjectStatus::from_json(
+            r#"{"ID":"1","Name":"media-jellyfin-1","Service":"jellyfin","Image":"jellyfin","State":"running","Health":"healthy"}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn write_merges_projects_and_read_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_cache_file(&dir.path().join("state"));
+
+        write_status_cache(&path, "media", &status()).unwrap();
+        write_status_cache(&path, "web", &status()).unwrap();
+
+        let cache = read_status_cache(&path, MAX_AGE);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), ["media", "web"]);
+        assert!(
+            cache["media"]
+                .services
+                .contains_key("jellyfin")
+        );
+    }
+
+    #[test]
+    fn stale_or_corrupt_snapshots_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = status_cache_file(dir.path());
+        write_status_cache(&path, "media", &status()).unwrap();
+
+        let later = chrono::Utc::now().timestamp() + 61;
+        assert!(read_status_cache_at(&path, later, MAX_AGE).is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(read_status_cache(&path, MAX_AGE).is_empty());
+        assert!(
+            read_status_cache(&dir.path().join("missing.json"), MAX_AGE)
+                .is_empty()
+        );
+    }
+}
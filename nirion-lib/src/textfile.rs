@@ -0,0 +1,395 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::{
+    docker::{ProjectStatus, ServiceState},
+    events::LockUpdateEvent,
+    lock::DiffEntry,
+    projects::{Project, TargetSelector},
+};
+
+pub const TEXTFILE_NAME: &str = "nirion.prom";
+
+/// A gauge with its samples, rendered in the Prometheus text format
+/// node_exporter reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Gauge {
+    /// A gauge with a single sample without labels.
+    pub fn single(
+        name: &'static str,
+        help: &'static str,
+        value: f64,
+    ) -> Self {
+        Self {
+            name,
+            help,
+            samples: vec![(Vec::new(), value)],
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = format!(
+            "# HELP {name} {help}\n# TYPE {name} gauge\n",
+            name = self.name,
+            help = self.help
+        );
+        for (labels, value) in &self.samples {
+            output.push_str(self.name);
+            if !labels.is_empty() {
+                let labels = labels
+                    .iter()
+                    .map(|(name, value)| {
+                        format!("{name}=\"{}\"", escape_label(value))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let _ = write!(output, "{{{labels}}}");
+            }
+            let _ = writeln!(output, " {value}");
+        }
+        output
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// What `update` reports: how many lock entries were behind, how many
+/// images failed to resolve, and when it ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateMetrics {
+    pub outdated: usize,
+    pub failed: usize,
+}
+
+impl UpdateMetrics {
+    pub fn observe(
+        &mut self,
+        event: &LockUpdateEvent,
+    ) {
+        match event {
+            LockUpdateEvent::ChangesDetected { diffs } => {
+                self.outdated = diffs
+                    .iter()
+                    .filter(|diff| !matches!(diff, DiffEntry::Removed { .. }))
+                    .count();
+            }
+            LockUpdateEvent::ResolutionFailed { report } => {
+                self.failed = report.failed;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn gauges(
+        &self,
+        success: bool,
+        now: SystemTime,
+    ) -> Vec<Gauge> {
+        vec![
+            Gauge::single(
+                "nirion_lock_outdated_total",
+                "Services whose lock entry the last update found behind",
+                self.outdated as f64,
+            ),
+            Gauge::single(
+                "nirion_update_failed_images",
+                "Images the last update failed to resolve",
+                self.failed as f64,
+            ),
+            Gauge::single(
+                "nirion_last_update_success",
+                "Whether the last update succeeded",
+                bool_value(success),
+            ),
+            Gauge::single(
+                "nirion_last_update_timestamp",
+                "When the last update finished, in seconds since the epoch",
+                timestamp(now),
+            ),
+        ]
+    }
+}
+
+/// What `up` reports: whether each selected service came up, and when it
+/// ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpMetrics {
+    failed: BTreeMap<(String, String), bool>,
+}
+
+impl UpMetrics {
+    /// Records the services of `project` that `target` selects. A service
+    /// failed if it has no container or one that failed, is unhealthy or
+    /// keeps restarting.
+    pub fn observe(
+        &mut self,
+        project_name: &str,
+        project: &Project,
+        status: &ProjectStatus,
+        target: &TargetSelector,
+    ) {
+        for service in project.services.keys() {
+            if !target.selects_service(project_name, service) {
+                continue;
+            }
+            let failed = status
+                .services
+                .get(service)
+                .is_none_or(|replicas| {
+                    replicas.is_empty()
+                        || replicas.iter().any(|replica| {
+                            matches!(
+                                replica.state,
+                                ServiceState::Failed
+                                    | ServiceState::Unhealthy
+                                    | ServiceState::Restarting
+                            )
+                        })
+                });
+            self.failed
+                .insert((project_name.to_string(), service.clone()), failed);
+        }
+    }
+
+    pub fn gauges(
+        &self,
+        success: bool,
+        now: SystemTime,
+    ) -> Vec<Gauge> {
+        vec![
+            Gauge {
+                name: "nirion_up_failed_services",
+                help: "Whether a service failed to come up in the last up",
+                samples: self
+                    .failed
+                    .iter()
+                    .map(|((project, service), failed)| {
+                        (
+                            vec![
+                                ("project", project.clone()),
+                                ("service", service.clone()),
+                            ],
+                            bool_value(*failed),
+                        )
+                    })
+                    .collect(),
+            },
+            Gauge::single(
+                "nirion_last_up_success",
+                "Whether the last up succeeded",
+                bool_value(success),
+            ),
+            Gauge::single(
+                "nirion_last_up_timestamp",
+                "When the last up finished, in seconds since the epoch",
+                timestamp(now),
+            ),
+        ]
+    }
+}
+
+fn bool_value(value: bool) -> f64 {
+    if value { 1.0 } else { 0.0 }
+}
+
+fn timestamp(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as f64)
+        .unwrap_or_default()
+}
+
+/// Writes `gauges` to [`TEXTFILE_NAME`] in `dir`, keeping the metrics
+/// already there that `gauges` doesn't replace. The file is swapped in
+/// with a rename, so the collector never reads half of it.
+pub fn write_textfile(
+    dir: &Path,
+    gauges: &[Gauge],
+) -> anyhow::Result<PathBuf> {
+    let path = dir.join(TEXTFILE_NAME);
+    let mut families = match fs::read_to_string(&path) {
+        Ok(contents) => parse_families(&contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+    for gauge in gauges {
+        families.insert(gauge.name.to_string(), gauge.render());
+    }
+
+    // node_exporter only reads `*.prom`, so the partial file is ignored.
+    let tmp = dir.join(format!(".{TEXTFILE_NAME}.tmp"));
+    fs::write(
+        &tmp,
+        families
+            .into_values()
+            .collect::<String>(),
+    )
+    .and_then(|_| fs::rename(&tmp, &path))
+    .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The lines of a textfile grouped by the metric they belong to.
+fn parse_families(contents: &str) -> BTreeMap<String, String> {
+    let mut families: BTreeMap<String, String> = BTreeMap::new();
+    for line in contents.lines() {
+        let name = match line.strip_prefix("# ") {
+            Some(comment) => match comment.split_whitespace().nth(1) {
+                Some(name)
+                    if comment.starts_with("HELP ")
+                        || comment.starts_with("TYPE ") =>
+                {
+                    name
+                }
+                _ => continue,
+            },
+            None => line
+                .split(['{', ' '])
+                .next()
+                .unwrap_or_default(),
+        };
+        if name.is_empty() {
+            continue;
+        }
+        let family = families
+            .entry(name.to_string())
+            .or_default();
+        family.push_str(line);
+        family.push('\n');
+    }
+    families
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::ServiceStatus;
+    use std::time::Duration;
+
+    fn project() -> Project {
+        serde_json::from_value(serde_json::json!({
+            "name": "media",
+            "dockerCompose": "media.yml",
+            "services": {
+                "jellyfin": {"image": "jellyfin", "restart": null},
+                "sonarr": {"image": "sonarr", "restart": null},
+                "radarr": {"image": "radarr", "restart": null}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn container(
+        service: &str,
+        state: ServiceState,
+    ) -> ServiceStatus {
+        ServiceStatus {
+            id: format!("{service}-id"),
+            service: service.to_string(),
+            container_name: format!("media-{service}-1"),
+            index: 1,
+            image: service.to_string(),
+            state,
+            health: None,
+            exit_code: None,
+            running_for: None,
+            created_at: None,
+            status: None,
+            ports: Vec::new(),
+            networks: Vec::new(),
+            details: None,
+            last_health_check: None,
+        }
+    }
+
+    #[test]
+    fn up_and_update_each_replace_only_their_own_metrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+
+        let mut up = UpMetrics::default();
+        up.observe(
+            "media",
+            &project(),
+            &ProjectStatus::from_containers([
+                container("jellyfin", ServiceState::Healthy),
+                container("sonarr", ServiceState::Restarting),
+            ]),
+            &TargetSelector::All,
+        );
+        write_textfile(dir.path(), &up.gauges(false, now)).unwrap();
+
+        let update = UpdateMetrics {
+            outdated: 3,
+            failed: 0,
+        };
+        let path =
+            write_textfile(dir.path(), &update.gauges(true, now)).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+
+        assert!(contents.contains(
+            "# HELP nirion_up_failed_services Whether a service failed to \
+             come up in the last up\n\
+             # TYPE nirion_up_failed_services gauge\n\
+             nirion_up_failed_services{project=\"media\",service=\"jellyfin\"} 0\n\
+             nirion_up_failed_services{project=\"media\",service=\"radarr\"} 1\n\
+             nirion_up_failed_services{project=\"media\",service=\"sonarr\"} 1\n"
+        ));
+        assert!(contents.contains("\nnirion_last_up_success 0\n"));
+        assert!(contents.contains("\nnirion_lock_outdated_total 3\n"));
+        assert!(
+            contents.contains("\nnirion_last_update_timestamp 1760000000\n")
+        );
+
+        let update = UpdateMetrics {
+            outdated: 0,
+            failed: 1,
+        };
+        let path =
+            write_textfile(dir.path(), &update.gauges(false, now)).unwrap();
+        let contents = fs::read_to_string(path).unwrap();
+        assert_eq!(contents.matches("# TYPE").count(), 7);
+        assert!(contents.contains("\nnirion_lock_outdated_total 0\n"));
+        assert!(contents.contains("\nnirion_last_up_success 0\n"));
+        assert_eq!(
+            fs::read_dir(dir.path())
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let gauge = Gauge {
+            name: "nirion_test",
+            help: "Test",
+            samples: vec![(vec![("project", "a\"b\\c".to_string())], 1.0)],
+        };
+        assert!(
+            gauge
+                .render()
+                .ends_with("nirion_test{project=\"a\\\"b\\\\c\"} 1\n")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use futures::StreamExt;
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+
+use crate::{
+    context::NirionContext, events::LockUpdateEvent, lock::DiffEntry,
+    lock_update::image_check_stream,
+};
+
+/// Runs [`image_check_stream`] every interval for a fixed set of images,
+/// until dropped. The lock file is never written.
+#[derive(Debug)]
+pub struct UpdateChecker {
+    updates: watch::Receiver<Vec<DiffEntry>>,
+    task: JoinHandle<()>,
+}
+
+impl UpdateChecker {
+    /// Checks `images` (`project.service` to reference) right away and
+    /// then every `interval`. Must be called from within a tokio runtime.
+    pub fn spawn(
+        context: &NirionContext,
+        images: BTreeMap<String, String>,
+        interval: Duration,
+        jobs: usize,
+    ) -> Self {
+        let context = context.clone();
+        let (updates_tx, updates) = watch::channel(Vec::new());
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            loop {
+                ticker.tick().await;
+                // A check that resolved nothing keeps the previous result
+                // rather than reporting everything as up to date.
+                let Some(updates) =
+                    check_once(&context, images.clone(), jobs).await
+                else {
+                    continue;
+                };
+                if updates_tx.send(updates).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { updates, task }
+    }
+
+    /// The updates found by the check that finished since the previous
+    /// call, if any.
+    pub fn take_updates(&mut self) -> Option<Vec<DiffEntry>> {
+        self.updates
+            .has_changed()
+            .unwrap_or(false)
+            .then(|| self.updates.borrow_and_update().clone())
+    }
+}
+
+impl Drop for UpdateChecker {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The lock entries with a newer image available. `None` if the check
+/// failed as a whole; images that failed on their own are left out.
+async fn check_once(
+    context: &NirionContext,
+    images: BTreeMap<String, String>,
+    jobs: usize,
+) -> Option<Vec<DiffEntry>> {
+    let mut events = image_check_stream(context, images, jobs);
+    let mut updates = Vec::new();
+    let mut resolved = true;
+    let mut partial = false;
+
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(LockUpdateEvent::ChangesDetected { diffs }) => {
+                // Entries that are only missing or renamed in the lock
+                // file aren't updates.
+                updates = diffs
+                    .into_iter()
+                    .filter(|diff| {
+                        matches!(
+                            diff,
+                            DiffEntry::Updated { old, new, .. }
+                                if old.digest != new.digest
+                        )
+                    })
+                    .collect();
+            }
+            Ok(LockUpdateEvent::ResolutionFailed { report }) => {
+                resolved = report.failed < report.total;
+                partial = true;
+            }
+            Ok(_) => {}
+            // Expected after a partial failure; anything else means the
+            // check didn't get as far as resolving.
+            Err(_) if partial => {}
+            Err(_) => resolved = false,
+        }
+    }
+
+    resolved.then_some(updates)
+}
@@ -1,14 +1,23 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
-    docker::{ProjectStatus, ServiceState},
-    projects::{Projects, TargetSelector},
+    docker::{ProjectStatus, ServiceState, ServiceStatus},
+    projects::{Project, Projects, Service, TargetSelector},
 };
 
+/// What a lifecycle operation waits for once docker compose has exited.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WaitTarget {
     NoWait,
-    Healthchecks,
+    /// Healthchecked services report a health result and one-shot services
+    /// have exited (used by up, start and restart).
+    Healthy,
+    /// No selected service has a live container anymore (used by down and
+    /// stop).
+    Stopped,
     Forever,
 }
 
@@ -17,11 +26,39 @@ pub fn wait_finished(
     projects: &Projects,
     statuses: &BTreeMap<String, ProjectStatus>,
     wait_target: WaitTarget,
+) -> bool {
+    target_project_names(target, projects)
+        .into_iter()
+        .all(|project_name| {
+            project_wait_finished(
+                target,
+                project_name,
+                projects,
+                statuses,
+                wait_target,
+            )
+        })
+}
+
+/// Checks a single project of `target`, so callers can tell which
+/// projects are done while others are still waiting.
+pub fn project_wait_finished(
+    target: &TargetSelector,
+    project_name: &str,
+    projects: &Projects,
+    statuses: &BTreeMap<String, ProjectStatus>,
+    wait_target: WaitTarget,
 ) -> bool {
     match wait_target {
         WaitTarget::NoWait => true,
-        WaitTarget::Healthchecks => {
-            healthchecks_finished(target, projects, statuses)
+        WaitTarget::Healthy => project_healthchecks_finished(
+            target,
+            project_name,
+            projects,
+            statuses,
+        ),
+        WaitTarget::Stopped => {
+            project_stopped(target, project_name, projects, statuses)
         }
         WaitTarget::Forever => false,
     }
@@ -32,69 +69,169 @@ pub fn healthchecks_finished(
     projects: &Projects,
     statuses: &BTreeMap<String, ProjectStatus>,
 ) -> bool {
-    let project_names: Vec<&str> = match target {
+    wait_finished(target, projects, statuses, WaitTarget::Healthy)
+}
+
+fn target_project_names<'a>(
+    target: &'a TargetSelector,
+    projects: &'a Projects,
+) -> Vec<&'a str> {
+    match target {
         TargetSelector::All => projects
             .iter()
             .map(|(n, _)| n)
             .collect(),
         TargetSelector::Project(p) => vec![p.name.as_str()],
         TargetSelector::Service(s) => vec![s.project.as_str()],
-    };
+    }
+}
 
-    for project_name in project_names {
-        let project = match projects.get(project_name) {
-            Some(p) => p,
-            None => continue,
-        };
+fn selected_services<'a>(
+    target: &'a TargetSelector,
+    project_name: &'a str,
+    project: &'a Project,
+) -> impl Iterator<Item = (&'a String, &'a Service)> {
+    project
+        .services
+        .iter()
+        .filter(move |(service_name, _)| match target {
+            TargetSelector::Service(sel) if sel.project == project_name => {
+                sel.service == **service_name
+            }
+            _ => true,
+        })
+}
 
-        let has_healthcheck =
-            project
-                .services
-                .iter()
-                .any(|(service_name, service)| {
-                    if let TargetSelector::Service(sel) = target {
-                        if sel.project == project_name
-                            && sel.service != *service_name
-                        {
-                            return false;
-                        }
-                    }
-
-                    service.healthcheck
-                });
-
-        if !has_healthcheck {
-            continue;
+/// How long a healthchecked service may keep restarting, counted from
+/// the creation of its container, before a healthy wait stops waiting on
+/// it and reports it as a straggler.
+const RESTART_GRACE: Duration = Duration::from_secs(60);
+
+fn healthy_wait_settled(
+    service: &Service,
+    status: &ServiceStatus,
+    now: SystemTime,
+) -> bool {
+    match status.state {
+        ServiceState::Healthy | ServiceState::Unhealthy => true,
+        // One-shots are expected to exit; once they have, they no longer
+        // hold up a healthy wait. A restart of anything else may be a
+        // crash on boot the service recovers from, e.g. while its
+        // database starts, so it keeps the wait open for a while.
+        ServiceState::Succeeded | ServiceState::Failed
+            if service.is_one_shot() =>
+        {
+            true
+        }
+        _ => crash_looping(service, status, now),
+    }
+}
+
+/// Whether a service that should keep running is still down once
+/// [`RESTART_GRACE`] has passed.
+fn crash_looping(
+    service: &Service,
+    status: &ServiceStatus,
+    now: SystemTime,
+) -> bool {
+    let down = match status.state {
+        ServiceState::Restarting => true,
+        ServiceState::Succeeded | ServiceState::Failed => {
+            !service.is_one_shot()
         }
+        _ => false,
+    };
+    down && status
+        .uptime(now)
+        .is_some_and(|uptime| uptime >= RESTART_GRACE)
+}
 
-        let status = match statuses.get(project_name) {
-            Some(s) => s,
-            None => return false,
-        };
+pub fn project_healthchecks_finished(
+    target: &TargetSelector,
+    project_name: &str,
+    projects: &Projects,
+    statuses: &BTreeMap<String, ProjectStatus>,
+) -> bool {
+    let Some(project) = projects.get(project_name) else {
+        return true;
+    };
 
-        for (service_name, service) in &project.services {
-            if let TargetSelector::Service(sel) = target {
-                if sel.project == project_name && sel.service != *service_name {
-                    continue;
-                }
-            }
+    let status = statuses.get(project_name);
+    let now = SystemTime::now();
 
-            if !service.healthcheck {
-                continue;
-            }
+    selected_services(target, project_name, project)
+        .filter(|(_, service)| service.healthcheck)
+        .all(|(service_name, service)| {
+            let replicas = status
+                .map(|status| status.replicas(service_name))
+                .unwrap_or_default();
 
-            let Some(service_status) = status.services.get(service_name) else {
-                return false;
-            };
+            !replicas.is_empty()
+                && replicas.iter().all(|service_status| {
+                    healthy_wait_settled(service, service_status, now)
+                })
+        })
+}
 
-            match service_status.state {
-                ServiceState::Healthy | ServiceState::Unhealthy => {}
-                _ => return false,
-            }
-        }
-    }
+/// The selected healthchecked services of `project_name` that settled a
+/// healthy wait by failing their healthcheck or by still restarting after
+/// [`RESTART_GRACE`], with the state of their first such replica.
+pub fn project_stragglers(
+    target: &TargetSelector,
+    project_name: &str,
+    projects: &Projects,
+    statuses: &BTreeMap<String, ProjectStatus>,
+) -> Vec<(String, ServiceState)> {
+    let (Some(project), Some(status)) =
+        (projects.get(project_name), statuses.get(project_name))
+    else {
+        return Vec::new();
+    };
+
+    let now = SystemTime::now();
 
-    true
+    selected_services(target, project_name, project)
+        .filter(|(_, service)| service.healthcheck)
+        .filter_map(|(service_name, service)| {
+            status
+                .replicas(service_name)
+                .iter()
+                .find(|replica| {
+                    replica.state == ServiceState::Unhealthy
+                        || crash_looping(service, replica, now)
+                })
+                .map(|replica| (service_name.clone(), replica.state.clone()))
+        })
+        .collect()
+}
+
+pub fn project_stopped(
+    target: &TargetSelector,
+    project_name: &str,
+    projects: &Projects,
+    statuses: &BTreeMap<String, ProjectStatus>,
+) -> bool {
+    let Some(project) = projects.get(project_name) else {
+        return true;
+    };
+
+    let Some(status) = statuses.get(project_name) else {
+        return false;
+    };
+
+    selected_services(target, project_name, project).all(|(service_name, _)| {
+        status
+            .replicas(service_name)
+            .iter()
+            .all(|service_status| {
+                matches!(
+                    service_status.state,
+                    ServiceState::Created
+                        | ServiceState::Succeeded
+                        | ServiceState::Failed
+                )
+            })
+    })
 }
 
 #[cfg(test)]
@@ -138,26 +275,27 @@ mod tests {
             id: format!("{service}-id"),
             service: service.to_string(),
             container_name: service.to_string(),
+            index: 1,
             image: "image".to_string(),
             state,
             health: None,
             exit_code: None,
             running_for: None,
+            created_at: None,
             status: None,
             ports: Vec::<Port>::new(),
             networks: Vec::new(),
+            details: None,
+            last_health_check: None,
         }
     }
 
     fn project_status(entries: Vec<(&str, ServiceState)>) -> ProjectStatus {
-        ProjectStatus {
-            services: entries
+        ProjectStatus::from_containers(
+            entries
                 .into_iter()
-                .map(|(service, state)| {
-                    (service.to_string(), service_status(service, state))
-                })
-                .collect(),
-        }
+                .map(|(service, state)| service_status(service, state)),
+        )
     }
 
     #[test]
@@ -269,4 +407,253 @@ mod tests {
 
         assert!(healthchecks_finished(&target, &projects, &statuses));
     }
+
+    #[test]
+    fn project_healthchecks_finished_is_scoped_to_one_project() {
+        let projects = projects();
+        let statuses = BTreeMap::from([
+            (
+                "myapp".to_string(),
+                project_status(vec![("web", ServiceState::Healthy)]),
+            ),
+            (
+                "api".to_string(),
+                project_status(vec![("server", ServiceState::Running)]),
+            ),
+        ]);
+
+        assert!(project_healthchecks_finished(
+            &TargetSelector::All,
+            "myapp",
+            &projects,
+            &statuses
+        ));
+        assert!(!project_healthchecks_finished(
+            &TargetSelector::All,
+            "api",
+            &projects,
+            &statuses
+        ));
+        assert!(!healthchecks_finished(
+            &TargetSelector::All,
+            &projects,
+            &statuses
+        ));
+    }
+
+    fn one_shot_projects() -> Projects {
+        serde_json::from_str(
+            r#"
+{
+  "app": {
+    "name": "app",
+    "dockerCompose": "compose.yml",
+    "services": {
+      "migrate": {"image": "app", "healthcheck": true, "restart": "no"},
+      "web": {"image": "app", "healthcheck": true, "restart": "unless-stopped"}
+    }
+  }
+}
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn wait_policy_matrix() {
+        use ServiceState::*;
+
+        let projects = one_shot_projects();
+        let cases = [
+            // (migrate, web, wait target, finished)
+            (Succeeded, Healthy, WaitTarget::Healthy, true),
+            (Failed, Healthy, WaitTarget::Healthy, true),
+            (Running, Healthy, WaitTarget::Healthy, false),
+            (Succeeded, Running, WaitTarget::Healthy, false),
+            (Succeeded, Succeeded, WaitTarget::Healthy, false),
+            (Succeeded, Unhealthy, WaitTarget::Healthy, true),
+            (Succeeded, Succeeded, WaitTarget::Stopped, true),
+            (Created, Failed, WaitTarget::Stopped, true),
+            (Succeeded, Running, WaitTarget::Stopped, false),
+            (Succeeded, Paused, WaitTarget::Stopped, false),
+            (Running, Running, WaitTarget::NoWait, true),
+            (Succeeded, Healthy, WaitTarget::Forever, false),
+        ];
+
+        for (migrate, web, wait, expected) in cases {
+            let statuses = BTreeMap::from([(
+                "app".to_string(),
+                project_status(vec![
+                    ("migrate", migrate.clone()),
+                    ("web", web.clone()),
+                ]),
+            )]);
+
+            assert_eq!(
+                wait_finished(&TargetSelector::All, &projects, &statuses, wait),
+                expected,
+                "migrate={migrate:?} web={web:?} wait={wait:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn unhealthy_services_settle_their_project_as_stragglers() {
+        let projects = projects();
+        let statuses = BTreeMap::from([
+            (
+                "myapp".to_string(),
+                project_status(vec![
+                    ("web", ServiceState::Unhealthy),
+                    ("worker", ServiceState::Failed),
+                ]),
+            ),
+            (
+                "api".to_string(),
+                project_status(vec![("server", ServiceState::Healthy)]),
+            ),
+        ]);
+
+        assert!(healthchecks_finished(
+            &TargetSelector::All,
+            &projects,
+            &statuses
+        ));
+        // worker has no healthcheck, so it is nobody's straggler.
+        assert_eq!(
+            project_stragglers(
+                &TargetSelector::All,
+                "myapp",
+                &projects,
+                &statuses
+            ),
+            vec![("web".to_string(), ServiceState::Unhealthy)]
+        );
+        assert!(
+            project_stragglers(
+                &TargetSelector::All,
+                "api",
+                &projects,
+                &statuses
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn restarting_services_keep_their_project_waiting() {
+        let projects = projects();
+        let statuses = BTreeMap::from([(
+            "myapp".to_string(),
+            project_status(vec![
+                ("web", ServiceState::Restarting),
+                ("worker", ServiceState::Running),
+            ]),
+        )]);
+
+        assert!(!project_healthchecks_finished(
+            &TargetSelector::All,
+            "myapp",
+            &projects,
+            &statuses
+        ));
+    }
+
+    #[test]
+    fn services_still_restarting_after_the_grace_settle_as_stragglers() {
+        let projects = one_shot_projects();
+        let created = |age: Duration, state: ServiceState| {
+            let mut status = service_status("web", state);
+            status.created_at = Some((SystemTime::now() - age).into());
+            status
+        };
+        let settled = |web: ServiceStatus| {
+            let statuses = BTreeMap::from([(
+                "app".to_string(),
+                ProjectStatus::from_containers([
+                    service_status("migrate", ServiceState::Succeeded),
+                    web,
+                ]),
+            )]);
+            (
+                healthchecks_finished(
+                    &TargetSelector::All,
+                    &projects,
+                    &statuses,
+                ),
+                project_stragglers(
+                    &TargetSelector::All,
+                    "app",
+                    &projects,
+                    &statuses,
+                ),
+            )
+        };
+
+        let fresh = Duration::from_secs(5);
+        let old = RESTART_GRACE + Duration::from_secs(5);
+        for state in [
+            ServiceState::Restarting,
+            ServiceState::Failed,
+            ServiceState::Succeeded,
+        ] {
+            assert_eq!(
+                settled(created(fresh, state.clone())),
+                (false, Vec::new()),
+                "{state:?}"
+            );
+            assert_eq!(
+                settled(created(old, state.clone())),
+                (true, vec![("web".to_string(), state.clone())]),
+                "{state:?}"
+            );
+        }
+        assert_eq!(
+            settled(created(old, ServiceState::Running)),
+            (false, Vec::new())
+        );
+    }
+
+    #[test]
+    fn stopped_wait_treats_removed_containers_as_stopped() {
+        let projects = one_shot_projects();
+        let statuses =
+            BTreeMap::from([("app".to_string(), project_status(vec![]))]);
+
+        assert!(wait_finished(
+            &TargetSelector::All,
+            &projects,
+            &statuses,
+            WaitTarget::Stopped
+        ));
+        assert!(!wait_finished(
+            &TargetSelector::All,
+            &projects,
+            &BTreeMap::new(),
+            WaitTarget::Stopped
+        ));
+    }
+
+    #[test]
+    fn stopped_wait_respects_service_target() {
+        let projects = one_shot_projects();
+        let statuses = BTreeMap::from([(
+            "app".to_string(),
+            project_status(vec![
+                ("migrate", ServiceState::Succeeded),
+                ("web", ServiceState::Running),
+            ]),
+        )]);
+        let target = TargetSelector::Service(ServiceSelector {
+            project: "app".into(),
+            service: "migrate".into(),
+        });
+
+        assert!(wait_finished(
+            &target,
+            &projects,
+            &statuses,
+            WaitTarget::Stopped
+        ));
+    }
 }
@@ -1,7 +1,7 @@
 use oci_client::secrets::RegistryAuth as OciRegistryAuth;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum RegistryAuth {
     Anonymous,
@@ -53,6 +53,28 @@ impl RegistryAuth {
     }
 }
 
+pub trait Authenticable {
+    fn apply_authentication(
+        self,
+        auth: &RegistryAuth,
+    ) -> Self;
+}
+
+impl Authenticable for reqwest::RequestBuilder {
+    fn apply_authentication(
+        self,
+        auth: &RegistryAuth,
+    ) -> Self {
+        match auth {
+            RegistryAuth::Anonymous => self,
+            RegistryAuth::Basic { username, password } => {
+                self.basic_auth(username, Some(password))
+            }
+            RegistryAuth::Bearer { token } => self.bearer_auth(token),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -161,25 +183,3 @@ mod tests {
         );
     }
 }
-
-pub trait Authenticable {
-    fn apply_authentication(
-        self,
-        auth: &RegistryAuth,
-    ) -> Self;
-}
-
-impl Authenticable for reqwest::RequestBuilder {
-    fn apply_authentication(
-        self,
-        auth: &RegistryAuth,
-    ) -> Self {
-        match auth {
-            RegistryAuth::Anonymous => self,
-            RegistryAuth::Basic { username, password } => {
-                self.basic_auth(username, Some(password))
-            }
-            RegistryAuth::Bearer { token } => self.bearer_auth(token),
-        }
-    }
-}
@@ -0,0 +1,478 @@
+use oci_client::{Reference, client::ClientProtocol};
+use reqwest::{
+    Method, RequestBuilder, Response, StatusCode,
+    header::{ACCEPT, HeaderMap, WWW_AUTHENTICATE},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::RegistryAuth, client::normalize_scope};
+
+const DOCKER_HUB_REGISTRY: &str = "index.docker.io";
+
+/// Where Docker Hub's registry API is served; `index.docker.io` only
+/// redirects there.
+const DOCKER_HUB_ENDPOINT: &str = "registry-1.docker.io";
+
+/// The repository Docker documents for reading the pull quota. HEAD
+/// requests on its manifest report the limit without using it up.
+const DOCKER_HUB_QUOTA_REPOSITORY: &str = "ratelimitpreview/test";
+
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.docker.distribution.manifest.v2+json";
+
+/// Whether a request was let through, and why not if it wasn't.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", content = "error", rename_all = "lowercase")]
+pub enum Access {
+    Granted,
+    Denied(String),
+}
+
+impl Access {
+    pub fn is_granted(&self) -> bool {
+        matches!(self, Access::Granted)
+    }
+}
+
+/// The pull quota a registry reported, from the `RateLimit-Limit` and
+/// `RateLimit-Remaining` headers Docker Hub sends.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct CredentialCheck {
+    /// `registry[/repository]`, as the credentials are configured.
+    pub scope: String,
+    /// The repository pull access was tried on; without one only the
+    /// registry's `/v2/` endpoint is requested.
+    pub repository: Option<String>,
+    pub anonymous: Access,
+    /// `None` if there are no credentials for the scope.
+    pub authenticated: Option<Access>,
+    /// The user the credentials log in as, if they name one.
+    pub username: Option<String>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl CredentialCheck {
+    /// Whether configured credentials were rejected.
+    pub fn failed(&self) -> bool {
+        self.authenticated
+            .as_ref()
+            .is_some_and(|access| !access.is_granted())
+    }
+}
+
+/// What a check requests once it is past the login.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Probe {
+    Registry,
+    /// HEAD on a manifest, which is where Docker Hub reports the quota.
+    Manifest {
+        repository: String,
+        reference: String,
+    },
+}
+
+impl Probe {
+    fn for_scope(
+        scope: &str,
+        image: Option<&Reference>,
+    ) -> Self {
+        if let Some(image) = image {
+            return Probe::Manifest {
+                repository: image.repository().to_string(),
+                reference: image
+                    .digest()
+                    .or(image.tag())
+                    .unwrap_or("latest")
+                    .to_string(),
+            };
+        }
+
+        // A repository scope may only be a namespace, so without an image
+        // there's no repository known to exist.
+        if registry_of(scope) == DOCKER_HUB_REGISTRY {
+            Probe::Manifest {
+                repository: DOCKER_HUB_QUOTA_REPOSITORY.to_string(),
+                reference: "latest".to_string(),
+            }
+        } else {
+            Probe::Registry
+        }
+    }
+
+    fn repository(&self) -> Option<&str> {
+        match self {
+            Probe::Registry => None,
+            Probe::Manifest { repository, .. } => Some(repository),
+        }
+    }
+
+    fn request(
+        &self,
+        http: &reqwest::Client,
+        base_url: &str,
+    ) -> RequestBuilder {
+        match self {
+            Probe::Registry => http.get(format!("{base_url}/v2/")),
+            Probe::Manifest {
+                repository,
+                reference,
+            } => http
+                .request(
+                    Method::HEAD,
+                    format!("{base_url}/v2/{repository}/manifests/{reference}"),
+                )
+                .header(ACCEPT, MANIFEST_ACCEPT),
+        }
+    }
+}
+
+/// Runs credential checks over a shared HTTP client.
+#[derive(Clone, Debug)]
+pub struct CredentialChecker {
+    http: reqwest::Client,
+    protocol: ClientProtocol,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+impl CredentialChecker {
+    /// Sends requests through `http`, over plain HTTP where `protocol`
+    /// says so.
+    pub fn new(
+        http: reqwest::Client,
+        protocol: ClientProtocol,
+    ) -> Self {
+        Self { http, protocol }
+    }
+
+    /// Tries `scope` anonymously and, unless `auth` is anonymous, with
+    /// `auth`. With an `image`, pull access to it is tried instead of
+    /// the registry's `/v2/` endpoint.
+    pub async fn check(
+        &self,
+        scope: &str,
+        auth: &RegistryAuth,
+        image: Option<&Reference>,
+    ) -> CredentialCheck {
+        let scope = normalize_scope(scope);
+        let base_url = self.base_url(registry_of(&scope));
+        let probe = Probe::for_scope(&scope, image);
+
+        let (anonymous, anonymous_headers) = self
+            .access(&base_url, &probe, &RegistryAuth::Anonymous)
+            .await;
+        let (authenticated, headers) = match auth {
+            RegistryAuth::Anonymous => (None, anonymous_headers),
+            auth => {
+                let (access, headers) = self
+                    .access(&base_url, &probe, auth)
+                    .await;
+                (Some(access), headers.or(anonymous_headers))
+            }
+        };
+        let username = match auth {
+            RegistryAuth::Basic { username, .. } => Some(username.clone()),
+            _ => None,
+        };
+
+        CredentialCheck {
+            repository: probe.repository().map(str::to_string),
+            scope,
+            anonymous,
+            authenticated,
+            username,
+            rate_limit: headers.as_ref().and_then(rate_limit),
+        }
+    }
+
+    fn base_url(
+        &self,
+        registry: &str,
+    ) -> String {
+        let host = if registry == DOCKER_HUB_REGISTRY {
+            DOCKER_HUB_ENDPOINT
+        } else {
+            registry
+        };
+        let plain_http = match &self.protocol {
+            ClientProtocol::Http => true,
+            ClientProtocol::Https => false,
+            ClientProtocol::HttpsExcept(registries) => registries
+                .iter()
+                .any(|plain| plain == registry),
+        };
+        let scheme = if plain_http { "http" } else { "https" };
+        format!("{scheme}://{host}")
+    }
+
+    /// Sends `probe` with `auth`, logging in through the registry's token
+    /// service if it asks for that. Returns the headers of the last
+    /// registry response, if there was one.
+    async fn access(
+        &self,
+        base_url: &str,
+        probe: &Probe,
+        auth: &RegistryAuth,
+    ) -> (Access, Option<HeaderMap>) {
+        let response =
+            match send(probe.request(&self.http, base_url), auth).await {
+                Ok(response) => response,
+                Err(error) => return (Access::Denied(error), None),
+            };
+
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(BearerChallenge::parse);
+        let response = match challenge {
+            // A configured bearer token is the registry token itself.
+            Some(challenge)
+                if response.status() == StatusCode::UNAUTHORIZED
+                    && !matches!(auth, RegistryAuth::Bearer { .. }) =>
+            {
+                let token = match self
+                    .token(&challenge, probe, auth)
+                    .await
+                {
+                    Ok(token) => token,
+                    Err(error) => return (Access::Denied(error), None),
+                };
+                match send(
+                    probe.request(&self.http, base_url),
+                    &RegistryAuth::bearer(token),
+                )
+                .await
+                {
+                    Ok(response) => response,
+                    Err(error) => return (Access::Denied(error), None),
+                }
+            }
+            _ => response,
+        };
+
+        let access = if response.status().is_success() {
+            Access::Granted
+        } else {
+            Access::Denied(format!("registry answered {}", response.status()))
+        };
+        (access, Some(response.headers().clone()))
+    }
+
+    async fn token(
+        &self,
+        challenge: &BearerChallenge,
+        probe: &Probe,
+        auth: &RegistryAuth,
+    ) -> Result<String, String> {
+        let mut query = Vec::new();
+        if let Some(service) = &challenge.service {
+            query.push(("service", service.clone()));
+        }
+        if let Some(repository) = probe.repository() {
+            query.push(("scope", format!("repository:{repository}:pull")));
+        }
+
+        let response = send(
+            self.http
+                .get(&challenge.realm)
+                .query(&query),
+            auth,
+        )
+        .await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "token service answered {}",
+                response.status()
+            ));
+        }
+
+        let body = response
+            .json::<TokenResponse>()
+            .await
+            .map_err(|error| format!("unreadable token response: {error}"))?;
+        body.token
+            .or(body.access_token)
+            .ok_or_else(|| "token service sent no token".to_string())
+    }
+}
+
+fn registry_of(scope: &str) -> &str {
+    scope
+        .split('/')
+        .next()
+        .unwrap_or_default()
+}
+
+async fn send(
+    request: RequestBuilder,
+    auth: &RegistryAuth,
+) -> Result<Response, String> {
+    let request = match auth {
+        RegistryAuth::Anonymous => request,
+        RegistryAuth::Basic { username, password } => {
+            request.basic_auth(username, Some(password))
+        }
+        RegistryAuth::Bearer { token } => request.bearer_auth(token),
+    };
+    request
+        .send()
+        .await
+        .map_err(|error| format!("request failed: {error}"))
+}
+
+/// The parameters of a `WWW-Authenticate: Bearer ...` challenge.
+#[derive(Debug, PartialEq, Eq)]
+struct BearerChallenge {
+    realm: String,
+    service: Option<String>,
+}
+
+impl BearerChallenge {
+    fn parse(header: &str) -> Option<Self> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut service = None;
+        for (name, value) in challenge_params(params) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "service" => service = Some(value),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            realm: realm?,
+            service,
+        })
+    }
+}
+
+/// Splits `name="value",name=value` pairs; quoted values may contain
+/// commas, as scopes with several actions do.
+fn challenge_params(params: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = params.trim();
+
+    while let Some((name, after)) = rest.split_once('=') {
+        let name = name
+            .trim()
+            .trim_start_matches(',')
+            .trim();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (
+                    &quoted[..end],
+                    quoted
+                        .get(end + 1..)
+                        .unwrap_or_default(),
+                )
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        pairs.push((name.to_string(), value.to_string()));
+        rest = remaining;
+    }
+
+    pairs
+}
+
+fn rate_limit(headers: &HeaderMap) -> Option<RateLimit> {
+    // The values look like `100;w=21600`: the count, then the window.
+    let count = |name: &str| {
+        headers
+            .get(name)?
+            .to_str()
+            .ok()?
+            .split(';')
+            .next()?
+            .trim()
+            .parse()
+            .ok()
+    };
+
+    let rate_limit = RateLimit {
+        limit: count("ratelimit-limit"),
+        remaining: count("ratelimit-remaining"),
+    };
+    (rate_limit.limit.is_some() || rate_limit.remaining.is_some())
+        .then_some(rate_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn bearer_challenge_reads_realm_and_service() {
+        assert_eq!(
+            BearerChallenge::parse(
+                r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/nginx:pull,push""#
+            ),
+            Some(BearerChallenge {
+                realm: "https://auth.docker.io/token".to_string(),
+                service: Some("registry.docker.io".to_string()),
+            })
+        );
+        assert_eq!(BearerChallenge::parse(r#"Basic realm="registry""#), None);
+    }
+
+    #[test]
+    fn rate_limit_reads_counts_without_the_window() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(rate_limit(&headers), None);
+
+        headers
+            .insert("ratelimit-limit", HeaderValue::from_static("100;w=21600"));
+        headers.insert(
+            "ratelimit-remaining",
+            HeaderValue::from_static("76;w=21600"),
+        );
+        assert_eq!(
+            rate_limit(&headers),
+            Some(RateLimit {
+                limit: Some(100),
+                remaining: Some(76),
+            })
+        );
+    }
+
+    #[test]
+    fn probes_follow_the_scope_and_image() {
+        let image = Reference::try_from("ghcr.io/me/app:1.2").unwrap();
+        assert_eq!(
+            Probe::for_scope("ghcr.io", Some(&image)),
+            Probe::Manifest {
+                repository: "me/app".to_string(),
+                reference: "1.2".to_string(),
+            }
+        );
+        assert_eq!(Probe::for_scope("ghcr.io/me", None), Probe::Registry);
+        assert_eq!(
+            Probe::for_scope(DOCKER_HUB_REGISTRY, None).repository(),
+            Some(DOCKER_HUB_QUOTA_REPOSITORY)
+        );
+    }
+}
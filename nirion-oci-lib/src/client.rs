@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -9,15 +9,17 @@ use serde::Deserialize;
 
 use crate::{
     auth::RegistryAuth,
+    check::CredentialChecker,
     docker_hub::DockerHubClient,
-    oci::{
-        get_version_from_config, get_version_from_oci_tags, resolve_registry,
-    },
+    http::{HttpConfig, explain_tls_error, with_plain_http},
+    oci::{get_source_from_config, get_version_from_config, resolve_registry},
     oci_client::{
         Client, Reference,
-        client::{Certificate, ClientConfig, ClientProtocol},
+        client::{Certificate, ClientConfig, ClientProtocol, TagResponse},
+        manifest::{OciImageManifest, OciManifest},
         secrets::RegistryAuth as OciRegistryAuth,
     },
+    registry::{ImageDigests, RegistryBackend, TagVersion},
     version::{VersionedImage, canonical_version_tag},
 };
 
@@ -86,6 +88,17 @@ impl NirionOciClientConfig {
 #[derive(Default, Clone, Debug)]
 pub struct AuthConfig {
     pub sources: HashMap<String, RegistryAuth>,
+    /// Registries marked `"insecure": true`, which are spoken to over
+    /// plain HTTP.
+    pub insecure_registries: BTreeSet<String>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    #[serde(flatten)]
+    auth: RegistryAuth,
+    #[serde(default)]
+    insecure: bool,
 }
 
 impl<'de> Deserialize<'de> for AuthConfig {
@@ -93,17 +106,23 @@ impl<'de> Deserialize<'de> for AuthConfig {
     where
         D: serde::Deserializer<'de>,
     {
-        let sources =
-            HashMap::<String, RegistryAuth>::deserialize(deserializer)?;
+        let entries = HashMap::<String, AuthEntry>::deserialize(deserializer)?;
 
-        let resolved_sources = sources
-            .into_iter()
-            .map(|(scope, auth)| (normalize_scope(&scope), auth))
-            .collect();
+        let mut config = AuthConfig::default();
+        for (scope, entry) in entries {
+            let scope = normalize_scope(&scope);
+            if entry.insecure {
+                let registry = scope
+                    .split_once('/')
+                    .map_or(scope.as_str(), |(registry, _)| registry);
+                config
+                    .insecure_registries
+                    .insert(registry.to_string());
+            }
+            config.sources.insert(scope, entry.auth);
+        }
 
-        Ok(AuthConfig {
-            sources: resolved_sources,
-        })
+        Ok(config)
     }
 }
 
@@ -121,12 +140,23 @@ impl AuthConfig {
         &self,
         image: &Reference,
     ) -> RegistryAuth {
+        self.scope_for(image)
+            .and_then(|scope| self.sources.get(&scope))
+            .cloned()
+            .unwrap_or_else(RegistryAuth::anonymous)
+    }
+
+    /// The most specific scope with credentials that covers `image`.
+    pub fn scope_for(
+        &self,
+        image: &Reference,
+    ) -> Option<String> {
         let registry = resolve_registry(image.registry().to_string());
         let mut key = format!("{}/{}", registry, image.repository());
 
         loop {
-            if let Some(auth) = self.sources.get(&key) {
-                return auth.clone();
+            if self.sources.contains_key(&key) {
+                return Some(key);
             }
 
             if let Some((parent, _)) = key.rsplit_once('/') {
@@ -137,21 +167,29 @@ impl AuthConfig {
         }
 
         self.sources
-            .get(&registry)
-            .cloned()
-            .unwrap_or_else(RegistryAuth::anonymous)
+            .contains_key(&registry)
+            .then_some(registry)
     }
 }
 
-fn normalize_scope(scope: &str) -> String {
+/// The key credentials for `scope` (`registry` or `registry/repository`,
+/// with or without a scheme) are stored under in [`AuthConfig::sources`].
+pub fn normalize_scope(scope: &str) -> String {
+    let scope = scope
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
     let mut parts = scope.splitn(2, '/');
     let registry = parts.next().unwrap_or_default();
     let registry = resolve_registry(registry.to_string());
 
-    if let Some(repository) = parts.next() {
-        format!("{registry}/{repository}")
-    } else {
-        registry
+    match parts
+        .next()
+        .map(|repository| repository.trim_end_matches('/'))
+    {
+        Some(repository) if !repository.is_empty() => {
+            format!("{registry}/{repository}")
+        }
+        _ => registry,
     }
 }
 
@@ -161,11 +199,31 @@ struct ClientKey {
     auth: RegistryAuth,
 }
 
+/// An image [`NirionOciClient`] resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedImage {
+    pub image: VersionedImage,
+    /// How many tags were read looking for its version, if that search
+    /// hit its limit before it was done.
+    pub tag_search_capped_after: Option<usize>,
+}
+
+impl ResolvedImage {
+    fn unchanged(image: VersionedImage) -> Self {
+        Self {
+            image,
+            tag_search_capped_after: None,
+        }
+    }
+}
+
 pub struct NirionOciClient {
     auth: AuthConfig,
     docker_hub: DockerHubClient,
     oci_client_config: NirionOciClientConfig,
+    mirrors: HashMap<String, String>,
     clients: Mutex<HashMap<ClientKey, Arc<Client>>>,
+    http: reqwest::Client,
 }
 
 impl NirionOciClient {
@@ -173,95 +231,241 @@ impl NirionOciClient {
         NirionOciClientBuilder::default()
     }
 
+    pub fn auth_config(&self) -> &AuthConfig {
+        &self.auth
+    }
+
+    /// A checker with the same network settings as the registry clients.
+    pub fn credential_checker(&self) -> CredentialChecker {
+        CredentialChecker::new(
+            self.http.clone(),
+            self.oci_client_config.protocol.clone(),
+        )
+    }
+
+    /// `image` as served by the mirror configured for its registry.
+    pub fn mirror_reference(
+        &self,
+        image: &Reference,
+    ) -> Option<Reference> {
+        let registry = self
+            .mirrors
+            .get(image.resolve_registry())?
+            .clone();
+        let repository = image.repository().to_string();
+
+        Some(match (image.tag(), image.digest()) {
+            (Some(tag), Some(digest)) => Reference::with_tag_and_digest(
+                registry,
+                repository,
+                tag.to_string(),
+                digest.to_string(),
+            ),
+            (None, Some(digest)) => {
+                Reference::with_digest(registry, repository, digest.to_string())
+            }
+            (tag, None) => Reference::with_tag(
+                registry,
+                repository,
+                tag.unwrap_or("latest").to_string(),
+            ),
+        })
+    }
+
     pub async fn get_versioned_image(
         &self,
         image: &Reference,
-    ) -> anyhow::Result<VersionedImage> {
-        let auth = self.auth.auth_for(image);
-        let client = self.client_for(image, &auth).await;
-        let oci_auth = auth.to_oci_auth();
+    ) -> anyhow::Result<ResolvedImage> {
+        self.via_mirror(image, image.to_string(), |image| async move {
+            let auth = self.auth.auth_for(&image);
+            let client = self.client_for(&image, &auth).await;
+            self.resolve_versioned_image(&self.with_docker_hub(&client), &image)
+                .await
+                .map_err(|error| {
+                    explain_tls_error(error, image.resolve_registry())
+                })
+        })
+        .await
+    }
 
-        let (version, digest) = self
-            .resolve_version_and_digest(&client, image, &oci_auth)
-            .await?;
+    pub async fn get_updated_versioned_image(
+        &self,
+        versioned_image: &VersionedImage,
+    ) -> anyhow::Result<ResolvedImage> {
+        let image = Reference::try_from(versioned_image.image.as_str())?;
+        self.via_mirror(
+            &image,
+            versioned_image.image.clone(),
+            |image| async move {
+                let auth = self.auth.auth_for(&image);
+                let client = self.client_for(&image, &auth).await;
+                self.resolve_updated_image(
+                    &self.with_docker_hub(&client),
+                    &image,
+                    versioned_image,
+                )
+                .await
+                .map_err(|error| {
+                    explain_tls_error(error, image.resolve_registry())
+                })
+            },
+        )
+        .await
+    }
 
-        Ok(VersionedImage {
-            image: image.to_string(),
-            version,
-            digest,
+    /// [`Self::get_versioned_image`] against an explicit registry
+    /// backend instead of the per-registry clients this one manages.
+    pub async fn get_versioned_image_with(
+        &self,
+        backend: &impl RegistryBackend,
+        image: &Reference,
+    ) -> anyhow::Result<ResolvedImage> {
+        self.via_mirror(image, image.to_string(), |image| async move {
+            self.resolve_versioned_image(backend, &image)
+                .await
         })
+        .await
     }
 
-    pub async fn get_updated_versioned_image(
+    pub async fn get_updated_versioned_image_with(
         &self,
+        backend: &impl RegistryBackend,
         versioned_image: &VersionedImage,
-    ) -> anyhow::Result<VersionedImage> {
+    ) -> anyhow::Result<ResolvedImage> {
         let image = Reference::try_from(versioned_image.image.as_str())?;
-        let auth = self.auth.auth_for(&image);
-        let client = self.client_for(&image, &auth).await;
-        let oci_auth = auth.to_oci_auth();
+        self.via_mirror(
+            &image,
+            versioned_image.image.clone(),
+            |image| async move {
+                self.resolve_updated_image(backend, &image, versioned_image)
+                    .await
+            },
+        )
+        .await
+    }
+
+    /// Resolves `image` through its registry's mirror, falling back to
+    /// the upstream registry if there is none or it fails. The result
+    /// always records `canonical`, so lock files stay portable across
+    /// hosts with different mirrors.
+    async fn via_mirror<F, Fut>(
+        &self,
+        image: &Reference,
+        canonical: String,
+        resolve: F,
+    ) -> anyhow::Result<ResolvedImage>
+    where
+        F: Fn(Reference) -> Fut,
+        Fut: Future<Output = anyhow::Result<ResolvedImage>>,
+    {
+        if let Some(mirrored) = self.mirror_reference(image) {
+            let resolved = resolve(mirrored).await;
+            if let Ok(mut resolved) = resolved {
+                resolved.image.image = canonical;
+                return Ok(resolved);
+            }
+        }
+
+        resolve(image.clone()).await
+    }
 
-        let (_, current_digest, _) = client
-            .pull_manifest_and_config(&image, &oci_auth)
+    async fn resolve_versioned_image(
+        &self,
+        backend: &impl RegistryBackend,
+        image: &Reference,
+    ) -> anyhow::Result<ResolvedImage> {
+        let oci_auth = self.auth.auth_for(image).to_oci_auth();
+
+        let (tag_version, digest, size, source) = self
+            .resolve_version_and_digest(backend, image, &oci_auth)
             .await?;
 
-        if current_digest == versioned_image.digest {
-            return Ok(VersionedImage {
-                image: versioned_image.image.clone(),
-                version: versioned_image.version.clone(),
-                digest: versioned_image.digest.clone(),
-            });
+        Ok(ResolvedImage {
+            image: VersionedImage {
+                image: image.to_string(),
+                version: tag_version.version,
+                digest,
+                size: Some(size),
+                source,
+            },
+            tag_search_capped_after: tag_version.capped_after,
+        })
+    }
+
+    async fn resolve_updated_image(
+        &self,
+        backend: &impl RegistryBackend,
+        image: &Reference,
+        versioned_image: &VersionedImage,
+    ) -> anyhow::Result<ResolvedImage> {
+        let oci_auth = self.auth.auth_for(image).to_oci_auth();
+
+        let (_, current_digests, _) = backend
+            .pull_manifest_and_config(image, &oci_auth)
+            .await?;
+
+        // Locks written before index digests were recorded hold the
+        // platform digest; that still counts as up to date.
+        if current_digests.contains(&versioned_image.digest) {
+            return Ok(ResolvedImage::unchanged(versioned_image.clone()));
         }
 
-        let (version, digest) = self
-            .resolve_version_and_digest(&client, &image, &oci_auth)
+        let (tag_version, digest, size, source) = self
+            .resolve_version_and_digest(backend, image, &oci_auth)
             .await?;
 
-        Ok(VersionedImage {
-            image: versioned_image.image.clone(),
-            version,
-            digest,
+        Ok(ResolvedImage {
+            image: VersionedImage {
+                image: versioned_image.image.clone(),
+                version: tag_version.version,
+                digest,
+                size: Some(size),
+                source,
+            },
+            tag_search_capped_after: tag_version.capped_after,
         })
     }
 
     async fn resolve_version_and_digest(
         &self,
-        client: &Client,
+        client: &impl RegistryBackend,
         image: &Reference,
         auth: &OciRegistryAuth,
-    ) -> anyhow::Result<(Option<String>, String)> {
-        let (_, digest, raw_config) = client
+    ) -> anyhow::Result<(TagVersion, String, u64, Option<String>)> {
+        let (manifest, digests, raw_config) = client
             .pull_manifest_and_config(image, auth)
             .await?;
+        let digest = digests.locked().to_string();
+        let size = manifest
+            .layers
+            .iter()
+            .map(|layer| u64::try_from(layer.size).unwrap_or_default())
+            .sum();
 
         let config: ConfigFile = serde_json::from_str(&raw_config)?;
+        let source = get_source_from_config(&config);
 
         if let Some(version) = get_version_from_config(&config) {
-            return Ok((Some(version), digest));
+            let version = TagVersion::complete(Some(version));
+            return Ok((version, digest, size, source));
         }
 
-        let version = self
-            .resolve_version_from_tags(client, image, &digest, auth)
+        let tag_version = client
+            .version_from_tags(image, &digests, auth)
             .await?;
 
-        Ok((version, digest))
+        Ok((tag_version, digest, size, source))
     }
 
-    async fn resolve_version_from_tags(
-        &self,
-        client: &Client,
-        image: &Reference,
-        digest: &str,
-        auth: &OciRegistryAuth,
-    ) -> anyhow::Result<Option<String>> {
-        if self.docker_hub.supports(image) {
-            let alias_tags = self
-                .docker_hub
-                .get_alias_tags(image, digest)
-                .await?;
-            Ok(canonical_version_tag(&alias_tags))
-        } else {
-            get_version_from_oci_tags(client, image, digest, auth).await
+    /// `client` with the version tags of Docker Hub images looked up
+    /// through the Docker Hub API.
+    fn with_docker_hub<'a>(
+        &'a self,
+        client: &'a Client,
+    ) -> WithDockerHub<'a, Client> {
+        WithDockerHub {
+            backend: client,
+            docker_hub: &self.docker_hub,
         }
     }
 
@@ -302,22 +506,80 @@ impl NirionOciClient {
     }
 }
 
-pub struct NirionOciClientBuilder {
-    auth: AuthConfig,
-    docker_hub: DockerHubClient,
-    oci_client_config: NirionOciClientConfig,
+/// A backend that finds the version tags of Docker Hub images through
+/// the Docker Hub API, which lists tags with their digests, rather than
+/// pulling the manifest of every tag.
+struct WithDockerHub<'a, B> {
+    backend: &'a B,
+    docker_hub: &'a DockerHubClient,
 }
 
-impl Default for NirionOciClientBuilder {
-    fn default() -> Self {
-        Self {
-            auth: AuthConfig::default(),
-            docker_hub: DockerHubClient::default(),
-            oci_client_config: NirionOciClientConfig::default(),
+impl<B: RegistryBackend> RegistryBackend for WithDockerHub<'_, B> {
+    async fn pull_manifest_and_config(
+        &self,
+        image: &Reference,
+        auth: &OciRegistryAuth,
+    ) -> anyhow::Result<(OciImageManifest, ImageDigests, String)> {
+        self.backend
+            .pull_manifest_and_config(image, auth)
+            .await
+    }
+
+    async fn list_tags(
+        &self,
+        image: &Reference,
+        auth: &OciRegistryAuth,
+        n: Option<usize>,
+        last: Option<&str>,
+    ) -> anyhow::Result<TagResponse> {
+        self.backend
+            .list_tags(image, auth, n, last)
+            .await
+    }
+
+    async fn pull_manifest(
+        &self,
+        image: &Reference,
+        auth: &OciRegistryAuth,
+    ) -> anyhow::Result<(OciManifest, String)> {
+        self.backend
+            .pull_manifest(image, auth)
+            .await
+    }
+
+    async fn version_from_tags(
+        &self,
+        image: &Reference,
+        digests: &ImageDigests,
+        auth: &OciRegistryAuth,
+    ) -> anyhow::Result<TagVersion> {
+        if !self.docker_hub.supports(image) {
+            return self
+                .backend
+                .version_from_tags(image, digests, auth)
+                .await;
         }
+
+        let alias_tags = self
+            .docker_hub
+            .get_alias_tags(image, digests)
+            .await?;
+        Ok(TagVersion {
+            version: canonical_version_tag(&alias_tags.tags),
+            capped_after: alias_tags.capped_after,
+        })
     }
 }
 
+#[derive(Default)]
+pub struct NirionOciClientBuilder {
+    auth: AuthConfig,
+    docker_hub: DockerHubClient,
+    oci_client_config: NirionOciClientConfig,
+    mirrors: HashMap<String, String>,
+    http: reqwest::Client,
+}
+
 impl NirionOciClientBuilder {
     pub fn auth(
         mut self,
@@ -360,12 +622,62 @@ impl NirionOciClientBuilder {
         self
     }
 
+    /// Applies proxy, certificate and plain-HTTP settings to the registry
+    /// clients and the Docker Hub client set so far.
+    pub fn http(
+        mut self,
+        http: &HttpConfig,
+    ) -> anyhow::Result<Self> {
+        http.apply(&mut self.oci_client_config);
+        self.http = http.reqwest_client()?;
+        self.docker_hub = self
+            .docker_hub
+            .with_http_client(self.http.clone());
+        Ok(self)
+    }
+
+    /// Resolves images from `upstream` (e.g. `docker.io`) against
+    /// `mirror` first, falling back to `upstream` if the mirror fails.
+    pub fn mirror(
+        mut self,
+        upstream: impl Into<String>,
+        mirror: impl Into<String>,
+    ) -> Self {
+        self.mirrors.insert(
+            resolve_registry(upstream.into()),
+            resolve_registry(mirror.into()),
+        );
+        self
+    }
+
     pub fn build(self) -> NirionOciClient {
+        let mut oci_client_config = self.oci_client_config;
+        oci_client_config.protocol = with_plain_http(
+            oci_client_config.protocol,
+            self.auth
+                .insecure_registries
+                .iter()
+                .cloned(),
+        );
+
+        // The Docker Hub API takes the same account as the registry.
+        let mut docker_hub = self.docker_hub;
+        if !docker_hub.has_credentials()
+            && let Some(auth) = self
+                .auth
+                .sources
+                .get(&resolve_registry("docker.io".to_string()))
+        {
+            docker_hub = docker_hub.with_credentials(auth.clone());
+        }
+
         NirionOciClient {
             auth: self.auth,
-            docker_hub: self.docker_hub,
-            oci_client_config: self.oci_client_config,
+            docker_hub,
+            oci_client_config,
+            mirrors: self.mirrors,
             clients: Mutex::new(HashMap::new()),
+            http: self.http,
         }
     }
 }
@@ -447,6 +759,56 @@ mod tests {
         assert_eq!(username(config.auth_for(&image)), Some("user".to_string()));
     }
 
+    #[test]
+    fn auth_scopes_with_ports_match_images_on_that_port() {
+        let config: AuthConfig = serde_json::from_str(
+            r#"{
+                "https://registry.lan:5000/": {
+                    "type": "bearer",
+                    "token": "lan"
+                },
+                "registry.lan": {"type": "bearer", "token": "default-port"}
+            }"#,
+        )
+        .unwrap();
+
+        let mirrored =
+            Reference::try_from("registry.lan:5000/library/postgres:16")
+                .unwrap();
+        let default_port =
+            Reference::try_from("registry.lan:443/library/postgres:16")
+                .unwrap();
+
+        assert_eq!(config.auth_for(&mirrored), RegistryAuth::bearer("lan"));
+        assert_eq!(
+            config.auth_for(&default_port),
+            RegistryAuth::bearer("default-port")
+        );
+    }
+
+    #[test]
+    fn insecure_auth_entries_switch_registry_to_plain_http() {
+        let config: AuthConfig = serde_json::from_str(
+            r#"{
+                "registry.lan:5000/library": {
+                    "type": "anonymous",
+                    "insecure": true
+                },
+                "ghcr.io": {"type": "bearer", "token": "t"}
+            }"#,
+        )
+        .unwrap();
+
+        let client = NirionOciClient::builder()
+            .auth(config)
+            .build();
+
+        assert_eq!(
+            client.oci_client_config.protocol,
+            ClientProtocol::HttpsExcept(vec!["registry.lan:5000".to_string()])
+        );
+    }
+
     #[test]
     fn builder_add_auth_and_protocol_configures_client() {
         let client = NirionOciClient::builder()
@@ -490,4 +852,16 @@ mod tests {
         assert!(client.docker_hub.supports(&local));
         assert_eq!(client.oci_client_config.protocol, ClientProtocol::Http);
     }
+    #[test]
+    fn builder_gives_docker_hub_the_docker_io_credentials() {
+        let anonymous = NirionOciClient::builder()
+            .add_auth("ghcr.io", auth("registry"))
+            .build();
+        assert!(!anonymous.docker_hub.has_credentials());
+
+        let client = NirionOciClient::builder()
+            .add_auth("docker.io", auth("hub"))
+            .build();
+        assert!(client.docker_hub.has_credentials());
+    }
 }
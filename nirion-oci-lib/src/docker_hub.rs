@@ -1,17 +1,32 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc};
 
 use oci_client::{Reference, config::Architecture};
-use reqwest::StatusCode;
+use reqwest::{RequestBuilder, StatusCode};
 use serde::Deserialize;
 use thiserror::Error;
+use tokio::sync::Mutex;
+
+use crate::{auth::RegistryAuth, registry::ImageDigests};
 
 const DOCKERHUB_BASE: &str = "https://hub.docker.com/v2";
 
+/// The largest page Docker Hub serves.
+const ALIAS_TAGS_PAGE_SIZE: u32 = 100;
+
+/// How many pages [`DockerHubClient::get_alias_tags`] reads before giving
+/// up, so repositories with tens of thousands of tags stay cheap.
+const MAX_ALIAS_TAG_PAGES: usize = 20;
+
+/// Talks to the Docker Hub API over one pooled HTTP client. Clones share
+/// the client and the login token.
 #[derive(Clone, Debug)]
 pub struct DockerHubClient {
     http: reqwest::Client,
     base_url: String,
     registries: HashSet<String>,
+    credentials: Option<RegistryAuth>,
+    /// The JWT from logging in with basic credentials, once requested.
+    token: Arc<Mutex<Option<String>>>,
 }
 
 impl Default for DockerHubClient {
@@ -20,6 +35,8 @@ impl Default for DockerHubClient {
             http: reqwest::Client::new(),
             base_url: DOCKERHUB_BASE.to_string(),
             registries: HashSet::from(["docker.io".to_string()]),
+            credentials: None,
+            token: Arc::default(),
         }
     }
 }
@@ -36,6 +53,16 @@ impl DockerHubClient {
         }
     }
 
+    /// Sends requests through `http`, e.g. one built from
+    /// [`crate::http::HttpConfig`].
+    pub fn with_http_client(
+        mut self,
+        http: reqwest::Client,
+    ) -> Self {
+        self.http = http;
+        self
+    }
+
     pub fn with_registries(
         mut self,
         registries: impl IntoIterator<Item = String>,
@@ -44,6 +71,26 @@ impl DockerHubClient {
         self
     }
 
+    /// Authenticates requests, which lifts the anonymous rate limit and
+    /// gives access to private repositories. Basic credentials (a
+    /// password or access token) are exchanged for a JWT on the first
+    /// request; a bearer token is sent as it is.
+    pub fn with_credentials(
+        mut self,
+        credentials: RegistryAuth,
+    ) -> Self {
+        self.credentials = match credentials {
+            RegistryAuth::Anonymous => None,
+            credentials => Some(credentials),
+        };
+        self.token = Arc::default();
+        self
+    }
+
+    pub fn has_credentials(&self) -> bool {
+        self.credentials.is_some()
+    }
+
     pub fn supports(
         &self,
         reference: &Reference,
@@ -57,12 +104,7 @@ impl DockerHubClient {
         reference: &Reference,
         page_size: u32,
     ) -> Result<TagsResponse, DockerHubError> {
-        let (namespace, repository) = self.dockerhub_parts(reference)?;
-
-        let mut next_url = Some(format!(
-            "{base}/repositories/{namespace}/{repository}/tags?page_size={page_size}&page=1",
-            base = self.base_url
-        ));
+        let mut next_url = Some(self.tags_url(reference, page_size, "")?);
 
         let mut all_results = Vec::new();
         let mut total_count = 0;
@@ -101,7 +143,7 @@ impl DockerHubClient {
             base = self.base_url
         );
 
-        let resp = self.http.get(&url).send().await?;
+        let resp = self.get(&url).await?;
 
         if resp.status().is_success() {
             Ok(resp.json::<Tag>().await?)
@@ -113,17 +155,67 @@ impl DockerHubClient {
     pub async fn get_alias_tags(
         &self,
         image: &Reference,
-        digest: &str,
-    ) -> anyhow::Result<Vec<String>> {
-        let tags = self
-            .fetch_all_tags(image, 100)
-            .await?
-            .results;
+        digests: &ImageDigests,
+    ) -> anyhow::Result<AliasTags> {
+        let arch = Architecture::default();
+        let mut next_url = Some(self.tags_url(
+            image,
+            ALIAS_TAGS_PAGE_SIZE,
+            "&ordering=last_updated",
+        )?);
+        let mut aliases = Vec::new();
+        let mut pages = 0;
+        let mut capped_after = None;
+
+        // Tags come newest first and the digest being resolved is a
+        // current one, so its aliases sit together near the start. Stop
+        // once that group has ended instead of reading every page.
+        while let Some(url) = next_url {
+            if pages == MAX_ALIAS_TAG_PAGES {
+                capped_after = Some(pages * ALIAS_TAGS_PAGE_SIZE as usize);
+                break;
+            }
+            pages += 1;
+
+            let body = self.fetch_tags_url(&url).await?;
+            let group_continues = body
+                .results
+                .last()
+                .is_some_and(|tag| tag_matches(tag, digests, &arch));
+            let found = alias_dockerhub_tags_from_tags(
+                body.results,
+                digests,
+                arch.clone(),
+            );
+
+            if !found.is_empty() && !group_continues {
+                aliases.extend(found);
+                break;
+            }
+            if found.is_empty() && !aliases.is_empty() {
+                break;
+            }
+            aliases.extend(found);
+            next_url = body.next;
+        }
+
+        Ok(AliasTags {
+            tags: aliases,
+            capped_after,
+        })
+    }
 
-        Ok(alias_dockerhub_tags_from_tags(
-            tags,
-            digest,
-            Architecture::default(),
+    fn tags_url(
+        &self,
+        reference: &Reference,
+        page_size: u32,
+        query: &str,
+    ) -> Result<String, DockerHubError> {
+        let (namespace, repository) = self.dockerhub_parts(reference)?;
+
+        Ok(format!(
+            "{base}/repositories/{namespace}/{repository}/tags?page_size={page_size}&page=1{query}",
+            base = self.base_url
         ))
     }
 
@@ -142,7 +234,7 @@ impl DockerHubClient {
         &self,
         url: &str,
     ) -> Result<TagsResponse, DockerHubError> {
-        let resp = self.http.get(url).send().await?;
+        let resp = self.get(url).await?;
 
         if resp.status().is_success() {
             Ok(resp.json::<TagsResponse>().await?)
@@ -150,6 +242,86 @@ impl DockerHubClient {
             parse_dockerhub_error(resp).await
         }
     }
+
+    /// GETs `url` with the credentials, if any. A login token that
+    /// expired is renewed once.
+    async fn get(
+        &self,
+        url: &str,
+    ) -> Result<reqwest::Response, DockerHubError> {
+        let request = self.http.get(url);
+        let resp = self
+            .authorize(
+                request
+                    .try_clone()
+                    .expect("GET has no body"),
+            )
+            .await?
+            .send()
+            .await?;
+        if resp.status() != StatusCode::UNAUTHORIZED
+            || !matches!(self.credentials, Some(RegistryAuth::Basic { .. }))
+        {
+            return Ok(resp);
+        }
+
+        self.token.lock().await.take();
+        Ok(self
+            .authorize(request)
+            .await?
+            .send()
+            .await?)
+    }
+
+    async fn authorize(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<RequestBuilder, DockerHubError> {
+        let token = match &self.credentials {
+            None | Some(RegistryAuth::Anonymous) => return Ok(request),
+            Some(RegistryAuth::Bearer { token }) => token.clone(),
+            Some(RegistryAuth::Basic { username, password }) => {
+                let mut cached = self.token.lock().await;
+                match &*cached {
+                    Some(token) => token.clone(),
+                    None => cached
+                        .insert(self.login(username, password).await?)
+                        .clone(),
+                }
+            }
+        };
+        Ok(request.bearer_auth(token))
+    }
+
+    async fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<String, DockerHubError> {
+        #[derive(Deserialize)]
+        struct LoginResponse {
+            token: String,
+        }
+
+        let resp = self
+            .http
+            .post(format!("{}/users/login", self.base_url))
+            .json(&serde_json::json!({
+                "username": username,
+                "password": password,
+            }))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(resp
+                .json::<LoginResponse>()
+                .await?
+                .token)
+        } else {
+            parse_dockerhub_error(resp).await
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -185,6 +357,15 @@ pub enum DockerHubError {
     MissingDigest,
 }
 
+/// The tags [`DockerHubClient::get_alias_tags`] found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AliasTags {
+    pub tags: Vec<String>,
+    /// How many tags were read, if the page limit was reached before
+    /// any tag matched or while the matching ones still continued.
+    pub capped_after: Option<usize>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct TagsResponse {
     pub count: u64,
@@ -196,6 +377,8 @@ pub struct TagsResponse {
 #[derive(Debug, Deserialize)]
 pub struct Tag {
     pub id: u64,
+    /// The digest the tag points at; an index for multi-platform images.
+    pub digest: Option<String>,
     pub images: Vec<Image>,
     pub creator: u64,
     pub last_updated: Option<String>,
@@ -250,7 +433,7 @@ fn dockerhub_repository_parts(
     let second = parts.next();
 
     match second {
-        Some(rest) => Ok((first.to_string(), format!("{rest}"))),
+        Some(rest) => Ok((first.to_string(), rest.to_string())),
         None => Ok(("library".to_string(), first.to_string())),
     }
 }
@@ -271,20 +454,33 @@ async fn parse_dockerhub_error<T>(
 
 fn alias_dockerhub_tags_from_tags(
     tags: Vec<Tag>,
-    digest: &str,
+    digests: &ImageDigests,
     arch: Architecture,
 ) -> Vec<String> {
     tags.into_iter()
-        .filter(|tag| {
-            tag.images.iter().any(|image| {
-                image.architecture == arch
-                    && image.digest.as_deref() == Some(digest)
-            })
-        })
+        .filter(|tag| tag_matches(tag, digests, &arch))
         .map(|tag| tag.name)
         .collect()
 }
 
+/// Whether `tag` points at the same index, or the same image for `arch`.
+fn tag_matches(
+    tag: &Tag,
+    digests: &ImageDigests,
+    arch: &Architecture,
+) -> bool {
+    let same_index = digests
+        .index
+        .as_ref()
+        .is_some_and(|index| tag.digest.as_ref() == Some(index));
+
+    same_index
+        || tag.images.iter().any(|image| {
+            &image.architecture == arch
+                && image.digest.as_deref() == Some(&digests.platform)
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +492,7 @@ mod tests {
     ) -> Tag {
         Tag {
             id: 0,
+            digest: None,
             images: vec![Image {
                 architecture,
                 features: String::new(),
@@ -388,6 +585,7 @@ mod tests {
     fn alias_dockerhub_tags_from_tags_returns_matching_digest_and_architecture()
     {
         let arch = Architecture::default();
+        let digests = ImageDigests::platform("sha256:abc");
         let tags = vec![
             tag("latest", arch.clone(), Some("sha256:abc")),
             tag("1.2.3", arch.clone(), Some("sha256:abc")),
@@ -395,7 +593,7 @@ mod tests {
         ];
 
         assert_eq!(
-            alias_dockerhub_tags_from_tags(tags, "sha256:abc", arch),
+            alias_dockerhub_tags_from_tags(tags, &digests, arch),
             vec!["latest".to_string(), "1.2.3".to_string()]
         );
     }
@@ -403,20 +601,46 @@ mod tests {
     #[test]
     fn alias_dockerhub_tags_from_tags_ignores_missing_digest() {
         let arch = Architecture::default();
+        let digests = ImageDigests::platform("sha256:abc");
         let tags = vec![tag("latest", arch.clone(), None)];
 
         assert!(
-            alias_dockerhub_tags_from_tags(tags, "sha256:abc", arch).is_empty()
+            alias_dockerhub_tags_from_tags(tags, &digests, arch).is_empty()
         );
     }
 
     #[test]
     fn alias_dockerhub_tags_from_tags_ignores_different_architecture() {
         let arch = Architecture::default();
+        let digests = ImageDigests::platform("sha256:abc");
         let tags = vec![tag("latest", Architecture::ARM64, Some("sha256:abc"))];
 
         assert!(
-            alias_dockerhub_tags_from_tags(tags, "sha256:abc", arch).is_empty()
+            alias_dockerhub_tags_from_tags(tags, &digests, arch).is_empty()
+        );
+    }
+
+    #[test]
+    fn alias_dockerhub_tags_from_tags_matches_index_or_platform_digest() {
+        let arch = Architecture::default();
+        let digests = ImageDigests {
+            index: Some("sha256:index".to_string()),
+            platform: "sha256:platform".to_string(),
+        };
+        let mut multi_arch =
+            tag("1.4", Architecture::ARM64, Some("sha256:arm"));
+        multi_arch.digest = Some("sha256:index".to_string());
+        let mut older = tag("1.3", arch.clone(), Some("sha256:older"));
+        older.digest = Some("sha256:older-index".to_string());
+        let tags = vec![
+            multi_arch,
+            tag("1.4-linux", arch.clone(), Some("sha256:platform")),
+            older,
+        ];
+
+        assert_eq!(
+            alias_dockerhub_tags_from_tags(tags, &digests, arch),
+            ["1.4", "1.4-linux"]
         );
     }
 }
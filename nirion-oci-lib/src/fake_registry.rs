@@ -0,0 +1,346 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use oci_client::{
+    Reference,
+    client::TagResponse,
+    config::{Architecture, Config, ConfigFile, Os},
+    errors::{OciDistributionError, OciEnvelope, OciError, OciErrorCode},
+    manifest::{
+        ImageIndexEntry, OciDescriptor, OciImageIndex, OciImageManifest,
+        OciManifest, Platform,
+    },
+    secrets::RegistryAuth,
+};
+
+use crate::{
+    oci::get_version_from_oci_tags,
+    registry::{ImageDigests, RegistryBackend, TagVersion},
+};
+
+#[derive(Debug, Default)]
+struct FakeRepository {
+    tags: BTreeMap<String, String>,
+    versions: HashMap<String, Option<String>>,
+    sizes: HashMap<String, u64>,
+    sources: HashMap<String, String>,
+    /// Image index digests and the platform manifest each one lists.
+    indexes: HashMap<String, String>,
+}
+
+/// An in-memory [`RegistryBackend`]. Clones share their contents, so a
+/// test can keep a handle and push new digests after handing the
+/// registry to the code under test.
+#[derive(Debug, Default, Clone)]
+pub struct FakeRegistry {
+    repositories: Arc<Mutex<HashMap<String, FakeRepository>>>,
+}
+
+impl FakeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Points `image` (which must carry a tag) at `digest`. The image
+    /// config carries `version` as its `org.opencontainers.image.version`
+    /// label, if given.
+    pub fn push(
+        &self,
+        image: &str,
+        digest: &str,
+        version: Option<&str>,
+    ) -> &Self {
+        let reference: Reference = image
+            .parse()
+            .expect("fake registry image reference");
+        let tag = reference
+            .tag()
+            .expect("fake registry images need a tag")
+            .to_string();
+
+        let mut repositories = self.repositories.lock().unwrap();
+        let repository = repositories
+            .entry(repository_key(&reference))
+            .or_default();
+        repository
+            .tags
+            .insert(tag, digest.to_string());
+        repository
+            .versions
+            .insert(digest.to_string(), version.map(str::to_string));
+        self
+    }
+
+    /// Points `image` at a multi-platform index `index_digest` whose
+    /// entry for this host's platform is `platform_digest`.
+    pub fn push_index(
+        &self,
+        image: &str,
+        index_digest: &str,
+        platform_digest: &str,
+        version: Option<&str>,
+    ) -> &Self {
+        self.push(image, index_digest, version);
+
+        let reference: Reference = image
+            .parse()
+            .expect("fake registry image reference");
+        let mut repositories = self.repositories.lock().unwrap();
+        let repository = repositories
+            .entry(repository_key(&reference))
+            .or_default();
+        repository
+            .versions
+            .insert(platform_digest.to_string(), version.map(str::to_string));
+        repository
+            .indexes
+            .insert(index_digest.to_string(), platform_digest.to_string());
+        drop(repositories);
+        self
+    }
+
+    /// Gives the manifest of `digest` in `image`'s repository a single
+    /// layer of `bytes`; manifests have no layers otherwise.
+    pub fn layer_size(
+        &self,
+        image: &str,
+        digest: &str,
+        bytes: u64,
+    ) -> &Self {
+        let reference: Reference = image
+            .parse()
+            .expect("fake registry image reference");
+        self.repositories
+            .lock()
+            .unwrap()
+            .entry(repository_key(&reference))
+            .or_default()
+            .sizes
+            .insert(digest.to_string(), bytes);
+        self
+    }
+
+    /// Gives the config of `digest` in `image`'s repository `source` as
+    /// its `org.opencontainers.image.source` label.
+    pub fn source(
+        &self,
+        image: &str,
+        digest: &str,
+        source: &str,
+    ) -> &Self {
+        let reference: Reference = image
+            .parse()
+            .expect("fake registry image reference");
+        self.repositories
+            .lock()
+            .unwrap()
+            .entry(repository_key(&reference))
+            .or_default()
+            .sources
+            .insert(digest.to_string(), source.to_string());
+        self
+    }
+
+    fn manifest(
+        &self,
+        image: &Reference,
+        digest: &str,
+    ) -> OciImageManifest {
+        let repositories = self.repositories.lock().unwrap();
+        let size = repositories
+            .get(&repository_key(image))
+            .and_then(|repository| repository.sizes.get(digest));
+
+        OciImageManifest {
+            layers: size
+                .map(|size| {
+                    vec![OciDescriptor {
+                        size: *size as i64,
+                        ..Default::default()
+                    }]
+                })
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// The platform manifest `digest` lists, if it is an index.
+    fn index_entry(
+        &self,
+        image: &Reference,
+        digest: &str,
+    ) -> Option<String> {
+        self.repositories
+            .lock()
+            .unwrap()
+            .get(&repository_key(image))?
+            .indexes
+            .get(digest)
+            .cloned()
+    }
+
+    fn resolve(
+        &self,
+        image: &Reference,
+    ) -> anyhow::Result<(String, Option<String>, Option<String>)> {
+        let repositories = self.repositories.lock().unwrap();
+        let repository = repositories
+            .get(&repository_key(image))
+            .ok_or_else(|| not_found(OciErrorCode::NameUnknown, image))?;
+
+        let digest = match (image.digest(), image.tag()) {
+            (Some(digest), _) => digest.to_string(),
+            (None, Some(tag)) => repository
+                .tags
+                .get(tag)
+                .cloned()
+                .ok_or_else(|| {
+                    not_found(OciErrorCode::ManifestUnknown, image)
+                })?,
+            (None, None) => {
+                return Err(not_found(OciErrorCode::ManifestUnknown, image));
+            }
+        };
+        let version = repository
+            .versions
+            .get(&digest)
+            .cloned()
+            .ok_or_else(|| not_found(OciErrorCode::ManifestUnknown, image))?;
+        let source = repository.sources.get(&digest).cloned();
+
+        Ok((digest, version, source))
+    }
+}
+
+/// The error a registry answers with for an unknown repository or tag.
+fn not_found(
+    code: OciErrorCode,
+    image: &Reference,
+) -> anyhow::Error {
+    OciDistributionError::RegistryError {
+        envelope: OciEnvelope {
+            errors: vec![OciError {
+                code,
+                message: format!("{image} unknown"),
+                detail: serde_json::Value::Null,
+            }],
+        },
+        url: format!("https://{}/v2/", image.resolve_registry()),
+    }
+    .into()
+}
+
+fn repository_key(image: &Reference) -> String {
+    format!("{}/{}", image.resolve_registry(), image.repository())
+}
+
+impl RegistryBackend for FakeRegistry {
+    async fn pull_manifest_and_config(
+        &self,
+        image: &Reference,
+        _auth: &RegistryAuth,
+    ) -> anyhow::Result<(OciImageManifest, ImageDigests, String)> {
+        let (digest, version, source) = self.resolve(image)?;
+        let digests = match self.index_entry(image, &digest) {
+            Some(platform) => ImageDigests {
+                index: Some(digest),
+                platform,
+            },
+            None => ImageDigests::platform(digest),
+        };
+        let labels = [
+            ("org.opencontainers.image.version", version),
+            ("org.opencontainers.image.source", source),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label.to_string(), value?)))
+        .collect::<HashMap<_, _>>();
+        let config = ConfigFile {
+            config: Some(Config {
+                labels: (!labels.is_empty()).then_some(labels),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        Ok((
+            self.manifest(image, &digests.platform),
+            digests,
+            serde_json::to_string(&config)?,
+        ))
+    }
+
+    async fn list_tags(
+        &self,
+        image: &Reference,
+        _auth: &RegistryAuth,
+        n: Option<usize>,
+        last: Option<&str>,
+    ) -> anyhow::Result<TagResponse> {
+        let repositories = self.repositories.lock().unwrap();
+        let tags = repositories
+            .get(&repository_key(image))
+            .map(|repository| {
+                repository
+                    .tags
+                    .keys()
+                    .filter(|tag| last.is_none_or(|last| tag.as_str() > last))
+                    .take(n.unwrap_or(usize::MAX))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(TagResponse {
+            name: image.repository().to_string(),
+            tags,
+        })
+    }
+
+    async fn pull_manifest(
+        &self,
+        image: &Reference,
+        _auth: &RegistryAuth,
+    ) -> anyhow::Result<(OciManifest, String)> {
+        let (digest, ..) = self.resolve(image)?;
+        let manifest = match self.index_entry(image, &digest) {
+            Some(platform) => OciManifest::ImageIndex(OciImageIndex {
+                schema_version: 2,
+                media_type: None,
+                manifests: vec![ImageIndexEntry {
+                    media_type: String::new(),
+                    digest: platform,
+                    size: 0,
+                    platform: Some(Platform {
+                        architecture: Architecture::default(),
+                        os: Os::default(),
+                        os_version: None,
+                        os_features: None,
+                        variant: None,
+                        features: None,
+                    }),
+                    artifact_type: None,
+                    annotations: None,
+                }],
+                artifact_type: None,
+                annotations: None,
+            }),
+            None => OciManifest::Image(self.manifest(image, &digest)),
+        };
+        Ok((manifest, digest))
+    }
+
+    /// Walks the tags like a plain registry, for Docker Hub images too.
+    async fn version_from_tags(
+        &self,
+        image: &Reference,
+        digests: &ImageDigests,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<TagVersion> {
+        get_version_from_oci_tags(self, image, digests, auth)
+            .await
+            .map(TagVersion::complete)
+    }
+}
@@ -0,0 +1,380 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use reqwest::{Certificate, NoProxy, Proxy};
+
+use crate::{
+    client::NirionOciClientConfig,
+    docker_hub::DockerHubError,
+    oci::resolve_registry,
+    oci_client::{
+        client::{
+            Certificate as OciCertificate, CertificateEncoding, ClientProtocol,
+        },
+        errors::{OciDistributionError, OciErrorCode},
+    },
+};
+
+/// Network settings shared by every HTTP client nirion builds, so the
+/// registry clients and the Docker Hub API client agree on proxies and
+/// trusted certificates.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpConfig {
+    pub https_proxy: Option<String>,
+    pub http_proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    /// PEM encoded certificates trusted in addition to the system roots.
+    pub extra_root_certificates: Vec<Vec<u8>>,
+    /// Registries (`host[:port]`) spoken to over plain HTTP.
+    pub insecure_registries: Vec<String>,
+}
+
+impl HttpConfig {
+    /// Proxies from `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`, or their
+    /// lowercase variants.
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let set = |name: &str| var(name).filter(|value| !value.is_empty());
+        let lookup =
+            |name: &str| set(name).or_else(|| set(&name.to_ascii_lowercase()));
+
+        Self {
+            https_proxy: lookup("HTTPS_PROXY"),
+            http_proxy: lookup("HTTP_PROXY"),
+            no_proxy: lookup("NO_PROXY"),
+            ..Default::default()
+        }
+    }
+
+    /// Trusts the PEM certificate(s) in `path` on top of the system roots.
+    pub fn add_root_certificate_file(
+        &mut self,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        let pem = fs::read(path).with_context(|| {
+            format!("failed to read registry CA {}", path.display())
+        })?;
+        let certificates = Certificate::from_pem_bundle(&pem).ok();
+        if certificates.is_none_or(|certificates| certificates.is_empty()) {
+            anyhow::bail!(
+                "{} is not a PEM encoded certificate",
+                path.display()
+            );
+        }
+        self.extra_root_certificates.push(pem);
+        Ok(())
+    }
+
+    pub fn add_insecure_registry(
+        &mut self,
+        registry: impl Into<String>,
+    ) {
+        self.insecure_registries
+            .push(resolve_registry(registry.into()));
+    }
+
+    /// A client for plain HTTPS APIs such as Docker Hub's.
+    pub fn reqwest_client(&self) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder();
+
+        let no_proxy = || {
+            self.no_proxy
+                .as_deref()
+                .and_then(NoProxy::from_string)
+        };
+        if let Some(proxy) = &self.https_proxy {
+            builder = builder.proxy(Proxy::https(proxy)?.no_proxy(no_proxy()));
+        }
+        if let Some(proxy) = &self.http_proxy {
+            builder = builder.proxy(Proxy::http(proxy)?.no_proxy(no_proxy()));
+        }
+
+        for pem in &self.extra_root_certificates {
+            builder =
+                builder.tls_certs_merge(Certificate::from_pem_bundle(pem)?);
+        }
+
+        builder
+            .build()
+            .context("failed to build HTTP client")
+    }
+
+    /// Copies these settings into the config used for registry clients.
+    pub fn apply(
+        &self,
+        config: &mut NirionOciClientConfig,
+    ) {
+        config.https_proxy = self.https_proxy.clone();
+        config.http_proxy = self.http_proxy.clone();
+        config.no_proxy = self.no_proxy.clone();
+        config.extra_root_certificates.extend(
+            self.extra_root_certificates
+                .iter()
+                .map(|pem| OciCertificate {
+                    encoding: CertificateEncoding::Pem,
+                    data: pem.clone(),
+                }),
+        );
+        config.protocol = with_plain_http(
+            config.protocol.clone(),
+            self.insecure_registries.iter().cloned(),
+        );
+    }
+}
+
+/// Adds `registries` to the plain-HTTP exceptions of `protocol`.
+pub(crate) fn with_plain_http(
+    protocol: ClientProtocol,
+    registries: impl IntoIterator<Item = String>,
+) -> ClientProtocol {
+    let mut registries = registries.into_iter().peekable();
+    if registries.peek().is_none() {
+        return protocol;
+    }
+
+    match protocol {
+        ClientProtocol::Http => ClientProtocol::Http,
+        ClientProtocol::Https => {
+            ClientProtocol::HttpsExcept(registries.collect())
+        }
+        ClientProtocol::HttpsExcept(mut exceptions) => {
+            for registry in registries {
+                if !exceptions.contains(&registry) {
+                    exceptions.push(registry);
+                }
+            }
+            ClientProtocol::HttpsExcept(exceptions)
+        }
+    }
+}
+
+/// Rewrites errors caused by certificate verification so they name the
+/// registry and how to trust it; other errors are returned unchanged.
+pub fn explain_tls_error(
+    error: anyhow::Error,
+    host: &str,
+) -> anyhow::Error {
+    let is_tls = error.chain().any(|cause| {
+        let message = cause.to_string().to_ascii_lowercase();
+        message.contains("certificate") || message.contains("unknownissuer")
+    });
+    if !is_tls {
+        return error;
+    }
+
+    error.context(format!(
+        "TLS verification failed for {host}; if it uses a private \
+         certificate authority, pass it with --registry-ca <pem>"
+    ))
+}
+
+/// The HTTP status a registry answered with, read from the typed errors
+/// in `error`'s chain. OCI error codes count as the status the
+/// distribution spec pairs them with.
+pub fn registry_status(error: &anyhow::Error) -> Option<u16> {
+    error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<OciDistributionError>() {
+            oci_status(error)
+        } else if let Some(error) = cause.downcast_ref::<DockerHubError>() {
+            match error {
+                DockerHubError::Http(error) => error
+                    .status()
+                    .map(|status| status.as_u16()),
+                DockerHubError::UnexpectedStatus(status) => {
+                    Some(status.as_u16())
+                }
+                DockerHubError::TagNotFound | DockerHubError::ImageNotFound => {
+                    Some(404)
+                }
+                _ => None,
+            }
+        } else {
+            cause
+                .downcast_ref::<reqwest::Error>()?
+                .status()
+                .map(|status| status.as_u16())
+        }
+    })
+}
+
+fn oci_status(error: &OciDistributionError) -> Option<u16> {
+    match error {
+        OciDistributionError::ServerError { code, .. } => Some(*code),
+        OciDistributionError::AuthenticationFailure(_)
+        | OciDistributionError::UnauthorizedError { .. } => Some(401),
+        OciDistributionError::ImageManifestNotFoundError(_) => Some(404),
+        OciDistributionError::RegistryError { envelope, .. } => envelope
+            .errors
+            .iter()
+            .find_map(|error| match error.code {
+                OciErrorCode::Toomanyrequests => Some(429),
+                OciErrorCode::Unauthorized => Some(401),
+                OciErrorCode::Denied => Some(403),
+                OciErrorCode::ManifestUnknown
+                | OciErrorCode::NameUnknown
+                | OciErrorCode::NotFound => Some(404),
+                _ => None,
+            }),
+        OciDistributionError::RequestError(error) => error
+            .status()
+            .map(|status| status.as_u16()),
+        _ => None,
+    }
+}
+
+/// Whether a request in `error`'s chain timed out.
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let request = match cause.downcast_ref::<OciDistributionError>() {
+            Some(OciDistributionError::RequestError(error)) => Some(error),
+            _ => match cause.downcast_ref::<DockerHubError>() {
+                Some(DockerHubError::Http(error)) => Some(error),
+                _ => cause.downcast_ref::<reqwest::Error>(),
+            },
+        };
+        request.is_some_and(reqwest::Error::is_timeout)
+            || cause
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|error| {
+                    error.kind() == std::io::ErrorKind::TimedOut
+                })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_vars_prefers_uppercase_and_skips_empty() {
+        let config = HttpConfig::from_vars(|name| match name {
+            "HTTPS_PROXY" => Some("http://proxy:3128".to_string()),
+            "https_proxy" => Some("http://ignored:1".to_string()),
+            "HTTP_PROXY" => Some(String::new()),
+            "http_proxy" => Some("http://lower:8080".to_string()),
+            "no_proxy" => Some("localhost,.internal".to_string()),
+            _ => None,
+        });
+
+        assert_eq!(config.https_proxy.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(config.http_proxy.as_deref(), Some("http://lower:8080"));
+        assert_eq!(config.no_proxy.as_deref(), Some("localhost,.internal"));
+    }
+
+    #[test]
+    fn apply_sets_proxies_certificates_and_protocol() {
+        let mut http = HttpConfig {
+            https_proxy: Some("http://proxy:3128".to_string()),
+            extra_root_certificates: vec![b"pem".to_vec()],
+            ..Default::default()
+        };
+        http.add_insecure_registry("http://localhost:5000/");
+
+        let mut config = NirionOciClientConfig::default();
+        http.apply(&mut config);
+
+        assert_eq!(config.https_proxy.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(config.extra_root_certificates.len(), 1);
+        assert_eq!(
+            config.protocol,
+            ClientProtocol::HttpsExcept(vec!["localhost:5000".to_string()])
+        );
+    }
+
+    #[test]
+    fn plain_http_registries_merge_into_existing_exceptions() {
+        let protocol = with_plain_http(
+            ClientProtocol::HttpsExcept(vec!["a:5000".to_string()]),
+            ["b:5000".to_string(), "a:5000".to_string()],
+        );
+        assert_eq!(
+            protocol,
+            ClientProtocol::HttpsExcept(vec![
+                "a:5000".to_string(),
+                "b:5000".to_string()
+            ])
+        );
+        assert_eq!(
+            with_plain_http(ClientProtocol::Https, []),
+            ClientProtocol::Https
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_certificates() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ca.pem");
+        fs::write(&path, "not a certificate").unwrap();
+
+        let error = HttpConfig::default()
+            .add_root_certificate_file(&path)
+            .unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("not a PEM encoded certificate")
+        );
+    }
+
+    #[test]
+    fn explains_certificate_errors_only() {
+        let tls = anyhow::anyhow!("invalid peer certificate: UnknownIssuer")
+            .context("error sending request");
+        let explained = explain_tls_error(tls, "registry.internal");
+        assert!(
+            explained
+                .to_string()
+                .contains("registry.internal")
+        );
+        assert!(
+            explained
+                .to_string()
+                .contains("--registry-ca")
+        );
+
+        let other = explain_tls_error(anyhow::anyhow!("not found"), "x");
+        assert_eq!(other.to_string(), "not found");
+    }
+
+    #[test]
+    fn reads_the_status_from_typed_errors_only() {
+        let envelope = |code: &str| {
+            let envelope = serde_json::from_str(&format!(
+                r#"{{"errors":[{{"code":"{code}","message":"m"}}]}}"#
+            ))
+            .unwrap();
+            anyhow::Error::new(OciDistributionError::RegistryError {
+                envelope,
+                url: "https://ghcr.io/v2/".to_string(),
+            })
+            .context("resolving ghcr.io/acme/api:1")
+        };
+
+        assert_eq!(registry_status(&envelope("TOOMANYREQUESTS")), Some(429));
+        assert_eq!(registry_status(&envelope("DENIED")), Some(403));
+        assert_eq!(registry_status(&envelope("NAME_UNKNOWN")), Some(404));
+        assert_eq!(registry_status(&envelope("BLOB_UNKNOWN")), None);
+        assert_eq!(
+            registry_status(&anyhow::Error::new(
+                OciDistributionError::ServerError {
+                    code: 502,
+                    url: "https://registry.lan/v2/".to_string(),
+                    message: "bad gateway".to_string(),
+                }
+            )),
+            Some(502)
+        );
+        assert_eq!(
+            registry_status(&anyhow::Error::new(DockerHubError::TagNotFound)),
+            Some(404)
+        );
+        assert_eq!(
+            registry_status(&anyhow::anyhow!("app:1.404 has no tag 429")),
+            None
+        );
+    }
+}
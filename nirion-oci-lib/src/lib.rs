@@ -1,9 +1,16 @@
 pub use oci_client;
 
 pub mod auth;
+pub mod check;
 pub mod client;
 pub mod docker_hub;
+#[cfg(feature = "test-util")]
+pub mod fake_registry;
+pub mod http;
+#[cfg(feature = "test-util")]
+pub mod mock_registry;
 pub mod oci;
+pub mod registry;
 #[cfg(feature = "test-registry")]
 pub mod test_registry;
 pub mod version;
@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use oci_client::{
+    config::{Architecture, Config, ConfigFile, Os},
+    manifest::{
+        IMAGE_CONFIG_MEDIA_TYPE, IMAGE_LAYER_GZIP_MEDIA_TYPE,
+        OCI_IMAGE_MEDIA_TYPE, OciDescriptor, OciImageManifest,
+    },
+};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Default)]
+struct Repository {
+    tags: BTreeMap<String, String>,
+    /// Manifests and config blobs by digest.
+    blobs: HashMap<String, Vec<u8>>,
+}
+
+/// A registry served over plain HTTP on localhost, for testing code that
+/// resolves images through the real registry clients. Those have to be
+/// told to speak plain HTTP to [`MockRegistry::host`]. Clones share
+/// their contents, so a test can push new images while it runs.
+#[derive(Debug, Clone)]
+pub struct MockRegistry {
+    host: String,
+    repositories: Arc<Mutex<HashMap<String, Repository>>>,
+}
+
+impl MockRegistry {
+    /// Serves an empty registry on an unused local port until the test
+    /// process exits.
+    pub fn start() -> Self {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").expect("mock registry listener");
+        let registry = Self {
+            host: listener
+                .local_addr()
+                .expect("mock registry address")
+                .to_string(),
+            repositories: Arc::default(),
+        };
+
+        let server = registry.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                thread::spawn(move || server.serve(stream));
+            }
+        });
+        registry
+    }
+
+    /// `host:port`, to prefix repositories with.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Points `repository:tag` at a new single-platform image whose
+    /// config carries `labels`, and returns its manifest digest. The one
+    /// layer it lists is `layer_size` bytes.
+    pub fn push(
+        &self,
+        repository: &str,
+        tag: &str,
+        labels: &[(&str, &str)],
+        layer_size: u64,
+    ) -> String {
+        let mut repositories = self.repositories.lock().unwrap();
+        let repository = repositories
+            .entry(repository.to_string())
+            .or_default();
+
+        let config = ConfigFile {
+            architecture: Architecture::Amd64,
+            os: Os::Linux,
+            config: Some(Config {
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(label, value)| {
+                            (label.to_string(), value.to_string())
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = serde_json::to_vec(&config).expect("mock image config");
+        let config_digest = sha256(&config);
+
+        // Each push gets its own layer, so pushing the same labels again
+        // still makes a new image.
+        let layer = format!("{tag} {}", repository.blobs.len());
+        let manifest = OciImageManifest {
+            media_type: Some(OCI_IMAGE_MEDIA_TYPE.to_string()),
+            config: OciDescriptor {
+                media_type: IMAGE_CONFIG_MEDIA_TYPE.to_string(),
+                digest: config_digest.clone(),
+                size: config.len() as i64,
+                ..Default::default()
+            },
+            layers: vec![OciDescriptor {
+                media_type: IMAGE_LAYER_GZIP_MEDIA_TYPE.to_string(),
+                digest: sha256(layer.as_bytes()),
+                size: layer_size as i64,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let manifest =
+            serde_json::to_vec(&manifest).expect("mock image manifest");
+        let digest = sha256(&manifest);
+
+        repository
+            .blobs
+            .insert(config_digest, config);
+        repository
+            .blobs
+            .insert(digest.clone(), manifest);
+        repository
+            .tags
+            .insert(tag.to_string(), digest.clone());
+        digest
+    }
+
+    fn serve(
+        &self,
+        mut stream: TcpStream,
+    ) {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        if reader
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            return;
+        }
+        // The rest of the request is headers; read them, lest closing
+        // with unread bytes resets the connection before the client
+        // sees the response.
+        let mut header = String::new();
+        while reader
+            .read_line(&mut header)
+            .is_ok_and(|read| read > 2)
+        {
+            header.clear();
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (method, path) = (parts.next().unwrap_or(""), parts.next());
+        let path = path
+            .unwrap_or("")
+            .split('?')
+            .next()
+            .unwrap_or("");
+        let response = self.respond(path);
+
+        let mut head = format!(
+            "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n",
+            response.status,
+            response.body.len()
+        );
+        for (name, value) in response.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        let _ = stream.write_all(head.as_bytes());
+        if method != "HEAD" {
+            let _ = stream.write_all(&response.body);
+        }
+    }
+
+    fn respond(
+        &self,
+        path: &str,
+    ) -> Response {
+        if path == "/v2/" {
+            return Response::json("200 OK", b"{}".to_vec());
+        }
+        let Some(path) = path.strip_prefix("/v2/") else {
+            return Response::error("404 Not Found", "NOT_FOUND");
+        };
+
+        let repositories = self.repositories.lock().unwrap();
+        if let Some(name) = path.strip_suffix("/tags/list") {
+            let Some(repository) = repositories.get(name) else {
+                return Response::error("404 Not Found", "NAME_UNKNOWN");
+            };
+            let tags = serde_json::json!({
+                "name": name,
+                "tags": repository.tags.keys().collect::<Vec<_>>(),
+            });
+            return Response::json("200 OK", tags.to_string().into_bytes());
+        }
+
+        let (name, kind, reference) = match path
+            .rsplit_once("/manifests/")
+            .map(|(name, reference)| (name, "manifests", reference))
+            .or_else(|| {
+                path.rsplit_once("/blobs/")
+                    .map(|(name, reference)| (name, "blobs", reference))
+            }) {
+            Some(parts) => parts,
+            None => return Response::error("404 Not Found", "NOT_FOUND"),
+        };
+        let Some(repository) = repositories.get(name) else {
+            return Response::error("404 Not Found", "NAME_UNKNOWN");
+        };
+        let digest = repository
+            .tags
+            .get(reference)
+            .map(String::as_str)
+            .unwrap_or(reference);
+        let Some(body) = repository.blobs.get(digest) else {
+            return Response::error("404 Not Found", "MANIFEST_UNKNOWN");
+        };
+
+        let media_type = match kind {
+            "manifests" => OCI_IMAGE_MEDIA_TYPE,
+            _ => "application/octet-stream",
+        };
+        Response {
+            status: "200 OK",
+            headers: vec![
+                ("content-type", media_type.to_string()),
+                ("docker-content-digest", digest.to_string()),
+            ],
+            body: body.clone(),
+        }
+    }
+}
+
+struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(
+        status: &'static str,
+        body: Vec<u8>,
+    ) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type", "application/json".to_string())],
+            body,
+        }
+    }
+
+    /// An OCI error envelope with `code`.
+    fn error(
+        status: &'static str,
+        code: &str,
+    ) -> Self {
+        let body = serde_json::json!({
+            "errors": [{"code": code, "message": code.to_lowercase()}],
+        });
+        Self::json(status, body.to_string().into_bytes())
+    }
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    let hash = Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256:{hash}")
+}
@@ -1,31 +1,69 @@
+use std::collections::HashMap;
+
 use oci_client::{
-    Client, Reference,
+    Reference,
     config::{Architecture, ConfigFile},
     manifest::OciManifest,
     secrets::RegistryAuth,
 };
 
-use crate::version::{canonical_version_score, clean_tag, is_non_version_tag};
+use crate::{
+    registry::{ImageDigests, RegistryBackend},
+    version::{canonical_version_score, clean_tag, is_non_version_tag},
+};
 
+/// Normalizes a registry host as written in config files, e.g.
+/// `https://registry.lan:443/` or `docker.io`, to the name image
+/// references resolve to. Non-default ports are kept.
 pub fn resolve_registry(registry: String) -> String {
-    Reference::with_tag(registry, "dummy".to_string(), "dummy".to_string())
-        .resolve_registry()
-        .to_string()
+    let registry = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+    let registry = registry
+        .strip_suffix(":443")
+        .unwrap_or(registry);
+
+    Reference::with_tag(
+        registry.to_string(),
+        "dummy".to_string(),
+        "dummy".to_string(),
+    )
+    .resolve_registry()
+    .to_string()
 }
 
 pub fn get_version_from_config(config: &ConfigFile) -> Option<String> {
     let config = config.config.as_ref()?;
-    let labels = config.labels.as_ref()?;
+    get_version_from_labels(config.labels.as_ref()?)
+}
+
+/// The version in an image's `org.opencontainers.image.version` label,
+/// unless it names a tag like `latest` rather than a version.
+pub fn get_version_from_labels(
+    labels: &HashMap<String, String>
+) -> Option<String> {
     labels
         .get("org.opencontainers.image.version")
         .filter(|version| !is_non_version_tag(version))
         .map(|t| clean_tag(t).to_string())
 }
 
+/// The `org.opencontainers.image.source` label of an image's config.
+pub fn get_source_from_config(config: &ConfigFile) -> Option<String> {
+    config
+        .config
+        .as_ref()?
+        .labels
+        .as_ref()?
+        .get("org.opencontainers.image.source")
+        .cloned()
+}
+
 pub async fn get_alias_oci_tags(
-    client: &Client,
+    client: &impl RegistryBackend,
     image: &Reference,
-    digest: &str,
+    digests: &ImageDigests,
     auth: &RegistryAuth,
 ) -> anyhow::Result<Vec<String>> {
     let tags = list_all_tags(client, image, auth).await?;
@@ -43,8 +81,8 @@ pub async fn get_alias_oci_tags(
         .peekable();
 
     while let Some(image) = tag_refs.peek() {
-        let tag_digest = pull_platform_digest(client, image, auth).await?;
-        if tag_digest == digest {
+        let tag_digests = pull_digests(client, image, auth).await?;
+        if tag_digests.matches(digests) {
             break;
         } else {
             tag_refs.next();
@@ -54,8 +92,8 @@ pub async fn get_alias_oci_tags(
     let mut candidates = Vec::new();
 
     while let Some(image) = tag_refs.peek() {
-        let tag_digest = pull_platform_digest(client, image, auth).await?;
-        if tag_digest != digest {
+        let tag_digests = pull_digests(client, image, auth).await?;
+        if !tag_digests.matches(digests) {
             break;
         } else {
             if let Some(tag) = image.tag() {
@@ -69,7 +107,7 @@ pub async fn get_alias_oci_tags(
 }
 
 pub async fn list_all_tags(
-    client: &Client,
+    client: &impl RegistryBackend,
     image: &Reference,
     auth: &RegistryAuth,
 ) -> anyhow::Result<Vec<String>> {
@@ -96,15 +134,31 @@ pub async fn list_all_tags(
 }
 
 pub async fn pull_platform_digest(
-    client: &Client,
+    client: &impl RegistryBackend,
     image: &Reference,
     auth: &RegistryAuth,
 ) -> anyhow::Result<String> {
+    Ok(pull_digests(client, image, auth)
+        .await?
+        .platform)
+}
+
+/// The digests `image` points at: the platform manifest and, for a
+/// multi-platform image, the index it was picked from.
+pub async fn pull_digests(
+    client: &impl RegistryBackend,
+    image: &Reference,
+    auth: &RegistryAuth,
+) -> anyhow::Result<ImageDigests> {
     let (manifest, digest) = client
         .pull_manifest(image, auth)
         .await?;
+    let platform = get_digest_from_manifest(&digest, &manifest)?;
 
-    get_digest_from_manifest(&digest, &manifest)
+    Ok(ImageDigests {
+        index: (platform != digest).then_some(digest),
+        platform,
+    })
 }
 
 pub fn get_digest_from_manifest(
@@ -133,9 +187,9 @@ pub fn get_digest_from_manifest(
 }
 
 pub async fn get_version_from_oci_tags(
-    client: &Client,
+    client: &impl RegistryBackend,
     image: &Reference,
-    digest: &str,
+    digests: &ImageDigests,
     auth: &RegistryAuth,
 ) -> anyhow::Result<Option<String>> {
     let tags = list_all_tags(client, image, auth).await?;
@@ -157,9 +211,8 @@ pub async fn get_version_from_oci_tags(
             tag.clone(),
         );
 
-        let tag_digest =
-            pull_platform_digest(client, &tag_reference, auth).await?;
-        if tag_digest == digest {
+        let tag_digests = pull_digests(client, &tag_reference, auth).await?;
+        if tag_digests.matches(digests) {
             let clean_tag = clean_tag(&tag).to_string();
             return Ok(Some(clean_tag));
         }
@@ -207,6 +260,22 @@ mod tests {
         assert_eq!(resolve_registry("ghcr.io".to_string()), "ghcr.io");
     }
 
+    #[test]
+    fn resolve_registry_keeps_ports_and_drops_scheme() {
+        assert_eq!(
+            resolve_registry("registry.lan:5000".to_string()),
+            "registry.lan:5000"
+        );
+        assert_eq!(
+            resolve_registry("http://registry.lan:5000/".to_string()),
+            "registry.lan:5000"
+        );
+        assert_eq!(
+            resolve_registry("https://docker.io:443".to_string()),
+            "index.docker.io"
+        );
+    }
+
     #[test]
     fn get_version_from_config_extracts_and_cleans_label() {
         let config =
@@ -0,0 +1,157 @@
+use std::future::Future;
+
+use oci_client::{
+    Client, Reference,
+    client::TagResponse,
+    manifest::{OciImageManifest, OciManifest},
+    secrets::RegistryAuth,
+};
+
+use crate::oci::get_version_from_oci_tags;
+
+/// The digests a tag resolved to. Locks record [`Self::locked`]; tags
+/// are aliases of each other when any of their digests agree, since some
+/// sources (Docker Hub's per-image entries, single-platform tags) only
+/// know the platform manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageDigests {
+    /// The image index (manifest list) digest, if the tag points at one.
+    pub index: Option<String>,
+    /// The manifest picked for this host's platform.
+    pub platform: String,
+}
+
+impl ImageDigests {
+    /// Digests of a single-platform image.
+    pub fn platform(digest: impl Into<String>) -> Self {
+        Self {
+            index: None,
+            platform: digest.into(),
+        }
+    }
+
+    /// The index digest if there is one: `image@digest` then keeps
+    /// resolving to the right platform on every host.
+    pub fn locked(&self) -> &str {
+        self.index
+            .as_deref()
+            .unwrap_or(&self.platform)
+    }
+
+    pub fn contains(
+        &self,
+        digest: &str,
+    ) -> bool {
+        self.platform == digest || self.index.as_deref() == Some(digest)
+    }
+
+    pub fn matches(
+        &self,
+        other: &ImageDigests,
+    ) -> bool {
+        other.contains(&self.platform)
+            || self
+                .index
+                .as_deref()
+                .is_some_and(|index| other.contains(index))
+    }
+}
+
+/// The version [`RegistryBackend::version_from_tags`] found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagVersion {
+    pub version: Option<String>,
+    /// How many tags were read, if the search hit its limit before it
+    /// was done. `version` may then be missing or not the best match.
+    pub capped_after: Option<usize>,
+}
+
+impl TagVersion {
+    /// The outcome of a search that read every tag it needed.
+    pub fn complete(version: Option<String>) -> Self {
+        Self {
+            version,
+            capped_after: None,
+        }
+    }
+}
+
+/// The registry operations version resolution needs. Implemented by
+/// [`oci_client::Client`]; tests can swap in an in-memory registry.
+pub trait RegistryBackend: Send + Sync {
+    /// Returns the platform manifest, the digests it was reached
+    /// through and the raw image config.
+    fn pull_manifest_and_config(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+    ) -> impl Future<
+        Output = anyhow::Result<(OciImageManifest, ImageDigests, String)>,
+    > + Send;
+
+    fn list_tags(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+        n: Option<usize>,
+        last: Option<&str>,
+    ) -> impl Future<Output = anyhow::Result<TagResponse>> + Send;
+
+    /// Returns the manifest (which may be an index) and its digest.
+    fn pull_manifest(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+    ) -> impl Future<Output = anyhow::Result<(OciManifest, String)>> + Send;
+
+    /// The version of an image without a version label, from the tags
+    /// that point at `digests`.
+    fn version_from_tags(
+        &self,
+        image: &Reference,
+        digests: &ImageDigests,
+        auth: &RegistryAuth,
+    ) -> impl Future<Output = anyhow::Result<TagVersion>> + Send;
+}
+
+impl RegistryBackend for Client {
+    async fn pull_manifest_and_config(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<(OciImageManifest, ImageDigests, String)> {
+        let (manifest, platform, config, index) =
+            Client::pull_manifest_and_config_and_list_digest(self, image, auth)
+                .await?;
+        Ok((manifest, ImageDigests { index, platform }, config))
+    }
+
+    async fn list_tags(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+        n: Option<usize>,
+        last: Option<&str>,
+    ) -> anyhow::Result<TagResponse> {
+        Ok(Client::list_tags(self, image, auth, n, last).await?)
+    }
+
+    async fn pull_manifest(
+        &self,
+        image: &Reference,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<(OciManifest, String)> {
+        Ok(Client::pull_manifest(self, image, auth).await?)
+    }
+
+    async fn version_from_tags(
+        &self,
+        image: &Reference,
+        digests: &ImageDigests,
+        auth: &RegistryAuth,
+    ) -> anyhow::Result<TagVersion> {
+        get_version_from_oci_tags(self, image, digests, auth)
+            .await
+            .map(TagVersion::complete)
+    }
+}
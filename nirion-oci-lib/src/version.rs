@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 use semver::Version as SemverVersion;
 use serde::{Deserialize, Serialize};
 
@@ -89,6 +91,14 @@ pub struct VersionedImage {
     pub image: String,
     pub version: Option<String>,
     pub digest: String,
+    /// Compressed size of the image's layers for the resolved platform,
+    /// i.e. roughly what pulling it downloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Where the image was built from, from its
+    /// `org.opencontainers.image.source` label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 fn version_prefix(tag: &str) -> &str {
@@ -156,6 +166,42 @@ pub fn is_non_version_tag(tag: &str) -> bool {
     NON_VERSION_TAGS.contains(&clean_tag(tag))
 }
 
+/// Orders two version tags, e.g. `v1.10` after `1.9.2`: as semver when
+/// both parse as one, otherwise by the numeric components they both
+/// have, so `16` and `16.4` compare equal. `None` if either isn't a
+/// version or their suffixes differ, like `1.2-alpine` and `1.3`.
+pub fn compare_versions(
+    a: &str,
+    b: &str,
+) -> Option<Ordering> {
+    let (a, b) = (clean_tag(a), clean_tag(b));
+    if is_non_version_tag(a) || is_non_version_tag(b) || suffix(a) != suffix(b)
+    {
+        return None;
+    }
+
+    let semver =
+        |tag| SemverVersion::parse(normalized_version_prefix(tag)).ok();
+    if let (Some(a), Some(b)) = (semver(a), semver(b)) {
+        return Some(a.cmp(&b));
+    }
+
+    let components = |tag| {
+        normalized_version_prefix(tag)
+            .split('.')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let (a, b) = (components(a)?, components(b)?);
+    Some(
+        a.iter()
+            .zip(&b)
+            .map(|(a, b)| a.cmp(b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -224,4 +270,19 @@ mod tests {
 
         assert_eq!(canonical_version_tag(&tags), None);
     }
+
+    #[test]
+    fn compare_versions_orders_numerically_and_rejects_mismatches() {
+        assert_eq!(compare_versions("1.9.2", "v1.10.0"), Some(Ordering::Less));
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("16.4", "15"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("16", "16.4"), Some(Ordering::Equal));
+        assert_eq!(
+            compare_versions("1.3-alpine", "1.2-alpine"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(compare_versions("1.3-alpine", "1.2"), None);
+        assert_eq!(compare_versions("latest", "1.2"), None);
+        assert_eq!(compare_versions("2024-01", "1.2"), None);
+    }
 }
@@ -2,6 +2,7 @@ use std::io::ErrorKind;
 
 use nirion_oci_lib::{
     auth::RegistryAuth as NirionRegistryAuth,
+    check::{Access, CredentialChecker, RateLimit},
     client::AuthConfig,
     docker_hub::{DockerHubClient, DockerHubError},
     oci::get_alias_oci_tags,
@@ -10,6 +11,7 @@ use nirion_oci_lib::{
         client::{ClientConfig, ClientProtocol},
         secrets::RegistryAuth,
     },
+    registry::ImageDigests,
     test_registry::{
         ACCOUNT_A, ACCOUNT_B, RegistryHandle, http_nirion_client,
         push_anonymous_test_image,
@@ -37,7 +39,8 @@ async fn resolves_local_registry_image_with_mocked_docker_hub_metadata()
 
     let resolved = client
         .get_versioned_image(&test_image.reference)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -57,7 +60,8 @@ async fn resolves_local_registry_image_with_generic_oci_tags()
 
     let resolved = client
         .get_versioned_image(&test_image.reference)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -76,11 +80,14 @@ async fn updated_image_preserves_version_when_digest_is_unchanged()
         image: test_image.reference.to_string(),
         version: Some("1.2.3".to_string()),
         digest: test_image.digest.clone(),
+        size: None,
+        source: None,
     };
 
     let resolved = client
         .get_updated_versioned_image(&current)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved, current);
 
@@ -97,11 +104,14 @@ async fn updated_image_resolves_version_when_digest_changes()
         image: test_image.reference.to_string(),
         version: Some("1.0.0".to_string()),
         digest: "sha256:0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+        size: None,
+        source: None,
     };
 
     let resolved = client
         .get_updated_versioned_image(&current)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -134,7 +144,8 @@ async fn resolves_repository_scoped_auth() -> anyhow::Result<()> {
     let client = http_nirion_client().auth(auth).build();
     let resolved = client
         .get_versioned_image(&test_image.reference)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -167,7 +178,8 @@ async fn falls_back_to_registry_scoped_auth() -> anyhow::Result<()> {
     let client = http_nirion_client().auth(auth).build();
     let resolved = client
         .get_versioned_image(&test_image.reference)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -216,12 +228,14 @@ async fn different_scopes_on_same_registry() -> anyhow::Result<()> {
 
     let resolved_a = client
         .get_versioned_image(&image_a.reference)
-        .await?;
+        .await?
+        .image;
     assert_eq!(resolved_a.digest, image_a.digest);
 
     let resolved_b = client
         .get_versioned_image(&image_b.reference)
-        .await?;
+        .await?
+        .image;
     assert_eq!(resolved_b.digest, image_b.digest);
 
     Ok(())
@@ -268,12 +282,14 @@ async fn scoped_auth_isolation_across_registries() -> anyhow::Result<()> {
 
     let resolved_a = client
         .get_versioned_image(&image_a.reference)
-        .await?;
+        .await?
+        .image;
     assert_eq!(resolved_a.digest, image_a.digest);
 
     let resolved_b = client
         .get_versioned_image(&image_b.reference)
-        .await?;
+        .await?
+        .image;
     assert_eq!(resolved_b.digest, image_b.digest);
 
     Ok(())
@@ -307,11 +323,14 @@ async fn updated_image_resolves_version_with_scoped_auth() -> anyhow::Result<()>
         image: test_image.reference.to_string(),
         version: Some("1.0.0".to_string()),
         digest: "sha256:0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+        size: None,
+        source: None,
     };
 
     let resolved = client
         .get_updated_versioned_image(&stale)
-        .await?;
+        .await?
+        .image;
 
     assert_eq!(resolved.image, test_image.reference.to_string());
     assert_eq!(resolved.digest, test_image.digest);
@@ -341,6 +360,120 @@ async fn docker_hub_client_follows_pagination() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn docker_hub_alias_tags_stop_after_the_digest_group()
+-> anyhow::Result<()> {
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let other = "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let page_url = |page: usize| {
+        format!(
+            "/repositories/library/nirion-test/tags?page_size=100&page={page}&ordering=last_updated"
+        )
+    };
+    let first = docker_hub_tags_page_with_digests(
+        Some(&format!("http://{addr}{}", page_url(2))),
+        &[("edge", other), ("1.2.3", digest)],
+    );
+    let second = docker_hub_tags_page_with_digests(
+        Some(&format!("http://{addr}{}", page_url(3))),
+        &[("1.2", digest), ("1.1.0", other)],
+    );
+    // Only two pages are served: asking for the third one fails.
+    let server = tokio::spawn(async move {
+        serve_http_response(&listener, 200, &first, &page_url(1)).await?;
+        serve_http_response(&listener, 200, &second, &page_url(2)).await?;
+        anyhow::Ok(())
+    });
+    let reference = Reference::try_from("localhost:5000/nirion-test:latest")?;
+    let client = DockerHubClient::with_base_url(format!("http://{addr}"))
+        .with_registries(["localhost:5000".to_string()]);
+
+    let aliases = client
+        .get_alias_tags(&reference, &ImageDigests::platform(digest))
+        .await?;
+
+    assert_eq!(aliases.tags, ["1.2.3", "1.2"]);
+    assert_eq!(aliases.capped_after, None);
+    server.await??;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn docker_hub_alias_tags_give_up_after_the_page_cap() -> anyhow::Result<()>
+{
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let other = "sha256:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let page_url = move |page: usize| {
+        format!(
+            "/repositories/library/nirion-test/tags?page_size=100&page={page}&ordering=last_updated"
+        )
+    };
+    let server = tokio::spawn(async move {
+        for page in 1..=20 {
+            let body = docker_hub_tags_page_with_digests(
+                Some(&format!("http://{addr}{}", page_url(page + 1))),
+                &[("nightly", other)],
+            );
+            serve_http_response(&listener, 200, &body, &page_url(page)).await?;
+        }
+        anyhow::Ok(())
+    });
+    let reference = Reference::try_from("localhost:5000/nirion-test:latest")?;
+    let client = DockerHubClient::with_base_url(format!("http://{addr}"))
+        .with_registries(["localhost:5000".to_string()]);
+
+    let aliases = client
+        .get_alias_tags(&reference, &ImageDigests::platform(digest))
+        .await?;
+
+    assert!(aliases.tags.is_empty());
+    assert_eq!(aliases.capped_after, Some(2000));
+    server.await??;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn docker_hub_alias_tags_report_a_cap_hit_while_still_matching()
+-> anyhow::Result<()> {
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let page_url = move |page: usize| {
+        format!(
+            "/repositories/library/nirion-test/tags?page_size=100&page={page}&ordering=last_updated"
+        )
+    };
+    let server = tokio::spawn(async move {
+        for page in 1..=20 {
+            let body = docker_hub_tags_page_with_digests(
+                Some(&format!("http://{addr}{}", page_url(page + 1))),
+                &[(&format!("1.{page}"), digest)],
+            );
+            serve_http_response(&listener, 200, &body, &page_url(page)).await?;
+        }
+        anyhow::Ok(())
+    });
+    let reference = Reference::try_from("localhost:5000/nirion-test:latest")?;
+    let client = DockerHubClient::with_base_url(format!("http://{addr}"))
+        .with_registries(["localhost:5000".to_string()]);
+
+    let aliases = client
+        .get_alias_tags(&reference, &ImageDigests::platform(digest))
+        .await?;
+
+    assert_eq!(aliases.tags.len(), 20);
+    assert_eq!(aliases.capped_after, Some(2000));
+    server.await??;
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn docker_hub_client_parses_api_errors() -> anyhow::Result<()> {
     let (base_url, server) = start_error_mock_docker_hub().await?;
@@ -388,6 +521,178 @@ async fn docker_hub_client_fetches_single_tag() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn docker_hub_client_logs_in_once_and_sends_the_token()
+-> anyhow::Result<()> {
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let arch =
+        nirion_oci_lib::oci_client::config::Architecture::default().to_string();
+    let tag = docker_hub_tag("1.2.3", &arch, digest);
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let base_url = format!("http://{}", listener.local_addr()?);
+    let server = tokio::spawn(async move {
+        let login = read_request(&listener, r#"{"token":"jwt-1"}"#).await?;
+        assert!(login.starts_with("POST /users/login "), "{login}");
+        for _ in 0..2 {
+            let request = read_request(&listener, &tag).await?;
+            assert!(
+                request
+                    .to_ascii_lowercase()
+                    .contains("authorization: bearer jwt-1"),
+                "{request}"
+            );
+        }
+        anyhow::Ok(())
+    });
+    let reference = Reference::try_from("localhost:5000/nirion-test:1.2.3")?;
+    let client = DockerHubClient::with_base_url(base_url)
+        .with_registries(["localhost:5000".to_string()])
+        .with_credentials(NirionRegistryAuth::basic("user", "secret"));
+
+    assert_eq!(client.fetch_tag(&reference).await?.name, "1.2.3");
+    assert_eq!(
+        client
+            .clone()
+            .fetch_tag(&reference)
+            .await?
+            .name,
+        "1.2.3"
+    );
+
+    server.await??;
+
+    Ok(())
+}
+
+/// Answers one request with `body` and returns the request head.
+async fn read_request(
+    listener: &TcpListener,
+    body: &str,
+) -> anyhow::Result<String> {
+    let (mut socket, _) = listener.accept().await?;
+    let mut request = vec![0; 4096];
+    let read = socket.read(&mut request).await?;
+    let response = format!(
+        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    socket
+        .write_all(response.as_bytes())
+        .await?;
+    Ok(String::from_utf8_lossy(&request[..read]).to_string())
+}
+
+#[tokio::test]
+async fn credential_check_logs_in_through_the_token_service()
+-> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let registry = listener.local_addr()?.to_string();
+    let realm = format!("http://{registry}/token");
+    let server = tokio::spawn(async move {
+        // Each check: the anonymous probe, token and retry, then the same
+        // with credentials, minus the retry once the login is rejected.
+        for _ in 0..11 {
+            serve_token_registry(&listener, &realm).await?;
+        }
+        anyhow::Ok(())
+    });
+    let checker = CredentialChecker::new(
+        reqwest::Client::new(),
+        ClientProtocol::HttpsExcept(vec![registry.clone()]),
+    );
+    let image = Reference::try_from(format!("{registry}/private/app:1.0"))?;
+
+    let check = checker
+        .check(
+            &registry,
+            &NirionRegistryAuth::basic("user", "secret"),
+            Some(&image),
+        )
+        .await;
+    assert_eq!(check.repository.as_deref(), Some("private/app"));
+    assert_eq!(
+        check.anonymous,
+        Access::Denied("registry answered 401 Unauthorized".to_string())
+    );
+    assert_eq!(check.authenticated, Some(Access::Granted));
+    assert_eq!(check.username.as_deref(), Some("user"));
+    assert_eq!(
+        check.rate_limit,
+        Some(RateLimit {
+            limit: Some(200),
+            remaining: Some(187),
+        })
+    );
+    assert!(!check.failed());
+
+    let check = checker
+        .check(
+            &registry,
+            &NirionRegistryAuth::basic("user", "wrong"),
+            Some(&image),
+        )
+        .await;
+    assert_eq!(
+        check.authenticated,
+        Some(Access::Denied(
+            "token service answered 401 Unauthorized".to_string()
+        ))
+    );
+    assert!(check.failed());
+
+    server.await??;
+
+    Ok(())
+}
+
+/// Answers one request like a registry whose `private/app` repository
+/// needs a token for `user:secret`.
+async fn serve_token_registry(
+    listener: &TcpListener,
+    realm: &str,
+) -> anyhow::Result<()> {
+    let (mut socket, _) = listener.accept().await?;
+    let mut request = vec![0; 4096];
+    let read = socket.read(&mut request).await?;
+    let request =
+        String::from_utf8_lossy(&request[..read]).to_ascii_lowercase();
+
+    let (status, headers, body) = if request.starts_with("get /token") {
+        if request.contains("authorization: basic dxnlcjpzzwnyzxq=") {
+            ("200 OK", String::new(), r#"{"token":"user-token"}"#)
+        } else if request.contains("authorization: basic") {
+            ("401 Unauthorized", String::new(), "")
+        } else {
+            ("200 OK", String::new(), r#"{"token":"anonymous-token"}"#)
+        }
+    } else if request.contains("authorization: bearer user-token") {
+        (
+            "200 OK",
+            "ratelimit-limit: 200;w=21600\r\nratelimit-remaining: 187;w=21600\r\n"
+                .to_string(),
+            "",
+        )
+    } else {
+        (
+            "401 Unauthorized",
+            format!(
+                "www-authenticate: Bearer realm=\"{realm}\",service=\"test\"\r\n"
+            ),
+            "",
+        )
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\n{headers}content-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket
+        .write_all(response.as_bytes())
+        .await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn docker_hub_client_rejects_digest_references() -> anyhow::Result<()> {
     let client = DockerHubClient::default();
@@ -472,7 +777,7 @@ async fn oci_alias_tags_return_tags_with_matching_digest() -> anyhow::Result<()>
     let tags = get_alias_oci_tags(
         &client,
         &latest.reference,
-        &latest.digest,
+        &ImageDigests::platform(latest.digest.clone()),
         &RegistryAuth::Anonymous,
     )
     .await?;
@@ -495,7 +800,7 @@ async fn start_mock_docker_hub(
             &listener,
             200,
             &body,
-            "/repositories/library/nirion-test/tags?page_size=100&page=1",
+            "/repositories/library/nirion-test/tags?page_size=100&page=1&ordering=last_updated",
         )
         .await?;
         Ok(())
@@ -637,13 +942,24 @@ fn docker_hub_tags_response(digest: &str) -> String {
 fn docker_hub_tags_page(
     next: Option<&str>,
     names: &[&str],
+) -> String {
+    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    let tags = names
+        .iter()
+        .map(|name| (*name, digest))
+        .collect::<Vec<_>>();
+    docker_hub_tags_page_with_digests(next, &tags)
+}
+
+fn docker_hub_tags_page_with_digests(
+    next: Option<&str>,
+    tags: &[(&str, &str)],
 ) -> String {
     let arch =
         nirion_oci_lib::oci_client::config::Architecture::default().to_string();
-    let digest = "sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
-    let results = names
+    let results = tags
         .iter()
-        .map(|name| docker_hub_tag(name, &arch, digest))
+        .map(|(name, digest)| docker_hub_tag(name, &arch, digest))
         .collect::<Vec<_>>()
         .join(",");
     let next = next
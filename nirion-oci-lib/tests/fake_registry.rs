@@ -0,0 +1,234 @@
+use nirion_oci_lib::{
+    client::NirionOciClient, docker_hub::DockerHubClient,
+    fake_registry::FakeRegistry, oci_client::Reference,
+    version::VersionedImage,
+};
+
+const DIGEST_A: &str = "sha256:aaaa";
+const DIGEST_B: &str = "sha256:bbbb";
+const INDEX_A: &str = "sha256:1111";
+
+#[tokio::test]
+async fn resolves_version_from_config_label() -> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push("registry.test/app:latest", DIGEST_A, Some("1.2.3"))
+        .layer_size("registry.test/app:latest", DIGEST_A, 312_000_000)
+        .source(
+            "registry.test/app:latest",
+            DIGEST_A,
+            "https://github.com/acme/app",
+        );
+
+    let image = Reference::try_from("registry.test/app:latest")?;
+    let resolved = NirionOciClient::builder()
+        .build()
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+
+    assert_eq!(resolved.digest, DIGEST_A);
+    assert_eq!(resolved.version.as_deref(), Some("1.2.3"));
+    assert_eq!(resolved.size, Some(312_000_000));
+    assert_eq!(
+        resolved.source.as_deref(),
+        Some("https://github.com/acme/app")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn resolves_version_from_matching_tag() -> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push("registry.test/app:latest", DIGEST_B, None)
+        .push("registry.test/app:1.0.0", DIGEST_A, None)
+        .push("registry.test/app:2.0.0", DIGEST_B, None)
+        .push("registry.test/app:2", DIGEST_B, None);
+
+    let image = Reference::try_from("registry.test/app:latest")?;
+    let resolved = NirionOciClient::builder()
+        .build()
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+
+    assert_eq!(resolved.digest, DIGEST_B);
+    assert_eq!(resolved.version.as_deref(), Some("2.0.0"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn update_follows_moved_tag() -> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry.push("registry.test/app:latest", DIGEST_A, Some("1.0.0"));
+    let client = NirionOciClient::builder().build();
+
+    let locked = VersionedImage {
+        image: "registry.test/app:latest".to_string(),
+        version: Some("1.0.0".to_string()),
+        digest: DIGEST_A.to_string(),
+        size: None,
+        source: None,
+    };
+    let unchanged = client
+        .get_updated_versioned_image_with(&registry, &locked)
+        .await?
+        .image;
+    assert_eq!(unchanged.digest, DIGEST_A);
+    assert_eq!(unchanged, locked);
+
+    registry
+        .push("registry.test/app:latest", DIGEST_B, Some("1.1.0"))
+        .layer_size("registry.test/app:latest", DIGEST_B, 5_000);
+    let updated = client
+        .get_updated_versioned_image_with(&registry, &locked)
+        .await?
+        .image;
+    assert_eq!(updated.digest, DIGEST_B);
+    assert_eq!(updated.version.as_deref(), Some("1.1.0"));
+    assert_eq!(updated.size, Some(5_000));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn docker_hub_images_find_their_version_tag_in_the_backend()
+-> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push("nginx:latest", DIGEST_A, None)
+        .push("nginx:1.25", DIGEST_A, None)
+        .push("nginx:1.24", DIGEST_B, None);
+    // Nothing listens here; asking the Docker Hub API would fail.
+    let client = NirionOciClient::builder()
+        .docker_hub(DockerHubClient::with_base_url("http://127.0.0.1:9"))
+        .build();
+
+    let image = Reference::try_from("nginx:latest")?;
+    let resolved = client
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+
+    assert_eq!(resolved.digest, DIGEST_A);
+    assert_eq!(resolved.version.as_deref(), Some("1.25"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_repository_is_an_error() {
+    let registry = FakeRegistry::new();
+    let image = Reference::try_from("registry.test/missing:latest").unwrap();
+
+    let result = NirionOciClient::builder()
+        .build()
+        .get_versioned_image_with(&registry, &image)
+        .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn resolves_through_mirror_but_records_upstream() -> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push("docker.io/library/postgres:16", DIGEST_A, Some("16.1"))
+        .push("mirror.lan/library/postgres:16", DIGEST_B, Some("16.2"));
+    let client = NirionOciClient::builder()
+        .mirror("docker.io", "mirror.lan")
+        .build();
+
+    let image = Reference::try_from("postgres:16")?;
+    let resolved = client
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+
+    assert_eq!(resolved.digest, DIGEST_B);
+    assert_eq!(resolved.image, "docker.io/library/postgres:16");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn falls_back_to_upstream_when_mirror_fails() -> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry.push("docker.io/library/postgres:16", DIGEST_A, Some("16.1"));
+    let client = NirionOciClient::builder()
+        .mirror("docker.io", "mirror.lan")
+        .build();
+
+    let locked = VersionedImage {
+        image: "postgres:16".to_string(),
+        version: None,
+        digest: DIGEST_B.to_string(),
+        size: None,
+        source: None,
+    };
+    let updated = client
+        .get_updated_versioned_image_with(&registry, &locked)
+        .await?
+        .image;
+
+    assert_eq!(updated.digest, DIGEST_A);
+    assert_eq!(updated.image, "postgres:16");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn multi_arch_images_lock_the_index_and_match_aliases_by_it()
+-> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push_index("registry.test/app:latest", INDEX_A, DIGEST_A, None)
+        .push_index("registry.test/app:1.4.2", INDEX_A, DIGEST_A, None)
+        .push("registry.test/app:1.4.1", DIGEST_B, None);
+
+    let image = Reference::try_from("registry.test/app:latest")?;
+    let resolved = NirionOciClient::builder()
+        .build()
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+
+    assert_eq!(resolved.digest, INDEX_A);
+    assert_eq!(resolved.version.as_deref(), Some("1.4.2"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn multi_arch_images_match_single_platform_tags_by_platform_digest()
+-> anyhow::Result<()> {
+    let registry = FakeRegistry::new();
+    registry
+        .push_index("registry.test/app:latest", INDEX_A, DIGEST_A, None)
+        .push("registry.test/app:1.4.2", DIGEST_A, None);
+    let client = NirionOciClient::builder().build();
+
+    let image = Reference::try_from("registry.test/app:latest")?;
+    let resolved = client
+        .get_versioned_image_with(&registry, &image)
+        .await?
+        .image;
+    assert_eq!(resolved.digest, INDEX_A);
+    assert_eq!(resolved.version.as_deref(), Some("1.4.2"));
+
+    // Older locks hold the platform digest; it is still current.
+    let locked = VersionedImage {
+        digest: DIGEST_A.to_string(),
+        ..resolved
+    };
+    let unchanged = client
+        .get_updated_versioned_image_with(&registry, &locked)
+        .await?
+        .image;
+    assert_eq!(unchanged, locked);
+
+    Ok(())
+}
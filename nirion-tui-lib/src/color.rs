@@ -4,6 +4,13 @@ use console::{StyledObject, style};
 pub const GREY: Color = Color::Color256(7);
 pub const DARK_GREY: Color = Color::Color256(8);
 
+/// Turns styling off on stdout and stderr for the rest of the process,
+/// like setting `NO_COLOR` does.
+pub fn disable_colors() {
+    console::set_colors_enabled(false);
+    console::set_colors_enabled_stderr(false);
+}
+
 macro_rules! colorize {
     (
         colors { $($color_method:ident => $color:expr),* $(,)? }
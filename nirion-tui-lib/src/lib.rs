@@ -1,6 +1,7 @@
 pub mod ansi;
 pub mod color;
 pub mod line_renderer;
+pub mod sparkline;
 pub mod spinner;
 pub mod status;
 pub mod table;
@@ -0,0 +1,33 @@
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One block per value, scaled so `max` and above are a full block and
+/// zero and below the lowest one.
+pub fn sparkline(
+    values: impl IntoIterator<Item = f64>,
+    max: f64,
+) -> String {
+    values
+        .into_iter()
+        .map(|value| {
+            let level = if max > 0.0 {
+                (value / max * (LEVELS.len() - 1) as f64).round()
+            } else {
+                0.0
+            };
+            LEVELS[level.clamp(0.0, (LEVELS.len() - 1) as f64) as usize]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_scaled_to_max() {
+        assert_eq!(sparkline([0.0, 2.5, 5.0, 7.5, 10.0], 10.0), "▁▃▅▆█");
+        assert_eq!(sparkline([20.0, -1.0], 10.0), "█▁");
+        assert_eq!(sparkline([3.0], 0.0), "▁");
+        assert_eq!(sparkline([], 1.0), "");
+    }
+}
@@ -1,46 +1,58 @@
-use std::{
-    sync::Mutex,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
-pub struct Spinner {
-    states: Vec<String>,
-    interval: Duration,
-    inner: Mutex<Inner>,
-}
+pub const DOTS: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+pub const PULSE: &[&str] = &["◜", "◝", "◞", "◟"];
 
-struct Inner {
-    current_index: usize,
-    last_trigger: Instant,
+/// A spinner whose frame is derived from the wall-clock time since it was
+/// created, so the animation speed does not depend on how often it is
+/// rendered.
+pub struct Spinner {
+    frames: Vec<String>,
+    period: Duration,
+    started: Instant,
 }
 
 impl Default for Spinner {
     fn default() -> Self {
+        Self::new(DOTS, Duration::from_millis(100))
+    }
+}
+
+impl Spinner {
+    /// Creates a spinner showing each of `frames` for `period`.
+    pub fn new(
+        frames: &[&str],
+        period: Duration,
+    ) -> Self {
+        assert!(!frames.is_empty(), "spinner needs at least one frame");
+
         Self {
-            states: ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏']
+            frames: frames
                 .iter()
-                .map(|c| c.to_string())
-                .collect::<Vec<_>>(),
-            interval: Duration::from_millis(100),
-            inner: Mutex::new(Inner {
-                current_index: 0,
-                last_trigger: Instant::now(),
-            }),
+                .map(|f| f.to_string())
+                .collect(),
+            period,
+            started: Instant::now(),
         }
     }
-}
 
-impl Spinner {
     pub fn get(&self) -> String {
-        let mut inner = self.inner.lock().unwrap();
-        let now = Instant::now();
+        self.frame_at(self.started.elapsed())
+            .to_string()
+    }
 
-        if now.duration_since(inner.last_trigger) >= self.interval {
-            inner.last_trigger = now;
-            inner.current_index = (inner.current_index + 1) % self.states.len();
-        }
+    pub fn frame_at(
+        &self,
+        elapsed: Duration,
+    ) -> &str {
+        let index = match self.period.as_nanos() {
+            0 => 0,
+            period => {
+                (elapsed.as_nanos() / period) as usize % self.frames.len()
+            }
+        };
 
-        self.states[inner.current_index].clone()
+        &self.frames[index]
     }
 }
 
@@ -48,44 +60,49 @@ impl Spinner {
 mod tests {
     use super::*;
 
-    fn spinner(
-        interval: Duration,
-        current_index: usize,
-    ) -> Spinner {
-        Spinner {
-            states: vec!["a".into(), "b".into(), "c".into()],
-            interval,
-            inner: Mutex::new(Inner {
-                current_index,
-                last_trigger: Instant::now(),
-            }),
-        }
+    fn spinner(period: Duration) -> Spinner {
+        Spinner::new(&["a", "b", "c"], period)
+    }
+
+    #[test]
+    fn frame_at_keeps_frame_within_period() {
+        let spinner = spinner(Duration::from_millis(100));
+
+        assert_eq!(spinner.frame_at(Duration::ZERO), "a");
+        assert_eq!(spinner.frame_at(Duration::from_millis(99)), "a");
     }
 
     #[test]
-    fn get_keeps_current_state_before_interval_elapses() {
-        let spinner = spinner(Duration::from_secs(3600), 1);
+    fn frame_at_advances_and_wraps_with_elapsed_time() {
+        let spinner = spinner(Duration::from_millis(100));
 
-        assert_eq!(spinner.get(), "b");
-        assert_eq!(spinner.get(), "b");
+        assert_eq!(spinner.frame_at(Duration::from_millis(100)), "b");
+        assert_eq!(spinner.frame_at(Duration::from_millis(250)), "c");
+        assert_eq!(spinner.frame_at(Duration::from_millis(300)), "a");
     }
 
     #[test]
-    fn get_advances_and_wraps_when_interval_elapses() {
-        let spinner = spinner(Duration::ZERO, 2);
+    fn frame_at_does_not_depend_on_call_count() {
+        let spinner = spinner(Duration::from_secs(3600));
 
         assert_eq!(spinner.get(), "a");
-        assert_eq!(spinner.get(), "b");
+        assert_eq!(spinner.get(), "a");
     }
 
     #[test]
-    fn default_cycles_multiple_states() {
-        let mut spinner = Spinner::default();
-        spinner.interval = Duration::ZERO;
+    fn zero_period_stays_on_first_frame() {
+        let spinner = spinner(Duration::ZERO);
+
+        assert_eq!(spinner.frame_at(Duration::from_secs(5)), "a");
+    }
 
-        let first = spinner.get();
-        let second = spinner.get();
+    #[test]
+    fn default_cycles_multiple_states() {
+        let spinner = Spinner::default();
 
-        assert_ne!(first, second);
+        assert_ne!(
+            spinner.frame_at(Duration::ZERO),
+            spinner.frame_at(Duration::from_millis(100))
+        );
     }
 }
@@ -38,10 +38,15 @@ impl Status {
         self.render_lines(width).join("\n")
     }
 
+    /// The framed status, or no lines at all without entries rather than
+    /// an empty frame.
     pub fn render_lines(
         &self,
         width: usize,
     ) -> Vec<String> {
+        if self.entries.is_empty() {
+            return Vec::new();
+        }
         let max_prefix_width = max_entry_width(&self.entries, |e| &e.prefix);
         let max_suffix_width = max_entry_width(&self.entries, |e| &e.suffix);
         let bar_width =
@@ -167,6 +172,15 @@ mod tests {
     use super::*;
     use console::strip_ansi_codes;
 
+    #[test]
+    fn render_lines_draws_no_frame_without_entries() {
+        assert!(
+            Status::new(vec![])
+                .render_lines(80)
+                .is_empty()
+        );
+    }
+
     #[test]
     fn render_status_bar_returns_spaces_for_no_segments() {
         assert_eq!(render_status_bar(&[], 4), "    ");
@@ -291,8 +305,7 @@ mod tests {
             .saturating_sub(ansi_len("ready"))
             .saturating_sub(STATUS_LINE_FIXED_WIDTH)
             .saturating_sub(DEFAULT_SAFETY_MARGIN)
-            .min(DEFAULT_MAX_BAR_WIDTH)
-            .max(DEFAULT_MIN_BAR_WIDTH);
+            .clamp(DEFAULT_MIN_BAR_WIDTH, DEFAULT_MAX_BAR_WIDTH);
 
         assert_eq!(bar_area.chars().count() - 2, expected_bar_width);
     }
@@ -16,6 +16,72 @@ impl Drop for HiddenCursorGuard {
     }
 }
 
+/// Puts the terminal settings of stdin back the way they were when the
+/// guard was created, e.g. after a child that switched the terminal to
+/// raw mode was killed before it could restore it. Does nothing when
+/// stdin isn't a terminal.
+pub struct TerminalModeGuard {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl TerminalModeGuard {
+    #[cfg(unix)]
+    pub fn save() -> Self {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr only writes to the termios it is given and
+        // reports failure, e.g. for a non-terminal stdin, through its
+        // return value.
+        let saved = unsafe {
+            (libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) == 0)
+                .then(|| termios.assume_init())
+        };
+        Self { saved }
+    }
+
+    #[cfg(not(unix))]
+    pub fn save() -> Self {
+        Self {}
+    }
+
+    /// Saves the terminal settings of stdin like [`Self::save`], then
+    /// turns off line buffering and echo, so key presses can be read as
+    /// they are typed. Ctrl-C still sends SIGINT.
+    #[cfg(unix)]
+    pub fn unbuffered() -> Self {
+        let guard = Self::save();
+        if let Some(saved) = &guard.saved {
+            let mut unbuffered = *saved;
+            unbuffered.c_lflag &= !(libc::ICANON | libc::ECHO);
+            unbuffered.c_cc[libc::VMIN] = 1;
+            unbuffered.c_cc[libc::VTIME] = 0;
+            // SAFETY: `unbuffered` is a copy of the settings tcgetattr
+            // filled in for the same fd.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &unbuffered);
+            }
+        }
+        guard
+    }
+
+    #[cfg(not(unix))]
+    pub fn unbuffered() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for TerminalModeGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: `saved` was filled in by tcgetattr on the same fd.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
 pub fn hide_cursor() -> anyhow::Result<()> {
     let term = Term::stdout();
     term.hide_cursor()?;
@@ -33,6 +99,10 @@ pub fn terminal_width() -> usize {
     Term::stdout().size().1 as usize
 }
 
+pub fn terminal_height() -> usize {
+    Term::stdout().size().0 as usize
+}
+
 pub fn move_cursor_up(lines: usize) -> anyhow::Result<()> {
     let term = Term::stdout();
     term.move_cursor_up(lines)?;
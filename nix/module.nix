@@ -42,16 +42,53 @@ let
       throw "Only one of nixEval.target, nixEval.rawTarget, or nixEval.nixos may be set";
 
   envVars = {
-    NIRION_LOCK_FILE = cfg.lockFileOutput;
     NIRION_PROJECT_FILE = cfg.out.projectsFile;
   }
+  // lib.optionalAttrs (cfg.lockFileOutput != null) {
+    NIRION_LOCK_FILE = cfg.lockFileOutput;
+  }
+  // lib.optionalAttrs (cfg.lockDirOutput != null) {
+    NIRION_LOCK_DIR = cfg.lockDirOutput;
+  }
   // lib.optionalAttrs (cfg.authFile != null) {
     NIRION_AUTH_FILE = cfg.authFile;
   }
+  // lib.optionalAttrs (cfg.registryMirrors != { }) {
+    NIRION_REGISTRY_MIRROR = lib.concatStringsSep "," (
+      lib.mapAttrsToList (registry: mirror: "${registry}=${mirror}") cfg.registryMirrors
+    );
+  }
   // lib.optionalAttrs (nirionNixTarget != null) {
     ${nirionNixTarget.name} = nirionNixTarget.value;
   };
 
+  # `imageRef` with its registry swapped for the mirror configured in
+  # registryMirrors, if any. Like docker, a first path segment only names
+  # a registry if it looks like a host; otherwise the image is on Docker Hub.
+  mirrorImage =
+    imageRef:
+    let
+      parts = lib.splitString "/" imageRef;
+      first = builtins.head parts;
+      hasRegistry =
+        builtins.length parts > 1
+        && (lib.hasInfix "." first || lib.hasInfix ":" first || first == "localhost");
+      registry =
+        if !hasRegistry || first == "index.docker.io" || first == "registry-1.docker.io" then
+          "docker.io"
+        else
+          first;
+      path =
+        if hasRegistry then
+          lib.concatStringsSep "/" (builtins.tail parts)
+        else if builtins.length parts == 1 then
+          "library/${imageRef}"
+        else
+          imageRef;
+      mirror = cfg.registryMirrors.${registry} or null;
+    in
+    if mirror == null then imageRef else "${mirror}/${path}";
+
   nirion = import ./module/wrapper.nix {
     inherit
       lib
@@ -61,6 +98,53 @@ let
       ;
   };
 
+  # nirion writes `{ "version": 2, "images": { ... } }`; older lock files
+  # are the flat map of images on their own.
+  readLockFile =
+    path:
+    let
+      data = lib.importJSON path;
+      versioned = data ? version && data ? images;
+    in
+    {
+      inherit path;
+      version = if versioned then data.version else null;
+      images = if versioned then data.images else data;
+    };
+
+  # `nirion --lock-dir` keeps a `<project>.lock` per project, keyed by
+  # service name alone.
+  lockDirFiles = lib.mapAttrsToList (
+    fileName: _:
+    readLockFile "${cfg.lockDir}/${fileName}"
+    // {
+      project = lib.removeSuffix ".lock" fileName;
+    }
+  ) (lib.filterAttrs (fileName: type: type == "regular" && lib.hasSuffix ".lock" fileName) (builtins.readDir cfg.lockDir));
+
+  lockFiles =
+    if cfg.lockDir != null then
+      lockDirFiles
+    else if cfg.lockFile != null then
+      [ (readLockFile cfg.lockFile) ]
+    else if cfg.images != { } then
+      lib.warn "nirion: No lockFile specified" [ ]
+    else
+      [ ];
+
+  lockFileImages = lib.foldl' (
+    images: file:
+    images
+    // (
+      if file ? project then
+        lib.mapAttrs' (service: lib.nameValuePair "${file.project}.${service}") file.images
+      else
+        file.images
+    )
+  ) { } lockFiles;
+
+  unsupportedLockFiles = lib.filter (file: file.version != null && file.version != 2) lockFiles;
+
   sopsTemplateName = projectName: "nirion/${projectName}/compose.yaml";
   sopsTemplatePath = projectName: config.sops.templates.${sopsTemplateName projectName}.path;
   hasSops = options ? sops;
@@ -162,6 +246,22 @@ in
           assertion = (cfg.nixEval.nixos.config == null) == (cfg.nixEval.nixos.host == null);
           message = "virtualisation.nirion.nixEval.nixos.config and virtualisation.nirion.nixEval.nixos.host must be set together.";
         }
+        {
+          assertion = cfg.lockFile == null || cfg.lockDir == null;
+          message = "virtualisation.nirion.lockFile and virtualisation.nirion.lockDir can't be set together.";
+        }
+        {
+          assertion = cfg.lockFileOutput == null || cfg.lockDirOutput == null;
+          message = "virtualisation.nirion.lockFileOutput and virtualisation.nirion.lockDirOutput can't be set together.";
+        }
+        {
+          assertion = unsupportedLockFiles == [ ];
+          message = "${
+            lib.concatMapStringsSep ", " (
+              file: "${toString file.path} uses lock file schema version ${toString file.version}"
+            ) unsupportedLockFiles
+          }, but the nirion module only understands version 2; update nirion.";
+        }
       ];
 
       virtualisation.docker.enable = lib.mkIf (cfg.projects != { }) true;
@@ -170,7 +270,9 @@ in
         out.images_v2 = lib.mapAttrs (
           _: project:
           lib.filterAttrs (_: value: value != null) (
-            lib.mapAttrs (_: service: service.image) project.services
+            lib.mapAttrs (
+              _: service: if service.build.context == null then service.image else null
+            ) project.services
           )
         ) cfg.projects;
 
@@ -183,35 +285,25 @@ in
           }) images
         ) { } cfg.out.images_v2;
 
-        out.locked_images =
-          let
-            lockFile =
-              if cfg.lockFile != null then
-                lib.importJSON cfg.lockFile
-              else if cfg.images != { } then
-                lib.warn "nirion: No lockFile specified" { }
-              else
-                { };
-          in
-          lib.mapAttrs (
-            name: imageRef:
-            if builtins.match ".*@sha256:.*" imageRef != null then
-              imageRef
+        out.locked_images = lib.mapAttrs (
+          name: imageRef:
+          if builtins.match ".*@sha256:.*" imageRef != null then
+            imageRef
+          else
+            let
+              entry = lockFileImages.${name} or null;
+              digest = if builtins.isAttrs entry then entry.digest else null;
+              lockedImage = if builtins.isAttrs entry then entry.image or null else null;
+            in
+            if entry != null && !builtins.isAttrs entry then
+              throw "nirion: Lock entry for image '${name}' must be an object with image, version, and digest fields"
+            else if lockedImage != null && lockedImage != imageRef then
+              lib.warn "nirion: Lock entry for image '${name}' was created for '${lockedImage}', not '${imageRef}' - using mutable tag" imageRef
+            else if digest != null then
+              "${mirrorImage imageRef}@${digest}"
             else
-              let
-                entry = lockFile.${name} or null;
-                digest = if builtins.isAttrs entry then entry.digest else null;
-                lockedImage = if builtins.isAttrs entry then entry.image or null else null;
-              in
-              if entry != null && !builtins.isAttrs entry then
-                throw "nirion: Lock entry for image '${name}' must be an object with image, version, and digest fields"
-              else if lockedImage != null && lockedImage != imageRef then
-                lib.warn "nirion: Lock entry for image '${name}' was created for '${lockedImage}', not '${imageRef}' - using mutable tag" imageRef
-              else if digest != null then
-                "${imageRef}@${digest}"
-              else
-                lib.warn "nirion: Image '${name}' (${imageRef}) not locked - using mutable tag" imageRef
-          ) cfg.images;
+              lib.warn "nirion: Image '${name}' (${imageRef}) not locked - using mutable tag" imageRef
+        ) cfg.images;
 
         out.compose = import ./module/compose/render.nix { inherit cfg lib pkgs; };
 
@@ -225,11 +317,15 @@ in
           {
             name = compose.name;
             dockerCompose = composeFile;
+            inherit (project) profiles hooks;
             services = lib.mapAttrs (serviceName: renderedService: {
               image = project.services.${serviceName}.image or null;
               resolvedImage = renderedService.image or null;
               healthcheck = renderedService ? healthcheck;
               restart = renderedService.restart or null;
+              profiles = renderedService.profiles or [ ];
+              lintIgnore = project.services.${serviceName}.lintIgnore or [ ];
+              build = renderedService ? build;
             }) compose.services;
           }
         ) cfg.projects;
@@ -96,6 +96,28 @@ let
       };
     };
   };
+  hookType = types.coercedTo types.str (command: { inherit command; }) (
+    types.submodule {
+      options = {
+        command = mkOption {
+          type = types.str;
+          description = "Shell command, run next to the project's compose file.";
+        };
+        ignoreFailure = mkOption {
+          type = types.bool;
+          default = false;
+          description = "Carry on with the operation if the command fails.";
+        };
+      };
+    }
+  );
+  hookListOption =
+    description:
+    mkOption {
+      type = types.listOf hookType;
+      default = [ ];
+      inherit description;
+    };
   defaultNetwork = lib.optionalAttrs config.enableDefaultNetwork {
     default = {
       name = config.composeProjectName;
@@ -114,6 +136,17 @@ in
       type = types.bool;
       default = true;
     };
+    profiles = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Compose profiles nirion enables for every compose invocation of this project.";
+    };
+    hooks = {
+      preUp = hookListOption "Commands run before `nirion up` starts the project.";
+      postUp = hookListOption "Commands run after `nirion up` started the project.";
+      preDown = hookListOption "Commands run before `nirion down` takes the project down.";
+      postDown = hookListOption "Commands run after `nirion down` took the project down.";
+    };
     services = mkOption {
       type = types.attrsOf (types.submodule serviceModule);
       default = { };
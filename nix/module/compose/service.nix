@@ -219,6 +219,16 @@ in
       type = types.nullOr types.str;
       default = null;
     };
+    profiles = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "Compose profiles that must be enabled for this service to start.";
+    };
+    lintIgnore = mkOption {
+      type = types.listOf types.str;
+      default = [ ];
+      description = "`nirion lint` findings to suppress for this service, by code or name.";
+    };
     stop_signal = mkOption {
       type = types.nullOr types.str;
       default = null;
@@ -325,6 +335,7 @@ in
         devices
         depends_on
         restart
+        profiles
         stop_signal
         stop_grace_period
         privileged
@@ -5,21 +5,44 @@ let
 in
 {
   lockFile = mkOption {
-    type = types.path;
+    type = types.nullOr types.path;
+    default = null;
     description = "Path to image lock file.";
   };
 
   lockFileOutput = mkOption {
-    type = types.str;
+    type = types.nullOr types.str;
+    default = null;
     description = "Writable output path for lock file updates.";
   };
 
+  lockDir = mkOption {
+    type = types.nullOr types.path;
+    default = null;
+    description = "Directory of per-project `<project>.lock` files, as `nirion --lock-dir` writes them. Used instead of lockFile.";
+  };
+
+  lockDirOutput = mkOption {
+    type = types.nullOr types.str;
+    default = null;
+    description = "Writable lock directory for lock file updates, used instead of lockFileOutput.";
+  };
+
   authFile = mkOption {
     type = types.nullOr types.path;
     default = null;
     description = "Optional path to OCI registry auth config.";
   };
 
+  registryMirrors = mkOption {
+    type = types.attrsOf types.str;
+    default = { };
+    example = {
+      "docker.io" = "mirror.lan";
+    };
+    description = "Registries to pull locked images through a mirror of, as `nirion --registry-mirror` resolves them. Lock entries keep the upstream reference.";
+  };
+
   nixEval = {
     target = mkOption {
       type = types.nullOr types.str;
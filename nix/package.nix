@@ -56,6 +56,9 @@ naersk-lib.buildPackage {
     # Fish completion
     mkdir -p $out/share/fish/vendor_completions.d
     COMPLETE=fish $out/bin/nirion > $out/share/fish/vendor_completions.d/nirion.fish
+
+    # Man pages
+    $out/bin/nirion man --output-dir $out/share/man/man1
   '';
 
   passthru = {
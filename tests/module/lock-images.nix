@@ -1,4 +1,5 @@
 {
+  lib,
   evalConfig,
   lockFile,
   ...
@@ -46,6 +47,39 @@ let
 
   cfg = system.config.virtualisation.nirion;
   services = cfg.out.compose.app.attrs.services;
+
+  # What `nirion lock` writes.
+  versionedSystem =
+    version:
+    evalConfig [
+      {
+        virtualisation.nirion = {
+          lockFile = lockFile {
+            inherit version;
+            images."app.web" = {
+              image = "nginx:1.27";
+              version = "1.27";
+              digest = "sha256:eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+            };
+          };
+          lockFileOutput = "/var/lib/nirion/lock.json";
+          projects.app.services.web.image = "nginx:1.27";
+        };
+      }
+    ];
+  v2System = versionedSystem 2;
+
+  lockDirSystem = evalConfig [
+    {
+      virtualisation.nirion = {
+        lockDir = ./lock-dir;
+        lockDirOutput = "/var/lib/nirion/locks";
+        projects.app.services.web.image = "nginx:1.27";
+      };
+    }
+  ];
+  failedAssertions =
+    system: map (check: check.message) (lib.filter (check: !check.assertion) system.config.assertions);
 in
 [
   {
@@ -100,4 +134,30 @@ in
       == "nginx:1.27@sha256:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
     message = "project metadata should include the evaluated resolved image reference";
   }
+  {
+    assertion =
+      v2System.config.virtualisation.nirion.out.compose.app.attrs.services.web.image
+      == "nginx:1.27@sha256:eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+    message = "version 2 lock file digest was not applied";
+  }
+  {
+    assertion = failedAssertions v2System == [ ];
+    message = "version 2 lock file should pass the module assertions";
+  }
+  {
+    assertion = lib.any (lib.hasInfix "schema version 3") (failedAssertions (versionedSystem 3));
+    message = "unknown lock file versions should fail a module assertion";
+  }
+  {
+    assertion =
+      lockDirSystem.config.virtualisation.nirion.out.compose.app.attrs.services.web.image
+      == "nginx:1.27@sha256:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+    message = "lock directory entries were not applied to their project";
+  }
+  {
+    assertion =
+      lockDirSystem.config.environment.variables.NIRION_LOCK_DIR == "/var/lib/nirion/locks"
+      && !(lockDirSystem.config.environment.variables ? NIRION_LOCK_FILE);
+    message = "lockDirOutput should be exposed as NIRION_LOCK_DIR only";
+  }
 ]